@@ -0,0 +1,171 @@
+//! Public amenities scaled to the population of the town: wells, bakeries, apothecaries,
+//! and a second church and a school for large towns. Towns with barracks over their gates also get a
+//! training yard for the guard.
+
+use log::info;
+use mcprogedit::coordinates::BlockColumnCoord;
+
+use crate::config::GenerationConfig;
+use crate::geometry;
+use crate::structure_builder::HousePurpose;
+use crate::zoning::DistrictUse;
+
+/// Number of people living in each house.
+pub const PERSONS_PER_HOUSEHOLD: usize = 5;
+/// Closest two bakeries may be to each other.
+const BAKERY_SPACING_MIN: usize = 32;
+/// Closest two apothecaries may be to each other.
+const APOTHECARY_SPACING_MIN: usize = 32;
+/// Closest a second church may be to the first one.
+pub const CHURCH_SPACING_MIN: usize = 48;
+/// Farthest the training yard may be from the nearest gate.
+const TRAINING_YARD_REACH: usize = 48;
+
+/// The public amenities needed by the population of the town.
+#[derive(Clone, Debug, Default)]
+pub struct AmenityPlan {
+    /// Number of houses expected to be built.
+    pub households: usize,
+    /// Number of wells to dig, at least one in each district.
+    pub wells: usize,
+    /// Plots to build bakeries on.
+    pub bakery_plots: Vec<usize>,
+    /// Number of churches to build, the landmark church included.
+    pub churches: usize,
+    /// Plot to lay out the training yard on, near the barracks over the gates.
+    pub training_yard_plot: Option<usize>,
+    /// Plot to build the school on, in the wealthiest district.
+    pub school_plot: Option<usize>,
+    /// Plots to build apothecaries on.
+    pub apothecary_plots: Vec<usize>,
+}
+
+impl AmenityPlan {
+    /// Number of people expected to live in town.
+    pub fn population(&self) -> usize {
+        self.households * PERSONS_PER_HOUSEHOLD
+    }
+
+    /// What the house on the plot with the given index is set aside for, if anything:
+    /// The school, a bakery or an apothecary.
+    pub fn plot_purpose(&self, index: usize) -> Option<HousePurpose> {
+        if self.school_plot == Some(index) {
+            Some(HousePurpose::School)
+        } else if self.bakery_plots.contains(&index) {
+            Some(HousePurpose::Bakery)
+        } else if self.apothecary_plots.contains(&index) {
+            Some(HousePurpose::Apothecary)
+        } else {
+            None
+        }
+    }
+}
+
+/// Work out the amenities needed for the houses to be built on `house_plots`, given as
+/// the plot index, the use of its district and its center, and pick the plots for the
+/// bakeries: in the market districts if possible, closest to the town center, spread out.
+/// If there are `gates`, with barracks over them, pick the plot closest to a gate for the
+/// training yard of the guard. For a large population, pick the plot among `wealthy_plots`
+/// closest to the town center for a school. Last, pick the plots for the apothecaries:
+/// in the residential districts if possible, closest to the town center, spread out.
+pub fn plan_amenities(
+    house_plots: &[(usize, DistrictUse, BlockColumnCoord)],
+    town_center: BlockColumnCoord,
+    gates: &[BlockColumnCoord],
+    wealthy_plots: &[usize],
+    config: &GenerationConfig,
+) -> AmenityPlan {
+    let households = house_plots.len();
+    let per = |interval: usize| if interval == 0 { 0 } else { (households + interval - 1) / interval };
+
+    let mut candidates: Vec<&(usize, DistrictUse, BlockColumnCoord)> = house_plots.iter()
+        .filter(|(_, district_use, _)| matches!(district_use, DistrictUse::Market | DistrictUse::Residential))
+        .collect();
+    candidates.sort_by_key(|(index, district_use, center)| {
+        (*district_use != DistrictUse::Market, geometry::manhattan_distance(*center, town_center), *index)
+    });
+    let mut bakeries: Vec<&(usize, DistrictUse, BlockColumnCoord)> = Vec::new();
+    for candidate in candidates {
+        if bakeries.len() >= per(config.households_per_bakery) {
+            break;
+        }
+        if bakeries.iter().all(|bakery| geometry::manhattan_distance(bakery.2, candidate.2) >= BAKERY_SPACING_MIN) {
+            bakeries.push(candidate);
+        }
+    }
+
+    let training_yard_plot = house_plots.iter()
+        .filter(|(index, district_use, _)| {
+            *district_use != DistrictUse::Market && !bakeries.iter().any(|bakery| bakery.0 == *index)
+        })
+        .filter_map(|(index, _, center)| {
+            gates.iter()
+                .map(|gate| geometry::manhattan_distance(*gate, *center))
+                .min()
+                .map(|distance| (distance, *index))
+        })
+        .filter(|(distance, _)| *distance <= TRAINING_YARD_REACH)
+        .min()
+        .map(|(_, index)| index);
+
+    let school_plot = house_plots.iter()
+        .filter(|_| config.school_population != 0 && households * PERSONS_PER_HOUSEHOLD >= config.school_population)
+        .filter(|(index, _, _)| {
+            wealthy_plots.contains(index)
+                && training_yard_plot != Some(*index)
+                && !bakeries.iter().any(|bakery| bakery.0 == *index)
+        })
+        .min_by_key(|(index, _, center)| (geometry::manhattan_distance(*center, town_center), *index))
+        .map(|(index, _, _)| *index);
+
+    let mut candidates: Vec<&(usize, DistrictUse, BlockColumnCoord)> = house_plots.iter()
+        .filter(|(index, district_use, _)| {
+            matches!(district_use, DistrictUse::Market | DistrictUse::Residential)
+                && training_yard_plot != Some(*index)
+                && school_plot != Some(*index)
+                && !bakeries.iter().any(|bakery| bakery.0 == *index)
+        })
+        .collect();
+    candidates.sort_by_key(|(index, district_use, center)| {
+        (*district_use != DistrictUse::Residential, geometry::manhattan_distance(*center, town_center), *index)
+    });
+    let mut apothecaries: Vec<&(usize, DistrictUse, BlockColumnCoord)> = Vec::new();
+    for candidate in candidates {
+        if apothecaries.len() >= per(config.households_per_apothecary) {
+            break;
+        }
+        if apothecaries.iter()
+            .all(|apothecary| geometry::manhattan_distance(apothecary.2, candidate.2) >= APOTHECARY_SPACING_MIN)
+        {
+            apothecaries.push(candidate);
+        }
+    }
+
+    let plan = AmenityPlan {
+        households,
+        wells: per(config.households_per_well),
+        bakery_plots: bakeries.iter().map(|(index, _, _)| *index).collect(),
+        churches: if config.second_church_population != 0
+            && households * PERSONS_PER_HOUSEHOLD >= config.second_church_population
+        {
+            2
+        } else {
+            1
+        },
+        training_yard_plot,
+        school_plot,
+        apothecary_plots: apothecaries.iter().map(|(index, _, _)| *index).collect(),
+    };
+    info!(
+        "Planned for a population of {}: {} wells, {} bakeries, {} apothecaries and {} churches.",
+        plan.population(), plan.wells, plan.bakery_plots.len(), plan.apothecary_plots.len(), plan.churches,
+    );
+    if let Some(index) = plan.training_yard_plot {
+        info!("Set aside plot {} for the training yard of the guard.", index);
+    }
+    if let Some(index) = plan.school_plot {
+        info!("Set aside plot {} for the school.", index);
+    }
+
+    plan
+}
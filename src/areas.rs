@@ -1,4 +1,6 @@
+use crate::config::GenerationConfig;
 use crate::features::Features;
+use crate::inspection::Inspection;
 
 use image::GrayImage;
 use image::imageops::*;
@@ -9,15 +11,41 @@ use imageproc::distance_transform::Norm;
 const TOWN_FLATNESS_TRESHOLD: u8 = 64;
 const TOWN_DISTANCE_INTO_WATER: u8 = 2;
 const WOOD_CONNECTEDNESS_TRESHOLD: u8 = 5;
-const AGRICULTURE_FLATNESS_TRESHOLD: u8 = 32;
+/// Lowest suitability of the land for agriculture.
+const AGRICULTURE_SUITABILITY_TRESHOLD: u8 = 191;
+const MINE_CONNECTEDNESS_TRESHOLD: u8 = 2;
+/// Distance from the cliffs kept out of the town area, so that no town straddles a cliff.
+const TOWN_DISTANCE_FROM_CLIFF: u8 = 1;
+/// Fertility of the soil, by the kind of ground at the surface.
+const FERTILITY_FERTILE: u8 = 255;
+const FERTILITY_SWAMP: u8 = 128;
+const FERTILITY_OTHER: u8 = 64;
+const FERTILITY_SAND: u8 = 24;
+const FERTILITY_BARREN: u8 = 0;
+/// Loss of flatness per block of slope.
+const FLATNESS_LOSS_PER_SLOPE: u8 = 64;
+/// Loss of nearness of water per block of distance from it.
+const WATER_NEARNESS_LOSS_PER_BLOCK: u8 = 8;
+/// Radius of the neighbourhood the density of the forest is measured over.
+const FOREST_DENSITY_RADIUS: u32 = 4;
+/// Lowest suitability of the land for grazing livestock, and its steepest slope.
+const PASTURE_SUITABILITY_TRESHOLD: u8 = 127;
+const PASTURE_SLOPE_MAX: u8 = 2;
 
 pub struct Areas {
     pub town: GrayImage,
     pub woodcutters: GrayImage,
     pub _agriculture: GrayImage,
-    pub _agriculture_without_trees: GrayImage,
+    pub agriculture_without_trees: GrayImage,
+    /// Suitability of each column for farming, from 0 for unusable to 255 for ideal land,
+    /// weighing its fertility, flatness, nearness of water and openness.
+    pub suitability: GrayImage,
+    /// Land fit for grazing livestock: Fertile, and not too steep.
+    pub pasture: GrayImage,
+    pub mines: GrayImage,
+    /// Plateaus, raised above their surroundings, for preferring as town sites.
+    pub defensible: GrayImage,
     //pub harbour: GrayImage,
-    //pub mines: GrayImage,
     //pub fishers: GrayImage,
     //pub town_road: GrayImage, // decide as part of town area instead?
     //pub lighthouse: GrayImage, // decide as part of harbour/fishers instead?
@@ -26,20 +54,39 @@ pub struct Areas {
 }
 
 impl Areas {
-    pub fn new_from_features(features: &Features) -> Self {
-        let town = Self::town(features);
-        let woodcutters = Self::woodcutters(features);
-        let (_agriculture, _agriculture_without_trees) = Self::agriculture(features);
+    pub fn new_from_features(features: &Features, config: &GenerationConfig, inspection: &Inspection) -> Self {
+        let town = Self::clipped(Self::town(features, inspection), features);
+        let woodcutters = Self::clipped(Self::woodcutters(features, inspection), features);
+        let suitability = Self::clipped(Self::suitability(features, config, inspection), features);
+        let (_agriculture, agriculture_without_trees) = Self::agriculture(features, &suitability, inspection);
+        let pasture = Self::pasture(features, &suitability, inspection);
+        let mines = Self::clipped(Self::mines(features, inspection), features);
+        let defensible = Self::clipped(features.plateau.clone(), features);
+        inspection.save_image("A-06 defensible.png", &defensible);
 
         Self {
             town,
             woodcutters,
             _agriculture,
-            _agriculture_without_trees,
+            agriculture_without_trees,
+            suitability,
+            pasture,
+            mines,
+            defensible,
         }
     }
 
-    fn town(features: &Features) -> GrayImage {
+    /// The area, without the columns outside the build boundary.
+    fn clipped(mut area: GrayImage, features: &Features) -> GrayImage {
+        for (x, z, pixel) in area.enumerate_pixels_mut() {
+            if image::Luma([255u8]) == features.outside[(x, z)] {
+                *pixel = image::Luma([0u8]);
+            }
+        }
+        area
+    }
+
+    fn town(features: &Features, inspection: &Inspection) -> GrayImage {
         // Suitable area for "town":
         // * on land, or a couple of blocks into water
         let mut land_mask = features.water.clone();
@@ -52,9 +99,13 @@ impl Areas {
         invert(&mut flat_mask);
         //flat_mask.save("A-01b flat mask.png").unwrap();
 
+        // * not on a cliff, keeping the cliffs between towns rather than through them
+        let mut cliff_mask = morphology::dilate(&features.cliff, Norm::LInf, TOWN_DISTANCE_FROM_CLIFF);
+        invert(&mut cliff_mask);
+
         // * not full of trees
         /* Uncomment this code for avoiding building cities on forests.
-        let mut forest_mask = Self::woodcutters(features);
+        let mut forest_mask = Self::woodcutters(features, inspection);
         invert(&mut forest_mask);
         morphology::dilate_mut(&mut forest_mask, Norm::LInf, 5u8);
         */
@@ -71,6 +122,7 @@ impl Areas {
             for z in 0..z_len as u32 {
                 if image::Luma([255u8]) == land_mask[(x, z)]
                 && image::Luma([255u8]) == flat_mask[(x, z)]
+                && image::Luma([255u8]) == cliff_mask[(x, z)]
                 //&& image::Luma([255u8]) == forest_mask[(x, z)] // Uncomment for avoiding building cities on forests.
                 {
                     town.put_pixel(x, z, image::Luma([255u8]));
@@ -78,13 +130,12 @@ impl Areas {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        town.save("A-01 town.png").unwrap();
+        inspection.save_image("A-01 town.png", &town);
 
         town
     }
 
-    fn woodcutters(features: &Features) -> GrayImage {
+    fn woodcutters(features: &Features, inspection: &Inspection) -> GrayImage {
         let mut woodcutters = features.forest.clone();
         morphology::close_mut(
             &mut woodcutters,
@@ -97,33 +148,84 @@ impl Areas {
             2 * WOOD_CONNECTEDNESS_TRESHOLD,
         );
 
-        #[cfg(feature = "debug_images")]
-        woodcutters.save("A-02 woodcutters.png").unwrap();
+        inspection.save_image("A-02 woodcutters.png", &woodcutters);
 
         woodcutters
     }
 
-    fn agriculture(features: &Features) -> (GrayImage, GrayImage) {
-        // Suitable area for "agriculture":
-        // * fertile land
-        // * not under water
-        // * not too many trees
-        // * not too steep
+    fn suitability(features: &Features, config: &GenerationConfig, inspection: &Inspection) -> GrayImage {
+        // Suitability for farming, as a weighted mean of:
+        // * the fertility of the soil, from the ground at the surface
+        // * the flatness of the land
+        // * the nearness of water, for watering the crops and the livestock
+        // * the openness of the land, with fewer trees to clear
+        // Under water, the land is not suitable at all.
 
         let (x_len, z_len) = features.dimensions();
 
-        let mut agriculture = features.fertile.clone();
-        let steep_mask = contrast::threshold(&features.scharr, AGRICULTURE_FLATNESS_TRESHOLD);
+        let mut land_mask = features.water.clone();
+        invert(&mut land_mask);
+        let water_distance = distance_transform::distance_transform(&land_mask, Norm::L1);
+        let forest_density = filter::box_filter(&features.forest, FOREST_DENSITY_RADIUS, FOREST_DENSITY_RADIUS);
+
+        let weights = [
+            config.suitability_fertility_weight.max(0.0),
+            config.suitability_flatness_weight.max(0.0),
+            config.suitability_water_weight.max(0.0),
+            config.suitability_openness_weight.max(0.0),
+        ];
+        let weight_sum: f32 = weights.iter().sum();
 
+        let mut suitability = image::ImageBuffer::new(x_len as u32, z_len as u32);
         for x in 0..x_len as u32 {
             for z in 0..z_len as u32 {
-                if image::Luma([255u8]) == steep_mask[(x, z)]
-                    || image::Luma([255u8]) == features.snow[(x, z)] {
-                    agriculture.put_pixel(x, z, image::Luma([0u8]));
+                let is_set = |stencil: &GrayImage| image::Luma([255u8]) == stencil[(x, z)];
+                if is_set(&features.water) || is_set(&features.lava) || weight_sum <= 0.0 {
+                    continue;
                 }
+
+                let fertility = if is_set(&features.snow)
+                    || is_set(&features.stone)
+                    || is_set(&features.exposed_ore)
+                    || is_set(&features.gravel)
+                {
+                    FERTILITY_BARREN
+                } else if is_set(&features.swamp) {
+                    FERTILITY_SWAMP
+                } else if is_set(&features.fertile) {
+                    FERTILITY_FERTILE
+                } else if is_set(&features.sand) {
+                    FERTILITY_SAND
+                } else {
+                    FERTILITY_OTHER
+                };
+                let image::Luma([slope]) = features.slope[(x, z)];
+                let flatness = u8::MAX.saturating_sub(slope.saturating_mul(FLATNESS_LOSS_PER_SLOPE));
+                let image::Luma([distance]) = water_distance[(x, z)];
+                let water_nearness = u8::MAX.saturating_sub(distance.saturating_mul(WATER_NEARNESS_LOSS_PER_BLOCK));
+                let image::Luma([density]) = forest_density[(x, z)];
+                let openness = u8::MAX - density;
+
+                let value = [fertility, flatness, water_nearness, openness].iter()
+                    .zip(weights.iter())
+                    .map(|(value, weight)| *value as f32 * weight)
+                    .sum::<f32>() / weight_sum;
+                suitability.put_pixel(x, z, image::Luma([value.round().min(255.0) as u8]));
             }
         }
 
+        inspection.save_image("A-07 suitability.png", &suitability);
+
+        suitability
+    }
+
+    fn agriculture(features: &Features, suitability: &GrayImage, inspection: &Inspection) -> (GrayImage, GrayImage) {
+        // Suitable area for "agriculture": Land suitable enough for farming.
+
+        let (x_len, z_len) = features.dimensions();
+
+        let agriculture = contrast::threshold(suitability, AGRICULTURE_SUITABILITY_TRESHOLD);
+
         let mut agriculture_without_trees = agriculture.clone();
 
         for x in 0..x_len as u32 {
@@ -134,12 +236,59 @@ impl Areas {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        {
-            agriculture.save("A-03 agriculture.png").unwrap();
-            agriculture_without_trees.save("A-04 agriculture without trees.png").unwrap();
-        }
+        inspection.save_image("A-03 agriculture.png", &agriculture);
+        inspection.save_image("A-04 agriculture without trees.png", &agriculture_without_trees);
 
         (agriculture, agriculture_without_trees)
     }
+
+    fn pasture(features: &Features, suitability: &GrayImage, inspection: &Inspection) -> GrayImage {
+        // Suitable area for "pasture":
+        // * fertile land, for the grass to grow
+        // * suitable enough for farming, if less so than fields need
+        // * steeper than fields allow, but not too steep for the livestock
+        let (x_len, z_len) = features.dimensions();
+        let mut pasture = image::ImageBuffer::new(x_len as u32, z_len as u32);
+
+        for x in 0..x_len as u32 {
+            for z in 0..z_len as u32 {
+                let image::Luma([value]) = suitability[(x, z)];
+                let image::Luma([slope]) = features.slope[(x, z)];
+                if image::Luma([255u8]) == features.fertile[(x, z)]
+                    && value > PASTURE_SUITABILITY_TRESHOLD
+                    && slope <= PASTURE_SLOPE_MAX {
+                    pasture.put_pixel(x, z, image::Luma([255u8]));
+                }
+            }
+        }
+
+        inspection.save_image("A-08 pasture.png", &pasture);
+
+        pasture
+    }
+
+    fn mines(features: &Features, inspection: &Inspection) -> GrayImage {
+        // Suitable area for "mines":
+        // * exposed stone or ore
+        // * not under water
+        let (x_len, z_len) = features.dimensions();
+        let mut mines = image::ImageBuffer::new(x_len as u32, z_len as u32);
+
+        for x in 0..x_len as u32 {
+            for z in 0..z_len as u32 {
+                if (image::Luma([255u8]) == features.stone[(x, z)]
+                    || image::Luma([255u8]) == features.exposed_ore[(x, z)])
+                    && image::Luma([255u8]) != features.water[(x, z)] {
+                    mines.put_pixel(x, z, image::Luma([255u8]));
+                }
+            }
+        }
+
+        morphology::close_mut(&mut mines, Norm::L1, MINE_CONNECTEDNESS_TRESHOLD);
+        morphology::open_mut(&mut mines, Norm::L1, 2 * MINE_CONNECTEDNESS_TRESHOLD);
+
+        inspection.save_image("A-05 mines.png", &mines);
+
+        mines
+    }
 }
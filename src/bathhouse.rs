@@ -0,0 +1,300 @@
+//! Bathhouses, for towns with lava near the surface below them: Pools heated by lava
+//! sealed in obsidian under a floor of soul sand, with changing rooms by the entrance
+//! and a boiler with a chimney in the corner of the bathing hall.
+
+use mcprogedit::block::{Block, Door, DoorHalf, Hinge};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{DoorMaterial, Material};
+use mcprogedit::positioning::{Surface2, Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_palette::BlockPalette;
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::partitioning;
+use crate::plot::{Plot, PlotEdgeKind};
+use crate::tree;
+use crate::types::Snake;
+
+use std::cmp::min;
+
+/// Half the width of the bathhouse, from its middle to the outer walls.
+const HALF_WIDTH: i64 = 4;
+/// Height of the walls, from the floor to the roof.
+const WALL_HEIGHT: i64 = 4;
+/// How far below the floor any lava is sealed off, around and under the bathhouse.
+const SEALING_DEPTH: i64 = 8;
+/// Shallowest and deepest below the ground lava may be, to count as safely below the town.
+const LAVA_DEPTH_MIN: i64 = 4;
+const LAVA_DEPTH_MAX: i64 = 16;
+/// Farthest from the lava below the town a bathhouse may be, to be heated by it.
+const LAVA_DISTANCE_MAX: usize = 32;
+
+/// A bathhouse on a courtyard plot, above lava near the surface.
+#[derive(Clone, Debug)]
+pub struct Bathhouse {
+    /// Index of the plot it is built on.
+    pub plot: usize,
+    /// Middle of the bathhouse.
+    pub center: BlockColumnCoord,
+    /// The side with the entrance, towards the street.
+    pub front: Surface4,
+}
+
+/// Find the lava near the surface, safely below the town within `wall_circle`,
+/// closest to the town center.
+pub fn lava_below_town(
+    excerpt: &WorldExcerpt,
+    features: &Features,
+    wall_circle: &Snake,
+    town_center: BlockColumnCoord,
+) -> Option<BlockCoord> {
+    let (x_len, _, z_len) = excerpt.dim();
+    let (town_offset, town_dimensions) = partitioning::snake_bounding_box(wall_circle);
+
+    let mut lava_below_town: Option<BlockCoord> = None;
+    for x in town_offset.0..min(x_len as i64, town_offset.0 + town_dimensions.0) {
+        for z in town_offset.1..min(z_len as i64, town_offset.1 + town_dimensions.1) {
+            let column = BlockColumnCoord(x, z);
+            if InOutSide::Inside != geometry::point_position_relative_to_polygon(column, wall_circle) {
+                continue;
+            }
+            let terrain_y = match features.terrain_height_map.height_at((x as usize, z as usize)) {
+                Some(terrain_y) => terrain_y as i64,
+                None => continue,
+            };
+            let lava = (LAVA_DEPTH_MIN..=LAVA_DEPTH_MAX)
+                .map(|depth| BlockCoord(x, terrain_y - depth, z))
+                .find(|coordinates| matches!(excerpt.block_at(*coordinates), Some(Block::LavaSource)));
+            if let Some(lava) = lava {
+                let is_closer = lava_below_town.map_or(true, |closest| {
+                    geometry::manhattan_distance(column, town_center)
+                        < geometry::manhattan_distance(closest.into(), town_center)
+                });
+                if is_closer {
+                    lava_below_town = Some(lava);
+                }
+            }
+        }
+    }
+    lava_below_town
+}
+
+/// Choose where to build the bathhouse, among the given plots and their indices: On the
+/// plot with room for one closest to the lava at `lava`, if any is close enough to be
+/// heated by it.
+pub fn plan_bathhouse<'a>(
+    plots: impl Iterator<Item = (usize, &'a Plot)>,
+    lava: BlockColumnCoord,
+    features: &Features,
+) -> Option<Bathhouse> {
+    let mut candidates: Vec<(usize, BlockColumnCoord, Surface4)> = plots
+        .filter_map(|(index, plot)| bathhouse_site(plot, features).map(|(center, front)| (index, center, front)))
+        .filter(|(_, center, _)| geometry::manhattan_distance(*center, lava) <= LAVA_DISTANCE_MAX)
+        .collect();
+    candidates.sort_by_key(|(index, center, _)| (geometry::manhattan_distance(*center, lava), *index));
+
+    candidates.first().map(|(plot, center, front)| Bathhouse { plot: *plot, center: *center, front: *front })
+}
+
+/// Find room for a bathhouse in the middle of the plot, with the entrance facing the
+/// closest street. Returns the middle of the bathhouse and the side of the entrance,
+/// if there is room for one.
+pub fn bathhouse_site(plot: &Plot, features: &Features) -> Option<(BlockColumnCoord, Surface4)> {
+    let polygon = plot.polygon();
+    let center = geometry::polygon_center(&polygon);
+    let (x_len, z_len) = features.dimensions();
+
+    let fits = (center.0 - HALF_WIDTH..=center.0 + HALF_WIDTH).all(|x| {
+        (center.1 - HALF_WIDTH..=center.1 + HALF_WIDTH).all(|z| {
+            x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64
+                && features.water[(x as u32, z as u32)] != image::Luma([255u8])
+                && geometry::point_position_relative_to_polygon(BlockColumnCoord(x, z), &polygon)
+                    == InOutSide::Inside
+        })
+    });
+    if !fits {
+        return None;
+    }
+
+    let street = plot.edges.iter()
+        .filter(|edge| matches!(edge.kind, PlotEdgeKind::Road { .. }))
+        .map(|edge| {
+            BlockColumnCoord(
+                (edge.points.0 .0 + edge.points.1 .0) / 2,
+                (edge.points.0 .2 + edge.points.1 .2) / 2,
+            )
+        })
+        .min_by_key(|middle| geometry::manhattan_distance(*middle, center))?;
+
+    let (dx, dz) = (street.0 - center.0, street.1 - center.1);
+    let front = if dx.abs() > dz.abs() {
+        if dx > 0 { Surface4::East } else { Surface4::West }
+    } else if dz > 0 {
+        Surface4::South
+    } else {
+        Surface4::North
+    };
+
+    Some((center, front))
+}
+
+/// Build the bathhouse, level with the highest ground under it.
+///
+/// Any lava around and under the bathhouse is first sealed off with obsidian. The lava
+/// heating the pools is then placed in a pocket lined with obsidian on every side but
+/// the top, which is covered by the soul sand floor of the pool.
+pub fn build_bathhouse(
+    excerpt: &mut WorldExcerpt,
+    bathhouse: &Bathhouse,
+    features: &Features,
+    palette: &BlockPalette,
+) {
+    let ground_at = |x: i64, z: i64| {
+        let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+        y as i64
+    };
+    let center = bathhouse.center;
+    let y = (center.0 - HALF_WIDTH..=center.0 + HALF_WIDTH)
+        .flat_map(|x| (center.1 - HALF_WIDTH..=center.1 + HALF_WIDTH).map(move |z| (x, z)))
+        .map(|(x, z)| ground_at(x, z))
+        .max()
+        .unwrap_or(0);
+
+    let front = bathhouse.front;
+    let right = front.rotated_90_cw();
+    let ahead = geometry::offset(front);
+    let side = geometry::offset(right);
+    let at = |along: i64, across: i64, up: i64| {
+        BlockCoord(
+            center.0 + ahead.0 * along + side.0 * across,
+            y + up,
+            center.1 + ahead.2 * along + side.2 * across,
+        )
+    };
+
+    // Seal off any lava that digging the pools could let loose
+    for along in -HALF_WIDTH - 1..=HALF_WIDTH + 1 {
+        for across in -HALF_WIDTH - 1..=HALF_WIDTH + 1 {
+            for up in -SEALING_DEPTH..=WALL_HEIGHT {
+                let coordinates = at(along, across, up);
+                if let Some(Block::LavaSource) | Some(Block::Lava { .. }) = excerpt.block_at(coordinates) {
+                    excerpt.set_block_at(coordinates, Block::Obsidian);
+                }
+            }
+        }
+    }
+
+    let is_pool = |along: i64, across: i64| (-2..=-1).contains(&along) && across.abs() <= 2;
+    let is_pool_lining = |along: i64, across: i64| {
+        (-3..=0).contains(&along) && across.abs() <= 3 && !is_pool(along, across)
+    };
+
+    for along in -HALF_WIDTH..=HALF_WIDTH {
+        for across in -HALF_WIDTH..=HALF_WIDTH {
+            // Level site, on a foundation
+            let BlockCoord(x, _, z) = at(along, across, 0);
+            let ground_y = ground_at(x, z);
+            for up in 0..=WALL_HEIGHT {
+                tree::chop(excerpt, at(along, across, up));
+                excerpt.set_block_at(at(along, across, up), Block::Air);
+            }
+            for fill_y in ground_y - 1..y - 1 {
+                excerpt.set_block_at(BlockCoord(x, fill_y, z), palette.foundation.clone());
+            }
+            excerpt.set_block_at(at(along, across, -1), palette.floor.clone());
+
+            // Heated pool: Water over soul sand, which lets bubbles up from the lava below
+            if is_pool(along, across) {
+                excerpt.set_block_at(at(along, across, -1), Block::WaterSource);
+                excerpt.set_block_at(at(along, across, -2), Block::WaterSource);
+                excerpt.set_block_at(at(along, across, -3), Block::SoulSand);
+                excerpt.set_block_at(at(along, across, -4), Block::LavaSource);
+                excerpt.set_block_at(at(along, across, -5), Block::Obsidian);
+            } else if is_pool_lining(along, across) {
+                excerpt.set_block_at(at(along, across, -2), Block::StoneBricks);
+                excerpt.set_block_at(at(along, across, -3), Block::StoneBricks);
+                excerpt.set_block_at(at(along, across, -4), Block::Obsidian);
+                excerpt.set_block_at(at(along, across, -5), Block::Obsidian);
+            }
+
+            // Outer walls, and the walls around the changing rooms by the entrance
+            let is_outer_wall = along.abs() == HALF_WIDTH || across.abs() == HALF_WIDTH;
+            let is_partition = (along == 1 && across != 0) || (along >= 1 && across.abs() == 1);
+            if is_outer_wall || is_partition {
+                for up in 0..WALL_HEIGHT {
+                    excerpt.set_block_at(at(along, across, up), palette.wall.clone());
+                }
+            }
+
+            // Flat roof, with a skylight over the pool
+            let roof_block = if is_pool(along, across) {
+                Block::Glass { colour: None }
+            } else if is_outer_wall {
+                palette.wall.clone()
+            } else {
+                palette.floor.clone()
+            };
+            excerpt.set_block_at(at(along, across, WALL_HEIGHT), roof_block);
+        }
+    }
+
+    // Windows along the sides of the bathing hall
+    for across in &[-HALF_WIDTH, HALF_WIDTH] {
+        for along in -2..=-1 {
+            for up in 1..=2 {
+                excerpt.set_block_at(at(along, *across, up), Block::Glass { colour: None });
+            }
+        }
+    }
+
+    // Entrance door, with a step in front of it
+    for (up, half) in &[(0, DoorHalf::Lower), (1, DoorHalf::Upper)] {
+        excerpt.set_block_at(at(HALF_WIDTH, 0, *up), Block::Door(Door {
+            material: DoorMaterial::Spruce,
+            facing: front.opposite(),
+            half: *half,
+            hinged_at: Hinge::Right,
+            open: false,
+        }));
+    }
+    excerpt.set_block_at(at(HALF_WIDTH + 1, 0, -1), palette.foundation.clone());
+    for up in 0..2 {
+        tree::chop(excerpt, at(HALF_WIDTH + 1, 0, up));
+        excerpt.set_block_at(at(HALF_WIDTH + 1, 0, up), Block::Air);
+    }
+
+    // Changing rooms on either side of the entrance, with a bench and a barrel for clothes
+    for (sign, direction) in &[(-1, right.opposite()), (1, right)] {
+        for up in 0..2 {
+            excerpt.set_block_at(at(2, *sign, up), Block::Air);
+        }
+        excerpt.set_block_at(at(3, 3 * sign, 0), Block::stairs((*direction).into(), Material::Spruce));
+        excerpt.set_block_at(at(2, 3 * sign, 0), Block::barrel(Surface6::Up));
+        excerpt.set_block_at(
+            at(3, 2 * sign, WALL_HEIGHT - 1),
+            Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+        );
+    }
+
+    // Benches along the back wall of the bathing hall
+    for across in -3..=1 {
+        excerpt.set_block_at(at(-3, across, 0), Block::stairs(front.opposite().into(), Material::Spruce));
+    }
+    for across in &[-3, 3] {
+        excerpt.set_block_at(
+            at(0, *across, WALL_HEIGHT - 1),
+            Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+        );
+    }
+
+    // Boiler in the corner, with a chimney up through the roof
+    excerpt.set_block_at(at(-3, 3, 0), Block::furnace(right.opposite()));
+    for up in 1..WALL_HEIGHT + 2 {
+        excerpt.set_block_at(at(-3, 3, up), Block::Cobblestone);
+    }
+    excerpt.set_block_at(
+        at(-3, 3, WALL_HEIGHT + 2),
+        Block::Campfire { facing: Surface4::North, lit: true, waterlogged: false },
+    );
+}
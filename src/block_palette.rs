@@ -1,16 +1,76 @@
+use crate::features::Features;
+use crate::partitioning;
+use crate::types::Snake;
+
+use log::info;
 use mcprogedit::block::Block;
-use mcprogedit::block::Flower;
+use mcprogedit::block::{Flower, Log};
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::material::{Material, WoodMaterial};
+use mcprogedit::positioning::{Axis3, Surface2};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use std::cmp::{min, Reverse};
+use std::collections::{HashMap, HashSet};
+
+/// How far out from the town to look for local materials.
+const SURVEY_REACH: i64 = 100;
+/// Smallest share of the ground around the town covered by basalt or blackstone, for
+/// building in it in the Nether, rather than in nether brick.
+const NETHER_STONE_SHARE_MIN: f32 = 0.1;
+
+/// The style of building suited to the surroundings of the town.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BiomeStyle {
+    /// Stone, brick and timber, for grassland and forest.
+    Temperate,
+    /// Sandstone, with covered cisterns instead of open wells, rain barrels on the roofs,
+    /// and pergolas shading the courtyards.
+    Desert,
+    /// Spruce and stone with details of packed ice, steep roofs to shed the snow, snow
+    /// lying on the roofs and the roads, and lanterns instead of open torches.
+    Snowy,
+    /// Mossy stone and dark timber, with the houses on stilts above the wet ground, and
+    /// boardwalks for streets over the shallow water.
+    Swamp,
+    /// Jungle wood and cobblestone, overgrown with moss, with vines hanging from the roofs
+    /// and down the walls.
+    Jungle,
+    /// Terracotta and red sandstone, for the badlands, with mostly flat roof terraces.
+    Badlands,
+    /// Nether brick, blackstone or basalt, for the Nether, with low flat roofs under the
+    /// ceiling and enclosed walkways for streets.
+    Nether,
+}
+
+/// Preferred orientation of gable roof ridges, relative to the street in front of the building.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RidgeOrientation {
+    /// Ridge along the long axis of the building, regardless of the street.
+    LongAxis,
+    /// Ridge parallel to the fronting street, e.g. for row-like blocks.
+    ParallelToStreet,
+    /// Gable end facing the street, e.g. for landmark buildings.
+    GableToStreet,
+}
 
 #[derive(Clone)]
 pub struct BlockPalette {
     pub city_wall_coronation: Block,
     pub city_wall_main: Block,
     pub city_wall_top: Block,
+    /// The style of building, and which variants of the town details to build.
+    pub biome: BiomeStyle,
     pub flat_window: Block,
     pub floor: Block,
     pub flowers: Vec<Flower>,
     pub foundation: Block,
+    pub palisade: Block,
     pub roof: Block,
+    pub roof_ridge: RidgeOrientation,
+    pub roof_stairs: Material,
+    /// Snow layer to lay on the roofs and the roads, for the snowy style.
+    pub snow_layer: Option<Block>,
     pub wall: Block,
 }
 
@@ -20,12 +80,376 @@ impl Default for BlockPalette {
             city_wall_coronation: Block::Cobblestone,
             city_wall_main: Block::StoneBricks,
             city_wall_top: Block::StoneBricks,
+            biome: BiomeStyle::Temperate,
             flat_window: Block::glass_pane(),
             floor: Block::dark_oak_planks(),
             flowers: Vec::new(),
             foundation: Block::StoneBricks,
+            palisade: Block::oak_log(Axis3::Y),
             roof: Block::BrickBlock,
+            roof_ridge: RidgeOrientation::ParallelToStreet,
+            roof_stairs: Material::Brick,
+            snow_layer: None,
             wall: Block::Cobblestone,
         }
     }
 }
+
+impl BlockPalette {
+    /// A light standing on the block below: A torch, or a lantern in the snowy style.
+    pub fn standing_light(&self) -> Block {
+        if self.biome == BiomeStyle::Snowy {
+            Block::Lantern { mounted_at: Surface2::Down, waterlogged: false }
+        } else {
+            Block::torch()
+        }
+    }
+
+    /// A variant of the palette for the plot with the given index,
+    /// depending on the diversity of available wood.
+    pub fn for_plot(&self, wood_available: &[WoodMaterial], index: usize) -> BlockPalette {
+        let mut custom_palette = self.clone();
+        if wood_available.is_empty() {
+            // Sadly no wood to use here.
+            // Replace some roofs with other materials
+            match index % 7 {
+                0 | 2 | 4 => custom_palette.roof = custom_palette.floor.clone(),
+                _ => (),
+            }
+        } else if wood_available.len() == 1 {
+            // Replace most walls with the available wood
+            match index % 4 {
+                0 | 1 | 2 => {
+                    custom_palette.foundation = self.wall.clone();
+                    custom_palette.wall = Block::Planks { material: wood_available[0] };
+                }
+                // If the walls were not replaced, replace the floor instead.
+                _ => {
+                    custom_palette.floor = Block::Planks { material: wood_available[0] };
+                },
+            }
+            // Replace some roofs with other materials
+            match index % 7 {
+                0 | 2 | 4 => custom_palette.roof = custom_palette.floor.clone(),
+                _ => (),
+            }
+        } else if wood_available.len() == 2 {
+            // Replace all roofs with one kind of wood.
+            custom_palette.roof = Block::Planks { material: wood_available[0] };
+            // Replace most walls with the other kind of wood.
+            match index % 4 {
+                0 | 1 | 2 => {
+                    custom_palette.foundation = self.wall.clone();
+                    custom_palette.wall = Block::Planks { material: wood_available[1] };
+                }
+                // If the walls were not replaced, replace the floor instead.
+                _ => {
+                    custom_palette.floor = Block::Planks { material: wood_available[1] };
+                },
+            }
+            // Replace some roofs with other materials
+            match index % 7 {
+                0 | 2 | 4 => custom_palette.roof = custom_palette.floor.clone(),
+                _ => (),
+            }
+        } else {
+            // Replace all roofs with one kind of wood.
+            custom_palette.roof = Block::Planks { material: wood_available[1] };
+            // Replace most walls with one of the other kinds of wood.
+            match index % 4 {
+                0 | 1 | 2 => {
+                    custom_palette.foundation = self.wall.clone();
+                    custom_palette.wall = Block::Planks { material: wood_available[2] };
+                }
+                _ => (),
+            }
+            // Replace quite a few floors with the other remaining kind of wood.
+            match index % 5 {
+                0 | 1 | 2 => {
+                    custom_palette.floor = Block::Planks { material: wood_available[0] };
+                }
+                _ => (),
+            }
+            // Replace some roofs with other materials
+            match index % 7 {
+                0 | 4 => custom_palette.roof = custom_palette.floor.clone(),
+                2 | 6 => custom_palette.roof = self.roof.clone(),
+                _ => (),
+            }
+        }
+
+        // Stair roofs in the same material as the roof, where there are such stairs.
+        if let Some(material) = stairs_material(&custom_palette.roof) {
+            custom_palette.roof_stairs = material;
+        }
+
+        custom_palette
+    }
+}
+
+/// The material of stairs matching the given full block, if there are such stairs.
+pub fn stairs_material(block: &Block) -> Option<Material> {
+    match block {
+        Block::BrickBlock => Some(Material::Brick),
+        Block::Cobblestone => Some(Material::Cobblestone),
+        Block::RedSandstone => Some(Material::RedSandstone),
+        Block::Sandstone => Some(Material::Sandstone),
+        Block::SmoothSandstone => Some(Material::SmoothSandstone),
+        Block::StoneBricks => Some(Material::StoneBrick),
+        Block::MossyStoneBricks => Some(Material::MossyStoneBrick),
+        Block::NetherBricks => Some(Material::NetherBrick),
+        Block::Planks { material } => Some(match material {
+            WoodMaterial::Acacia => Material::Acacia,
+            WoodMaterial::Birch => Material::Birch,
+            WoodMaterial::DarkOak => Material::DarkOak,
+            WoodMaterial::Jungle => Material::Jungle,
+            WoodMaterial::Spruce => Material::Spruce,
+            _ => Material::Oak,
+        }),
+        _ => None,
+    }
+}
+
+/// A weathered variant of the given block of stone: Overgrown with moss if `mossy`, or else
+/// cracked. Returns `None` for blocks without such a variant.
+pub fn weathered(block: &Block, mossy: bool) -> Option<Block> {
+    match (block, mossy) {
+        (Block::StoneBricks, true) => Some(Block::MossyStoneBricks),
+        (Block::StoneBricks, false) => Some(Block::CrackedStoneBricks),
+        (Block::Cobblestone, true) => Some(Block::MossyCobblestone),
+        _ => None,
+    }
+}
+
+/// Whether the block is open air, or outside the excerpt.
+pub fn is_open(block: Option<&Block>) -> bool {
+    matches!(block, None | Some(Block::Air) | Some(Block::None))
+}
+
+/// Whether the block is a window: A block of glass, or a glass pane.
+pub fn is_window(block: &Block) -> bool {
+    matches!(block, Block::Glass { .. }) || *block == Block::glass_pane()
+}
+
+/// The building materials found at the surface in and around a town.
+pub struct MaterialSurvey {
+    /// Default palette for the town, in the building style suited to the materials found.
+    pub palette: BlockPalette,
+    /// The woods common enough around the town to build with, sorted by colour.
+    pub wood_available: Vec<WoodMaterial>,
+    /// Whether there is hardly any exposed stone around the town.
+    pub is_stone_scarce: bool,
+}
+
+/// Survey the surface in and around the town within `wall_circle` for local materials,
+/// and decide on the building style from them. Builds in the Nether style if `is_nether`,
+/// and in the swamp style if `is_stilt_village`, unless in desert or badlands.
+pub fn survey_materials(
+    excerpt: &WorldExcerpt,
+    features: &Features,
+    wall_circle: &Snake,
+    is_nether: bool,
+    is_stilt_village: bool,
+) -> MaterialSurvey {
+    let (x_len, _, z_len) = excerpt.dim();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    let (town_offset, town_dimensions) = partitioning::snake_bounding_box(wall_circle);
+
+    let proximity_min_x = town_offset.0.saturating_sub(SURVEY_REACH);
+    let proximity_max_x = min(x_len, town_offset.0 + town_dimensions.0 + SURVEY_REACH);
+    let proximity_min_z = town_offset.1.saturating_sub(SURVEY_REACH);
+    let proximity_max_z = min(z_len, town_offset.1 + town_dimensions.0 + SURVEY_REACH);
+
+    let mut sand_count = 0;
+    let mut grass_count = 0;
+    let mut stone_count = 0;
+    let mut snow_count = 0;
+    let mut snow_layer = None;
+    let mut badlands_count = 0;
+    let mut terracotta = None;
+    let mut basalt_count = 0;
+    let mut basalt = None;
+    let mut blackstone_count = 0;
+    let mut blackstone = None;
+    let mut column_count = 0;
+    let mut available_flowers = HashSet::new();
+    let mut wood_statistics = HashMap::new();
+
+    for x in proximity_min_x..proximity_max_x {
+        for z in proximity_min_z..proximity_max_z {
+            if let Some(terrain_y) = features.terrain_height_map.height_at(
+                (x as usize, z as usize)
+            ) {
+                column_count += 1;
+                for y in terrain_y-1..terrain_y+1 {
+                    match excerpt.block_at(BlockCoord(x, y as i64, z)) {
+                        // Make some statistics
+                        Some(Block::Sand) => sand_count += 1,
+                        Some(Block::GrassBlock) => grass_count += 1,
+                        Some(block @ Block::Snow { .. }) => {
+                            snow_count += 1;
+                            snow_layer.get_or_insert_with(|| block.clone());
+                        }
+                        Some(Block::SnowBlock)
+                        | Some(Block::Ice)
+                        | Some(Block::BlueIce) => snow_count += 1,
+                        Some(block @ Block::Terracotta { .. }) => {
+                            badlands_count += 1;
+                            terracotta.get_or_insert_with(|| block.clone());
+                        }
+                        Some(Block::RedSand) => badlands_count += 1,
+                        Some(block @ Block::Basalt { .. }) => {
+                            basalt_count += 1;
+                            stone_count += 1;
+                            basalt.get_or_insert_with(|| block.clone());
+                        }
+                        Some(block @ Block::Blackstone { .. }) => {
+                            blackstone_count += 1;
+                            stone_count += 1;
+                            blackstone.get_or_insert_with(|| block.clone());
+                        }
+                        Some(Block::Stone)
+                        | Some(Block::Andesite)
+                        | Some(Block::Diorite)
+                        | Some(Block::Granite)
+                        | Some(Block::Sandstone)
+                        | Some(Block::RedSandstone)
+                        | Some(Block::Netherrack) => stone_count += 1,
+                        Some(Block::Flower(flower)) => {
+                            available_flowers.insert(*flower);
+                        }
+                        Some(Block::Log(Log { material, .. })) => {
+                            *wood_statistics.entry(*material).or_insert(0) += 1;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+
+    // Most common first, with ties broken by name, so the same seed gives the same palette
+    let mut wood_statistics: Vec<_> = wood_statistics.into_iter().collect();
+    wood_statistics.sort_by_key(|(wood, count)| (Reverse(*count), format!("{:?}", wood)));
+
+    // wood_available to be used later, for replacing wall/roof materials in the
+    // block palette used for building individual houses.
+    let mut wood_available = Vec::new();
+    let most_common_wood = wood_statistics.first().map(|(wood, _)| *wood);
+    let max_wood_count = if let Some((_, count)) = wood_statistics.first() {
+        *count
+    } else {
+        0
+    };
+    for (wood, count) in wood_statistics {
+        if count >= max_wood_count / 50 {
+            wood_available.push(wood);
+        }
+    }
+    // Sort the woods by colour in order not to get too psychedelic.
+    wood_available.sort_by_key(|wood_material| match wood_material {
+        WoodMaterial::Acacia => 5,
+        WoodMaterial::Birch => 4,
+        WoodMaterial::DarkOak => 0,
+        WoodMaterial::Jungle => 3,
+        WoodMaterial::Oak => 2,
+        WoodMaterial::Spruce => 1,
+        _ => 6,
+    });
+
+    info!("Decided that {:?} are the common wood materials.", wood_available);
+
+    // Use found materials for a default block palette
+    let mut block_palette = BlockPalette {
+        flowers: available_flowers.clone().into_iter().collect(),
+        ..Default::default()
+    };
+
+    if is_nether {
+        // The Nether; build in nether brick, or in the basalt or the blackstone
+        // around the town where there is plenty of it, on floors of crimson planks.
+        let (local_stone_count, local_stone) = if basalt_count > blackstone_count {
+            (basalt_count, basalt)
+        } else {
+            (blackstone_count, blackstone)
+        };
+        let local_stone = local_stone
+            .filter(|_| local_stone_count as f32 >= NETHER_STONE_SHARE_MIN * column_count as f32);
+        block_palette.biome = BiomeStyle::Nether;
+        block_palette.city_wall_coronation = Block::NetherBricks;
+        block_palette.city_wall_main = local_stone.clone().unwrap_or(Block::NetherBricks);
+        block_palette.city_wall_top = Block::NetherBricks;
+        block_palette.foundation = Block::NetherBricks;
+        block_palette.floor = Block::Planks { material: WoodMaterial::Crimson };
+        block_palette.roof = Block::NetherBricks;
+        block_palette.roof_stairs = Material::NetherBrick;
+        block_palette.wall = local_stone.unwrap_or(Block::NetherBricks);
+    } else if sand_count > grass_count {
+        // Assume that we are in or close to a desert biome;
+        // Use sandstone instead of stone, for city wall and other "stone" structures.
+        block_palette.city_wall_coronation = Block::Sandstone;
+        block_palette.city_wall_main = Block::Sandstone;
+        block_palette.city_wall_top = Block::SmoothSandstone;
+        block_palette.biome = BiomeStyle::Desert;
+        block_palette.foundation = Block::EndStoneBricks;
+        block_palette.floor = Block::SmoothSandstone;
+        block_palette.wall = Block::Sandstone;
+    } else if badlands_count > grass_count {
+        // Red sand and terracotta; build in the colours of the badlands.
+        block_palette.biome = BiomeStyle::Badlands;
+        block_palette.city_wall_coronation = Block::RedSandstone;
+        block_palette.city_wall_main = Block::RedSandstone;
+        block_palette.city_wall_top = Block::RedSandstone;
+        block_palette.foundation = Block::RedSandstone;
+        block_palette.roof = Block::RedSandstone;
+        block_palette.roof_stairs = Material::RedSandstone;
+        block_palette.wall = terracotta.unwrap_or(Block::RedSandstone);
+    } else if is_stilt_village {
+        // Swamp or shallow water over most of the town; build on stilts, with mossy
+        // stone and dark timber.
+        block_palette.biome = BiomeStyle::Swamp;
+        block_palette.city_wall_coronation = Block::MossyCobblestone;
+        block_palette.city_wall_main = Block::MossyStoneBricks;
+        block_palette.city_wall_top = Block::MossyStoneBricks;
+        block_palette.foundation = Block::MossyStoneBricks;
+        block_palette.roof = Block::dark_oak_planks();
+        block_palette.roof_stairs = Material::DarkOak;
+        block_palette.wall = Block::MossyCobblestone;
+    } else if most_common_wood == Some(WoodMaterial::Jungle) {
+        // Jungle trees all around; build in jungle wood and cobblestone, mossy
+        // where it meets the damp ground.
+        block_palette.biome = BiomeStyle::Jungle;
+        block_palette.city_wall_coronation = Block::MossyCobblestone;
+        block_palette.foundation = Block::MossyCobblestone;
+        block_palette.floor = Block::Planks { material: WoodMaterial::Jungle };
+        block_palette.roof = Block::Planks { material: WoodMaterial::Jungle };
+        block_palette.roof_stairs = Material::Jungle;
+        wood_available = vec![WoodMaterial::Jungle];
+    } else if snow_count * 3 > column_count {
+        // Snow on a third of the ground or more; build for the cold,
+        // in spruce and stone with packed ice details.
+        block_palette.biome = BiomeStyle::Snowy;
+        block_palette.city_wall_coronation = Block::BlueIce;
+        block_palette.city_wall_main = Block::Stone;
+        block_palette.foundation = Block::Stone;
+        block_palette.floor = Block::Planks { material: WoodMaterial::Spruce };
+        block_palette.roof = Block::Planks { material: WoodMaterial::Spruce };
+        block_palette.roof_stairs = Material::Spruce;
+        block_palette.snow_layer = snow_layer;
+        block_palette.wall = Block::Stone;
+        wood_available = vec![WoodMaterial::Spruce];
+    }
+    info!("Decided on the {:?} building style.", block_palette.biome);
+
+    info!(
+        "Found {} different flowers.",
+        available_flowers.len(),
+    );
+
+    MaterialSurvey {
+        palette: block_palette,
+        wood_available,
+        is_stone_scarce: stone_count * 100 < column_count,
+    }
+}
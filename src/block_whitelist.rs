@@ -0,0 +1,315 @@
+//! Keeping to a whitelist of blocks, for themed servers: Every block placed by the
+//! generator is checked against the whitelist, and can be replaced by an allowed
+//! alternative.
+
+use std::collections::BTreeMap;
+
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::positioning::Surface2;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+/// The name of the kind of block, in snake case, such as "stone_bricks" or "planks".
+/// Blocks of different materials or colours share the same name.
+///
+/// The blocks the generator places have their names listed, so that whitelists keep
+/// working across changes to how blocks are printed. Other blocks, only found in the
+/// world, are named from their debug output.
+pub fn block_name(block: &Block) -> String {
+    match listed_name(block) {
+        Some(name) => name.to_string(),
+        None => debug_name(block),
+    }
+}
+
+/// The listed name of the kind of block, for the blocks the generator places.
+fn listed_name(block: &Block) -> Option<&'static str> {
+    let name = match block {
+        Block::Air => "air",
+        Block::None => "none",
+        Block::Andesite => "andesite",
+        Block::Anvil { .. } => "anvil",
+        Block::Banner { .. } => "banner",
+        Block::Basalt { .. } => "basalt",
+        Block::Bed { .. } => "bed",
+        Block::Blackstone { .. } => "blackstone",
+        Block::BlockOfGold => "block_of_gold",
+        Block::BlueIce => "blue_ice",
+        Block::Bookshelf => "bookshelf",
+        Block::BrickBlock => "brick_block",
+        Block::BrownMushroom => "brown_mushroom",
+        Block::Campfire { .. } => "campfire",
+        Block::Carrots { .. } => "carrots",
+        Block::Cauldron { .. } => "cauldron",
+        Block::Chain { .. } => "chain",
+        Block::Chest { .. } => "chest",
+        Block::ChiseledStoneBricks => "chiseled_stone_bricks",
+        Block::CoalOre => "coal_ore",
+        Block::CoarseDirt => "coarse_dirt",
+        Block::Cobblestone => "cobblestone",
+        Block::Cobweb => "cobweb",
+        Block::Concrete { .. } => "concrete",
+        Block::CoralBlock { .. } => "coral_block",
+        Block::CrackedStoneBricks => "cracked_stone_bricks",
+        Block::DiamondOre => "diamond_ore",
+        Block::Diorite => "diorite",
+        Block::Dirt => "dirt",
+        Block::Door { .. } => "door",
+        Block::EmeraldOre => "emerald_ore",
+        Block::EndStoneBricks => "end_stone_bricks",
+        Block::Farmland { .. } => "farmland",
+        Block::Fence { .. } => "fence",
+        Block::FenceGate { .. } => "fence_gate",
+        Block::Flower { .. } => "flower",
+        Block::FlowerPot { .. } => "flower_pot",
+        Block::FrostedIce => "frosted_ice",
+        Block::Furnace { .. } => "furnace",
+        Block::Glass { .. } => "glass",
+        Block::GoldOre => "gold_ore",
+        Block::Granite => "granite",
+        Block::Grass { .. } => "grass",
+        Block::GrassBlock => "grass_block",
+        Block::GrassPath => "grass_path",
+        Block::Gravel => "gravel",
+        Block::HayBale { .. } => "hay_bale",
+        Block::Head { .. } => "head",
+        Block::Ice => "ice",
+        Block::IronBars { .. } => "iron_bars",
+        Block::IronOre => "iron_ore",
+        Block::Jukebox { .. } => "jukebox",
+        Block::Ladder { .. } => "ladder",
+        Block::Lantern { .. } => "lantern",
+        Block::LapisLazuliOre => "lapis_lazuli_ore",
+        Block::Lava { .. } => "lava",
+        Block::LavaSource => "lava_source",
+        Block::Leaves { .. } => "leaves",
+        Block::Lectern { .. } => "lectern",
+        Block::Log { .. } => "log",
+        Block::MossyCobblestone => "mossy_cobblestone",
+        Block::MossyStoneBricks => "mossy_stone_bricks",
+        Block::NetherBricks => "nether_bricks",
+        Block::Netherrack => "netherrack",
+        Block::Obsidian => "obsidian",
+        Block::Planks { .. } => "planks",
+        Block::Podzol => "podzol",
+        Block::Potatoes { .. } => "potatoes",
+        Block::Rail { .. } => "rail",
+        Block::RedMushroom => "red_mushroom",
+        Block::RedSand => "red_sand",
+        Block::RedSandstone => "red_sandstone",
+        Block::RedstoneOre => "redstone_ore",
+        Block::Sand => "sand",
+        Block::Sandstone => "sandstone",
+        Block::Sapling { .. } => "sapling",
+        Block::Scaffolding { .. } => "scaffolding",
+        Block::SeaPickle { .. } => "sea_pickle",
+        Block::Sign { .. } => "sign",
+        Block::Slab { .. } => "slab",
+        Block::SmithingTable => "smithing_table",
+        Block::SmoothSandstone => "smooth_sandstone",
+        Block::Snow { .. } => "snow",
+        Block::SnowBlock => "snow_block",
+        Block::SoulSand => "soul_sand",
+        Block::Stairs { .. } => "stairs",
+        Block::Stone => "stone",
+        Block::StoneBricks => "stone_bricks",
+        Block::Target => "target",
+        Block::Terracotta { .. } => "terracotta",
+        Block::Torch { .. } => "torch",
+        Block::Tripwire => "tripwire",
+        Block::TurtleEgg { .. } => "turtle_egg",
+        Block::Vines { .. } => "vines",
+        Block::Water { .. } => "water",
+        Block::WaterSource => "water_source",
+        Block::Wheat { .. } => "wheat",
+        Block::Wool { .. } => "wool",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The name of the kind of block, from the name of its variant in the debug output.
+fn debug_name(block: &Block) -> String {
+    let debug = format!("{:?}", block);
+    let mut name = String::new();
+    for (index, character) in debug.chars().take_while(|character| character.is_alphanumeric()).enumerate() {
+        if character.is_uppercase() && index > 0 {
+            name.push('_');
+        }
+        name.extend(character.to_lowercase());
+    }
+    name
+}
+
+/// Whether the block may be placed. Clearing blocks away is always allowed.
+pub fn is_allowed(block: &Block, whitelist: &[String]) -> bool {
+    matches!(block, Block::Air | Block::None) || whitelist.contains(&block_name(block))
+}
+
+/// Blocks that can stand in for the given block, from the closest match.
+fn alternatives(block: &Block) -> Vec<Block> {
+    match block {
+        Block::EndStoneBricks => vec![Block::StoneBricks, Block::Sandstone, Block::Cobblestone],
+        Block::SmoothSandstone => vec![Block::Sandstone, Block::StoneBricks, Block::Cobblestone],
+        Block::Sandstone => vec![Block::StoneBricks, Block::Cobblestone],
+        Block::StoneBricks => vec![Block::Cobblestone, Block::Stone],
+        Block::CrackedStoneBricks | Block::ChiseledStoneBricks => {
+            vec![Block::StoneBricks, Block::Cobblestone, Block::Stone]
+        }
+        Block::Andesite | Block::Obsidian => vec![Block::Cobblestone, Block::Stone],
+        Block::BrickBlock => vec![Block::StoneBricks, Block::Cobblestone],
+        Block::Cobblestone => vec![Block::Stone, Block::StoneBricks],
+        Block::CoralBlock { .. } => vec![Block::Gravel, Block::Cobblestone, Block::CoarseDirt],
+        Block::Gravel => vec![Block::CoarseDirt, Block::Cobblestone],
+        Block::CoarseDirt => vec![Block::Gravel, Block::Dirt],
+        Block::Concrete { colour } => vec![Block::Wool { colour: *colour }, Block::Cobblestone],
+        Block::Lantern { mounted_at: Surface2::Down, .. } => vec![Block::torch()],
+        _ => Vec::new(),
+    }
+}
+
+/// An allowed block that can stand in for the given block, if there is one.
+pub fn substitute(block: &Block, whitelist: &[String]) -> Option<Block> {
+    alternatives(block).into_iter().find(|alternative| is_allowed(alternative, whitelist))
+}
+
+/// Check every block placed by the generator, that is every block of `excerpt` differing
+/// from `original`, against the whitelist. With `strict`, blocks not on the whitelist are
+/// replaced by allowed alternatives where there are any.
+///
+/// Returns the number of blocks left that are not on the whitelist, by name.
+pub fn enforce_whitelist(
+    excerpt: &mut WorldExcerpt,
+    original: &WorldExcerpt,
+    whitelist: &[String],
+    strict: bool,
+) -> BTreeMap<String, usize> {
+    let mut violations: BTreeMap<String, usize> = BTreeMap::new();
+    let mut substitution_count = 0;
+
+    let (x_len, y_len, z_len) = excerpt.dim();
+    for x in 0..x_len as i64 {
+        for y in 0..y_len as i64 {
+            for z in 0..z_len as i64 {
+                let coordinates = BlockCoord(x, y, z);
+                let block = match excerpt.block_at(coordinates) {
+                    Some(block) if original.block_at(coordinates) != Some(block) => block.clone(),
+                    _ => continue,
+                };
+                if is_allowed(&block, whitelist) {
+                    continue;
+                }
+                match substitute(&block, whitelist) {
+                    Some(alternative) if strict => {
+                        excerpt.set_block_at(coordinates, alternative);
+                        substitution_count += 1;
+                    }
+                    _ => *violations.entry(block_name(&block)).or_insert(0) += 1,
+                }
+            }
+        }
+    }
+
+    if strict {
+        info!("Substituted {} blocks not on the block whitelist.", substitution_count);
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mcprogedit::material::WoodMaterial;
+
+    fn whitelist(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// A small excerpt of air, with the given blocks set.
+    fn excerpt_with(blocks: &[(BlockCoord, Block)]) -> WorldExcerpt {
+        let mut excerpt = WorldExcerpt::new(2, 2, 2);
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    excerpt.set_block_at(BlockCoord(x, y, z), Block::Air);
+                }
+            }
+        }
+        for (coordinates, block) in blocks {
+            excerpt.set_block_at(*coordinates, block.clone());
+        }
+        excerpt
+    }
+
+    /// Excerpts before and after generation, with a block of andesite left untouched,
+    /// and stone bricks, gravel, stone and a bookshelf placed.
+    fn excerpts() -> (WorldExcerpt, WorldExcerpt) {
+        let untouched = (BlockCoord(1, 1, 1), Block::Andesite);
+        let original = excerpt_with(&[untouched.clone()]);
+        let excerpt = excerpt_with(&[
+            untouched,
+            (BlockCoord(0, 0, 0), Block::StoneBricks),
+            (BlockCoord(1, 0, 0), Block::Gravel),
+            (BlockCoord(0, 1, 0), Block::Stone),
+            (BlockCoord(0, 0, 1), Block::Bookshelf),
+        ]);
+        (original, excerpt)
+    }
+
+    #[test]
+    fn block_names() {
+        assert_eq!("stone_bricks", block_name(&Block::StoneBricks));
+        assert_eq!("planks", block_name(&Block::Planks { material: WoodMaterial::Spruce }));
+        assert_eq!("planks", block_name(&Block::Planks { material: WoodMaterial::Oak }));
+    }
+
+    #[test]
+    fn listed_names_match_debug_names() {
+        for block in &[Block::Air, Block::StoneBricks, Block::LapisLazuliOre, Block::Bookshelf] {
+            assert_eq!(debug_name(block), block_name(block));
+        }
+    }
+
+    #[test]
+    fn clearing_is_allowed() {
+        assert!(is_allowed(&Block::Air, &[]));
+        assert!(!is_allowed(&Block::Stone, &[]));
+    }
+
+    #[test]
+    fn substitute_closest_allowed() {
+        assert_eq!(Some(Block::Cobblestone), substitute(&Block::StoneBricks, &whitelist(&["stone", "cobblestone"])));
+        assert_eq!(Some(Block::Stone), substitute(&Block::StoneBricks, &whitelist(&["stone"])));
+        assert_eq!(None, substitute(&Block::StoneBricks, &whitelist(&["dirt"])));
+        assert_eq!(None, substitute(&Block::Bookshelf, &whitelist(&["stone", "cobblestone"])));
+    }
+
+    #[test]
+    fn enforce_whitelist_counts_placed_blocks() {
+        let (original, mut excerpt) = excerpts();
+
+        let violations = enforce_whitelist(&mut excerpt, &original, &whitelist(&["stone", "cobblestone"]), false);
+
+        let expected: BTreeMap<String, usize> = [("bookshelf", 1), ("gravel", 1), ("stone_bricks", 1)].iter()
+            .map(|(name, count)| (name.to_string(), *count))
+            .collect();
+        assert_eq!(expected, violations);
+        assert_eq!(Some(&Block::StoneBricks), excerpt.block_at(BlockCoord(0, 0, 0)));
+    }
+
+    #[test]
+    fn enforce_whitelist_strictly() {
+        let (original, mut excerpt) = excerpts();
+
+        let violations = enforce_whitelist(&mut excerpt, &original, &whitelist(&["stone", "cobblestone"]), true);
+
+        let expected: BTreeMap<String, usize> = std::iter::once(("bookshelf".to_string(), 1)).collect();
+        assert_eq!(expected, violations);
+        assert_eq!(Some(&Block::Cobblestone), excerpt.block_at(BlockCoord(0, 0, 0)));
+        assert_eq!(Some(&Block::Cobblestone), excerpt.block_at(BlockCoord(1, 0, 0)));
+        assert_eq!(Some(&Block::Stone), excerpt.block_at(BlockCoord(0, 1, 0)));
+        assert_eq!(Some(&Block::Andesite), excerpt.block_at(BlockCoord(1, 1, 1)));
+    }
+}
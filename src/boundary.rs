@@ -0,0 +1,206 @@
+//! User-supplied build boundaries: A polygon to keep the settlement inside, for building
+//! in areas other than the axis-aligned box of the selection.
+//!
+//! Boundaries are read from GeoJSON, with the same planar coordinates as the GeoJSON export:
+//! Block x as easting and z negated as northing, but in world coordinates rather than
+//! relative to the selection.
+
+use std::fs;
+use std::path::Path;
+
+use mcprogedit::coordinates::BlockColumnCoord;
+use serde_json::Value;
+
+use crate::types::Snake;
+
+/// Read the boundary from the GeoJSON file at `path`: The outer ring of the first polygon
+/// found, as a geometry, a feature, or in a feature collection. Returns the polygon in world
+/// coordinates, closed, with the last point the same as the first.
+pub fn read_geojson(path: &Path) -> Result<Snake, String> {
+    let contents = fs::read_to_string(path).map_err(|error| format!("{}", error))?;
+    let value: Value = serde_json::from_str(&contents).map_err(|error| format!("{}", error))?;
+    let ring = outer_ring(&value).ok_or_else(|| "No polygon found.".to_string())?;
+
+    let mut polygon = Vec::new();
+    for position in ring {
+        let easting = position.get(0).and_then(Value::as_f64);
+        let northing = position.get(1).and_then(Value::as_f64);
+        match (easting, northing) {
+            (Some(easting), Some(northing)) => {
+                polygon.push(BlockColumnCoord(easting.round() as i64, -northing.round() as i64));
+            }
+            _ => return Err(format!("Not a position: {}", position)),
+        }
+    }
+    if polygon.len() < 3 {
+        return Err("The polygon has fewer than three corners.".to_string());
+    }
+    if polygon.first() != polygon.last() {
+        polygon.push(polygon[0]);
+    }
+    Ok(polygon)
+}
+
+/// The smallest and the largest corner of the bounding box of the polygon, both inclusive.
+pub fn bounding_box(polygon: &[BlockColumnCoord]) -> (BlockColumnCoord, BlockColumnCoord) {
+    let x_min = polygon.iter().map(|point| point.0).min().unwrap_or(0);
+    let x_max = polygon.iter().map(|point| point.0).max().unwrap_or(0);
+    let z_min = polygon.iter().map(|point| point.1).min().unwrap_or(0);
+    let z_max = polygon.iter().map(|point| point.1).max().unwrap_or(0);
+    (BlockColumnCoord(x_min, z_min), BlockColumnCoord(x_max, z_max))
+}
+
+/// The polygon, moved to coordinates relative to `origin`, the corner of the selection.
+pub fn relative_to(polygon: &[BlockColumnCoord], origin: BlockColumnCoord) -> Snake {
+    polygon.iter()
+        .map(|point| BlockColumnCoord(point.0 - origin.0, point.1 - origin.1))
+        .collect()
+}
+
+/// The columns along the segment from `start` to `end`, in order, both ends included.
+pub fn segment_columns(start: BlockColumnCoord, end: BlockColumnCoord) -> Vec<BlockColumnCoord> {
+    let (dx, dz) = (end.0 - start.0, end.1 - start.1);
+    let steps = dx.abs().max(dz.abs()).max(1);
+    (0..=steps)
+        .map(|step| {
+            let x = start.0 as f32 + (dx * step) as f32 / steps as f32;
+            let z = start.1 as f32 + (dz * step) as f32 / steps as f32;
+            BlockColumnCoord(x.round() as i64, z.round() as i64)
+        })
+        .collect()
+}
+
+fn outer_ring(value: &Value) -> Option<&Vec<Value>> {
+    match value.get("type")?.as_str()? {
+        "FeatureCollection" => value.get("features")?.as_array()?.iter().find_map(outer_ring),
+        "Feature" => outer_ring(value.get("geometry")?),
+        "Polygon" => value.get("coordinates")?.get(0)?.as_array(),
+        "MultiPolygon" => value.get("coordinates")?.get(0)?.get(0)?.as_array(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn read_from(contents: &str) -> Result<Snake, String> {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("boundary.geojson");
+        fs::write(&path, contents).unwrap();
+        read_geojson(&path)
+    }
+
+    fn points(corners: &[(i64, i64)]) -> Snake {
+        corners.iter().map(|(x, z)| BlockColumnCoord(*x, *z)).collect()
+    }
+
+    #[test]
+    fn read_polygon() {
+        let polygon = read_from(r#"{"type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, -5], [0, 0]]]}"#);
+        assert_eq!(Ok(points(&[(0, 0), (10, 0), (10, 5), (0, 0)])), polygon);
+    }
+
+    #[test]
+    fn read_closes_open_ring() {
+        let polygon = read_from(r#"{"type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, -5]]]}"#);
+        assert_eq!(Ok(points(&[(0, 0), (10, 0), (10, 5), (0, 0)])), polygon);
+    }
+
+    #[test]
+    fn read_first_polygon_of_feature_collection() {
+        let polygon = read_from(r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}},
+                {"type": "Feature", "geometry": {"type": "Polygon", "coordinates": [[[1, 1], [4, 1], [4, 4]]]}}
+            ]
+        }"#);
+        assert_eq!(Ok(points(&[(1, -1), (4, -1), (4, -4), (1, -1)])), polygon);
+    }
+
+    #[test]
+    fn read_ignores_holes() {
+        let polygon = read_from(r#"{
+            "type": "Polygon",
+            "coordinates": [
+                [[0, 0], [20, 0], [20, -20], [0, -20], [0, 0]],
+                [[5, -5], [10, -5], [10, -10], [5, -5]]
+            ]
+        }"#);
+        assert_eq!(Ok(points(&[(0, 0), (20, 0), (20, 20), (0, 20), (0, 0)])), polygon);
+    }
+
+    #[test]
+    fn read_rejects_malformed_json() {
+        assert!(read_from(r#"{"type": "Polygon", "coordinates": [[[0, 0], [10, 0]"#).is_err());
+    }
+
+    #[test]
+    fn read_rejects_missing_polygon() {
+        assert!(read_from(r#"{"type": "LineString", "coordinates": [[0, 0], [10, 0]]}"#).is_err());
+    }
+
+    #[test]
+    fn read_rejects_too_few_corners() {
+        assert!(read_from(r#"{"type": "Polygon", "coordinates": [[[0, 0], [10, 0]]]}"#).is_err());
+    }
+
+    #[test]
+    fn read_rejects_bad_position() {
+        assert!(read_from(r#"{"type": "Polygon", "coordinates": [[[0, 0], [10], [10, -5]]]}"#).is_err());
+    }
+
+    #[test]
+    fn read_missing_file() {
+        assert!(read_geojson(Path::new("no/such/boundary.geojson")).is_err());
+    }
+
+    #[test]
+    fn outer_ring_of_multi_polygon() {
+        let value = json!({
+            "type": "MultiPolygon",
+            "coordinates": [[[[0, 0], [1, 0], [1, 1]], [[2, 2], [3, 3], [2, 3]]], [[[9, 9], [8, 9], [8, 8]]]],
+        });
+        assert_eq!(Some(&vec![json!([0, 0]), json!([1, 0]), json!([1, 1])]), outer_ring(&value));
+    }
+
+    #[test]
+    fn outer_ring_of_unknown_type() {
+        assert_eq!(None, outer_ring(&json!({"type": "Point", "coordinates": [0, 0]})));
+    }
+
+    #[test]
+    fn relative_to_origin() {
+        assert_eq!(
+            points(&[(-10, 5), (0, 5), (0, 15)]),
+            relative_to(&points(&[(90, 205), (100, 205), (100, 215)]), BlockColumnCoord(100, 200)),
+        );
+    }
+
+    #[test]
+    fn bounding_box_corners() {
+        assert_eq!(
+            (BlockColumnCoord(-3, 1), BlockColumnCoord(4, 9)),
+            bounding_box(&points(&[(0, 1), (4, 5), (-3, 9)])),
+        );
+    }
+
+    #[test]
+    fn segment_columns_diagonal() {
+        assert_eq!(
+            points(&[(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]),
+            segment_columns(BlockColumnCoord(0, 0), BlockColumnCoord(4, 2)),
+        );
+    }
+
+    #[test]
+    fn segment_columns_of_single_column() {
+        assert_eq!(
+            points(&[(3, 3), (3, 3)]),
+            segment_columns(BlockColumnCoord(3, 3), BlockColumnCoord(3, 3)),
+        );
+    }
+}
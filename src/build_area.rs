@@ -157,6 +157,17 @@ impl BuildArea {
                         }
                     }
                 }
+                PlotEdgeKind::Canal { width } => {
+                    let line = line::line(&edge.points.0, &edge.points.1, width as i64);
+
+                    for position in &line {
+                        let coordinates = (position.0 as usize, position.2 as usize);
+                        build_area.set_designation_at(
+                            coordinates,
+                            AreaDesignation::Irrelevant(BuildRights::Forbidden),
+                        );
+                    }
+                }
                 PlotEdgeKind::Plot => {
                     let line = line::line(&edge.points.0, &edge.points.1, 2i64);
 
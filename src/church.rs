@@ -0,0 +1,334 @@
+//! Church for the most prominent plot in town, as a landmark: a tall nave under a
+//! pitched roof, with stained glass windows, and a bell tower over the entrance.
+
+use std::collections::HashSet;
+
+use mcprogedit::block::{Block, Door, DoorHalf, Hinge};
+use mcprogedit::colour::Colour;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{DoorMaterial, FenceMaterial};
+use mcprogedit::positioning::{Axis3, Surface2, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::block_palette::BlockPalette;
+use crate::build_area::BuildArea;
+use crate::geometry;
+use crate::room_interior::{self, ColumnKind, RoomShape};
+use crate::structure_builder::{ExteriorDoor, House};
+
+/// Outer width of the nave.
+const NAVE_WIDTH: i64 = 7;
+/// Outer lengths of the nave to try fitting on the plot, longest first.
+const NAVE_LENGTHS: [i64; 3] = [15, 13, 11];
+/// Height from the floor to the ceiling of the nave walls, in blocks of air.
+const NAVE_WALL_HEIGHT: i64 = 6;
+/// Outer width of the square bell tower.
+const TOWER_WIDTH: i64 = 5;
+/// Height of the bell tower walls, above the floor.
+const TOWER_HEIGHT: i64 = 14;
+/// Smallest plot area (in m²) worth trying to fit a church on.
+pub const CHURCH_PLOT_AREA_MIN: i64 = 150;
+
+/// Colours for the stained glass windows.
+const STAINED_GLASS_COLOURS: [Colour; 6] = [
+    Colour::Blue,
+    Colour::Red,
+    Colour::Yellow,
+    Colour::Purple,
+    Colour::LightBlue,
+    Colour::Green,
+];
+
+/// Where on the plot the church goes.
+#[derive(Clone, Copy, Debug)]
+struct Site {
+    /// The column at the front left corner of the tower, seen from the street.
+    anchor: BlockColumnCoord,
+    /// Direction from the church towards the street.
+    front: Surface4,
+    /// Outer length of the nave.
+    nave_length: i64,
+}
+
+impl Site {
+    /// The column `along` from the front, and `across` from the left hand side, of the church.
+    fn column(&self, along: i64, across: i64) -> BlockColumnCoord {
+        let forward = geometry::offset(self.front.opposite());
+        let right = geometry::offset(self.front.rotated_90_ccw());
+        BlockColumnCoord(
+            self.anchor.0 + forward.0 * along + right.0 * across,
+            self.anchor.1 + forward.2 * along + right.2 * across,
+        )
+    }
+
+    fn at(&self, along: i64, across: i64, y: i64) -> BlockCoord {
+        let column = self.column(along, across);
+        BlockCoord(column.0, y, column.1)
+    }
+
+    /// First and last rows of the nave, counted from the front.
+    fn nave_rows(&self) -> (i64, i64) {
+        (TOWER_WIDTH - 1, TOWER_WIDTH - 2 + self.nave_length)
+    }
+
+    /// First column of the tower, counted from the left.
+    fn tower_left(&self) -> i64 {
+        (NAVE_WIDTH - TOWER_WIDTH) / 2
+    }
+
+    /// All columns covered by the church, as `(along, across)`.
+    fn footprint(&self) -> Vec<(i64, i64)> {
+        let (_, nave_back) = self.nave_rows();
+        let tower_left = self.tower_left();
+        let mut footprint = Vec::new();
+        for along in 0..=nave_back {
+            for across in 0..NAVE_WIDTH {
+                let is_tower = along < TOWER_WIDTH
+                    && across >= tower_left && across < tower_left + TOWER_WIDTH;
+                if along >= TOWER_WIDTH - 1 || is_tower {
+                    footprint.push((along, across));
+                }
+            }
+        }
+        footprint
+    }
+}
+
+/// Build a church on the buildable part of the plot, with the entrance facing the street.
+/// Returns `None` if the church does not fit on the plot.
+pub fn build_church(
+    excerpt: &WorldExcerpt,
+    build_area: &BuildArea,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) -> Option<House> {
+    let site = find_site(build_area)?;
+    let height_map = excerpt.ground_height_map();
+
+    // WorldExcerpt for holding the additions/changes to the world
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut output = WorldExcerpt::new(x_len, y_len, z_len);
+
+    let footprint = site.footprint();
+    let ground_at = |(along, across): (i64, i64)| {
+        let column = site.column(along, across);
+        height_map.height_at((column.0 as usize, column.1 as usize)).map(|y| y as i64)
+    };
+    let floor_y = footprint.iter().filter_map(|position| ground_at(*position)).max()?;
+
+    let (nave_front, nave_back) = site.nave_rows();
+    let tower_left = site.tower_left();
+    let door_across = NAVE_WIDTH / 2;
+    let roof_y = floor_y + NAVE_WALL_HEIGHT + 1;
+    let window_colours = (
+        STAINED_GLASS_COLOURS[rng.gen_range(0..STAINED_GLASS_COLOURS.len())],
+        STAINED_GLASS_COLOURS[rng.gen_range(0..STAINED_GLASS_COLOURS.len())],
+    );
+
+    // Foundation and floor
+    for position in &footprint {
+        let ground = ground_at(*position).unwrap_or(floor_y);
+        for y in ground - 1..floor_y {
+            output.set_block_at(site.at(position.0, position.1, y), palette.foundation.clone());
+        }
+        output.set_block_at(site.at(position.0, position.1, floor_y), palette.floor.clone());
+    }
+
+    // The nave, with tall stained glass windows along the sides and behind the altar
+    for along in nave_front..=nave_back {
+        for across in 0..NAVE_WIDTH {
+            let is_end = along == nave_front || along == nave_back;
+            let is_side = across == 0 || across == NAVE_WIDTH - 1;
+            let is_side_window = is_side && !is_end && (along - nave_front) % 2 == 0;
+            let is_back_window = along == nave_back && across == door_across;
+
+            for y in floor_y + 1..roof_y {
+                let height = y - floor_y;
+                let block = if is_end && is_side {
+                    palette.foundation.clone()
+                } else if (is_side_window || is_back_window) && height >= 2 && height < NAVE_WALL_HEIGHT {
+                    let colour = if height % 2 == 0 { window_colours.0 } else { window_colours.1 };
+                    Block::Glass { colour: Some(colour) }
+                } else if is_end || is_side {
+                    palette.wall.clone()
+                } else {
+                    Block::Air
+                };
+                output.set_block_at(site.at(along, across, y), block);
+            }
+
+            // Pitched roof, with gables at the ends
+            let from_side = across.min(NAVE_WIDTH - 1 - across);
+            for level in 0..=from_side {
+                let block = if level == from_side {
+                    palette.roof.clone()
+                } else if is_end {
+                    palette.wall.clone()
+                } else {
+                    Block::Air
+                };
+                output.set_block_at(site.at(along, across, roof_y + level), block);
+            }
+        }
+    }
+
+    // The bell tower, open from the entrance up to the belfry
+    let belfry_y = floor_y + TOWER_HEIGHT - 5;
+    let top_y = floor_y + TOWER_HEIGHT;
+    for along in 0..TOWER_WIDTH {
+        for across in tower_left..tower_left + TOWER_WIDTH {
+            let is_wall = along == 0 || along == TOWER_WIDTH - 1
+                || across == tower_left || across == tower_left + TOWER_WIDTH - 1;
+            let is_middle = along == TOWER_WIDTH / 2 || across == door_across;
+            for y in floor_y + 1..top_y {
+                let block = if is_wall {
+                    let is_belfry_opening = is_middle && y > belfry_y && y < top_y - 1;
+                    if is_belfry_opening { Block::Air } else { palette.foundation.clone() }
+                } else if y == belfry_y {
+                    palette.floor.clone()
+                } else {
+                    Block::Air
+                };
+                output.set_block_at(site.at(along, across, y), block);
+            }
+            output.set_block_at(site.at(along, across, top_y), palette.foundation.clone());
+        }
+    }
+
+    // Spire, with a cross on top
+    for level in 0..=TOWER_WIDTH / 2 {
+        for along in level..TOWER_WIDTH - level {
+            for across in tower_left + level..tower_left + TOWER_WIDTH - level {
+                output.set_block_at(site.at(along, across, top_y + 1 + level), palette.roof.clone());
+            }
+        }
+    }
+    let spire_top = top_y + 2 + TOWER_WIDTH / 2;
+    let tower_middle = (TOWER_WIDTH / 2, door_across);
+    for y in spire_top..spire_top + 3 {
+        output.set_block_at(
+            site.at(tower_middle.0, tower_middle.1, y),
+            Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false },
+        );
+    }
+    for across in &[door_across - 1, door_across + 1] {
+        output.set_block_at(
+            site.at(tower_middle.0, *across, spire_top + 1),
+            Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false },
+        );
+    }
+
+    // Bell hanging in the belfry, and a lantern lighting the entrance below it
+    output.set_block_at(
+        site.at(tower_middle.0, tower_middle.1, top_y - 1),
+        Block::Chain { alignment: Axis3::Y },
+    );
+    output.set_block_at(site.at(tower_middle.0, tower_middle.1, top_y - 2), Block::BlockOfGold);
+    output.set_block_at(
+        site.at(tower_middle.0, tower_middle.1, belfry_y - 1),
+        Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+    );
+
+    // Passage from the tower into the nave
+    for across in door_across - 1..=door_across + 1 {
+        for y in floor_y + 1..floor_y + 4 {
+            output.set_block_at(site.at(nave_front, across, y), Block::Air);
+        }
+    }
+
+    // Entrance door, in the front of the tower
+    let facing = site.front.opposite();
+    let door = site.at(0, door_across, floor_y + 1);
+    for (y, half) in &[(0, DoorHalf::Lower), (1, DoorHalf::Upper)] {
+        output.set_block_at(door + BlockCoord(0, *y, 0), Block::Door(Door {
+            material: DoorMaterial::Oak,
+            facing,
+            half: *half,
+            hinged_at: Hinge::Right,
+            open: false,
+        }));
+    }
+
+    // Pews and altar
+    let nave_columns: Vec<((i64, i64), BlockColumnCoord)> = footprint.iter()
+        .filter(|(along, _)| *along >= nave_front)
+        .map(|(along, across)| ((*along, *across), site.column(*along, *across)))
+        .collect();
+    let min_x = nave_columns.iter().map(|(_, column)| column.0).min()?;
+    let max_x = nave_columns.iter().map(|(_, column)| column.0).max()?;
+    let min_z = nave_columns.iter().map(|(_, column)| column.1).min()?;
+    let max_z = nave_columns.iter().map(|(_, column)| column.1).max()?;
+
+    let mut room_shape = RoomShape::new(((max_x - min_x + 1) as usize, (max_z - min_z + 1) as usize));
+    for ((along, across), column) in &nave_columns {
+        let is_end = *along == nave_front || *along == nave_back;
+        let is_side = *across == 0 || *across == NAVE_WIDTH - 1;
+        let column_kind = if *along == nave_front && (across - door_across).abs() <= 1 {
+            ColumnKind::Door
+        } else if is_side && !is_end && (along - nave_front) % 2 == 0 {
+            ColumnKind::Window
+        } else if is_end || is_side {
+            ColumnKind::Wall
+        } else {
+            ColumnKind::Floor(NAVE_WALL_HEIGHT as usize)
+        };
+        room_shape.set_column_kind_at(((column.0 - min_x) as usize, (column.1 - min_z) as usize), column_kind);
+    }
+    if let Some(interior) = room_interior::furnish_nave(&room_shape, site.front.opposite(), rng) {
+        output.paste(BlockCoord(min_x, floor_y + 1, min_z), &interior);
+    }
+
+    Some(House {
+        structure: output,
+        doors: vec![ExteriorDoor { coordinates: door, facing }],
+    })
+}
+
+/// Find where on the plot the largest church fits, preferring the entrance close to the street.
+fn find_site(build_area: &BuildArea) -> Option<Site> {
+    // Keep one block off the edge of the plot.
+    let buildable: HashSet<(usize, usize)> = build_area.buildable_coordinates()
+        .difference(&build_area.buildable_edge_coordinates())
+        .copied()
+        .collect();
+    let mut anchors: Vec<(usize, usize)> = buildable.iter().copied().collect();
+    anchors.sort_unstable();
+    let mut street: Vec<(usize, usize)> = build_area.road_along_buildable_coordinates().into_iter().collect();
+    street.sort_unstable();
+
+    for nave_length in &NAVE_LENGTHS {
+        let mut best: Option<(i64, Site)> = None;
+        for front in &[Surface4::North, Surface4::East, Surface4::South, Surface4::West] {
+            for (x, z) in &anchors {
+                let site = Site {
+                    anchor: BlockColumnCoord(*x as i64, *z as i64),
+                    front: *front,
+                    nave_length: *nave_length,
+                };
+                let fits = site.footprint().iter().all(|(along, across)| {
+                    let column = site.column(*along, *across);
+                    column.0 >= 0 && column.1 >= 0
+                        && buildable.contains(&(column.0 as usize, column.1 as usize))
+                });
+                if !fits {
+                    continue;
+                }
+
+                let entrance = site.column(-1, NAVE_WIDTH / 2);
+                let street_distance = street.iter()
+                    .map(|(x, z)| (*x as i64 - entrance.0).abs() + (*z as i64 - entrance.1).abs())
+                    .min()
+                    .unwrap_or(0);
+                if best.map_or(true, |(best_distance, _)| street_distance < best_distance) {
+                    best = Some((street_distance, site));
+                }
+            }
+        }
+        if let Some((_, site)) = best {
+            return Some(site);
+        }
+    }
+    None
+}
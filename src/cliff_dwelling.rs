@@ -0,0 +1,311 @@
+//! Dwellings carved into cliffs and steep hillsides, for the cliff style of settlement:
+//! Rooms excavated into the rock behind a facade with a door and windows, in up to two
+//! tiers, with a stairway carved along the cliff face up to the ledge of the upper tier.
+
+use std::collections::HashSet;
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::Material;
+use mcprogedit::positioning::Surface4;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+
+use crate::block_palette::{stairs_material, BlockPalette};
+use crate::features::Features;
+use crate::foundation;
+use crate::geometry;
+use crate::partitioning;
+use crate::room_interior::{self, ColumnKind, RoomShape};
+use crate::tree;
+use crate::types::Snake;
+
+/// Half the inner width of the room, along the cliff face.
+const ROOM_HALF_WIDTH: i64 = 3;
+/// Inner depth of the room, into the cliff.
+const ROOM_DEPTH: i64 = 6;
+/// Inner height of the room, from the floor to the ceiling.
+const ROOM_HEIGHT: i64 = 3;
+/// Least thickness of rock left above the ceiling.
+const ROCK_ABOVE_MIN: i64 = 1;
+/// Largest difference in ground height in front of the dwelling.
+const FOOT_SLOPE_MAX: i64 = 1;
+/// How far outside the town wall to look for cliffs.
+const SEARCH_MARGIN: i64 = 32;
+/// Least distance between neighbouring dwellings along the cliff.
+const DWELLING_SPACING: i64 = 2;
+
+/// A dwelling carved into a cliff.
+#[derive(Clone, Copy, Debug)]
+pub struct CliffDwelling {
+    /// The doorway in the facade, at the floor of the room.
+    pub door: BlockCoord,
+    /// Direction from the cliff out towards the open ground, as the facade faces.
+    pub outward: Surface4,
+    /// Y coordinate of the ground at the foot of the cliff. Dwellings higher up the cliff
+    /// are reached by a stairway carved along the cliff face.
+    pub ground_y: i64,
+}
+
+impl CliffDwelling {
+    /// Height of the door above the ground at the foot of the cliff.
+    pub fn rise(&self) -> i64 {
+        self.door.1 - self.ground_y
+    }
+
+    /// The columns of the dwelling: The room, its facade, the ground or ledge in front of
+    /// it, and the stairway up to it, if any.
+    pub fn footprint(&self) -> Vec<BlockColumnCoord> {
+        let mut footprint = Vec::new();
+        for inward in -1..=ROOM_DEPTH + 1 {
+            for along in -(ROOM_HALF_WIDTH + 1)..=ROOM_HALF_WIDTH + 1 + self.rise() {
+                footprint.push(self.column_at(along, inward));
+            }
+        }
+        footprint
+    }
+
+    /// The column `along` the cliff face and `inward` into the cliff from the door.
+    fn column_at(&self, along: i64, inward: i64) -> BlockColumnCoord {
+        let along_offset = geometry::offset(self.outward.rotated_90_cw());
+        let inward_offset = geometry::offset(self.outward.opposite());
+        BlockColumnCoord(
+            self.door.0 + along_offset.0 * along + inward_offset.0 * inward,
+            self.door.2 + along_offset.2 * along + inward_offset.2 * inward,
+        )
+    }
+
+    /// The block `along` the cliff face and `inward` into the cliff from the door, at `y`.
+    fn at(&self, along: i64, inward: i64, y: i64) -> BlockCoord {
+        let column = self.column_at(along, inward);
+        BlockCoord(column.0, y, column.1)
+    }
+}
+
+/// Find places for up to `count_max` dwellings in the cliffs in and around the town
+/// within `wall_circle`, closest to the town center first. Where the cliff is tall enough,
+/// a second dwelling is carved above the first, set one block further into the cliff.
+pub fn plan_cliff_dwellings(
+    features: &Features,
+    wall_circle: &Snake,
+    town_center: BlockColumnCoord,
+    count_max: usize,
+) -> Vec<CliffDwelling> {
+    let (x_len, z_len) = features.dimensions();
+    let (offset, dimensions) = partitioning::snake_bounding_box(wall_circle);
+    let x_range = (offset.0 - SEARCH_MARGIN).max(1)..(offset.0 + dimensions.0 + SEARCH_MARGIN).min(x_len as i64 - 1);
+    let z_range = (offset.1 - SEARCH_MARGIN).max(1)..(offset.1 + dimensions.1 + SEARCH_MARGIN).min(z_len as i64 - 1);
+    let terrain_at = |column: BlockColumnCoord| -> Option<i64> {
+        if column.0 < 0 || column.1 < 0 || column.0 >= x_len as i64 || column.1 >= z_len as i64 {
+            return None;
+        }
+        let (x, z) = (column.0 as u32, column.1 as u32);
+        if features.water[(x, z)] == image::Luma([255u8]) {
+            return None;
+        }
+        let image::Luma([y]) = features.terrain[(x, z)];
+        Some(y as i64)
+    };
+    // Whether there is rock enough for the room, its walls and the rock above it.
+    let room_fits = |dwelling: &CliffDwelling| {
+        let top_y = dwelling.door.1 + ROOM_HEIGHT + ROCK_ABOVE_MIN + 1;
+        (0..=ROOM_DEPTH + 1).all(|inward| {
+            (-(ROOM_HALF_WIDTH + 1)..=ROOM_HALF_WIDTH + 1).all(|along| {
+                terrain_at(dwelling.column_at(along, inward)).map_or(false, |y| y >= top_y)
+            })
+        })
+    };
+
+    // Level open ground at the foot of a cliff, with the dwelling fitting into the cliff
+    let mut candidates = Vec::new();
+    for x in x_range {
+        for z in z_range.clone() {
+            let foot = BlockColumnCoord(x, z);
+            let ground_y = match terrain_at(foot) {
+                Some(ground_y) => ground_y,
+                None => continue,
+            };
+            for outward in [Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
+                let inward = geometry::offset(outward.opposite());
+                let dwelling = CliffDwelling {
+                    door: BlockCoord(x + inward.0, ground_y, z + inward.2),
+                    outward,
+                    ground_y,
+                };
+                let is_level_in_front = (-ROOM_HALF_WIDTH..=ROOM_HALF_WIDTH).all(|along| {
+                    terrain_at(dwelling.column_at(along, -1))
+                        .map_or(false, |y| (y - ground_y).abs() <= FOOT_SLOPE_MAX)
+                });
+                if is_level_in_front && room_fits(&dwelling) {
+                    candidates.push(dwelling);
+                }
+            }
+        }
+    }
+    candidates.sort_by_key(|dwelling| {
+        geometry::manhattan_distance(BlockColumnCoord(dwelling.door.0, dwelling.door.2), town_center)
+    });
+
+    // Take the closest dwellings that keep clear of each other, with the upper tiers
+    let mut occupied: HashSet<BlockColumnCoord> = HashSet::new();
+    let is_clear = |dwelling: &CliffDwelling, occupied: &HashSet<BlockColumnCoord>| {
+        dwelling.footprint().iter().all(|column| !occupied.contains(column))
+    };
+    let mut dwellings = Vec::new();
+    for dwelling in candidates {
+        if dwellings.len() >= count_max {
+            break;
+        }
+        if !is_clear(&dwelling, &occupied) {
+            continue;
+        }
+        let inward = geometry::offset(dwelling.outward.opposite());
+        let upper = CliffDwelling {
+            door: BlockCoord(
+                dwelling.door.0 + inward.0,
+                dwelling.door.1 + ROOM_HEIGHT + 1,
+                dwelling.door.2 + inward.2,
+            ),
+            ..dwelling
+        };
+        let has_upper = dwellings.len() + 1 < count_max && room_fits(&upper) && is_clear(&upper, &occupied);
+
+        for placed in std::iter::once(&dwelling).chain(Some(&upper).filter(|_| has_upper)) {
+            for column in placed.footprint() {
+                for dx in -DWELLING_SPACING..=DWELLING_SPACING {
+                    for dz in -DWELLING_SPACING..=DWELLING_SPACING {
+                        occupied.insert(BlockColumnCoord(column.0 + dx, column.1 + dz));
+                    }
+                }
+            }
+        }
+        dwellings.push(dwelling);
+        if has_upper {
+            dwellings.push(upper);
+        }
+    }
+    dwellings
+}
+
+/// Carve out the dwelling and build it: The room excavated into the rock, with a floor
+/// and the walls patched where the rock is broken, the facade with its door and windows,
+/// the ledge in front of it, the stairway up to the ledge, and the furniture.
+pub fn build_cliff_dwelling(
+    excerpt: &mut WorldExcerpt,
+    dwelling: &CliffDwelling,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) {
+    let y = dwelling.door.1;
+    let half_width = ROOM_HALF_WIDTH;
+
+    // Room, excavated into the rock, with the walls and ceiling patched where they are open
+    for inward in 0..=ROOM_DEPTH + 1 {
+        for along in -(half_width + 1)..=half_width + 1 {
+            let is_inside = inward >= 1 && inward <= ROOM_DEPTH && along.abs() <= half_width;
+            for block_y in y - 1..=y + ROOM_HEIGHT {
+                let coordinates = dwelling.at(along, inward, block_y);
+                if is_inside && block_y == y - 1 {
+                    excerpt.set_block_at(coordinates, palette.floor.clone());
+                } else if is_inside && block_y < y + ROOM_HEIGHT {
+                    excerpt.set_block_at(coordinates, Block::Air);
+                } else if inward > 0 && foundation::is_open(excerpt.block_at(coordinates)) {
+                    excerpt.set_block_at(coordinates, palette.foundation.clone());
+                }
+            }
+        }
+    }
+
+    // Facade, with the door in the middle and a window on either side of it
+    for along in -half_width..=half_width {
+        for block_y in y - 1..=y + ROOM_HEIGHT {
+            let block = if block_y == y - 1 || block_y == y + ROOM_HEIGHT {
+                palette.foundation.clone()
+            } else if along.abs() == 2 && block_y == y + 1 {
+                palette.flat_window.clone()
+            } else {
+                palette.wall.clone()
+            };
+            excerpt.set_block_at(dwelling.at(along, 0, block_y), block);
+        }
+    }
+    let facing = dwelling.outward.opposite();
+    excerpt.set_block_at(dwelling.at(0, 0, y), Block::Door(mcprogedit::block::Door {
+        material: mcprogedit::material::DoorMaterial::Oak,
+        facing,
+        half: mcprogedit::block::DoorHalf::Lower,
+        hinged_at: mcprogedit::block::Hinge::Right,
+        open: false,
+    }));
+    excerpt.set_block_at(dwelling.at(0, 0, y + 1), Block::Door(mcprogedit::block::Door {
+        material: mcprogedit::material::DoorMaterial::Oak,
+        facing,
+        half: mcprogedit::block::DoorHalf::Upper,
+        hinged_at: mcprogedit::block::Hinge::Right,
+        open: false,
+    }));
+
+    // Ground or ledge in front of the facade, cleared and made firm
+    let rise = dwelling.rise();
+    let ledge_reach = if rise > 0 { half_width + 1 } else { 1 };
+    for along in -ledge_reach..=ledge_reach {
+        let below = dwelling.at(along, -1, y - 1);
+        if rise > 0 || foundation::is_open(excerpt.block_at(below)) {
+            excerpt.set_block_at(below, palette.foundation.clone());
+        }
+        for block_y in y..y + 3 {
+            tree::chop(excerpt, dwelling.at(along, -1, block_y));
+            excerpt.set_block_at(dwelling.at(along, -1, block_y), Block::Air);
+        }
+    }
+
+    // Stairway carved along the cliff face, from the ground up to the end of the ledge
+    if rise > 0 {
+        let material = stairs_material(&palette.foundation).unwrap_or(Material::Cobblestone);
+        let upward = dwelling.outward.rotated_90_ccw();
+        for step in 1..=rise {
+            let along = half_width + 1 + rise - step;
+            let step_y = dwelling.ground_y + step - 1;
+            excerpt.set_block_at(dwelling.at(along, -1, step_y), Block::stairs(upward.into(), material));
+            for block_y in step_y + 1..=step_y + 3 {
+                tree::chop(excerpt, dwelling.at(along, -1, block_y));
+                excerpt.set_block_at(dwelling.at(along, -1, block_y), Block::Air);
+            }
+            for block_y in (dwelling.ground_y - 1..step_y).rev() {
+                let coordinates = dwelling.at(along, -1, block_y);
+                if !foundation::is_open(excerpt.block_at(coordinates)) {
+                    break;
+                }
+                excerpt.set_block_at(coordinates, palette.foundation.clone());
+            }
+        }
+    }
+
+    // Furniture, as for a cottage
+    let columns: Vec<(i64, i64, BlockColumnCoord)> = (0..=ROOM_DEPTH + 1)
+        .flat_map(|inward| {
+            (-(half_width + 1)..=half_width + 1).map(move |along| (along, inward, dwelling.column_at(along, inward)))
+        })
+        .collect();
+    let min_x = columns.iter().map(|(_, _, column)| column.0).min().unwrap_or(0);
+    let min_z = columns.iter().map(|(_, _, column)| column.1).min().unwrap_or(0);
+    let max_x = columns.iter().map(|(_, _, column)| column.0).max().unwrap_or(0);
+    let max_z = columns.iter().map(|(_, _, column)| column.1).max().unwrap_or(0);
+    let mut room_shape = RoomShape::new(((max_x - min_x + 1) as usize, (max_z - min_z + 1) as usize));
+    for (along, inward, column) in columns {
+        let local = ((column.0 - min_x) as usize, (column.1 - min_z) as usize);
+        let kind = if inward >= 1 && inward <= ROOM_DEPTH && along.abs() <= half_width {
+            ColumnKind::Floor(ROOM_HEIGHT as usize)
+        } else if inward == 0 && along == 0 {
+            ColumnKind::Door
+        } else if inward == 0 && along.abs() == 2 {
+            ColumnKind::Window
+        } else {
+            ColumnKind::Wall
+        };
+        room_shape.set_column_kind_at(local, kind);
+    }
+    if let Some(furniture) = room_interior::furnish_cottage(&room_shape, rng) {
+        excerpt.paste(BlockCoord(min_x, y, min_z), &furniture);
+    }
+}
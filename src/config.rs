@@ -0,0 +1,574 @@
+//! Configurable generation parameters, loadable from a TOML file.
+//!
+//! Any parameter left out of the file keeps its default value.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mcprogedit::positioning::Surface4;
+use serde::{Deserialize, Serialize};
+
+use crate::wall::DefenceKind;
+
+/// Largest coverage radius for roads and streets, given or scaled to the town size.
+pub const COVERAGE_RADIUS_MAX: u8 = 64;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GenerationConfig {
+    /// Largest number of walled towns to place, if there are suitable sites for them.
+    pub max_towns: usize,
+    /// Dimension of the world the settlement is in.
+    pub dimension: Dimension,
+
+    // Roads and streets, as built
+    pub street_width: i64,
+    pub country_road_width: i64,
+    pub city_road_width: i64,
+    /// Width of the alleys: streets no longer than `alley_length_max`.
+    pub alley_width: i64,
+    pub alley_length_max: usize,
+    /// Width of the footpaths out to the fields and the windmill.
+    pub path_width: i64,
+    /// Whether to build roundabouts where four or more major roads meet.
+    pub roundabouts: bool,
+
+    // Roads and streets, as reserved in the land usage plan
+    pub street_reserved_width: i64,
+    pub city_road_reserved_width: i64,
+
+    // Street coverage
+    pub road_coverage_radius: u8,
+    pub street_coverage_radius: u8,
+    /// Largest height difference between the ends of a street segment. Steeper segments
+    /// are rerouted along the terrain, or left out if no way around is found.
+    pub street_climb_max: i64,
+
+    // Scaling with town size
+    /// Total town area, in blocks, for which the widths and coverage radii are used as given.
+    /// For other town areas they are scaled by the square root of the area ratio,
+    /// limited to between `town_scale_min` and `town_scale_max`. 0 turns scaling off.
+    pub reference_town_area: usize,
+    pub town_scale_min: f32,
+    pub town_scale_max: f32,
+
+    // Water inside town
+    /// Largest pond, in blocks of water surface, to fill in. Larger ponds are kept as
+    /// ornamental ponds, and rivers running through town are lined as canals.
+    pub pond_fill_area_max: usize,
+    /// Whether to dig a canal into town from the river or the sea, if there is one within reach.
+    pub canal: bool,
+    /// Whether to build an aqueduct into town from the high ground, if there is any within reach.
+    pub aqueduct: bool,
+    /// Smallest share of the town area that is swamp or shallow water, for building a stilt
+    /// village, with the houses on stilts and boardwalks for streets. Above 1 means never.
+    pub stilt_village_swamp_share: f32,
+    /// Smallest share of a plot at risk of flooding, for building its house on stilts rather
+    /// than on a raised stone foundation. Houses on plots at any risk get their floors above
+    /// the flood level either way.
+    pub flood_stilt_share: f32,
+
+    // Town wall
+    pub wall_width: i64,
+    /// Width of the strip along either side of the town wall kept clear of buildings and trees.
+    pub pomerium_width: i64,
+    /// Kind of town defences. Decided from the local materials if not given.
+    pub defence: Option<DefenceKind>,
+
+    // Houses
+    /// How the townsfolk are housed: In houses on the plots, or in dwellings carved into the cliffs.
+    pub style: SettlementStyle,
+    /// Largest number of dwellings to carve into the cliffs, in the cliff style.
+    pub cliff_dwellings_max: usize,
+    /// Share of the buildings fallen into ruin, in the ruins style.
+    pub ruin_share: f32,
+    pub house_interior_area_min: usize,
+    pub house_interior_area_max: usize,
+    pub story_height: usize,
+    /// Most stories above the street, for the houses in the districts closest to the town center.
+    /// The number of stories falls off towards the edge of town, down to a single story.
+    pub stories_max: usize,
+    /// Tallest a house may be, in blocks, from its lowest floor to the eaves. 0 means no limit.
+    pub building_height_max: usize,
+    /// Hemisphere the settlement is in. Houses get more windows on the walls facing the sun.
+    pub hemisphere: Hemisphere,
+    /// Whether to let house roofs overhang the walls, and add dormers to the larger roofs.
+    pub roof_details: bool,
+    /// Widest street frontage, in blocks, for building on a plot as part of a row of houses,
+    /// sharing party walls with its neighbours along the same street. 0 means no row houses.
+    pub row_house_frontage_max: usize,
+    /// Largest plot area, in m², counting as too small for a house of its own. Two such plots
+    /// next to each other along a street are built as a pair of semi-detached houses, sharing
+    /// a party wall and a chimney. 0 means no semi-detached houses.
+    pub semi_detached_area_max: usize,
+    /// Whether to build arcades in front of the houses facing the market square, with the
+    /// houses set back from the street to make room for them.
+    pub arcades: bool,
+    /// Whether to level the ground of the plots before building on them, grading it back
+    /// to the surrounding ground along the edges of the plots.
+    pub terraforming: bool,
+    /// Share of the large corner plots near the market to build taverns on.
+    pub tavern_share: f64,
+    /// Share of the plots along the city roads to build workshops on.
+    /// All plots in a workshop quarter get workshops.
+    pub workshop_share: f64,
+    /// Share of the homes with a cat or a dog sitting by the hearth.
+    pub pet_share: f64,
+    /// Chance of a tavern having a parrot.
+    pub tavern_parrot_chance: f64,
+
+    /// Leave every Nth plot without a house, for a well in each district. 0 means build on all plots.
+    pub plot_skip_interval: usize,
+
+    // Amenities, scaled to the population
+    /// Number of households to dig a well for. Every district gets a well regardless,
+    /// 0 means no more wells than that.
+    pub households_per_well: usize,
+    /// Number of households to build a bakery for. 0 means no bakeries.
+    pub households_per_bakery: usize,
+    /// Number of households to build an apothecary for. 0 means no apothecaries.
+    pub households_per_apothecary: usize,
+    /// Smallest population for building a second church. 0 means never.
+    pub second_church_population: usize,
+    /// Smallest population for building a school, with a library, in the wealthiest
+    /// district. 0 means never.
+    pub school_population: usize,
+    /// Largest number of graves in the graveyard beside each church.
+    pub graves_max: usize,
+
+    // Farms
+    /// Largest number of fields to lay out around the towns.
+    pub max_fields: usize,
+    /// Largest number of pastures for livestock to lay out around the towns.
+    pub max_pastures: usize,
+
+    // Land suitability
+    /// Weights of the soil fertility, the flatness, the nearness of water and the openness
+    /// of the land, in its suitability for fields and pastures. Only their ratios matter.
+    pub suitability_fertility_weight: f32,
+    pub suitability_flatness_weight: f32,
+    pub suitability_water_weight: f32,
+    pub suitability_openness_weight: f32,
+
+    // Harbour
+    /// Smallest total town area, in m², for building a shipyard by the harbour. 0 means never.
+    pub shipyard_town_area_min: usize,
+
+    // Zoning
+    /// Whether to lay out a market square in the district at the town center.
+    pub central_plaza: bool,
+    /// Smallest number of market districts, taken closest to the town center.
+    pub market_districts_min: usize,
+    /// Smallest and largest share of the town area to set aside as green space.
+    pub green_space_min: f32,
+    pub green_space_max: f32,
+    /// Whether to put a workshop quarter by the town gate facing the mine.
+    pub workshop_quarter_by_mine: bool,
+    /// Whether to put a tannery and a dyeworks outside the wall, downstream along the river.
+    pub nuisance_industries: bool,
+    /// Tallest the houses may be, in blocks, along the sightlines from the town gates
+    /// to the landmark at the town center. 0 means the sightlines are not kept open.
+    pub view_corridor_height: usize,
+
+    // Blocks
+    /// Names of the only blocks the generator may place, in snake case, such as "stone_bricks"
+    /// or "planks". Any block may be placed if not given. Clearing blocks away is always allowed.
+    pub block_whitelist: Option<Vec<String>>,
+    /// Whether to replace the blocks not on the whitelist by allowed alternatives,
+    /// instead of only reporting them.
+    pub strict_palette: bool,
+
+    // Villages
+    /// Whether to adopt the existing villages in the selection into the settlement: Joining
+    /// their paths to the roads, and rebuilding their houses in the materials of the town,
+    /// instead of building over them.
+    pub adopt_villages: bool,
+    /// Farthest from the town wall a village may be, for walling it in with the town.
+    pub village_wall_distance: f32,
+
+    // Adventure mode
+    /// Whether to hide treasure chests with books of town lore around the town, for exploring.
+    pub adventure_mode: bool,
+    /// Number of treasures to hide in adventure mode, if there are enough hiding places.
+    pub treasure_count: usize,
+
+    // Night
+    /// Whether to light up the town for night-time screenshots, with a light inside every
+    /// window and lanterns along the façades of the houses.
+    pub night_lighting: bool,
+    /// Whether to light every spot inside the town walls dark enough for monsters to spawn,
+    /// in the streets and yards as well as inside the houses.
+    pub light_audit: bool,
+
+    // Weathering
+    /// How weathered the finished town looks, from 0 for freshly built to 1 for long
+    /// neglected: Cracked and mossy stonework, vines on the walls, cobwebs in the corners,
+    /// and grass growing through the gravel of the roads.
+    pub weathering: f32,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            max_towns: 3,
+            dimension: Dimension::Overworld,
+
+            street_width: 2,
+            country_road_width: 3,
+            city_road_width: 4,
+            alley_width: 1,
+            alley_length_max: 16,
+            path_width: 1,
+            roundabouts: true,
+
+            street_reserved_width: 2,
+            city_road_reserved_width: 6,
+
+            road_coverage_radius: 10,
+            street_coverage_radius: 9,
+            street_climb_max: 3,
+
+            reference_town_area: 6000,
+            town_scale_min: 0.7,
+            town_scale_max: 1.6,
+
+            pond_fill_area_max: 48,
+            canal: true,
+            aqueduct: false,
+            stilt_village_swamp_share: 0.5,
+            flood_stilt_share: 0.5,
+
+            wall_width: 3,
+            pomerium_width: 2,
+            defence: None,
+
+            style: SettlementStyle::Town,
+            cliff_dwellings_max: 24,
+            ruin_share: 0.6,
+            house_interior_area_min: 9,
+            house_interior_area_max: 100,
+            story_height: 3,
+            stories_max: 3,
+            building_height_max: 12,
+            hemisphere: Hemisphere::Northern,
+            roof_details: true,
+            row_house_frontage_max: 8,
+            semi_detached_area_max: 60,
+            arcades: true,
+            terraforming: true,
+            tavern_share: 0.5,
+            workshop_share: 0.2,
+            pet_share: 0.3,
+            tavern_parrot_chance: 0.25,
+
+            plot_skip_interval: 10,
+
+            households_per_well: 12,
+            households_per_bakery: 30,
+            households_per_apothecary: 40,
+            second_church_population: 300,
+            school_population: 200,
+            graves_max: 16,
+
+            max_fields: 12,
+            max_pastures: 4,
+
+            suitability_fertility_weight: 0.4,
+            suitability_flatness_weight: 0.3,
+            suitability_water_weight: 0.1,
+            suitability_openness_weight: 0.2,
+
+            shipyard_town_area_min: 8000,
+
+            central_plaza: true,
+            market_districts_min: 0,
+            green_space_min: 0.0,
+            green_space_max: 0.2,
+            workshop_quarter_by_mine: false,
+            nuisance_industries: true,
+            view_corridor_height: 0,
+
+            block_whitelist: None,
+            strict_palette: false,
+
+            adopt_villages: true,
+            village_wall_distance: 32.0,
+
+            adventure_mode: false,
+            treasure_count: 5,
+
+            night_lighting: false,
+            light_audit: true,
+            weathering: 0.0,
+        }
+    }
+}
+
+/// The dimension of the world the settlement is in.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimension {
+    /// The overworld, open to the sky.
+    Overworld,
+    /// The Nether, under a ceiling of bedrock and netherrack, without any water.
+    Nether,
+}
+
+impl Dimension {
+    /// The directory holding the world of this dimension, within the save directory:
+    /// The save directory itself for the overworld, and its DIM-1 directory for the Nether.
+    pub fn directory(self, save_directory: &Path) -> PathBuf {
+        match self {
+            Dimension::Overworld => save_directory.to_path_buf(),
+            Dimension::Nether => save_directory.join("DIM-1"),
+        }
+    }
+
+    /// Whether the world of this dimension has a ceiling, with the ground found below it
+    /// rather than at the top of the world.
+    pub fn has_ceiling(self) -> bool {
+        self == Dimension::Nether
+    }
+}
+
+/// How the townsfolk of the settlement are housed.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementStyle {
+    /// Free-standing houses on the plots of the town.
+    Town,
+    /// Dwellings carved into the cliffs and steep hillsides in and around the town,
+    /// with the plots laid out as gardens.
+    Cliff,
+    /// The houses of a town abandoned long ago, a share of them fallen into ruin.
+    Ruins,
+}
+
+/// The hemisphere of the world the settlement is in.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hemisphere {
+    Northern,
+    Southern,
+}
+
+impl Hemisphere {
+    /// The direction towards the midday sun.
+    pub fn sunward(self) -> Surface4 {
+        match self {
+            Self::Northern => Surface4::South,
+            Self::Southern => Surface4::North,
+        }
+    }
+}
+
+impl GenerationConfig {
+    /// Read a configuration from the TOML file at `path`.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|error| format!("{}", error))?;
+        let config: Self = toml::from_str(&contents).map_err(|error| format!("{}", error))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that the parameters make sense together, with an error describing the first
+    /// one that does not.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.house_interior_area_min > self.house_interior_area_max {
+            return Err(format!(
+                "house_interior_area_min ({}) is larger than house_interior_area_max ({}).",
+                self.house_interior_area_min, self.house_interior_area_max,
+            ));
+        }
+        if self.story_height == 0 {
+            return Err("story_height must be at least 1.".to_string());
+        }
+        for (name, radius) in &[
+            ("road_coverage_radius", self.road_coverage_radius),
+            ("street_coverage_radius", self.street_coverage_radius),
+        ] {
+            if *radius == 0 || *radius > COVERAGE_RADIUS_MAX {
+                return Err(format!("{} ({}) must be between 1 and {}.", name, radius, COVERAGE_RADIUS_MAX));
+            }
+        }
+        if self.town_scale_min <= 0.0 || self.town_scale_min > self.town_scale_max {
+            return Err(format!(
+                "town_scale_min ({}) must be above 0, and no larger than town_scale_max ({}).",
+                self.town_scale_min, self.town_scale_max,
+            ));
+        }
+        if self.green_space_min > self.green_space_max {
+            return Err(format!(
+                "green_space_min ({}) is larger than green_space_max ({}).",
+                self.green_space_min, self.green_space_max,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Factor for scaling widths and coverage radii, for towns with the given total area.
+    pub fn town_scale(&self, town_area: usize) -> f32 {
+        if self.reference_town_area == 0 {
+            return 1.0;
+        }
+        let scale = (town_area as f32 / self.reference_town_area as f32).sqrt();
+        scale.max(self.town_scale_min).min(self.town_scale_max)
+    }
+
+    /// This configuration, with the street, road and wall widths and the coverage radii
+    /// scaled to suit towns with the given total area.
+    ///
+    /// The widths of the country roads, the alleys and the footpaths are left alone.
+    /// The country roads run between the towns and out of the area, so they are shared
+    /// by towns of different sizes and keep one width along their whole length. The
+    /// alleys and the footpaths are as narrow as a way can be walked, whatever the size
+    /// of the town, and widening them would make them streets.
+    pub fn scaled_to_town_area(&self, town_area: usize) -> Self {
+        let scale = self.town_scale(town_area);
+        let width = |width: i64| ((width as f32 * scale).round() as i64).max(1);
+        let radius = |radius: u8| (radius as f32 * scale).round().max(1.0).min(COVERAGE_RADIUS_MAX as f32) as u8;
+
+        let street_width = width(self.street_width);
+        let city_road_width = width(self.city_road_width);
+        Self {
+            street_width,
+            city_road_width,
+            street_reserved_width: width(self.street_reserved_width).max(street_width),
+            city_road_reserved_width: width(self.city_road_reserved_width).max(city_road_width),
+            road_coverage_radius: radius(self.road_coverage_radius),
+            street_coverage_radius: radius(self.street_coverage_radius),
+            wall_width: width(self.wall_width),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the plot with the given index is to be left without a house.
+    pub fn is_skipped_plot(&self, index: usize) -> bool {
+        self.plot_skip_interval != 0 && index % self.plot_skip_interval == self.plot_skip_interval - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn config_from_toml(contents: &str) -> Result<GenerationConfig, String> {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+        fs::write(&path, contents).unwrap();
+        GenerationConfig::from_file(&path)
+    }
+
+    /// The default configuration, allowing towns to be scaled up to three times as wide.
+    fn widely_scaling_config() -> GenerationConfig {
+        GenerationConfig { town_scale_max: 3.0, ..Default::default() }
+    }
+
+    #[test]
+    fn town_scale_without_reference_area() {
+        let config = GenerationConfig { reference_town_area: 0, ..Default::default() };
+        assert_eq!(1.0, config.town_scale(0));
+        assert_eq!(1.0, config.town_scale(100_000));
+    }
+
+    #[test]
+    fn town_scale_at_reference_area() {
+        let config = GenerationConfig::default();
+        assert_eq!(1.0, config.town_scale(config.reference_town_area));
+    }
+
+    #[test]
+    fn town_scale_of_quadruple_area() {
+        let config = widely_scaling_config();
+        assert_eq!(2.0, config.town_scale(config.reference_town_area * 4));
+    }
+
+    #[test]
+    fn town_scale_clamped_to_min() {
+        let config = GenerationConfig::default();
+        assert_eq!(config.town_scale_min, config.town_scale(0));
+    }
+
+    #[test]
+    fn town_scale_clamped_to_max() {
+        let config = GenerationConfig::default();
+        assert_eq!(config.town_scale_max, config.town_scale(config.reference_town_area * 100));
+    }
+
+    #[test]
+    fn scaled_to_reference_area_is_unchanged() {
+        let config = GenerationConfig::default();
+        let scaled = config.scaled_to_town_area(config.reference_town_area);
+        assert_eq!(config.street_width, scaled.street_width);
+        assert_eq!(config.city_road_width, scaled.city_road_width);
+        assert_eq!(config.wall_width, scaled.wall_width);
+        assert_eq!(config.street_coverage_radius, scaled.street_coverage_radius);
+    }
+
+    #[test]
+    fn scaled_to_quadruple_area() {
+        let config = widely_scaling_config();
+        let scaled = config.scaled_to_town_area(config.reference_town_area * 4);
+        assert_eq!(config.street_width * 2, scaled.street_width);
+        assert_eq!(config.city_road_width * 2, scaled.city_road_width);
+        assert_eq!(config.wall_width * 2, scaled.wall_width);
+        assert_eq!(config.road_coverage_radius * 2, scaled.road_coverage_radius);
+        assert_eq!(config.street_coverage_radius * 2, scaled.street_coverage_radius);
+    }
+
+    #[test]
+    fn scaled_leaves_unscaled_widths() {
+        let config = GenerationConfig { alley_width: 2, country_road_width: 5, ..widely_scaling_config() };
+        let scaled = config.scaled_to_town_area(config.reference_town_area * 4);
+        assert_eq!(config.street_width * 2, scaled.street_width);
+        assert_eq!(2, scaled.alley_width);
+        assert_eq!(5, scaled.country_road_width);
+        assert_eq!(config.path_width, scaled.path_width);
+    }
+
+    #[test]
+    fn scaled_widths_at_least_1() {
+        let config = GenerationConfig { street_width: 1, town_scale_min: 0.1, ..Default::default() };
+        assert_eq!(1, config.scaled_to_town_area(0).street_width);
+    }
+
+    #[test]
+    fn scaled_radius_clamped_to_max() {
+        let config = GenerationConfig { street_coverage_radius: 60, ..widely_scaling_config() };
+        let scaled = config.scaled_to_town_area(config.reference_town_area * 4);
+        assert_eq!(COVERAGE_RADIUS_MAX, scaled.street_coverage_radius);
+    }
+
+    #[test]
+    fn scaled_reserved_width_at_least_built_width() {
+        let config = GenerationConfig { street_reserved_width: 0, ..Default::default() };
+        let scaled = config.scaled_to_town_area(config.reference_town_area);
+        assert_eq!(scaled.street_width, scaled.street_reserved_width);
+    }
+
+    #[test]
+    fn from_file_keeps_defaults() {
+        let config = config_from_toml("street_width = 5\n").unwrap();
+        assert_eq!(5, config.street_width);
+        assert_eq!(GenerationConfig::default().city_road_width, config.city_road_width);
+    }
+
+    #[test]
+    fn from_file_rejects_invalid_values() {
+        assert!(config_from_toml("story_height = 0\n").is_err());
+        assert!(config_from_toml("street_coverage_radius = 0\n").is_err());
+        assert!(config_from_toml("town_scale_min = 2.0\ntown_scale_max = 1.0\n").is_err());
+    }
+
+    #[test]
+    fn from_file_rejects_malformed_file() {
+        assert!(config_from_toml("street_width = \"wide\"\n").is_err());
+    }
+
+    #[test]
+    fn from_missing_file() {
+        assert!(GenerationConfig::from_file(Path::new("no/such/config.toml")).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Dock crane and loading area at the harbour: A timber crane on the quay, with its arm
+//! reaching out over the water and a barrel hanging from it on a chain, and a loading area
+//! behind it, roped off and stacked with crates and barrels.
+//!
+//! Both are generated from a handful of parameters, so that the harbour can fit them to
+//! the shore at hand.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, Material, WoodMaterial};
+use mcprogedit::positioning::{Axis3, Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::features::Features;
+use crate::geometry;
+use crate::tree;
+
+/// A timber crane, turning on a mast standing on the quay.
+#[derive(Clone, Copy, Debug)]
+pub struct Crane {
+    /// Foot of the mast, at the ground.
+    pub base: BlockCoord,
+    /// Direction the arm reaches out in, over the water.
+    pub seaward: Surface4,
+    /// Height of the mast, from the ground up to the arm.
+    pub mast_height: i64,
+    /// Length of the arm, from the mast out to where the load hangs.
+    pub arm_length: i64,
+    /// Length of the arm behind the mast, carrying the counterweight.
+    pub counter_arm_length: i64,
+    /// Length of the chain the load hangs from.
+    pub chain_length: i64,
+}
+
+/// An area for loading and unloading goods, behind the crane.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadingArea {
+    /// Middle of the front of the loading area, facing the quay.
+    pub front_middle: BlockColumnCoord,
+    /// Y coordinate of the level ground of the loading area.
+    pub floor_y: i64,
+    /// Direction from the loading area out towards the water.
+    pub seaward: Surface4,
+    /// Size of the loading area, along and across the quay.
+    pub size: (i64, i64),
+}
+
+impl LoadingArea {
+    /// The columns of the loading area.
+    pub fn footprint(&self) -> Vec<BlockColumnCoord> {
+        let along = geometry::offset(self.seaward.rotated_90_cw());
+        let landward = geometry::offset(self.seaward.opposite());
+        let mut footprint = Vec::new();
+        for across in 0..self.size.1 {
+            for along_distance in -(self.size.0 / 2)..=self.size.0 / 2 {
+                footprint.push(BlockColumnCoord(
+                    self.front_middle.0 + along.0 * along_distance + landward.0 * across,
+                    self.front_middle.1 + along.2 * along_distance + landward.2 * across,
+                ));
+            }
+        }
+        footprint
+    }
+}
+
+/// Build the crane: A mast of logs on a stone footing, with a slab turntable at its foot,
+/// an arm of fences braced against the mast, a counterweight at the back of the arm, and
+/// a barrel hanging on a chain from the front of the arm.
+pub fn build_crane(excerpt: &mut WorldExcerpt, crane: &Crane) {
+    let ahead = geometry::offset(crane.seaward);
+    let side = geometry::offset(crane.seaward.rotated_90_cw());
+    let at = |along: i64, across: i64, up: i64| {
+        BlockCoord(
+            crane.base.0 + ahead.0 * along + side.0 * across,
+            crane.base.1 + up,
+            crane.base.2 + ahead.2 * along + side.2 * across,
+        )
+    };
+    let fence = Block::Fence { material: FenceMaterial::Spruce, waterlogged: false };
+    let arm_up = crane.mast_height;
+
+    // Footing and turntable
+    for along in -1..=1 {
+        for across in -1..=1 {
+            excerpt.set_block_at(at(along, across, -1), Block::StoneBricks);
+            tree::chop(excerpt, at(along, across, 0));
+            let turntable = if (along, across) == (0, 0) { Block::oak_log(Axis3::Y) } else { Block::Air };
+            excerpt.set_block_at(at(along, across, 0), turntable);
+        }
+    }
+    for (along, across) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+        excerpt.set_block_at(at(*along, *across, 0), Block::bottom_slab(Material::Spruce));
+    }
+
+    // Mast
+    for up in 1..=arm_up {
+        tree::chop(excerpt, at(0, 0, up));
+        excerpt.set_block_at(at(0, 0, up), Block::oak_log(Axis3::Y));
+    }
+
+    // Arm, braced against the mast on both sides
+    for along in -crane.counter_arm_length..=crane.arm_length {
+        if along != 0 {
+            tree::chop(excerpt, at(along, 0, arm_up));
+            excerpt.set_block_at(at(along, 0, arm_up), fence.clone());
+        }
+    }
+    excerpt.set_block_at(at(1, 0, arm_up - 1), Block::stairs_inverted(crane.seaward.opposite().into(), Material::Spruce));
+    excerpt.set_block_at(at(-1, 0, arm_up - 1), Block::stairs_inverted(crane.seaward.into(), Material::Spruce));
+
+    // Counterweight
+    excerpt.set_block_at(at(-crane.counter_arm_length, 0, arm_up - 1), Block::Cobblestone);
+
+    // Load, hanging on a chain
+    for up in arm_up - crane.chain_length..arm_up {
+        excerpt.set_block_at(at(crane.arm_length, 0, up), Block::Chain { alignment: Axis3::Y });
+    }
+    excerpt.set_block_at(at(crane.arm_length, 0, arm_up - crane.chain_length - 1), Block::barrel(Surface6::Up));
+}
+
+/// Build the loading area: Level gravel ground, roped off on the sides and at the back
+/// by lines strung between posts, with stacks of crates and barrels along the back.
+pub fn build_loading_area(
+    excerpt: &mut WorldExcerpt,
+    area: &LoadingArea,
+    features: &Features,
+    rng: &mut StdRng,
+) {
+    let y = area.floor_y;
+    let along_offset = geometry::offset(area.seaward.rotated_90_cw());
+    let landward = geometry::offset(area.seaward.opposite());
+    let at = |along: i64, across: i64, up: i64| {
+        BlockCoord(
+            area.front_middle.0 + along_offset.0 * along + landward.0 * across,
+            y + up,
+            area.front_middle.1 + along_offset.2 * along + landward.2 * across,
+        )
+    };
+    let fence = Block::Fence { material: FenceMaterial::Spruce, waterlogged: false };
+
+    // Level ground, cleared of vegetation
+    for column in area.footprint() {
+        for clear_y in y..y + 4 {
+            tree::chop(excerpt, BlockCoord(column.0, clear_y, column.1));
+            excerpt.set_block_at(BlockCoord(column.0, clear_y, column.1), Block::Air);
+        }
+        let image::Luma([ground]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        for fill_y in ground as i64 - 1..y - 1 {
+            excerpt.set_block_at(BlockCoord(column.0, fill_y, column.1), Block::Cobblestone);
+        }
+        excerpt.set_block_at(BlockCoord(column.0, y - 1, column.1), Block::Gravel);
+    }
+
+    // Posts in the corners, with rope strung between them, open towards the quay
+    let half_length = area.size.0 / 2;
+    let back = area.size.1 - 1;
+    for along in -half_length..=half_length {
+        for across in 0..=back {
+            let is_side = along.abs() == half_length;
+            let is_back = across == back;
+            if !is_side && !is_back {
+                continue;
+            }
+            let is_post = is_side && (across == 0 || is_back);
+            if is_post {
+                excerpt.set_block_at(at(along, across, 0), fence.clone());
+                excerpt.set_block_at(at(along, across, 1), fence.clone());
+            } else {
+                excerpt.set_block_at(at(along, across, 1), Block::Tripwire);
+            }
+        }
+    }
+
+    // Crates and barrels, stacked along the back
+    for along in -half_length + 1..half_length {
+        let goods = if rng.gen_bool(0.5) {
+            Block::Planks { material: WoodMaterial::Spruce }
+        } else {
+            Block::barrel(Surface6::Up)
+        };
+        let height = rng.gen_range(1..=2);
+        for up in 0..height {
+            excerpt.set_block_at(at(along, back - 1, up), goods.clone());
+        }
+    }
+}
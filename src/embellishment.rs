@@ -0,0 +1,237 @@
+//! Façade embellishments, added to a house after its base shell is built.
+
+use std::collections::HashSet;
+
+use log::trace;
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::positioning::Surface4;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_palette::BlockPalette;
+use crate::build_area::BuildArea;
+
+/// Optional add-ons for the façade of wealthy houses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Embellishment {
+    /// A circular-ish tower on a corner of the house, overhanging the ground floor,
+    /// with a conical roof.
+    CornerTurret,
+    /// A window projecting one block out from the façade towards the street.
+    BayWindow,
+}
+
+/// The parts of a house shell that the embellishments build upon.
+pub struct HouseShell<'a> {
+    /// Outer wall (x, z) coordinates.
+    pub outline: &'a HashSet<(usize, usize)>,
+    /// Interior (x, z) coordinates.
+    pub interior: &'a HashSet<(usize, usize)>,
+    /// Coordinates of the exterior doors.
+    pub doors: &'a [(usize, usize)],
+    /// Facing of the main door, i.e. pointing from the street into the house.
+    pub street_facing: Surface4,
+    /// Y coordinate of the floor blocks of the top story.
+    pub top_floor: i64,
+    /// Y coordinate at which the roof starts.
+    pub cornice_height: usize,
+}
+
+/// Add the given embellishment to the house shell in `output`.
+/// Returns true if there was room for the embellishment.
+pub fn add_embellishment(
+    output: &mut WorldExcerpt,
+    build_area: &BuildArea,
+    shell: &HouseShell,
+    palette: &BlockPalette,
+    embellishment: Embellishment,
+) -> bool {
+    match embellishment {
+        Embellishment::CornerTurret => add_corner_turret(output, build_area, shell, palette),
+        Embellishment::BayWindow => add_bay_window(output, build_area, shell, palette),
+    }
+}
+
+/// Put a turret on one of the outer corners of the house, preferably on the street side.
+pub fn add_corner_turret(
+    output: &mut WorldExcerpt,
+    build_area: &BuildArea,
+    shell: &HouseShell,
+    palette: &BlockPalette,
+) -> bool {
+    let street_direction = shell.street_facing.opposite();
+
+    // Outer corners are wall pieces with open space in two perpendicular directions.
+    let mut corners: Vec<((usize, usize), Vec<Surface4>)> = shell.outline.iter()
+        .filter_map(|coordinates| {
+            let open_directions: Vec<Surface4> = DIRECTIONS.iter()
+                .filter(|direction| {
+                    match neighbour_in_direction(*coordinates, **direction) {
+                        Some(neighbour) => is_outside(shell, neighbour),
+                        None => false,
+                    }
+                })
+                .copied()
+                .collect();
+            if open_directions.len() == 2 && open_directions[0] != open_directions[1].opposite() {
+                Some((*coordinates, open_directions))
+            } else {
+                None
+            }
+        })
+        .collect();
+    corners.sort_by_key(|(coordinates, open_directions)| {
+        (!open_directions.contains(&street_direction), *coordinates)
+    });
+
+    for (corner, open_directions) in corners {
+        // The turret is a "plus" shape centered on the corner, overhanging on the open sides.
+        let mut body = vec![corner];
+        let mut fits = true;
+        for direction in &DIRECTIONS {
+            match neighbour_in_direction(corner, *direction) {
+                Some(neighbour) if shell.interior.contains(&neighbour) => (),
+                Some(neighbour) if !is_in_excerpt(output, neighbour) => fits = false,
+                Some(neighbour) => match build_area.designation_at(neighbour) {
+                    Some(designation) if designation.is_buildable() || designation.is_road() => {
+                        body.push(neighbour);
+                    }
+                    _ => fits = false,
+                },
+                None => fits = false,
+            }
+        }
+        if !fits {
+            continue;
+        }
+
+        trace!("Adding corner turret at {:?}.", corner);
+        let bottom = shell.top_floor;
+        let top = shell.cornice_height as i64 + 1;
+
+        for (x, z) in &body {
+            // Corbelled base for the overhanging parts
+            if !shell.outline.contains(&(*x, *z)) {
+                output.set_block_at(BlockCoord(*x as i64, bottom, *z as i64), palette.foundation.clone());
+            }
+            for y in bottom + 1..=top {
+                output.set_block_at(BlockCoord(*x as i64, y, *z as i64), palette.wall.clone());
+            }
+            // Conical roof, first layer
+            output.set_block_at(BlockCoord(*x as i64, top + 1, *z as i64), palette.roof.clone());
+        }
+
+        // Windows on the open sides
+        for direction in &open_directions {
+            if let Some((x, z)) = neighbour_in_direction(corner, *direction) {
+                output.set_block_at(BlockCoord(x as i64, bottom + 2, z as i64), palette.flat_window.clone());
+            }
+        }
+
+        // Conical roof, spire
+        output.set_block_at(BlockCoord(corner.0 as i64, top + 2, corner.1 as i64), palette.roof.clone());
+        output.set_block_at(BlockCoord(corner.0 as i64, top + 3, corner.1 as i64), palette.roof.clone());
+
+        return true;
+    }
+
+    false
+}
+
+/// Put a three block wide bay window on the street side of the top story.
+pub fn add_bay_window(
+    output: &mut WorldExcerpt,
+    build_area: &BuildArea,
+    shell: &HouseShell,
+    palette: &BlockPalette,
+) -> bool {
+    const BAY_WIDTH: usize = 3;
+
+    let street_direction = shell.street_facing.opposite();
+    let along_wall = shell.street_facing.rotated_90_cw();
+
+    // Wall pieces facing the street, that are not doors or next to doors.
+    let facade: HashSet<(usize, usize)> = shell.outline.iter()
+        .filter(|coordinates| {
+            let is_street_facing = match (
+                neighbour_in_direction(**coordinates, street_direction),
+                neighbour_in_direction(**coordinates, shell.street_facing),
+            ) {
+                (Some(outside), Some(inside)) => is_outside(shell, outside) && shell.interior.contains(&inside),
+                _ => false,
+            };
+            let is_by_door = shell.doors.iter().any(|door| {
+                *door == **coordinates
+                    || neighbour_in_direction(*door, along_wall) == Some(**coordinates)
+                    || neighbour_in_direction(*door, along_wall.opposite()) == Some(**coordinates)
+            });
+            is_street_facing && !is_by_door
+        })
+        .copied()
+        .collect();
+
+    let mut candidates: Vec<(usize, usize)> = facade.iter().copied().collect();
+    candidates.sort();
+
+    'candidates: for start in candidates {
+        // Find a run of wall pieces along the façade, with room in front of them.
+        let mut run = Vec::new();
+        let mut current = start;
+        while run.len() < BAY_WIDTH {
+            if !facade.contains(&current) {
+                continue 'candidates;
+            }
+            let projection = match neighbour_in_direction(current, street_direction) {
+                Some(projection) if is_in_excerpt(output, projection) => projection,
+                _ => continue 'candidates,
+            };
+            match build_area.designation_at(projection) {
+                Some(designation) if designation.is_buildable() || designation.is_road() => (),
+                _ => continue 'candidates,
+            }
+            run.push((current, projection));
+            current = match neighbour_in_direction(current, along_wall) {
+                Some(next) => next,
+                None => continue 'candidates,
+            };
+        }
+
+        trace!("Adding bay window at {:?}.", start);
+        let floor = shell.top_floor;
+
+        for (wall, (x, z)) in &run {
+            // Bay floor, sill, window and roof
+            output.set_block_at(BlockCoord(*x as i64, floor, *z as i64), palette.floor.clone());
+            output.set_block_at(BlockCoord(*x as i64, floor + 1, *z as i64), palette.wall.clone());
+            output.set_block_at(BlockCoord(*x as i64, floor + 2, *z as i64), palette.flat_window.clone());
+            output.set_block_at(BlockCoord(*x as i64, floor + 3, *z as i64), palette.roof.clone());
+
+            // Open up the façade behind the bay
+            output.set_block_at(BlockCoord(wall.0 as i64, floor + 2, wall.1 as i64), palette.flat_window.clone());
+        }
+
+        return true;
+    }
+
+    false
+}
+
+const DIRECTIONS: [Surface4; 4] = [Surface4::North, Surface4::East, Surface4::South, Surface4::West];
+
+fn is_outside(shell: &HouseShell, coordinates: (usize, usize)) -> bool {
+    !shell.outline.contains(&coordinates) && !shell.interior.contains(&coordinates)
+}
+
+fn is_in_excerpt(excerpt: &WorldExcerpt, (x, z): (usize, usize)) -> bool {
+    let (x_len, _, z_len) = excerpt.dim();
+    x < x_len && z < z_len
+}
+
+fn neighbour_in_direction((x, z): (usize, usize), direction: Surface4) -> Option<(usize, usize)> {
+    match direction {
+        Surface4::North => if z > 0 { Some((x, z - 1)) } else { None },
+        Surface4::South => Some((x, z + 1)),
+        Surface4::East => Some((x + 1, z)),
+        Surface4::West => if x > 0 { Some((x - 1, z)) } else { None },
+    }
+}
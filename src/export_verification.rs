@@ -0,0 +1,152 @@
+//! Verification of the exported world: The saved world is read back in and compared
+//! block by block with what the generator placed, so that blocks silently lost on export,
+//! such as unsupported block states or chunks failing to write, are caught early. Only the
+//! blocks the generator changed are compared, as the rest were never placed by it.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_whitelist::block_name;
+
+/// Largest number of differing blocks to keep as examples.
+const EXAMPLES_MAX: usize = 20;
+
+/// The blocks that did not persist as placed.
+#[derive(Clone, Debug, Default)]
+pub struct ExportMismatches {
+    /// Number of blocks compared, i.e. the blocks changed from the original excerpt.
+    pub compared_count: usize,
+    /// Number of differing blocks, by the name of the block placed.
+    pub counts: BTreeMap<String, usize>,
+    /// The first few differing blocks, in world coordinates, with the block placed and
+    /// the block read back, if any.
+    pub examples: Vec<(BlockCoord, Block, Option<Block>)>,
+}
+
+impl ExportMismatches {
+    /// Whether every block persisted as placed.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Total number of differing blocks.
+    pub fn count(&self) -> usize {
+        self.counts.values().sum()
+    }
+}
+
+/// Read back the world saved in `save_directory`, for the excerpt placed at `offset`, and
+/// compare it with the excerpt, for the blocks that differ from the `original` excerpt the
+/// generation started from. Blocks not loaded in the excerpt are not compared.
+pub fn verify_export(
+    excerpt: &WorldExcerpt,
+    original: &WorldExcerpt,
+    offset: BlockCoord,
+    save_directory: &Path,
+) -> ExportMismatches {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let (x_len, y_len, z_len) = (x_len as i64, y_len as i64, z_len as i64);
+    let saved = WorldExcerpt::from_save(
+        offset,
+        BlockCoord(offset.0 + x_len - 1, offset.1 + y_len - 1, offset.2 + z_len - 1),
+        save_directory,
+    );
+
+    let mut mismatches = ExportMismatches::default();
+    for x in 0..x_len {
+        for y in 0..y_len {
+            for z in 0..z_len {
+                let coordinates = BlockCoord(x, y, z);
+                let placed = match excerpt.block_at(coordinates) {
+                    None | Some(Block::None) => continue,
+                    Some(block) => block,
+                };
+                if original.block_at(coordinates) == Some(placed) {
+                    continue;
+                }
+                mismatches.compared_count += 1;
+                let found = saved.block_at(coordinates);
+                if found == Some(placed) {
+                    continue;
+                }
+                *mismatches.counts.entry(block_name(placed)).or_insert(0) += 1;
+                if mismatches.examples.len() < EXAMPLES_MAX {
+                    mismatches.examples.push((
+                        BlockCoord(offset.0 + x, offset.1 + y, offset.2 + z),
+                        placed.clone(),
+                        found.cloned(),
+                    ));
+                }
+            }
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small excerpt of air, with the given blocks set.
+    fn excerpt_with(blocks: &[(BlockCoord, Block)]) -> WorldExcerpt {
+        let mut excerpt = WorldExcerpt::new(2, 2, 2);
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    excerpt.set_block_at(BlockCoord(x, y, z), Block::Air);
+                }
+            }
+        }
+        for (coordinates, block) in blocks {
+            excerpt.set_block_at(*coordinates, block.clone());
+        }
+        excerpt
+    }
+
+    fn placed() -> WorldExcerpt {
+        excerpt_with(&[
+            (BlockCoord(0, 0, 0), Block::StoneBricks),
+            (BlockCoord(1, 0, 0), Block::Cobblestone),
+        ])
+    }
+
+    #[test]
+    fn export_persisted() {
+        let directory = tempfile::tempdir().unwrap();
+        let offset = BlockCoord(16, 64, 32);
+        let original = excerpt_with(&[]);
+        let excerpt = placed();
+        excerpt.to_save(offset, directory.path());
+
+        let mismatches = verify_export(&excerpt, &original, offset, directory.path());
+
+        assert_eq!(2, mismatches.compared_count);
+        assert!(mismatches.is_empty());
+        assert!(mismatches.examples.is_empty());
+    }
+
+    #[test]
+    fn changed_block_reported() {
+        let directory = tempfile::tempdir().unwrap();
+        let offset = BlockCoord(16, 64, 32);
+        let original = excerpt_with(&[]);
+        let mut excerpt = placed();
+        excerpt.to_save(offset, directory.path());
+        excerpt.set_block_at(BlockCoord(1, 0, 0), Block::Bookshelf);
+
+        let mismatches = verify_export(&excerpt, &original, offset, directory.path());
+
+        assert_eq!(2, mismatches.compared_count);
+        assert_eq!(1, mismatches.count());
+        assert_eq!(Some(&1), mismatches.counts.get("bookshelf"));
+        assert_eq!(1, mismatches.examples.len());
+        let (coordinates, placed, found) = &mismatches.examples[0];
+        assert_eq!((17, 64, 32), (coordinates.0, coordinates.1, coordinates.2));
+        assert_eq!(&Block::Bookshelf, placed);
+        assert_eq!(&Some(Block::Cobblestone), found);
+    }
+}
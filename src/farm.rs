@@ -0,0 +1,271 @@
+//! Fields of crops, on fertile land outside the town walls.
+
+use std::cmp::Reverse;
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::bounded_ints::Int0Through7;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, WoodMaterial};
+use mcprogedit::positioning::Surface4;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::areas::Areas;
+use crate::features::Features;
+use crate::geometry;
+use crate::pathfinding::{self, RoadPath};
+use crate::tree;
+use crate::types::Snake;
+
+/// Size of a field along the x axis: Two strips of four rows of crops, with a water channel between.
+const FIELD_WIDTH: i64 = 9;
+/// Size of a field along the z axis.
+const FIELD_LENGTH: i64 = 9;
+/// Distance between the crop rows and the water channel watering them.
+const WATER_CHANNEL_INTERVAL: i64 = 4;
+/// Distance between the corners of neighbouring fields, leaving room for fences and paths.
+const FIELD_STRIDE: i64 = FIELD_WIDTH + 3;
+/// Distance fields must keep from town walls.
+const TOWN_MARGIN: u8 = 8;
+/// Farthest a field may be from the nearest road.
+const MAX_ROAD_DISTANCE: u8 = 40;
+/// Loss of score of a field per block of distance to the nearest road, against the mean
+/// suitability of its land.
+const ROAD_DISTANCE_COST: i64 = 2;
+
+/// The crop grown on a field.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Crop {
+    Wheat,
+    Carrots,
+    Potatoes,
+}
+
+/// A fenced rectangular field.
+#[derive(Clone, Debug)]
+pub struct Field {
+    /// Corner of the field with the lowest (x, z) coordinates, inside the fence.
+    pub origin: BlockColumnCoord,
+    /// Y coordinate of the farmland.
+    pub farmland_y: i64,
+    pub crop: Crop,
+    /// Side of the field with the gate, facing the nearest road.
+    pub gate_side: Surface4,
+    /// Path from the gate to the nearest country road, if one was found.
+    pub path: Option<RoadPath>,
+}
+
+impl Field {
+    /// Coordinates of the fence gate.
+    pub fn gate(&self) -> BlockColumnCoord {
+        let BlockColumnCoord(x, z) = self.origin;
+        match self.gate_side {
+            Surface4::North => BlockColumnCoord(x + FIELD_WIDTH / 2, z - 1),
+            Surface4::South => BlockColumnCoord(x + FIELD_WIDTH / 2, z + FIELD_LENGTH),
+            Surface4::West => BlockColumnCoord(x - 1, z + FIELD_LENGTH / 2),
+            Surface4::East => BlockColumnCoord(x + FIELD_WIDTH, z + FIELD_LENGTH / 2),
+        }
+    }
+
+    /// Corners of the field including its fence, as (min, max), both inclusive.
+    pub fn fenced_area(&self) -> (BlockColumnCoord, BlockColumnCoord) {
+        let BlockColumnCoord(x, z) = self.origin;
+        (BlockColumnCoord(x - 1, z - 1), BlockColumnCoord(x + FIELD_WIDTH, z + FIELD_LENGTH))
+    }
+}
+
+/// Find places for up to `max_count` fields on open, flat, fertile land,
+/// outside of the given town walls, on the most suitable land close to the country roads.
+pub fn plan_fields(
+    features: &Features,
+    areas: &Areas,
+    wall_circles: &[&Snake],
+    country_roads: &[RoadPath],
+    max_count: usize,
+    rng: &mut StdRng,
+) -> Vec<Field> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    // Keep clear of the towns.
+    let mut towns = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut towns, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+    imageproc::morphology::dilate_mut(&mut towns, Norm::LInf, TOWN_MARGIN);
+
+    // Candidate fields, on a grid, scored by the suitability of the land and the distance
+    // to the nearest road.
+    let mut candidates: Vec<(i64, BlockColumnCoord, i64)> = Vec::new();
+    for x in (2..x_len - FIELD_WIDTH - 2).step_by(FIELD_STRIDE as usize) {
+        for z in (2..z_len - FIELD_LENGTH - 2).step_by(FIELD_STRIDE as usize) {
+            if let Some((road_distance, suitability, farmland_y)) = field_suitability(features, areas, &towns, (x, z)) {
+                let score = suitability as i64 - road_distance as i64 * ROAD_DISTANCE_COST;
+                candidates.push((score, BlockColumnCoord(x, z), farmland_y));
+            }
+        }
+    }
+    candidates.sort_by_key(|(score, origin, _)| (Reverse(*score), origin.0, origin.1));
+
+    let obstacles = imageproc::morphology::dilate(&features.water, Norm::LInf, 2);
+
+    let mut fields = Vec::new();
+    for (_, origin, farmland_y) in candidates.into_iter().take(max_count) {
+        let center = BlockColumnCoord(origin.0 + FIELD_WIDTH / 2, origin.1 + FIELD_LENGTH / 2);
+        let nearest_road_node = country_roads.iter()
+            .flat_map(|road| road.iter())
+            .min_by_key(|node| geometry::manhattan_distance(center, node.coordinates.into()));
+
+        let gate_side = match nearest_road_node {
+            Some(node) => {
+                let (dx, dz) = (node.coordinates.0 - center.0, node.coordinates.2 - center.1);
+                if dx.abs() > dz.abs() {
+                    if dx > 0 { Surface4::East } else { Surface4::West }
+                } else if dz > 0 {
+                    Surface4::South
+                } else {
+                    Surface4::North
+                }
+            }
+            None => Surface4::North,
+        };
+
+        let crop = match rng.gen_range(0..4) {
+            0 => Crop::Carrots,
+            1 => Crop::Potatoes,
+            _ => Crop::Wheat,
+        };
+
+        let mut field = Field { origin, farmland_y, crop, gate_side, path: None };
+
+        // Path from just outside the gate to the nearest road
+        if let Some(node) = nearest_road_node {
+            let gate = field.gate();
+            let outside = match gate_side {
+                Surface4::North => BlockColumnCoord(gate.0, gate.1 - 1),
+                Surface4::South => BlockColumnCoord(gate.0, gate.1 + 1),
+                Surface4::West => BlockColumnCoord(gate.0 - 1, gate.1),
+                Surface4::East => BlockColumnCoord(gate.0 + 1, gate.1),
+            };
+            let image::Luma([start_y]) = features.terrain[(outside.0 as u32, outside.1 as u32)];
+            field.path = pathfinding::road_path(
+                BlockCoord(outside.0, start_y as i64, outside.1),
+                node.coordinates,
+                &features.terrain,
+                Some(&obstacles),
+            );
+        }
+
+        fields.push(field);
+    }
+
+    info!("Planned {} fields.", fields.len());
+    fields
+}
+
+/// Check if a field (with its fence) fits at `origin`. Returns the distance to the nearest
+/// road, the mean suitability of the land, and the height to put the farmland at.
+fn field_suitability(
+    features: &Features,
+    areas: &Areas,
+    towns: &GrayImage,
+    origin: (i64, i64),
+) -> Option<(u8, u8, i64)> {
+    let mut min_y = u8::MAX;
+    let mut max_y = u8::MIN;
+    let mut road_distance = u8::MAX;
+    let mut suitability_sum = 0;
+    let mut column_count = 0;
+
+    for x in origin.0 - 1..=origin.0 + FIELD_WIDTH {
+        for z in origin.1 - 1..=origin.1 + FIELD_LENGTH {
+            let column = (x as u32, z as u32);
+            if areas.agriculture_without_trees[column] != image::Luma([255u8])
+                || towns[column] == image::Luma([255u8])
+            {
+                return None;
+            }
+            match features.road_distance_at(column) {
+                Some(distance) if distance <= 1 => return None,
+                Some(distance) => road_distance = road_distance.min(distance),
+                None => (),
+            }
+            let image::Luma([y]) = features.terrain[column];
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+            let image::Luma([suitability]) = areas.suitability[column];
+            suitability_sum += suitability as usize;
+            column_count += 1;
+        }
+    }
+
+    if max_y - min_y > 1 || road_distance > MAX_ROAD_DISTANCE {
+        None
+    } else {
+        Some((road_distance, (suitability_sum / column_count.max(1)) as u8, min_y as i64 - 1))
+    }
+}
+
+/// Till the field, dig its water channels, plant its crops, and fence it in.
+pub fn build_field(excerpt: &mut WorldExcerpt, field: &Field, features: &Features) {
+    let BlockColumnCoord(x0, z0) = field.origin;
+    let y = field.farmland_y;
+
+    for x in x0..x0 + FIELD_WIDTH {
+        for z in z0..z0 + FIELD_LENGTH {
+            // Clear anything growing on the field.
+            for clear_y in y + 1..y + 4 {
+                tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+            }
+
+            if (x - x0 + 1) % (WATER_CHANNEL_INTERVAL + 1) == 0 {
+                excerpt.set_block_at(BlockCoord(x, y, z), Block::WaterSource);
+            } else {
+                excerpt.set_block_at(BlockCoord(x, y, z), Block::Farmland {
+                    wetness: Int0Through7::new(7).unwrap(),
+                });
+                excerpt.set_block_at(BlockCoord(x, y + 1, z), crop_block(field.crop));
+            }
+        }
+    }
+
+    // Fence around the field, with a gate towards the road.
+    let gate = field.gate();
+    for x in x0 - 1..=x0 + FIELD_WIDTH {
+        for z in z0 - 1..=z0 + FIELD_LENGTH {
+            let is_inside = x >= x0 && x < x0 + FIELD_WIDTH && z >= z0 && z < z0 + FIELD_LENGTH;
+            if is_inside {
+                continue;
+            }
+            let image::Luma([ground]) = features.terrain[(x as u32, z as u32)];
+            let coordinates = BlockCoord(x, ground as i64, z);
+            tree::chop(excerpt, coordinates);
+            if BlockColumnCoord(x, z) == gate {
+                excerpt.set_block_at(coordinates, Block::FenceGate {
+                    material: WoodMaterial::Oak,
+                    facing: field.gate_side,
+                    open: false,
+                });
+            } else {
+                excerpt.set_block_at(coordinates, Block::Fence {
+                    material: FenceMaterial::Oak,
+                    waterlogged: false,
+                });
+            }
+        }
+    }
+}
+
+fn crop_block(crop: Crop) -> Block {
+    let growth_stage = Int0Through7::new(7).unwrap();
+    match crop {
+        Crop::Wheat => Block::Wheat { growth_stage },
+        Crop::Carrots => Block::Carrots { growth_stage },
+        Crop::Potatoes => Block::Potatoes { growth_stage },
+    }
+}
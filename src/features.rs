@@ -1,14 +1,26 @@
 extern crate image;
 extern crate mcprogedit;
 
+use std::collections::VecDeque;
+
 use image::{GrayImage, RgbImage};
 use image::imageops::filter3x3;
 use imageproc::contrast::threshold;
-use imageproc::distance_transform::Norm;
+use imageproc::distance_transform::{distance_transform, Norm};
+use imageproc::drawing::draw_line_segment_mut;
+use imageproc::region_labelling::{connected_components, Connectivity};
+use log::info;
 use mcprogedit::block::*;
+use mcprogedit::coordinates::BlockColumnCoord;
 use mcprogedit::height_map::HeightMap;
 use mcprogedit::world_excerpt::WorldExcerpt;
 
+use crate::config::Dimension;
+use crate::geometry::{self, InOutSide};
+use crate::inspection::Inspection;
+use crate::pathfinding::RoadPath;
+use crate::types::Snake;
+
 pub struct Features {
     // Height maps
     pub height_map: HeightMap,
@@ -20,10 +32,27 @@ pub struct Features {
     // Gradients
     pub heights: GrayImage,
     pub terrain: GrayImage,
+    /// Height of the underside of the ceiling above the ground, 255 where there is no ceiling.
+    pub ceiling: GrayImage,
     pub water_depth: GrayImage,
+    /// Distance (LInf, saturating at 255) to the nearest lava at the surface.
+    pub lava_distance: GrayImage,
+    /// Width of the river around each river column, 0 outside the rivers.
+    pub river_width: GrayImage,
+    /// Direction of the river around each river column, as the angle in degrees (0 to 179)
+    /// from the x axis towards the z axis, 255 outside the rivers.
+    pub river_direction: GrayImage,
+    /// Height of the first dry block above a flood, over the land at risk of flooding, and 0
+    /// elsewhere.
+    pub flood_level: GrayImage,
     pub sobel_relief: GrayImage,
     pub scharr: GrayImage,
     pub scharr_cleaned: GrayImage,
+    /// Largest height difference to any of the four neighbouring columns.
+    pub slope: GrayImage,
+    /// Height above the average of the four neighbouring columns, times four, offset by 128:
+    /// Above 128 on convex ground such as ridges, below 128 on concave ground such as valleys.
+    pub curvature: GrayImage,
 
     // Stencils
     pub hilltop: GrayImage,
@@ -32,19 +61,326 @@ pub struct Features {
     pub sand: GrayImage,
     pub gravel: GrayImage,
     pub exposed_ore: GrayImage,
+    pub stone: GrayImage,
     pub forest: GrayImage,
     pub snow: GrayImage,
+    /// Shallow water, and the wet land along it.
+    pub swamp: GrayImage,
+    /// Land along the water, no more than `FLOOD_RISE` blocks above its level, at risk of
+    /// flooding.
+    pub flood_risk: GrayImage,
+    /// Lava at the surface.
+    pub lava: GrayImage,
+    /// Existing paths at the surface.
+    pub paths: GrayImage,
+    /// Steep drops, of `CLIFF_SLOPE_MIN` or more between neighbouring columns.
+    pub cliff: GrayImage,
+    /// Convex ground along the crests of the hills, other than cliffs.
+    pub ridge: GrayImage,
+    /// Flat ground raised above its surroundings, with steep or cliff edges, easily defended.
+    pub plateau: GrayImage,
+    /// Columns outside the build boundary, if one was given.
+    pub outside: GrayImage,
+
+    // Vector features
+    /// The cliffs, ridge lines and plateaus, each as the connected region of columns it covers.
+    pub terrain_features: Vec<TerrainFeature>,
+    /// The rivers: Long and narrow bodies of water.
+    pub rivers: Vec<River>,
+    /// The build boundary, if one was given, relative to the excerpt.
+    pub boundary: Option<Snake>,
+
+    // Infrastructure
+    /// Distance from each column to the nearest road, once the road network is final.
+    /// Used for placing the fields and the pastures near the roads.
+    pub road_distance: Option<GrayImage>,
+}
+
+/// Smallest height difference between neighbouring columns, for a cliff.
+const CLIFF_SLOPE_MIN: u8 = 4;
+/// Smallest curvature above flat ground, for a ridge.
+const RIDGE_CURVATURE_MIN: u8 = 3;
+/// Steepest slope on a plateau.
+const PLATEAU_SLOPE_MAX: u8 = 1;
+/// Least height of a plateau above the lowest ground within `PLATEAU_RADIUS` of it.
+const PLATEAU_RISE_MIN: u8 = 6;
+const PLATEAU_RADIUS: u32 = 12;
+/// Fewest columns in a cliff, ridge or plateau, for keeping it as a vector feature.
+const TERRAIN_FEATURE_SIZE_MIN: usize = 16;
+
+/// Smallest body of water, in columns, that may be a river.
+const RIVER_AREA_MIN: usize = 64;
+/// Smallest ratio of length to width of a river.
+const RIVER_ELONGATION_MIN: f32 = 4.0;
+/// Radius of the neighbourhood the direction and width of a river are measured over.
+const RIVER_NEIGHBOURHOOD_RADIUS: i64 = 8;
+/// Share of the columns at each end of a river, for comparing the heights of the ends.
+const RIVER_END_SHARE: f32 = 0.1;
+
+/// A river: A long and narrow body of water.
+#[derive(Clone, Debug)]
+pub struct River {
+    pub columns: Vec<BlockColumnCoord>,
+    /// Direction of flow, as a unit vector along the x and z axes, from the end with the
+    /// higher ground around it towards the end with the lower.
+    pub flow: (f32, f32),
+    /// Widest the river is, in blocks.
+    pub width_max: u8,
+}
+
+/// The kind of a terrain feature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TerrainFeatureKind {
+    Cliff,
+    Ridge,
+    Plateau,
 }
 
+/// A cliff, ridge line or plateau, as the connected region of columns it covers.
+#[derive(Clone, Debug)]
+pub struct TerrainFeature {
+    pub kind: TerrainFeatureKind,
+    pub columns: Vec<BlockColumnCoord>,
+}
+
+impl TerrainFeature {
+    /// The average of the columns of the feature.
+    pub fn center(&self) -> BlockColumnCoord {
+        let count = self.columns.len().max(1) as i64;
+        let (x_sum, z_sum) = self.columns.iter()
+            .fold((0, 0), |(x_sum, z_sum), column| (x_sum + column.0, z_sum + column.1));
+        BlockColumnCoord(x_sum / count, z_sum / count)
+    }
+
+    /// Length of the diagonal of the bounding box of the feature, as a rough measure of
+    /// the length of cliffs and ridge lines, and the breadth of plateaus.
+    pub fn extent(&self) -> f32 {
+        let x_min = self.columns.iter().map(|column| column.0).min().unwrap_or(0);
+        let x_max = self.columns.iter().map(|column| column.0).max().unwrap_or(0);
+        let z_min = self.columns.iter().map(|column| column.1).min().unwrap_or(0);
+        let z_max = self.columns.iter().map(|column| column.1).max().unwrap_or(0);
+        (((x_max - x_min).pow(2) + (z_max - z_min).pow(2)) as f32).sqrt()
+    }
+}
+
+/// Deepest water counting as swamp, rather than open water.
+const SWAMP_WATER_DEPTH_MAX: u8 = 2;
+/// How far from the shallow water the land counts as swamp.
+const SWAMP_SHORE_WIDTH: u8 = 2;
+/// Highest the water may rise above its level in a flood.
+const FLOOD_RISE: u8 = 2;
+/// Farthest from the water, in columns, that a flood may reach.
+const FLOOD_REACH: u32 = 16;
+
 impl Features {
     pub fn dimensions(&self) -> (usize, usize) {
         self.height_map.dim()
     }
 
-    pub fn new_from_world_excerpt(excerpt: &WorldExcerpt) -> Self {
+    /// Distance (L1, saturating at 255) from the given column to the nearest road.
+    /// Returns None if the road network has not yet been set.
+    pub fn road_distance_at(&self, column: (u32, u32)) -> Option<u8> {
+        let road_distance = self.road_distance.as_ref()?;
+        let (x_len, z_len) = road_distance.dimensions();
+        if column.0 >= x_len || column.1 >= z_len {
+            return None;
+        }
+        let image::Luma([distance]) = road_distance[column];
+        Some(distance)
+    }
+
+    /// Check if a site covering the given columns is inside the area, free, dry,
+    /// and no steeper than `slope_max`. Returns the height of the floor if it is,
+    /// level with the highest ground on the site.
+    pub fn site_floor(
+        &self,
+        occupied: &GrayImage,
+        columns: &[(i64, i64)],
+        slope_max: i64,
+    ) -> Option<i64> {
+        let (x_len, z_len) = self.dimensions();
+
+        let mut min_y = i64::MAX;
+        let mut max_y = i64::MIN;
+        for (x, z) in columns {
+            if *x < 0 || *z < 0 || *x >= x_len as i64 || *z >= z_len as i64 {
+                return None;
+            }
+            let column = (*x as u32, *z as u32);
+            if occupied[column] == image::Luma([255u8]) || self.water[column] == image::Luma([255u8]) {
+                return None;
+            }
+            let image::Luma([y]) = self.terrain[column];
+            min_y = min_y.min(y as i64);
+            max_y = max_y.max(y as i64);
+        }
+
+        if max_y - min_y > slope_max {
+            None
+        } else {
+            Some(max_y - 1)
+        }
+    }
+
+    /// Share of the area inside the polygon that is swamp or shallow water.
+    pub fn swamp_share(&self, polygon: &[BlockColumnCoord]) -> f32 {
+        self.stencil_share(&self.swamp, polygon)
+    }
+
+    /// Share of the area inside the polygon that is at risk of flooding.
+    pub fn flood_risk_share(&self, polygon: &[BlockColumnCoord]) -> f32 {
+        self.stencil_share(&self.flood_risk, polygon)
+    }
+
+    /// Height of the first dry block above the highest flood inside the polygon, or None if
+    /// none of it is at risk of flooding.
+    pub fn flood_level_max(&self, polygon: &[BlockColumnCoord]) -> Option<usize> {
+        self.columns_inside(polygon).into_iter()
+            .map(|column| {
+                let image::Luma([level]) = self.flood_level[column];
+                level as usize
+            })
+            .filter(|level| *level > 0)
+            .max()
+    }
+
+    /// Least room between the ground and the ceiling inside the polygon, or None if there
+    /// is no ceiling above any of it.
+    pub fn headroom_min(&self, polygon: &[BlockColumnCoord]) -> Option<usize> {
+        self.columns_inside(polygon).into_iter()
+            .filter_map(|column| {
+                let image::Luma([ceiling_y]) = self.ceiling[column];
+                let image::Luma([terrain_y]) = self.terrain[column];
+                if ceiling_y == u8::MAX {
+                    None
+                } else {
+                    Some(ceiling_y.saturating_sub(terrain_y) as usize)
+                }
+            })
+            .min()
+    }
+
+    /// Stencil of both the water and the lava, for keeping roads and streets out of either.
+    pub fn water_and_lava(&self) -> GrayImage {
+        let mut water_and_lava = self.water.clone();
+        for (x, z, pixel) in self.lava.enumerate_pixels() {
+            if *pixel == image::Luma([255u8]) {
+                water_and_lava.put_pixel(x, z, *pixel);
+            }
+        }
+        water_and_lava
+    }
+
+    /// Share of the area inside the polygon that is set in the stencil.
+    fn stencil_share(&self, stencil: &GrayImage, polygon: &[BlockColumnCoord]) -> f32 {
+        let columns = self.columns_inside(polygon);
+        let set_count = columns.iter()
+            .filter(|column| stencil[**column] == image::Luma([255u8]))
+            .count();
+        if columns.is_empty() {
+            0.0
+        } else {
+            set_count as f32 / columns.len() as f32
+        }
+    }
+
+    /// The columns inside the polygon, within the bounds of the features.
+    fn columns_inside(&self, polygon: &[BlockColumnCoord]) -> Vec<(u32, u32)> {
+        let (x_len, z_len) = self.terrain.dimensions();
+        let x_range = polygon.iter().map(|point| point.0.max(0) as u32).min().unwrap_or(0)
+            ..=polygon.iter().map(|point| point.0.max(0) as u32).max().unwrap_or(0).min(x_len.saturating_sub(1));
+        let z_range = polygon.iter().map(|point| point.1.max(0) as u32).min().unwrap_or(0)
+            ..=polygon.iter().map(|point| point.1.max(0) as u32).max().unwrap_or(0).min(z_len.saturating_sub(1));
+        let mut columns = Vec::new();
+        for x in x_range {
+            for z in z_range.clone() {
+                let column = BlockColumnCoord(x as i64, z as i64);
+                if geometry::point_position_relative_to_polygon(column, polygon) == InOutSide::Inside {
+                    columns.push((x, z));
+                }
+            }
+        }
+        columns
+    }
+
+    /// Whether the column is outside the build boundary, or outside the excerpt.
+    pub fn is_outside(&self, column: (i64, i64)) -> bool {
+        let (x_len, z_len) = self.outside.dimensions();
+        column.0 < 0
+            || column.1 < 0
+            || column.0 >= x_len as i64
+            || column.1 >= z_len as i64
+            || self.outside[(column.0 as u32, column.1 as u32)] == image::Luma([255u8])
+    }
+
+    /// Keep to the given build boundary, relative to the excerpt: Mark the columns outside
+    /// of it, for the areas and the roads to keep out of them.
+    pub fn clip_to_boundary(&mut self, boundary: Snake, inspection: &Inspection) {
+        let (x_len, z_len) = self.terrain.dimensions();
+
+        // Inside, including the boundary itself
+        let mut inside = image::ImageBuffer::new(x_len, z_len);
+        geometry::draw_area(&mut inside, &boundary, BlockColumnCoord(0, 0), image::Luma([255u8]));
+        for segment in boundary.windows(2) {
+            let start = (segment[0].0 as f32, segment[0].1 as f32);
+            let end = (segment[1].0 as f32, segment[1].1 as f32);
+            draw_line_segment_mut(&mut inside, start, end, image::Luma([255u8]));
+        }
+
+        let mut outside = inside;
+        image::imageops::invert(&mut outside);
+        inspection.save_image("09 outside boundary.png", &outside);
+
+        self.outside = outside;
+        self.boundary = Some(boundary);
+    }
+
+    /// Compute the distance-to-nearest-road raster for the final road network.
+    ///
+    /// This should be done once, after all roads and streets are decided,
+    /// so that every later pass can share the same distance field, rather than
+    /// computing its own. Passes placing things among the fields and the countryside
+    /// read it through `road_distance_at`.
+    pub fn set_road_network<'a, I>(&mut self, roads: I, inspection: &Inspection)
+    where
+        I: IntoIterator<Item = &'a RoadPath>,
+    {
+        let (x_len, z_len) = self.terrain.dimensions();
+
+        let mut road_mask = image::ImageBuffer::new(x_len, z_len);
+        for road in roads {
+            for line in road.windows(2) {
+                let start = (line[0].coordinates.0 as f32, line[0].coordinates.2 as f32);
+                let end = (line[1].coordinates.0 as f32, line[1].coordinates.2 as f32);
+                draw_line_segment_mut(&mut road_mask, start, end, image::Luma([255u8]));
+            }
+            if let [node] = road.as_slice() {
+                let (x, z) = (node.coordinates.0, node.coordinates.2);
+                if x >= 0 && z >= 0 && (x as u32) < x_len && (z as u32) < z_len {
+                    road_mask.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+                }
+            }
+        }
+
+        let road_distance = distance_transform(&road_mask, Norm::L1);
+
+        inspection.save_image("07 road distance.png", &road_distance);
+
+        self.road_distance = Some(road_distance);
+    }
+
+    /// Extract the features of the world excerpt. In dimensions with a ceiling, the ground
+    /// is the floor of the highest open space below the ceiling, so the excerpt should be
+    /// bounded in y to the layer of the world to build in.
+    pub fn new_from_world_excerpt(excerpt: &WorldExcerpt, dimension: Dimension, inspection: &Inspection) -> Self {
         let (x_len, y_len, z_len) = excerpt.dim();
 
-        let height_map = excerpt.height_map();
+        let (height_map, ceiling) = if dimension.has_ceiling() {
+            floor_height_map(excerpt)
+        } else {
+            (excerpt.height_map(), image::ImageBuffer::from_pixel(x_len as u32, z_len as u32, image::Luma([u8::MAX])))
+        };
+        inspection.save_image("02b ceiling.png", &ceiling);
 
         // Create a bitmap showing the (raw) height map.
         let mut heights = image::ImageBuffer::new(x_len as u32, z_len as u32);
@@ -131,8 +467,7 @@ impl Features {
         let horizontal_reverse_img = image::imageops::filter3x3(&terrain, &horizontal_reverse_sobel);
         let vertical_reverse_img = image::imageops::filter3x3(&terrain, &vertical_reverse_sobel);
 
-        #[cfg(feature = "debug_images")]
-        {
+        if inspection.is_enabled() {
             let gauss = [1.0f32/8.0, 1.0/4.0, 1.0/8.0,
                          1.0/4.0, 1.0/2.0, 1.0/4.0,
                          1.0/8.0, 1.0/4.0, 1.0/8.0];
@@ -145,9 +480,9 @@ impl Features {
             let edge_img = image::imageops::filter3x3(&edge_img, &gauss);
             let edge_img = image::imageops::filter3x3(&edge_img, &edge);
 
-            horizontal_img.save("04a horizontal sobel.png").unwrap();
-            vertical_img.save("04b vertical sobel.png").unwrap();
-            edge_img.save("04c edge.png").unwrap();
+            inspection.save_image("04a horizontal sobel.png", &horizontal_img);
+            inspection.save_image("04b vertical sobel.png", &vertical_img);
+            inspection.save_image("04c edge.png", &edge_img);
         }
 
         // Full Sobel
@@ -168,8 +503,7 @@ impl Features {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        sobel_relief.save("04d sobel.png").unwrap();
+        inspection.save_image("04d sobel.png", &sobel_relief);
 
         // Full Scharr
         let horizontal_img = image::imageops::filter3x3(&terrain, &horizontal_scharr);
@@ -192,8 +526,7 @@ impl Features {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        scharr.save("04e scharr.png").unwrap();
+        inspection.save_image("04e scharr.png", &scharr);
 
         // Hilltops (double scharr)
         const THRESHOLD: u8 = 9;
@@ -230,8 +563,7 @@ impl Features {
             },
         );
 
-        #[cfg(feature = "debug_images")]
-        hilltop.save("04f hilltop.png").unwrap();
+        inspection.save_image("04f hilltop.png", &hilltop);
 
         // scharr with low values removed
         let mut scharr_cleaned = scharr.clone();
@@ -245,8 +577,7 @@ impl Features {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        scharr_cleaned.save("04f scharr cleaned.png").unwrap();
+        inspection.save_image("04f scharr cleaned.png", &scharr_cleaned);
 
         // Various features
         let mut water = image::ImageBuffer::new(x_len as u32, z_len as u32);
@@ -254,7 +585,10 @@ impl Features {
         let mut sand = image::ImageBuffer::new(x_len as u32, z_len as u32);
         let mut gravel = image::ImageBuffer::new(x_len as u32, z_len as u32);
         let mut exposed_ore = image::ImageBuffer::new(x_len as u32, z_len as u32);
+        let mut stone = image::ImageBuffer::new(x_len as u32, z_len as u32);
         let mut snow = image::ImageBuffer::new(x_len as u32, z_len as u32);
+        let mut lava = image::ImageBuffer::new(x_len as u32, z_len as u32);
+        let mut paths = image::ImageBuffer::new(x_len as u32, z_len as u32);
 
         for x in 0..x_len as u32 {
             for z in 0..z_len as u32 {
@@ -274,6 +608,9 @@ impl Features {
                             }
                             if let Some(block) = excerpt.block_at((x as i64, y as i64 - 1, z as i64).into()) {
                                 match block {
+                                    Block::LavaSource
+                                    | Block::Lava { .. } => lava.put_pixel(x, z, image::Luma([255u8])),
+                                    Block::GrassPath => paths.put_pixel(x, z, image::Luma([255u8])),
                                     Block::CoarseDirt
                                     | Block::Dirt
                                     | Block::Farmland { .. }
@@ -289,6 +626,10 @@ impl Features {
                                     | Block::IronOre
                                     | Block::LapisLazuliOre
                                     | Block::RedstoneOre => exposed_ore.put_pixel(x, z, image::Luma([255u8])),
+                                    Block::Stone
+                                    | Block::Andesite
+                                    | Block::Diorite
+                                    | Block::Granite => stone.put_pixel(x, z, image::Luma([255u8])),
                                     _ => (),
                                 }
                             }
@@ -298,14 +639,16 @@ impl Features {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        {
-            water.save("05a water.png").unwrap();
-            fertile.save("05b fertile land.png").unwrap();
-            sand.save("05c sand.png").unwrap();
-            gravel.save("05d gravel.png").unwrap();
-            exposed_ore.save("05e exposed ore.png").unwrap();
-        }
+        inspection.save_image("05a water.png", &water);
+        inspection.save_image("05b fertile land.png", &fertile);
+        inspection.save_image("05c sand.png", &sand);
+        inspection.save_image("05d gravel.png", &gravel);
+        inspection.save_image("05e exposed ore.png", &exposed_ore);
+        inspection.save_image("05h stone.png", &stone);
+        inspection.save_image("05i lava.png", &lava);
+        inspection.save_image("05j paths.png", &paths);
+
+        let lava_distance = distance_transform(&lava, Norm::LInf);
 
         // Forests
         let mut forest = image::ImageBuffer::new(x_len as u32, z_len as u32);
@@ -322,11 +665,8 @@ impl Features {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        {
-            forest.save("05f forest.png").unwrap();
-            snow.save("05g snow.png").unwrap();
-        }
+        inspection.save_image("05f forest.png", &forest);
+        inspection.save_image("05g snow.png", &snow);
 
         // Water depth
         let mut water_depth = image::ImageBuffer::new(x_len as u32, z_len as u32);
@@ -345,8 +685,76 @@ impl Features {
             }
         }
 
-        #[cfg(feature = "debug_images")]
-        water_depth.save("06 water depth.png").unwrap();
+        inspection.save_image("06 water depth.png", &water_depth);
+
+        // Swamp: Shallow water, and the wet land along it, but not the deeper water
+        let mut shallow_water = image::ImageBuffer::new(x_len as u32, z_len as u32);
+        for x in 0..x_len as u32 {
+            for z in 0..z_len as u32 {
+                let image::Luma([depth]) = water_depth[(x, z)];
+                if water[(x, z)] == image::Luma([255u8]) && depth <= SWAMP_WATER_DEPTH_MAX {
+                    shallow_water.put_pixel(x, z, image::Luma([255u8]));
+                }
+            }
+        }
+        let mut swamp = imageproc::morphology::dilate(&shallow_water, Norm::LInf, SWAMP_SHORE_WIDTH);
+        for x in 0..x_len as u32 {
+            for z in 0..z_len as u32 {
+                let image::Luma([depth]) = water_depth[(x, z)];
+                if water[(x, z)] == image::Luma([255u8]) && depth > SWAMP_WATER_DEPTH_MAX {
+                    swamp.put_pixel(x, z, image::Luma([0u8]));
+                }
+            }
+        }
+
+        inspection.save_image("06b swamp.png", &swamp);
+
+        // Land low enough along the water to be flooded
+        let (flood_risk, flood_level) = flood_risk(&water, &heights, &terrain);
+        inspection.save_image("06c flood risk.png", &flood_risk);
+
+        // Cliffs, ridges and plateaus, from the slope and the curvature of the terrain
+        let (slope, curvature) = slope_and_curvature(&terrain);
+        inspection.save_image("08a slope.png", &slope);
+        inspection.save_image("08b curvature.png", &curvature);
+
+        let lowest_nearby = local_minimum(&terrain, PLATEAU_RADIUS);
+        let mut cliff = image::ImageBuffer::new(x_len as u32, z_len as u32);
+        let mut ridge = image::ImageBuffer::new(x_len as u32, z_len as u32);
+        let mut plateau = image::ImageBuffer::new(x_len as u32, z_len as u32);
+        for x in 0..x_len as u32 {
+            for z in 0..z_len as u32 {
+                if water[(x, z)] == image::Luma([255u8]) {
+                    continue;
+                }
+                let image::Luma([column_slope]) = slope[(x, z)];
+                let image::Luma([column_curvature]) = curvature[(x, z)];
+                let image::Luma([height]) = terrain[(x, z)];
+                let image::Luma([lowest]) = lowest_nearby[(x, z)];
+                if column_slope >= CLIFF_SLOPE_MIN {
+                    cliff.put_pixel(x, z, image::Luma([255u8]));
+                } else if column_curvature >= 128 + RIDGE_CURVATURE_MIN {
+                    ridge.put_pixel(x, z, image::Luma([255u8]));
+                } else if column_slope <= PLATEAU_SLOPE_MAX && height >= lowest.saturating_add(PLATEAU_RISE_MIN) {
+                    plateau.put_pixel(x, z, image::Luma([255u8]));
+                }
+            }
+        }
+        inspection.save_image("08c cliffs.png", &cliff);
+        inspection.save_image("08d ridges.png", &ridge);
+        inspection.save_image("08e plateaus.png", &plateau);
+
+        let terrain_features: Vec<TerrainFeature> = regions_as_features(&cliff, TerrainFeatureKind::Cliff)
+            .into_iter()
+            .chain(regions_as_features(&ridge, TerrainFeatureKind::Ridge))
+            .chain(regions_as_features(&plateau, TerrainFeatureKind::Plateau))
+            .collect();
+
+        // Rivers, with their local direction and width
+        let (rivers, river_width, river_direction) = detect_rivers(&water, &terrain);
+        info!("Found {} river(s).", rivers.len());
+        inspection.save_image("08f river width.png", &river_width);
+        inspection.save_image("08g river direction.png", &river_direction);
 
         Self {
             // Height maps
@@ -359,10 +767,17 @@ impl Features {
             // Gradients
             heights,
             terrain,
+            ceiling,
             water_depth,
+            lava_distance,
+            river_width,
+            river_direction,
+            flood_level,
             sobel_relief,
             scharr,
             scharr_cleaned,
+            slope,
+            curvature,
 
             // Stencils
             hilltop,
@@ -371,8 +786,302 @@ impl Features {
             sand,
             gravel,
             exposed_ore,
+            stone,
             forest,
             snow,
+            swamp,
+            flood_risk,
+            lava,
+            paths,
+            cliff,
+            ridge,
+            plateau,
+            outside: image::ImageBuffer::new(x_len as u32, z_len as u32),
+
+            // Vector features
+            terrain_features,
+            rivers,
+            boundary: None,
+
+            // Infrastructure
+            road_distance: None,
+        }
+    }
+}
+
+/// Height map of the ground below the ceiling, for dimensions with a ceiling: The floor of
+/// the highest open space found going down from the top of the excerpt, through the ceiling
+/// if there is one. Also returns the height of the underside of the ceiling above each
+/// column, or 255 where the column is open to the top of the excerpt.
+fn floor_height_map(excerpt: &WorldExcerpt) -> (HeightMap, GrayImage) {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut height_map = excerpt.height_map();
+    let mut ceiling = image::ImageBuffer::from_pixel(x_len as u32, z_len as u32, image::Luma([u8::MAX]));
+
+    for x in 0..x_len {
+        for z in 0..z_len {
+            let is_air = |y: i64| {
+                matches!(excerpt.block_at((x as i64, y, z as i64).into()), Some(Block::Air))
+            };
+
+            // Down through the ceiling, if there is one
+            let mut y = y_len as i64 - 1;
+            while y >= 0 && !is_air(y) {
+                y -= 1;
+            }
+            if y < 0 {
+                // Solid all the way down; keep the top as the ground
+                continue;
+            }
+            if y < y_len as i64 - 1 {
+                ceiling.put_pixel(x as u32, z as u32, image::Luma([(y + 1).min(u8::MAX as i64 - 1) as u8]));
+            }
+
+            // Down through the open space, to the floor
+            while y >= 0 && is_air(y) {
+                y -= 1;
+            }
+            height_map.set_height((x, z), (y + 1) as u32);
+        }
+    }
+
+    (height_map, ceiling)
+}
+
+/// Slope and curvature of the terrain height map. The slope is the largest height difference
+/// to any of the four neighbouring columns. The curvature is four times the height above the
+/// average of the four neighbours, offset by 128, and limited to 0 through 255.
+fn slope_and_curvature(terrain: &GrayImage) -> (GrayImage, GrayImage) {
+    let (x_len, z_len) = terrain.dimensions();
+    let mut slope = GrayImage::new(x_len, z_len);
+    let mut curvature = GrayImage::new(x_len, z_len);
+    for x in 0..x_len {
+        for z in 0..z_len {
+            let image::Luma([height]) = terrain[(x, z)];
+            let neighbours = [
+                (x.wrapping_sub(1), z),
+                (x + 1, z),
+                (x, z.wrapping_sub(1)),
+                (x, z + 1),
+            ];
+            let mut slope_max = 0i32;
+            let mut convexity = 0i32;
+            for (neighbour_x, neighbour_z) in neighbours.iter() {
+                // Columns outside the edges count as level with this one
+                let neighbour_height = if *neighbour_x < x_len && *neighbour_z < z_len {
+                    terrain[(*neighbour_x, *neighbour_z)][0] as i32
+                } else {
+                    height as i32
+                };
+                slope_max = slope_max.max((height as i32 - neighbour_height).abs());
+                convexity += height as i32 - neighbour_height;
+            }
+            slope.put_pixel(x, z, image::Luma([slope_max.min(255) as u8]));
+            curvature.put_pixel(x, z, image::Luma([(128 + convexity).max(0).min(255) as u8]));
+        }
+    }
+    (slope, curvature)
+}
+
+/// The lowest value within `radius` (LInf) of each pixel.
+fn local_minimum(image: &GrayImage, radius: u32) -> GrayImage {
+    let (x_len, z_len) = image.dimensions();
+    let mut along_x = GrayImage::new(x_len, z_len);
+    for x in 0..x_len {
+        for z in 0..z_len {
+            let lowest = (x.saturating_sub(radius)..=(x + radius).min(x_len - 1))
+                .map(|x| image[(x, z)][0])
+                .min()
+                .unwrap_or(0);
+            along_x.put_pixel(x, z, image::Luma([lowest]));
+        }
+    }
+    let mut minimum = GrayImage::new(x_len, z_len);
+    for x in 0..x_len {
+        for z in 0..z_len {
+            let lowest = (z.saturating_sub(radius)..=(z + radius).min(z_len - 1))
+                .map(|z| along_x[(x, z)][0])
+                .min()
+                .unwrap_or(0);
+            minimum.put_pixel(x, z, image::Luma([lowest]));
         }
     }
+    minimum
+}
+
+/// The connected regions of the stencil, of at least `TERRAIN_FEATURE_SIZE_MIN` columns,
+/// as terrain features of the given kind.
+fn regions_as_features(stencil: &GrayImage, kind: TerrainFeatureKind) -> Vec<TerrainFeature> {
+    let labels = connected_components(stencil, Connectivity::Eight, image::Luma([0u8]));
+    let mut regions: Vec<Vec<BlockColumnCoord>> = Vec::new();
+    for (x, z, label) in labels.enumerate_pixels() {
+        let image::Luma([label]) = *label;
+        if label == 0 {
+            continue;
+        }
+        let index = label as usize - 1;
+        if regions.len() <= index {
+            regions.resize(index + 1, Vec::new());
+        }
+        regions[index].push(BlockColumnCoord(x as i64, z as i64));
+    }
+    regions.into_iter()
+        .filter(|columns| columns.len() >= TERRAIN_FEATURE_SIZE_MIN)
+        .map(|columns| TerrainFeature { kind, columns })
+        .collect()
+}
+
+/// Find the land at risk of flooding: Reached from the water over land no more than
+/// `FLOOD_RISE` blocks above the level of the water, within `FLOOD_REACH` columns of it.
+/// Returns the stencil of the land at risk, and the height of the first dry block above the
+/// flood over each column of it.
+fn flood_risk(water: &GrayImage, heights: &GrayImage, terrain: &GrayImage) -> (GrayImage, GrayImage) {
+    let (x_len, z_len) = water.dimensions();
+    let mut flood_risk = image::ImageBuffer::new(x_len, z_len);
+    let mut flood_level = image::ImageBuffer::new(x_len, z_len);
+    let is_water = |x: u32, z: u32| water[(x, z)] == image::Luma([255u8]);
+
+    // Spread out over the land from the shore, keeping the level of the water it came from
+    let mut queue = VecDeque::new();
+    for x in 0..x_len {
+        for z in 0..z_len {
+            if is_water(x, z) {
+                let image::Luma([surface]) = heights[(x, z)];
+                queue.push_back((x, z, surface.saturating_add(FLOOD_RISE), 0));
+            }
+        }
+    }
+    while let Some((x, z, level, distance)) = queue.pop_front() {
+        if distance >= FLOOD_REACH {
+            continue;
+        }
+        let neighbours = [
+            (x.wrapping_sub(1), z),
+            (x + 1, z),
+            (x, z.wrapping_sub(1)),
+            (x, z + 1),
+        ];
+        for (nx, nz) in neighbours {
+            if nx >= x_len || nz >= z_len || is_water(nx, nz) || flood_level[(nx, nz)] != image::Luma([0u8]) {
+                continue;
+            }
+            let image::Luma([ground]) = terrain[(nx, nz)];
+            if ground <= level {
+                flood_risk.put_pixel(nx, nz, image::Luma([255u8]));
+                flood_level.put_pixel(nx, nz, image::Luma([level]));
+                queue.push_back((nx, nz, level, distance + 1));
+            }
+        }
+    }
+
+    (flood_risk, flood_level)
+}
+
+/// Find the rivers among the bodies of water: Those long and narrow enough, judged by their
+/// area and their width. Also returns the width and direction of the rivers around each of
+/// their columns, as in `Features::river_width` and `Features::river_direction`.
+fn detect_rivers(water: &GrayImage, terrain: &GrayImage) -> (Vec<River>, GrayImage, GrayImage) {
+    let (x_len, z_len) = water.dimensions();
+    let mut river_width = GrayImage::new(x_len, z_len);
+    let mut river_direction = GrayImage::from_pixel(x_len, z_len, image::Luma([u8::MAX]));
+    let mut rivers = Vec::new();
+
+    // Distance from each water column to the nearest land
+    let mut land = water.clone();
+    image::imageops::colorops::invert(&mut land);
+    let shore_distance = distance_transform(&land, Norm::L1);
+
+    let labels = connected_components(water, Connectivity::Eight, image::Luma([0u8]));
+    let mut bodies: Vec<Vec<BlockColumnCoord>> = Vec::new();
+    for (x, z, label) in labels.enumerate_pixels() {
+        let image::Luma([label]) = *label;
+        if label == 0 {
+            continue;
+        }
+        let index = label as usize - 1;
+        if bodies.len() <= index {
+            bodies.resize(index + 1, Vec::new());
+        }
+        bodies[index].push(BlockColumnCoord(x as i64, z as i64));
+    }
+
+    for columns in bodies {
+        if columns.len() < RIVER_AREA_MIN {
+            continue;
+        }
+        let width_max = columns.iter()
+            .map(|column| shore_distance[(column.0 as u32, column.1 as u32)][0])
+            .max()
+            .unwrap_or(0)
+            .saturating_mul(2);
+        let length = columns.len() as f32 / width_max.max(1) as f32;
+        if length < RIVER_ELONGATION_MIN * width_max as f32 {
+            continue;
+        }
+
+        // Local width and direction, over the river columns in the neighbourhood
+        let label = labels[(columns[0].0 as u32, columns[0].1 as u32)];
+        for column in &columns {
+            let mut neighbourhood = Vec::new();
+            let mut width = 0;
+            for x in column.0 - RIVER_NEIGHBOURHOOD_RADIUS..=column.0 + RIVER_NEIGHBOURHOOD_RADIUS {
+                for z in column.1 - RIVER_NEIGHBOURHOOD_RADIUS..=column.1 + RIVER_NEIGHBOURHOOD_RADIUS {
+                    if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 || labels[(x as u32, z as u32)] != label {
+                        continue;
+                    }
+                    neighbourhood.push(BlockColumnCoord(x, z));
+                    width = width.max(shore_distance[(x as u32, z as u32)][0].saturating_mul(2));
+                }
+            }
+            let (direction_x, direction_z) = principal_direction(&neighbourhood);
+            let degrees = direction_z.atan2(direction_x).to_degrees().rem_euclid(180.0) as u8;
+            river_width.put_pixel(column.0 as u32, column.1 as u32, image::Luma([width]));
+            river_direction.put_pixel(column.0 as u32, column.1 as u32, image::Luma([degrees.min(179)]));
+        }
+
+        // Flow from the end with the higher ground around it, towards the lower
+        let direction = principal_direction(&columns);
+        let mut projections: Vec<(f32, BlockColumnCoord)> = columns.iter()
+            .map(|column| (column.0 as f32 * direction.0 + column.1 as f32 * direction.1, *column))
+            .collect();
+        projections.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let end_count = ((projections.len() as f32 * RIVER_END_SHARE) as usize).max(1);
+        let end_height = |end: &[(f32, BlockColumnCoord)]| {
+            let heights: Vec<f32> = end.iter()
+                .flat_map(|(_, column)| {
+                    (column.0 - 2..=column.0 + 2).flat_map(move |x| (column.1 - 2..=column.1 + 2).map(move |z| (x, z)))
+                })
+                .filter(|(x, z)| *x >= 0 && *z >= 0 && *x < x_len as i64 && *z < z_len as i64)
+                .map(|(x, z)| terrain[(x as u32, z as u32)][0] as f32)
+                .collect();
+            heights.iter().sum::<f32>() / heights.len().max(1) as f32
+        };
+        let start_height = end_height(&projections[..end_count]);
+        let finish_height = end_height(&projections[projections.len() - end_count..]);
+        let flow = if start_height >= finish_height {
+            direction
+        } else {
+            (-direction.0, -direction.1)
+        };
+
+        rivers.push(River { columns, flow, width_max });
+    }
+
+    (rivers, river_width, river_direction)
+}
+
+/// Direction of the principal axis of the columns, as a unit vector along the x and z axes.
+fn principal_direction(columns: &[BlockColumnCoord]) -> (f32, f32) {
+    let count = columns.len().max(1) as f32;
+    let x_mean = columns.iter().map(|column| column.0 as f32).sum::<f32>() / count;
+    let z_mean = columns.iter().map(|column| column.1 as f32).sum::<f32>() / count;
+    let (mut xx, mut zz, mut xz) = (0.0f32, 0.0f32, 0.0f32);
+    for column in columns {
+        let (dx, dz) = (column.0 as f32 - x_mean, column.1 as f32 - z_mean);
+        xx += dx * dx;
+        zz += dz * dz;
+        xz += dx * dz;
+    }
+    let angle = 0.5 * (2.0 * xz).atan2(xx - zz);
+    (angle.cos(), angle.sin())
 }
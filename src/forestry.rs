@@ -0,0 +1,260 @@
+//! Forestry in the woods near town: A lumber camp in a clear-cut patch of forest,
+//! with a cabin, stacks of logs and a sawpit, and saplings replanted in rows.
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::info;
+use mcprogedit::block::{Block, SaplingMaterial};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{Material, WoodMaterial};
+use mcprogedit::positioning::{Axis3, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+
+use crate::block_palette::BlockPalette;
+use crate::config::GenerationConfig;
+use crate::farm::Field;
+use crate::features::Features;
+use crate::generation_context::paste_chopping_trees;
+use crate::geometry::{self, disc, is_in_disc, step};
+use crate::hut::{self, HUT_FOUNDATION_DEPTH};
+use crate::pathfinding::{self, RoadPath};
+use crate::road::{self, RoadStyle};
+use crate::signs;
+use crate::tree;
+use crate::types::Snake;
+
+/// Distance between the candidate sites that are considered.
+const SITE_SPACING: usize = 4;
+/// Distance the camp must keep from the fields and from the town walls.
+const CLEARANCE: u8 = 4;
+/// Radius of the clear-cut patch.
+const CLEARING_RADIUS: i64 = 9;
+/// Largest difference in ground height within the clearing.
+const CLEARING_SLOPE_MAX: i64 = 5;
+/// Outer dimensions of the cabin.
+const CABIN_SIZE: (i64, i64) = (5, 5);
+/// Distance between the saplings, both within and between rows.
+const SAPLING_SPACING: i64 = 3;
+/// Width of the road from the camp.
+const ROAD_WIDTH: i64 = 2;
+/// Cost of a site per step of suitability of its land for farming, against its distance
+/// to town, for sparing the land best suited for fields.
+const SUITABILITY_COST: usize = 1;
+
+/// A lumber camp, and the road from it.
+#[derive(Clone, Debug)]
+pub struct LumberCamp {
+    /// Middle of the clear-cut patch.
+    pub center: BlockColumnCoord,
+    /// Direction from the middle of the camp towards its road.
+    pub front: Surface4,
+    /// Road from the camp to the nearest country road, if one was found.
+    pub road: Option<RoadPath>,
+}
+
+/// Find a patch of forest close to town, away from the towns and fields, for a lumber camp.
+/// Land less suitable for farming, as given by `suitability`, is preferred.
+pub fn plan_lumber_camp(
+    features: &Features,
+    woodcutters: &GrayImage,
+    suitability: &GrayImage,
+    fields: &[Field],
+    wall_circles: &[&Snake],
+    town_center: BlockColumnCoord,
+    country_roads: &[RoadPath],
+) -> Option<LumberCamp> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    // Keep clear of the fields and the towns.
+    let mut occupied = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut occupied, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+    for field in fields {
+        let (min, max) = field.fenced_area();
+        for x in min.0.max(0)..=max.0.min(x_len - 1) {
+            for z in min.1.max(0)..=max.1.min(z_len - 1) {
+                occupied.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+    }
+    let occupied = imageproc::morphology::dilate(&occupied, Norm::LInf, CLEARANCE);
+
+    let margin = CLEARING_RADIUS + 2;
+    let mut best: Option<(usize, BlockColumnCoord)> = None;
+    for x in (margin..x_len - margin).step_by(SITE_SPACING) {
+        for z in (margin..z_len - margin).step_by(SITE_SPACING) {
+            let center = BlockColumnCoord(x, z);
+            if woodcutters[(x as u32, z as u32)] != image::Luma([255u8])
+                || !is_clearable(features, &occupied, center)
+            {
+                continue;
+            }
+            let image::Luma([land_suitability]) = suitability[(x as u32, z as u32)];
+            let cost = geometry::manhattan_distance(center, town_center)
+                + land_suitability as usize * SUITABILITY_COST;
+            if best.map_or(true, |(best_cost, _)| cost < best_cost) {
+                best = Some((cost, center));
+            }
+        }
+    }
+    let (_, center) = best?;
+    let front = signs::surface_from_vector(
+        (town_center.0 - center.0) as f32,
+        (town_center.1 - center.1) as f32,
+    );
+
+    // Road from the front of the clearing to the nearest country road
+    let road_start = step(center, front, CLEARING_RADIUS + 1);
+    let image::Luma([start_y]) = features.terrain[(road_start.0 as u32, road_start.1 as u32)];
+    let road_start = BlockCoord(road_start.0, start_y as i64, road_start.1);
+    let road = country_roads.iter()
+        .flat_map(|road| road.iter())
+        .min_by_key(|node| geometry::manhattan_distance(road_start.into(), node.coordinates.into()))
+        .and_then(|node| {
+            pathfinding::road_path(road_start, node.coordinates, &features.terrain, Some(&features.water))
+        });
+
+    info!("Planned a lumber camp at {:?}.", center);
+    Some(LumberCamp { center, front, road })
+}
+
+/// Check if the patch around `center` is dry, unoccupied and level enough for the camp.
+fn is_clearable(features: &Features, occupied: &GrayImage, center: BlockColumnCoord) -> bool {
+    let mut min_y = i64::MAX;
+    let mut max_y = i64::MIN;
+
+    for (x, z) in disc(center, CLEARING_RADIUS) {
+        let column = (x as u32, z as u32);
+        if occupied[column] == image::Luma([255u8]) || features.water[column] == image::Luma([255u8]) {
+            return false;
+        }
+        let image::Luma([y]) = features.terrain[column];
+        min_y = min_y.min(y as i64);
+        max_y = max_y.max(y as i64);
+    }
+
+    max_y - min_y <= CLEARING_SLOPE_MAX
+}
+
+/// Clear-cut the patch, and build the lumber camp and the road from it.
+pub fn build_lumber_camp(
+    excerpt: &mut WorldExcerpt,
+    camp: &LumberCamp,
+    features: &Features,
+    palette: &BlockPalette,
+    config: &GenerationConfig,
+    rng: &mut StdRng,
+) {
+    if let Some(road) = &camp.road {
+        let style = RoadStyle { width: ROAD_WIDTH, ..road.class.style(config) };
+        road::build_road_in_style(excerpt, road, &features.terrain, &style, rng);
+    }
+
+    let ground_at = |column: BlockColumnCoord| {
+        let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        BlockCoord(column.0, y as i64, column.1)
+    };
+    // Positions within the camp, `along` towards the front and `across` to the right.
+    let side = camp.front.rotated_90_cw();
+    let at = |along: i64, across: i64| step(step(camp.center, camp.front, along), side, across);
+
+    // Clear-cut the patch, taking note of what the trees were.
+    let mut wood_counts: Vec<(WoodMaterial, usize)> = Vec::new();
+    let mut cut_log = None;
+    for (x, z) in disc(camp.center, CLEARING_RADIUS) {
+        let ground = ground_at(BlockColumnCoord(x, z));
+        if let Some(Block::Log(log)) = excerpt.block_at(ground) {
+            match wood_counts.iter_mut().find(|(material, _)| *material == log.material) {
+                Some((_, count)) => *count += 1,
+                None => wood_counts.push((log.material, 1)),
+            }
+            cut_log.get_or_insert_with(|| Block::Log(log.clone()));
+        }
+        for y in 0..2 {
+            tree::chop(excerpt, ground + BlockCoord(0, y, 0));
+        }
+    }
+    let wood = wood_counts.into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(material, _)| material)
+        .unwrap_or(WoodMaterial::Oak);
+    let cut_log = cut_log.unwrap_or_else(|| Block::oak_log(Axis3::Y));
+
+    // Stacks of cut logs, stood on end
+    for along in 0..=2 {
+        for across in -6..=-4 {
+            let ground = ground_at(at(along, across));
+            for y in 0..2 {
+                excerpt.set_block_at(ground + BlockCoord(0, y, 0), cut_log.clone());
+            }
+        }
+    }
+
+    // Sawpit, with planks across the ends for the top sawyer
+    for along in 2..=5 {
+        for across in -1..=0 {
+            let ground = ground_at(at(along, across));
+            let is_end = along == 2 || along == 5;
+            excerpt.set_block_at(ground + BlockCoord(0, -1, 0), Block::Air);
+            excerpt.set_block_at(ground + BlockCoord(0, -2, 0), Block::Air);
+            if is_end {
+                excerpt.set_block_at(ground + BlockCoord(0, -1, 0), Block::bottom_slab(Material::Spruce));
+            }
+        }
+    }
+
+    // Saplings in rows in the back half of the clearing
+    if let Some(sapling) = sapling_material(wood) {
+        for along in (-CLEARING_RADIUS + 1..=-2).step_by(SAPLING_SPACING as usize) {
+            for across in (-CLEARING_RADIUS + 1..CLEARING_RADIUS).step_by(SAPLING_SPACING as usize) {
+                let column = at(along, across);
+                if !is_in_disc((column.0 - camp.center.0, column.1 - camp.center.1), CLEARING_RADIUS - 1) {
+                    continue;
+                }
+                let ground = ground_at(column);
+                let is_soil = match excerpt.block_at(ground + BlockCoord(0, -1, 0)) {
+                    Some(Block::GrassBlock)
+                    | Some(Block::Dirt)
+                    | Some(Block::CoarseDirt)
+                    | Some(Block::Podzol) => true,
+                    _ => false,
+                };
+                if is_soil && excerpt.block_at(ground) == Some(&Block::Air) {
+                    excerpt.set_block_at(ground, Block::Sapling { material: sapling });
+                }
+            }
+        }
+    }
+
+    // The cabin, on the right hand side, with its door towards the middle of the camp.
+    let cabin_center = at(1, 5);
+    let cabin_origin = BlockColumnCoord(cabin_center.0 - CABIN_SIZE.0 / 2, cabin_center.1 - CABIN_SIZE.1 / 2);
+    let mut floor_y = i64::MIN;
+    for x in cabin_origin.0..cabin_origin.0 + CABIN_SIZE.0 {
+        for z in cabin_origin.1..cabin_origin.1 + CABIN_SIZE.1 {
+            floor_y = floor_y.max(ground_at(BlockColumnCoord(x, z)).1);
+        }
+    }
+    let cabin = hut::build_hut(CABIN_SIZE, side.opposite(), palette, true, rng);
+    paste_chopping_trees(
+        excerpt,
+        BlockCoord(cabin_origin.0, floor_y - HUT_FOUNDATION_DEPTH, cabin_origin.1),
+        &cabin,
+    );
+}
+
+/// The kind of sapling that grows into the given wood, if there is one.
+fn sapling_material(wood: WoodMaterial) -> Option<SaplingMaterial> {
+    match wood {
+        WoodMaterial::Acacia => Some(SaplingMaterial::Acacia),
+        WoodMaterial::Birch => Some(SaplingMaterial::Birch),
+        WoodMaterial::DarkOak => Some(SaplingMaterial::DarkOak),
+        WoodMaterial::Jungle => Some(SaplingMaterial::Jungle),
+        WoodMaterial::Oak => Some(SaplingMaterial::Oak),
+        WoodMaterial::Spruce => Some(SaplingMaterial::Spruce),
+        _ => None,
+    }
+}
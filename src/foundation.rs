@@ -0,0 +1,78 @@
+//! Foundations on loose ground: Sand and gravel give way when dug out or weathered,
+//! bringing down whatever rests on them, so structures are underpinned down to stable
+//! ground, or rest on a support slab where the loose ground is too deep. Over swamp and
+//! shallow water, structures are instead raised on stilts down to the ground.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::material::FenceMaterial;
+use mcprogedit::positioning::Axis3;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+/// Deepest a foundation is extended through loose ground, looking for stable ground.
+const UNDERPINNING_DEPTH_MAX: i64 = 8;
+/// Deepest a stilt reaches down through water and air, looking for the ground.
+const STILT_DEPTH_MAX: i64 = 12;
+
+/// Whether the block gives way when the ground under it is dug out.
+pub fn is_loose(block: Option<&Block>) -> bool {
+    matches!(block, Some(Block::Sand) | Some(Block::RedSand) | Some(Block::Gravel))
+}
+
+/// The blocks extending the foundation at the given column down from below `bottom_y`,
+/// through loose ground in `excerpt`, to stable ground such as sandstone or stone. Where
+/// there is no stable ground within reach, the foundation ends in a slab of sandstone,
+/// which holds up the loose ground above it even when the ground below is dug out.
+pub fn underpinning(excerpt: &WorldExcerpt, x: i64, bottom_y: i64, z: i64, foundation: &Block) -> Vec<(BlockCoord, Block)> {
+    let loose_depth = (1..=UNDERPINNING_DEPTH_MAX)
+        .take_while(|depth| is_loose(excerpt.block_at(BlockCoord(x, bottom_y - depth, z))))
+        .last()
+        .unwrap_or(0);
+    (1..=loose_depth)
+        .map(|depth| {
+            let block = if depth == UNDERPINNING_DEPTH_MAX { Block::Sandstone } else { foundation.clone() };
+            (BlockCoord(x, bottom_y - depth, z), block)
+        })
+        .collect()
+}
+
+/// Loose ground right under a floor at `y`, to be replaced by a support slab of sandstone.
+pub fn support_slab(excerpt: &WorldExcerpt, x: i64, y: i64, z: i64) -> Option<(BlockCoord, Block)> {
+    let coordinates = BlockCoord(x, y - 1, z);
+    if is_loose(excerpt.block_at(coordinates)) {
+        Some((coordinates, Block::Sandstone))
+    } else {
+        None
+    }
+}
+
+/// Whether a stilt reaches down through the block: Air, water and plants.
+pub fn is_open(block: Option<&Block>) -> bool {
+    match block {
+        Some(Block::Air) | Some(Block::WaterSource) | Some(Block::Water { .. }) => true,
+        Some(block) => block.is_foilage(),
+        None => false,
+    }
+}
+
+/// The blocks of a stilt holding up a floor at `floor_y` at the given column, down through
+/// air, water and plants in `excerpt` to the ground. Corner stilts are logs, and the stilts
+/// between them are fences, waterlogged where they stand in water.
+pub fn stilt(excerpt: &WorldExcerpt, x: i64, floor_y: i64, z: i64, is_corner: bool) -> Vec<(BlockCoord, Block)> {
+    (1..=STILT_DEPTH_MAX)
+        .map(|depth| BlockCoord(x, floor_y - depth, z))
+        .take_while(|coordinates| is_open(excerpt.block_at(*coordinates)))
+        .map(|coordinates| {
+            let block = if is_corner {
+                Block::oak_log(Axis3::Y)
+            } else {
+                let waterlogged = matches!(
+                    excerpt.block_at(coordinates),
+                    Some(Block::WaterSource) | Some(Block::Water { .. }),
+                );
+                Block::Fence { material: FenceMaterial::Oak, waterlogged }
+            };
+            (coordinates, block)
+        })
+        .collect()
+}
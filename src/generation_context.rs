@@ -0,0 +1,556 @@
+//! Shared state for the settlement generation pipeline.
+//!
+//! The `GenerationContext` accumulates the artefacts produced by each phase of
+//! generation (features, areas, town layout, roads, plots, palette, ...), so
+//! that later phases can pick up what earlier phases produced, without having
+//! every intermediate result threaded through by hand.
+
+mod analysis;
+mod area_usage;
+mod buildings;
+mod finishing;
+mod infrastructure;
+mod land_usage;
+mod materials;
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::WoodMaterial;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::amenity::AmenityPlan;
+use crate::areas::Areas;
+use crate::bathhouse::Bathhouse;
+use crate::block_palette::{BiomeStyle, BlockPalette, RidgeOrientation};
+use crate::build_area;
+use crate::cliff_dwelling::CliffDwelling;
+use crate::config::{Dimension, GenerationConfig};
+use crate::embellishment::Embellishment;
+use crate::farm::Field;
+use crate::features::Features;
+use crate::forestry::LumberCamp;
+use crate::harbor::Harbor;
+use crate::lighthouse::Lighthouse;
+use crate::geometry::{self, LandUsageGraph, RawEdge2d};
+use crate::graveyard::Grave;
+use crate::inspection::Inspection;
+use crate::mine::Mine;
+use crate::pasture::Pasture;
+use crate::pathfinding::RoadPath;
+use crate::pets::Pet;
+use crate::plot::Plot;
+use crate::pond::{Pond, WaterTreatment};
+use crate::signs::Address;
+use crate::structure_builder::{self, ExteriorDoor, HouseOptions, HousePurpose};
+use crate::tannery::NuisanceIndustry;
+use crate::toll_station::TollStation;
+use crate::tree;
+use crate::types::Snake;
+use crate::viewpoint::Viewpoint;
+use crate::village::Village;
+use crate::wall::DefenceKind;
+use crate::waterways::{Aqueduct, Canal};
+use crate::windmill::Windmill;
+use crate::zoning::{DistrictUse, Infeasibility};
+
+const FEATURES_MISSING: &str = "Features must be extracted before this phase.";
+const AREAS_MISSING: &str = "Areas must be found before this phase.";
+
+/// Farthest from the town center a plot outside the market districts may be, to count as near the market.
+const MARKET_DISTANCE_MAX: usize = 48;
+/// Room left between the eaves of the houses and the ceiling above them, in the Nether.
+const CEILING_CLEARANCE: usize = 3;
+
+/// A building that has been placed on a plot.
+#[derive(Clone)]
+pub struct Building {
+    /// Index of the plot the building stands on.
+    pub plot_index: usize,
+    /// The (x, z) columns covered by the building.
+    pub footprint: Vec<BlockColumnCoord>,
+    /// The palette the building was built with.
+    pub palette: BlockPalette,
+    /// The exterior doors of the building.
+    pub doors: Vec<ExteriorDoor>,
+    /// The address of the building, if it faces a named street.
+    pub address: Option<Address>,
+    /// What the building is used for, if it is a house.
+    pub purpose: Option<HousePurpose>,
+    /// Family name of the household living in the building, if it is a house.
+    pub family_name: Option<String>,
+}
+
+/// A town other than the main town, with its own wall and streets.
+#[derive(Clone)]
+pub struct OutlyingTown {
+    pub circumference: Snake,
+    pub center: BlockColumnCoord,
+    pub wall_circle: Snake,
+    pub city_roads: Vec<RoadPath>,
+    pub streets: Vec<RoadPath>,
+}
+
+pub struct GenerationContext {
+    // Parameters
+    pub config: GenerationConfig,
+    /// The configuration with the widths and coverage radii scaled to the size of the town,
+    /// once the town is located.
+    pub scaled_config: GenerationConfig,
+    pub inspection: Inspection,
+
+    // World
+    pub excerpt: WorldExcerpt,
+    /// The world excerpt as it was before generation, kept for checking the placed blocks
+    /// against the block whitelist, if there is one.
+    pub original_excerpt: Option<WorldExcerpt>,
+    pub player_location: BlockColumnCoord,
+    /// Polygon to keep the settlement inside, relative to the excerpt, if any.
+    /// Handed over to the features once they are extracted, and kept there.
+    pub boundary: Option<Snake>,
+
+    // Analysis
+    pub features: Option<Features>,
+    pub areas: Option<Areas>,
+
+    // Town layout
+    pub town_name: String,
+    pub town_circumference: Snake,
+    pub town_center: BlockColumnCoord,
+    pub wall_circle: Snake,
+    pub outlying_towns: Vec<OutlyingTown>,
+    /// Existing villages in the selection, adopted into the settlement.
+    pub villages: Vec<Village>,
+
+    // Infrastructure
+    pub city_roads: Vec<RoadPath>,
+    pub country_roads: Vec<RoadPath>,
+    pub streets: Vec<RoadPath>,
+    pub city_road_names: Vec<String>,
+    pub street_names: Vec<String>,
+    pub toll_stations: Vec<TollStation>,
+
+    // Land usage
+    pub land_usage_graph: LandUsageGraph,
+    pub districts: Vec<Snake>,
+    pub wealthiest_district: Option<usize>,
+    pub district_uses: Vec<DistrictUse>,
+    /// Constraints on the mix of district uses that the zoning could not satisfy.
+    pub zoning_infeasibilities: Vec<Infeasibility>,
+    /// Names of the districts, for the signposts at the street corners.
+    pub district_names: Vec<String>,
+    /// Most stories for the houses in each district, taller towards the town center.
+    pub district_story_caps: Vec<usize>,
+    /// Tallest the houses on each plot may be, keeping the view corridors open. 0 means no limit.
+    pub plot_height_caps: Vec<usize>,
+    pub plots: Vec<Plot>,
+    pub plot_districts: Vec<usize>,
+    pub buildings: Vec<Building>,
+    /// Dwellings carved into the cliffs, in the cliff style.
+    pub cliff_dwellings: Vec<CliffDwelling>,
+    /// Indices of the buildings fallen into ruin, in the ruins style.
+    pub ruins: Vec<usize>,
+    /// Index of the plot with the town landmark, if one was built.
+    pub landmark_plot: Option<usize>,
+    /// Index of the plot with the second church of a large town, if one was built.
+    pub second_church_plot: Option<usize>,
+    /// Bathhouse over the lava below the town, if one was built.
+    pub bathhouse: Option<Bathhouse>,
+    /// Pets in the homes and taverns, to be summoned into the world.
+    pub pets: Vec<Pet>,
+    /// Ponds and canals inside the town walls, and what is done with them.
+    pub ponds: Vec<Pond>,
+    /// Canal from the water outside town into the main town, if one was planned.
+    pub canal: Option<Canal>,
+    /// Aqueduct from the high ground to a cistern in the main town, if one was planned.
+    pub aqueduct: Option<Aqueduct>,
+    /// Graves in the graveyards beside the churches.
+    pub graves: Vec<Grave>,
+    /// Wells, bakeries and churches needed by the population.
+    pub amenities: AmenityPlan,
+    /// Edges each plot shares with its neighbours in a row of houses, for the party walls.
+    pub row_house_party_walls: Vec<Vec<RawEdge2d>>,
+    /// For each plot of a pair of semi-detached houses, the other plot and the party wall.
+    pub semi_detached_pairs: Vec<Option<(usize, RawEdge2d)>>,
+    /// Tanneries and dyeworks, outside the town wall.
+    pub nuisance_industries: Vec<NuisanceIndustry>,
+
+    // Primary sector
+    pub fields: Vec<Field>,
+    /// Pastures for livestock, with their herds to be summoned into the world.
+    pub pastures: Vec<Pasture>,
+    pub harbor: Option<Harbor>,
+    pub lighthouse: Option<Lighthouse>,
+    pub lumber_camp: Option<LumberCamp>,
+    pub mine: Option<Mine>,
+    pub windmill: Option<Windmill>,
+    pub viewpoints: Vec<Viewpoint>,
+
+    // Materials
+    pub block_palette: BlockPalette,
+    pub wood_available: Vec<WoodMaterial>,
+    pub defence: DefenceKind,
+    /// Lava found near the surface, safely below the town.
+    pub lava_below_town: Option<BlockCoord>,
+
+    // Randomness, seeded for reproducible generation
+    pub rng: StdRng,
+}
+
+impl GenerationContext {
+    /// Create a new context for generating a settlement in the given excerpt.
+    ///
+    /// All random choices are drawn from an RNG seeded with `seed`, so that
+    /// generating from the same excerpt with the same seed gives the same result.
+    pub fn new(excerpt: WorldExcerpt, seed: u64, config: GenerationConfig) -> Self {
+        let (x_len, _, z_len) = excerpt.dim();
+        let player_location: BlockColumnCoord = (x_len as i64 / 2, z_len as i64 / 2).into();
+        let original_excerpt = config.block_whitelist.as_ref().map(|_| {
+            let (_, y_len, _) = excerpt.dim();
+            WorldExcerpt::from_world_excerpt((0, 0, 0), (x_len - 1, y_len - 1, z_len - 1), &excerpt)
+        });
+
+        Self {
+            scaled_config: config.clone(),
+            config,
+            inspection: Inspection::disabled(),
+            excerpt,
+            original_excerpt,
+            player_location: player_location.clone(),
+            boundary: None,
+            features: None,
+            areas: None,
+            town_name: String::new(),
+            town_circumference: Vec::new(),
+            town_center: player_location,
+            wall_circle: Vec::new(),
+            outlying_towns: Vec::new(),
+            villages: Vec::new(),
+            city_roads: Vec::new(),
+            country_roads: Vec::new(),
+            streets: Vec::new(),
+            city_road_names: Vec::new(),
+            street_names: Vec::new(),
+            toll_stations: Vec::new(),
+            land_usage_graph: LandUsageGraph::new(),
+            districts: Vec::new(),
+            wealthiest_district: None,
+            district_uses: Vec::new(),
+            zoning_infeasibilities: Vec::new(),
+            district_names: Vec::new(),
+            district_story_caps: Vec::new(),
+            plot_height_caps: Vec::new(),
+            plots: Vec::new(),
+            plot_districts: Vec::new(),
+            buildings: Vec::new(),
+            cliff_dwellings: Vec::new(),
+            ruins: Vec::new(),
+            landmark_plot: None,
+            second_church_plot: None,
+            bathhouse: None,
+            pets: Vec::new(),
+            ponds: Vec::new(),
+            canal: None,
+            aqueduct: None,
+            graves: Vec::new(),
+            amenities: Default::default(),
+            row_house_party_walls: Vec::new(),
+            semi_detached_pairs: Vec::new(),
+            nuisance_industries: Vec::new(),
+            fields: Vec::new(),
+            pastures: Vec::new(),
+            harbor: None,
+            lighthouse: None,
+            lumber_camp: None,
+            mine: None,
+            windmill: None,
+            viewpoints: Vec::new(),
+            block_palette: Default::default(),
+            wood_available: Vec::new(),
+            defence: DefenceKind::StoneWall,
+            lava_below_town: None,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Give back the (possibly modified) world excerpt.
+    pub fn into_excerpt(self) -> WorldExcerpt {
+        self.excerpt
+    }
+
+    /// Dimensions of the world excerpt, as signed (x, y, z) lengths.
+    fn dimensions(&self) -> (i64, i64, i64) {
+        let (x_len, y_len, z_len) = self.excerpt.dim();
+        (x_len as i64, y_len as i64, z_len as i64)
+    }
+
+    /// The wall circles of the main town and of the outlying towns.
+    fn wall_circles(&self) -> Vec<&Snake> {
+        town_wall_circles(&self.wall_circle, &self.outlying_towns)
+    }
+
+    /// Whether the settlement is in the Nether, without water and under a ceiling.
+    fn is_nether(&self) -> bool {
+        self.config.dimension == Dimension::Nether
+    }
+
+    /// Whether the main town is mostly swamp or shallow water, to be built as a stilt village.
+    fn is_stilt_village(&self) -> bool {
+        self.features.as_ref().map_or(false, |features| {
+            features.swamp_share(&self.wall_circle) >= self.config.stilt_village_swamp_share
+        })
+    }
+
+    /// The use of the district the plot with the given index is in.
+    fn plot_use(&self, index: usize) -> DistrictUse {
+        self.plot_districts.get(index)
+            .and_then(|district| self.district_uses.get(*district))
+            .copied()
+            .unwrap_or(DistrictUse::Residential)
+    }
+
+    /// Whether the plot with the given index is laid out as a park: Plots in the green
+    /// districts, and plots with water kept in town, for the park to embrace.
+    fn is_park_plot(&self, index: usize) -> bool {
+        if self.plot_use(index) == DistrictUse::Green {
+            return true;
+        }
+        let plot = &self.plots[index];
+        let (min, max) = match plot.bounding_box() {
+            Some(bounding_box) => bounding_box,
+            None => return false,
+        };
+        let polygon = plot.polygon();
+        self.ponds.iter()
+            .filter(|pond| pond.treatment != WaterTreatment::Fill)
+            .flat_map(|pond| pond.columns.iter())
+            .filter(|column| column.0 >= min.0 && column.0 <= max.0 && column.1 >= min.2 && column.1 <= max.2)
+            .any(|column| geometry::point_position_relative_to_polygon(*column, &polygon) == geometry::InOutSide::Inside)
+    }
+
+    /// Whether the bathhouse was built on the plot with the given index.
+    fn is_bathhouse_plot(&self, index: usize) -> bool {
+        self.bathhouse.as_ref().map_or(false, |bathhouse| bathhouse.plot == index)
+    }
+
+    /// Whether the plot with the given index is in a market district, or close to the town center.
+    fn is_near_market(&self, index: usize) -> bool {
+        matches!(self.plot_use(index), DistrictUse::Market | DistrictUse::Plaza)
+            || geometry::manhattan_distance(geometry::polygon_center(&self.plots[index].polygon()), self.town_center)
+                <= MARKET_DISTANCE_MAX
+    }
+
+    /// The world excerpt around the plot, with a block of context on each side,
+    /// its offset into the world, and the build area of the plot within it.
+    fn plot_site(&self, plot: &Plot) -> Option<(BlockCoord, WorldExcerpt, build_area::BuildArea)> {
+        let (_, y_len, _) = self.dimensions();
+        let bounding_box = plot.bounding_box()?;
+
+        // Increase the size by 1, in order to provide at least one block of context.
+        let mut bounding_box = (
+            bounding_box.0 - BlockCoord(1, 0, 1),
+            bounding_box.1 + BlockCoord(1, 0, 1),
+        );
+        bounding_box.0 .1 = 0;
+        bounding_box.1 .1 = y_len - 1;
+
+        // Get the relative plot description and relative world excerpt
+        let offset_plot = plot.offset(bounding_box.0);
+        let plot_excerpt = WorldExcerpt::from_world_excerpt(
+            (bounding_box.0 .0 as usize, bounding_box.0 .1 as usize, bounding_box.0 .2 as usize),
+            (bounding_box.1 .0 as usize, bounding_box.1 .1 as usize, bounding_box.1 .2 as usize),
+            &self.excerpt,
+        );
+
+        // Get the build area description structure for the (now offset) plot
+        let plot_build_area =
+            build_area::BuildArea::from_world_excerpt_and_plot(&plot_excerpt, &offset_plot);
+
+        Some((bounding_box.0, plot_excerpt, plot_build_area))
+    }
+
+    /// Decide on the options for the house on the plot with the given index: Its height,
+    /// roof and embellishment, and what it is used for. Adjusts `palette` for houses raised
+    /// above the flood level, and for gable ends facing the street.
+    fn house_options(
+        &mut self,
+        index: usize,
+        palette: &mut BlockPalette,
+        is_row_house: bool,
+        is_semi_detached: bool,
+    ) -> HouseOptions {
+        // Embellish some of the houses in the wealthiest district
+        let mut house_options = HouseOptions {
+            interior_area_min: self.config.house_interior_area_min,
+            interior_area_max: self.config.house_interior_area_max,
+            story_height: self.config.story_height,
+            height_max: self.config.building_height_max,
+            sunward: self.config.hemisphere.sunward(),
+            roof_details: self.config.roof_details,
+            stilts: palette.biome == BiomeStyle::Swamp,
+            ..Default::default()
+        };
+        if self.wealthiest_district.is_some()
+            && self.plot_districts.get(index) == self.wealthiest_district.as_ref()
+        {
+            house_options.embellishment = match self.rng.gen_range(0..3) {
+                0 => Some(Embellishment::CornerTurret),
+                1 => Some(Embellishment::BayWindow),
+                _ => None,
+            };
+        }
+
+        // Up to the story cap of the district, sometimes a story lower, for a varied skyline
+        let stories_cap = self.plot_districts.get(index)
+            .and_then(|district| self.district_story_caps.get(*district))
+            .copied()
+            .unwrap_or(1);
+        house_options.stories_max = self.rng.gen_range(stories_cap.saturating_sub(1).max(1)..=stories_cap);
+
+        // Low houses with flat roofs in the view corridors, keeping the sightlines open
+        if let Some(&height_cap) = self.plot_height_caps.get(index).filter(|cap| **cap > 0) {
+            house_options.cap_height(height_cap);
+        }
+
+        // Low houses with flat roofs under the ceiling, in the Nether
+        if let Some(headroom) = self.features.as_ref()
+            .and_then(|features| features.headroom_min(&self.plots[index].polygon()))
+        {
+            house_options.cap_height(headroom.saturating_sub(CEILING_CLEARANCE).max(1));
+        }
+
+        // Floors above the flood level along the water, on stilts where much of the
+        // plot floods, and on raised stone foundations elsewhere
+        if let Some(features) = &self.features {
+            let polygon = self.plots[index].polygon();
+            if let Some(flood_level) = features.flood_level_max(&polygon) {
+                house_options.floor_y_min = flood_level;
+                if features.flood_risk_share(&polygon) >= self.config.flood_stilt_share {
+                    house_options.stilts = true;
+                } else if matches!(palette.foundation, Block::Planks { .. } | Block::Log(_)) {
+                    palette.foundation = self.block_palette.foundation.clone();
+                }
+            }
+        }
+
+        // Even rows of roofs along the street, for row houses
+        if is_row_house {
+            house_options.stories_max = stories_cap;
+            house_options.prefer_gable_roof(palette.biome);
+        }
+
+        // Plain matching fronts, for semi-detached houses
+        if is_semi_detached {
+            house_options.embellishment = None;
+        }
+
+        // The school, bakeries and apothecaries on the plots set aside for them
+        if let Some(purpose) = self.amenities.plot_purpose(index) {
+            house_options.purpose = purpose;
+
+        // Taverns on some of the large corner plots near the market
+        } else if !is_semi_detached
+            && structure_builder::suits_tavern(&self.plots[index])
+            && self.is_near_market(index)
+            && self.rng.gen_bool(self.config.tavern_share)
+        {
+            house_options.purpose = HousePurpose::Tavern;
+
+        // Workshops in the workshop quarter, and on some of the plots along the city roads
+        } else if !is_semi_detached
+            && (self.plot_use(index) == DistrictUse::Workshop
+                || (structure_builder::suits_workshop(
+                        &self.plots[index],
+                        self.scaled_config.city_road_reserved_width as usize,
+                    )
+                    && self.rng.gen_bool(self.config.workshop_share)))
+        {
+            house_options.purpose = HousePurpose::Workshop;
+        }
+
+        // Gable ends facing the street, for the school and the taverns
+        if matches!(house_options.purpose, HousePurpose::School | HousePurpose::Tavern) {
+            palette.roof_ridge = RidgeOrientation::GableToStreet;
+            house_options.prefer_gable_roof(palette.biome);
+        }
+
+        house_options
+    }
+}
+
+/// Find the district containing the town center,
+/// or failing that, the district with its centroid closest to the town center.
+fn wealthiest_district(districts: &[Snake], town_center: &BlockColumnCoord) -> Option<usize> {
+    if let Some(index) = districts.iter().position(|district| {
+        geometry::InOutSide::Inside
+            == geometry::point_position_relative_to_polygon(town_center.clone(), district)
+    }) {
+        return Some(index);
+    }
+
+    districts.iter()
+        .enumerate()
+        .filter(|(_, district)| !district.is_empty())
+        .min_by_key(|(_, district)| {
+            let count = district.len() as i64;
+            let sum = district.iter()
+                .fold(BlockColumnCoord(0, 0), |sum, point| sum + *point);
+            let centroid = BlockColumnCoord(sum.0 / count, sum.1 / count);
+            geometry::manhattan_distance(centroid, town_center.clone())
+        })
+        .map(|(index, _)| index)
+}
+
+/// The (x, z) columns of `structure` holding building blocks, offset by `offset`.
+fn footprint(structure: &WorldExcerpt, offset: BlockCoord) -> Vec<BlockColumnCoord> {
+    let (x_len, y_len, z_len) = structure.dim();
+    let mut footprint = Vec::new();
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            let is_built = (0..y_len as i64).any(|y| {
+                match structure.block_at(BlockCoord(x, y, z)) {
+                    Some(Block::None)
+                    | Some(Block::Air)
+                    | Some(Block::Flower(_))
+                    | Some(Block::FlowerPot(_))
+                    | None => false,
+                    Some(_) => true,
+                }
+            });
+            if is_built {
+                footprint.push(BlockColumnCoord(x + offset.0, z + offset.2));
+            }
+        }
+    }
+    footprint
+}
+
+/// The wall circles of the main town and of the outlying towns, borrowing only the
+/// fields involved, for where other fields of the context are borrowed mutably.
+fn town_wall_circles<'a>(wall_circle: &'a Snake, outlying_towns: &'a [OutlyingTown]) -> Vec<&'a Snake> {
+    std::iter::once(wall_circle)
+        .chain(outlying_towns.iter().map(|town| &town.wall_circle))
+        .collect()
+}
+
+/// Paste `structure` into `excerpt` at `offset`, first chopping any tree
+/// that would be affected by the blocks being pasted.
+pub fn paste_chopping_trees(excerpt: &mut WorldExcerpt, offset: BlockCoord, structure: &WorldExcerpt) {
+    let (x_len, y_len, z_len) = structure.dim();
+    for x in 0..x_len as i64 {
+        for y in 0..y_len as i64 {
+            for z in 0..z_len as i64 {
+                if let Some(Block::None) = structure.block_at(BlockCoord(x, y, z)) {
+                    // Nothing will be pasted, so nothing to do.
+                } else {
+                    // Some block will be pasted, chop any affected tree.
+                    tree::chop(excerpt, BlockCoord(x, y, z) + offset);
+                }
+            }
+        }
+    }
+
+    // Paste it back into the "main" excerpt
+    excerpt.paste(offset, structure)
+}
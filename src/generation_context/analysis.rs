@@ -0,0 +1,38 @@
+//! Stages extracting the features of the terrain and finding the areas suited for
+//! the different uses of the land.
+
+use crate::areas::Areas;
+use crate::features::Features;
+use crate::village;
+
+use super::{GenerationContext, FEATURES_MISSING};
+
+impl GenerationContext {
+    /// Extract terrain features from the world excerpt, clipped to the build boundary.
+    pub fn extract_features(&mut self) {
+        let mut features = Features::new_from_world_excerpt(
+            &self.excerpt,
+            self.config.dimension,
+            &self.inspection,
+        );
+        if let Some(boundary) = self.boundary.take() {
+            features.clip_to_boundary(boundary, &self.inspection);
+        }
+        self.features = Some(features);
+    }
+
+    /// Find areas suitable for various purposes, based on the features.
+    pub fn find_areas(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        self.areas = Some(Areas::new_from_features(features, &self.config, &self.inspection));
+    }
+
+    /// Find the existing villages in the selection, for adopting them into the settlement.
+    pub fn find_villages(&mut self) {
+        if !self.config.adopt_villages {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        self.villages = village::find_villages(&self.excerpt, features);
+    }
+}
@@ -0,0 +1,270 @@
+//! Stages locating the towns and planning the water and the roads around them.
+
+use log::info;
+
+use crate::geometry;
+use crate::partitioning::divide_town_into_blocks;
+use crate::pathfinding::{RoadClass, RoadPath};
+use crate::road::{self, roads_split};
+use crate::types::Snake;
+use crate::village;
+use crate::walled_town::walled_town_contours;
+
+use super::{GenerationContext, OutlyingTown, AREAS_MISSING, FEATURES_MISSING};
+
+impl GenerationContext {
+    /// Find the town locations, and the circumference of their walls.
+    /// The largest town becomes the main town, any others are outlying towns.
+    pub fn locate_town(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let areas = self.areas.as_ref().expect(AREAS_MISSING);
+
+        let mut towns = walled_town_contours(features, areas, self.config.max_towns, &self.inspection).into_iter();
+        let (town_circumference, town_center) = towns.next().expect("No suitable town location found.");
+
+        // Wall in the villages close to the town
+        let town_circumference = village::wall_in_villages(
+            town_circumference,
+            &self.villages,
+            self.config.village_wall_distance,
+            features.dimensions(),
+        );
+
+        // Get full wall circle, by copying the first node of the wall to the end.
+        let wall_circle = closed_circle(&town_circumference);
+
+        // Get town size
+        let town_area = geometry::area(&wall_circle);
+        info!("The found city has a total area of {} m².", town_area);
+
+        let outlying_towns: Vec<OutlyingTown> = towns
+            .map(|(circumference, center)| {
+                let wall_circle = closed_circle(&circumference);
+                info!(
+                    "Found an outlying town at {:?}, with a total area of {} m².",
+                    center,
+                    geometry::area(&wall_circle),
+                );
+                OutlyingTown {
+                    circumference,
+                    center,
+                    wall_circle,
+                    city_roads: Vec::new(),
+                    streets: Vec::new(),
+                }
+            })
+            .collect();
+
+        // Scale the street, road and wall widths, and the spacing of streets, to the town size.
+        let total_area = outlying_towns.iter()
+            .map(|town| geometry::area(&town.wall_circle).abs())
+            .sum::<i64>() + town_area.abs();
+        // The configuration itself is kept as given, so that running this again scales the
+        // given values afresh, and the report shows what was asked for.
+        let scaled_config = self.config.scaled_to_town_area(total_area as usize);
+        info!(
+            "Scaled for a total town area of {} m²: street width {}, city road width {}, \
+            wall width {}, street coverage radius {}.",
+            total_area,
+            scaled_config.street_width,
+            scaled_config.city_road_width,
+            scaled_config.wall_width,
+            scaled_config.street_coverage_radius,
+        );
+
+        self.town_circumference = town_circumference;
+        self.town_center = town_center;
+        self.wall_circle = wall_circle;
+        self.outlying_towns = outlying_towns;
+        self.scaled_config = scaled_config;
+    }
+
+    /// Decide what to do with the water inside the town walls: Fill in the small ponds,
+    /// keep the larger ones as ornamental ponds, and line the rivers through town as canals.
+    /// The ponds to be filled in are marked as dry ground, for planning the town across them.
+    pub fn plan_ponds(&mut self) {
+        // The water of a stilt village is kept as it is, to build over
+        if self.is_stilt_village() {
+            info!("Leaving the water in town as it is, for a stilt village.");
+            return;
+        }
+
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let mut ponds = Vec::new();
+        for wall_circle in self.wall_circles() {
+            ponds.extend(pond::plan_ponds(features, wall_circle, self.config.pond_fill_area_max));
+        }
+        pond::mark_filled_ponds(self.features.as_mut().expect(FEATURES_MISSING), &ponds);
+        self.ponds = ponds;
+    }
+
+    /// Plan a canal into the main town from the water outside it, and an aqueduct from the
+    /// high ground, if there is water and high ground within reach. The canal is cut into
+    /// the terrain, for the streets to bridge it.
+    pub fn plan_waterways(&mut self) {
+        // There is no water to lead anywhere in the Nether
+        if self.is_nether() {
+            return;
+        }
+
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let canal = if self.config.canal {
+            waterways::plan_canal(features, self.town_center, &self.wall_circle)
+        } else {
+            None
+        };
+        let aqueduct = if self.config.aqueduct {
+            waterways::plan_aqueduct(features, self.town_center, &self.wall_circle)
+        } else {
+            None
+        };
+        if let Some(canal) = &canal {
+            waterways::mark_canal(self.features.as_mut().expect(FEATURES_MISSING), canal);
+        }
+        self.canal = canal;
+        self.aqueduct = aqueduct;
+    }
+
+    /// Find roads leading to town, and streets inside town.
+    pub fn plan_roads(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        // Paths from where roads beyond the map would come in
+        let mut start_coordinates = road_entry::road_entry_points(features);
+
+        if geometry::InOutSide::Outside
+            == geometry::point_position_relative_to_polygon(self.player_location.clone(), &self.wall_circle)
+        {
+            // Path from the player start location
+            start_coordinates.push((self.player_location.0, self.player_location.1));
+        }
+
+        let start_coordinates: Vec<_> = start_coordinates
+        .iter()
+        .map(|(x, z)| {
+            let image::Luma([y]) = features.terrain[(*x as u32, *z as u32)];
+            BlockCoord(*x, y as i64, *z)
+        })
+        .collect();
+
+        let image::Luma([goal_y]) = features.terrain[
+            (self.town_center.0 as u32, self.town_center.1 as u32)
+        ];
+        let goal = BlockCoord(self.town_center.0 as i64, goal_y as i64, self.town_center.1 as i64);
+
+        let mut road_path_image = features.coloured_map.clone();
+
+        let mut raw_roads = Vec::new();
+
+        let mut water_and_lava = features.water_and_lava();
+        let mut obstacles = imageproc::morphology::dilate(
+            &water_and_lava,
+            imageproc::distance_transform::Norm::LInf,
+            2,
+        );
+
+        // Keep the roads and streets out of the houses of the villages
+        village::mark_houses(&mut water_and_lava, &self.villages);
+        village::mark_houses(&mut obstacles, &self.villages);
+
+        // Roads between neighbouring towns, along a relative neighbourhood graph of the towns.
+        let town_centers: Vec<BlockColumnCoord> = std::iter::once(self.town_center)
+            .chain(self.outlying_towns.iter().map(|town| town.center))
+            .collect();
+        let town_connections = geometry::relative_neighbourhood_graph(&town_centers)
+            .into_iter()
+            .map(|(from, to)| (town_centers[from], town_centers[to]));
+
+        // Roads from the paths of the villages, joining them to the road network
+        let village_starts: Vec<BlockCoord> = self.villages.iter()
+            .map(|village| {
+                let end = village.road_end(self.town_center);
+                let image::Luma([y]) = features.terrain[(end.0 as u32, end.1 as u32)];
+                BlockCoord(end.0, y as i64, end.1)
+            })
+            .collect();
+
+        let road_ends = start_coordinates.into_iter()
+            .chain(village_starts)
+            .map(|start| (start, goal))
+            .chain(town_connections.map(|(from, to)| {
+                let image::Luma([from_y]) = features.terrain[(from.0 as u32, from.1 as u32)];
+                let image::Luma([to_y]) = features.terrain[(to.0 as u32, to.1 as u32)];
+                (BlockCoord(from.0, from_y as i64, from.1), BlockCoord(to.0, to_y as i64, to.1))
+            }));
+
+        for (start, goal) in road_ends {
+            if let Some(path) = pathfinding::road_path_over_terrain(
+                start,
+                goal,
+                &features.terrain,
+                Some(&obstacles),
+                features,
+            ) {
+                // Draw road on map
+                pathfinding::draw_road_path(&mut road_path_image, &path);
+
+                // Store road
+                raw_roads.push(path.with_class(RoadClass::Highway));
+            }
+        }
+
+        self.inspection.save_image("road_path_001.png", &road_path_image);
+
+        // Split out the raw roads into city roads and country roads
+        let (city_roads, mut country_roads) = roads_split(&raw_roads, &self.wall_circle);
+        let city_roads: Vec<RoadPath> = city_roads.into_iter()
+            .map(|road| road.with_class(RoadClass::CityRoad))
+            .collect();
+
+        // Fill out with minor roads inside town
+        let streets: Vec<RoadPath> = divide_town_into_blocks(
+            &self.town_circumference,
+            &self.town_center,
+            &city_roads,
+            &features.terrain,
+            &water_and_lava,
+            &self.scaled_config,
+            &self.inspection,
+        )
+        .into_iter()
+        .map(|street| {
+            let class = road::street_class(&street, &self.config);
+            street.with_class(class)
+        })
+        .collect();
+
+        // Likewise for the outlying towns
+        for town in &mut self.outlying_towns {
+            let (town_roads, remaining_country_roads) = roads_split(&country_roads, &town.wall_circle);
+            let town_roads: Vec<RoadPath> = town_roads.into_iter()
+                .map(|road| road.with_class(RoadClass::CityRoad))
+                .collect();
+            town.streets = divide_town_into_blocks(
+                &town.circumference,
+                &town.center,
+                &town_roads,
+                &features.terrain,
+                &water_and_lava,
+                &self.scaled_config,
+                &self.inspection,
+            )
+            .into_iter()
+            .map(|street| street.with_class(RoadClass::Street))
+            .collect();
+            town.city_roads = town_roads;
+            country_roads = remaining_country_roads;
+        }
+
+        self.city_roads = city_roads;
+        self.country_roads = country_roads;
+        self.streets = streets;
+    }
+}
+
+/// Close a town circumference, by copying its first node to the end.
+fn closed_circle(circumference: &Snake) -> Snake {
+    let mut circle = circumference.clone();
+    circle.push(circumference[0]);
+    circle
+}
@@ -0,0 +1,414 @@
+//! Stages building the landmarks, houses and other buildings in town.
+
+use std::collections::HashMap;
+
+use log::info;
+
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use rand::rngs::StdRng;
+
+use crate::amenity;
+use crate::bathhouse;
+use crate::block_palette::BiomeStyle;
+use crate::build_area;
+use crate::church;
+use crate::cliff_dwelling;
+use crate::config::SettlementStyle;
+use crate::geometry;
+use crate::line::line;
+use crate::naming;
+use crate::pathfinding::RoadPath;
+use crate::pergola;
+use crate::plaza;
+use crate::plot::Plot;
+use crate::road;
+use crate::structure_builder::{self, ExteriorDoor};
+use crate::terraforming;
+use crate::training_yard;
+use crate::types::Snake;
+use crate::village;
+use crate::wall::DefenceKind;
+use crate::well;
+use crate::zoning::{self, DistrictUse};
+
+use super::{footprint, paste_chopping_trees, Building, GenerationContext, FEATURES_MISSING};
+
+/// Number of the most central plots to try fitting the town landmark on.
+const LANDMARK_CANDIDATES_MAX: usize = 5;
+
+impl GenerationContext {
+    /// Lay out a market square in the district zoned as plaza, if any.
+    pub fn build_plaza(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let index = match self.district_uses.iter().position(|district_use| *district_use == DistrictUse::Plaza) {
+            Some(index) => index,
+            None => return,
+        };
+        plaza::build_plaza(
+            &mut self.excerpt,
+            &self.districts[index],
+            self.town_center,
+            features,
+            &mut self.rng,
+        );
+    }
+
+    /// Build a church as the town landmark, on the most central plot of the main town
+    /// that it fits on, and for large towns a second church in a residential district,
+    /// well away from the first.
+    pub fn build_landmark(&mut self) {
+        // Plots large enough for a church, closest to the town center first
+        let mut candidates: Vec<(usize, usize)> = self.plots.iter()
+            .enumerate()
+            .filter(|(index, plot)| {
+                !self.config.is_skipped_plot(*index)
+                    && !matches!(self.plot_use(*index), DistrictUse::Green | DistrictUse::Plaza)
+                    && geometry::area(&plot.polygon()).abs() >= church::CHURCH_PLOT_AREA_MIN
+            })
+            .map(|(index, plot)| {
+                (geometry::manhattan_distance(geometry::polygon_center(&plot.polygon()), self.town_center), index)
+            })
+            .collect();
+        candidates.sort_unstable();
+
+        for (_, index) in candidates.iter().take(LANDMARK_CANDIDATES_MAX) {
+            if self.build_church(*index) {
+                self.landmark_plot = Some(*index);
+                break;
+            }
+        }
+        let landmark_center = match self.landmark_plot {
+            Some(index) => geometry::polygon_center(&self.plots[index].polygon()),
+            None => {
+                info!("Found no plot for a church.");
+                return;
+            }
+        };
+
+        // A second church, for a large population
+        if self.amenities.churches < 2 {
+            return;
+        }
+        let second_candidates: Vec<usize> = candidates.iter()
+            .map(|(_, index)| *index)
+            .filter(|index| {
+                self.plot_use(*index) == DistrictUse::Residential
+                    && geometry::manhattan_distance(geometry::polygon_center(&self.plots[*index].polygon()), landmark_center)
+                        >= amenity::CHURCH_SPACING_MIN
+            })
+            .take(LANDMARK_CANDIDATES_MAX)
+            .collect();
+        for index in second_candidates {
+            if self.build_church(index) {
+                self.second_church_plot = Some(index);
+                return;
+            }
+        }
+        info!("Found no plot for a second church.");
+    }
+
+    /// Lay out the training yard of the guard on the plot set aside for it, near the barracks
+    /// over the gates. Earthworks have no barracks, and so no training yard. The plot is
+    /// given back to the houses if the yard does not fit.
+    pub fn build_training_yard(&mut self) {
+        let index = match self.amenities.training_yard_plot {
+            Some(index) => index,
+            None => return,
+        };
+        let yard = match self.plot_site(&self.plots[index]) {
+            Some((offset, plot_excerpt, plot_build_area)) if self.defence == DefenceKind::StoneWall => {
+                training_yard::build_training_yard(&plot_excerpt, &plot_build_area, &self.block_palette, &mut self.rng)
+                    .map(|yard| (offset, yard))
+            }
+            _ => None,
+        };
+        let (offset, yard) = match yard {
+            Some(yard) => yard,
+            None => {
+                self.amenities.training_yard_plot = None;
+                return;
+            }
+        };
+        paste_chopping_trees(&mut self.excerpt, offset, &yard);
+        info!("Laid out a training yard on plot {}.", index);
+
+        self.buildings.push(Building {
+            plot_index: index,
+            footprint: footprint(&yard, offset),
+            palette: self.block_palette.clone(),
+            doors: Vec::new(),
+            address: None,
+            purpose: None,
+            family_name: None,
+        });
+    }
+
+    /// Build a church on the plot with the given index, if it fits.
+    fn build_church(&mut self, index: usize) -> bool {
+        let (offset, plot_excerpt, plot_build_area) = match self.plot_site(&self.plots[index]) {
+            Some(site) => site,
+            None => return false,
+        };
+        let church = match church::build_church(
+            &plot_excerpt,
+            &plot_build_area,
+            &self.block_palette,
+            &mut self.rng,
+        ) {
+            Some(church) => church,
+            None => return false,
+        };
+        paste_chopping_trees(&mut self.excerpt, offset, &church.structure);
+        info!("Built a church on plot {}.", index);
+
+        self.buildings.push(Building {
+            plot_index: index,
+            footprint: footprint(&church.structure, offset),
+            palette: self.block_palette.clone(),
+            doors: church.doors.iter()
+                .map(|door| ExteriorDoor {
+                    coordinates: door.coordinates + offset,
+                    facing: door.facing,
+                })
+                .collect(),
+            address: None,
+            purpose: None,
+            family_name: None,
+        });
+        true
+    }
+
+    /// Dig a well on one of the empty plots in each district, in a corner next to a street,
+    /// and on more of the empty plots if the population needs more wells. Desert towns get
+    /// covered cisterns instead of wells, and pergolas shading the other empty plots.
+    pub fn build_wells(&mut self) {
+        // Water boils away in the Nether
+        if self.is_nether() {
+            return;
+        }
+
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let is_desert = self.block_palette.biome == BiomeStyle::Desert;
+
+        let courtyards: Vec<(usize, Option<usize>, &Plot)> = self.plots.iter()
+            .enumerate()
+            .filter(|(index, _)| {
+                self.config.is_skipped_plot(*index)
+                    && self.plot_use(*index) != DistrictUse::Plaza
+                    && !self.is_bathhouse_plot(*index)
+            })
+            .map(|(index, plot)| (index, self.plot_districts.get(index).copied(), plot))
+            .collect();
+        let well_plots = well::dig_wells(&mut self.excerpt, &courtyards, self.amenities.wells, features, is_desert);
+        info!("Dug {} wells.", well_plots.len());
+
+        // Shade for the courtyards without a well, in desert towns
+        if is_desert {
+            let unshaded = courtyards.iter()
+                .filter(|(index, _, _)| !well_plots.contains(index))
+                .map(|(_, _, plot)| *plot);
+            let pergola_count = pergola::shade_courtyards(&mut self.excerpt, unshaded, features);
+            info!("Put up {} pergolas.", pergola_count);
+        }
+    }
+
+    /// Build some structures (houses?) on the plots.
+    pub fn build_plots(&mut self) {
+        // The second house of each semi-detached pair is built with the random choices
+        // of the first, for matching houses on either side of the party wall.
+        let mut semi_detached_rngs: HashMap<usize, StdRng> = HashMap::new();
+        let square = self.district_uses.iter()
+            .position(|district_use| *district_use == DistrictUse::Plaza)
+            .filter(|_| self.config.arcades)
+            .map(|index| self.districts[index].clone());
+
+        for index in 0..self.plots.len() {
+            // Skip every Nth plot, the church plots, and the plots making up the plaza
+            if self.config.is_skipped_plot(index)
+                || self.landmark_plot == Some(index)
+                || self.second_church_plot == Some(index)
+                || self.amenities.training_yard_plot == Some(index)
+                || self.plot_use(index) == DistrictUse::Plaza
+                || village::has_house_inside(&self.plots[index].polygon(), &self.villages)
+            {
+                continue;
+            }
+
+            // Level the ground for the houses, keeping the lie of the land in the parks
+            if self.config.terraforming && !self.is_park_plot(index) {
+                if let Some(features) = &self.features {
+                    terraforming::level_plot(&mut self.excerpt, &self.plots[index].polygon(), features);
+                }
+            }
+
+            if let Some((offset, plot_excerpt, mut plot_build_area)) = self.plot_site(&self.plots[index]) {
+                // Lay out parks in the green districts, and around the ponds and canals,
+                // instead of building houses. In the cliff style, all plots are gardens.
+                if self.is_park_plot(index) || self.config.style == SettlementStyle::Cliff {
+                    let park = zoning::build_park(&plot_excerpt, &plot_build_area, &self.block_palette, &mut self.rng);
+                    paste_chopping_trees(&mut self.excerpt, offset, &park);
+                    continue;
+                }
+
+                // Let row houses build up to the edges shared with their neighbours,
+                // so that neighbouring houses share the party wall between them.
+                let party_walls = self.row_house_party_walls.get(index).map(Vec::as_slice).unwrap_or(&[]);
+                for (start, end) in party_walls {
+                    let start = BlockCoord(start.0 - offset.0, 0, start.1 - offset.2);
+                    let end = BlockCoord(end.0 - offset.0, 0, end.1 - offset.2);
+                    for position in line(&start, &end, 1) {
+                        plot_build_area.set_designation_at(
+                            (position.0 as usize, position.2 as usize),
+                            build_area::AreaDesignation::Plot(build_area::BuildRights::Buildable),
+                        );
+                    }
+                }
+                let is_row_house = !party_walls.is_empty();
+                let semi_detached_pair = self.semi_detached_pairs.get(index).copied().flatten();
+
+                // Set the houses facing the market square back from the street, for the arcades,
+                // with the walkway under the arcade counting as street for the front doors
+                let arcade_walkway = match &square {
+                    Some(square) => plaza::arcade_walkway(&self.plots[index], square),
+                    None => Vec::new(),
+                };
+                for column in &arcade_walkway {
+                    plot_build_area.set_designation_at(
+                        ((column.0 - offset.0) as usize, (column.1 - offset.2) as usize),
+                        build_area::AreaDesignation::Road(build_area::BuildRights::Forbidden),
+                    );
+                }
+
+                let mut custom_palette = self.block_palette.for_plot(&self.wood_available, index);
+                let house_options = self.house_options(
+                    index,
+                    &mut custom_palette,
+                    is_row_house,
+                    semi_detached_pair.is_some(),
+                );
+
+                // Generate a structure on the plot
+                let mut partner_rng = semi_detached_rngs.remove(&index);
+                if let (Some((partner, _)), None) = (semi_detached_pair, &partner_rng) {
+                    semi_detached_rngs.insert(partner, self.rng.clone());
+                }
+                if let Some(house) = structure_builder::build_house(
+                    &plot_excerpt,
+                    &plot_build_area,
+                    &custom_palette,
+                    &house_options,
+                    partner_rng.as_mut().unwrap_or(&mut self.rng),
+                ) {
+                    // TODO Enforce plot_build_area before pasting the new plot into the world?
+                    paste_chopping_trees(&mut self.excerpt, offset, &house.structure);
+
+                    if let Some(floor_y) = house.doors.iter().map(|door| door.coordinates.1 + offset.1).min() {
+                        if !arcade_walkway.is_empty() {
+                            plaza::build_arcade(
+                                &mut self.excerpt,
+                                &arcade_walkway,
+                                floor_y,
+                                self.config.story_height,
+                                &custom_palette,
+                            );
+                        }
+                    }
+
+                    // Once both houses of a semi-detached pair stand, join them by their chimney
+                    if let Some((partner, party_wall)) = semi_detached_pair {
+                        if self.buildings.iter().any(|building| building.plot_index == partner) {
+                            structure_builder::build_shared_chimney(&mut self.excerpt, party_wall);
+                        }
+                    }
+
+                    self.buildings.push(Building {
+                        plot_index: index,
+                        footprint: footprint(&house.structure, offset),
+                        palette: custom_palette,
+                        doors: house.doors.iter()
+                            .map(|door| ExteriorDoor {
+                                coordinates: door.coordinates + offset,
+                                facing: door.facing,
+                            })
+                            .collect(),
+                        address: None,
+                        purpose: Some(house_options.purpose),
+                        family_name: Some(naming::family_name(&mut self.rng)),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Carve the dwellings into the cliffs, and furnish them.
+    pub fn build_cliff_dwellings(&mut self) {
+        for dwelling in &self.cliff_dwellings {
+            cliff_dwelling::build_cliff_dwelling(&mut self.excerpt, dwelling, &self.block_palette, &mut self.rng);
+        }
+    }
+
+    /// In desert towns, cover the street running furthest through the market districts,
+    /// as a shaded market street between the houses along it.
+    pub fn build_covered_market(&mut self) {
+        if self.block_palette.biome != BiomeStyle::Desert {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let market_districts: Vec<&Snake> = self.districts.iter()
+            .zip(&self.district_uses)
+            .filter(|(_, district_use)| **district_use == DistrictUse::Market)
+            .map(|(district, _)| district)
+            .collect();
+        if let Some((street, share)) = road::market_street(&self.streets, &market_districts) {
+            info!("Covering a market street, {:.0}% inside the market districts.", share * 100.0);
+            road::build_covered_street(&mut self.excerpt, street, &features.terrain, self.scaled_config.street_width);
+        }
+    }
+
+    /// In the Nether, enclose the city roads and the streets of the towns in walkways, as
+    /// shelter from the ghasts, left open where the streets meet and at the front doors.
+    pub fn build_enclosed_walkways(&mut self) {
+        if !self.is_nether() {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let config = &self.scaled_config;
+        let walkways: Vec<(&RoadPath, i64)> = self.city_roads.iter()
+            .chain(self.streets.iter())
+            .chain(self.outlying_towns.iter()
+                .flat_map(|town| town.city_roads.iter().chain(town.streets.iter())))
+            .map(|road| (road, road.class.style(config).width))
+            .collect();
+
+        road::build_enclosed_walkways(
+            &mut self.excerpt,
+            &walkways,
+            self.buildings.iter().flat_map(|building| building.doors.iter()),
+            &features.terrain,
+            &self.block_palette.city_wall_main,
+        );
+        info!("Enclosed {} streets and city roads in walkways.", walkways.len());
+    }
+
+    /// Build a bathhouse on the courtyard plot closest to any lava found safely below
+    /// the town, with its pools heated by the lava.
+    pub fn build_bathhouse(&mut self) {
+        let lava: BlockColumnCoord = match self.lava_below_town {
+            Some(lava) if !self.is_nether() => lava.into(),
+            _ => return,
+        };
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let courtyards = self.plots.iter()
+            .enumerate()
+            .filter(|(index, _)| self.config.is_skipped_plot(*index) && self.plot_use(*index) != DistrictUse::Plaza);
+        if let Some(bathhouse) = bathhouse::plan_bathhouse(courtyards, lava, features) {
+            bathhouse::build_bathhouse(&mut self.excerpt, &bathhouse, features, &self.block_palette);
+            info!("Built a bathhouse at {:?}, heated by the lava below the town.", bathhouse.center);
+            self.bathhouse = Some(bathhouse);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Stages finishing the settlement: Street furniture, signs, graveyards, weathering,
+//! lighting, and checking the result.
+
+use std::collections::HashSet;
+
+use log::{info, warn};
+
+use mcprogedit::coordinates::BlockColumnCoord;
+use rand::Rng;
+
+use crate::block_palette::BiomeStyle;
+use crate::block_whitelist;
+use crate::config::SettlementStyle;
+use crate::graveyard;
+use crate::light_audit;
+use crate::line::line;
+use crate::naming;
+use crate::night_lighting;
+use crate::pathfinding::{RoadClass, RoadPath};
+use crate::pets;
+use crate::road;
+use crate::ruin;
+use crate::signs;
+use crate::street_detail;
+use crate::treasure;
+use crate::types::Snake;
+use crate::village;
+use crate::wall::{self, DefenceKind};
+use crate::weathering;
+
+use super::{footprint, GenerationContext, FEATURES_MISSING};
+
+impl GenerationContext {
+    /// Put up railings along the roads and streets where they run high above the ground
+    /// beside them, once everything along the roads has been built.
+    pub fn build_guard_rails(&mut self) {
+        let config = &self.scaled_config;
+        let roads = self.country_roads.iter()
+            .chain(self.city_roads.iter())
+            .chain(self.streets.iter())
+            .chain(self.outlying_towns.iter()
+                .flat_map(|town| town.city_roads.iter().chain(town.streets.iter())))
+            .map(|road| (road, road.class.style(config).width));
+
+        let mut rail_count = 0;
+        for (road, road_width) in roads {
+            rail_count += road::build_guard_rails(
+                &mut self.excerpt,
+                road,
+                road_width,
+                &self.block_palette.city_wall_main,
+            );
+        }
+        info!("Put up {} blocks of railings along elevated roads.", rail_count);
+    }
+
+    /// Put lamp posts, benches and district signposts along the city roads and streets,
+    /// keeping clear of the doors of the buildings along them.
+    pub fn place_street_furniture(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let entrances: Vec<BlockColumnCoord> = self.buildings.iter()
+            .flat_map(|building| building.doors.iter().map(|door| door.coordinates.into()))
+            .collect();
+
+        let mut count = 0;
+        for street in self.city_roads.iter().chain(self.streets.iter()) {
+            count += street_detail::place_street_furniture(
+                &mut self.excerpt,
+                street,
+                &street.class.style(&self.scaled_config),
+                features,
+                &self.districts,
+                &self.district_names,
+                &entrances,
+            );
+        }
+        info!("Placed {} pieces of street furniture.", count);
+    }
+
+    /// Number the houses along each named street, and put up signs with
+    /// the addresses by the doors and the street names at the street corners.
+    pub fn place_signs(&mut self) {
+        // Addresses can be on both city roads and streets.
+        let named_roads: Vec<(&RoadPath, &String, i64)> = self.city_roads.iter()
+            .zip(self.city_road_names.iter())
+            .chain(self.streets.iter().zip(self.street_names.iter()))
+            .map(|(road, name)| (road, name, road.class.style(&self.scaled_config).width))
+            .collect();
+
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        signs::put_up_signs(&mut self.excerpt, &mut self.buildings, &named_roads, &features.terrain);
+    }
+
+    /// In snowy towns, keep the main streets clear of snow. The highways, city roads and
+    /// streets are cleared, while the snow is left lying on the alleys and paths.
+    pub fn clear_snow_from_streets(&mut self) {
+        if self.block_palette.biome != BiomeStyle::Snowy {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let main_roads = self.country_roads.iter()
+            .chain(self.city_roads.iter())
+            .chain(self.streets.iter())
+            .chain(self.outlying_towns.iter()
+                .flat_map(|town| town.city_roads.iter().chain(town.streets.iter())))
+            .filter(|road| !matches!(road.class, RoadClass::Alley | RoadClass::Path));
+        let mut cleared_count = 0;
+        for road in main_roads {
+            cleared_count += road::clear_snow(&mut self.excerpt, road, road.class.style(&self.scaled_config).width);
+        }
+        info!("Cleared {} snow layers off the main streets.", cleared_count);
+    }
+
+    /// Build the top of the town wall. Earthworks have no crowning.
+    pub fn build_wall_crowning(&mut self) {
+        if self.defence != DefenceKind::StoneWall {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let towns = std::iter::once((&self.wall_circle, &self.city_roads))
+            .chain(self.outlying_towns.iter().map(|town| (&town.wall_circle, &town.city_roads)));
+        for (wall_circle, city_roads) in towns {
+            wall::build_wall_crowning(
+                &mut self.excerpt,
+                wall_circle,
+                city_roads,
+                self.scaled_config.city_road_width,
+                features,
+                &self.block_palette,
+                self.scaled_config.wall_width,
+            );
+        }
+    }
+
+    /// Lay out graveyards on the free ground around the churches, with headstones for
+    /// members of the households in town.
+    pub fn build_graveyards(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let family_names: Vec<String> = self.buildings.iter()
+            .filter_map(|building| building.family_name.clone())
+            .collect();
+
+        let church_plots = self.landmark_plot.iter().chain(self.second_church_plot.iter());
+        for plot_index in church_plots {
+            let church = match self.buildings.iter().find(|building| building.plot_index == *plot_index) {
+                Some(church) => church,
+                None => continue,
+            };
+            let graves = graveyard::plan_graves(
+                &self.plots[*plot_index].polygon(),
+                &church.footprint,
+                features,
+                &family_names,
+                self.config.graves_max,
+                &mut self.rng,
+            );
+            for grave in &graves {
+                graveyard::build_grave(&mut self.excerpt, grave);
+            }
+            info!("Laid out {} graves beside the church on plot {}.", graves.len(), plot_index);
+            self.graves.extend(graves);
+        }
+    }
+
+    /// Plan pets for some of the homes, sitting by the hearth, and now and then a parrot
+    /// in a tavern, of breeds fitting the surroundings of the town.
+    pub fn place_pets(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let surroundings = pets::surroundings(features, self.block_palette.biome == BiomeStyle::Desert);
+
+        self.pets = pets::plan_pets(
+            &self.excerpt,
+            &self.buildings,
+            surroundings,
+            self.config.pet_share,
+            self.config.tavern_parrot_chance,
+            &mut self.rng,
+        );
+        info!("Found homes for {} pets, in {:?} surroundings.", self.pets.len(), surroundings);
+    }
+
+    /// Rebuild the houses of the adopted villages in the materials of the town.
+    pub fn upgrade_villages(&mut self) {
+        let mut count = 0;
+        for village in &self.villages {
+            count += village::upgrade_houses(&mut self.excerpt, village, &self.block_palette);
+        }
+        if !self.villages.is_empty() {
+            info!("Replaced {} blocks of the village houses with the materials of the town.", count);
+        }
+    }
+
+    /// In the ruins style, let a share of the buildings fall into ruin.
+    pub fn ruin_buildings(&mut self) {
+        if self.config.style != SettlementStyle::Ruins {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let ruin_share = self.config.ruin_share.max(0.0).min(1.0) as f64;
+        for (index, building) in self.buildings.iter().enumerate() {
+            if self.rng.gen_bool(ruin_share) {
+                ruin::ruin_building(&mut self.excerpt, building, features, &mut self.rng);
+                self.ruins.push(index);
+            }
+        }
+        info!("Let {} of {} building(s) fall into ruin.", self.ruins.len(), self.buildings.len());
+    }
+
+    /// Weather the finished town for a lived-in look, as much as set in the config.
+    /// In the jungle style, vines grow down the walls however fresh the town is.
+    pub fn weather_town(&mut self) {
+        if self.block_palette.biome == BiomeStyle::Jungle {
+            let count = weathering::overgrow_with_vines(&mut self.excerpt, &self.buildings, &mut self.rng);
+            info!("Hung {} blocks of vines on the walls of the jungle town.", count);
+        }
+        if self.config.weathering <= 0.0 {
+            return;
+        }
+        let count = weathering::weather(&mut self.excerpt, &self.buildings, self.config.weathering, &mut self.rng);
+        info!("Weathered the town, changing {} blocks.", count);
+    }
+
+    /// Light up the town for night-time screenshots: A light inside every window, and
+    /// lanterns along the façades of the houses. The ruins are left dark.
+    pub fn light_for_night(&mut self) {
+        if !self.config.night_lighting {
+            return;
+        }
+        let mut window_count = 0;
+        let mut lantern_count = 0;
+        for (index, building) in self.buildings.iter().enumerate() {
+            if self.ruins.contains(&index) {
+                continue;
+            }
+            window_count += night_lighting::light_windows(&mut self.excerpt, building);
+            lantern_count += night_lighting::hang_facade_lanterns(&mut self.excerpt, building);
+        }
+        info!("Lit {} window(s), and hung {} lantern(s) on the façades.", window_count, lantern_count);
+    }
+
+    /// Light every spot inside the walls of the towns dark enough for monsters to spawn.
+    pub fn audit_lighting(&mut self) {
+        if !self.config.light_audit {
+            return;
+        }
+        let circumferences: Vec<&Snake> = std::iter::once(&self.town_circumference)
+            .chain(self.outlying_towns.iter().map(|town| &town.circumference))
+            .collect();
+
+        // Keep the roads and the entrances clear of lights standing on the ground
+        let config = &self.scaled_config;
+        let roads: HashSet<BlockColumnCoord> = self.city_roads.iter()
+            .chain(self.streets.iter())
+            .chain(self.outlying_towns.iter()
+                .flat_map(|town| town.city_roads.iter().chain(town.streets.iter())))
+            .map(|road| (road, road.class.style(config).width))
+            .flat_map(|(path, width)| path.windows(2)
+                .flat_map(move |segment| line(&segment[0].coordinates, &segment[1].coordinates, width)))
+            .map(|position| BlockColumnCoord(position.0, position.2))
+            .collect();
+        let entrances: Vec<BlockColumnCoord> = self.buildings.iter()
+            .flat_map(|building| building.doors.iter().map(|door| door.coordinates.into()))
+            .collect();
+
+        let count = light_audit::light_dark_spots(
+            &mut self.excerpt,
+            &circumferences,
+            &roads,
+            &entrances,
+            &self.block_palette,
+        );
+        info!("Placed {} light(s) in the dark spots of town.", count);
+    }
+
+    /// Hide treasure chests around the town in adventure mode, each with a book of town
+    /// lore: In a crypt under the church, behind a waterfall, and under the floorboards
+    /// of houses.
+    pub fn hide_treasures(&mut self) {
+        if !self.config.adventure_mode || self.config.treasure_count == 0 {
+            return;
+        }
+        let treasure_count = self.config.treasure_count;
+        let lore = treasure::Lore {
+            town_name: &self.town_name,
+            street_names: &self.street_names,
+            district_names: &self.district_names,
+            founder: naming::founder_name(&mut self.rng),
+            saint: naming::saint_name(&mut self.rng),
+        };
+        let hidden_count = treasure::hide_treasures(
+            &mut self.excerpt,
+            &self.buildings,
+            self.landmark_plot,
+            self.town_center,
+            &lore,
+            treasure_count,
+            &mut self.rng,
+        );
+        info!("Hid {} treasures around {}.", hidden_count, lore.town_name);
+    }
+
+    /// Check the placed blocks against the block whitelist, if there is one, replacing
+    /// the blocks not on it by allowed alternatives with a strict palette.
+    pub fn enforce_block_whitelist(&mut self) {
+        let (whitelist, original) = match (&self.config.block_whitelist, &self.original_excerpt) {
+            (Some(whitelist), Some(original)) => (whitelist, original),
+            _ => return,
+        };
+        let violations = block_whitelist::enforce_whitelist(
+            &mut self.excerpt,
+            original,
+            whitelist,
+            self.config.strict_palette,
+        );
+        for (name, count) in &violations {
+            warn!("Placed {} blocks of {}, which is not on the block whitelist.", count, name);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Stages building the walls, waterways and roads, and the structures outside
+//! the town walls.
+
+use log::info;
+
+use crate::block_palette::BiomeStyle;
+use crate::farm;
+use crate::forestry;
+use crate::harbor;
+use crate::lighthouse;
+use crate::mine;
+use crate::pasture;
+use crate::pathfinding::{RoadClass, RoadPath};
+use crate::pond;
+use crate::road;
+use crate::tannery;
+use crate::toll_station;
+use crate::viewpoint;
+use crate::wall::{self, DefenceKind};
+use crate::waterways;
+use crate::waymarks;
+use crate::windmill;
+
+use super::{town_wall_circles, GenerationContext, FEATURES_MISSING};
+
+impl GenerationContext {
+    /// Clear the trees and the undergrowth off the strips along either side of the town walls.
+    pub fn clear_pomerium(&mut self) {
+        if self.config.pomerium_width <= 0 {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let wall_circles = town_wall_circles(&self.wall_circle, &self.outlying_towns);
+        for wall_circle in wall_circles {
+            wall::clear_pomerium(
+                &mut self.excerpt,
+                wall_circle,
+                features,
+                self.scaled_config.wall_width,
+                self.config.pomerium_width,
+            );
+        }
+    }
+
+    /// Build the main body of the town walls, or the earthworks, depending on the kind of defences.
+    pub fn build_wall(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let towns = std::iter::once((&self.wall_circle, &self.city_roads))
+            .chain(self.outlying_towns.iter().map(|town| (&town.wall_circle, &town.city_roads)));
+        for (wall_circle, city_roads) in towns {
+            match self.defence {
+                DefenceKind::StoneWall => wall::build_wall(
+                    &mut self.excerpt,
+                    wall_circle,
+                    city_roads,
+                    self.scaled_config.city_road_width,
+                    features,
+                    &self.block_palette,
+                    self.scaled_config.wall_width,
+                    &mut self.rng,
+                ),
+                DefenceKind::Earthwork => wall::build_earthwork(
+                    &mut self.excerpt,
+                    wall_circle,
+                    features,
+                    &self.block_palette,
+                    self.scaled_config.wall_width,
+                ),
+            }
+        }
+    }
+
+    /// Fill in the ponds planned to be filled in, and edge the other ponds and canals with stone.
+    pub fn build_ponds(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        for pond in &self.ponds {
+            pond::build_pond(&mut self.excerpt, pond, features);
+        }
+    }
+
+    /// Build the canal and the aqueduct, where planned.
+    pub fn build_waterways(&mut self) {
+        if let Some(canal) = &self.canal {
+            waterways::build_canal(&mut self.excerpt, canal);
+        }
+        if let Some(aqueduct) = &self.aqueduct {
+            let features = self.features.as_ref().expect(FEATURES_MISSING);
+            waterways::build_aqueduct(&mut self.excerpt, aqueduct, features);
+        }
+    }
+
+    /// Build the various roads and streets, each in the style of its class.
+    pub fn build_roads(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        // Boardwalks instead of gravel and cobbles, for the streets of stilt villages
+        let is_stilt_village = self.block_palette.biome == BiomeStyle::Swamp;
+        let config = &self.scaled_config;
+        let town_road_style = |class: RoadClass| {
+            let style = class.style(config);
+            if is_stilt_village && class != RoadClass::Highway {
+                style.boardwalk()
+            } else {
+                style
+            }
+        };
+
+        let streets = self.streets.iter()
+            .chain(self.outlying_towns.iter().flat_map(|town| town.streets.iter()));
+        for street in streets {
+            road::build_road_in_style(
+                &mut self.excerpt,
+                street,
+                &features.terrain,
+                &town_road_style(street.class),
+                &mut self.rng,
+            );
+        }
+
+        for road in &self.country_roads {
+            let style = road.class.style(&self.scaled_config);
+            road::build_road_in_style(&mut self.excerpt, road, &features.terrain, &style, &mut self.rng);
+            road::build_bridge_keepers(
+                &mut self.excerpt,
+                road,
+                features,
+                style.width,
+                &self.block_palette,
+                &mut self.rng,
+            );
+        }
+
+        let city_roads = self.city_roads.iter()
+            .chain(self.outlying_towns.iter().flat_map(|town| town.city_roads.iter()));
+        for road in city_roads {
+            road::build_road_in_style(
+                &mut self.excerpt,
+                road,
+                &features.terrain,
+                &town_road_style(road.class),
+                &mut self.rng,
+            );
+        }
+
+        // Paved junctions where the roads and streets meet
+        let roads: Vec<(&RoadPath, RoadClass, i64)> = self.country_roads.iter()
+            .chain(self.city_roads.iter())
+            .chain(self.streets.iter())
+            .chain(self.outlying_towns.iter()
+                .flat_map(|town| town.city_roads.iter().chain(town.streets.iter())))
+            .map(|road| (road, road.class, road.class.style(&self.scaled_config).width))
+            .collect();
+        let junctions = road::find_junctions(&roads);
+        for junction in &junctions {
+            road::build_junction(
+                &mut self.excerpt,
+                junction,
+                &town_road_style(junction.class),
+                self.config.roundabouts,
+                &mut self.rng,
+            );
+        }
+        info!("Paved {} junctions.", junctions.len());
+
+        // Decks over the water for the boardwalks, on piles down to the bottom
+        if is_stilt_village {
+            let mut deck_count = 0;
+            for (road, class, width) in &roads {
+                if *class != RoadClass::Highway {
+                    deck_count += road::build_boardwalk(&mut self.excerpt, road, *width, features);
+                }
+            }
+            info!("Laid {} blocks of boardwalk over the water.", deck_count);
+        }
+
+        // Snow lying on the roads in snowy towns, until the main streets are cleared
+        if let Some(snow_layer) = &self.block_palette.snow_layer {
+            let mut covered_count = 0;
+            for (road, _, width) in &roads {
+                covered_count += road::cover_with_snow(&mut self.excerpt, road, *width, snow_layer);
+            }
+            info!("Covered {} road blocks with snow.", covered_count);
+        }
+    }
+
+    /// Build the fields, and the paths leading to them.
+    pub fn build_farms(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        for field in &self.fields {
+            if let Some(path) = &field.path {
+                let path_style = path.class.style(&self.scaled_config);
+                road::build_road_in_style(&mut self.excerpt, path, &features.terrain, &path_style, &mut self.rng);
+            }
+            farm::build_field(&mut self.excerpt, field, features);
+        }
+    }
+
+    /// Build the pastures, and the paths leading to them.
+    pub fn build_pastures(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        for pasture in &self.pastures {
+            if let Some(path) = &pasture.path {
+                let path_style = path.class.style(&self.scaled_config);
+                road::build_road_in_style(&mut self.excerpt, path, &features.terrain, &path_style, &mut self.rng);
+            }
+            pasture::build_pasture(&mut self.excerpt, pasture, features);
+        }
+    }
+
+    /// Build the windmill, and the path from it to the fields.
+    pub fn build_windmill(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        if let Some(windmill) = &self.windmill {
+            if let Some(path) = &windmill.path {
+                let path_style = path.class.style(&self.scaled_config);
+                road::build_road_in_style(&mut self.excerpt, path, &features.terrain, &path_style, &mut self.rng);
+            }
+            windmill::build_windmill(&mut self.excerpt, windmill, features, &self.block_palette);
+        }
+    }
+
+    /// Build the harbour, with its piers, boats, quay, crane and fish market.
+    pub fn build_harbor(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        if let Some(harbor) = &self.harbor {
+            harbor::build_harbor(
+                &mut self.excerpt,
+                harbor,
+                features,
+                &self.block_palette,
+                &self.scaled_config,
+                &mut self.rng,
+            );
+        }
+    }
+
+    /// Build the lighthouse and the keeper's cottage, and the path from there to the harbour.
+    pub fn build_lighthouse(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        if let Some(lighthouse) = &self.lighthouse {
+            if let Some(path) = &lighthouse.path {
+                let path_style = path.class.style(&self.scaled_config);
+                road::build_road_in_style(&mut self.excerpt, path, &features.terrain, &path_style, &mut self.rng);
+            }
+            lighthouse::build_lighthouse(&mut self.excerpt, lighthouse, features, &self.block_palette);
+        }
+    }
+
+    /// Dig the quarry or mining tunnel, and build the road from it.
+    pub fn build_mine(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        if let Some(mine) = &self.mine {
+            mine::build_mine(&mut self.excerpt, mine, features, &self.scaled_config, &mut self.rng);
+        }
+    }
+
+    /// Clear-cut a patch of forest, and build the lumber camp there with a road from it.
+    pub fn build_lumber_camp(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        if let Some(camp) = &self.lumber_camp {
+            forestry::build_lumber_camp(
+                &mut self.excerpt,
+                camp,
+                features,
+                &self.block_palette,
+                &self.scaled_config,
+                &mut self.rng,
+            );
+        }
+    }
+
+    /// Build the structures at the viewpoints, with the footpaths up to them.
+    pub fn build_viewpoints(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        for viewpoint in &self.viewpoints {
+            viewpoint::build_viewpoint(&mut self.excerpt, viewpoint, features, &self.scaled_config, &mut self.rng);
+        }
+    }
+
+    /// Put up milestones and wayside shrines along the country roads.
+    pub fn place_waymarks(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        waymarks::place_waymarks(
+            &mut self.excerpt,
+            &self.country_roads,
+            features,
+            &self.wall_circle,
+            &self.town_name,
+            self.config.country_road_width,
+            &mut self.rng,
+        );
+    }
+
+    /// Build the tanneries and dyeworks, and the paths from them to the town gates.
+    pub fn build_nuisance_industries(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        for industry in &self.nuisance_industries {
+            if let Some(path) = &industry.path {
+                let path_style = path.class.style(&self.scaled_config);
+                road::build_road_in_style(&mut self.excerpt, path, &features.terrain, &path_style, &mut self.rng);
+            }
+            tannery::build_nuisance_industry(&mut self.excerpt, industry, features, &self.block_palette, &mut self.rng);
+        }
+    }
+
+    /// Build toll bars and guard lodges where the country roads enter the area.
+    pub fn build_toll_stations(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        for station in &self.toll_stations {
+            toll_station::build_toll_station(
+                &mut self.excerpt,
+                station,
+                features,
+                &self.block_palette,
+                self.config.country_road_width,
+                &self.town_name,
+                &mut self.rng,
+            );
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! Stages planning the use of the land, from the districts and plots in town to
+//! the fields, pastures and industries around it.
+
+use log::{info, warn};
+
+use imageproc::stats::histogram;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+
+use crate::amenity;
+use crate::cliff_dwelling;
+use crate::config::SettlementStyle;
+use crate::farm;
+use crate::forestry;
+use crate::geometry::{self, extract_blocks, LandUsageGraph};
+use crate::harbor;
+use crate::lighthouse;
+use crate::mine;
+use crate::naming;
+use crate::partitioning;
+use crate::pasture;
+use crate::pathfinding::{self, RoadNode, RoadNodeKind, RoadPath};
+use crate::plot::{self, divide_city_block, Plot};
+use crate::road::roads_split;
+use crate::toll_station;
+use crate::types::Snake;
+use crate::viewpoint;
+use crate::wall;
+use crate::waterways::{self, Canal};
+use crate::windmill;
+use crate::zoning::{self, DistrictUse};
+
+use super::{town_wall_circles, wealthiest_district, GenerationContext, AREAS_MISSING, FEATURES_MISSING};
+
+impl GenerationContext {
+    /// Divide the towns into districts, and the districts into plots.
+    pub fn plan_land_usage(&mut self) {
+        // Add intersection points between roads/streets and circumference,
+        // so that the geometry actually describes distinct areas.
+        geometry::add_intersection_points(&mut self.streets, &mut self.wall_circle);
+        geometry::add_intersection_points(&mut self.city_roads, &mut self.wall_circle);
+        for town in &mut self.outlying_towns {
+            geometry::add_intersection_points(&mut town.streets, &mut town.wall_circle);
+            geometry::add_intersection_points(&mut town.city_roads, &mut town.wall_circle);
+        }
+
+        // Likewise for the waterways, with the wall and with the streets crossing them
+        let mut waterway_paths: Vec<RoadPath> = self.canal.iter()
+            .map(|canal| canal.path.clone())
+            .chain(self.aqueduct.iter().map(|aqueduct| aqueduct.path.clone()))
+            .collect();
+        geometry::add_intersection_points(&mut waterway_paths, &mut self.wall_circle);
+        let (inside_waterways, _) = roads_split(&waterway_paths, &self.wall_circle);
+        let mut waterway_paths = Vec::new();
+        for waterway in inside_waterways {
+            let y = waterway[0].coordinates.1;
+            let mut snake = pathfinding::snake_from_road_path(&waterway);
+            geometry::add_intersection_points(&mut self.streets, &mut snake);
+            geometry::add_intersection_points(&mut self.city_roads, &mut snake);
+            waterway_paths.push(snake.iter()
+                .map(|column| RoadNode { coordinates: BlockCoord(column.0, y, column.1), kind: RoadNodeKind::Ground })
+                .collect::<RoadPath>());
+        }
+
+        // The road network is now final; compute the shared distance-to-road field.
+        self.features.as_mut().expect(FEATURES_MISSING).set_road_network(
+            self.streets.iter()
+                .chain(self.city_roads.iter())
+                .chain(self.country_roads.iter())
+                .chain(self.outlying_towns.iter().flat_map(|town| town.streets.iter().chain(town.city_roads.iter()))),
+            &self.inspection,
+        );
+
+        let (land_usage_graph, mut districts, mut plots, mut plot_districts) =
+            self.plan_districts(&self.wall_circle, &self.city_roads, &self.streets, &waterway_paths, 0);
+
+        // The district at the heart of town is considered the wealthiest.
+        let wealthiest_district = wealthiest_district(&districts, &self.town_center);
+        info!("The wealthiest district is {:?}.", wealthiest_district);
+
+        // The outlying towns get districts and plots of their own, following those of the main town.
+        for town in &self.outlying_towns {
+            let (_, mut town_districts, mut town_plots, mut town_plot_districts) =
+                self.plan_districts(&town.wall_circle, &town.city_roads, &town.streets, &[], districts.len());
+            districts.append(&mut town_districts);
+            plots.append(&mut town_plots);
+            plot_districts.append(&mut town_plot_districts);
+        }
+
+        if self.inspection.is_enabled() {
+            let features = self.features.as_ref().expect(FEATURES_MISSING);
+            let mut city_plan = features.coloured_map.clone();
+            for plot in &plots {
+                plot.draw(&mut city_plan);
+            }
+            for street in &self.streets {
+                pathfinding::draw_road_path(&mut city_plan, street);
+            }
+            for road in &self.country_roads {
+                pathfinding::draw_road_path(&mut city_plan, road);
+            }
+            for road in &self.city_roads {
+                pathfinding::draw_road_path(&mut city_plan, road);
+            }
+            for town in &self.outlying_towns {
+                for road in town.streets.iter().chain(town.city_roads.iter()) {
+                    pathfinding::draw_road_path(&mut city_plan, road);
+                }
+            }
+            self.inspection.save_image("city plan.png", &city_plan);
+        }
+
+        self.land_usage_graph = land_usage_graph;
+        self.districts = districts;
+        self.wealthiest_district = wealthiest_district;
+        self.plots = plots;
+        self.plot_districts = plot_districts;
+    }
+
+    /// Divide a single town into districts, and the districts into plots, bordered by the
+    /// streets, the roads, the waterways and the town wall.
+    /// Returns the land usage graph, the districts, the plots, and the district index of each plot.
+    /// District indices start from `first_district_index`.
+    fn plan_districts(
+        &self,
+        wall_circle: &Snake,
+        city_roads: &[RoadPath],
+        streets: &[RoadPath],
+        waterways: &[RoadPath],
+        first_district_index: usize,
+    ) -> (LandUsageGraph, Vec<Snake>, Vec<Plot>, Vec<usize>) {
+        let (x_len, _, z_len) = self.dimensions();
+
+        let mut land_usage_graph = LandUsageGraph::new();
+        land_usage_graph.add_roads(
+            streets,
+            geometry::EdgeKind::Street,
+            self.scaled_config.street_reserved_width,
+        );
+        land_usage_graph.add_roads(
+            city_roads,
+            geometry::EdgeKind::Road,
+            self.scaled_config.city_road_reserved_width,
+        );
+        land_usage_graph.add_roads(
+            waterways,
+            geometry::EdgeKind::Canal,
+            waterways::CANAL_WIDTH + 2,
+        );
+        land_usage_graph.add_circumference(
+            wall_circle,
+            geometry::EdgeKind::Wall,
+            self.scaled_config.wall_width + 2 * self.config.pomerium_width.max(0),
+        );
+
+        // Get the polygons for each "city block"
+        let districts = extract_blocks(&land_usage_graph);
+
+        // Make images of the extracted city blocks (for debug visuals only)
+        for (index, district) in districts.iter().enumerate() {
+            let colour = first_district_index + index;
+            let mut district_image = image::ImageBuffer::new(x_len as u32, z_len as u32);
+            geometry::draw_area(
+                &mut district_image,
+                district,
+                BlockColumnCoord(0, 0),
+                image::Luma([63u8]),
+            );
+            partitioning::draw_offset_snake(
+                &mut district_image,
+                district,
+                &BlockColumnCoord(0, 0),
+                image::Luma([255u8]),
+            );
+
+            self.inspection.save_image(&format!("D-01 district {:0>2}.png", colour), &district_image);
+
+            info!("District {} has area {}.", colour, geometry::area(district));
+
+            let stats = histogram(&district_image);
+            let surface_area = stats.channels[0][63];
+            let border_area = stats.channels[0][255];
+            info!(
+                "District {} image areas: {} + ({} / 2) = {}",
+                colour, surface_area, border_area, surface_area + (border_area / 2)
+            );
+        }
+
+        // Split the city blocks
+        let mut plots = Vec::new();
+        let mut plot_districts = Vec::new();
+        for (index, district) in districts.iter().enumerate() {
+            let mut district_plots = divide_city_block(district, &land_usage_graph);
+            // TODO draw the plots or something...
+            info!("Found {} plots for a district.", district_plots.len());
+            plot_districts.extend(std::iter::repeat(first_district_index + index).take(district_plots.len()));
+            plots.append(&mut district_plots);
+        }
+
+        (land_usage_graph, districts, plots, plot_districts)
+    }
+
+    /// Give names to the town, its city roads and its streets.
+    pub fn name_streets(&mut self) {
+        self.town_name = naming::town_name(&mut self.rng);
+        info!("The town is named {}.", self.town_name);
+
+        let mut names = naming::street_names(self.city_roads.len() + self.streets.len(), &mut self.rng);
+        self.street_names = names.split_off(self.city_roads.len());
+        self.city_road_names = names;
+    }
+
+    /// Find room for fields outside the towns, with paths to the country roads.
+    pub fn plan_farms(&mut self) {
+        // Nothing grows in fields without water, as in the Nether
+        if self.is_nether() {
+            return;
+        }
+
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let areas = self.areas.as_ref().expect(AREAS_MISSING);
+
+        let wall_circles = town_wall_circles(&self.wall_circle, &self.outlying_towns);
+
+        self.fields = farm::plan_fields(
+            features,
+            areas,
+            &wall_circles,
+            &self.country_roads,
+            self.config.max_fields,
+            &mut self.rng,
+        );
+    }
+
+    /// Find high ground by the fields for a windmill.
+    pub fn plan_windmill(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let wall_circles = self.wall_circles();
+
+        self.windmill = windmill::plan_windmill(features, &self.fields, &wall_circles);
+    }
+
+    /// Find a stretch of shore next to town for a harbour, if there is one,
+    /// with a shipyard if the towns are large enough.
+    pub fn plan_harbor(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let town_area = self.wall_circles()
+            .iter()
+            .map(|wall_circle| geometry::area(wall_circle).abs())
+            .sum::<i64>() as usize;
+        let with_shipyard = self.config.shipyard_town_area_min != 0
+            && town_area >= self.config.shipyard_town_area_min;
+
+        self.harbor = harbor::plan_harbor(
+            features,
+            &self.wall_circle,
+            self.town_center,
+            &self.country_roads,
+            with_shipyard,
+        );
+    }
+
+    /// Find a promontory near the harbour for a lighthouse, if there is a harbour.
+    pub fn plan_lighthouse(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let wall_circles = self.wall_circles();
+
+        self.lighthouse = self.harbor.as_ref()
+            .and_then(|harbor| lighthouse::plan_lighthouse(features, harbor, &wall_circles));
+    }
+
+    /// Find exposed rock for a quarry or a mining tunnel, with a road from it.
+    pub fn plan_mine(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let areas = self.areas.as_ref().expect(AREAS_MISSING);
+
+        let wall_circles = self.wall_circles();
+
+        self.mine = mine::plan_mine(
+            features,
+            &areas.mines,
+            &self.fields,
+            &wall_circles,
+            self.town_center,
+            &self.country_roads,
+        );
+    }
+
+    /// Decide what each district is used for, within the constraints on the mix of uses.
+    pub fn plan_zoning(&mut self) {
+        let (district_uses, infeasibilities) = zoning::plan_zoning(
+            &self.districts,
+            self.town_center,
+            &self.wall_circle,
+            &self.city_roads,
+            self.mine.as_ref().map(|mine| mine.site.into()),
+            &self.config,
+        );
+        self.district_uses = district_uses;
+        self.zoning_infeasibilities = infeasibilities;
+        self.district_names = naming::district_names(&self.district_uses, &mut self.rng);
+        self.district_story_caps = zoning::district_story_caps(&self.districts, self.town_center, &self.config);
+        self.plot_height_caps = zoning::view_corridor_height_caps(
+            &self.plots,
+            &self.wall_circle,
+            &self.city_roads,
+            self.town_center,
+            &self.config,
+        );
+    }
+
+    /// Work out the wells, bakeries, apothecaries, churches and school needed by the
+    /// population of the town, and set aside a plot near the gates of the main town for the
+    /// training yard of the guard.
+    pub fn plan_amenities(&mut self) {
+        let house_plots: Vec<(usize, DistrictUse, BlockColumnCoord)> = (0..self.plots.len())
+            .filter(|index| !self.config.is_skipped_plot(*index))
+            .map(|index| (index, self.plot_use(index), geometry::polygon_center(&self.plots[index].polygon())))
+            .filter(|(_, district_use, _)| !matches!(district_use, DistrictUse::Green | DistrictUse::Plaza))
+            .collect();
+        let gates: Vec<BlockColumnCoord> = wall::gates(&self.wall_circle, &self.city_roads).into_iter()
+            .map(|(gate, _)| gate)
+            .collect();
+        let wealthy_plots: Vec<usize> = (0..self.plots.len())
+            .filter(|index| {
+                self.wealthiest_district.is_some() && self.plot_districts.get(*index) == self.wealthiest_district.as_ref()
+            })
+            .collect();
+        self.amenities = amenity::plan_amenities(
+            &house_plots,
+            self.town_center,
+            &gates,
+            &wealthy_plots,
+            &self.config,
+        );
+    }
+
+    /// Join narrow plots next to each other along the same street into rows of houses,
+    /// with party walls between them, and pair up the small plots left over into
+    /// semi-detached houses.
+    pub fn plan_row_houses(&mut self) {
+        let is_house_plot = |index| {
+            !self.config.is_skipped_plot(index)
+                && !matches!(self.plot_use(index), DistrictUse::Green | DistrictUse::Plaza)
+        };
+        let mut party_walls = plot::row_house_party_walls(
+            &self.plots,
+            self.config.row_house_frontage_max,
+            is_house_plot,
+        );
+        let semi_detached_pairs = plot::semi_detached_pairs(
+            &self.plots,
+            self.config.semi_detached_area_max,
+            |index| is_house_plot(index) && party_walls[index].is_empty(),
+        );
+        for (index, pair) in semi_detached_pairs.iter().enumerate() {
+            if let Some((_, party_wall)) = pair {
+                party_walls[index].push(*party_wall);
+            }
+        }
+        self.row_house_party_walls = party_walls;
+        self.semi_detached_pairs = semi_detached_pairs;
+    }
+
+    /// Find sites downstream and downwind of town for the tannery and the dyeworks.
+    pub fn plan_nuisance_industries(&mut self) {
+        if !self.config.nuisance_industries || self.is_nether() {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let wall_circles = self.wall_circles();
+        let taken: Vec<BlockColumnCoord> = self.windmill.iter().map(|windmill| windmill.center)
+            .chain(self.lumber_camp.iter().map(|camp| camp.center))
+            .chain(self.mine.iter().map(|mine| mine.site.into()))
+            .chain(self.harbor.iter().map(|harbor| harbor.quay[harbor.quay.len() / 2].coordinates.into()))
+            .chain(self.harbor.iter().flat_map(|harbor| harbor.fisheries.iter().map(|fishery| fishery.front_middle)))
+            .chain(self.harbor.iter().flat_map(|harbor| harbor.shipyard.iter().map(|shipyard| shipyard.front_middle)))
+            .chain(self.lighthouse.iter().map(|lighthouse| lighthouse.center))
+            .chain(self.viewpoints.iter().map(|viewpoint| viewpoint.center))
+            .collect();
+
+        self.nuisance_industries = zoning::plan_nuisance_industries(
+            features,
+            &self.wall_circle,
+            &self.city_roads,
+            self.town_center,
+            &wall_circles,
+            &self.fields,
+            &taken,
+        );
+    }
+
+    /// Find a patch of forest near town for a lumber camp, with a road from it.
+    pub fn plan_lumber_camp(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let areas = self.areas.as_ref().expect(AREAS_MISSING);
+
+        let wall_circles = self.wall_circles();
+
+        self.lumber_camp = forestry::plan_lumber_camp(
+            features,
+            &areas.woodcutters,
+            &areas.suitability,
+            &self.fields,
+            &wall_circles,
+            self.town_center,
+            &self.country_roads,
+        );
+    }
+
+    /// Find room for pastures outside the towns, clear of the fields, the windmill and the
+    /// lumber camp, with paths to the country roads.
+    pub fn plan_pastures(&mut self) {
+        // Nothing grazes without grass, as in the Nether
+        if self.is_nether() {
+            return;
+        }
+
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let areas = self.areas.as_ref().expect(AREAS_MISSING);
+
+        let wall_circles = town_wall_circles(&self.wall_circle, &self.outlying_towns);
+        let landmarks: Vec<BlockColumnCoord> = self.windmill.iter().map(|windmill| windmill.center)
+            .chain(self.lumber_camp.iter().map(|camp| camp.center))
+            .collect();
+
+        self.pastures = pasture::plan_pastures(
+            features,
+            areas,
+            &wall_circles,
+            &self.fields,
+            &landmarks,
+            &self.country_roads,
+            self.config.max_pastures,
+            &mut self.rng,
+        );
+    }
+
+    /// Find high points near town with a view, for cairns, shrines and beacons.
+    pub fn plan_viewpoints(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let wall_circles = town_wall_circles(&self.wall_circle, &self.outlying_towns);
+        let landmarks: Vec<BlockColumnCoord> = self.windmill.iter().map(|windmill| windmill.center)
+            .chain(self.lumber_camp.iter().map(|camp| camp.center))
+            .chain(self.mine.iter().map(|mine| mine.site.into()))
+            .collect();
+
+        self.viewpoints = viewpoint::plan_viewpoints(
+            features,
+            &wall_circles,
+            self.town_center,
+            &self.country_roads,
+            &landmarks,
+            &mut self.rng,
+        );
+    }
+
+    /// In the cliff style, find places in the cliffs in and around the main town for
+    /// dwellings carved into the rock.
+    pub fn plan_cliff_dwellings(&mut self) {
+        if self.config.style != SettlementStyle::Cliff {
+            return;
+        }
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        self.cliff_dwellings = cliff_dwelling::plan_cliff_dwellings(
+            features,
+            &self.wall_circle,
+            self.town_center,
+            self.config.cliff_dwellings_max,
+        );
+        if self.cliff_dwellings.is_empty() {
+            warn!("Found no cliffs to carve dwellings into.");
+        } else {
+            info!("Planned {} dwellings in the cliffs.", self.cliff_dwellings.len());
+        }
+    }
+
+    /// Find hilltops within sight of each other between neighbouring towns, for chains of signal pyres.
+    pub fn plan_signal_chains(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+
+        let town_centers: Vec<BlockColumnCoord> = std::iter::once(self.town_center)
+            .chain(self.outlying_towns.iter().map(|town| town.center))
+            .collect();
+        let wall_circles = self.wall_circles();
+        let taken: Vec<BlockColumnCoord> = self.windmill.iter().map(|windmill| windmill.center)
+            .chain(self.lumber_camp.iter().map(|camp| camp.center))
+            .chain(self.mine.iter().map(|mine| mine.site.into()))
+            .chain(self.viewpoints.iter().map(|viewpoint| viewpoint.center))
+            .collect();
+
+        let mut signal_pyres = viewpoint::plan_signal_chains(
+            features,
+            &town_centers,
+            &wall_circles,
+            &self.country_roads,
+            &taken,
+        );
+        self.viewpoints.append(&mut signal_pyres);
+    }
+
+    /// Find where the country roads enter the area, for putting up toll stations there.
+    pub fn plan_toll_stations(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        self.toll_stations = toll_station::plan_toll_stations(&self.country_roads, features);
+        info!("Planned {} toll stations.", self.toll_stations.len());
+    }
+}
@@ -0,0 +1,44 @@
+//! Stage surveying the local materials, for the block palette.
+
+use log::info;
+
+use crate::bathhouse;
+use crate::block_palette;
+use crate::wall::DefenceKind;
+
+use super::{GenerationContext, FEATURES_MISSING};
+
+impl GenerationContext {
+    /// Survey the area inside and around town, to find local materials,
+    /// and set up the block palette accordingly.
+    pub fn survey_materials(&mut self) {
+        let features = self.features.as_ref().expect(FEATURES_MISSING);
+        let survey = block_palette::survey_materials(
+            &self.excerpt,
+            features,
+            &self.wall_circle,
+            self.is_nether(),
+            self.is_stilt_village(),
+        );
+
+        let lava_below_town = bathhouse::lava_below_town(&self.excerpt, features, &self.wall_circle, self.town_center);
+        if let Some(lava) = lava_below_town {
+            info!("Found lava {:?} safely below the town.", lava);
+        }
+
+        // With hardly any exposed stone around, defend the town with earthworks instead of masonry.
+        let defence = self.config.defence.unwrap_or(
+            if survey.is_stone_scarce {
+                DefenceKind::Earthwork
+            } else {
+                DefenceKind::StoneWall
+            }
+        );
+        info!("Decided on {:?} for the town defences.", defence);
+
+        self.block_palette = survey.palette;
+        self.wood_available = survey.wood_available;
+        self.defence = defence;
+        self.lava_below_town = lava_below_town;
+    }
+}
@@ -0,0 +1,241 @@
+//! Export of the city plan as GeoJSON, for viewing and editing in GIS tools.
+//!
+//! Block coordinates are used directly as planar coordinates, with x as easting
+//! and z negated as northing, so that north is up like on an in-game map.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use image::GrayImage;
+use mcprogedit::coordinates::BlockColumnCoord;
+use serde_json::{json, Value};
+
+use crate::config::GenerationConfig;
+use crate::generation_context::GenerationContext;
+use crate::pathfinding::{RoadClass, RoadNodeKind, RoadPath};
+
+/// Collect the roads, town walls, water and districts of the plan held by `context`,
+/// as a GeoJSON feature collection.
+pub fn feature_collection(context: &GenerationContext) -> Value {
+    let mut features = Vec::new();
+
+    // Roads, each as wide as it is built
+    let config = &context.scaled_config;
+    for (index, path) in context.city_roads.iter().enumerate() {
+        features.push(road(path, config, context.city_road_names.get(index)));
+    }
+    for (index, path) in context.streets.iter().enumerate() {
+        features.push(road(path, config, context.street_names.get(index)));
+    }
+    for path in &context.country_roads {
+        features.push(road(path, config, None));
+    }
+    for town in &context.outlying_towns {
+        for path in town.city_roads.iter().chain(town.streets.iter()) {
+            features.push(road(path, config, None));
+        }
+    }
+
+    // Town walls
+    let town_name = if context.town_name.is_empty() { None } else { Some(&context.town_name) };
+    features.push(wall(&context.wall_circle, context.scaled_config.wall_width, town_name));
+    for town in &context.outlying_towns {
+        features.push(wall(&town.wall_circle, context.scaled_config.wall_width, None));
+    }
+
+    // Districts
+    for (index, district) in context.districts.iter().enumerate() {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": [ring(district)],
+            },
+            "properties": {
+                "class": "district",
+                "index": index,
+                "wealthiest": context.wealthiest_district == Some(index),
+                "use": context.district_uses.get(index),
+            },
+        }));
+    }
+
+    // Water
+    if let Some(map_features) = &context.features {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "MultiPolygon",
+                "coordinates": water_polygons(&map_features.water),
+            },
+            "properties": {
+                "class": "water",
+            },
+        }));
+    }
+
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+/// Write the plan held by `context` as GeoJSON to the file at `path`.
+pub fn write_geojson(context: &GenerationContext, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|error| format!("{}", error))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &feature_collection(context))
+        .map_err(|error| format!("{}", error))
+}
+
+fn position(x: i64, z: i64) -> Value {
+    json!([x, -z])
+}
+
+/// Closed ring of positions around the given polygon.
+fn ring(polygon: &[BlockColumnCoord]) -> Vec<Value> {
+    let mut ring: Vec<Value> = polygon.iter().map(|point| position(point.0, point.1)).collect();
+    if polygon.first() != polygon.last() {
+        if let Some(first) = polygon.first() {
+            ring.push(position(first.0, first.1));
+        }
+    }
+    ring
+}
+
+/// The kind of road, as named in the exported properties.
+fn road_kind(class: RoadClass) -> &'static str {
+    match class {
+        RoadClass::Highway => "country_road",
+        RoadClass::CityRoad => "city_road",
+        RoadClass::Street => "street",
+        RoadClass::Alley => "alley",
+        RoadClass::Path => "path",
+    }
+}
+
+fn road(path: &RoadPath, config: &GenerationConfig, name: Option<&String>) -> Value {
+    let is_bridge = path.iter()
+        .any(|node| node.kind == RoadNodeKind::WoodenSupport || node.kind == RoadNodeKind::StoneSupport);
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": path.iter()
+                .map(|node| position(node.coordinates.0, node.coordinates.2))
+                .collect::<Vec<_>>(),
+        },
+        "properties": {
+            "class": "road",
+            "kind": road_kind(path.class),
+            "width": path.class.style(config).width,
+            "name": name,
+            "bridge": is_bridge,
+        },
+    })
+}
+
+fn wall(circle: &[BlockColumnCoord], width: i64, town_name: Option<&String>) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": ring(circle),
+        },
+        "properties": {
+            "class": "wall",
+            "width": width,
+            "name": town_name,
+        },
+    })
+}
+
+/// The water, as one rectangle for each run of water along the x axis.
+fn water_polygons(water: &GrayImage) -> Vec<Value> {
+    let (x_len, z_len) = water.dimensions();
+    let mut polygons = Vec::new();
+
+    for z in 0..z_len {
+        let mut run_start = None;
+        for x in 0..=x_len {
+            let is_water = x < x_len && water[(x, z)] == image::Luma([255u8]);
+            match (run_start, is_water) {
+                (None, true) => run_start = Some(x),
+                (Some(start), false) => {
+                    let (x0, x1) = (start as i64, x as i64);
+                    let (z0, z1) = (z as i64, z as i64 + 1);
+                    // Counterclockwise, once z is negated
+                    polygons.push(json!([[
+                        position(x0, z0),
+                        position(x0, z1),
+                        position(x1, z1),
+                        position(x1, z0),
+                        position(x0, z0),
+                    ]]));
+                    run_start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    polygons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mcprogedit::coordinates::BlockCoord;
+
+    use crate::pathfinding::RoadNode;
+
+    #[test]
+    fn water_polygons_per_run() {
+        // Water at (1, 0) and (2, 0), and at (0, 1)
+        let mut water = GrayImage::new(3, 2);
+        water.put_pixel(1, 0, image::Luma([255u8]));
+        water.put_pixel(2, 0, image::Luma([255u8]));
+        water.put_pixel(0, 1, image::Luma([255u8]));
+
+        assert_eq!(
+            vec![
+                json!([[[1, 0], [1, -1], [3, -1], [3, 0], [1, 0]]]),
+                json!([[[0, -1], [0, -2], [1, -2], [1, -1], [0, -1]]]),
+            ],
+            water_polygons(&water),
+        );
+    }
+
+    #[test]
+    fn water_polygons_without_water() {
+        assert_eq!(Vec::<Value>::new(), water_polygons(&GrayImage::new(4, 4)));
+    }
+
+    #[test]
+    fn road_properties() {
+        let config = GenerationConfig::default();
+        let path = RoadPath::new(
+            vec![
+                RoadNode { coordinates: BlockCoord(0, 64, 0), kind: RoadNodeKind::Ground },
+                RoadNode { coordinates: BlockCoord(4, 64, 2), kind: RoadNodeKind::WoodenSupport },
+            ],
+            RoadClass::Alley,
+        );
+        let name = String::from("Fish Lane");
+
+        let feature = road(&path, &config, Some(&name));
+
+        assert_eq!(json!([[0, 0], [4, -2]]), feature["geometry"]["coordinates"]);
+        assert_eq!(
+            json!({
+                "class": "road",
+                "kind": "alley",
+                "width": config.alley_width,
+                "name": "Fish Lane",
+                "bridge": true,
+            }),
+            feature["properties"],
+        );
+    }
+}
@@ -3,6 +3,7 @@ use crate::plot::{Plot, PlotEdge, PlotEdgeKind};
 use crate::types::Snake;
 use image::GrayImage;
 use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::positioning::Surface4;
 use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::f32::consts::PI;
@@ -80,6 +81,7 @@ pub enum EdgeKind {
     Road,
     Street,
     Wall,
+    Canal,
 }
 
 #[derive(Clone, Copy, Debug, Ord, PartialEq, PartialOrd, Eq)]
@@ -136,6 +138,9 @@ impl LandUsageGraph {
                         EdgeKind::Wall => PlotEdgeKind::Wall {
                             width: *width as usize,
                         },
+                        EdgeKind::Canal => PlotEdgeKind::Canal {
+                            width: *width as usize,
+                        },
                     };
                     edges.push(PlotEdge {
                         kind,
@@ -265,19 +270,22 @@ pub fn extract_blocks(graph: &LandUsageGraph) -> Vec<Vec<BlockColumnCoord>> {
     let mut areas = Vec::<Vec<BlockColumnCoord>>::new();
 
     // Populate queue
-    //println!("Populating queue…");
-    for edge in graph.edges() {
+    trace!("Populating queue…");
+    // Sort the edges, so that the resulting areas do not depend on hash map iteration order.
+    let mut edges = graph.edges();
+    edges.sort_by_key(|edge| (edge.0 .0, edge.0 .1, edge.1 .0, edge.1 .1));
+    for edge in edges {
         queue.push_back(edge);
     }
-    //println!("Queue populated with {} edges.", queue.len());
+    trace!("Queue populated with {} edges.", queue.len());
 
     // For each element in queue:
     while let Some(edge) = queue.pop_front() {
         if visited.contains(&edge) {
-            //println!("Already visited edge {:?}", edge);
+            trace!("Already visited edge {:?}", edge);
             continue;
         } else {
-            //println!("Visiting edge {:?} for the first time", edge);
+            trace!("Visiting edge {:?} for the first time", edge);
             visited.insert(edge);
         }
 
@@ -295,11 +303,11 @@ pub fn extract_blocks(graph: &LandUsageGraph) -> Vec<Vec<BlockColumnCoord>> {
         loop {
             let next_vertex = match graph.get_left_turn(current_edge) {
                 None => {
-                    //println!("No next vertex from {:?}", current_edge);
+                    trace!("No next vertex from {:?}", current_edge);
                     break;
                 }
                 Some(vertex) => {
-                    //println!("Next vertex from {:?} is {:?}", current_edge, vertex);
+                    trace!("Next vertex from {:?} is {:?}", current_edge, vertex);
                     vertex
                 }
             };
@@ -308,17 +316,15 @@ pub fn extract_blocks(graph: &LandUsageGraph) -> Vec<Vec<BlockColumnCoord>> {
             visited.insert(next_edge);
 
             if visited_in_area.contains(&next_edge) {
-                /*
-                println!(
+                trace!(
                     "We found a loop (size {}) when starting from edge {:?}, that loops from {:?}",
                     area.len(),
                     first_edge,
                     next_edge,
                 );
-                */
 
                 if first_edge == next_edge {
-                    //println!("The loop is accepted.");
+                    trace!("The loop is accepted.");
                     areas.push(area);
                 }
                 break;
@@ -385,7 +391,7 @@ pub fn add_intersection_points(roads: &mut Vec<RoadPath>, snake: &mut Snake) {
             }
             new_road.push(road_segment[1]);
         }
-        new_roads.push(new_road);
+        new_roads.push(RoadPath::new(new_road, road.class));
     }
 
     *roads = new_roads;
@@ -554,6 +560,88 @@ pub fn euclidean_distance_3d(a: BlockCoord, b: BlockCoord) -> f32 {
     .sqrt()
 }
 
+/// Returns the edges of the relative neighbourhood graph of `points`, as pairs of indices.
+///
+/// Two points are neighbours if no third point is closer to both of them
+/// than they are to each other.
+pub fn relative_neighbourhood_graph(points: &[BlockColumnCoord]) -> Vec<(usize, usize)> {
+    let mut edges = Vec::new();
+    for a in 0..points.len() {
+        for b in a + 1..points.len() {
+            let distance = euclidean_distance(points[a], points[b]);
+            let is_blocked = (0..points.len())
+                .filter(|c| *c != a && *c != b)
+                .any(|c| {
+                    euclidean_distance(points[a], points[c]) < distance
+                        && euclidean_distance(points[b], points[c]) < distance
+                });
+            if !is_blocked {
+                edges.push((a, b));
+            }
+        }
+    }
+    edges
+}
+
+/// The four cardinal directions.
+pub fn directions() -> [Surface4; 4] {
+    [Surface4::North, Surface4::South, Surface4::East, Surface4::West]
+}
+
+/// Unit step in the given direction.
+pub fn offset(direction: Surface4) -> BlockCoord {
+    match direction {
+        Surface4::North => BlockCoord(0, 0, -1),
+        Surface4::South => BlockCoord(0, 0, 1),
+        Surface4::East => BlockCoord(1, 0, 0),
+        Surface4::West => BlockCoord(-1, 0, 0),
+    }
+}
+
+/// Unit steps in the given direction from the given column.
+pub fn step(column: BlockColumnCoord, direction: Surface4, distance: i64) -> BlockColumnCoord {
+    let offset = offset(direction);
+    BlockColumnCoord(column.0 + offset.0 * distance, column.1 + offset.2 * distance)
+}
+
+/// The average of the corners of the polygon.
+pub fn polygon_center(polygon: &[BlockColumnCoord]) -> BlockColumnCoord {
+    let count = polygon.len().max(1) as i64;
+    BlockColumnCoord(
+        polygon.iter().map(|point| point.0).sum::<i64>() / count,
+        polygon.iter().map(|point| point.1).sum::<i64>() / count,
+    )
+}
+
+/// Whether the offset from the middle of a disc with the given radius is within the disc.
+pub fn is_in_disc((dx, dz): (i64, i64), radius: i64) -> bool {
+    (dx * dx + dz * dz) as f32 <= (radius as f32 + 0.5).powi(2)
+}
+
+/// The columns of a disc with the given center and radius.
+pub fn disc(center: BlockColumnCoord, radius: i64) -> Vec<(i64, i64)> {
+    let mut columns = Vec::new();
+    for dx in -radius..=radius {
+        for dz in -radius..=radius {
+            if is_in_disc((dx, dz), radius) {
+                columns.push((center.0 + dx, center.1 + dz));
+            }
+        }
+    }
+    columns
+}
+
+/// The columns of a square with the given center and half width.
+pub fn square(center: BlockColumnCoord, half_width: i64) -> Vec<(i64, i64)> {
+    let mut columns = Vec::new();
+    for x in center.0 - half_width..=center.0 + half_width {
+        for z in center.1 - half_width..=center.1 + half_width {
+            columns.push((x, z));
+        }
+    }
+    columns
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -625,6 +713,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn relative_neighbourhood_graph_of_triangle() {
+        // The long edge of an obtuse triangle is blocked by the third point.
+        assert_eq!(
+            vec![(0, 1), (1, 2)],
+            relative_neighbourhood_graph(&[
+                BlockColumnCoord(0, 0),
+                BlockColumnCoord(4, 1),
+                BlockColumnCoord(8, 0),
+            ]),
+        );
+    }
+
     #[test]
     fn angle_0_deg() {
         assert_eq!(
@@ -672,4 +773,45 @@ mod tests {
             ),
         );
     }
+
+    #[test]
+    fn step_north() {
+        assert_eq!(
+            BlockColumnCoord(3, -2),
+            step(BlockColumnCoord(3, 1), Surface4::North, 3),
+        );
+    }
+
+    #[test]
+    fn step_backwards_east() {
+        assert_eq!(
+            BlockColumnCoord(1, 1),
+            step(BlockColumnCoord(3, 1), Surface4::East, -2),
+        );
+    }
+
+    #[test]
+    fn disc_of_radius_0() {
+        assert_eq!(vec![(5, -5)], disc(BlockColumnCoord(5, -5), 0));
+    }
+
+    #[test]
+    fn disc_of_radius_1_fills_its_square() {
+        assert_eq!(square(BlockColumnCoord(0, 0), 1), disc(BlockColumnCoord(0, 0), 1));
+    }
+
+    #[test]
+    fn disc_of_radius_2_cuts_corners() {
+        let columns = disc(BlockColumnCoord(0, 0), 2);
+        assert_eq!(21, columns.len());
+        assert!(columns.contains(&(2, 1)));
+        assert!(!columns.contains(&(2, 2)));
+    }
+
+    #[test]
+    fn disc_edge() {
+        assert!(is_in_disc((3, 0), 3));
+        assert!(!is_in_disc((4, 0), 3));
+        assert!(!is_in_disc((3, 2), 3));
+    }
 }
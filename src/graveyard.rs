@@ -0,0 +1,124 @@
+//! Graveyards beside the churches: Rows of graves, each with a headstone carrying the name
+//! of someone from one of the households in town, and the years they lived.
+
+use mcprogedit::block::{Block, WallOrRotatedOnFloor};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::positioning::Surface4;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::naming;
+use crate::signs;
+use crate::types::Snake;
+
+/// Distance between the graves along a row, and between the rows.
+const GRAVE_SPACING: (i64, i64) = (2, 4);
+/// Shortest distance from the church to a grave, leaving a path around the church.
+const CHURCH_CLEARANCE: usize = 2;
+/// Earliest and latest year of death.
+const DEATH_YEARS: (u32, u32) = (1250, 1400);
+/// Shortest and longest lives.
+const LIFE_SPANS: (u32, u32) = (3, 85);
+
+/// A grave, with its headstone.
+#[derive(Clone, Debug)]
+pub struct Grave {
+    /// Where the headstone stands.
+    pub coordinates: BlockCoord,
+    pub given_name: String,
+    pub family_name: String,
+    pub born: u32,
+    pub died: u32,
+}
+
+/// Lay out graves on the free ground of the church plot, around the church with the given
+/// footprint, for members of the given households. Returns the graves, laid out in rows
+/// with the headstones to the north, but not yet built.
+pub fn plan_graves(
+    plot: &Snake,
+    church_footprint: &[BlockColumnCoord],
+    features: &Features,
+    family_names: &[String],
+    graves_max: usize,
+    rng: &mut StdRng,
+) -> Vec<Grave> {
+    if family_names.is_empty() {
+        return Vec::new();
+    }
+    let (x_len, z_len) = features.dimensions();
+    let ground_at = |column: BlockColumnCoord| -> Option<i64> {
+        if column.0 < 0 || column.1 < 0 || column.0 >= x_len as i64 || column.1 >= z_len as i64 {
+            return None;
+        }
+        let (x, z) = (column.0 as u32, column.1 as u32);
+        if features.water[(x, z)] == image::Luma([255u8]) {
+            return None;
+        }
+        let image::Luma([y]) = features.terrain[(x, z)];
+        Some(y as i64)
+    };
+    let is_free = |column: BlockColumnCoord| {
+        geometry::point_position_relative_to_polygon(column, plot) == InOutSide::Inside
+            && church_footprint.iter()
+                .all(|built| geometry::manhattan_distance(*built, column) > CHURCH_CLEARANCE)
+    };
+
+    let (x_min, x_max) = (plot.iter().map(|point| point.0).min(), plot.iter().map(|point| point.0).max());
+    let (z_min, z_max) = (plot.iter().map(|point| point.1).min(), plot.iter().map(|point| point.1).max());
+    let (x_min, x_max, z_min, z_max) = match (x_min, x_max, z_min, z_max) {
+        (Some(x_min), Some(x_max), Some(z_min), Some(z_max)) => (x_min, x_max, z_min, z_max),
+        _ => return Vec::new(),
+    };
+
+    let mut graves = Vec::new();
+    for z in (z_min..=z_max).step_by(GRAVE_SPACING.1 as usize) {
+        for x in (x_min..=x_max).step_by(GRAVE_SPACING.0 as usize) {
+            if graves.len() >= graves_max {
+                return graves;
+            }
+            // The headstone, and the grave in front of it, on level ground
+            let columns = [BlockColumnCoord(x, z), BlockColumnCoord(x, z + 1), BlockColumnCoord(x, z + 2)];
+            let heights: Vec<Option<i64>> = columns.iter().map(|column| ground_at(*column)).collect();
+            let is_level = heights.iter().all(|height| height.is_some() && *height == heights[0]);
+            if !is_level || !columns.iter().all(|column| is_free(*column)) {
+                continue;
+            }
+
+            let died = rng.gen_range(DEATH_YEARS.0..=DEATH_YEARS.1);
+            let born = died - rng.gen_range(LIFE_SPANS.0..=LIFE_SPANS.1);
+            graves.push(Grave {
+                coordinates: BlockCoord(x, heights[0].unwrap(), z),
+                given_name: naming::given_name(rng),
+                family_name: family_names[rng.gen_range(0..family_names.len())].clone(),
+                born,
+                died,
+            });
+        }
+    }
+    graves
+}
+
+/// Build the grave: A headstone with the name and years on a sign in front of it,
+/// over a grave of coarse dirt.
+pub fn build_grave(excerpt: &mut WorldExcerpt, grave: &Grave) {
+    let headstone = grave.coordinates;
+    let front = geometry::offset(Surface4::South);
+    excerpt.set_block_at(headstone, Block::StoneBricks);
+    excerpt.set_block_at(
+        headstone + front,
+        signs::sign(
+            WallOrRotatedOnFloor::Wall(Surface4::North),
+            &[
+                grave.given_name.clone(),
+                grave.family_name.clone(),
+                format!("{} - {}", grave.born, grave.died),
+            ],
+        ),
+    );
+    for length in 1..=2 {
+        excerpt.set_block_at(headstone + front * length - BlockCoord(0, 1, 0), Block::CoarseDirt);
+    }
+}
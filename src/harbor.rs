@@ -0,0 +1,870 @@
+//! Harbour on the shore next to town: Piers on wooden supports out to deep water,
+//! boats moored along the piers, a quay road along the shore with a crane and a loading
+//! area, and a fish market, with drying racks and smokehouses further along the shore,
+//! and a shipyard on a beach nearby for the larger towns.
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::info;
+use mcprogedit::block::{Block, Door, DoorHalf, Hinge};
+use mcprogedit::colour::Colour;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{DoorMaterial, FenceMaterial, Material, WoodMaterial};
+use mcprogedit::positioning::{Surface2, Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::block_palette::BlockPalette;
+use crate::config::GenerationConfig;
+use crate::crane::{self, Crane, LoadingArea};
+use crate::features::Features;
+use crate::geometry::{self, step};
+use crate::pathfinding::{self, RoadClass, RoadNode, RoadNodeKind, RoadPath};
+use crate::plot::{Plot, PlotEdge, PlotEdgeKind};
+use crate::road::{self, RoadStyle};
+use crate::shipyard::{self, Shipyard};
+use crate::tree;
+use crate::types::Snake;
+
+/// Farthest from the town wall the harbour may be.
+const HARBOR_REACH: u8 = 24;
+/// Water depth needed for boats to moor.
+const DEEP_WATER_DEPTH: u8 = 3;
+/// Longest distance from the shore out to deep water, for a pier.
+const PIER_LENGTH_MAX: i64 = 20;
+/// How far the piers continue past the point where the deep water starts.
+const PIER_EXTENSION: i64 = 4;
+/// Distance between the wooden supports of a pier.
+const PIER_SUPPORT_SPACING: i64 = 4;
+/// Distance between neighbouring piers, along the quay.
+const PIER_SPACING: i64 = 8;
+/// Number of piers on each side of the middle pier.
+const SIDE_PIER_COUNT: i64 = 1;
+/// Width of the pier decks.
+const PIER_WIDTH: i64 = 2;
+/// Width of the quay road.
+const QUAY_WIDTH: i64 = 3;
+/// Size of the fish market, along and across the quay.
+const MARKET_SIZE: (i64, i64) = (9, 6);
+/// Largest difference in ground height under the fish market.
+const MARKET_SLOPE_MAX: i64 = 2;
+/// Length and width of the moored boats.
+const BOAT_SIZE: (i64, i64) = (5, 3);
+/// Largest number of fisheries along the shore.
+const FISHERY_COUNT_MAX: usize = 4;
+/// Farthest from the middle of the quay a fishery may be.
+const FISHERY_REACH: usize = 40;
+/// Size of a fishery, along and across the shore.
+const FISHERY_SIZE: (i64, i64) = (9, 5);
+/// Largest difference in ground height under a fishery.
+const FISHERY_SLOPE_MAX: i64 = 2;
+/// Distance kept clear around the quay, the piers and the roads, when placing fisheries.
+const FISHERY_CLEARANCE: u8 = 2;
+/// Distance along the quay from its middle to the crane, clear of the fish market.
+const CRANE_OFFSET: i64 = MARKET_SIZE.0 / 2 + 2;
+/// Height of the crane mast.
+const CRANE_MAST_HEIGHT: i64 = 5;
+/// Shortest and longest arm of the crane, reaching out over the water.
+const CRANE_ARM_LENGTH: (i64, i64) = (2, 5);
+/// Size of the loading area behind the crane, along and across the quay.
+const LOADING_AREA_SIZE: (i64, i64) = (3, 4);
+/// Farthest from the middle of the quay the shipyard may be.
+const SHIPYARD_REACH: usize = 48;
+/// Largest difference in ground height under the shipyard.
+const SHIPYARD_SLOPE_MAX: i64 = 3;
+
+/// A boat moored by a pier.
+#[derive(Clone, Copy, Debug)]
+pub struct Boat {
+    /// Middle of the hull, at the water surface.
+    pub center: BlockCoord,
+    /// Direction of the bow.
+    pub heading: Surface4,
+}
+
+/// Drying racks and a smokehouse, on the shore near the harbour.
+#[derive(Clone, Copy, Debug)]
+pub struct Fishery {
+    /// Middle of the front of the fishery, at the water's edge.
+    pub front_middle: BlockColumnCoord,
+    /// Y coordinate of the level ground of the fishery.
+    pub floor_y: i64,
+    /// Direction from the shore out towards the water.
+    pub seaward: Surface4,
+}
+
+/// A harbour, with its piers, boats, quay, fish market and fisheries.
+#[derive(Clone, Debug)]
+pub struct Harbor {
+    /// Direction from the shore out towards the water.
+    pub seaward: Surface4,
+    /// Road along the shore.
+    pub quay: RoadPath,
+    /// Road from the quay to the nearest country road, if one was found.
+    pub access: Option<RoadPath>,
+    /// The piers, each from the quay out to deep water.
+    pub piers: Vec<RoadPath>,
+    pub boats: Vec<Boat>,
+    /// Plot for the fish market, just inland from the quay.
+    pub market: Option<Plot>,
+    /// Crane on the quay, beside the fish market.
+    pub crane: Option<Crane>,
+    /// Loading area just inland from the crane.
+    pub loading_area: Option<LoadingArea>,
+    /// Drying racks and smokehouses along the shore.
+    pub fisheries: Vec<Fishery>,
+    /// Shipyard on a beach near the quay.
+    pub shipyard: Option<Shipyard>,
+}
+
+/// Find a stretch of shore close to the town, with deep enough water for boats,
+/// and lay out a harbour there, with a shipyard if `with_shipyard` is set.
+pub fn plan_harbor(
+    features: &Features,
+    wall_circle: &Snake,
+    town_center: BlockColumnCoord,
+    country_roads: &[RoadPath],
+    with_shipyard: bool,
+) -> Option<Harbor> {
+    let (x_len, z_len) = features.dimensions();
+
+    let mut town = GrayImage::new(x_len as u32, z_len as u32);
+    geometry::draw_area(&mut town, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    let near_town = imageproc::morphology::dilate(&town, Norm::LInf, HARBOR_REACH);
+
+    // Shore columns near town, with the direction towards the water.
+    let mut candidates: Vec<(BlockColumnCoord, Surface4)> = Vec::new();
+    for x in 1..x_len as i64 - 1 {
+        for z in 1..z_len as i64 - 1 {
+            let column = BlockColumnCoord(x, z);
+            if is_water(features, column) != Some(false)
+                || near_town[(x as u32, z as u32)] != image::Luma([255u8])
+                || town[(x as u32, z as u32)] == image::Luma([255u8])
+            {
+                continue;
+            }
+            for seaward in &[Surface4::North, Surface4::East, Surface4::South, Surface4::West] {
+                if is_water(features, step(column, *seaward, 1)) == Some(true) {
+                    candidates.push((column, *seaward));
+                }
+            }
+        }
+    }
+    candidates.sort_by_key(|(column, _)| geometry::manhattan_distance(*column, town_center));
+
+    let mut harbor = candidates.into_iter()
+        .find_map(|(shore, seaward)| harbor_at(features, &town, shore, seaward))?;
+
+    // Road from the middle of the quay to the nearest country road
+    let quay_middle = harbor.quay[harbor.quay.len() / 2].coordinates;
+    let nearest_road_node = country_roads.iter()
+        .flat_map(|road| road.iter())
+        .min_by_key(|node| geometry::manhattan_distance(quay_middle.into(), node.coordinates.into()));
+    if let Some(node) = nearest_road_node {
+        harbor.access = pathfinding::road_path(
+            quay_middle,
+            node.coordinates,
+            &features.terrain,
+            Some(&features.water),
+        )
+        .map(|access| access.with_class(RoadClass::CityRoad));
+    }
+
+    harbor.fisheries = plan_fisheries(features, &town, &harbor);
+    if with_shipyard {
+        harbor.shipyard = plan_shipyard(features, &town, &harbor);
+    }
+
+    info!(
+        "Planned a harbour with {} piers, {} boats, {} fisheries and {} shipyard, facing {:?}.",
+        harbor.piers.len(),
+        harbor.boats.len(),
+        harbor.fisheries.len(),
+        if harbor.shipyard.is_some() { "a" } else { "no" },
+        harbor.seaward,
+    );
+    Some(harbor)
+}
+
+/// Lay out a harbour with its middle pier going out from `shore`, towards `seaward`.
+fn harbor_at(
+    features: &Features,
+    town: &GrayImage,
+    shore: BlockColumnCoord,
+    seaward: Surface4,
+) -> Option<Harbor> {
+    let along = seaward.rotated_90_cw();
+    let landward = seaward.opposite();
+    let middle_pier = plan_pier(features, shore, seaward)?;
+
+    // The quay follows the shore, as far as there is land.
+    let quay_center = step(shore, landward, QUAY_WIDTH / 2);
+    let half_length = SIDE_PIER_COUNT * PIER_SPACING + 2;
+    let is_quay_land = |column: BlockColumnCoord| {
+        is_water(features, column) == Some(false)
+            && town[(column.0 as u32, column.1 as u32)] != image::Luma([255u8])
+    };
+    let reach = |direction: Surface4| {
+        (1..=half_length)
+            .take_while(|distance| is_quay_land(step(quay_center, direction, *distance)))
+            .last()
+            .unwrap_or(0)
+    };
+    let (reach_back, reach_forward) = (reach(along.opposite()), reach(along));
+    if reach_back + reach_forward < MARKET_SIZE.0 {
+        return None;
+    }
+    let quay: RoadPath = (-reach_back..=reach_forward)
+        .map(|distance| ground_node(features, step(quay_center, along, distance)))
+        .collect::<Option<RoadPath>>()?
+        .with_class(RoadClass::CityRoad);
+
+    // Piers at regular intervals along the quay
+    let mut piers = vec![middle_pier];
+    for index in 1..=SIDE_PIER_COUNT {
+        for direction in &[along, along.opposite()] {
+            let distance = index * PIER_SPACING;
+            if distance > reach(*direction) {
+                continue;
+            }
+            if let Some(pier) = plan_pier(features, step(quay_center, *direction, distance), seaward) {
+                piers.push(pier);
+            }
+        }
+    }
+
+    // A boat by the end of each pier, on whichever side there is room.
+    let mut boats = Vec::new();
+    for pier in &piers {
+        let end: BlockColumnCoord = pier[pier.len() - 1].coordinates.into();
+        let side_distance = PIER_WIDTH / 2 + 1 + BOAT_SIZE.1 / 2;
+        for side in &[along, along.opposite()] {
+            let center = step(step(end, landward, BOAT_SIZE.0 / 2), *side, side_distance);
+            if let Some(boat) = plan_boat(features, center, seaward) {
+                boats.push(boat);
+                break;
+            }
+        }
+    }
+
+    // A crane on the quay to either side of the fish market, with a loading area behind it
+    let (crane, loading_area) = [along, along.opposite()].iter()
+        .filter(|direction| reach(**direction) >= CRANE_OFFSET)
+        .find_map(|direction| plan_crane(features, town, step(quay_center, *direction, CRANE_OFFSET), seaward))
+        .map_or((None, None), |(crane, loading_area)| (Some(crane), Some(loading_area)));
+
+    Some(Harbor {
+        seaward,
+        quay,
+        access: None,
+        piers,
+        boats,
+        market: plan_market(features, town, quay_center, seaward),
+        crane,
+        loading_area,
+        fisheries: Vec::new(),
+        shipyard: None,
+    })
+}
+
+/// Lay out a pier from the given column on land, straight out to deep water.
+fn plan_pier(features: &Features, start: BlockColumnCoord, seaward: Surface4) -> Option<RoadPath> {
+    // Across any land, to the water's edge
+    let land_length = (0..PIER_LENGTH_MAX)
+        .take_while(|distance| is_water(features, step(start, seaward, *distance)) == Some(false))
+        .count() as i64;
+    if land_length == 0 {
+        return None;
+    }
+    let shore = ground_node(features, step(start, seaward, land_length - 1))?;
+    let deck_y = shore.coordinates.1;
+
+    let mut pier = vec![ground_node(features, start)?];
+    if land_length > 1 {
+        pier.push(shore);
+    }
+
+    // Out over the water, until it is deep enough
+    let mut deep_water_at = None;
+    let mut distance = 0;
+    while distance < PIER_LENGTH_MAX + PIER_EXTENSION {
+        distance += 1;
+        let column = step(shore.coordinates.into(), seaward, distance);
+        let depth = water_depth(features, column)?;
+        let image::Luma([bottom]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        if bottom as i64 + depth as i64 > deck_y {
+            // The deck would be under water.
+            return None;
+        }
+        if deep_water_at.is_none() && depth >= DEEP_WATER_DEPTH {
+            deep_water_at = Some(distance);
+        }
+
+        let is_end = deep_water_at.map_or(false, |deep| distance >= deep + PIER_EXTENSION);
+        if is_end || distance % PIER_SUPPORT_SPACING == 0 {
+            pier.push(RoadNode {
+                coordinates: BlockCoord(column.0, deck_y, column.1),
+                kind: RoadNodeKind::WoodenSupport,
+            });
+        }
+        if is_end {
+            return Some(RoadPath::new(pier, RoadClass::CityRoad));
+        }
+    }
+
+    None
+}
+
+/// Find room for a boat centered on `center`, pointing towards `heading`.
+fn plan_boat(features: &Features, center: BlockColumnCoord, heading: Surface4) -> Option<Boat> {
+    let (half_length, half_width) = (BOAT_SIZE.0 / 2, BOAT_SIZE.1 / 2);
+    let side = heading.rotated_90_cw();
+    for along in -half_length..=half_length {
+        for across in -half_width..=half_width {
+            let column = step(step(center, heading, along), side, across);
+            if water_depth(features, column)? < 2 {
+                return None;
+            }
+        }
+    }
+
+    let image::Luma([bottom]) = features.terrain[(center.0 as u32, center.1 as u32)];
+    let surface_y = bottom as i64 + water_depth(features, center)? as i64 - 1;
+    Some(Boat { center: BlockCoord(center.0, surface_y, center.1), heading })
+}
+
+/// Find room for the fish market, just inland from the middle of the quay.
+fn plan_market(
+    features: &Features,
+    town: &GrayImage,
+    quay_center: BlockColumnCoord,
+    seaward: Surface4,
+) -> Option<Plot> {
+    let along = seaward.rotated_90_cw();
+    let landward = seaward.opposite();
+    let front_middle = step(quay_center, landward, QUAY_WIDTH / 2 + 2);
+
+    let mut min_y = i64::MAX;
+    let mut max_y = i64::MIN;
+    for across in 0..MARKET_SIZE.1 {
+        for along_distance in -(MARKET_SIZE.0 / 2)..=MARKET_SIZE.0 / 2 {
+            let column = step(step(front_middle, landward, across), along, along_distance);
+            if is_water(features, column) != Some(false)
+                || town[(column.0 as u32, column.1 as u32)] == image::Luma([255u8])
+            {
+                return None;
+            }
+            let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+            min_y = min_y.min(y as i64);
+            max_y = max_y.max(y as i64);
+        }
+    }
+    if max_y - min_y > MARKET_SLOPE_MAX {
+        return None;
+    }
+
+    // Corners, starting with the front corners facing the quay
+    let front_left = step(front_middle, along.opposite(), MARKET_SIZE.0 / 2);
+    let front_right = step(front_middle, along, MARKET_SIZE.0 / 2);
+    let back_right = step(front_right, landward, MARKET_SIZE.1 - 1);
+    let back_left = step(front_left, landward, MARKET_SIZE.1 - 1);
+    let corner = |column: BlockColumnCoord| BlockCoord(column.0, max_y, column.1);
+
+    Some(Plot {
+        edges: vec![
+            PlotEdge {
+                kind: PlotEdgeKind::Road { width: QUAY_WIDTH as usize },
+                points: (corner(front_left), corner(front_right)),
+            },
+            PlotEdge { kind: PlotEdgeKind::Terrain, points: (corner(front_right), corner(back_right)) },
+            PlotEdge { kind: PlotEdgeKind::Terrain, points: (corner(back_right), corner(back_left)) },
+            PlotEdge { kind: PlotEdgeKind::Terrain, points: (corner(back_left), corner(front_left)) },
+        ],
+    })
+}
+
+/// Find room for a crane on the seaward edge of the quay at `quay_column`, with its arm
+/// reaching out over the water, and for a loading area just inland from it.
+fn plan_crane(
+    features: &Features,
+    town: &GrayImage,
+    quay_column: BlockColumnCoord,
+    seaward: Surface4,
+) -> Option<(Crane, LoadingArea)> {
+    let landward = seaward.opposite();
+    let base = ground_node(features, step(quay_column, seaward, QUAY_WIDTH / 2))?.coordinates;
+    if is_water(features, base.into()) != Some(false) {
+        return None;
+    }
+    let arm_length = (CRANE_ARM_LENGTH.0..=CRANE_ARM_LENGTH.1)
+        .find(|length| water_depth(features, step(base.into(), seaward, *length)).is_some())?;
+    let crane = Crane {
+        base,
+        seaward,
+        mast_height: CRANE_MAST_HEIGHT,
+        arm_length,
+        counter_arm_length: 2,
+        chain_length: 2,
+    };
+
+    let mut loading_area = LoadingArea {
+        front_middle: step(quay_column, landward, QUAY_WIDTH / 2 + 2),
+        floor_y: 0,
+        seaward,
+        size: LOADING_AREA_SIZE,
+    };
+    let mut heights = Vec::new();
+    for column in loading_area.footprint() {
+        if is_water(features, column) != Some(false)
+            || town[(column.0 as u32, column.1 as u32)] == image::Luma([255u8])
+        {
+            return None;
+        }
+        let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        heights.push(y as i64);
+    }
+    let floor_y = *heights.iter().max()?;
+    if floor_y - heights.iter().min()? > MARKET_SLOPE_MAX {
+        return None;
+    }
+    loading_area.floor_y = floor_y;
+
+    Some((crane, loading_area))
+}
+
+/// Find room for fisheries on the shore near the quay, clear of the rest of the harbour.
+fn plan_fisheries(features: &Features, town: &GrayImage, harbor: &Harbor) -> Vec<Fishery> {
+    let (x_len, z_len) = features.dimensions();
+    let quay_middle: BlockColumnCoord = harbor.quay[harbor.quay.len() / 2].coordinates.into();
+    let mut occupied = imageproc::morphology::dilate(&harbor_area(features, harbor), Norm::LInf, FISHERY_CLEARANCE);
+    let candidates = shore_columns(features, quay_middle, FISHERY_REACH);
+
+    let mut fisheries = Vec::new();
+    for (front_middle, seaward) in candidates {
+        if fisheries.len() >= FISHERY_COUNT_MAX {
+            break;
+        }
+        let footprint = fishery_footprint(front_middle, seaward);
+        let fits = footprint.iter().all(|column| {
+            is_water(features, *column) == Some(false)
+                && town[(column.0 as u32, column.1 as u32)] != image::Luma([255u8])
+                && occupied[(column.0 as u32, column.1 as u32)] != image::Luma([255u8])
+        });
+        if !fits {
+            continue;
+        }
+        let heights: Vec<i64> = footprint.iter()
+            .map(|column| {
+                let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+                y as i64
+            })
+            .collect();
+        let floor_y = *heights.iter().max().unwrap();
+        if floor_y - heights.iter().min().unwrap() > FISHERY_SLOPE_MAX {
+            continue;
+        }
+
+        // Keep the next fisheries clear of this one.
+        for column in &footprint {
+            for dx in -(FISHERY_CLEARANCE as i64)..=FISHERY_CLEARANCE as i64 {
+                for dz in -(FISHERY_CLEARANCE as i64)..=FISHERY_CLEARANCE as i64 {
+                    let (x, z) = (column.0 + dx, column.1 + dz);
+                    if x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64 {
+                        occupied.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+                    }
+                }
+            }
+        }
+        fisheries.push(Fishery { front_middle, floor_y, seaward });
+    }
+
+    fisheries
+}
+
+/// Find a beach near the quay for a shipyard, clear of the rest of the harbour, gently
+/// sloping down to water deep enough for the slipway.
+fn plan_shipyard(features: &Features, town: &GrayImage, harbor: &Harbor) -> Option<Shipyard> {
+    let quay_middle: BlockColumnCoord = harbor.quay[harbor.quay.len() / 2].coordinates.into();
+    let occupied = imageproc::morphology::dilate(&harbor_area(features, harbor), Norm::LInf, FISHERY_CLEARANCE);
+
+    for (front_middle, seaward) in shore_columns(features, quay_middle, SHIPYARD_REACH) {
+        let water = step(front_middle, seaward, 1);
+        let water_y = match features.height_map.height_at((water.0 as usize, water.1 as usize)) {
+            Some(water_y) => water_y as i64,
+            None => continue,
+        };
+        let shipyard = Shipyard { front_middle, floor_y: 0, water_y, seaward };
+        let footprint = shipyard.footprint();
+        let fits = footprint.iter().all(|column| {
+            is_water(features, *column) == Some(false)
+                && town[(column.0 as u32, column.1 as u32)] != image::Luma([255u8])
+                && occupied[(column.0 as u32, column.1 as u32)] != image::Luma([255u8])
+        });
+        if !fits || !shipyard.slipway_reach().iter().all(|column| water_depth(features, *column).is_some()) {
+            continue;
+        }
+
+        // A low beach, rising gently from the water
+        let heights: Vec<i64> = footprint.iter()
+            .map(|column| {
+                let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+                y as i64
+            })
+            .collect();
+        let (min_y, max_y) = (*heights.iter().min()?, *heights.iter().max()?);
+        let image::Luma([edge_y]) = features.terrain[(front_middle.0 as u32, front_middle.1 as u32)];
+        if min_y < water_y || edge_y as i64 > water_y + 1 || max_y - min_y > SHIPYARD_SLOPE_MAX {
+            continue;
+        }
+
+        return Some(Shipyard { floor_y: max_y, ..shipyard });
+    }
+    None
+}
+
+/// The roads, quay and piers, the fish market, the loading area and the fisheries.
+fn harbor_area(features: &Features, harbor: &Harbor) -> GrayImage {
+    let (x_len, z_len) = features.dimensions();
+    let mut harbor_area = GrayImage::new(x_len as u32, z_len as u32);
+    let paths = std::iter::once(&harbor.quay).chain(harbor.access.iter()).chain(harbor.piers.iter());
+    for path in paths {
+        for segment in path.windows(2) {
+            let (start, end): (BlockColumnCoord, BlockColumnCoord) =
+                (segment[0].coordinates.into(), segment[1].coordinates.into());
+            let length = geometry::manhattan_distance(start, end).max(1) as i64;
+            for distance in 0..=length {
+                let x = start.0 + (end.0 - start.0) * distance / length;
+                let z = start.1 + (end.1 - start.1) * distance / length;
+                if x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64 {
+                    harbor_area.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+                }
+            }
+        }
+    }
+    if let Some((min, max)) = harbor.market.as_ref().and_then(|market| market.bounding_box()) {
+        for x in min.0..=max.0 {
+            for z in min.2..=max.2 {
+                harbor_area.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+    }
+    let footprints = harbor.loading_area.iter().flat_map(|loading_area| loading_area.footprint())
+        .chain(harbor.fisheries.iter().flat_map(|fishery| fishery_footprint(fishery.front_middle, fishery.seaward)));
+    for column in footprints {
+        harbor_area.put_pixel(column.0 as u32, column.1 as u32, image::Luma([255u8]));
+    }
+    harbor_area
+}
+
+/// Shore columns within `reach` of `center`, closest first, with the direction towards the water.
+fn shore_columns(features: &Features, center: BlockColumnCoord, reach: usize) -> Vec<(BlockColumnCoord, Surface4)> {
+    let (x_len, z_len) = features.dimensions();
+    let mut candidates: Vec<(BlockColumnCoord, Surface4)> = Vec::new();
+    for x in 1..x_len as i64 - 1 {
+        for z in 1..z_len as i64 - 1 {
+            let column = BlockColumnCoord(x, z);
+            if is_water(features, column) != Some(false) || geometry::manhattan_distance(column, center) > reach {
+                continue;
+            }
+            for seaward in &[Surface4::North, Surface4::East, Surface4::South, Surface4::West] {
+                if is_water(features, step(column, *seaward, 1)) == Some(true) {
+                    candidates.push((column, *seaward));
+                }
+            }
+        }
+    }
+    candidates.sort_by_key(|(column, _)| geometry::manhattan_distance(*column, center));
+    candidates
+}
+
+/// The columns of a fishery, with the middle of its front at `front_middle`.
+fn fishery_footprint(front_middle: BlockColumnCoord, seaward: Surface4) -> Vec<BlockColumnCoord> {
+    let along = seaward.rotated_90_cw();
+    let landward = seaward.opposite();
+    let mut footprint = Vec::new();
+    for across in 0..FISHERY_SIZE.1 {
+        for along_distance in -(FISHERY_SIZE.0 / 2)..=FISHERY_SIZE.0 / 2 {
+            footprint.push(step(step(front_middle, landward, across), along, along_distance));
+        }
+    }
+    footprint
+}
+
+/// Build the quay, the road to it, the piers, the boats, the crane and the loading area,
+/// the fish market, the fisheries and the shipyard.
+pub fn build_harbor(
+    excerpt: &mut WorldExcerpt,
+    harbor: &Harbor,
+    features: &Features,
+    palette: &BlockPalette,
+    config: &GenerationConfig,
+    rng: &mut StdRng,
+) {
+    // The harbour's own widths, and no edging along the piers out over the water
+    let style = |path: &RoadPath, width: i64| RoadStyle { width, edging: None, ..path.class.style(config) };
+
+    if let Some(access) = &harbor.access {
+        road::build_road_in_style(excerpt, access, &features.terrain, &style(access, PIER_WIDTH), rng);
+    }
+    road::build_road_in_style(excerpt, &harbor.quay, &features.terrain, &style(&harbor.quay, QUAY_WIDTH), rng);
+    for pier in &harbor.piers {
+        road::build_road_in_style(excerpt, pier, &features.terrain, &style(pier, PIER_WIDTH), rng);
+    }
+
+    for boat in &harbor.boats {
+        build_boat(excerpt, boat);
+    }
+
+    if let Some(crane) = &harbor.crane {
+        crane::build_crane(excerpt, crane);
+    }
+    if let Some(loading_area) = &harbor.loading_area {
+        crane::build_loading_area(excerpt, loading_area, features, rng);
+    }
+
+    if let Some(market) = &harbor.market {
+        build_fish_market(excerpt, market, harbor.seaward, features, palette);
+    }
+
+    for fishery in &harbor.fisheries {
+        build_fishery(excerpt, fishery, features, palette, rng);
+    }
+
+    if let Some(shipyard) = &harbor.shipyard {
+        shipyard::build_shipyard(excerpt, shipyard, features, rng);
+    }
+}
+
+/// Build a small sailing boat of planks, with a mast and a furled sail.
+fn build_boat(excerpt: &mut WorldExcerpt, boat: &Boat) {
+    let (half_length, half_width) = (BOAT_SIZE.0 / 2, BOAT_SIZE.1 / 2);
+    let side = geometry::offset(boat.heading.rotated_90_cw());
+    let ahead = geometry::offset(boat.heading);
+    let at = |along: i64, across: i64, up: i64| {
+        BlockCoord(
+            boat.center.0 + ahead.0 * along + side.0 * across,
+            boat.center.1 + up,
+            boat.center.2 + ahead.2 * along + side.2 * across,
+        )
+    };
+
+    for along in -half_length..=half_length {
+        for across in -half_width..=half_width {
+            // Pointed bow
+            if along == half_length && across != 0 {
+                continue;
+            }
+            excerpt.set_block_at(at(along, across, 0), Block::Planks { material: WoodMaterial::Spruce });
+
+            let is_gunwale = across.abs() == half_width || along.abs() == half_length;
+            excerpt.set_block_at(
+                at(along, across, 1),
+                if is_gunwale { Block::bottom_slab(Material::Spruce) } else { Block::Air },
+            );
+        }
+    }
+
+    // Mast, with the sail furled along the boom
+    for up in 1..=4 {
+        excerpt.set_block_at(at(0, 0, up), Block::Fence { material: FenceMaterial::Spruce, waterlogged: false });
+    }
+    for along in -half_length + 1..0 {
+        excerpt.set_block_at(at(along, 0, 2), Block::Wool { colour: Colour::White });
+    }
+}
+
+/// Build the fish market, as an open hall with a slab roof on posts,
+/// and a counter of barrels facing the quay.
+fn build_fish_market(
+    excerpt: &mut WorldExcerpt,
+    market: &Plot,
+    seaward: Surface4,
+    features: &Features,
+    palette: &BlockPalette,
+) {
+    let (min, max) = match market.bounding_box() {
+        Some(bounding_box) => bounding_box,
+        None => return,
+    };
+    let y = max.1;
+    let roof_y = y + 3;
+
+    for x in min.0..=max.0 {
+        for z in min.2..=max.2 {
+            let is_edge_x = x == min.0 || x == max.0;
+            let is_edge_z = z == min.2 || z == max.2;
+
+            // Level floor on a solid foundation
+            for clear_y in y..roof_y {
+                tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+            }
+            let image::Luma([ground]) = features.terrain[(x as u32, z as u32)];
+            for fill_y in ground as i64 - 1..y {
+                excerpt.set_block_at(BlockCoord(x, fill_y, z), palette.foundation.clone());
+            }
+
+            // Posts in the corners and along the sides
+            let is_post = (is_edge_x && is_edge_z)
+                || (is_edge_x && (z - min.2) % 4 == 0)
+                || (is_edge_z && (x - min.0) % 4 == 0);
+            if is_post {
+                for post_y in y..roof_y {
+                    excerpt.set_block_at(
+                        BlockCoord(x, post_y, z),
+                        Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+                    );
+                }
+            }
+
+            excerpt.set_block_at(BlockCoord(x, roof_y, z), Block::bottom_slab(Material::Spruce));
+        }
+    }
+
+    // Counter of barrels, one block in from the front, with a gap in the middle
+    let (counter_start, counter_length) = match seaward {
+        Surface4::North => (BlockCoord(min.0, y, min.2 + 1), max.0 - min.0),
+        Surface4::South => (BlockCoord(min.0, y, max.2 - 1), max.0 - min.0),
+        Surface4::West => (BlockCoord(min.0 + 1, y, min.2), max.2 - min.2),
+        Surface4::East => (BlockCoord(max.0 - 1, y, min.2), max.2 - min.2),
+    };
+    let along = match seaward {
+        Surface4::North | Surface4::South => BlockCoord(1, 0, 0),
+        Surface4::East | Surface4::West => BlockCoord(0, 0, 1),
+    };
+    for distance in 1..counter_length {
+        if distance == counter_length / 2 {
+            continue;
+        }
+        let coordinates = BlockCoord(
+            counter_start.0 + along.0 * distance,
+            y,
+            counter_start.2 + along.2 * distance,
+        );
+        excerpt.set_block_at(coordinates, Block::barrel(Surface6::Up));
+    }
+
+    // Lantern hanging from the middle of the roof
+    let middle = BlockCoord((min.0 + max.0) / 2, roof_y - 1, (min.2 + max.2) / 2);
+    excerpt.set_block_at(middle, Block::Lantern { mounted_at: Surface2::Up, waterlogged: false });
+}
+
+/// Build a fishery: Two drying racks of fence posts with lines of string,
+/// a cobblestone smokehouse around a campfire, and barrels of fish by the water.
+fn build_fishery(
+    excerpt: &mut WorldExcerpt,
+    fishery: &Fishery,
+    features: &Features,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) {
+    let y = fishery.floor_y;
+    let landward = geometry::offset(fishery.seaward.opposite());
+    // The smokehouse is on either side of the racks.
+    let along_direction = if rng.gen_bool(0.5) {
+        fishery.seaward.rotated_90_cw()
+    } else {
+        fishery.seaward.rotated_90_ccw()
+    };
+    let side = geometry::offset(along_direction);
+    let at = |along: i64, across: i64, up: i64| {
+        BlockCoord(
+            fishery.front_middle.0 + side.0 * along + landward.0 * across,
+            y + up,
+            fishery.front_middle.1 + side.2 * along + landward.2 * across,
+        )
+    };
+    let fence = Block::Fence { material: FenceMaterial::Spruce, waterlogged: false };
+
+    // Level ground, cleared of vegetation
+    for column in fishery_footprint(fishery.front_middle, fishery.seaward) {
+        for clear_y in y..y + 5 {
+            tree::chop(excerpt, BlockCoord(column.0, clear_y, column.1));
+            excerpt.set_block_at(BlockCoord(column.0, clear_y, column.1), Block::Air);
+        }
+        let image::Luma([ground]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        for fill_y in ground as i64 - 1..y - 1 {
+            excerpt.set_block_at(BlockCoord(column.0, fill_y, column.1), palette.foundation.clone());
+        }
+        excerpt.set_block_at(BlockCoord(column.0, y - 1, column.1), Block::CoarseDirt);
+    }
+
+    // Drying racks, with fish hanging on lines strung below the top rail
+    for across in &[1, 3] {
+        for along in -4..=-1 {
+            if along == -4 || along == -1 {
+                for up in 0..2 {
+                    excerpt.set_block_at(at(along, *across, up), fence.clone());
+                }
+            } else {
+                excerpt.set_block_at(at(along, *across, 1), Block::Tripwire);
+            }
+            excerpt.set_block_at(at(along, *across, 2), fence.clone());
+        }
+    }
+
+    // Smokehouse, with the smoke escaping through a hole in the roof
+    for along in 1..=3 {
+        for across in 1..=4 {
+            let is_wall = along != 2 || across == 1 || across == 4;
+            if is_wall {
+                for up in 0..3 {
+                    excerpt.set_block_at(at(along, across, up), Block::Cobblestone);
+                }
+            }
+            if (along, across) != (2, 3) {
+                excerpt.set_block_at(at(along, across, 3), Block::bottom_slab(Material::Spruce));
+            }
+        }
+    }
+    excerpt.set_block_at(
+        at(2, 3, 0),
+        Block::Campfire { facing: fishery.seaward, lit: true, waterlogged: false },
+    );
+    for (up, half) in &[(0, DoorHalf::Lower), (1, DoorHalf::Upper)] {
+        excerpt.set_block_at(at(2, 1, *up), Block::Door(Door {
+            material: DoorMaterial::Spruce,
+            facing: fishery.seaward.opposite(),
+            half: *half,
+            hinged_at: Hinge::Right,
+            open: false,
+        }));
+    }
+
+    // Barrels of fish, by the water and beside the smokehouse
+    for (along, across) in &[(1, 0), (3, 0), (4, 1), (4, 2)] {
+        if rng.gen_bool(0.75) {
+            excerpt.set_block_at(at(*along, *across, 0), Block::barrel(Surface6::Up));
+        }
+    }
+}
+
+/// Whether the column is covered by water, or None if the column is outside the area.
+fn is_water(features: &Features, column: BlockColumnCoord) -> Option<bool> {
+    let (x_len, z_len) = features.dimensions();
+    if column.0 < 0 || column.1 < 0 || column.0 >= x_len as i64 || column.1 >= z_len as i64 {
+        return None;
+    }
+    Some(features.water[(column.0 as u32, column.1 as u32)] == image::Luma([255u8]))
+}
+
+/// Depth of the water in the column, or None if there is no water there.
+fn water_depth(features: &Features, column: BlockColumnCoord) -> Option<u8> {
+    if is_water(features, column)? {
+        let image::Luma([depth]) = features.water_depth[(column.0 as u32, column.1 as u32)];
+        Some(depth)
+    } else {
+        None
+    }
+}
+
+/// Road node on the ground in the given column.
+fn ground_node(features: &Features, column: BlockColumnCoord) -> Option<RoadNode> {
+    is_water(features, column)?;
+    let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+    Some(RoadNode {
+        coordinates: BlockCoord(column.0, y as i64, column.1),
+        kind: RoadNodeKind::Ground,
+    })
+}
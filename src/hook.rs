@@ -0,0 +1,127 @@
+//! Post-processing hooks, run after the stages of the generation, for custom tweaks to the
+//! plan or the world without changing the crate: Closures given through the library API,
+//! or external commands given the city plan and the world as built so far.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::error;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::generation_context::GenerationContext;
+use crate::plan_export;
+
+/// A hook, run after each stage with the name of the stage and the context.
+pub type Hook = Box<dyn FnMut(&str, &mut GenerationContext) + Send>;
+
+/// An external command run as a hook. After each stage it runs for, the program is run with
+/// its arguments, followed by three more: The name of the stage, the path of the city plan as JSON, and the
+/// path of a save directory holding the world excerpt as built so far, with its lowest
+/// corner at the origin. Once the command has succeeded, the world in the save directory is
+/// read back into the excerpt, with any changes the command made to it.
+#[derive(Clone, Debug)]
+pub struct CommandHook {
+    program: String,
+    arguments: Vec<String>,
+    stages: Vec<String>,
+    directory: PathBuf,
+}
+
+impl CommandHook {
+    /// A hook running `program` with the given `arguments`, with the city plan and the world
+    /// written into `directory`. Runs after every stage, unless limited with `with_stages`.
+    ///
+    /// Saving the world and reading it back takes a while for large excerpts, so the hook
+    /// is best limited to the stages it is needed after.
+    pub fn new(program: &str, arguments: Vec<String>, directory: &Path) -> Result<Self, String> {
+        if program.is_empty() {
+            return Err("The hook command is empty.".to_string());
+        }
+        Ok(Self {
+            program: program.to_string(),
+            arguments,
+            stages: Vec::new(),
+            directory: directory.to_path_buf(),
+        })
+    }
+
+    /// This hook, running only after the stages with the given names.
+    pub fn with_stages(mut self, stages: Vec<String>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// Whether the command runs after the stage with the given name.
+    pub fn runs_after(&self, stage_name: &str) -> bool {
+        self.stages.is_empty() || self.stages.iter().any(|stage| stage == stage_name)
+    }
+
+    /// Run the command for the stage just run, if it runs after that stage.
+    pub fn run(&self, stage_name: &str, context: &mut GenerationContext) -> Result<(), String> {
+        if !self.runs_after(stage_name) {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.directory).map_err(|error| format!("{}", error))?;
+        let plan_path = self.directory.join("plan.json");
+        let save_directory = self.directory.join("world");
+        plan_export::write_plan(context, &plan_path)?;
+        context.excerpt.to_save(BlockCoord(0, 0, 0), &save_directory);
+
+        let status = Command::new(&self.program)
+            .args(&self.arguments)
+            .arg(stage_name)
+            .arg(&plan_path)
+            .arg(&save_directory)
+            .status()
+            .map_err(|error| format!("Unable to run {}: {}", self.program, error))?;
+        if !status.success() {
+            return Err(format!("{} failed after stage {}: {}", self.program, stage_name, status));
+        }
+
+        let (x_len, y_len, z_len) = context.excerpt.dim();
+        context.excerpt = WorldExcerpt::from_save(
+            BlockCoord(0, 0, 0),
+            BlockCoord(x_len as i64 - 1, y_len as i64 - 1, z_len as i64 - 1),
+            &save_directory,
+        );
+        Ok(())
+    }
+
+    /// The command as a hook, logging any failure and carrying on with the generation.
+    pub fn into_hook(self) -> Hook {
+        Box::new(move |stage_name: &str, context: &mut GenerationContext| {
+            if let Err(error) = self.run(stage_name, context) {
+                error!("Hook failed: {}", error);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_program() {
+        assert!(CommandHook::new("", Vec::new(), Path::new("hook")).is_err());
+    }
+
+    #[test]
+    fn runs_after_every_stage() {
+        let hook = CommandHook::new("true", Vec::new(), Path::new("hook")).unwrap();
+        assert!(hook.runs_after("extract_features"));
+        assert!(hook.runs_after("build_plots"));
+    }
+
+    #[test]
+    fn runs_after_given_stages() {
+        let hook = CommandHook::new("true", Vec::new(), Path::new("hook"))
+            .unwrap()
+            .with_stages(vec!["build_plots".to_string()]);
+        assert!(hook.runs_after("build_plots"));
+        assert!(!hook.runs_after("extract_features"));
+    }
+}
@@ -0,0 +1,232 @@
+//! Report of a generation run as a single self-contained HTML page, for sharing and
+//! debugging: The site chosen for the town, counts of what was planned and built, the
+//! plots left without a building, the land at risk of flooding, the city plan as JSON, and
+//! the inspection images, embedded in the page.
+
+use std::fs;
+use std::path::Path;
+
+use image::codecs::png::PngEncoder;
+use image::{ColorType, Rgb};
+
+use crate::config::SettlementStyle;
+use crate::features::Features;
+use crate::generation_context::GenerationContext;
+use crate::geometry;
+use crate::plan_export::PlanExport;
+use crate::structure_builder::HousePurpose;
+
+/// Write the report of the generation so far to the HTML file at `path`, conventionally
+/// named `report.html`. The inspection images are embedded if inspection is enabled.
+pub fn write_report(context: &GenerationContext, path: &Path) -> Result<(), String> {
+    fs::write(path, report(context)?).map_err(|error| format!("{}", error))
+}
+
+/// The report of the generation so far, as an HTML page.
+pub fn report(context: &GenerationContext) -> Result<String, String> {
+    let plan = serde_json::to_string_pretty(&PlanExport::from_context(context))
+        .map_err(|error| format!("{}", error))?;
+
+    let title = if context.town_name.is_empty() {
+        "Settlement generation report".to_string()
+    } else {
+        format!("Settlement generation report: {}", context.town_name)
+    };
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n", escape(&title)));
+    html.push_str(
+        "<style>\n\
+        body { font-family: sans-serif; margin: 2em; }\n\
+        table { border-collapse: collapse; margin-bottom: 1em; }\n\
+        td, th { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }\n\
+        figure { display: inline-block; margin: 0.5em; vertical-align: top; }\n\
+        img { max-width: 480px; image-rendering: pixelated; border: 1px solid #ccc; }\n\
+        pre { max-height: 40em; overflow: auto; background: #f4f4f4; padding: 0.5em; }\n\
+        </style>\n",
+    );
+    html.push_str("</head>\n<body>\n");
+    html.push_str(&format!("<h1>{}</h1>\n", escape(&title)));
+
+    // Site choice
+    let outlying_town_areas: Vec<i64> = context.outlying_towns.iter()
+        .map(|town| geometry::area(&town.wall_circle).abs())
+        .collect();
+    html.push_str("<h2>Site</h2>\n");
+    html.push_str(&table(&[
+        ("Dimension", format!("{:?}", context.config.dimension)),
+        ("Town center", format!("{:?}", context.town_center)),
+        ("Town area", format!("{} m²", geometry::area(&context.wall_circle).abs())),
+        ("Outlying towns", format!("{} ({:?} m²)", outlying_town_areas.len(), outlying_town_areas)),
+        ("Adopted villages", context.villages.len().to_string()),
+        ("Building style", format!("{:?}", context.block_palette.biome)),
+        ("Defences", format!("{:?}", context.defence)),
+        ("Common woods", format!("{:?}", context.wood_available)),
+    ]));
+
+    // What was planned and built
+    let purpose_count = |purpose: HousePurpose| {
+        context.buildings.iter().filter(|building| building.purpose == Some(purpose)).count()
+    };
+    let yes_or_no = |is_present: bool| if is_present { "yes" } else { "no" }.to_string();
+    html.push_str("<h2>Plan and buildings</h2>\n");
+    html.push_str(&table(&[
+        ("Districts", context.districts.len().to_string()),
+        ("Plots", context.plots.len().to_string()),
+        ("Streets", context.streets.len().to_string()),
+        ("City roads", context.city_roads.len().to_string()),
+        ("Country roads", context.country_roads.len().to_string()),
+        ("Buildings", context.buildings.len().to_string()),
+        ("Dwellings", purpose_count(HousePurpose::Dwelling).to_string()),
+        ("Taverns", purpose_count(HousePurpose::Tavern).to_string()),
+        ("Workshops", purpose_count(HousePurpose::Workshop).to_string()),
+        ("Bakeries", purpose_count(HousePurpose::Bakery).to_string()),
+        ("Apothecaries", purpose_count(HousePurpose::Apothecary).to_string()),
+        ("Schools", purpose_count(HousePurpose::School).to_string()),
+        ("Other buildings", context.buildings.iter().filter(|building| building.purpose.is_none()).count().to_string()),
+        ("Cliff dwellings", context.cliff_dwellings.len().to_string()),
+        ("Ruined buildings", context.ruins.len().to_string()),
+        ("Fields", context.fields.len().to_string()),
+        ("Pastures", context.pastures.len().to_string()),
+        ("Livestock", context.pastures.iter().map(|pasture| pasture.animals.len()).sum::<usize>().to_string()),
+        ("Pets", context.pets.len().to_string()),
+        ("Graves", context.graves.len().to_string()),
+        ("Harbour", yes_or_no(context.harbor.is_some())),
+        ("Lighthouse", yes_or_no(context.lighthouse.is_some())),
+        ("Mine", yes_or_no(context.mine.is_some())),
+        ("Windmill", yes_or_no(context.windmill.is_some())),
+        ("Lumber camp", yes_or_no(context.lumber_camp.is_some())),
+        ("Training yard", yes_or_no(context.amenities.training_yard_plot.is_some())),
+        ("Viewpoints", context.viewpoints.len().to_string()),
+    ]));
+
+    // Land at risk of flooding, and the buildings raised above the floods
+    if let Some(features) = &context.features {
+        let risk_area = features.flood_risk.pixels().filter(|pixel| **pixel == image::Luma([255u8])).count();
+        let raised_count = context.buildings.iter()
+            .filter(|building| features.flood_level_max(&context.plots[building.plot_index].polygon()).is_some())
+            .count();
+        html.push_str("<h2>Flood risk</h2>
+");
+        html.push_str(&table(&[
+            ("Land at risk of flooding", format!("{} m²", risk_area)),
+            ("Buildings raised above floods", raised_count.to_string()),
+        ]));
+        html.push_str(&figure(&flood_map(features)?, "Land at risk of flooding"));
+    }
+
+    // Plots meant for a building, that did not get one
+    let unbuilt_plots: Vec<usize> = (0..context.plots.len())
+        .filter(|_| context.config.style != SettlementStyle::Cliff)
+        .filter(|index| !context.config.is_skipped_plot(*index))
+        .filter(|index| !context.buildings.iter().any(|building| building.plot_index == *index))
+        .collect();
+    html.push_str("<h2>Failures</h2>\n");
+    if context.features.is_none() {
+        html.push_str("<p>The terrain features were never extracted.</p>\n");
+    }
+    if !context.zoning_infeasibilities.is_empty() {
+        html.push_str("<p>Zoning constraints that could not be satisfied:</p>\n<ul>\n");
+        for infeasibility in &context.zoning_infeasibilities {
+            html.push_str(&format!("<li>{}</li>\n", escape(&infeasibility.to_string())));
+        }
+        html.push_str("</ul>\n");
+    }
+    if unbuilt_plots.is_empty() {
+        html.push_str("<p>Every plot meant for a building got one.</p>\n");
+    } else {
+        html.push_str(&format!(
+            "<p>{} plots were left without a building: {:?}</p>\n",
+            unbuilt_plots.len(),
+            unbuilt_plots,
+        ));
+    }
+
+    // Images of the intermediate results
+    html.push_str("<h2>Plan maps and inspection images</h2>\n");
+    match context.inspection.directory() {
+        Some(directory) => {
+            let mut image_paths: Vec<_> = fs::read_dir(directory)
+                .map_err(|error| format!("{}", error))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map_or(false, |extension| extension == "png"))
+                .collect();
+            image_paths.sort();
+            for image_path in image_paths {
+                let bytes = fs::read(&image_path).map_err(|error| format!("{}", error))?;
+                let name = image_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                html.push_str(&figure(&bytes, &name));
+            }
+        }
+        None => html.push_str("<p>Inspection was not enabled, so there are no images.</p>\n"),
+    }
+
+    // The full plan
+    html.push_str("<h2>City plan</h2>\n<details>\n<summary>City plan as JSON</summary>\n");
+    html.push_str(&format!("<pre>{}</pre>\n</details>\n", escape(&plan)));
+
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+/// The map of the site, with the land at risk of flooding tinted blue, as PNG.
+fn flood_map(features: &Features) -> Result<Vec<u8>, String> {
+    let mut map = features.coloured_map.clone();
+    for (x, z, pixel) in map.enumerate_pixels_mut() {
+        if features.flood_risk[(x, z)] == image::Luma([255u8]) {
+            *pixel = Rgb([pixel[0] / 2, pixel[1] / 2, pixel[2] / 2 + 128]);
+        }
+    }
+    let mut bytes = Vec::new();
+    PngEncoder::new(&mut bytes)
+        .encode(&map, map.width(), map.height(), ColorType::Rgb8)
+        .map_err(|error| format!("{}", error))?;
+    Ok(bytes)
+}
+
+/// An HTML figure of the PNG image, with its name as the caption.
+fn figure(png: &[u8], name: &str) -> String {
+    format!(
+        "<figure><img src=\"data:image/png;base64,{}\" alt=\"{}\"><figcaption>{}</figcaption></figure>\n",
+        base64(png),
+        escape(name),
+        escape(name),
+    )
+}
+
+/// A two column HTML table of names and values.
+fn table(rows: &[(&str, String)]) -> String {
+    let mut html = String::from("<table>\n");
+    for (name, value) in rows {
+        html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", escape(name), escape(value)));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// The text, with the characters that have a meaning in HTML escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The bytes in standard base64 encoding, with padding, for embedding in data URLs.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
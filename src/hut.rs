@@ -0,0 +1,116 @@
+//! Small single room huts, for guards, keepers and the like.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::positioning::Surface4;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+
+use crate::block_palette::BlockPalette;
+use crate::room_interior::{self, ColumnKind, RoomShape};
+
+/// Depth of the foundation below the floor, for huts on uneven ground.
+pub const HUT_FOUNDATION_DEPTH: i64 = 3;
+/// Height from the floor to the ceiling, in blocks of air.
+const HUT_WALL_HEIGHT: i64 = 3;
+
+/// Build a hut with outer dimensions `(x_len, z_len)`, with the door in the middle of
+/// the wall on `door_side`, and windows in the middle of the other walls.
+///
+/// The hut is returned as an excerpt, with the floor at y = `HUT_FOUNDATION_DEPTH`.
+/// If `furnished`, the interior is furnished as a cottage.
+pub fn build_hut(
+    (x_len, z_len): (i64, i64),
+    door_side: Surface4,
+    palette: &BlockPalette,
+    furnished: bool,
+    rng: &mut StdRng,
+) -> WorldExcerpt {
+    let floor_y = HUT_FOUNDATION_DEPTH;
+    let roof_y = floor_y + HUT_WALL_HEIGHT + 1;
+    let mut output = WorldExcerpt::new(x_len as usize, roof_y as usize + 1, z_len as usize);
+
+    let is_wall = |x: i64, z: i64| x == 0 || z == 0 || x == x_len - 1 || z == z_len - 1;
+    let is_corner = |x: i64, z: i64| (x == 0 || x == x_len - 1) && (z == 0 || z == z_len - 1);
+
+    let middle_of_side = |side: Surface4| match side {
+        Surface4::North => (x_len / 2, 0),
+        Surface4::South => (x_len / 2, z_len - 1),
+        Surface4::West => (0, z_len / 2),
+        Surface4::East => (x_len - 1, z_len / 2),
+    };
+    let door = middle_of_side(door_side);
+    let windows: Vec<(i64, i64)> = [Surface4::North, Surface4::East, Surface4::South, Surface4::West]
+        .iter()
+        .filter(|side| **side != door_side)
+        .map(|side| middle_of_side(*side))
+        .collect();
+
+    let mut room_shape = RoomShape::new((x_len as usize, z_len as usize));
+
+    for x in 0..x_len {
+        for z in 0..z_len {
+            // Foundation and floor
+            for y in 0..floor_y {
+                output.set_block_at(BlockCoord(x, y, z), palette.foundation.clone());
+            }
+            output.set_block_at(BlockCoord(x, floor_y, z), palette.floor.clone());
+
+            // Walls, or open space inside
+            for y in floor_y + 1..roof_y {
+                let block = if is_corner(x, z) {
+                    palette.foundation.clone()
+                } else if is_wall(x, z) {
+                    palette.wall.clone()
+                } else {
+                    Block::Air
+                };
+                output.set_block_at(BlockCoord(x, y, z), block);
+            }
+
+            // Flat roof
+            output.set_block_at(BlockCoord(x, roof_y, z), palette.roof.clone());
+
+            let column_kind = if (x, z) == door {
+                ColumnKind::Door
+            } else if windows.contains(&(x, z)) {
+                ColumnKind::Window
+            } else if is_wall(x, z) {
+                ColumnKind::Wall
+            } else {
+                ColumnKind::Floor(HUT_WALL_HEIGHT as usize)
+            };
+            room_shape.set_column_kind_at((x as usize, z as usize), column_kind);
+        }
+    }
+
+    // Windows
+    for (x, z) in &windows {
+        output.set_block_at(BlockCoord(*x, floor_y + 2, *z), palette.flat_window.clone());
+    }
+
+    // Door, facing into the hut
+    let facing = door_side.opposite();
+    output.set_block_at(BlockCoord(door.0, floor_y + 1, door.1), Block::Door(mcprogedit::block::Door {
+        material: mcprogedit::material::DoorMaterial::Oak,
+        facing,
+        half: mcprogedit::block::DoorHalf::Lower,
+        hinged_at: mcprogedit::block::Hinge::Right,
+        open: false,
+    }));
+    output.set_block_at(BlockCoord(door.0, floor_y + 2, door.1), Block::Door(mcprogedit::block::Door {
+        material: mcprogedit::material::DoorMaterial::Oak,
+        facing,
+        half: mcprogedit::block::DoorHalf::Upper,
+        hinged_at: mcprogedit::block::Hinge::Right,
+        open: false,
+    }));
+
+    if furnished {
+        if let Some(interior) = room_interior::furnish_cottage(&room_shape, rng) {
+            output.paste(BlockCoord(0, floor_y + 1, 0), &interior);
+        }
+    }
+
+    output
+}
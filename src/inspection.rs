@@ -0,0 +1,51 @@
+//! Inspection of intermediate results, as images written while generating.
+//!
+//! Nothing is written unless an output directory has been given, so that several
+//! generations can run side by side without writing into the working directory.
+
+use std::path::{Path, PathBuf};
+
+use image::{ImageBuffer, Pixel};
+use log::{debug, warn};
+
+/// Where to write the inspection images, if anywhere.
+#[derive(Clone, Debug, Default)]
+pub struct Inspection {
+    directory: Option<PathBuf>,
+}
+
+impl Inspection {
+    /// Inspection that writes nothing.
+    pub fn disabled() -> Self {
+        Self { directory: None }
+    }
+
+    /// Inspection that writes images into the given directory.
+    pub fn to_directory(directory: &Path) -> Self {
+        Self { directory: Some(directory.to_path_buf()) }
+    }
+
+    /// Whether images are written. Use for skipping work that is only done for inspection.
+    pub fn is_enabled(&self) -> bool {
+        self.directory.is_some()
+    }
+
+    /// The directory images are written into, if inspection is enabled.
+    pub fn directory(&self) -> Option<&Path> {
+        self.directory.as_deref()
+    }
+
+    /// Write the image to the file with the given name, if inspection is enabled.
+    pub fn save_image<P>(&self, name: &str, image: &ImageBuffer<P, Vec<u8>>)
+    where
+        P: Pixel<Subpixel = u8> + 'static,
+    {
+        if let Some(directory) = &self.directory {
+            let path = directory.join(name);
+            match image.save(&path) {
+                Ok(()) => debug!("Saved {:?}", path),
+                Err(error) => warn!("Unable to save {:?}: {}", path, error),
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Leifsbudir - settlement generator for Minecraft
+//!
+//! The generation pipeline is exposed through [`SettlementGenerator`], which
+//! runs the stages (feature extraction, area selection, town layout, plots,
+//! building) on a world excerpt, either all at once or one stage at a time.
+//! Custom [`Stage`]s can be inserted into the pipeline, and hooks can be added
+//! for tweaking the results after each stage.
+//!
+//! The library keeps no global state, and writes no files of its own accord;
+//! images of intermediate results are only written through an [`Inspection`].
+//! Generators are `Send`, so several generations may run on separate threads.
+
+extern crate mcprogedit;
+
+pub mod amenity;
+pub mod areas;
+pub mod bathhouse;
+pub mod block_palette;
+pub mod block_whitelist;
+pub mod boundary;
+pub mod build_area;
+pub mod church;
+pub mod cliff_dwelling;
+pub mod config;
+pub mod crane;
+pub mod embellishment;
+pub mod export_verification;
+pub mod farm;
+pub mod features;
+pub mod forestry;
+pub mod foundation;
+pub mod generation_context;
+pub mod geojson_export;
+pub mod geometry;
+pub mod graveyard;
+pub mod harbor;
+pub mod hook;
+pub mod html_report;
+pub mod hut;
+pub mod inspection;
+pub mod light_audit;
+pub mod lighthouse;
+pub mod line;
+pub mod mine;
+pub mod naming;
+pub mod night_lighting;
+pub mod partitioning;
+pub mod pasture;
+pub mod pathfinding;
+pub mod pergola;
+pub mod pets;
+pub mod plan_export;
+pub mod plaza;
+pub mod plot;
+pub mod pond;
+pub mod render;
+pub mod road;
+pub mod road_entry;
+pub mod room_interior;
+pub mod ruin;
+pub mod shipyard;
+pub mod signs;
+pub mod stage;
+pub mod street_detail;
+pub mod structure_builder;
+pub mod tannery;
+pub mod terraforming;
+pub mod toll_station;
+pub mod training_yard;
+pub mod treasure;
+pub mod tree;
+pub mod types;
+pub mod viewpoint;
+pub mod village;
+pub mod wall;
+pub mod walled_town;
+pub mod waterways;
+pub mod waymarks;
+pub mod weathering;
+pub mod well;
+pub mod windmill;
+pub mod zoning;
+
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+pub use crate::config::GenerationConfig;
+pub use crate::generation_context::GenerationContext;
+pub use crate::hook::{CommandHook, Hook};
+pub use crate::inspection::Inspection;
+pub use crate::stage::{FnStage, Stage};
+
+/// Rough number of bytes per column for the terrain feature images and height maps.
+/// A heuristic, rounded up from the images and height maps extracted today, rather
+/// than a measured figure; raise it when adding feature rasters.
+const FEATURE_BYTES_PER_COLUMN: usize = 64;
+
+/// Generates a settlement in a world excerpt.
+///
+/// Generation runs through an ordered list of [`Stage`]s operating on the
+/// underlying [`GenerationContext`]. The list starts out with the built-in
+/// stages, and custom stages can be inserted before or after any of them.
+pub struct SettlementGenerator {
+    context: GenerationContext,
+    stages: Vec<Box<dyn Stage>>,
+    next_stage: usize,
+    hooks: Vec<Hook>,
+}
+
+impl SettlementGenerator {
+    /// Create a generator for the given excerpt, with random choices seeded by `seed`.
+    pub fn new(excerpt: WorldExcerpt, seed: u64) -> Self {
+        Self::new_with_config(excerpt, seed, GenerationConfig::default())
+    }
+
+    /// Create a generator for the given excerpt, with the given generation parameters.
+    pub fn new_with_config(excerpt: WorldExcerpt, seed: u64, config: GenerationConfig) -> Self {
+        Self {
+            context: GenerationContext::new(excerpt, seed, config),
+            stages: stage::default_stages(),
+            next_stage: 0,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Rough estimate of the memory, in bytes, needed for generating in an excerpt of the
+    /// given dimensions with the given parameters: The world excerpt, a copy of it when
+    /// keeping to a block whitelist, the terrain features, and `extra_copies` more copies
+    /// of the excerpt held alongside, e.g. for verifying the export or for running hooks.
+    ///
+    /// This is a heuristic for refusing hopelessly large areas up front, not a bound: It
+    /// counts the blocks at their in-memory size, and the features at a fixed number of
+    /// bytes per column, leaving out the roads, plots and buildings planned on the way.
+    pub fn estimated_memory(
+        dimensions: (usize, usize, usize),
+        config: &GenerationConfig,
+        extra_copies: usize,
+    ) -> usize {
+        let (x_len, y_len, z_len) = dimensions;
+        let excerpt_copies = if config.block_whitelist.is_some() { 2 } else { 1 } + extra_copies;
+        x_len * y_len * z_len * std::mem::size_of::<Block>() * excerpt_copies
+            + x_len * z_len * FEATURE_BYTES_PER_COLUMN
+    }
+
+    /// Keep the settlement inside the given polygon, relative to the excerpt, instead of
+    /// using the whole excerpt. Must be set before the features are extracted.
+    pub fn set_boundary(&mut self, boundary: types::Snake) {
+        self.context.boundary = Some(boundary);
+    }
+
+    /// Write images of intermediate results through the given inspection.
+    pub fn set_inspection(&mut self, inspection: Inspection) {
+        self.context.inspection = inspection;
+    }
+
+    /// Names of all stages, in the order they run.
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    /// Add a stage to the end of the pipeline.
+    pub fn push_stage(&mut self, stage: Box<dyn Stage>) {
+        self.stages.push(stage);
+    }
+
+    /// Insert a stage right before the stage named `name`.
+    /// Returns false, and does not insert, if there is no such stage.
+    pub fn insert_stage_before(&mut self, name: &str, stage: Box<dyn Stage>) -> bool {
+        if let Some(index) = self.stage_index(name) {
+            self.insert_stage_at(index, stage);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Insert a stage right after the stage named `name`.
+    /// Returns false, and does not insert, if there is no such stage.
+    pub fn insert_stage_after(&mut self, name: &str, stage: Box<dyn Stage>) -> bool {
+        if let Some(index) = self.stage_index(name) {
+            self.insert_stage_at(index + 1, stage);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add a post-processing hook, run after each stage with the name of the stage and the
+    /// context, for custom tweaks to the plan or the world. Hooks run in the order added.
+    pub fn add_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&str, &mut GenerationContext) + Send + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Run all remaining stages of the pipeline.
+    pub fn generate(&mut self) {
+        while self.run_next_stage().is_some() {}
+    }
+
+    /// Run the remaining stages up to and including the stage named `name`.
+    pub fn run_until(&mut self, name: &str) {
+        while let Some(stage_name) = self.run_next_stage() {
+            if stage_name == name {
+                break;
+            }
+        }
+    }
+
+    /// Run the next stage, and the hooks after it, returning the name of the stage,
+    /// or None if all stages have run.
+    pub fn run_next_stage(&mut self) -> Option<String> {
+        let stage = self.stages.get_mut(self.next_stage)?;
+        self.next_stage += 1;
+
+        let name = stage.name().to_string();
+        info!("Running stage {}", name);
+        stage.run(&mut self.context);
+        for hook in &mut self.hooks {
+            hook(&name, &mut self.context);
+        }
+        Some(name)
+    }
+
+    /// The planning artefacts produced so far.
+    pub fn context(&self) -> &GenerationContext {
+        &self.context
+    }
+
+    /// Mutable access to the planning artefacts, e.g. for adjusting the plan between stages.
+    pub fn context_mut(&mut self) -> &mut GenerationContext {
+        &mut self.context
+    }
+
+    /// Give back the world excerpt, with the settlement built into it.
+    pub fn into_excerpt(self) -> WorldExcerpt {
+        self.context.into_excerpt()
+    }
+
+    fn stage_index(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|stage| stage.name() == name)
+    }
+
+    fn insert_stage_at(&mut self, index: usize, stage: Box<dyn Stage>) {
+        self.stages.insert(index, stage);
+        if index < self.next_stage {
+            self.next_stage += 1;
+        }
+    }
+}
+
+// Generators must be movable to other threads, for running generations side by side.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<SettlementGenerator>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator() -> SettlementGenerator {
+        SettlementGenerator::new(WorldExcerpt::new(4, 4, 4), 0)
+    }
+
+    fn custom_stage() -> Box<dyn Stage> {
+        Box::new(FnStage::new("custom", |_| ()))
+    }
+
+    #[test]
+    fn insert_before_first_stage() {
+        let mut generator = generator();
+        let first = generator.stage_names()[0].to_string();
+        assert!(generator.insert_stage_before(&first, custom_stage()));
+        assert_eq!(vec!["custom", first.as_str()], generator.stage_names()[..2].to_vec());
+    }
+
+    #[test]
+    fn insert_after_last_stage() {
+        let mut generator = generator();
+        let last = generator.stage_names().last().unwrap().to_string();
+        assert!(generator.insert_stage_after(&last, custom_stage()));
+        let names = generator.stage_names();
+        assert_eq!(vec![last.as_str(), "custom"], names[names.len() - 2..].to_vec());
+    }
+
+    #[test]
+    fn insert_before_missing_stage() {
+        let mut generator = generator();
+        let count = generator.stage_names().len();
+        assert!(!generator.insert_stage_before("no_such_stage", custom_stage()));
+        assert_eq!(count, generator.stage_names().len());
+    }
+
+    #[test]
+    fn insert_after_missing_stage() {
+        let mut generator = generator();
+        let count = generator.stage_names().len();
+        assert!(!generator.insert_stage_after("no_such_stage", custom_stage()));
+        assert_eq!(count, generator.stage_names().len());
+    }
+
+    #[test]
+    fn insert_before_run_stages_keeps_next_stage() {
+        let mut generator = generator();
+        generator.next_stage = 2;
+        generator.insert_stage_at(1, custom_stage());
+        assert_eq!(3, generator.next_stage);
+    }
+
+    #[test]
+    fn insert_at_next_stage_runs_it_next() {
+        let mut generator = generator();
+        generator.next_stage = 2;
+        generator.insert_stage_at(2, custom_stage());
+        assert_eq!(2, generator.next_stage);
+        assert_eq!("custom", generator.stage_names()[2]);
+    }
+
+    #[test]
+    fn estimated_memory_of_one_excerpt() {
+        let block_bytes = 2 * 3 * 4 * std::mem::size_of::<Block>();
+        let feature_bytes = 2 * 4 * FEATURE_BYTES_PER_COLUMN;
+        assert_eq!(
+            block_bytes + feature_bytes,
+            SettlementGenerator::estimated_memory((2, 3, 4), &GenerationConfig::default(), 0),
+        );
+    }
+
+    #[test]
+    fn estimated_memory_counts_whitelist_and_extra_copies() {
+        let config = GenerationConfig {
+            block_whitelist: Some(vec!["stone".to_string()]),
+            ..Default::default()
+        };
+        let block_bytes = 2 * 3 * 4 * std::mem::size_of::<Block>();
+        let feature_bytes = 2 * 4 * FEATURE_BYTES_PER_COLUMN;
+        assert_eq!(
+            5 * block_bytes + feature_bytes,
+            SettlementGenerator::estimated_memory((2, 3, 4), &config, 3),
+        );
+    }
+}
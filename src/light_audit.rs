@@ -0,0 +1,273 @@
+//! Audit of the light levels in town after building: Every spot inside the town walls where
+//! monsters could spawn in the dark gets lit, in the streets and yards as well as inside
+//! the houses, with torches on the walls, lanterns hung from the ceilings, or lights
+//! standing on the ground. The roads and doorways are kept clear, with lamp posts by the
+//! side of them instead.
+//!
+//! As in the game, the light spreads from each light through all but full blocks, one
+//! level dimmer for each step, so that the walls keep the light of a room inside it.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryInto;
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::positioning::{Direction, Surface2, Surface4, Surface5};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_palette::BlockPalette;
+use crate::boundary;
+use crate::geometry::{self, point_position_relative_to_polygon, InOutSide};
+use crate::street_detail;
+use crate::types::Snake;
+
+/// Lowest light level at which monsters can not spawn.
+const LIGHT_LEVEL_MIN: i64 = 8;
+/// Light level given off by a lantern, campfire or lava.
+const LANTERN_BRIGHTNESS: i64 = 15;
+/// Light level given off by a torch.
+const TORCH_BRIGHTNESS: i64 = 14;
+/// Farthest the light from any light reaches, for keeping monsters away.
+const LIGHT_REACH: i64 = LANTERN_BRIGHTNESS - LIGHT_LEVEL_MIN + 1;
+/// Highest above the ground a ceiling may be, for hanging a lantern from it.
+const CEILING_HEIGHT_MAX: i64 = 4;
+/// Farthest from a dark spot on a road or in a doorway to look for room for a lamp post.
+const LAMP_POST_REACH: i64 = 4;
+
+/// The light levels in the excerpt, high enough to keep monsters away.
+#[derive(Default)]
+struct LightMap {
+    levels: HashMap<(i64, i64, i64), i64>,
+}
+
+impl LightMap {
+    /// Spread the light from a light at the coordinates, through all but full blocks,
+    /// until it is too dim to keep monsters away.
+    fn add_light(&mut self, excerpt: &WorldExcerpt, coordinates: BlockCoord, brightness: i64) {
+        let mut queue: VecDeque<(BlockCoord, i64)> = VecDeque::new();
+        queue.push_back((coordinates, brightness));
+        while let Some((coordinates, level)) = queue.pop_front() {
+            let key = (coordinates.0, coordinates.1, coordinates.2);
+            if self.levels.get(&key).map_or(false, |known| *known >= level) {
+                continue;
+            }
+            self.levels.insert(key, level);
+            if level - 1 < LIGHT_LEVEL_MIN {
+                continue;
+            }
+            for offset in &neighbour_offsets() {
+                let next = coordinates + *offset;
+                let block = excerpt.block_at(next);
+                if block.is_some() && !is_full_block(block) {
+                    queue.push_back((next, level - 1));
+                }
+            }
+        }
+    }
+
+    /// Light level at the coordinates, from the lights reaching them.
+    fn light_level_at(&self, coordinates: BlockCoord) -> i64 {
+        self.levels.get(&(coordinates.0, coordinates.1, coordinates.2)).copied().unwrap_or(0)
+    }
+}
+
+/// Light every spot inside the town `circumferences` where monsters could spawn, until the
+/// light level everywhere is at least `LIGHT_LEVEL_MIN`. The `roads` and the `entrances`
+/// are kept clear, with lamp posts beside them instead of lights standing on them.
+/// Returns the number of lights placed.
+pub fn light_dark_spots(
+    excerpt: &mut WorldExcerpt,
+    circumferences: &[&Snake],
+    roads: &HashSet<BlockColumnCoord>,
+    entrances: &[BlockColumnCoord],
+    palette: &BlockPalette,
+) -> usize {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut lights: Vec<(BlockCoord, i64)> = Vec::new();
+    let mut spots: Vec<BlockCoord> = Vec::new();
+
+    for circumference in circumferences {
+        let (min, max) = boundary::bounding_box(circumference);
+        for x in (min.0 - LIGHT_REACH).max(0)..=(max.0 + LIGHT_REACH).min(x_len as i64 - 1) {
+            for z in (min.1 - LIGHT_REACH).max(0)..=(max.1 + LIGHT_REACH).min(z_len as i64 - 1) {
+                let is_inside = point_position_relative_to_polygon(BlockColumnCoord(x, z), circumference)
+                    == InOutSide::Inside;
+                for y in 1..y_len as i64 - 1 {
+                    let coordinates = BlockCoord(x, y, z);
+                    let block = excerpt.block_at(coordinates);
+                    if let Some(brightness) = block.and_then(brightness) {
+                        lights.push((coordinates, brightness));
+                    }
+                    if is_inside
+                        && block.map_or(false, is_room_for_spawning)
+                        && excerpt.block_at(coordinates + BlockCoord(0, 1, 0)) == Some(&Block::Air)
+                        && is_full_block(excerpt.block_at(coordinates - BlockCoord(0, 1, 0)))
+                    {
+                        spots.push(coordinates);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut light_map = LightMap::default();
+    for (coordinates, brightness) in lights {
+        light_map.add_light(excerpt, coordinates, brightness);
+    }
+
+    let mut count = 0;
+    for spot in spots {
+        if light_map.light_level_at(spot) >= LIGHT_LEVEL_MIN {
+            continue;
+        }
+        if let Some((coordinates, brightness)) = place_light(excerpt, spot, roads, entrances, palette) {
+            light_map.add_light(excerpt, coordinates, brightness);
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Light the spot: With a torch on a wall next to it at head height, a lantern hung from
+/// the ceiling above it, or else a light standing on the spot itself. On the roads and in
+/// the doorways, a lamp post nearby takes the place of the light standing on the spot.
+/// Returns where the light was placed, and how bright it is, or `None` if there was no
+/// room for it.
+fn place_light(
+    excerpt: &mut WorldExcerpt,
+    spot: BlockCoord,
+    roads: &HashSet<BlockColumnCoord>,
+    entrances: &[BlockColumnCoord],
+    palette: &BlockPalette,
+) -> Option<(BlockCoord, i64)> {
+    let head = spot + BlockCoord(0, 1, 0);
+    for direction in &[Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
+        if is_full_block(excerpt.block_at(head + geometry::offset(*direction))) {
+            let attached: Surface5 = Direction::from(*direction)
+                .try_into()
+                .expect("Converting from Surface4 to Surface5 should be safe.");
+            excerpt.set_block_at(head, Block::Torch { attached });
+            return Some((head, TORCH_BRIGHTNESS));
+        }
+    }
+
+    for height in 2..CEILING_HEIGHT_MAX {
+        let coordinates = spot + BlockCoord(0, height, 0);
+        if excerpt.block_at(coordinates) != Some(&Block::Air) {
+            break;
+        }
+        if is_full_block(excerpt.block_at(coordinates + BlockCoord(0, 1, 0))) {
+            excerpt.set_block_at(coordinates, Block::Lantern { mounted_at: Surface2::Up, waterlogged: false });
+            return Some((coordinates, LANTERN_BRIGHTNESS));
+        }
+    }
+
+    if is_kept_clear(spot.into(), roads, entrances) {
+        return place_lamp_post_near(excerpt, spot, roads, entrances);
+    }
+
+    let light = palette.standing_light();
+    let brightness = brightness(&light).unwrap_or(TORCH_BRIGHTNESS);
+    excerpt.set_block_at(spot, light);
+    Some((spot, brightness))
+}
+
+/// Put up a lamp post as close to the spot as there is room for it, off the roads and
+/// away from the entrances. Returns where the lantern on top of the post is, and how
+/// bright it is, or `None` if there was no room for a lamp post nearby.
+fn place_lamp_post_near(
+    excerpt: &mut WorldExcerpt,
+    spot: BlockCoord,
+    roads: &HashSet<BlockColumnCoord>,
+    entrances: &[BlockColumnCoord],
+) -> Option<(BlockCoord, i64)> {
+    let mut columns: Vec<BlockColumnCoord> = (-LAMP_POST_REACH..=LAMP_POST_REACH)
+        .flat_map(|dx| {
+            (-LAMP_POST_REACH..=LAMP_POST_REACH).map(move |dz| BlockColumnCoord(spot.0 + dx, spot.2 + dz))
+        })
+        .filter(|column| !is_kept_clear(*column, roads, entrances))
+        .collect();
+    columns.sort_by_key(|column| (geometry::manhattan_distance(*column, spot.into()), column.0, column.1));
+
+    for column in columns {
+        for dy in &[0, 1, -1] {
+            let coordinates = BlockCoord(column.0, spot.1 + dy, column.1);
+            if street_detail::place_lamp_post(excerpt, coordinates, entrances) {
+                let lantern = coordinates + BlockCoord(0, street_detail::LAMP_POST_HEIGHT, 0);
+                return Some((lantern, LANTERN_BRIGHTNESS));
+            }
+        }
+    }
+    None
+}
+
+/// Whether the column is to be kept clear of lights standing on the ground: On a road,
+/// or right by an entrance.
+fn is_kept_clear(column: BlockColumnCoord, roads: &HashSet<BlockColumnCoord>, entrances: &[BlockColumnCoord]) -> bool {
+    roads.contains(&column) || entrances.iter().any(|entrance| geometry::manhattan_distance(*entrance, column) <= 1)
+}
+
+/// The light level given off by the block, if it is a light.
+fn brightness(block: &Block) -> Option<i64> {
+    match block {
+        Block::Lantern { .. } | Block::Campfire { .. } | Block::LavaSource | Block::Lava { .. } => {
+            Some(LANTERN_BRIGHTNESS)
+        }
+        Block::Torch { .. } => Some(TORCH_BRIGHTNESS),
+        _ => None,
+    }
+}
+
+/// Whether a monster could stand in the block: Air, or low plants.
+fn is_room_for_spawning(block: &Block) -> bool {
+    matches!(block, Block::Air | Block::Flower { .. } | Block::Grass { .. })
+}
+
+/// Whether the block is full and solid, for monsters to spawn on top of, and for lights
+/// to be fixed to.
+fn is_full_block(block: Option<&Block>) -> bool {
+    match block {
+        None | Some(Block::Air) | Some(Block::None) => false,
+        Some(block) => !block.is_foilage() && !matches!(
+            block,
+            Block::WaterSource
+                | Block::Water { .. }
+                | Block::LavaSource
+                | Block::Lava { .. }
+                | Block::Glass { .. }
+                | Block::Slab { .. }
+                | Block::Stairs { .. }
+                | Block::Fence { .. }
+                | Block::FenceGate { .. }
+                | Block::Door { .. }
+                | Block::Bed { .. }
+                | Block::Chest { .. }
+                | Block::Farmland { .. }
+                | Block::Cauldron { .. }
+                | Block::IronBars { .. }
+                | Block::Chain { .. }
+                | Block::Ladder { .. }
+                | Block::Scaffolding { .. }
+                | Block::Lantern { .. }
+                | Block::Torch { .. }
+                | Block::Campfire { .. }
+                | Block::Sign { .. }
+                | Block::Banner { .. }
+                | Block::FlowerPot { .. }
+                | Block::Snow { .. }
+                | Block::Vines { .. }
+                | Block::Cobweb
+        ),
+    }
+}
+
+fn neighbour_offsets() -> [BlockCoord; 6] {
+    [
+        BlockCoord(1, 0, 0),
+        BlockCoord(-1, 0, 0),
+        BlockCoord(0, 1, 0),
+        BlockCoord(0, -1, 0),
+        BlockCoord(0, 0, 1),
+        BlockCoord(0, 0, -1),
+    ]
+}
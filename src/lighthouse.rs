@@ -0,0 +1,314 @@
+//! Lighthouse on a promontory by the sea, with the keeper's cottage beside it,
+//! and a path from there to the harbour.
+
+use image::GrayImage;
+use log::info;
+use mcprogedit::block::{Block, Door, DoorHalf, Hinge};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{DoorMaterial, Material};
+use mcprogedit::positioning::{Surface2, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_palette::BlockPalette;
+use crate::features::Features;
+use crate::geometry::{self, disc, is_in_disc, square, step};
+use crate::harbor::Harbor;
+use crate::pathfinding::{self, RoadPath};
+use crate::signs;
+use crate::tree;
+use crate::types::Snake;
+
+/// Radius of the lower half of the tower, measured to the middle of the wall.
+const BASE_RADIUS: i64 = 3;
+/// Radius of the upper half of the tower.
+const TOP_RADIUS: i64 = 2;
+/// Height of the floor of the lamp room, above the ground floor.
+const TOWER_HEIGHT: i64 = 18;
+/// Height of the glass walls of the lamp room.
+const LAMP_ROOM_HEIGHT: i64 = 3;
+/// Half the width of the square keeper's cottage.
+const COTTAGE_HALF_WIDTH: i64 = 2;
+/// Height of the cottage walls, above the floor.
+const COTTAGE_HEIGHT: i64 = 3;
+/// Distance from the middle of the tower to the middle of the cottage.
+const COTTAGE_DISTANCE: i64 = BASE_RADIUS + COTTAGE_HALF_WIDTH + 2;
+/// Farthest from the harbour the lighthouse may be.
+const HARBOR_DISTANCE_MAX: f32 = 128.0;
+/// Distance from the site to the ring of columns checked for water, to find promontories.
+const PROMONTORY_RADIUS: f32 = 12.0;
+/// Number of directions checked for water around a promontory.
+const PROMONTORY_DIRECTIONS: usize = 16;
+/// Smallest number of directions with water, for a site to count as a promontory.
+const PROMONTORY_WATER_MIN: usize = 10;
+/// Closest to a fishery the lighthouse may be.
+const FISHERY_DISTANCE_MIN: f32 = 16.0;
+/// Largest difference in ground height under the tower and the cottage.
+const SITE_SLOPE_MAX: i64 = 3;
+
+/// Positions of the spiral stairs around the central column, in (along, across) steps
+/// from the middle of the tower, in the order they are climbed.
+const SPIRAL: [(i64, i64); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+/// A lighthouse, its keeper's cottage, and the path from there to the harbour.
+#[derive(Clone, Debug)]
+pub struct Lighthouse {
+    /// Middle of the tower.
+    pub center: BlockColumnCoord,
+    /// Y coordinate of the ground floor of the tower and the cottage.
+    pub floor_y: i64,
+    /// Direction of the doors, towards the harbour.
+    pub front: Surface4,
+    /// Path from the front of the tower to the quay of the harbour, if one was found.
+    pub path: Option<RoadPath>,
+}
+
+/// Find a promontory near the harbour, reaching out into the water, for a lighthouse.
+pub fn plan_lighthouse(features: &Features, harbor: &Harbor, wall_circles: &[&Snake]) -> Option<Lighthouse> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+    let quay_middle = harbor.quay.get(harbor.quay.len() / 2)?.coordinates;
+
+    let mut towns = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut towns, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+
+    // Dry columns with water on most sides, most surrounded first
+    let margin = PROMONTORY_RADIUS.ceil() as i64 + 1;
+    let mut candidates: Vec<(usize, BlockColumnCoord)> = Vec::new();
+    for x in margin..x_len - margin {
+        for z in margin..z_len - margin {
+            let center = BlockColumnCoord(x, z);
+            if features.water[(x as u32, z as u32)] == image::Luma([255u8])
+                || towns[(x as u32, z as u32)] == image::Luma([255u8])
+                || geometry::euclidean_distance(center, quay_middle.into()) > HARBOR_DISTANCE_MAX
+                || harbor.fisheries.iter().any(|fishery| {
+                    geometry::euclidean_distance(center, fishery.front_middle) < FISHERY_DISTANCE_MIN
+                })
+            {
+                continue;
+            }
+            let water_directions = water_directions(features, center);
+            if water_directions >= PROMONTORY_WATER_MIN {
+                candidates.push((water_directions, center));
+            }
+        }
+    }
+    candidates.sort_by_key(|(water_directions, center)| {
+        (std::cmp::Reverse(*water_directions), geometry::manhattan_distance(*center, quay_middle.into()))
+    });
+
+    for (_, center) in candidates {
+        let front = signs::surface_from_vector(
+            (quay_middle.0 - center.0) as f32,
+            (quay_middle.2 - center.1) as f32,
+        );
+        let site = site_columns(center, front);
+        let floor_y = match features.site_floor(&towns, &site, SITE_SLOPE_MAX) {
+            Some(floor_y) => floor_y,
+            None => continue,
+        };
+
+        let path_start = step(center, front, BASE_RADIUS + 2);
+        let image::Luma([start_y]) = features.terrain[(path_start.0 as u32, path_start.1 as u32)];
+        let path = pathfinding::road_path(
+            BlockCoord(path_start.0, start_y as i64, path_start.1),
+            quay_middle,
+            &features.terrain,
+            Some(&features.water),
+        );
+
+        info!("Planned a lighthouse at {:?}.", center);
+        return Some(Lighthouse { center, floor_y, front, path });
+    }
+
+    None
+}
+
+/// Number of directions from `center` in which there is water, a bit out from the shore.
+fn water_directions(features: &Features, center: BlockColumnCoord) -> usize {
+    (0..PROMONTORY_DIRECTIONS)
+        .filter(|direction| {
+            let angle = *direction as f32 * std::f32::consts::TAU / PROMONTORY_DIRECTIONS as f32;
+            let x = center.0 + (angle.cos() * PROMONTORY_RADIUS).round() as i64;
+            let z = center.1 + (angle.sin() * PROMONTORY_RADIUS).round() as i64;
+            features.water[(x as u32, z as u32)] == image::Luma([255u8])
+        })
+        .count()
+}
+
+/// The columns covered by the tower at `center`, and by the cottage beside it.
+fn site_columns(center: BlockColumnCoord, front: Surface4) -> Vec<(i64, i64)> {
+    let cottage = step(center, front.rotated_90_cw(), COTTAGE_DISTANCE);
+
+    let mut columns = disc(center, BASE_RADIUS);
+    columns.extend(square(cottage, COTTAGE_HALF_WIDTH));
+    columns
+}
+
+/// Build the lighthouse tower and the keeper's cottage.
+pub fn build_lighthouse(
+    excerpt: &mut WorldExcerpt,
+    lighthouse: &Lighthouse,
+    features: &Features,
+    palette: &BlockPalette,
+) {
+    build_tower(excerpt, lighthouse, features);
+    build_cottage(excerpt, lighthouse, features, palette);
+}
+
+/// Tapered stone tower, with spiral stairs around a central column up to the lamp room.
+fn build_tower(excerpt: &mut WorldExcerpt, lighthouse: &Lighthouse, features: &Features) {
+    let BlockColumnCoord(cx, cz) = lighthouse.center;
+    let floor_y = lighthouse.floor_y;
+    let top_y = floor_y + TOWER_HEIGHT;
+    let roof_y = top_y + LAMP_ROOM_HEIGHT + 1;
+
+    // Positions within the tower, `along` towards the front and `across` to the right.
+    let side = geometry::offset(lighthouse.front.rotated_90_cw());
+    let ahead = geometry::offset(lighthouse.front);
+    let at = |along: i64, across: i64, y: i64| {
+        BlockCoord(cx + ahead.0 * along + side.0 * across, y, cz + ahead.2 * along + side.2 * across)
+    };
+
+    // Foundation, and the walls, narrowing halfway up
+    for (x, z) in disc(lighthouse.center, BASE_RADIUS) {
+        let (dx, dz) = (x - cx, z - cz);
+        let image::Luma([ground]) = features.terrain[(x as u32, z as u32)];
+        for y in ground as i64 - 1..floor_y {
+            excerpt.set_block_at(BlockCoord(x, y, z), Block::StoneBricks);
+        }
+        excerpt.set_block_at(BlockCoord(x, floor_y, z), Block::StoneBricks);
+
+        for y in floor_y + 1..=roof_y {
+            tree::chop(excerpt, BlockCoord(x, y, z));
+            let radius = if y < floor_y + TOWER_HEIGHT / 2 { BASE_RADIUS } else { TOP_RADIUS };
+            let block = if !is_in_disc((dx, dz), radius) {
+                Block::Air
+            } else if !is_in_disc((dx, dz), radius - 1) && y < top_y {
+                // Bands of andesite in the stone brick walls
+                if (y - floor_y) % 6 == 5 { Block::Andesite } else { Block::StoneBricks }
+            } else {
+                Block::Air
+            };
+            excerpt.set_block_at(BlockCoord(x, y, z), block);
+        }
+    }
+
+    // Central column, carrying the lamp
+    for y in floor_y + 1..=top_y {
+        excerpt.set_block_at(BlockCoord(cx, y, cz), Block::StoneBricks);
+    }
+
+    // Floor of the lamp room, with an opening above the last few stairs
+    let stair_count = top_y - floor_y;
+    let stair_at = |step: i64| SPIRAL[(step % SPIRAL.len() as i64) as usize];
+    for (x, z) in disc(lighthouse.center, TOP_RADIUS) {
+        excerpt.set_block_at(BlockCoord(x, top_y, z), Block::StoneBricks);
+    }
+    for step in stair_count - 3..stair_count - 1 {
+        let (along, across) = stair_at(step);
+        excerpt.set_block_at(at(along, across, top_y), Block::Air);
+    }
+
+    // Spiral stairs, one step up for each step around the column
+    for step in 0..stair_count {
+        let (along, across) = stair_at(step);
+        let (next_along, next_across) = stair_at(step + 1);
+        let coordinates = at(along, across, floor_y + 1 + step);
+        let next = at(next_along, next_across, floor_y + 1 + step);
+        let upwards = signs::surface_from_vector(
+            (next.0 - coordinates.0) as f32,
+            (next.2 - coordinates.2) as f32,
+        );
+        excerpt.set_block_at(coordinates, Block::stairs(upwards.into(), Material::StoneBrick));
+    }
+
+    // Lamp room with glass walls, a lantern on top of the column, and a stone roof
+    for (x, z) in disc(lighthouse.center, TOP_RADIUS) {
+        let (dx, dz) = (x - cx, z - cz);
+        if !is_in_disc((dx, dz), TOP_RADIUS - 1) {
+            for y in top_y + 1..=top_y + LAMP_ROOM_HEIGHT {
+                excerpt.set_block_at(BlockCoord(x, y, z), Block::Glass { colour: None });
+            }
+        }
+        excerpt.set_block_at(BlockCoord(x, roof_y, z), Block::StoneBricks);
+    }
+    excerpt.set_block_at(
+        BlockCoord(cx, top_y + 1, cz),
+        Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+    );
+    excerpt.set_block_at(
+        BlockCoord(cx, roof_y - 1, cz),
+        Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+    );
+    excerpt.set_block_at(BlockCoord(cx, roof_y + 1, cz), Block::bottom_slab(Material::StoneBrick));
+
+    // Door towards the harbour
+    build_door(excerpt, at(BASE_RADIUS, 0, floor_y + 1), lighthouse.front);
+}
+
+/// Small cottage for the keeper, beside the tower, with its door towards the harbour.
+fn build_cottage(
+    excerpt: &mut WorldExcerpt,
+    lighthouse: &Lighthouse,
+    features: &Features,
+    palette: &BlockPalette,
+) {
+    let center = step(lighthouse.center, lighthouse.front.rotated_90_cw(), COTTAGE_DISTANCE);
+    let floor_y = lighthouse.floor_y;
+    let roof_y = floor_y + COTTAGE_HEIGHT + 1;
+
+    for (x, z) in square(center, COTTAGE_HALF_WIDTH) {
+        let is_wall = (x - center.0).abs() == COTTAGE_HALF_WIDTH || (z - center.1).abs() == COTTAGE_HALF_WIDTH;
+        let image::Luma([ground]) = features.terrain[(x as u32, z as u32)];
+        for y in ground as i64 - 1..floor_y {
+            excerpt.set_block_at(BlockCoord(x, y, z), palette.foundation.clone());
+        }
+        excerpt.set_block_at(BlockCoord(x, floor_y, z), palette.floor.clone());
+
+        for y in floor_y + 1..roof_y {
+            tree::chop(excerpt, BlockCoord(x, y, z));
+            let block = if is_wall {
+                if y == floor_y + 2 && (x - center.0).abs() != (z - center.1).abs() {
+                    palette.flat_window.clone()
+                } else {
+                    palette.wall.clone()
+                }
+            } else {
+                Block::Air
+            };
+            excerpt.set_block_at(BlockCoord(x, y, z), block);
+        }
+        tree::chop(excerpt, BlockCoord(x, roof_y, z));
+        excerpt.set_block_at(BlockCoord(x, roof_y, z), palette.roof.clone());
+    }
+
+    // Sea chest against the back wall, and a lantern in the ceiling
+    let back = lighthouse.front.opposite();
+    let inside_back = step(center, back, COTTAGE_HALF_WIDTH - 1);
+    excerpt.set_block_at(
+        BlockCoord(inside_back.0, floor_y + 1, inside_back.1),
+        Block::chest(lighthouse.front),
+    );
+    excerpt.set_block_at(
+        BlockCoord(center.0, roof_y - 1, center.1),
+        Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+    );
+
+    let door = step(center, lighthouse.front, COTTAGE_HALF_WIDTH);
+    build_door(excerpt, BlockCoord(door.0, floor_y + 1, door.1), lighthouse.front);
+}
+
+/// A door in the wall, leading out towards `outwards`.
+fn build_door(excerpt: &mut WorldExcerpt, coordinates: BlockCoord, outwards: Surface4) {
+    for (y, half) in &[(0, DoorHalf::Lower), (1, DoorHalf::Upper)] {
+        excerpt.set_block_at(coordinates + BlockCoord(0, *y, 0), Block::Door(Door {
+            material: DoorMaterial::Spruce,
+            facing: outwards.opposite(),
+            half: *half,
+            hinged_at: Hinge::Right,
+            open: false,
+        }));
+    }
+}
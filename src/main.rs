@@ -3,565 +3,287 @@
 extern crate clap;
 extern crate mcprogedit;
 
-mod areas;
-mod block_palette;
-mod build_area;
-mod features;
-mod geometry;
-mod line;
-mod partitioning;
-mod pathfinding;
-mod plot;
-mod road;
-mod room_interior;
-mod structure_builder;
-mod tree;
-mod types;
-mod wall;
-mod walled_town;
-
-use std::cmp::min;
-use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
-use log::{error, info, LevelFilter};
+use log::{error, info, warn, LevelFilter};
 use simple_logger::SimpleLogger;
 
-use imageproc::stats::histogram;
-use mcprogedit::block::{Block, Log};
-use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
-use mcprogedit::material::{CoralMaterial, WoodMaterial};
+use mcprogedit::coordinates::BlockColumnCoord;
 use mcprogedit::world_excerpt::WorldExcerpt;
 
-use crate::areas::*;
-use crate::block_palette::BlockPalette;
-use crate::features::*;
-use crate::geometry::{extract_blocks, LandUsageGraph};
-use crate::partitioning::divide_town_into_blocks;
-use crate::plot::divide_city_block;
-use crate::road::roads_split;
-use crate::walled_town::*;
+use leifsbu::{boundary, export_verification, geojson_export, html_report, plan_export, render};
+use leifsbu::types::Snake;
+use leifsbu::config::{Dimension, SettlementStyle};
+use leifsbu::{CommandHook, GenerationConfig, Inspection, SettlementGenerator};
 
 fn main() {
+    let matches = matches();
+
     // Initialize logging
-    SimpleLogger::new().with_level(LevelFilter::Warn).init().unwrap();
+    let log_level = if matches.is_present("quiet") {
+        LevelFilter::Error
+    } else {
+        match matches.occurrences_of("verbose") {
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    SimpleLogger::new().with_level(log_level).init().unwrap();
 
     // Read arguments
     // **************
-    let matches = matches();
     let input_directory = matches.value_of("input_save").unwrap_or(".");
     let output_directory = matches.value_of("output_save").unwrap_or(input_directory);
-    let x = matches.value_of("x").map(parse_i64_or_exit).unwrap();
+    let plan_output = matches.value_of("plan_output");
+    let geojson_output = matches.value_of("geojson_output");
+    let report_output = matches.value_of("report_output");
+    let inspection_directory = matches.value_of("inspection_directory");
+    let verify_export = matches.is_present("verify_export");
+    let hook_command = matches.value_of("hook");
+    let hook_arguments: Vec<String> = matches
+        .values_of("hook_arg")
+        .map(|arguments| arguments.map(str::to_string).collect())
+        .unwrap_or_default();
+    let hook_stages: Vec<String> = matches
+        .values_of("hook_stage")
+        .map(|stages| stages.map(str::to_string).collect())
+        .unwrap_or_default();
+    let max_memory = matches.value_of("max_memory").map(parse_u64_or_exit);
+    let boundary_polygon = matches.value_of("boundary").map(read_boundary_or_exit);
+    let bounding_box = boundary_polygon.as_ref().map(|polygon| boundary::bounding_box(polygon));
+    let x = matches.value_of("x").map(parse_i64_or_exit)
+        .or_else(|| bounding_box.map(|(min, _)| min.0))
+        .unwrap();
     let y = matches.value_of("y").map(parse_i64_or_exit).unwrap_or(0);
-    let z = matches.value_of("z").map(parse_i64_or_exit).unwrap();
-    let x_len = matches.value_of("dx").map(parse_i64_or_exit).unwrap();
+    let z = matches.value_of("z").map(parse_i64_or_exit)
+        .or_else(|| bounding_box.map(|(min, _)| min.1))
+        .unwrap();
+    let x_len = matches.value_of("dx").map(parse_i64_or_exit)
+        .or_else(|| bounding_box.map(|(min, max)| max.0 - min.0 + 1))
+        .unwrap();
     let y_len = matches
         .value_of("dy")
         .map(parse_i64_or_exit)
         .unwrap_or(255 - y);
-    let z_len = matches.value_of("dz").map(parse_i64_or_exit).unwrap();
+    let z_len = matches.value_of("dz").map(parse_i64_or_exit)
+        .or_else(|| bounding_box.map(|(min, max)| max.1 - min.1 + 1))
+        .unwrap();
+    let mut config = matches
+        .value_of("config")
+        .map(read_config_or_exit)
+        .unwrap_or_default();
+    if let Some(max_towns) = matches.value_of("towns").map(parse_u64_or_exit) {
+        config.max_towns = max_towns as usize;
+    }
+    if matches.is_present("strict_palette") {
+        config.strict_palette = true;
+    }
+    if matches.is_present("adventure") {
+        config.adventure_mode = true;
+    }
+    if matches.is_present("night") {
+        config.night_lighting = true;
+    }
+    match matches.value_of("style") {
+        Some("town") => config.style = SettlementStyle::Town,
+        Some("cliff") => config.style = SettlementStyle::Cliff,
+        Some("ruins") => config.style = SettlementStyle::Ruins,
+        _ => (),
+    }
+    match matches.value_of("dimension") {
+        Some("overworld") => config.dimension = Dimension::Overworld,
+        Some("nether") => config.dimension = Dimension::Nether,
+        _ => (),
+    }
+    let input_directory = config.dimension.directory(Path::new(input_directory));
+    let output_directory = config.dimension.directory(Path::new(output_directory));
+    let given_seed = matches.value_of("seed").map(parse_u64_or_exit);
+    let seed = given_seed.unwrap_or_else(rand::random);
+
+    // Before and after render
+    // ***********************
+    if let Some(diff_matches) = matches
+        .subcommand_matches("render")
+        .and_then(|render_matches| render_matches.subcommand_matches("diff"))
+    {
+        let image_path = diff_matches.value_of("image").unwrap();
+        if output_directory == input_directory {
+            error!("Rendering a diff needs an output save (-o) other than the input save, to compare against.");
+            std::process::exit(1);
+        }
+        let layout = if diff_matches.is_present("blend") {
+            render::DiffLayout::Blended
+        } else {
+            render::DiffLayout::SideBySide
+        };
+        info!("Rendering {:?} before and {:?} after", input_directory, output_directory);
+        let before = WorldExcerpt::from_save(
+            (x, y, z).into(),
+            (x + x_len - 1, y + y_len - 1, z + z_len - 1).into(),
+            &input_directory,
+        );
+        let after = WorldExcerpt::from_save(
+            (x, y, z).into(),
+            (x + x_len - 1, y + y_len - 1, z + z_len - 1).into(),
+            &output_directory,
+        );
+        let diff = render::render_diff(&before, &after, layout);
+        info!("{} of {} columns were modified.", diff.modified_count, diff.column_count);
+        if let Err(error) = diff.image.save(image_path) {
+            error!("Unable to save render to {}: {}", image_path, error);
+            std::process::exit(1);
+        }
+        return;
+    }
 
+    // Refuse areas too large for the memory allowed, before importing them
+    if let Some(max_memory) = max_memory {
+        let dimensions = (x_len as usize, y_len as usize, z_len as usize);
+        // The imported excerpt and the excerpt read back when verifying the export, and the
+        // excerpt read back from a hook while the one written out is still held
+        let extra_copies = 2 * verify_export as usize + hook_command.is_some() as usize;
+        let estimate = SettlementGenerator::estimated_memory(dimensions, &config, extra_copies) / (1024 * 1024) + 1;
+        if estimate > max_memory as usize {
+            error!(
+                "Generating in an area of {:?} blocks needs about {} MiB, more than the {} MiB allowed.",
+                dimensions,
+                estimate,
+                max_memory,
+            );
+            std::process::exit(1);
+        }
+        info!("Generating in an area of {:?} blocks needs about {} MiB.", dimensions, estimate);
+    }
 
     // World import
     // ************
     info!("Importing from {:?}", input_directory);
-    let mut excerpt = WorldExcerpt::from_save(
+    let excerpt = WorldExcerpt::from_save(
         (x, y, z).into(),
         (x + x_len - 1, y + y_len - 1, z + z_len - 1).into(),
-        Path::new(input_directory),
+        &input_directory,
     );
     info!("Imported world excerpt of dimensions {:?}", excerpt.dim());
 
+    // A copy of the excerpt as imported, for verifying only the blocks changed on export
+    let imported_excerpt = if verify_export {
+        let (x_len, y_len, z_len) = excerpt.dim();
+        Some(WorldExcerpt::from_world_excerpt((0, 0, 0), (x_len - 1, y_len - 1, z_len - 1), &excerpt))
+    } else {
+        None
+    };
 
-    // Initial information extraction
-    // ******************************
-    let player_location: BlockColumnCoord = (x_len / 2, z_len / 2).into();
-
-    // Extract features
-    let features = Features::new_from_world_excerpt(&excerpt);
-
-    // Find areas suitable for various purposes (based on features)
-    let areas = Areas::new_from_features(&features);
-
-
-    // Decide on area usage
-    // ********************
-
-    // Some thoughts:
-    // - Fields on fertile, reasonably flat, open land
-    // - Wind mills on hills within or by fertile land
-    // - Fields closer to wind mills are predominantly wheat fields
-    // - Livestock on fertile, flat to half-steep, open to semi-open land
-    // - Forestry on forested land
-    // - Mining on exposed rock, either surface (quarry) or hillside (mining tunnel)
-    // - Fishing on shorelines with access to sea
-    // - Infrastructure: Maybe connect "traversable" areas through bridges, tunnels, etc?
-    // - Town is complicated. Can to some extent displace fields/livestock/forest
-
-    // Find town location
-    let (town_circumference, town_center) = walled_town_contour(&features, &areas);
-
-    // Get full wall circle, by copying the first node of the wall to the end.
-    let mut wall_circle = town_circumference.clone();
-    wall_circle.push(town_circumference[0]);
-
-    // Get town size
-    let town_area = geometry::area(&wall_circle);
-    info!("The found city has a total area of {} m².", town_area);
-
-    // TODO FUTURE WORK
-    // - Find primary sector areas (agriculture, fishing, forestry, mining)
-    // - Put major roads from primary sectors to town circumference
-    // - Actually, find more settlement locations as well,
-    //      and use some nice triangulation for connecting everything.
-    //      (e.g. Delaunay, Gabriel graph, or Relative neighbourhood graph.)
-
-    // Create road paths...
-    // TODO refactor: Move the path generation somewhere else?
-    // TODO to be replaced by other means of finding road start locations
-    let mut start_coordinates = vec![
-        // Paths from the four corners of the map
-        (0, 0),
-        (0, z_len - 1),
-        (x_len - 1, z_len - 1),
-        (x_len - 1, 0),
-    ];
-
-    if geometry::InOutSide::Outside == geometry::point_position_relative_to_polygon(player_location.clone(), &wall_circle) {
-        // Path from the player start location
-        start_coordinates.push((player_location.0, player_location.1));
-    }
-
-    let start_coordinates: Vec<_> = start_coordinates
-    .iter()
-    .map(|(x, z)| {
-        let image::Luma([y]) = features.terrain[(*x as u32, *z as u32)];
-        BlockCoord(*x, y as i64, *z)
-    })
-    .collect();
-
-    let image::Luma([goal_y]) = features.terrain[
-        (town_center.0 as u32, town_center.1 as u32)
-    ];
-    let goal = BlockCoord(town_center.0 as i64, goal_y as i64, town_center.1 as i64);
-
-    let mut road_path_image = features.coloured_map.clone();
-
-    let mut raw_roads = Vec::new();
-
-    for start in start_coordinates {
-        if let Some(path) = pathfinding::road_path(
-            start,
-            goal,
-            &features.terrain,
-            Some(
-                &imageproc::morphology::dilate(
-                    &features.water,
-                    imageproc::distance_transform::Norm::LInf,
-                    2,
-                )
-            ),
-        ) {
-            // Draw road on map
-            pathfinding::draw_road_path(&mut road_path_image, &path);
-
-            // Store road
-            raw_roads.push(path);
-        }
-    }
-
-    #[cfg(feature = "debug_images")]
-    road_path_image.save("road_path_001.png").unwrap();
-
-    // Split out the raw roads into city roads and country roads
-    let (mut city_roads, country_roads) = roads_split(&raw_roads, &wall_circle);
-
-    // Fill out with minor roads inside town
-    let mut streets =
-        divide_town_into_blocks(&town_circumference, &town_center, &city_roads, &features.terrain);
-
-
-    // Make land usage plan
-    // ********************
-
-    // Add intersection points between roads/streets and circumference,
-    // so that the geometry actually describes distinct areas.
-    geometry::add_intersection_points(&mut streets, &mut wall_circle);
-    geometry::add_intersection_points(&mut city_roads, &mut wall_circle);
-
-    // TODO decide width of streets/roads/walls based on total town area?
-    let mut land_usage_graph = LandUsageGraph::new();
-    land_usage_graph.add_roads(&streets, geometry::EdgeKind::Street, 2);
-    land_usage_graph.add_roads(&city_roads, geometry::EdgeKind::Road, 6);
-    land_usage_graph.add_circumference(&wall_circle, geometry::EdgeKind::Wall, 3);
-
-    // Get the polygons for each "city block"
-    let districts = extract_blocks(&land_usage_graph);
-
-    // Make images of the extracted city blocks (for debug visuals only)
-    for (colour, district) in districts.iter().enumerate() {
-        let mut district_image = image::ImageBuffer::new(x_len as u32, z_len as u32);
-        geometry::draw_area(
-            &mut district_image,
-            district,
-            BlockColumnCoord(0, 0),
-            image::Luma([63u8]),
-        );
-        partitioning::draw_offset_snake(
-            &mut district_image,
-            district,
-            &BlockColumnCoord(0, 0),
-            image::Luma([255u8]),
-        );
-
-        #[cfg(feature = "debug_images")]
-        district_image.save(format!("D-01 district {:0>2}.png", colour)).unwrap();
-
-        info!("District {} has area {}.", colour, geometry::area(district));
-    
-        let stats = histogram(&district_image);
-        let surface_area = stats.channels[0][63];
-        let border_area = stats.channels[0][255];
-        info!(
-            "District {} image areas: {} + ({} / 2) = {}",
-            colour, surface_area, border_area, surface_area + (border_area / 2)
-        );
-    }
-
-    // TODO Save only if debug images is enabled
-    //district_image.save("D-01 districts.png").unwrap();
-
-    // Split the city blocks
-    let mut plots = Vec::new();
-    for district in districts {
-        let mut district_plots = divide_city_block(&district, &land_usage_graph);
-        // TODO draw the plots or something...
-        info!("Found {} plots for a district.", district_plots.len());
-        plots.append(&mut district_plots);
-    }
-
-    let mut city_plan = features.coloured_map.clone();
-    for plot in &plots {
-        plot.draw(&mut city_plan);
-    }
-    for street in &streets {
-        pathfinding::draw_road_path(&mut city_plan, street);
+    // A random seed is logged even when info is not, for reproducing the settlement
+    if given_seed.is_some() {
+        info!("Generating with seed {}", seed);
+    } else {
+        warn!("Generating with random seed {}, use --seed {} to reproduce", seed, seed);
     }
-    for road in &country_roads {
-        pathfinding::draw_road_path(&mut city_plan, road);
+    let mut generator = SettlementGenerator::new_with_config(excerpt, seed, config);
+    if let Some(polygon) = boundary_polygon {
+        generator.set_boundary(boundary::relative_to(&polygon, BlockColumnCoord(x, z)));
     }
-    for road in &city_roads {
-        pathfinding::draw_road_path(&mut city_plan, road);
+    if let Some(inspection_directory) = inspection_directory {
+        if let Err(error) = std::fs::create_dir_all(inspection_directory) {
+            error!("Unable to create inspection directory {}: {}", inspection_directory, error);
+            std::process::exit(1);
+        }
+        generator.set_inspection(Inspection::to_directory(Path::new(inspection_directory)));
     }
-
-    #[cfg(feature = "debug_images")]
-    city_plan.save("city plan.png").unwrap();
-
-
-    // Find local materials
-    // ********************
-
-    // Survey the area inside and around town, to find local materials.
-    let (town_offset, town_dimensions) = partitioning::snake_bounding_box(&wall_circle);
-
-    let proximity_min_x = town_offset.0.saturating_sub(100);
-    let proximity_max_x = min(x_len, town_offset.0 + town_dimensions.0 + 100);
-    let proximity_min_z = town_offset.1.saturating_sub(100);
-    let proximity_max_z = min(z_len, town_offset.1 + town_dimensions.0 + 100);
-
-    let mut sand_count = 0;
-    let mut grass_count = 0;
-    let mut available_flowers = HashSet::new();
-    let mut wood_statistics = HashMap::new();
-
-    for x in proximity_min_x..proximity_max_x {
-        for z in proximity_min_z..proximity_max_z {
-            if let Some(terrain_y) = features.terrain_height_map.height_at(
-                (x as usize, z as usize)
-            ) {
-                for y in terrain_y-1..terrain_y+1 {
-                    match excerpt.block_at(BlockCoord(x, y as i64, z)) {
-                        // Make some statistics
-                        Some(Block::Sand) => sand_count += 1,
-                        Some(Block::GrassBlock) => grass_count += 1,
-                        Some(Block::Flower(flower)) => {
-                            available_flowers.insert(*flower);
-                        }
-                        Some(Block::Log(Log { material, .. })) => {
-                            *wood_statistics.entry(*material).or_insert(0) += 1;
-                        }
-                        _ => (),
-                    }
-                }
+    // The hook directory is removed when the guard is dropped, after the generation.
+    let mut hook_directory: Option<tempfile::TempDir> = None;
+    if let Some(hook_command) = hook_command {
+        let directory = tempfile::Builder::new()
+            .prefix("leifsbu-hook-")
+            .tempdir()
+            .unwrap_or_else(|error| {
+                error!("Unable to create hook directory: {}", error);
+                std::process::exit(1);
+            });
+        // Without any stages given, run the hook once, after the last stage.
+        let hook_stages = if hook_stages.is_empty() {
+            generator.stage_names().last().map(|name| vec![name.to_string()]).unwrap_or_default()
+        } else {
+            hook_stages
+        };
+        match CommandHook::new(hook_command, hook_arguments, directory.path()) {
+            Ok(hook) => {
+                info!("Running {:?} after the stages {:?}, in {:?}", hook_command, hook_stages, directory.path());
+                generator.add_hook(hook.with_stages(hook_stages).into_hook());
+            }
+            Err(error) => {
+                error!("Unable to set up hook: {}", error);
+                std::process::exit(1);
             }
         }
+        hook_directory = Some(directory);
     }
 
-    let mut wood_statistics: Vec<_> = wood_statistics.into_iter().collect();
-    wood_statistics.sort_by(|a, b| a.1.cmp(&b.1).reverse());
 
-    // wood_available to be used later, for replacing wall/roof materials in the
-    // block palette used for building individual houses.
-    let mut wood_available = Vec::new();
-    let max_wood_count = if let Some((_, count)) = wood_statistics.first() {
-        *count
-    } else {
-        0
-    };
-    for (wood, count) in wood_statistics {
-        if count >= max_wood_count / 50 {
-            wood_available.push(wood);
-        }
-    }
-    // Sort the woods by colour in order not to get too psychedelic.
-    wood_available.sort_by_key(|wood_material| match wood_material {
-        WoodMaterial::Acacia => 5,
-        WoodMaterial::Birch => 4,
-        WoodMaterial::DarkOak => 0,
-        WoodMaterial::Jungle => 3,
-        WoodMaterial::Oak => 2,
-        WoodMaterial::Spruce => 1,
-        _ => 6,
-    });
+    // Settlement generation
+    // *********************
+    generator.generate();
+    drop(hook_directory);
 
-    info!("Decided that {:?} are the common wood materials.", wood_available);
 
-    // Use found materials for a default block palette
-    let mut block_palette = BlockPalette {
-        flowers: available_flowers.clone().into_iter().collect(),
-        ..Default::default()
-    };
-
-    if sand_count > grass_count {
-        // Assume that we are in or close to a desert biome;
-        // Use sandstone instead of stone, for city wall and other "stone" structures.
-        block_palette.city_wall_coronation = Block::Sandstone;
-        block_palette.city_wall_main = Block::Sandstone;
-        block_palette.city_wall_top = Block::SmoothSandstone;
-        block_palette.foundation = Block::EndStoneBricks;
-        block_palette.floor = Block::SmoothSandstone;
-        block_palette.wall = Block::Sandstone;
+    // Plan export
+    // ***********
+    if let Some(plan_output) = plan_output {
+        info!("Exporting city plan to {:?}", plan_output);
+        if let Err(error) = plan_export::write_plan(generator.context(), Path::new(plan_output)) {
+            error!("Unable to export city plan: {}", error);
+        }
     }
-
-    info!(
-        "Found {} different flowers.",
-        available_flowers.len(),
-    );
-
-
-    // Build structures
-    // ****************
-
-    // Build that wall! (But who is going to pay for it?)
-    wall::build_wall(&mut excerpt, &wall_circle, &features, &block_palette);
-
-    // Build the various roads and streets...
-    // TODO Change road width depending on total town area?
-    let city_streets_cover = vec![
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Horn, dead: true },
-    ];
-    for street in streets {
-        road::build_road(&mut excerpt, &street, &features.terrain, 2, &city_streets_cover);
+    if let Some(geojson_output) = geojson_output {
+        info!("Exporting city plan as GeoJSON to {:?}", geojson_output);
+        if let Err(error) = geojson_export::write_geojson(generator.context(), Path::new(geojson_output)) {
+            error!("Unable to export city plan as GeoJSON: {}", error);
+        }
     }
-
-    let country_roads_cover = vec![
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Bubble, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Horn, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Tube, dead: true },
-        Block::CoarseDirt,
-        Block::CoarseDirt,
-        Block::CoarseDirt,
-    ];
-    for road in country_roads {
-        road::build_road(&mut excerpt, &road, &features.terrain, 3, &country_roads_cover);
+    if let Some(report_output) = report_output {
+        info!("Writing generation report to {:?}", report_output);
+        if let Err(error) = html_report::write_report(generator.context(), Path::new(report_output)) {
+            error!("Unable to write generation report: {}", error);
+        }
     }
 
-    let city_roads_cover = vec![
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::Gravel,
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Fire, dead: true },
-        Block::Andesite,
-        Block::Andesite,
-        Block::CoralBlock { material: CoralMaterial::Bubble, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Horn, dead: true },
-        Block::CoralBlock { material: CoralMaterial::Tube, dead: true },
-        Block::CrackedStoneBricks,
-        Block::CrackedStoneBricks,
-        Block::StoneBricks,
-        Block::Cobblestone,
-        Block::Cobblestone,
-    ];
-    for road in city_roads {
-        road::build_road(&mut excerpt, &road, &features.terrain, 4, &city_roads_cover);
-    }
 
-    // Build some structures (houses?) on the plots.
-    for (index, plot) in plots.iter().enumerate() {
-        // Skip every Nth plot
-        if index % 10 == 9 {
-            continue;
-        }
+    // World export
+    // ************
+    let excerpt = generator.into_excerpt();
+    info!("Exporting to {:?}", output_directory);
+    excerpt.to_save((x, y, z).into(), &output_directory);
+    info!("Exported world excerpt of dimensions {:?}", excerpt.dim());
 
-        if let Some(bounding_box) = plot.bounding_box() {
-            // Increase the size by 1, in order to provide at least one block of context.
-            let mut bounding_box = (
-                bounding_box.0 - BlockCoord(1, 0, 1),
-                bounding_box.1 + BlockCoord(1, 0, 1),
-            );
-            bounding_box.0 .1 = 0;
-            bounding_box.1 .1 = y_len - 1;
 
-            // Get the relative plot description and relative world excerpt
-            let offset_plot = plot.offset(bounding_box.0);
-            let plot_excerpt = WorldExcerpt::from_world_excerpt(
-                (bounding_box.0 .0 as usize, bounding_box.0 .1 as usize, bounding_box.0 .2 as usize),
-                (bounding_box.1 .0 as usize, bounding_box.1 .1 as usize, bounding_box.1 .2 as usize),
-                &excerpt,
+    // Export verification
+    // *******************
+    if let Some(imported_excerpt) = &imported_excerpt {
+        info!("Verifying the export, reading it back from {:?}", output_directory);
+        let mismatches = export_verification::verify_export(
+            &excerpt,
+            imported_excerpt,
+            (x, y, z).into(),
+            &output_directory,
+        );
+        if mismatches.is_empty() {
+            info!("All {} changed blocks persisted as placed.", mismatches.compared_count);
+        } else {
+            warn!(
+                "{} of {} changed blocks did not persist as placed.",
+                mismatches.count(),
+                mismatches.compared_count,
             );
-
-            // Get the build area description structure for the (now offset) plot
-            let plot_build_area =
-                build_area::BuildArea::from_world_excerpt_and_plot(&plot_excerpt, &offset_plot);
-
-            // Modify the palette, depending on the diversity of available wood
-            let mut custom_palette = block_palette.clone();
-            if wood_available.is_empty() {
-                // Sadly no wood to use here.
-                // Replace some roofs with other materials
-                match index % 7 {
-                    0 | 2 | 4 => custom_palette.roof = custom_palette.floor.clone(),
-                    _ => (),
-                }
-            } else if wood_available.len() == 1 {
-                // Replace most walls with the available wood
-                match index % 4 {
-                    0 | 1 | 2 => {
-                        custom_palette.foundation = block_palette.wall.clone();
-                        custom_palette.wall = Block::Planks { material: wood_available[0] };
-                    }
-                    // If the walls were not replaced, replace the floor instead.
-                    _ => {
-                        custom_palette.floor = Block::Planks { material: wood_available[0] };
-                    },
-                }
-                // Replace some roofs with other materials
-                match index % 7 {
-                    0 | 2 | 4 => custom_palette.roof = custom_palette.floor.clone(),
-                    _ => (),
-                }
-            } else if wood_available.len() == 2 {
-                // Replace all roofs with one kind of wood.
-                custom_palette.roof = Block::Planks { material: wood_available[0] };
-                // Replace most walls with the other kind of wood.
-                match index % 4 {
-                    0 | 1 | 2 => {
-                        custom_palette.foundation = block_palette.wall.clone();
-                        custom_palette.wall = Block::Planks { material: wood_available[1] };
-                    }
-                    // If the walls were not replaced, replace the floor instead.
-                    _ => {
-                        custom_palette.floor = Block::Planks { material: wood_available[1] };
-                    },
-                }
-                // Replace some roofs with other materials
-                match index % 7 {
-                    0 | 2 | 4 => custom_palette.roof = custom_palette.floor.clone(),
-                    _ => (),
-                }
-            } else {
-                // Replace all roofs with one kind of wood.
-                custom_palette.roof = Block::Planks { material: wood_available[1] };
-                // Replace most walls with one of the other kinds of wood.
-                match index % 4 {
-                    0 | 1 | 2 => {
-                        custom_palette.foundation = block_palette.wall.clone();
-                        custom_palette.wall = Block::Planks { material: wood_available[2] };
-                    }
-                    _ => (),
-                }
-                // Replace quite a few floors with the other remaining kind of wood.
-                match index % 5 {
-                    0 | 1 | 2 => {
-                        custom_palette.floor = Block::Planks { material: wood_available[0] };
-                    }
-                    _ => (),
-                }
-                // Replace some roofs with other materials
-                match index % 7 {
-                    0 | 4 => custom_palette.roof = custom_palette.floor.clone(),
-                    2 | 6 => custom_palette.roof = block_palette.roof.clone(),
-                    _ => (),
-                }
+            for (name, count) in &mismatches.counts {
+                warn!("{} blocks of {} did not persist.", count, name);
             }
-
-            // Generate a structure on the plot
-            if let Some(new_plot) =
-                structure_builder::build_house(&plot_excerpt, &plot_build_area, &custom_palette)
-            {
-                // TODO Enforce plot_build_area before pasting the new plot into the world?
-
-                // If there are trees that will be affected by pasting the new plot, chop them.
-                let (new_x_len, new_y_len, new_z_len) = new_plot.dim();
-                for x in 0..new_x_len as i64 {
-                    for y in 0..new_y_len as i64 {
-                        for z in 0..new_z_len as i64 {
-                            if let Some(Block::None) =  new_plot.block_at(BlockCoord(x, y, z)) {
-                                // Nothing will be pasted, so nothing to do.
-                            } else {
-                                // Some block will be pasted, chop any affected tree.
-                                tree::chop(&mut excerpt, BlockCoord(x, y, z) + bounding_box.0);
-                            }
-                        }
-                    }
-                }
-
-                // Paste it back into the "main" excerpt
-                excerpt.paste(bounding_box.0, &new_plot)
+            for (coordinates, placed, found) in &mismatches.examples {
+                warn!("Placed {:?} at {:?}, but read back {:?}.", placed, coordinates, found);
             }
         }
     }
-
-    wall::build_wall_crowning(&mut excerpt, &wall_circle, &features, &block_palette);
-
-    /*
-    println!("Testing rainbow trees!");
-    tree::rainbow_trees(&mut excerpt);
-    println!("Rainbow trees finished!");
-    */
-
-
-    // World export
-    // ************
-    info!("Exporting to {:?}", output_directory);
-    excerpt.to_save((x, y, z).into(), Path::new(output_directory));
-    info!("Exported world excerpt of dimensions {:?}", excerpt.dim());
 }
 
 fn parse_i64_or_exit(string: &str) -> i64 {
@@ -571,6 +293,27 @@ fn parse_i64_or_exit(string: &str) -> i64 {
     })
 }
 
+fn parse_u64_or_exit(string: &str) -> u64 {
+    string.parse::<u64>().unwrap_or_else(|_| {
+        error!("Not an unsigned integer: {}", string);
+        std::process::exit(1);
+    })
+}
+
+fn read_boundary_or_exit(path: &str) -> Snake {
+    boundary::read_geojson(Path::new(path)).unwrap_or_else(|error| {
+        error!("Unable to read boundary file {}: {}", path, error);
+        std::process::exit(1);
+    })
+}
+
+fn read_config_or_exit(path: &str) -> GenerationConfig {
+    GenerationConfig::from_file(Path::new(path)).unwrap_or_else(|error| {
+        error!("Unable to read config file {}: {}", path, error);
+        std::process::exit(1);
+    })
+}
+
 fn matches() -> clap::ArgMatches<'static> {
     clap::App::new("leifsbu - A Minecraft settlement generator.")
         .set_term_width(80)
@@ -591,6 +334,65 @@ fn matches() -> clap::ArgMatches<'static> {
                 .help("Output save directory. Set to input directory if not provided.")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("config")
+                .short("-c")
+                .long("config")
+                .value_name("FILE")
+                .help("TOML file with generation parameters. Defaults are used for anything not given.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("plan_output")
+                .long("plan-output")
+                .value_name("FILE")
+                .help("Export the city plan as JSON to the given file.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("geojson_output")
+                .long("geojson-output")
+                .value_name("FILE")
+                .help("Export roads, walls, water and districts as GeoJSON to the given file.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("report_output")
+                .long("report-output")
+                .value_name("FILE")
+                .help("Write a self-contained HTML report of the generation, e.g. report.html, embedding any inspection images.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("verbose")
+                .short("-v")
+                .long("verbose")
+                .help("Log progress. Repeat for more details: -vv for debug output, -vvv for tracing.")
+                .multiple(true),
+        )
+        .arg(
+            clap::Arg::with_name("quiet")
+                .short("-q")
+                .long("quiet")
+                .help("Log nothing but errors, e.g. for batch generation.")
+                .conflicts_with("verbose"),
+        )
+        .arg(
+            clap::Arg::with_name("inspection_directory")
+                .long("inspection-directory")
+                .value_name("DIRECTORY")
+                .help("Write images of intermediate generation results to the given directory.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("boundary")
+                .long("boundary")
+                .value_name("FILE")
+                .help("GeoJSON file with a polygon to keep the settlement inside, in world coordinates, with x as easting and z negated as northing. The selection defaults to its bounding box.")
+                .takes_value(true)
+                .number_of_values(1)
+                .required(false),
+        )
         .arg(
             clap::Arg::with_name("x")
                 .short("-x")
@@ -600,7 +402,7 @@ fn matches() -> clap::ArgMatches<'static> {
                 .takes_value(true)
                 .number_of_values(1)
                 .allow_hyphen_values(true)
-                .required(true),
+                .required_unless("boundary"),
         )
         .arg(
             clap::Arg::with_name("dx")
@@ -611,7 +413,7 @@ fn matches() -> clap::ArgMatches<'static> {
                 .takes_value(true)
                 .number_of_values(1)
                 .allow_hyphen_values(true)
-                .required(true),
+                .required_unless("boundary"),
         )
         .arg(
             clap::Arg::with_name("y")
@@ -644,7 +446,7 @@ fn matches() -> clap::ArgMatches<'static> {
                 .takes_value(true)
                 .number_of_values(1)
                 .allow_hyphen_values(true)
-                .required(true),
+                .required_unless("boundary"),
         )
         .arg(
             clap::Arg::with_name("dz")
@@ -655,7 +457,129 @@ fn matches() -> clap::ArgMatches<'static> {
                 .takes_value(true)
                 .number_of_values(1)
                 .allow_hyphen_values(true)
-                .required(true),
+                .required_unless("boundary"),
+        )
+        .arg(
+            clap::Arg::with_name("towns")
+                .short("-t")
+                .long("towns")
+                .value_name("count")
+                .help("Largest number of walled towns to place, connected by roads. Overrides the config file.")
+                .takes_value(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("strict_palette")
+                .long("strict-palette")
+                .help("Replace blocks not on the block whitelist of the config file with allowed alternatives."),
+        )
+        .arg(
+            clap::Arg::with_name("max_memory")
+                .long("max-memory")
+                .value_name("MiB")
+                .help("Refuse to generate in areas needing more memory than this, in MiB.")
+                .takes_value(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("verify_export")
+                .long("verify-export")
+                .help("Read the exported world back in, and report any changed blocks that did not persist as placed."),
+        )
+        .arg(
+            clap::Arg::with_name("style")
+                .long("style")
+                .value_name("style")
+                .help("How the townsfolk are housed: In houses on the plots, in dwellings carved into the cliffs, or in the ruins of an abandoned town. Overrides the config file.")
+                .possible_values(&["town", "cliff", "ruins"])
+                .takes_value(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("hook")
+                .long("hook")
+                .value_name("program")
+                .help("Program to run after the last stage, or after the stages given with --hook-stage. It gets the arguments given with --hook-arg, then the stage name, the city plan as JSON and a save directory with the world built so far. Changes to the world in that save are kept.")
+                .takes_value(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("hook_arg")
+                .long("hook-arg")
+                .value_name("argument")
+                .help("Argument for the hook program. May be given several times, for several arguments in order.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .allow_hyphen_values(true)
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("hook_stage")
+                .long("hook-stage")
+                .value_name("stage")
+                .help("Run the hook program after this stage, instead of after the last one. May be given several times.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("dimension")
+                .long("dimension")
+                .value_name("dimension")
+                .help("Dimension to build the settlement in. In the Nether, bound the selection in y to the layer to build in, below the ceiling. Overrides the config file.")
+                .possible_values(&["overworld", "nether"])
+                .takes_value(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("adventure")
+                .long("adventure")
+                .help("Hide treasure chests with books of town lore around the town."),
+        )
+        .arg(
+            clap::Arg::with_name("night")
+                .long("night")
+                .help("Light up every window and hang lanterns on the façades, for night-time screenshots."),
+        )
+        .arg(
+            clap::Arg::with_name("seed")
+                .short("-s")
+                .long("seed")
+                .value_name("u64")
+                .help("Seed for the random number generator. Random if not provided, in which case it is logged as a warning.")
+                .takes_value(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("render")
+                .about("Render the selection from above, without generating anything.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("diff")
+                        .about("Render the selection in the input save before and in the output save (-o) after, with the modified columns highlighted.")
+                        .arg(
+                            clap::Arg::with_name("image")
+                                .long("image")
+                                .value_name("FILE")
+                                .help("PNG file to write the render to.")
+                                .takes_value(true)
+                                .number_of_values(1)
+                                .required(true),
+                        )
+                        .arg(
+                            clap::Arg::with_name("blend")
+                                .long("blend")
+                                .help("Blend the renders before and after into one, instead of side by side."),
+                        ),
+                ),
         )
         .get_matches()
 }
@@ -0,0 +1,323 @@
+//! Mining on exposed rock: Either a terraced open-pit quarry on level ground,
+//! or a timbered tunnel into a hillside, with a road from the mine to town.
+
+use std::convert::TryInto;
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::info;
+use mcprogedit::block::{Block, RailShape};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::WoodMaterial;
+use mcprogedit::positioning::{Axis3, Direction, Surface4, Surface5, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+
+use crate::config::GenerationConfig;
+use crate::farm::Field;
+use crate::features::Features;
+use crate::geometry::{self, disc, is_in_disc, step};
+use crate::pathfinding::{self, RoadPath};
+use crate::road::{self, RoadStyle};
+use crate::signs;
+use crate::tree;
+use crate::types::Snake;
+
+/// Distance between the candidate sites that are considered.
+const SITE_SPACING: usize = 4;
+/// Distance the mine must keep from the fields and from the town walls.
+const CLEARANCE: u8 = 8;
+/// Radius of the quarry pit, at the top.
+const QUARRY_RADIUS: i64 = 7;
+/// Number of terraces in the quarry.
+const TERRACE_COUNT: i64 = 3;
+/// Height of each terrace.
+const TERRACE_DEPTH: i64 = 2;
+/// How much narrower each terrace is than the one above it.
+const TERRACE_INSET: i64 = 2;
+/// Largest difference in ground height around the quarry.
+const QUARRY_SLOPE_MAX: i64 = 4;
+/// Length of the tunnel, from its mouth.
+const TUNNEL_LENGTH: i64 = 12;
+/// Inner height of the tunnel.
+const TUNNEL_HEIGHT: i64 = 3;
+/// Distance between the timber supports in the tunnel.
+const SUPPORT_SPACING: i64 = 4;
+/// How far in from the mouth of the tunnel the hillside must start.
+const CLIFF_DISTANCE: i64 = 3;
+/// Width of the road from the mine.
+const ROAD_WIDTH: i64 = 2;
+
+/// The kinds of mine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MineKind {
+    /// Open pit, dug down in terraces.
+    Quarry,
+    /// Tunnel into a hillside.
+    Tunnel,
+}
+
+/// A mine, and the road from it.
+#[derive(Clone, Debug)]
+pub struct Mine {
+    pub kind: MineKind,
+    /// For a quarry the middle of the pit, for a tunnel the middle of its mouth.
+    /// At the height of the first block above the ground.
+    pub site: BlockCoord,
+    /// Direction out of the mine, towards its road.
+    pub front: Surface4,
+    /// Road from the mine to the nearest country road or town wall, if one was found.
+    pub road: Option<RoadPath>,
+}
+
+/// Find exposed rock within the mining areas, away from the towns and fields,
+/// and lay out a mine there, as close to town as possible.
+pub fn plan_mine(
+    features: &Features,
+    mines: &GrayImage,
+    fields: &[Field],
+    wall_circles: &[&Snake],
+    town_center: BlockColumnCoord,
+    country_roads: &[RoadPath],
+) -> Option<Mine> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    // Keep clear of the fields and the towns.
+    let mut occupied = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut occupied, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+    for field in fields {
+        let (min, max) = field.fenced_area();
+        for x in min.0.max(0)..=max.0.min(x_len - 1) {
+            for z in min.1.max(0)..=max.1.min(z_len - 1) {
+                occupied.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+    }
+    let occupied = imageproc::morphology::dilate(&occupied, Norm::LInf, CLEARANCE);
+
+    let margin = QUARRY_RADIUS.max(TUNNEL_LENGTH) + 2;
+    let mut candidates = Vec::new();
+    for x in (margin..x_len - margin).step_by(SITE_SPACING) {
+        for z in (margin..z_len - margin).step_by(SITE_SPACING) {
+            let column = (x as u32, z as u32);
+            if mines[column] != image::Luma([255u8])
+                || occupied[column] == image::Luma([255u8])
+                || features.water[column] == image::Luma([255u8])
+            {
+                continue;
+            }
+            let site = BlockColumnCoord(x, z);
+            let mine = tunnel_at(features, &occupied, site)
+                .or_else(|| quarry_at(features, &occupied, site, town_center));
+            if let Some(mine) = mine {
+                candidates.push(mine);
+            }
+        }
+    }
+    let mut mine = candidates.into_iter()
+        .min_by_key(|mine| geometry::manhattan_distance(mine.site.into(), town_center))?;
+
+    // Road from the front of the mine to the nearest country road, or else to the nearest town wall.
+    let front_distance = match mine.kind {
+        MineKind::Quarry => QUARRY_RADIUS + 1,
+        MineKind::Tunnel => 1,
+    };
+    let road_start = step(mine.site.into(), mine.front, front_distance);
+    let image::Luma([start_y]) = features.terrain[(road_start.0 as u32, road_start.1 as u32)];
+    let road_start = BlockCoord(road_start.0, start_y as i64, road_start.1);
+    let distance_from_mine = |column: BlockColumnCoord| geometry::manhattan_distance(road_start.into(), column);
+    let road_end = country_roads.iter()
+        .flat_map(|road| road.iter())
+        .map(|node| node.coordinates)
+        .min_by_key(|coordinates| distance_from_mine((*coordinates).into()))
+        .or_else(|| {
+            wall_circles.iter()
+                .flat_map(|wall_circle| wall_circle.iter())
+                .min_by_key(|point| distance_from_mine(**point))
+                .map(|point| {
+                    let image::Luma([y]) = features.terrain[(point.0 as u32, point.1 as u32)];
+                    BlockCoord(point.0, y as i64, point.1)
+                })
+        });
+    if let Some(road_end) = road_end {
+        mine.road = pathfinding::road_path(road_start, road_end, &features.terrain, Some(&features.water));
+    }
+
+    info!("Planned a {:?} mine at {:?}.", mine.kind, mine.site);
+    Some(mine)
+}
+
+/// Check if there is a hillside for a tunnel right by `site`, in any direction.
+fn tunnel_at(features: &Features, occupied: &GrayImage, site: BlockColumnCoord) -> Option<Mine> {
+    let image::Luma([floor_y]) = features.terrain[(site.0 as u32, site.1 as u32)];
+    let floor_y = floor_y as i64;
+
+    for inwards in &[Surface4::North, Surface4::East, Surface4::South, Surface4::West] {
+        // The tunnel needs rock above it, all the way from the hillside to the end.
+        let is_covered = (CLIFF_DISTANCE..TUNNEL_LENGTH).all(|distance| {
+            let column = step(site, *inwards, distance);
+            let column = (column.0 as u32, column.1 as u32);
+            let image::Luma([ground]) = features.terrain[column];
+            ground as i64 > floor_y + TUNNEL_HEIGHT
+                && occupied[column] != image::Luma([255u8])
+                && features.water[column] != image::Luma([255u8])
+        });
+        if is_covered {
+            return Some(Mine {
+                kind: MineKind::Tunnel,
+                site: BlockCoord(site.0, floor_y, site.1),
+                front: inwards.opposite(),
+                road: None,
+            });
+        }
+    }
+
+    None
+}
+
+/// Check if there is level enough ground around `site` for a quarry.
+fn quarry_at(
+    features: &Features,
+    occupied: &GrayImage,
+    site: BlockColumnCoord,
+    town_center: BlockColumnCoord,
+) -> Option<Mine> {
+    let mut min_y = i64::MAX;
+    let mut max_y = i64::MIN;
+
+    for (x, z) in disc(site, QUARRY_RADIUS + 1) {
+        let column = (x as u32, z as u32);
+        if occupied[column] == image::Luma([255u8]) || features.water[column] == image::Luma([255u8]) {
+            return None;
+        }
+        let image::Luma([y]) = features.terrain[column];
+        min_y = min_y.min(y as i64);
+        max_y = max_y.max(y as i64);
+    }
+    if max_y - min_y > QUARRY_SLOPE_MAX {
+        return None;
+    }
+
+    let image::Luma([y]) = features.terrain[(site.0 as u32, site.1 as u32)];
+    Some(Mine {
+        kind: MineKind::Quarry,
+        site: BlockCoord(site.0, y as i64, site.1),
+        front: signs::surface_from_vector((town_center.0 - site.0) as f32, (town_center.1 - site.1) as f32),
+        road: None,
+    })
+}
+
+/// Build the mine, and the road from it.
+pub fn build_mine(
+    excerpt: &mut WorldExcerpt,
+    mine: &Mine,
+    features: &Features,
+    config: &GenerationConfig,
+    rng: &mut StdRng,
+) {
+    if let Some(road) = &mine.road {
+        let style = RoadStyle { width: ROAD_WIDTH, ..road.class.style(config) };
+        road::build_road_in_style(excerpt, road, &features.terrain, &style, rng);
+    }
+
+    match mine.kind {
+        MineKind::Quarry => build_quarry(excerpt, mine, features),
+        MineKind::Tunnel => build_tunnel(excerpt, mine),
+    }
+}
+
+/// Dig out the quarry in terraces, each narrower than the one above,
+/// with scaffolding for climbing between them on the front side.
+fn build_quarry(excerpt: &mut WorldExcerpt, mine: &Mine, features: &Features) {
+    let center: BlockColumnCoord = mine.site.into();
+    let top_y = mine.site.1 - 1;
+    let terrace_radius = |terrace: i64| QUARRY_RADIUS - terrace * TERRACE_INSET;
+    let terrace_floor = |terrace: i64| top_y - (terrace + 1) * TERRACE_DEPTH;
+
+    for (x, z) in disc(center, QUARRY_RADIUS) {
+        let (dx, dz) = (x - center.0, z - center.1);
+        let terrace = (0..TERRACE_COUNT)
+            .filter(|terrace| is_in_disc((dx, dz), terrace_radius(*terrace)))
+            .last()
+            .unwrap_or(0);
+        let floor_y = terrace_floor(terrace);
+
+        let image::Luma([ground]) = features.terrain[(x as u32, z as u32)];
+        for y in floor_y + 1..=(ground as i64).max(top_y + 1) {
+            tree::chop(excerpt, BlockCoord(x, y, z));
+            excerpt.set_block_at(BlockCoord(x, y, z), Block::Air);
+        }
+        // Fill in any caves under the floor.
+        if excerpt.block_at(BlockCoord(x, floor_y, z)) == Some(&Block::Air) {
+            excerpt.set_block_at(BlockCoord(x, floor_y, z), Block::Cobblestone);
+        }
+    }
+
+    // Scaffolding up from each terrace to the one above it
+    for terrace in 0..TERRACE_COUNT {
+        let column = step(center, mine.front, terrace_radius(terrace));
+        let upper_floor = if terrace == 0 { top_y } else { terrace_floor(terrace - 1) };
+        for y in terrace_floor(terrace) + 1..=upper_floor {
+            excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::Scaffolding { waterlogged: false });
+        }
+    }
+}
+
+/// Dig the tunnel straight into the hillside, with timber supports,
+/// a rail down the middle, and torches on the walls between the supports.
+fn build_tunnel(excerpt: &mut WorldExcerpt, mine: &Mine) {
+    let inwards = mine.front.opposite();
+    let side = inwards.rotated_90_cw();
+    let floor_y = mine.site.1;
+    let rail_shape = match inwards {
+        Surface4::North | Surface4::South => RailShape::NorthSouth,
+        Surface4::East | Surface4::West => RailShape::EastWest,
+    };
+
+    for distance in 0..TUNNEL_LENGTH {
+        let middle = step(mine.site.into(), inwards, distance);
+        let is_support = distance % SUPPORT_SPACING == 0;
+
+        for across in -1..=1 {
+            let column = step(middle, side, across);
+            if excerpt.block_at(BlockCoord(column.0, floor_y - 1, column.1)) == Some(&Block::Air) {
+                excerpt.set_block_at(BlockCoord(column.0, floor_y - 1, column.1), Block::Cobblestone);
+            }
+            for y in floor_y..floor_y + TUNNEL_HEIGHT {
+                tree::chop(excerpt, BlockCoord(column.0, y, column.1));
+                let block = if is_support && y == floor_y + TUNNEL_HEIGHT - 1 {
+                    Block::Planks { material: WoodMaterial::Oak }
+                } else if is_support && across != 0 {
+                    Block::oak_log(Axis3::Y)
+                } else {
+                    Block::Air
+                };
+                excerpt.set_block_at(BlockCoord(column.0, y, column.1), block);
+            }
+        }
+
+        excerpt.set_block_at(BlockCoord(middle.0, floor_y, middle.1), Block::Rail { shape: rail_shape });
+
+        // Torch on the wall, halfway between the supports
+        if distance % SUPPORT_SPACING == SUPPORT_SPACING / 2 {
+            let wall = step(middle, side, 2);
+            if excerpt.block_at(BlockCoord(wall.0, floor_y + 1, wall.1)) != Some(&Block::Air) {
+                let torch = step(middle, side, 1);
+                let attached: Surface5 = Direction::from(side.opposite())
+                    .try_into()
+                    .expect("Converting from Surface4 to Surface5 should be safe.");
+                excerpt.set_block_at(BlockCoord(torch.0, floor_y + 1, torch.1), Block::Torch { attached });
+            }
+        }
+    }
+
+    // Barrels for the ore at the far end
+    let end = step(mine.site.into(), inwards, TUNNEL_LENGTH - 1);
+    for across in &[-1, 1] {
+        let column = step(end, side, *across);
+        excerpt.set_block_at(BlockCoord(column.0, floor_y, column.1), Block::barrel(Surface6::Up));
+    }
+}
@@ -0,0 +1,187 @@
+//! Names for the town, its streets, its districts and its wayside shrines.
+
+use std::collections::HashSet;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use crate::zoning::DistrictUse;
+
+/// Trades and landmarks that streets are named after.
+const STREET_NAME_STEMS: [&str; 24] = [
+    "Bakar", "Borg", "Bryggar", "Fisk", "Fiskar", "Garvar", "Gås", "Hamn",
+    "Kyrk", "Kvarn", "Kött", "Mur", "Munk", "Ost", "Präst", "Rådhus",
+    "Salt", "Sko", "Skol", "Smed", "Stall", "Torg", "Vävar", "Väg",
+];
+
+/// Endings for the street names, "street" and "alley".
+const STREET_NAME_SUFFIXES: [&str; 2] = ["gata", "gränd"];
+
+/// Qualifiers, for telling apart streets named after the same thing.
+const STREET_NAME_QUALIFIERS: [&str; 5] = ["", "Lilla ", "Stora ", "Norra ", "Södra "];
+
+/// Come up with `count` distinct street names, such as "Fiskargata" or "Lilla Smedgränd".
+pub fn street_names(count: usize, rng: &mut StdRng) -> Vec<String> {
+    let mut stems = STREET_NAME_STEMS.to_vec();
+    stems.shuffle(rng);
+
+    let mut names = Vec::with_capacity(count);
+    let mut used: HashSet<String> = HashSet::new();
+
+    'qualifiers: for qualifier in &STREET_NAME_QUALIFIERS {
+        for stem in &stems {
+            if names.len() >= count {
+                break 'qualifiers;
+            }
+            let suffix = STREET_NAME_SUFFIXES.choose(rng).unwrap();
+            let name = format!("{}{}{}", qualifier, stem, suffix);
+            if used.insert(name.clone()) {
+                names.push(name);
+            }
+        }
+    }
+
+    // Out of combinations; resort to numbering the remaining streets.
+    while names.len() < count {
+        names.push(format!("Gata {}", names.len() + 1));
+    }
+
+    names
+}
+
+/// What districts are named after, by the use of the district.
+fn district_name_stems(district_use: DistrictUse) -> &'static [&'static str] {
+    match district_use {
+        DistrictUse::Residential => &["Borg", "Kyrk", "Mur", "Munk", "Präst", "Skol", "Stall", "Väg"],
+        DistrictUse::Market => &["Torg", "Salt", "Fisk", "Ost", "Kött", "Bakar"],
+        DistrictUse::Green => &["Lund", "Äng", "Trädgårds", "Hag"],
+        DistrictUse::Workshop => &["Smed", "Garvar", "Vävar", "Sko", "Bryggar"],
+        DistrictUse::Plaza => &["Stortorgs"],
+    }
+}
+
+/// Come up with a distinct name for each of the districts, after what it is used for,
+/// such as "Smedkvarteret" for a workshop district.
+pub fn district_names(district_uses: &[DistrictUse], rng: &mut StdRng) -> Vec<String> {
+    let mut names = Vec::with_capacity(district_uses.len());
+    let mut used: HashSet<String> = HashSet::new();
+
+    for (index, district_use) in district_uses.iter().enumerate() {
+        let mut stems = district_name_stems(*district_use).to_vec();
+        stems.shuffle(rng);
+        let name = stems.iter()
+            .map(|stem| format!("{}kvarteret", stem))
+            .find(|name| !used.contains(name))
+            // Out of names for this use; resort to numbering the district.
+            .unwrap_or_else(|| format!("Kvarter {}", index + 1));
+        used.insert(name.clone());
+        names.push(name);
+    }
+
+    names
+}
+
+/// First parts of town names.
+const TOWN_NAME_STEMS: [&str; 12] = [
+    "Alf", "Bjørn", "Eid", "Fjell", "Grøn", "Hav", "Leif", "Lund", "Sand", "Sol", "Stein", "Ulv",
+];
+
+/// Endings of town names: Bay, farm, booths, home, place.
+const TOWN_NAME_SUFFIXES: [&str; 5] = ["vik", "by", "bu", "heim", "stad"];
+
+/// Come up with a name for a town, such as "Leifsbu" or "Steinvik".
+pub fn town_name(rng: &mut StdRng) -> String {
+    let stem = TOWN_NAME_STEMS.choose(rng).unwrap();
+    let suffix = TOWN_NAME_SUFFIXES.choose(rng).unwrap();
+    let joint = if stem.ends_with('f') || stem.ends_with('n') { "s" } else { "" };
+    format!("{}{}{}", stem, joint, suffix)
+}
+
+/// Saints that wayside shrines are dedicated to.
+const SAINTS: [&str; 10] = [
+    "Sankt Olav", "Sankt Erik", "Sankt Göran", "Sankt Nikolaus", "Sankt Sigfrid",
+    "Sankt Botvid", "Sankta Birgitta", "Sankta Gertrud", "Sankta Sunniva", "Sankta Katarina",
+];
+
+/// Pick a saint to dedicate a wayside shrine to, such as "Sankt Olav".
+pub fn saint_name(rng: &mut StdRng) -> String {
+    SAINTS.choose(rng).unwrap().to_string()
+}
+
+/// Given names of town founders, and whether each is a daughter or a son.
+const FOUNDER_GIVEN_NAMES: [(&str, bool); 8] = [
+    ("Leif", false), ("Ragnhild", true), ("Torstein", false), ("Ingrid", true),
+    ("Ulf", false), ("Sigrid", true), ("Bjørn", false), ("Åsa", true),
+];
+
+/// Names of the fathers of town founders.
+const FOUNDER_FATHERS: [&str; 5] = ["Eirik", "Håkon", "Olav", "Sverre", "Harald"];
+
+/// Come up with the name of the founder of a town, such as "Leif Eiriksson".
+pub fn founder_name(rng: &mut StdRng) -> String {
+    let (given_name, is_daughter) = FOUNDER_GIVEN_NAMES.choose(rng).unwrap();
+    let father = FOUNDER_FATHERS.choose(rng).unwrap();
+    let suffix = if *is_daughter { "sdotter" } else { "sson" };
+    format!("{} {}{}", given_name, father, suffix)
+}
+
+/// First parts of family names, after features of the landscape.
+const FAMILY_NAME_STEMS: [&str; 10] = ["Berg", "Lind", "Holm", "Sand", "Ek", "Ström", "Dahl", "Sjö", "Nord", "Lund"];
+
+/// Endings of family names.
+const FAMILY_NAME_SUFFIXES: [&str; 6] = ["ström", "berg", "gren", "qvist", "lund", "man"];
+
+/// Come up with a family name for a household, such as "Lindqvist" or "Ekman".
+pub fn family_name(rng: &mut StdRng) -> String {
+    let stem = FAMILY_NAME_STEMS.choose(rng).unwrap();
+    let suffix = FAMILY_NAME_SUFFIXES
+        .iter()
+        .filter(|suffix| !suffix.eq_ignore_ascii_case(stem))
+        .collect::<Vec<_>>()
+        .choose(rng)
+        .copied()
+        .unwrap();
+    format!("{}{}", stem, suffix)
+}
+
+/// Pick a given name, such as "Ingrid".
+pub fn given_name(rng: &mut StdRng) -> String {
+    FOUNDER_GIVEN_NAMES.choose(rng).unwrap().0.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+
+    #[test]
+    fn no_street_names() {
+        assert!(street_names(0, &mut StdRng::seed_from_u64(0)).is_empty());
+    }
+
+    #[test]
+    fn distinct_street_names() {
+        let names = street_names(30, &mut StdRng::seed_from_u64(0));
+        let distinct: HashSet<&String> = names.iter().collect();
+        assert_eq!(30, names.len());
+        assert_eq!(30, distinct.len());
+    }
+
+    #[test]
+    fn street_names_numbered_when_out_of_combinations() {
+        let count = STREET_NAME_STEMS.len() * STREET_NAME_QUALIFIERS.len() + 10;
+        let names = street_names(count, &mut StdRng::seed_from_u64(0));
+        let distinct: HashSet<&String> = names.iter().collect();
+        assert_eq!(count, distinct.len());
+        assert_eq!(format!("Gata {}", count), names[count - 1]);
+    }
+
+    #[test]
+    fn street_names_from_seed() {
+        assert_eq!(
+            street_names(10, &mut StdRng::seed_from_u64(7)),
+            street_names(10, &mut StdRng::seed_from_u64(7)),
+        );
+    }
+}
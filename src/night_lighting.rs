@@ -0,0 +1,149 @@
+//! Lighting for night-time screenshots: A light inside behind every window, so that each
+//! window glows when seen from outside, and lanterns hung on brackets along the façades.
+
+use std::collections::HashSet;
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::FenceMaterial;
+use mcprogedit::positioning::Surface2;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_palette::{is_open, is_window};
+use crate::generation_context::Building;
+use crate::geometry;
+
+/// Farthest from the inside of a window a light may be, for the window to count as lit.
+const WINDOW_LIGHT_REACH: i64 = 2;
+/// Farthest inwards from a window to look for the far wall, telling the inside from the outside.
+const INSIDE_DEPTH_MAX: i64 = 16;
+/// Farthest above or below the inside of a window to look for a ceiling or floor to put a light on.
+const MOUNTING_DISTANCE_MAX: i64 = 3;
+/// Distance along the façade between the lanterns, on either side of the door.
+const FACADE_LANTERN_SPACING: i64 = 4;
+
+/// Light every window of the building that has no light inside near it, with a lantern
+/// hung from the ceiling or stood on the floor just inside. Returns the number of lights
+/// placed.
+pub fn light_windows(excerpt: &mut WorldExcerpt, building: &Building) -> usize {
+    let (_, y_len, _) = excerpt.dim();
+    let footprint: HashSet<BlockColumnCoord> = building.footprint.iter().copied().collect();
+    let mut count = 0;
+
+    for column in &building.footprint {
+        for y in 0..y_len as i64 {
+            let window = BlockCoord(column.0, y, column.1);
+            if !excerpt.block_at(window).map_or(false, is_window) {
+                continue;
+            }
+            let inside = match inside_of(excerpt, &footprint, window) {
+                Some(inside) => inside,
+                None => continue,
+            };
+            if has_light_near(excerpt, inside) {
+                continue;
+            }
+            if place_light(excerpt, inside) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// Hang lanterns on brackets along the façade of each exterior door of the building, above
+/// head height, flanking the door and at even intervals further along the wall. Returns the
+/// number of lanterns hung.
+pub fn hang_facade_lanterns(excerpt: &mut WorldExcerpt, building: &Building) -> usize {
+    let mut count = 0;
+    for door in &building.doors {
+        let outward = geometry::offset(door.facing.opposite());
+        for along in &[door.facing.rotated_90_cw(), door.facing.rotated_90_ccw()] {
+            let step = geometry::offset(*along);
+            let mut distance = 1;
+            loop {
+                let wall = door.coordinates + BlockCoord(step.0 * distance, 2, step.2 * distance);
+                let wall_above = wall + BlockCoord(0, 1, 0);
+                if is_open(excerpt.block_at(wall)) || is_open(excerpt.block_at(wall_above)) {
+                    break;
+                }
+                let bracket = wall_above + outward;
+                let lantern = wall + outward;
+                if (distance - 1) % FACADE_LANTERN_SPACING == 0
+                    && excerpt.block_at(bracket) == Some(&Block::Air)
+                    && excerpt.block_at(lantern) == Some(&Block::Air)
+                {
+                    excerpt.set_block_at(bracket, Block::Fence { material: FenceMaterial::Spruce, waterlogged: false });
+                    excerpt.set_block_at(lantern, Block::Lantern { mounted_at: Surface2::Up, waterlogged: false });
+                    count += 1;
+                }
+                distance += 1;
+            }
+        }
+    }
+    count
+}
+
+/// The open block just inside the window, found as the side of it from which a wall of the
+/// building is reached before leaving its footprint.
+fn inside_of(excerpt: &WorldExcerpt, footprint: &HashSet<BlockColumnCoord>, window: BlockCoord) -> Option<BlockCoord> {
+    for direction in &geometry::directions() {
+        let step = geometry::offset(*direction);
+        let next_to = window + step;
+        if excerpt.block_at(next_to) != Some(&Block::Air) {
+            continue;
+        }
+        for distance in 2..=INSIDE_DEPTH_MAX {
+            let coordinates = window + BlockCoord(step.0 * distance, 0, step.2 * distance);
+            if !footprint.contains(&BlockColumnCoord(coordinates.0, coordinates.2)) {
+                break;
+            }
+            if !is_open(excerpt.block_at(coordinates)) {
+                return Some(next_to);
+            }
+        }
+    }
+    None
+}
+
+/// Whether there is a light within `WINDOW_LIGHT_REACH` of the coordinates.
+fn has_light_near(excerpt: &WorldExcerpt, coordinates: BlockCoord) -> bool {
+    let reach = -WINDOW_LIGHT_REACH..=WINDOW_LIGHT_REACH;
+    reach.clone().any(|dx| {
+        reach.clone().any(|dy| {
+            reach.clone().any(|dz| {
+                excerpt.block_at(coordinates + BlockCoord(dx, dy, dz)).map_or(false, is_light)
+            })
+        })
+    })
+}
+
+/// Put a lantern just inside a window: Hanging from the ceiling above, or else standing on
+/// the floor below. Returns whether there was a ceiling or floor near enough for it.
+fn place_light(excerpt: &mut WorldExcerpt, inside: BlockCoord) -> bool {
+    for distance in 0..MOUNTING_DISTANCE_MAX {
+        let coordinates = inside + BlockCoord(0, distance, 0);
+        if excerpt.block_at(coordinates) != Some(&Block::Air) {
+            break;
+        }
+        if !is_open(excerpt.block_at(coordinates + BlockCoord(0, 1, 0))) {
+            excerpt.set_block_at(coordinates, Block::Lantern { mounted_at: Surface2::Up, waterlogged: false });
+            return true;
+        }
+    }
+    for distance in 0..MOUNTING_DISTANCE_MAX {
+        let coordinates = inside - BlockCoord(0, distance, 0);
+        if excerpt.block_at(coordinates) != Some(&Block::Air) {
+            break;
+        }
+        if !is_open(excerpt.block_at(coordinates - BlockCoord(0, 1, 0))) {
+            excerpt.set_block_at(coordinates, Block::Lantern { mounted_at: Surface2::Down, waterlogged: false });
+            return true;
+        }
+    }
+    false
+}
+
+fn is_light(block: &Block) -> bool {
+    matches!(block, Block::Lantern { .. } | Block::Torch { .. } | Block::Campfire { .. })
+}
@@ -1,23 +1,23 @@
+use crate::config::GenerationConfig;
 use crate::geometry;
+use crate::inspection::Inspection;
 use crate::pathfinding;
-use crate::pathfinding::{road_path_from_snake, snake_from_road_path, RoadPath};
+use crate::pathfinding::{road_path_from_snake, snake_from_road_path, RoadNode, RoadPath};
 use crate::types::*;
 
 use image::{GrayImage, ImageBuffer, Luma};
+use imageproc::contrast::stretch_contrast;
 use imageproc::distance_transform::Norm;
 use imageproc::drawing::draw_line_segment_mut;
 use imageproc::morphology::*;
 use imageproc::region_labelling::{connected_components, Connectivity};
 use imageproc::stats::histogram;
 use imageproc::template_matching::{find_extremes, Extremes};
-use log::{info, warn};
+use log::{debug, info, warn};
 use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
 use num_integer::Roots;
 use std::cmp::{max, min};
 
-#[cfg(feature = "debug_images")]
-use imageproc::contrast::stretch_contrast;
-
 // Plot partitioning…
 // Let's figure out how to do that!
 //
@@ -105,21 +105,24 @@ pub fn divide_town_into_blocks(
     town_center: &BlockColumnCoord,
     roads: &[RoadPath],
     height_map: &GrayImage,
+    water: &GrayImage,
+    config: &GenerationConfig,
+    inspection: &Inspection,
 ) -> Vec<RoadPath> {
     const COVERED: Luma<u8> = Luma([255u8]);
 
-    const ROAD_COVERAGE_RADIUS: u8 = 10;
+    let road_coverage_radius: u8 = config.road_coverage_radius;
     const _ROAD_HALF_WIDTH: u8 = 3;
 
-    const STREET_COVERAGE_RADIUS: u8 = 9; // 8
-    const STREET_COVERAGE_FULL_WIDTH: u8 = 2 * (STREET_COVERAGE_RADIUS + STREET_HALF_WIDTH);
+    let street_coverage_radius: u8 = config.street_coverage_radius;
+    let street_coverage_full_width: u32 = 2 * (street_coverage_radius as u32 + STREET_HALF_WIDTH as u32);
     const STREET_HALF_WIDTH: u8 = 2;
 
     const TOWN_BORDER_HALF_WIDTH: u8 = 2;
     const TOWN_BORDER_DISTANCE_TO_CLOSE_STREET: i64 =
         (STREET_HALF_WIDTH + TOWN_BORDER_HALF_WIDTH) as i64;
-    const TOWN_BORDER_DISTANCE_TO_FAR_STREET: i64 =
-        (STREET_COVERAGE_RADIUS + TOWN_BORDER_HALF_WIDTH - 1) as i64;
+    let town_border_distance_to_far_street: i64 =
+        street_coverage_radius as i64 + TOWN_BORDER_HALF_WIDTH as i64 - 1;
 
     const UNCOVERED_AREA_SIZE_THRESHOLD: u32 = 32;
 
@@ -149,8 +152,7 @@ pub fn divide_town_into_blocks(
         COVERED,
     );
 
-    #[cfg(feature = "debug_images")]
-    settlement_stencil.save("P-01 circumference.png").unwrap();
+    inspection.save_image("P-01 circumference.png", &settlement_stencil);
 
     // Mark the outside of the town as covered
     let components = connected_components(&settlement_stencil, Connectivity::Four, COVERED);
@@ -161,8 +163,16 @@ pub fn divide_town_into_blocks(
         }
     }
 
-    #[cfg(feature = "debug_images")]
-    settlement_stencil.save("P-02 area stencil.png").unwrap();
+    // Mark the water as covered, keeping the streets out of the ponds and canals
+    let (water_x_len, water_z_len) = water.dimensions();
+    for (x, z, value) in settlement_stencil.enumerate_pixels_mut() {
+        let (water_x, water_z) = (x + offset.0 as u32, z + offset.1 as u32);
+        if water_x < water_x_len && water_z < water_z_len && water[(water_x, water_z)] == COVERED {
+            *value = COVERED;
+        }
+    }
+
+    inspection.save_image("P-02 area stencil.png", &settlement_stencil);
 
     // Mark roads
     let mut infrastructure = image::ImageBuffer::new(dimensions.0 as u32, dimensions.1 as u32);
@@ -170,16 +180,12 @@ pub fn divide_town_into_blocks(
         draw_offset_road(&mut infrastructure, road, &offset, COVERED);
     }
 
-    #[cfg(feature = "debug_images")]
-    infrastructure
-        .save("P-03 existing infrastructure.png")
-        .unwrap();
+    inspection.save_image("P-03 existing infrastructure.png", &infrastructure);
 
     // Get map of initial areas as divided by initial roads
     let initial_areas = combine_max(&settlement_stencil, &infrastructure);
 
-    #[cfg(feature = "debug_images")]
-    initial_areas.save("P-04 initial areas.png").unwrap();
+    inspection.save_image("P-04 initial areas.png", &initial_areas);
 
     // Find distinct initial areas
     let initial_areas = image_u32_to_u8(&connected_components(
@@ -193,23 +199,20 @@ pub fn divide_town_into_blocks(
     } = find_extremes(&initial_areas);
     info!("Found {} distinct existing areas.", full_area_count);
 
-    #[cfg(feature = "debug_images")]
-    if full_area_count > 0 {
+    if inspection.is_enabled() && full_area_count > 0 {
         let areas = stretch_contrast(&initial_areas, 0u8, full_area_count);
-        areas.save("P-05 full areas.png").unwrap();
+        inspection.save_image("P-05 full areas.png", &areas);
     }
 
     // Mark areas close to roads as covered
-    let road_coverage = dilate(&infrastructure, Norm::LInf, ROAD_COVERAGE_RADIUS);
+    let road_coverage = dilate(&infrastructure, Norm::LInf, road_coverage_radius);
 
-    #[cfg(feature = "debug_images")]
-    road_coverage.save("P-06 close to road.png").unwrap();
+    inspection.save_image("P-06 close to road.png", &road_coverage);
 
     // Get map of initial coverage
     let initial_coverage = combine_max(&settlement_stencil, &road_coverage);
 
-    #[cfg(feature = "debug_images")]
-    initial_coverage.save("P-07 initial coverage.png").unwrap();
+    inspection.save_image("P-07 initial coverage.png", &initial_coverage);
 
     // Find distinct uncovered areas
     let uncovered_areas = image_u32_to_u8(&connected_components(
@@ -226,22 +229,20 @@ pub fn divide_town_into_blocks(
         area_count
     );
 
-    #[cfg(feature = "debug_images")]
-    if area_count > 0 {
+    if inspection.is_enabled() && area_count > 0 {
         let areas = stretch_contrast(&uncovered_areas, 0u8, area_count);
-        areas.save("P-08 areas.png").unwrap();
+        inspection.save_image("P-08 areas.png", &areas);
     }
 
     // Find the size of each area
     let stats = histogram(&uncovered_areas);
 
-    // NB Only for debug prints...
-    info!("Area statistics:");
-    info!("Background size:\t{}", stats.channels[0][0]);
+    debug!("Area statistics:");
+    debug!("Background size:\t{}", stats.channels[0][0]);
     for area_index in 1..stats.channels[0].len() {
         let size = stats.channels[0][area_index];
         if size > 0 {
-            info!("Area {} size:\t{}", area_index, size);
+            debug!("Area {} size:\t{}", area_index, size);
         }
     }
 
@@ -290,8 +291,8 @@ pub fn divide_town_into_blocks(
             (normal.1 * -TOWN_BORDER_DISTANCE_TO_CLOSE_STREET) / 20,
         );
         let far_offset = (
-            (normal.0 * -TOWN_BORDER_DISTANCE_TO_FAR_STREET) / 20,
-            (normal.1 * -TOWN_BORDER_DISTANCE_TO_FAR_STREET) / 20,
+            (normal.0 * -town_border_distance_to_far_street) / 20,
+            (normal.1 * -town_border_distance_to_far_street) / 20,
         );
 
         street_close_to_border.push(
@@ -319,8 +320,7 @@ pub fn divide_town_into_blocks(
     draw_offset_snake(&mut wall_roads, &street_close_to_border, &offset, COVERED);
     draw_offset_snake(&mut wall_roads, &street_far_from_border, &offset, COVERED);
 
-    #[cfg(feature = "debug_images")]
-    wall_roads.save("P-09 wall roads.png").unwrap();
+    inspection.save_image("P-09 wall roads.png", &wall_roads);
 
     let mut streets = Vec::new();
 
@@ -336,20 +336,14 @@ pub fn divide_town_into_blocks(
         // Get the uncovered stencil for only this area
         let mut area_stencil = stencil_from_value(&uncovered_areas, Luma([area_index as u8]));
 
-        #[cfg(feature = "debug_images")]
-        area_stencil
-            .save(format!("P-10 area {:0>2}.png", area_index))
-            .unwrap();
+        inspection.save_image(&format!("P-10 area {:0>2}.png", area_index), &area_stencil);
 
         // Get the full stencil for only this area
         let location = location_from_value(&uncovered_areas, Luma([area_index as u8])).unwrap();
         let value = initial_areas[location];
         let full_area_stencil = stencil_from_value(&initial_areas, value);
 
-        #[cfg(feature = "debug_images")]
-        full_area_stencil
-            .save(format!("P-10 full area {:0>2}.png", area_index))
-            .unwrap();
+        inspection.save_image(&format!("P-10 full area {:0>2}.png", area_index), &full_area_stencil);
 
         //  Find possible path close by wall
         let close_path = sub_snake(&street_close_to_border, &full_area_stencil, &offset);
@@ -359,21 +353,18 @@ pub fn divide_town_into_blocks(
         let far_path = sub_snake(&street_far_from_border, &full_area_stencil, &offset);
         let far_path = attach_to_road_system(&far_path, roads, 6f32);
 
-        #[cfg(feature = "debug_images")]
-        {
+        if inspection.is_enabled() {
             let mut wall_roads = image::ImageBuffer::new(dimensions.0 as u32, dimensions.1 as u32);
             draw_offset_snake(&mut wall_roads, &close_path, &offset, COVERED);
             draw_offset_snake(&mut wall_roads, &far_path, &offset, COVERED);
 
-            wall_roads
-                .save(format!("P-10 wall roads {:0>2}.png", area_index))
-                .unwrap();
+            inspection.save_image(&format!("P-10 wall roads {:0>2}.png", area_index), &wall_roads);
         }
 
         // Find coverage area for found close path
         let mut close_cover = image::ImageBuffer::new(dimensions.0 as u32, dimensions.1 as u32);
         draw_offset_snake(&mut close_cover, &close_path, &offset, COVERED);
-        dilate_mut(&mut close_cover, Norm::LInf, STREET_COVERAGE_RADIUS);
+        dilate_mut(&mut close_cover, Norm::LInf, street_coverage_radius);
 
         // If it fully covers, add it and go on to next area.
         if fully_covers(&area_stencil, &close_cover) {
@@ -385,7 +376,7 @@ pub fn divide_town_into_blocks(
         // Find coverage area for found far path
         let mut far_cover = image::ImageBuffer::new(dimensions.0 as u32, dimensions.1 as u32);
         draw_offset_snake(&mut far_cover, &far_path, &offset, COVERED);
-        dilate_mut(&mut far_cover, Norm::LInf, STREET_COVERAGE_RADIUS);
+        dilate_mut(&mut far_cover, Norm::LInf, street_coverage_radius);
 
         // If it fully covers, add it and go on to next area.
         if fully_covers(&area_stencil, &far_cover) {
@@ -402,10 +393,7 @@ pub fn divide_town_into_blocks(
         remove_cover(&mut area_stencil, &far_cover);
 
 
-        #[cfg(feature = "debug_images")]
-        area_stencil
-            .save(format!("P-10 area {:0>2} after wall path.png", area_index))
-            .unwrap();
+        inspection.save_image(&format!("P-10 area {:0>2} after wall path.png", area_index), &area_stencil);
 
         // Add border street to infrastructure
         let mut new_infrastructure = infrastructure.clone();
@@ -426,21 +414,18 @@ pub fn divide_town_into_blocks(
         let new_area_stencil = stencil_from_value(&continuous_regions, area_colour);
 
 
-        #[cfg(feature = "debug_images")]
-        new_area_stencil
-            .save(format!("P-10 new area {:0>2}.png", area_index))
-            .unwrap();
+        inspection.save_image(&format!("P-10 new area {:0>2}.png", area_index), &new_area_stencil);
 
         // Get bounding box for remaining area
         let (uncovered_offset, uncovered_size) = stencil_bounding_box(&area_stencil);
 
-        fn calculate_offsets(uncovered_length: u32) -> Vec<u32> {
+        fn calculate_offsets(uncovered_length: u32, coverage_full_width: u32) -> Vec<u32> {
             fn ceiling_div(dividend: u32, divisor: u32) -> u32 {
                 (dividend + divisor - 1) / divisor
             }
 
-            let full_distance = STREET_COVERAGE_FULL_WIDTH as u32 + uncovered_length;
-            let interval_count = ceiling_div(full_distance, STREET_COVERAGE_FULL_WIDTH as u32);
+            let full_distance = coverage_full_width + uncovered_length;
+            let interval_count = ceiling_div(full_distance, coverage_full_width);
             let interval_length = full_distance / interval_count;
             let edge_offset = (full_distance - (interval_count * interval_length)) / 2;
 
@@ -452,7 +437,7 @@ pub fn divide_town_into_blocks(
             let mut offsets = Vec::with_capacity((interval_count - 1) as usize);
             for i in 1..interval_count {
                 let offset = edge_offset + i * interval_length;
-                offsets.push(offset - STREET_COVERAGE_FULL_WIDTH as u32 / 2);
+                offsets.push(offset - coverage_full_width / 2);
             }
             offsets
         }
@@ -460,7 +445,7 @@ pub fn divide_town_into_blocks(
         if uncovered_size.0 < uncovered_size.1 {
             // shortest along x axis
             info!("Decided to spread along Z axis.");
-            let z_offsets = calculate_offsets(uncovered_size.1);
+            let z_offsets = calculate_offsets(uncovered_size.1, street_coverage_full_width);
             info!("Z offsets: {:?}", z_offsets);
 
             // Fill with horizontal paths
@@ -500,7 +485,7 @@ pub fn divide_town_into_blocks(
 
                     // Get the path
                     if let Some(horizontal_path) =
-                        pathfinding::road_path(start_point, goal_point, height_map, None)
+                        pathfinding::road_path(start_point, goal_point, height_map, Some(water))
                     {
                         streets.push(horizontal_path);
                     }
@@ -509,7 +494,7 @@ pub fn divide_town_into_blocks(
         } else {
             // shortest along z axis
             info!("Decided to spread along X axis.");
-            let x_offsets = calculate_offsets(uncovered_size.0);
+            let x_offsets = calculate_offsets(uncovered_size.0, street_coverage_full_width);
             info!("X offsets: {:?}", x_offsets);
 
             // Fill with vertical paths
@@ -549,7 +534,7 @@ pub fn divide_town_into_blocks(
 
                     // Get the path
                     if let Some(vertical_path) =
-                        pathfinding::road_path(start_point, goal_point, height_map, None)
+                        pathfinding::road_path(start_point, goal_point, height_map, Some(water))
                     {
                         streets.push(vertical_path);
                     }
@@ -558,6 +543,15 @@ pub fn divide_town_into_blocks(
         }
     }
 
+    // Reroute or split the streets where they climb too steeply
+    let street_count = streets.len();
+    let streets: Vec<RoadPath> = streets.iter()
+        .flat_map(|street| follow_terrain(street, height_map, config.street_climb_max))
+        .collect();
+    if streets.len() != street_count {
+        info!("Split {} streets into {} along steep terrain.", street_count, streets.len());
+    }
+
     // Some final visual debug
     for street in &streets {
         let street = snake_from_road_path(street);
@@ -581,6 +575,66 @@ pub fn _divide_area_into_plots(
     unimplemented!();
 }
 
+/// Reroute the segments of `street` that climb more than `climb_max` between their ends,
+/// along a path found through the surrounding terrain. Where no such path is found,
+/// the street is split in two instead, leaving out the steep segment.
+fn follow_terrain(street: &RoadPath, height_map: &GrayImage, climb_max: i64) -> Vec<RoadPath> {
+    let mut streets = Vec::new();
+    let mut current = RoadPath::new(street.iter().take(1).copied().collect(), street.class);
+
+    for segment in street.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        if (end.coordinates.1 - start.coordinates.1).abs() <= climb_max {
+            current.push(end);
+        } else if let Some(path) = local_road_path(start.coordinates, end.coordinates, height_map) {
+            current.extend(path.into_iter().skip(1));
+        } else {
+            if current.len() >= 2 {
+                streets.push(current);
+            }
+            current = RoadPath::new(vec![end], street.class);
+        }
+    }
+    if current.len() >= 2 {
+        streets.push(current);
+    }
+
+    streets
+}
+
+/// Find a road path between `start` and `goal`, searching only the terrain close to them.
+fn local_road_path(start: BlockCoord, goal: BlockCoord, height_map: &GrayImage) -> Option<RoadPath> {
+    const MARGIN: i64 = 8;
+
+    let (x_len, z_len) = height_map.dimensions();
+    let min_x = (min(start.0, goal.0) - MARGIN).max(0);
+    let min_z = (min(start.2, goal.2) - MARGIN).max(0);
+    let max_x = (max(start.0, goal.0) + MARGIN).min(x_len as i64 - 1);
+    let max_z = (max(start.2, goal.2) + MARGIN).min(z_len as i64 - 1);
+
+    let local_height_map = image::imageops::crop_imm(
+        height_map,
+        min_x as u32,
+        min_z as u32,
+        (max_x - min_x + 1) as u32,
+        (max_z - min_z + 1) as u32,
+    )
+    .to_image();
+    let local_offset = BlockCoord(min_x, 0, min_z);
+
+    let path = pathfinding::road_path(
+        start - local_offset,
+        goal - local_offset,
+        &local_height_map,
+        None,
+    )?;
+    Some(
+        path.into_iter()
+            .map(|node| RoadNode { coordinates: node.coordinates + local_offset, ..node })
+            .collect(),
+    )
+}
+
 fn attach_to_road_system(path: &Snake, attach_to: &[RoadPath], epsilon: f32) -> Snake {
     let mut path = path.clone();
 
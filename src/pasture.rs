@@ -0,0 +1,348 @@
+//! Pastures for livestock, on semi-open fertile land outside the town walls: Fenced pens
+//! with a gate towards the road, a shelter in one corner and a water trough.
+//!
+//! The world excerpt holds blocks only, so the animals are planned here, and exported with
+//! the city plan for summoning into the world.
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, WoodMaterial};
+use mcprogedit::positioning::Surface4;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::areas::Areas;
+use crate::farm::Field;
+use crate::features::Features;
+use crate::geometry;
+use crate::pathfinding::{self, RoadPath};
+use crate::tree;
+use crate::types::Snake;
+
+/// Size of a pasture along the x axis, inside the fence.
+const PASTURE_WIDTH: i64 = 13;
+/// Size of a pasture along the z axis, inside the fence.
+const PASTURE_LENGTH: i64 = 11;
+/// Distance between the corners of neighbouring candidate pastures.
+const PASTURE_STRIDE: i64 = PASTURE_WIDTH + 4;
+/// Largest difference in ground height within a pasture.
+const PASTURE_SLOPE_MAX: u8 = 4;
+/// Distance pastures must keep from the towns, the fields and each other.
+const CLEARANCE: u8 = 4;
+/// Distance pastures must keep from the windmill and the lumber camp.
+const LANDMARK_CLEARANCE: usize = 16;
+/// Farthest a pasture may be from the nearest road.
+const MAX_ROAD_DISTANCE: u8 = 48;
+/// Size of the shelter in the corner of the pasture, along both axes.
+const SHELTER_SIZE: i64 = 3;
+/// Height of the shelter roof above the highest ground under it.
+const SHELTER_HEIGHT: i64 = 3;
+/// Length of the water trough.
+const TROUGH_LENGTH: i64 = 3;
+/// Smallest and largest number of animals in a pasture.
+const HERD_SIZE_MIN: usize = 3;
+const HERD_SIZE_MAX: usize = 6;
+
+/// The kind of livestock kept in a pasture.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Livestock {
+    Cow,
+    Sheep,
+    Pig,
+    Chicken,
+}
+
+impl Livestock {
+    /// The Minecraft name of the animal, for summoning it.
+    pub fn minecraft_name(self) -> &'static str {
+        match self {
+            Self::Cow => "cow",
+            Self::Sheep => "sheep",
+            Self::Pig => "pig",
+            Self::Chicken => "chicken",
+        }
+    }
+}
+
+/// A fenced rectangular pasture, with its herd.
+#[derive(Clone, Debug)]
+pub struct Pasture {
+    /// Corner of the pasture with the lowest (x, z) coordinates, inside the fence.
+    pub origin: BlockColumnCoord,
+    pub livestock: Livestock,
+    /// Side of the pasture with the gate, facing the nearest road.
+    pub gate_side: Surface4,
+    /// Path from the gate to the nearest country road, if one was found.
+    pub path: Option<RoadPath>,
+    /// Where the animals stand, to be summoned into the world.
+    pub animals: Vec<BlockCoord>,
+}
+
+impl Pasture {
+    /// Coordinates of the fence gate.
+    pub fn gate(&self) -> BlockColumnCoord {
+        let BlockColumnCoord(x, z) = self.origin;
+        match self.gate_side {
+            Surface4::North => BlockColumnCoord(x + PASTURE_WIDTH / 2, z - 1),
+            Surface4::South => BlockColumnCoord(x + PASTURE_WIDTH / 2, z + PASTURE_LENGTH),
+            Surface4::West => BlockColumnCoord(x - 1, z + PASTURE_LENGTH / 2),
+            Surface4::East => BlockColumnCoord(x + PASTURE_WIDTH, z + PASTURE_LENGTH / 2),
+        }
+    }
+
+    /// Corners of the pasture including its fence, as (min, max), both inclusive.
+    pub fn fenced_area(&self) -> (BlockColumnCoord, BlockColumnCoord) {
+        let BlockColumnCoord(x, z) = self.origin;
+        (BlockColumnCoord(x - 1, z - 1), BlockColumnCoord(x + PASTURE_WIDTH, z + PASTURE_LENGTH))
+    }
+
+    /// Corner of the shelter with the lowest (x, z) coordinates: In the corner of the
+    /// pasture farthest from the gate.
+    fn shelter_origin(&self) -> BlockColumnCoord {
+        let BlockColumnCoord(x, z) = self.origin;
+        let gate = self.gate();
+        let shelter_x = if gate.0 < x + PASTURE_WIDTH / 2 { x + PASTURE_WIDTH - SHELTER_SIZE } else { x };
+        let shelter_z = if gate.1 < z + PASTURE_LENGTH / 2 { z + PASTURE_LENGTH - SHELTER_SIZE } else { z };
+        BlockColumnCoord(shelter_x, shelter_z)
+    }
+
+    /// Whether the column is under the shelter.
+    fn is_sheltered(&self, column: BlockColumnCoord) -> bool {
+        let BlockColumnCoord(x, z) = self.shelter_origin();
+        column.0 >= x && column.0 < x + SHELTER_SIZE && column.1 >= z && column.1 < z + SHELTER_SIZE
+    }
+
+    /// Columns of the water trough: Along the middle of the pasture.
+    fn trough(&self) -> impl Iterator<Item = BlockColumnCoord> {
+        let BlockColumnCoord(x, z) = self.origin;
+        let x0 = x + (PASTURE_WIDTH - TROUGH_LENGTH) / 2;
+        let z = z + PASTURE_LENGTH / 2;
+        (x0..x0 + TROUGH_LENGTH).map(move |x| BlockColumnCoord(x, z))
+    }
+}
+
+/// Find places for up to `max_count` pastures on land fit for grazing, outside of the
+/// given town walls, clear of the fields and the given landmarks, and close to the
+/// country roads. Each gets a herd of a random kind of livestock.
+pub fn plan_pastures(
+    features: &Features,
+    areas: &Areas,
+    wall_circles: &[&Snake],
+    fields: &[Field],
+    landmarks: &[BlockColumnCoord],
+    country_roads: &[RoadPath],
+    max_count: usize,
+    rng: &mut StdRng,
+) -> Vec<Pasture> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    // Keep clear of the towns and the fields.
+    let mut occupied = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut occupied, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+    for field in fields {
+        let (min, max) = field.fenced_area();
+        for x in min.0.max(0)..=max.0.min(x_len - 1) {
+            for z in min.1.max(0)..=max.1.min(z_len - 1) {
+                occupied.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+    }
+    let occupied = imageproc::morphology::dilate(&occupied, Norm::LInf, CLEARANCE);
+
+    // Candidate pastures, on a grid, together with their distance to the nearest road.
+    let mut candidates: Vec<(u8, BlockColumnCoord)> = Vec::new();
+    for x in (2..x_len - PASTURE_WIDTH - 2).step_by(PASTURE_STRIDE as usize) {
+        for z in (2..z_len - PASTURE_LENGTH - 2).step_by(PASTURE_STRIDE as usize) {
+            let origin = BlockColumnCoord(x, z);
+            let center = BlockColumnCoord(x + PASTURE_WIDTH / 2, z + PASTURE_LENGTH / 2);
+            let near_landmark = landmarks.iter()
+                .any(|landmark| geometry::manhattan_distance(center, *landmark) < LANDMARK_CLEARANCE);
+            if near_landmark {
+                continue;
+            }
+            if let Some(road_distance) = pasture_suitability(features, areas, &occupied, origin) {
+                candidates.push((road_distance, origin));
+            }
+        }
+    }
+    candidates.sort_by_key(|(road_distance, origin)| (*road_distance, origin.0, origin.1));
+
+    let obstacles = imageproc::morphology::dilate(&features.water, Norm::LInf, 2);
+    let kinds = [Livestock::Cow, Livestock::Sheep, Livestock::Pig, Livestock::Chicken];
+
+    let mut pastures = Vec::new();
+    for (_, origin) in candidates.into_iter().take(max_count) {
+        let center = BlockColumnCoord(origin.0 + PASTURE_WIDTH / 2, origin.1 + PASTURE_LENGTH / 2);
+        let nearest_road_node = country_roads.iter()
+            .flat_map(|road| road.iter())
+            .min_by_key(|node| geometry::manhattan_distance(center, node.coordinates.into()));
+
+        let gate_side = match nearest_road_node {
+            Some(node) => {
+                let (dx, dz) = (node.coordinates.0 - center.0, node.coordinates.2 - center.1);
+                if dx.abs() > dz.abs() {
+                    if dx > 0 { Surface4::East } else { Surface4::West }
+                } else if dz > 0 {
+                    Surface4::South
+                } else {
+                    Surface4::North
+                }
+            }
+            None => Surface4::North,
+        };
+
+        let livestock = *kinds.choose(rng).unwrap();
+        let mut pasture = Pasture { origin, livestock, gate_side, path: None, animals: Vec::new() };
+
+        // The herd, spread out over the open part of the pasture
+        let herd_size = rng.gen_range(HERD_SIZE_MIN..=HERD_SIZE_MAX);
+        let mut spots: Vec<BlockColumnCoord> = (origin.0..origin.0 + PASTURE_WIDTH)
+            .flat_map(|x| (origin.1..origin.1 + PASTURE_LENGTH).map(move |z| BlockColumnCoord(x, z)))
+            .filter(|column| !pasture.is_sheltered(*column) && !pasture.trough().any(|trough| trough == *column))
+            .collect();
+        spots.shuffle(rng);
+        pasture.animals = spots.into_iter()
+            .take(herd_size)
+            .map(|column| {
+                let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+                BlockCoord(column.0, y as i64, column.1)
+            })
+            .collect();
+
+        // Path from just outside the gate to the nearest road
+        if let Some(node) = nearest_road_node {
+            let gate = pasture.gate();
+            let outside = match gate_side {
+                Surface4::North => BlockColumnCoord(gate.0, gate.1 - 1),
+                Surface4::South => BlockColumnCoord(gate.0, gate.1 + 1),
+                Surface4::West => BlockColumnCoord(gate.0 - 1, gate.1),
+                Surface4::East => BlockColumnCoord(gate.0 + 1, gate.1),
+            };
+            let image::Luma([start_y]) = features.terrain[(outside.0 as u32, outside.1 as u32)];
+            pasture.path = pathfinding::road_path(
+                BlockCoord(outside.0, start_y as i64, outside.1),
+                node.coordinates,
+                &features.terrain,
+                Some(&obstacles),
+            );
+        }
+
+        pastures.push(pasture);
+    }
+
+    info!("Planned {} pastures.", pastures.len());
+    pastures
+}
+
+/// Check if a pasture (with its fence) fits at `origin`.
+/// Returns the distance to the nearest road.
+fn pasture_suitability(
+    features: &Features,
+    areas: &Areas,
+    occupied: &GrayImage,
+    origin: BlockColumnCoord,
+) -> Option<u8> {
+    let mut min_y = u8::MAX;
+    let mut max_y = u8::MIN;
+    let mut road_distance = u8::MAX;
+
+    for x in origin.0 - 1..=origin.0 + PASTURE_WIDTH {
+        for z in origin.1 - 1..=origin.1 + PASTURE_LENGTH {
+            let column = (x as u32, z as u32);
+            if areas.pasture[column] != image::Luma([255u8])
+                || occupied[column] == image::Luma([255u8])
+            {
+                return None;
+            }
+            match features.road_distance_at(column) {
+                Some(distance) if distance <= 1 => return None,
+                Some(distance) => road_distance = road_distance.min(distance),
+                None => (),
+            }
+            let image::Luma([y]) = features.terrain[column];
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+        }
+    }
+
+    if max_y - min_y > PASTURE_SLOPE_MAX || road_distance > MAX_ROAD_DISTANCE {
+        None
+    } else {
+        Some(road_distance)
+    }
+}
+
+/// Fence the pasture in, following the lie of the land, and build its shelter and trough.
+pub fn build_pasture(excerpt: &mut WorldExcerpt, pasture: &Pasture, features: &Features) {
+    let ground = |column: BlockColumnCoord| {
+        let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        y as i64
+    };
+
+    // Fence around the pasture, with a gate towards the road.
+    let (min, max) = pasture.fenced_area();
+    let gate = pasture.gate();
+    for x in min.0..=max.0 {
+        for z in min.1..=max.1 {
+            let is_inside = x > min.0 && x < max.0 && z > min.1 && z < max.1;
+            if is_inside {
+                continue;
+            }
+            let column = BlockColumnCoord(x, z);
+            let coordinates = BlockCoord(x, ground(column), z);
+            tree::chop(excerpt, coordinates);
+            if column == gate {
+                excerpt.set_block_at(coordinates, Block::FenceGate {
+                    material: WoodMaterial::Oak,
+                    facing: pasture.gate_side,
+                    open: false,
+                });
+            } else {
+                excerpt.set_block_at(coordinates, Block::Fence {
+                    material: FenceMaterial::Oak,
+                    waterlogged: false,
+                });
+            }
+        }
+    }
+
+    // Shelter: A plank roof on fence posts, open on all sides.
+    let BlockColumnCoord(shelter_x, shelter_z) = pasture.shelter_origin();
+    let shelter_columns: Vec<BlockColumnCoord> = (shelter_x..shelter_x + SHELTER_SIZE)
+        .flat_map(|x| (shelter_z..shelter_z + SHELTER_SIZE).map(move |z| BlockColumnCoord(x, z)))
+        .collect();
+    let roof_y = shelter_columns.iter().map(|column| ground(*column)).max().unwrap_or(0) + SHELTER_HEIGHT;
+    for column in &shelter_columns {
+        for y in ground(*column)..roof_y {
+            tree::chop(excerpt, BlockCoord(column.0, y, column.1));
+            excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::Air);
+        }
+        let is_corner = (column.0 == shelter_x || column.0 == shelter_x + SHELTER_SIZE - 1)
+            && (column.1 == shelter_z || column.1 == shelter_z + SHELTER_SIZE - 1);
+        if is_corner {
+            for y in ground(*column)..roof_y {
+                excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::Fence {
+                    material: FenceMaterial::Oak,
+                    waterlogged: false,
+                });
+            }
+        }
+        excerpt.set_block_at(BlockCoord(column.0, roof_y, column.1), Block::Planks { material: WoodMaterial::Oak });
+    }
+
+    // Trough: Water sunk into the ground, on a bed of planks.
+    for column in pasture.trough() {
+        let y = ground(column);
+        excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::Air);
+        excerpt.set_block_at(BlockCoord(column.0, y - 1, column.1), Block::WaterSource);
+        excerpt.set_block_at(BlockCoord(column.0, y - 2, column.1), Block::Planks { material: WoodMaterial::Oak });
+    }
+}
@@ -5,6 +5,7 @@ use num_integer::Roots;
 use pathfinding::prelude::astar;
 use std::cmp::{max, min};
 
+use crate::features::Features;
 use crate::types::*;
 
 // For distance calculations, how many units to divide one block length into.
@@ -14,6 +15,19 @@ const WOODEN_SUPPORT_HEIGHT_MAX: i64 = 8;
 const STONE_SUPPORT_HEIGHT_MAX: i64 = 24;
 const WOODEN_SUPPORT_COST: i64 = 200;
 const STONE_SUPPORT_COST: i64 = 300;
+/// Cost of cutting through one block of terrain above the road, per node.
+const CUTTING_COST: i64 = 150;
+/// Cost of each node of a tunnel, regardless of the depth of the terrain above it.
+const TUNNEL_COST: i64 = 1200;
+/// Distance from the lava within which a road costs more, the closer it gets.
+const LAVA_CLEARANCE: u8 = 4;
+/// Extra cost of a node one block closer to the lava than `LAVA_CLEARANCE`.
+const LAVA_PROXIMITY_COST: i64 = 100;
+/// Extra cost of a bridge node over a river, per block of river width there.
+const RIVER_WIDTH_COST: i64 = 40;
+/// Extra cost of a bridge node over a river, for following the river rather than
+/// crossing it, scaled down to nothing for crossing at right angles.
+const RIVER_ALONG_COST: i64 = 400;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct RoadNode {
@@ -29,11 +43,101 @@ pub enum RoadNodeKind {
     Ground,
     WoodenSupport,
     StoneSupport,
-    //Cutting,
-    //Tunnel,
+    /// Level road cut into the terrain, between retaining walls.
+    Cutting,
+    /// Level road through a tunnel, deeper below the terrain than a cutting.
+    Tunnel,
 }
 
-pub type RoadPath = Vec<RoadNode>;
+/// What a road is for, deciding how wide it is, what it is built of, and how it is lit.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum RoadClass {
+    /// Country roads between the towns and out of the area.
+    Highway,
+    /// Main roads through the towns, from gate to gate.
+    CityRoad,
+    /// Streets between the city blocks.
+    Street,
+    /// Short, narrow streets.
+    Alley,
+    /// Footpaths out to the fields, the windmill and other places outside the towns.
+    /// Roads not given a class by the planner are footpaths.
+    #[default]
+    Path,
+}
+
+/// A road: The nodes along its middle, and the class of road it is.
+///
+/// Dereferences to the nodes, so the path can be walked and changed like a `Vec`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct RoadPath {
+    pub nodes: Vec<RoadNode>,
+    pub class: RoadClass,
+}
+
+impl RoadPath {
+    pub fn new(nodes: Vec<RoadNode>, class: RoadClass) -> Self {
+        Self { nodes, class }
+    }
+
+    /// The same path, as a road of the given class.
+    pub fn with_class(self, class: RoadClass) -> Self {
+        Self { class, ..self }
+    }
+}
+
+impl std::ops::Deref for RoadPath {
+    type Target = Vec<RoadNode>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.nodes
+    }
+}
+
+impl std::ops::DerefMut for RoadPath {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.nodes
+    }
+}
+
+impl From<Vec<RoadNode>> for RoadPath {
+    fn from(nodes: Vec<RoadNode>) -> Self {
+        Self { nodes, class: RoadClass::default() }
+    }
+}
+
+impl std::iter::FromIterator<RoadNode> for RoadPath {
+    fn from_iter<I: IntoIterator<Item = RoadNode>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl IntoIterator for RoadPath {
+    type Item = RoadNode;
+    type IntoIter = std::vec::IntoIter<RoadNode>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RoadPath {
+    type Item = &'a RoadNode;
+    type IntoIter = std::slice::Iter<'a, RoadNode>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut RoadPath {
+    type Item = &'a mut RoadNode;
+    type IntoIter = std::slice::IterMut<'a, RoadNode>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.iter_mut()
+    }
+}
 
 pub fn draw_road_path(image: &mut RgbImage, path: &RoadPath) {
     const MARKER_RADIUS: i64 = 1;
@@ -65,6 +169,8 @@ pub fn draw_road_path(image: &mut RgbImage, path: &RoadPath) {
         let node_colour = match kind {
             RoadNodeKind::WoodenSupport => image::Rgb([64u8, 0u8, 0u8]),
             RoadNodeKind::StoneSupport => image::Rgb([32u8, 32u8, 32u8]),
+            RoadNodeKind::Cutting => image::Rgb([96u8, 64u8, 32u8]),
+            RoadNodeKind::Tunnel => image::Rgb([0u8, 0u8, 0u8]),
             _ => continue,
         };
 
@@ -81,6 +187,31 @@ pub fn road_path(
     goal: BlockCoord,
     height_map: &GrayImage,
     ground_block_map: Option<&GrayImage>,
+) -> Option<RoadPath> {
+    road_path_with_features(start, goal, height_map, ground_block_map, None)
+}
+
+/// Find a road path as with `road_path`, taking the lava and the rivers of the features
+/// into account. The road keeps off the lava, and clear of it where there is room: There
+/// are no ground nodes or wooden supports on the lava, and the nodes cost more the closer
+/// they are to it. Rivers are bridged where they are narrow, straight across the flow.
+/// The road keeps inside the build boundary, if the features have one.
+pub fn road_path_over_terrain(
+    start: BlockCoord,
+    goal: BlockCoord,
+    height_map: &GrayImage,
+    ground_block_map: Option<&GrayImage>,
+    features: &Features,
+) -> Option<RoadPath> {
+    road_path_with_features(start, goal, height_map, ground_block_map, Some(features))
+}
+
+fn road_path_with_features(
+    start: BlockCoord,
+    goal: BlockCoord,
+    height_map: &GrayImage,
+    ground_block_map: Option<&GrayImage>,
+    features: Option<&Features>,
 ) -> Option<RoadPath> {
     let (x_len, z_len) = height_map.dimensions();
 
@@ -94,8 +225,11 @@ pub fn road_path(
         .sqrt() as u64
     }
 
+    // Height of the terrain at the given column, or None off the map or outside the build
+    // boundary, where the road may not go.
     let get_terrain_height = |x: i64, z: i64| -> Option<i64> {
-        if x >= 0 && x < x_len as i64 && z >= 0 && z < z_len as i64 {
+        let is_outside = features.map_or(false, |features| features.is_outside((x, z)));
+        if x >= 0 && x < x_len as i64 && z >= 0 && z < z_len as i64 && !is_outside {
             let image::Luma([terrain_height]) = height_map[(x as u32, z as u32)];
             Some(terrain_height as i64)
         } else {
@@ -104,6 +238,9 @@ pub fn road_path(
     };
 
     let support_cost = |node: &RoadNode| -> u64 {
+        let depth = || {
+            (get_terrain_height(node.coordinates.0, node.coordinates.2).unwrap() - node.coordinates.1) as u64
+        };
         match node.kind {
             RoadNodeKind::WoodenSupport => {
                 (node.coordinates.1
@@ -117,19 +254,67 @@ pub fn road_path(
                     + 1) as u64
                     * STONE_SUPPORT_COST as u64
             }
+            RoadNodeKind::Cutting => depth() * CUTTING_COST as u64,
+            RoadNodeKind::Tunnel => TUNNEL_COST as u64,
             _ => 0u64,
         }
     };
 
+    // Distance from the given column to the nearest lava, saturating at LAVA_CLEARANCE.
+    let get_lava_distance = |x: i64, z: i64| -> u8 {
+        match features {
+            Some(features) if x >= 0 && x < x_len as i64 && z >= 0 && z < z_len as i64 => {
+                let image::Luma([distance]) = features.lava_distance[(x as u32, z as u32)];
+                distance.min(LAVA_CLEARANCE)
+            }
+            _ => LAVA_CLEARANCE,
+        }
+    };
+
+    let is_lava = |x: i64, z: i64| -> bool { get_lava_distance(x, z) == 0 };
+
+    let lava_cost = |node: &RoadNode| -> u64 {
+        (LAVA_CLEARANCE - get_lava_distance(node.coordinates.0, node.coordinates.2)) as u64
+            * LAVA_PROXIMITY_COST as u64
+    };
+
+    // Cost of bridging a river from a to b: More where the river is wide, and where the
+    // bridge follows the river instead of crossing it.
+    let river_cost = |a: &RoadNode, b: &RoadNode| -> u64 {
+        let features = match features {
+            Some(features) => features,
+            None => return 0,
+        };
+        let (x, z) = (b.coordinates.0, b.coordinates.2);
+        let is_bridge = b.kind == RoadNodeKind::WoodenSupport || b.kind == RoadNodeKind::StoneSupport;
+        if !is_bridge || x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 {
+            return 0;
+        }
+        let image::Luma([width]) = features.river_width[(x as u32, z as u32)];
+        let image::Luma([direction]) = features.river_direction[(x as u32, z as u32)];
+        if width == 0 || direction == u8::MAX {
+            return 0;
+        }
+        let (step_x, step_z) = ((x - a.coordinates.0) as f32, (z - a.coordinates.2) as f32);
+        let step_length = (step_x * step_x + step_z * step_z).sqrt().max(1.0);
+        let angle = (direction as f32).to_radians();
+        let alignment = ((step_x * angle.cos() + step_z * angle.sin()) / step_length).abs();
+        width as u64 * RIVER_WIDTH_COST as u64 + (alignment * RIVER_ALONG_COST as f32) as u64
+    };
+
     // Calculate the cost between two given road nodes.
     let cost = |a: &RoadNode, b: &RoadNode| -> u64 {
         stretched_euclidean_distance(&a.coordinates, &b.coordinates)
             + support_cost(a)
             + support_cost(b)
+            + lava_cost(b)
+            + river_cost(a, b)
     };
 
     let is_ground_blocked = |x: i64, z: i64| -> bool {
-        if let Some(ground_block_map) = ground_block_map {
+        if is_lava(x, z) {
+            true
+        } else if let Some(ground_block_map) = ground_block_map {
             image::Luma([0u8]) != ground_block_map[(x as u32, z as u32)]
         } else {
             false
@@ -155,7 +340,7 @@ pub fn road_path(
                     } else if terrain_height < y {
                         // Bridge
                         let support_height = y - terrain_height;
-                        if support_height <= WOODEN_SUPPORT_HEIGHT_MAX {
+                        if support_height <= WOODEN_SUPPORT_HEIGHT_MAX && !is_lava(x, z) {
                             neighbours.push(RoadNode {
                                 coordinates: (x, y, z).into(),
                                 kind: RoadNodeKind::WoodenSupport,
@@ -169,8 +354,16 @@ pub fn road_path(
                         }
                     } else if terrain_height > (y + CUT_DEPTH_MAX) {
                         // Tunnel
+                        neighbours.push(RoadNode {
+                            coordinates: (x, y, z).into(),
+                            kind: RoadNodeKind::Tunnel,
+                        });
                     } else { // Terrain barely higher than node
                         // Cut
+                        neighbours.push(RoadNode {
+                            coordinates: (x, y, z).into(),
+                            kind: RoadNodeKind::Cutting,
+                        });
                     }
                 }
             }
@@ -184,9 +377,12 @@ pub fn road_path(
                                 kind: RoadNodeKind::Ground,
                             });
                         }
-                        // Add edges to WoodenSupport
+                        // Add edges to WoodenSupport, other than over lava
                         // NB Currently only flat bridge. Add slopes as well?
-                        if y > terrain_height && y <= terrain_height + WOODEN_SUPPORT_HEIGHT_MAX {
+                        if y > terrain_height
+                            && y <= terrain_height + WOODEN_SUPPORT_HEIGHT_MAX
+                            && !is_lava(*new_x, *new_z)
+                        {
                             neighbours.push(RoadNode {
                                 coordinates: (*new_x, y, *new_z).into(),
                                 kind: RoadNodeKind::WoodenSupport,
@@ -200,14 +396,40 @@ pub fn road_path(
                                 kind: RoadNodeKind::StoneSupport,
                             });
                         }
+                        // Add edges to Cutting
+                        // NB Currently only level cuttings, as with the bridges.
+                        if y < terrain_height && terrain_height <= y + CUT_DEPTH_MAX {
+                            neighbours.push(RoadNode {
+                                coordinates: (*new_x, y, *new_z).into(),
+                                kind: RoadNodeKind::Cutting,
+                            });
+                        }
                     }
                 }
             }
-            RoadNodeKind::WoodenSupport => {
+            RoadNodeKind::Cutting | RoadNodeKind::Tunnel => {
                 for (new_x, new_z) in &ground_neighbour_locations(x, z) {
                     if let Some(terrain_height) = get_terrain_height(*new_x, *new_z) {
-                        // Add ground node if on ground level
+                        let coordinates = (*new_x, y, *new_z).into();
                         if y == terrain_height {
+                            // Out onto the ground, from a cutting
+                            if node.kind == RoadNodeKind::Cutting && !is_ground_blocked(*new_x, *new_z) {
+                                neighbours.push(RoadNode { coordinates, kind: RoadNodeKind::Ground });
+                            }
+                        } else if y < terrain_height && terrain_height <= y + CUT_DEPTH_MAX {
+                            neighbours.push(RoadNode { coordinates, kind: RoadNodeKind::Cutting });
+                        } else if terrain_height > y + CUT_DEPTH_MAX {
+                            // Into the hill, from the deepest part of a cutting
+                            neighbours.push(RoadNode { coordinates, kind: RoadNodeKind::Tunnel });
+                        }
+                    }
+                }
+            }
+            RoadNodeKind::WoodenSupport => {
+                for (new_x, new_z) in &ground_neighbour_locations(x, z) {
+                    if let Some(terrain_height) = get_terrain_height(*new_x, *new_z) {
+                        // Add ground node if on ground level, other than on lava
+                        if y == terrain_height && !is_lava(*new_x, *new_z) {
                             neighbours.push(RoadNode {
                                 coordinates: (*new_x, y, *new_z).into(),
                                 kind: RoadNodeKind::Ground,
@@ -217,8 +439,11 @@ pub fn road_path(
                 }
                 for (new_x, new_z) in &wood_neighbour_locations(x, z) {
                     if let Some(terrain_height) = get_terrain_height(*new_x, *new_z) {
-                        // Add support node if above ground and below support limit
-                        if y > terrain_height && y <= terrain_height + WOODEN_SUPPORT_HEIGHT_MAX {
+                        // Add support node if above ground and below support limit, other than over lava
+                        if y > terrain_height
+                            && y <= terrain_height + WOODEN_SUPPORT_HEIGHT_MAX
+                            && !is_lava(*new_x, *new_z)
+                        {
                             neighbours.push(RoadNode {
                                 coordinates: (*new_x, y, *new_z).into(),
                                 kind: RoadNodeKind::WoodenSupport,
@@ -230,8 +455,8 @@ pub fn road_path(
             RoadNodeKind::StoneSupport => {
                 for (new_x, new_z) in &ground_neighbour_locations(x, z) {
                     if let Some(terrain_height) = get_terrain_height(*new_x, *new_z) {
-                        // Add ground node if on ground level
-                        if y == terrain_height {
+                        // Add ground node if on ground level, other than on lava
+                        if y == terrain_height && !is_lava(*new_x, *new_z) {
                             neighbours.push(RoadNode {
                                 coordinates: (*new_x, y, *new_z).into(),
                                 kind: RoadNodeKind::Ground,
@@ -292,7 +517,7 @@ pub fn road_path(
 
     // Run A* algorithm
     if let Some((path, _)) = astar(&start_node, successors, heuristic, success) {
-        Some(path)
+        Some(path.into())
     } else {
         None
     }
@@ -300,7 +525,7 @@ pub fn road_path(
 
 // TODO handle water, steepness, etc. as well...
 pub fn road_path_from_snake(path: &Snake, height_map: &GrayImage) -> RoadPath {
-    let mut road_path = Vec::with_capacity(path.len());
+    let mut road_path = RoadPath::from(Vec::with_capacity(path.len()));
 
     for BlockColumnCoord(x, z) in path {
         let image::Luma([y]) = height_map[(*x as u32, *z as u32)];
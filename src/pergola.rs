@@ -0,0 +1,110 @@
+//! Pergolas: open frames of sandstone pillars and fence beams, overgrown with leaves,
+//! shading the courtyards of desert towns.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, LeavesMaterial};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::plot::Plot;
+use crate::tree;
+
+/// Half the width of the pergola, from its middle to the pillars.
+const HALF_WIDTH: i64 = 2;
+/// Height of the leafy roof above the highest ground under the pergola.
+const ROOF_HEIGHT: i64 = 3;
+
+/// Find room for a pergola in the middle of the plot.
+/// Returns the middle of the pergola, if there is room for one.
+pub fn pergola_site(plot: &Plot, features: &Features) -> Option<BlockColumnCoord> {
+    let polygon = plot.polygon();
+    let center = geometry::polygon_center(&polygon);
+    let (x_len, z_len) = features.dimensions();
+
+    let fits = (center.0 - HALF_WIDTH..=center.0 + HALF_WIDTH).all(|x| {
+        (center.1 - HALF_WIDTH..=center.1 + HALF_WIDTH).all(|z| {
+            x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64
+                && features.water[(x as u32, z as u32)] != image::Luma([255u8])
+                && geometry::point_position_relative_to_polygon(BlockColumnCoord(x, z), &polygon)
+                    == InOutSide::Inside
+        })
+    });
+
+    if fits {
+        Some(center)
+    } else {
+        None
+    }
+}
+
+/// Put up a pergola on each of the given courtyard plots with room for one.
+/// Returns the number of pergolas put up.
+pub fn shade_courtyards<'a>(
+    excerpt: &mut WorldExcerpt,
+    courtyards: impl Iterator<Item = &'a Plot>,
+    features: &Features,
+) -> usize {
+    let mut pergola_count = 0;
+    for plot in courtyards {
+        if let Some(center) = pergola_site(plot, features) {
+            build_pergola(excerpt, center, features);
+            pergola_count += 1;
+        }
+    }
+    pergola_count
+}
+
+/// Build a pergola centered on `center`: Sandstone pillars at the corners and fence posts
+/// between them, carrying a roof of sandstone beams around a lattice of fences and leaves.
+pub fn build_pergola(excerpt: &mut WorldExcerpt, center: BlockColumnCoord, features: &Features) {
+    let ground_at = |x: i64, z: i64| {
+        let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+        y as i64
+    };
+    let roof_y = (center.0 - HALF_WIDTH..=center.0 + HALF_WIDTH)
+        .flat_map(|x| (center.1 - HALF_WIDTH..=center.1 + HALF_WIDTH).map(move |z| (x, z)))
+        .map(|(x, z)| ground_at(x, z))
+        .max()
+        .unwrap_or(0)
+        + ROOF_HEIGHT;
+
+    for dx in -HALF_WIDTH..=HALF_WIDTH {
+        for dz in -HALF_WIDTH..=HALF_WIDTH {
+            let (x, z) = (center.0 + dx, center.1 + dz);
+            let ground_y = ground_at(x, z);
+            for clear_y in ground_y..=roof_y {
+                tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+            }
+
+            let is_corner = dx.abs() == HALF_WIDTH && dz.abs() == HALF_WIDTH;
+            let is_edge = dx.abs() == HALF_WIDTH || dz.abs() == HALF_WIDTH;
+
+            // Pillars at the corners, and posts in the middle of each side
+            if is_corner {
+                for pillar_y in ground_y - 1..roof_y {
+                    excerpt.set_block_at(BlockCoord(x, pillar_y, z), Block::Sandstone);
+                }
+            } else if is_edge && (dx == 0 || dz == 0) {
+                for post_y in ground_y..roof_y {
+                    excerpt.set_block_at(
+                        BlockCoord(x, post_y, z),
+                        Block::Fence { material: FenceMaterial::Oak, waterlogged: false },
+                    );
+                }
+            }
+
+            // Beams along the edges, and a lattice overgrown with leaves in between
+            let roof_block = if is_edge {
+                Block::Sandstone
+            } else if (dx + dz) % 2 == 0 {
+                Block::Leaves { material: LeavesMaterial::Oak, distance_to_trunk: None, persistent: true }
+            } else {
+                Block::Fence { material: FenceMaterial::Oak, waterlogged: false }
+            };
+            excerpt.set_block_at(BlockCoord(x, roof_y, z), roof_block);
+        }
+    }
+}
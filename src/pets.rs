@@ -0,0 +1,176 @@
+//! Pets: Cats and dogs sitting by the hearth in some of the homes, and now and then a
+//! parrot in a tavern, of breeds fitting the surroundings of the town.
+//!
+//! The world excerpt holds blocks only, so the pets are planned here, and exported with
+//! the city plan for summoning into the world.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::features::Features;
+use crate::generation_context::Building;
+use crate::structure_builder::HousePurpose;
+
+/// Highest above the lowest floor of a house to look for its hearth.
+const HEARTH_SEARCH_HEIGHT: i64 = 12;
+/// Smallest share of the map covered by something, for it to set the surroundings.
+const SURROUNDINGS_SHARE_MIN: f32 = 0.2;
+
+/// The kind of animal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Species {
+    Cat,
+    Dog,
+    Parrot,
+}
+
+/// What the town is surrounded by, for picking breeds that fit in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Surroundings {
+    Snow,
+    Desert,
+    Forest,
+    Plains,
+}
+
+/// A pet, where it sits or stands.
+#[derive(Clone, Debug)]
+pub struct Pet {
+    pub species: Species,
+    /// The breed, or the colour for parrots, by its Minecraft name.
+    pub variant: &'static str,
+    pub coordinates: BlockCoord,
+    /// Whether the pet is sitting, rather than wandering about.
+    pub sitting: bool,
+}
+
+/// Plan pets for a share `pet_share` of the homes among the buildings, sitting by the
+/// hearth, and a parrot in a share `parrot_chance` of the taverns.
+pub fn plan_pets(
+    excerpt: &WorldExcerpt,
+    buildings: &[Building],
+    surroundings: Surroundings,
+    pet_share: f64,
+    parrot_chance: f64,
+    rng: &mut StdRng,
+) -> Vec<Pet> {
+    let mut pets = Vec::new();
+    for building in buildings {
+        let floor_y = match building.doors.iter().map(|door| door.coordinates.1).min() {
+            Some(floor_y) => floor_y,
+            None => continue,
+        };
+        match building.purpose {
+            Some(HousePurpose::Tavern) => {
+                if !rng.gen_bool(parrot_chance) {
+                    continue;
+                }
+                if let Some(coordinates) = on_the_floor(excerpt, &building.footprint, floor_y, rng) {
+                    pets.push(Pet {
+                        species: Species::Parrot,
+                        variant: parrot_colour(rng),
+                        coordinates,
+                        sitting: false,
+                    });
+                }
+            }
+            Some(_) => {
+                if !rng.gen_bool(pet_share) {
+                    continue;
+                }
+                if let Some(coordinates) = by_the_hearth(excerpt, &building.footprint, floor_y) {
+                    let (species, variant) = home_pet(surroundings, rng);
+                    pets.push(Pet { species, variant, coordinates, sitting: true });
+                }
+            }
+            None => (),
+        }
+    }
+    pets
+}
+
+/// What the town is mostly surrounded by, judging by the terrain features of the map.
+pub fn surroundings(features: &Features, desert: bool) -> Surroundings {
+    let share = |image: &image::GrayImage| {
+        let (x_len, z_len) = image.dimensions();
+        let count = image.pixels().filter(|pixel| **pixel == image::Luma([255u8])).count();
+        count as f32 / (x_len * z_len).max(1) as f32
+    };
+    if desert {
+        Surroundings::Desert
+    } else if share(&features.snow) >= SURROUNDINGS_SHARE_MIN {
+        Surroundings::Snow
+    } else if share(&features.forest) >= SURROUNDINGS_SHARE_MIN {
+        Surroundings::Forest
+    } else {
+        Surroundings::Plains
+    }
+}
+
+/// Pick a cat or a dog for a home, of a breed fitting the surroundings.
+pub fn home_pet(surroundings: Surroundings, rng: &mut StdRng) -> (Species, &'static str) {
+    let species = *[Species::Cat, Species::Dog].choose(rng).unwrap();
+    let breeds: &[&'static str] = match (species, surroundings) {
+        (Species::Cat, Surroundings::Desert) => &["red", "siamese", "calico"],
+        (Species::Cat, Surroundings::Snow) => &["white", "british_shorthair", "persian"],
+        (Species::Cat, _) => &["tabby", "black", "ragdoll", "calico"],
+        (_, Surroundings::Desert) => &["spotted", "striped"],
+        (_, Surroundings::Snow) => &["snowy", "ashen"],
+        (_, Surroundings::Forest) => &["woods", "chestnut", "black"],
+        (_, Surroundings::Plains) => &["pale", "rusty"],
+    };
+    (species, breeds.choose(rng).unwrap())
+}
+
+/// Pick the colour of a parrot.
+pub fn parrot_colour(rng: &mut StdRng) -> &'static str {
+    ["red", "blue", "green", "cyan", "grey"].choose(rng).unwrap()
+}
+
+/// A spot by a hearth of the house with the given footprint, in front of its furnace,
+/// with the lowest floor of the house at `floor_y`.
+pub fn by_the_hearth(excerpt: &WorldExcerpt, footprint: &[BlockColumnCoord], floor_y: i64) -> Option<BlockCoord> {
+    let neighbours = [BlockCoord(1, 0, 0), BlockCoord(-1, 0, 0), BlockCoord(0, 0, 1), BlockCoord(0, 0, -1)];
+    for column in footprint {
+        for y in floor_y..floor_y + HEARTH_SEARCH_HEIGHT {
+            let furnace = BlockCoord(column.0, y, column.1);
+            let is_hearth = matches!(excerpt.block_at(furnace), Some(Block::Furnace { .. }))
+                && excerpt.block_at(furnace + BlockCoord(0, 1, 0)) == Some(&Block::Cobblestone);
+            if !is_hearth {
+                continue;
+            }
+            let spot = neighbours.iter()
+                .map(|offset| furnace + *offset)
+                .find(|spot| has_room(excerpt, *spot));
+            if spot.is_some() {
+                return spot;
+            }
+        }
+    }
+    None
+}
+
+/// A free spot on the floor of the house with the given footprint, at `floor_y`.
+pub fn on_the_floor(
+    excerpt: &WorldExcerpt,
+    footprint: &[BlockColumnCoord],
+    floor_y: i64,
+    rng: &mut StdRng,
+) -> Option<BlockCoord> {
+    let spots: Vec<BlockCoord> = footprint.iter()
+        .map(|column| BlockCoord(column.0, floor_y, column.1))
+        .filter(|spot| has_room(excerpt, *spot))
+        .collect();
+    spots.choose(rng).copied()
+}
+
+/// Whether an animal fits at the spot: Two blocks of air, over a solid floor.
+fn has_room(excerpt: &WorldExcerpt, spot: BlockCoord) -> bool {
+    excerpt.block_at(spot) == Some(&Block::Air)
+        && excerpt.block_at(spot + BlockCoord(0, 1, 0)) == Some(&Block::Air)
+        && !matches!(excerpt.block_at(spot - BlockCoord(0, 1, 0)), None | Some(Block::Air) | Some(Block::None))
+}
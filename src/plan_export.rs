@@ -0,0 +1,293 @@
+//! Export of the city plan as structured JSON, for use by other tools.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use serde::Serialize;
+
+use crate::block_palette::BlockPalette;
+use crate::generation_context::GenerationContext;
+use crate::pathfinding::{RoadNodeKind, RoadPath};
+use crate::pets::Species;
+use crate::plot::{Plot, PlotEdgeKind};
+use crate::zoning::DistrictUse;
+
+/// (x, z) coordinates
+type Point2d = [i64; 2];
+/// (x, y, z) coordinates
+type Point3d = [i64; 3];
+
+#[derive(Serialize)]
+pub struct PlanExport {
+    pub town_center: Point2d,
+    pub wall: Vec<Point2d>,
+    pub outlying_towns: Vec<OutlyingTownExport>,
+    pub city_roads: Vec<RoadExport>,
+    pub country_roads: Vec<RoadExport>,
+    pub streets: Vec<RoadExport>,
+    pub districts: Vec<DistrictExport>,
+    pub zoning_infeasibilities: Vec<String>,
+    pub plots: Vec<PlotExport>,
+    pub buildings: Vec<BuildingExport>,
+    pub fields: Vec<FieldExport>,
+    pub pastures: Vec<PastureExport>,
+    pub pets: Vec<PetExport>,
+    pub graves: Vec<GraveExport>,
+    pub palette: PaletteExport,
+}
+
+#[derive(Serialize)]
+pub struct OutlyingTownExport {
+    pub town_center: Point2d,
+    pub wall: Vec<Point2d>,
+    pub city_roads: Vec<RoadExport>,
+    pub streets: Vec<RoadExport>,
+}
+
+#[derive(Serialize)]
+pub struct RoadExport {
+    pub name: Option<String>,
+    pub nodes: Vec<RoadNodeExport>,
+}
+
+#[derive(Serialize)]
+pub struct RoadNodeExport {
+    pub coordinates: Point3d,
+    pub kind: String,
+}
+
+#[derive(Serialize)]
+pub struct DistrictExport {
+    pub polygon: Vec<Point2d>,
+    pub wealthiest: bool,
+    #[serde(rename = "use")]
+    pub district_use: Option<DistrictUse>,
+}
+
+#[derive(Serialize)]
+pub struct PlotExport {
+    pub district: Option<usize>,
+    pub edges: Vec<PlotEdgeExport>,
+}
+
+#[derive(Serialize)]
+pub struct PlotEdgeExport {
+    pub kind: String,
+    pub width: Option<usize>,
+    pub from: Point3d,
+    pub to: Point3d,
+}
+
+#[derive(Serialize)]
+pub struct BuildingExport {
+    pub plot: usize,
+    pub footprint: Vec<Point2d>,
+    pub address: Option<String>,
+    pub household: Option<String>,
+    pub palette: PaletteExport,
+}
+
+#[derive(Serialize)]
+pub struct FieldExport {
+    pub origin: Point2d,
+    pub crop: String,
+    pub path: Option<RoadExport>,
+}
+
+#[derive(Serialize)]
+pub struct PastureExport {
+    pub origin: Point2d,
+    pub livestock: String,
+    pub animals: Vec<Point3d>,
+    pub path: Option<RoadExport>,
+}
+
+#[derive(Serialize)]
+pub struct PetExport {
+    pub species: String,
+    pub variant: String,
+    pub coordinates: Point3d,
+    pub sitting: bool,
+}
+
+#[derive(Serialize)]
+pub struct GraveExport {
+    pub headstone: Point3d,
+    pub name: String,
+    pub born: u32,
+    pub died: u32,
+}
+
+#[derive(Serialize)]
+pub struct PaletteExport {
+    pub city_wall_coronation: String,
+    pub city_wall_main: String,
+    pub city_wall_top: String,
+    pub flat_window: String,
+    pub floor: String,
+    pub foundation: String,
+    pub palisade: String,
+    pub roof: String,
+    pub roof_ridge: String,
+    pub wall: String,
+}
+
+impl PlanExport {
+    pub fn from_context(context: &GenerationContext) -> Self {
+        Self {
+            town_center: point_2d(&context.town_center),
+            wall: context.wall_circle.iter().map(point_2d).collect(),
+            outlying_towns: context.outlying_towns.iter()
+                .map(|town| OutlyingTownExport {
+                    town_center: point_2d(&town.center),
+                    wall: town.wall_circle.iter().map(point_2d).collect(),
+                    city_roads: town.city_roads.iter().map(|path| road(path, None)).collect(),
+                    streets: town.streets.iter().map(|path| road(path, None)).collect(),
+                })
+                .collect(),
+            city_roads: context.city_roads.iter()
+                .enumerate()
+                .map(|(index, path)| road(path, context.city_road_names.get(index)))
+                .collect(),
+            country_roads: context.country_roads.iter().map(|path| road(path, None)).collect(),
+            streets: context.streets.iter()
+                .enumerate()
+                .map(|(index, path)| road(path, context.street_names.get(index)))
+                .collect(),
+            districts: context.districts.iter()
+                .enumerate()
+                .map(|(index, district)| DistrictExport {
+                    polygon: district.iter().map(point_2d).collect(),
+                    wealthiest: context.wealthiest_district == Some(index),
+                    district_use: context.district_uses.get(index).copied(),
+                })
+                .collect(),
+            zoning_infeasibilities: context.zoning_infeasibilities.iter()
+                .map(|infeasibility| infeasibility.to_string())
+                .collect(),
+            plots: context.plots.iter()
+                .enumerate()
+                .map(|(index, plot_to_export)| plot(plot_to_export, context.plot_districts.get(index).copied()))
+                .collect(),
+            buildings: context.buildings.iter()
+                .map(|building| BuildingExport {
+                    plot: building.plot_index,
+                    footprint: building.footprint.iter().map(point_2d).collect(),
+                    address: building.address.as_ref().map(|address| address.to_string()),
+                    household: building.family_name.clone(),
+                    palette: palette(&building.palette),
+                })
+                .collect(),
+            fields: context.fields.iter()
+                .map(|field| FieldExport {
+                    origin: point_2d(&field.origin),
+                    crop: format!("{:?}", field.crop),
+                    path: field.path.as_ref().map(|path| road(path, None)),
+                })
+                .collect(),
+            pastures: context.pastures.iter()
+                .map(|pasture| PastureExport {
+                    origin: point_2d(&pasture.origin),
+                    livestock: pasture.livestock.minecraft_name().to_string(),
+                    animals: pasture.animals.iter().map(point_3d).collect(),
+                    path: pasture.path.as_ref().map(|path| road(path, None)),
+                })
+                .collect(),
+            pets: context.pets.iter()
+                .map(|pet| PetExport {
+                    species: match pet.species {
+                        Species::Cat => "cat",
+                        Species::Dog => "wolf",
+                        Species::Parrot => "parrot",
+                    }.to_string(),
+                    variant: pet.variant.to_string(),
+                    coordinates: point_3d(&pet.coordinates),
+                    sitting: pet.sitting,
+                })
+                .collect(),
+            graves: context.graves.iter()
+                .map(|grave| GraveExport {
+                    headstone: point_3d(&grave.coordinates),
+                    name: format!("{} {}", grave.given_name, grave.family_name),
+                    born: grave.born,
+                    died: grave.died,
+                })
+                .collect(),
+            palette: palette(&context.block_palette),
+        }
+    }
+}
+
+/// Write the plan held by `context` as JSON to the file at `path`.
+pub fn write_plan(context: &GenerationContext, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|error| format!("{}", error))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &PlanExport::from_context(context))
+        .map_err(|error| format!("{}", error))
+}
+
+fn point_2d(point: &BlockColumnCoord) -> Point2d {
+    [point.0, point.1]
+}
+
+fn point_3d(point: &BlockCoord) -> Point3d {
+    [point.0, point.1, point.2]
+}
+
+fn road(path: &RoadPath, name: Option<&String>) -> RoadExport {
+    RoadExport {
+        name: name.cloned(),
+        nodes: path.iter()
+            .map(|node| RoadNodeExport {
+                coordinates: point_3d(&node.coordinates),
+                kind: match node.kind {
+                    RoadNodeKind::Start => "start",
+                    RoadNodeKind::Ground => "ground",
+                    RoadNodeKind::WoodenSupport => "wooden_support",
+                    RoadNodeKind::StoneSupport => "stone_support",
+                    RoadNodeKind::Cutting => "cutting",
+                    RoadNodeKind::Tunnel => "tunnel",
+                }.to_string(),
+            })
+            .collect(),
+    }
+}
+
+fn plot(plot: &Plot, district: Option<usize>) -> PlotExport {
+    PlotExport {
+        district,
+        edges: plot.edges.iter()
+            .map(|edge| {
+                let (kind, width) = match edge.kind {
+                    PlotEdgeKind::Road { width } => ("road", Some(width)),
+                    PlotEdgeKind::Wall { width } => ("wall", Some(width)),
+                    PlotEdgeKind::Canal { width } => ("canal", Some(width)),
+                    PlotEdgeKind::Plot => ("plot", None),
+                    PlotEdgeKind::Terrain => ("terrain", None),
+                };
+                PlotEdgeExport {
+                    kind: kind.to_string(),
+                    width,
+                    from: point_3d(&edge.points.0),
+                    to: point_3d(&edge.points.1),
+                }
+            })
+            .collect(),
+    }
+}
+
+fn palette(palette: &BlockPalette) -> PaletteExport {
+    PaletteExport {
+        city_wall_coronation: format!("{:?}", palette.city_wall_coronation),
+        city_wall_main: format!("{:?}", palette.city_wall_main),
+        city_wall_top: format!("{:?}", palette.city_wall_top),
+        flat_window: format!("{:?}", palette.flat_window),
+        floor: format!("{:?}", palette.floor),
+        foundation: format!("{:?}", palette.foundation),
+        palisade: format!("{:?}", palette.palisade),
+        roof: format!("{:?}", palette.roof),
+        roof_ridge: format!("{:?}", palette.roof_ridge),
+        wall: format!("{:?}", palette.wall),
+    }
+}
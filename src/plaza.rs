@@ -0,0 +1,298 @@
+//! Market square at the heart of town: a paved plaza around a well or a statue,
+//! with market stalls and lamp posts, and arcades in front of the houses facing it.
+
+use image::GrayImage;
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::colour::Colour;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, Material};
+use mcprogedit::positioning::{Surface2, Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::block_palette::{self, BlockPalette};
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::line;
+use crate::plot::{Plot, PlotEdgeKind};
+use crate::signs;
+use crate::tree;
+use crate::types::Snake;
+use crate::well;
+
+/// Distance from the middle of the plaza to the market stalls.
+const STALL_RING_RADIUS: f32 = 7.0;
+/// Largest number of market stalls around the plaza.
+const STALL_COUNT_MAX: usize = 8;
+/// Largest difference in ground height under a market stall.
+const STALL_SLOPE_MAX: i64 = 1;
+/// Distance from the middle of the plaza to the lamp posts.
+const LAMP_RING_RADIUS: f32 = 11.0;
+/// Number of lamp posts around the plaza, if there is room for them.
+const LAMP_COUNT: usize = 8;
+/// Distance between the columns of an arcade.
+const ARCADE_COLUMN_SPACING: usize = 2;
+
+/// Colours for the awnings of the market stalls.
+const AWNING_COLOURS: [Colour; 5] = [
+    Colour::Red,
+    Colour::White,
+    Colour::Yellow,
+    Colour::Blue,
+    Colour::Green,
+];
+
+/// Turn the district into a paved market square, with a well or statue in the middle,
+/// closest to `center`, surrounded by market stalls and lamp posts.
+pub fn build_plaza(
+    excerpt: &mut WorldExcerpt,
+    district: &Snake,
+    center: BlockColumnCoord,
+    features: &Features,
+    rng: &mut StdRng,
+) {
+    let (x_len, z_len) = features.dimensions();
+    let mut stencil = GrayImage::new(x_len as u32, z_len as u32);
+    geometry::draw_area(&mut stencil, district, BlockColumnCoord(0, 0), image::Luma([255u8]));
+
+    let is_plaza = |column: BlockColumnCoord| {
+        column.0 >= 0 && column.1 >= 0
+            && column.0 < x_len as i64 && column.1 < z_len as i64
+            && stencil[(column.0 as u32, column.1 as u32)] == image::Luma([255u8])
+            && features.water[(column.0 as u32, column.1 as u32)] != image::Luma([255u8])
+    };
+    let ground_at = |column: BlockColumnCoord| {
+        let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        BlockCoord(column.0, y as i64, column.1)
+    };
+
+    // Pave the whole square, clearing away any vegetation.
+    let mut columns = Vec::new();
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            if is_plaza(BlockColumnCoord(x, z)) {
+                columns.push(BlockColumnCoord(x, z));
+            }
+        }
+    }
+    if columns.is_empty() {
+        return;
+    }
+    for column in &columns {
+        let ground = ground_at(*column);
+        for y in 0..3 {
+            tree::chop(excerpt, ground + BlockCoord(0, y, 0));
+            excerpt.set_block_at(ground + BlockCoord(0, y, 0), Block::Air);
+        }
+        let paving = match rng.gen_range(0..8) {
+            0 => Block::Cobblestone,
+            1 => Block::Andesite,
+            2 => Block::CrackedStoneBricks,
+            _ => Block::StoneBricks,
+        };
+        excerpt.set_block_at(ground - BlockCoord(0, 1, 0), paving);
+    }
+
+    // The middle of the square, as close to the given center as the square reaches
+    let middle = *columns.iter()
+        .min_by_key(|column| geometry::manhattan_distance(**column, center))
+        .expect("The plaza has at least one column.");
+    if rng.gen_bool(0.5) {
+        well::build_well(excerpt, middle, features);
+    } else {
+        build_statue(excerpt, ground_at(middle));
+    }
+
+    // Market stalls in a ring around the middle, facing inwards
+    let mut stall_count = 0;
+    for index in 0..STALL_COUNT_MAX {
+        let angle = index as f32 * std::f32::consts::TAU / STALL_COUNT_MAX as f32;
+        let (dx, dz) = (angle.cos() * STALL_RING_RADIUS, angle.sin() * STALL_RING_RADIUS);
+        let stall = BlockColumnCoord(middle.0 + dx.round() as i64, middle.1 + dz.round() as i64);
+        let front = signs::surface_from_vector(-dx, -dz);
+
+        let footprint = stall_footprint(stall, front);
+        if !footprint.iter().all(|column| is_plaza(*column)) {
+            continue;
+        }
+        let heights: Vec<i64> = footprint.iter().map(|column| ground_at(*column).1).collect();
+        let floor_y = *heights.iter().max().unwrap();
+        if floor_y - heights.iter().min().unwrap() > STALL_SLOPE_MAX {
+            continue;
+        }
+
+        let colour = AWNING_COLOURS[rng.gen_range(0..AWNING_COLOURS.len())];
+        build_stall(excerpt, stall, floor_y, front, colour);
+        stall_count += 1;
+    }
+
+    // Lamp posts in a wider ring
+    for index in 0..LAMP_COUNT {
+        let angle = (index as f32 + 0.5) * std::f32::consts::TAU / LAMP_COUNT as f32;
+        let lamp = BlockColumnCoord(
+            middle.0 + (angle.cos() * LAMP_RING_RADIUS).round() as i64,
+            middle.1 + (angle.sin() * LAMP_RING_RADIUS).round() as i64,
+        );
+        if is_plaza(lamp) {
+            build_lamp_post(excerpt, ground_at(lamp));
+        }
+    }
+
+    info!("Built a market square at {:?}, with {} market stalls.", middle, stall_count);
+}
+
+/// A statue on a stepped pedestal.
+/// The walkway of an arcade along the sides of the plot facing the market square across
+/// the street: The row of the plot next to those streets, where the houses are set back
+/// from the street. Empty if the plot does not face the square.
+pub fn arcade_walkway(plot: &Plot, square: &Snake) -> Vec<BlockColumnCoord> {
+    let polygon = plot.polygon();
+    let mut walkway = Vec::new();
+    for edge in &plot.edges {
+        let width = match edge.kind {
+            PlotEdgeKind::Road { width } => width as i64,
+            _ => continue,
+        };
+        let (start, end) = edge.points;
+        let (dx, dz) = ((end.0 - start.0) as f32, (end.2 - start.2) as f32);
+        let length = (dx * dx + dz * dz).sqrt();
+        if length == 0.0 {
+            continue;
+        }
+
+        // Facing the square, if the square is right across the street from the middle of the edge
+        let reach = (width + 2) as f32;
+        let (middle_x, middle_z) = ((start.0 + end.0) as f32 / 2.0, (start.2 + end.2) as f32 / 2.0);
+        let faces_square = [-1.0, 1.0].iter().any(|side| {
+            let across = BlockColumnCoord(
+                (middle_x - side * dz / length * reach).round() as i64,
+                (middle_z + side * dx / length * reach).round() as i64,
+            );
+            geometry::point_position_relative_to_polygon(across, square) == InOutSide::Inside
+        });
+        if !faces_square {
+            continue;
+        }
+
+        let street: Vec<BlockColumnCoord> = line::line(&start, &end, width)
+            .iter()
+            .map(|position| BlockColumnCoord(position.0, position.2))
+            .collect();
+        for column in &street {
+            for (x, z) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let neighbour = BlockColumnCoord(column.0 + x, column.1 + z);
+                if !street.contains(&neighbour)
+                    && geometry::point_position_relative_to_polygon(neighbour, &polygon) == InOutSide::Inside
+                {
+                    walkway.push(neighbour);
+                }
+            }
+        }
+    }
+    walkway.sort_unstable_by_key(|column| (column.0, column.1));
+    walkway.dedup();
+    walkway
+}
+
+/// Build an arcade over the walkway, in front of a house with its ground floor at `floor_y`:
+/// Columns along the walkway, carrying a slab roof at the height of the upper floor.
+pub fn build_arcade(
+    excerpt: &mut WorldExcerpt,
+    walkway: &[BlockColumnCoord],
+    floor_y: i64,
+    story_height: usize,
+    palette: &BlockPalette,
+) {
+    let roof_y = floor_y + story_height as i64 - 1;
+    let roof = Block::bottom_slab(block_palette::stairs_material(&palette.roof).unwrap_or(Material::Cobblestone));
+    for (index, column) in walkway.iter().enumerate() {
+        let ground = BlockCoord(column.0, floor_y, column.1);
+        let is_column = index % ARCADE_COLUMN_SPACING == 0 || index + 1 == walkway.len();
+        for y in 0..roof_y - floor_y {
+            let block = if is_column { palette.wall.clone() } else { Block::Air };
+            tree::chop(excerpt, ground + BlockCoord(0, y, 0));
+            excerpt.set_block_at(ground + BlockCoord(0, y, 0), block);
+        }
+        excerpt.set_block_at(ground - BlockCoord(0, 1, 0), Block::StoneBricks);
+        excerpt.set_block_at(BlockCoord(column.0, roof_y, column.1), roof.clone());
+    }
+}
+
+fn build_statue(excerpt: &mut WorldExcerpt, ground: BlockCoord) {
+    for dx in -1..=1 {
+        for dz in -1..=1 {
+            excerpt.set_block_at(ground + BlockCoord(dx, 0, dz), Block::StoneBricks);
+        }
+    }
+    excerpt.set_block_at(ground + BlockCoord(0, 1, 0), Block::ChiseledStoneBricks);
+    for y in 2..5 {
+        excerpt.set_block_at(ground + BlockCoord(0, y, 0), Block::Andesite);
+    }
+    excerpt.set_block_at(ground + BlockCoord(0, 5, 0), Block::ChiseledStoneBricks);
+}
+
+/// The columns of a market stall, three wide and two deep, with its front towards `front`.
+fn stall_footprint(stall: BlockColumnCoord, front: Surface4) -> Vec<BlockColumnCoord> {
+    let back = geometry::offset(front.opposite());
+    let side = geometry::offset(front.rotated_90_cw());
+    let mut footprint = Vec::new();
+    for along in 0..2 {
+        for across in -1..=1 {
+            footprint.push(BlockColumnCoord(
+                stall.0 + back.0 * along + side.0 * across,
+                stall.1 + back.2 * along + side.2 * across,
+            ));
+        }
+    }
+    footprint
+}
+
+/// A market stall: a counter in front, a chest of wares behind it,
+/// and a wool awning on fence posts over it all.
+fn build_stall(
+    excerpt: &mut WorldExcerpt,
+    stall: BlockColumnCoord,
+    floor_y: i64,
+    front: Surface4,
+    colour: Colour,
+) {
+    let back = geometry::offset(front.opposite());
+    let side = geometry::offset(front.rotated_90_cw());
+    let at = |along: i64, across: i64, y: i64| {
+        BlockCoord(stall.0 + back.0 * along + side.0 * across, y, stall.1 + back.2 * along + side.2 * across)
+    };
+
+    for along in 0..2 {
+        for across in -1..=1 {
+            // Corner posts
+            if across != 0 {
+                for y in floor_y..floor_y + 2 {
+                    excerpt.set_block_at(
+                        at(along, across, y),
+                        Block::Fence { material: FenceMaterial::Oak, waterlogged: false },
+                    );
+                }
+            }
+            excerpt.set_block_at(at(along, across, floor_y + 2), Block::Wool { colour });
+        }
+    }
+
+    excerpt.set_block_at(at(0, 0, floor_y), Block::barrel(Surface6::Up));
+    excerpt.set_block_at(at(1, 0, floor_y), Block::chest(front));
+}
+
+/// A fence post with a lantern on top.
+fn build_lamp_post(excerpt: &mut WorldExcerpt, ground: BlockCoord) {
+    for y in 0..3 {
+        excerpt.set_block_at(
+            ground + BlockCoord(0, y, 0),
+            Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false },
+        );
+    }
+    excerpt.set_block_at(
+        ground + BlockCoord(0, 3, 0),
+        Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+    );
+}
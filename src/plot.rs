@@ -3,7 +3,7 @@ use crate::geometry::{IntersectionPoints, LandUsageGraph, RawEdge2d, RawEdge3d};
 use imageproc::drawing::draw_line_segment_mut;
 use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
 
-use log::{trace, warn};
+use log::{info, trace, warn};
 
 const PLOT_AREA_MIN: i64 = 40;
 const PLOT_AREA_MAX: i64 = 150;
@@ -23,6 +23,7 @@ pub struct PlotEdge {
 pub enum PlotEdgeKind {
     Road { width: usize },
     Wall { width: usize },
+    Canal { width: usize },
     Plot,
     Terrain,
 }
@@ -289,6 +290,7 @@ impl Plot {
             let colour = match edge.kind {
                 PlotEdgeKind::Road { .. } => image::Rgb([191u8, 63u8, 63u8]),
                 PlotEdgeKind::Wall { .. } => image::Rgb([63u8, 63u8, 63u8]),
+                PlotEdgeKind::Canal { .. } => image::Rgb([63u8, 63u8, 191u8]),
                 PlotEdgeKind::Plot => image::Rgb([127u8, 255u8, 127u8]),
                 PlotEdgeKind::Terrain => image::Rgb([0u8, 127u8, 127u8]),
             };
@@ -302,6 +304,167 @@ impl Plot {
     }
 }
 
+/// Find runs of narrow plots next to each other along the same street, to build as row houses.
+/// Returns for each plot the edges it shares with its neighbours in the row, for the party walls.
+///
+/// Plots are narrow if their street frontage is at most `frontage_max` blocks, and only the
+/// plots for which `is_house_plot` holds are joined into rows. 0 for `frontage_max` means
+/// no row houses.
+pub fn row_house_party_walls<F>(plots: &[Plot], frontage_max: usize, is_house_plot: F) -> Vec<Vec<RawEdge2d>>
+where
+    F: Fn(usize) -> bool,
+{
+    let mut party_walls = vec![Vec::new(); plots.len()];
+    if frontage_max == 0 {
+        return party_walls;
+    }
+
+    let is_street = |edge: &&PlotEdge| matches!(edge.kind, PlotEdgeKind::Road { .. });
+    let is_narrow: Vec<bool> = plots.iter()
+        .enumerate()
+        .map(|(index, plot)| {
+            let frontage: f32 = plot.edges.iter()
+                .filter(is_street)
+                .map(|edge| geometry::euclidean_distance(edge.points.0.into(), edge.points.1.into()))
+                .sum();
+            is_house_plot(index) && frontage > 0.0 && frontage <= frontage_max as f32
+        })
+        .collect();
+
+    // Neighbouring narrow plots, sharing an edge that reaches the street at a corner of both
+    for index in 0..plots.len() {
+        if !is_narrow[index] {
+            continue;
+        }
+        for other in index + 1..plots.len() {
+            if !is_narrow[other] {
+                continue;
+            }
+            let shared = street_party_walls(&plots[index], &plots[other]);
+            party_walls[index].extend(shared.iter().copied());
+            party_walls[other].extend(shared);
+        }
+    }
+
+    info!(
+        "Joined {} narrow plots into rows of houses.",
+        party_walls.iter().filter(|walls| !walls.is_empty()).count(),
+    );
+    party_walls
+}
+
+/// Pair up small plots next to each other along the same street, that are each too small
+/// for a house of their own, to build as semi-detached houses. Returns for each plot its
+/// partner in the pair, and the edge they share, for the party wall and chimney.
+///
+/// Plots are too small if their area is at most `area_max` m², and only the plots for which
+/// `is_house_plot` holds are paired. 0 for `area_max` means no semi-detached houses.
+pub fn semi_detached_pairs<F>(plots: &[Plot], area_max: usize, is_house_plot: F) -> Vec<Option<(usize, RawEdge2d)>>
+where
+    F: Fn(usize) -> bool,
+{
+    let mut pairs = vec![None; plots.len()];
+    if area_max == 0 {
+        return pairs;
+    }
+
+    let is_small: Vec<bool> = plots.iter()
+        .enumerate()
+        .map(|(index, plot)| {
+            let has_street = plot.edges.iter().any(|edge| matches!(edge.kind, PlotEdgeKind::Road { .. }));
+            is_house_plot(index) && has_street && geometry::area(&plot.polygon()).abs() <= area_max as i64
+        })
+        .collect();
+
+    for index in 0..plots.len() {
+        if !is_small[index] || pairs[index].is_some() {
+            continue;
+        }
+        for other in index + 1..plots.len() {
+            if !is_small[other] || pairs[other].is_some() {
+                continue;
+            }
+            if let Some(shared) = street_party_walls(&plots[index], &plots[other]).first() {
+                pairs[index] = Some((other, *shared));
+                pairs[other] = Some((index, *shared));
+                break;
+            }
+        }
+    }
+
+    info!(
+        "Paired {} small plots into semi-detached houses.",
+        pairs.iter().filter(|pair| pair.is_some()).count(),
+    );
+    pairs
+}
+
+/// The edges two plots share, that reach the street at a corner of both plots,
+/// for party walls between houses built on them.
+fn street_party_walls(plot: &Plot, other: &Plot) -> Vec<RawEdge2d> {
+    let street_corners = |plot: &Plot| -> Vec<BlockColumnCoord> {
+        plot.edges.iter()
+            .filter(|edge| matches!(edge.kind, PlotEdgeKind::Road { .. }))
+            .flat_map(|edge| vec![BlockColumnCoord::from(edge.points.0), BlockColumnCoord::from(edge.points.1)])
+            .collect()
+    };
+    let neighbour_edges = |plot: &Plot| -> Vec<RawEdge2d> {
+        plot.edges.iter()
+            .filter(|edge| matches!(edge.kind, PlotEdgeKind::Plot))
+            .map(|edge| (edge.points.0.into(), edge.points.1.into()))
+            .collect()
+    };
+    let (corners, other_corners) = (street_corners(plot), street_corners(other));
+
+    let mut walls = Vec::new();
+    for edge in neighbour_edges(plot) {
+        for other_edge in neighbour_edges(other) {
+            if let Some(shared) = shared_segment(edge, other_edge) {
+                let reaches_street = |corners: &[BlockColumnCoord]| {
+                    corners.iter().any(|corner| {
+                        geometry::manhattan_distance(*corner, shared.0) <= 2
+                            || geometry::manhattan_distance(*corner, shared.1) <= 2
+                    })
+                };
+                if reaches_street(&corners) && reaches_street(&other_corners) {
+                    walls.push(shared);
+                }
+            }
+        }
+    }
+    walls
+}
+
+/// The part two line segments have in common, if they run along the same line and overlap.
+/// Allows for the endpoints being up to a block off the line, from rounding.
+fn shared_segment(a: RawEdge2d, b: RawEdge2d) -> Option<RawEdge2d> {
+    let direction = ((a.1 .0 - a.0 .0) as f32, (a.1 .1 - a.0 .1) as f32);
+    let length = (direction.0 * direction.0 + direction.1 * direction.1).sqrt();
+    if length == 0.0 {
+        return None;
+    }
+    let offset = |point: BlockColumnCoord| ((point.0 - a.0 .0) as f32, (point.1 - a.0 .1) as f32);
+    let across = |point: BlockColumnCoord| {
+        let (dx, dz) = offset(point);
+        (dx * direction.1 - dz * direction.0).abs() / length
+    };
+    let along = |point: BlockColumnCoord| {
+        let (dx, dz) = offset(point);
+        (dx * direction.0 + dz * direction.1) / length
+    };
+    if across(b.0) > 1.0 || across(b.1) > 1.0 {
+        return None;
+    }
+
+    let (b_start, b_end) = (along(b.0).min(along(b.1)), along(b.0).max(along(b.1)));
+    if length.min(b_end) - b_start.max(0.0) < 1.0 {
+        return None;
+    }
+    let mut points = [a.0, a.1, b.0, b.1];
+    points.sort_by(|p, q| along(*p).partial_cmp(&along(*q)).unwrap_or(std::cmp::Ordering::Equal));
+    Some((points[1], points[2]))
+}
+
 pub fn divide_city_block(
     city_block: &[BlockColumnCoord],
     land_usage: &LandUsageGraph,
@@ -315,13 +478,13 @@ fn divide_plot(plot: &Plot) -> Vec<Plot> {
 }
 
 fn rec_subdiv_obb(plot: &Plot, area_bounds: (i64, i64)) -> Vec<Plot> {
-    //println!("rec_subdiv_obb()");
+    trace!("rec_subdiv_obb()");
     let polygon = plot.polygon();
     let area = geometry::area(&polygon);
 
     // Do not split if already small enough
     if area < area_bounds.1 {
-        //println!("Area already satisfactory. Aborting.");
+        trace!("Area already satisfactory. Aborting.");
         return vec![plot.clone()];
     }
 
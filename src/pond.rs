@@ -0,0 +1,162 @@
+//! Water inside the town wall: Small ponds are filled in, larger ponds are kept as
+//! ornamental ponds edged with stone, and rivers running through town are lined as canals.
+
+use std::collections::{HashSet, VecDeque};
+
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::tree;
+use crate::types::Snake;
+
+/// Largest difference in height between the water and its bank, for edging the bank in stone.
+const EDGING_HEIGHT_MAX: i64 = 1;
+
+/// What is done with a body of water inside the town wall.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WaterTreatment {
+    /// Filled in with earth, leaving dry land to build on.
+    Fill,
+    /// Kept as an ornamental pond, edged with stone.
+    OrnamentalPond,
+    /// A river running through the town wall, lined with stone as a canal.
+    Canal,
+}
+
+/// A body of water inside the town wall.
+#[derive(Clone, Debug)]
+pub struct Pond {
+    /// The columns of water, inside the town wall.
+    pub columns: Vec<BlockColumnCoord>,
+    /// Height of the water surface, as the lowest air above the water.
+    pub surface_y: i64,
+    pub treatment: WaterTreatment,
+}
+
+/// Find the bodies of water inside the town wall, and decide what to do with each of them.
+/// Ponds of at most `fill_area_max` columns are filled in, larger ones kept, and water
+/// reaching out through the town wall is lined as a canal.
+pub fn plan_ponds(features: &Features, wall_circle: &Snake, fill_area_max: usize) -> Vec<Pond> {
+    let (x_len, z_len) = features.dimensions();
+    let is_water = |x: i64, z: i64| {
+        x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64
+            && features.water[(x as u32, z as u32)] == image::Luma([255u8])
+    };
+    let is_inside = |column: BlockColumnCoord| {
+        geometry::point_position_relative_to_polygon(column, wall_circle) == InOutSide::Inside
+    };
+
+    let mut visited = vec![false; x_len * z_len];
+    let mut ponds = Vec::new();
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            if visited[x as usize * z_len + z as usize] || !is_water(x, z) || !is_inside(BlockColumnCoord(x, z)) {
+                continue;
+            }
+
+            // Flood fill the body of water, also outside the wall to tell rivers from ponds
+            let mut columns = Vec::new();
+            let mut reaches_outside = false;
+            let mut queue = VecDeque::new();
+            visited[x as usize * z_len + z as usize] = true;
+            queue.push_back(BlockColumnCoord(x, z));
+            while let Some(column) = queue.pop_front() {
+                if is_inside(column) {
+                    columns.push(column);
+                } else {
+                    reaches_outside = true;
+                }
+                for (dx, dz) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    let (next_x, next_z) = (column.0 + dx, column.1 + dz);
+                    if is_water(next_x, next_z) && !visited[next_x as usize * z_len + next_z as usize] {
+                        visited[next_x as usize * z_len + next_z as usize] = true;
+                        queue.push_back(BlockColumnCoord(next_x, next_z));
+                    }
+                }
+            }
+
+            let surface_y = columns.iter()
+                .filter_map(|column| features.height_map.height_at((column.0 as usize, column.1 as usize)))
+                .max()
+                .unwrap_or(0) as i64;
+            let treatment = if reaches_outside {
+                WaterTreatment::Canal
+            } else if columns.len() <= fill_area_max {
+                WaterTreatment::Fill
+            } else {
+                WaterTreatment::OrnamentalPond
+            };
+            ponds.push(Pond { columns, surface_y, treatment });
+        }
+    }
+
+    info!(
+        "Found {} bodies of water inside the town wall, of which {} are to be filled in.",
+        ponds.len(),
+        ponds.iter().filter(|pond| pond.treatment == WaterTreatment::Fill).count(),
+    );
+    ponds
+}
+
+/// Mark the ponds to be filled in as dry ground in the features, ahead of filling them,
+/// so that the streets and plots are laid out across them.
+pub fn mark_filled_ponds(features: &mut Features, ponds: &[Pond]) {
+    for pond in ponds.iter().filter(|pond| pond.treatment == WaterTreatment::Fill) {
+        for column in &pond.columns {
+            let (x, z) = (column.0 as u32, column.1 as u32);
+            features.water.put_pixel(x, z, image::Luma([0u8]));
+            features.water_depth.put_pixel(x, z, image::Luma([0u8]));
+            features.terrain.put_pixel(x, z, image::Luma([pond.surface_y as u8]));
+        }
+    }
+}
+
+/// Fill in the pond with earth up to the water surface, or edge it with stone along its
+/// banks, as decided for it.
+pub fn build_pond(excerpt: &mut WorldExcerpt, pond: &Pond, features: &Features) {
+    let ground_at = |column: BlockColumnCoord| -> Option<i64> {
+        features.terrain_height_map
+            .height_at((column.0 as usize, column.1 as usize))
+            .map(|y| y as i64)
+    };
+
+    match pond.treatment {
+        WaterTreatment::Fill => {
+            for column in &pond.columns {
+                let bottom_y = ground_at(*column).unwrap_or(pond.surface_y - 1);
+                for y in bottom_y..pond.surface_y - 1 {
+                    excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::Dirt);
+                }
+                excerpt.set_block_at(BlockCoord(column.0, pond.surface_y - 1, column.1), Block::GrassBlock);
+            }
+        }
+        WaterTreatment::OrnamentalPond | WaterTreatment::Canal => {
+            let columns: HashSet<BlockColumnCoord> = pond.columns.iter().copied().collect();
+            for column in &pond.columns {
+                for (dx, dz) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    let bank = BlockColumnCoord(column.0 + dx, column.1 + dz);
+                    if columns.contains(&bank) {
+                        continue;
+                    }
+                    let bank_y = match ground_at(bank) {
+                        Some(bank_y) if (bank_y - pond.surface_y).abs() <= EDGING_HEIGHT_MAX => bank_y,
+                        _ => continue,
+                    };
+                    if matches!(
+                        excerpt.block_at(BlockCoord(bank.0, bank_y - 1, bank.1)),
+                        Some(Block::WaterSource) | Some(Block::Water { .. }),
+                    ) {
+                        continue;
+                    }
+                    tree::chop(excerpt, BlockCoord(bank.0, bank_y, bank.1));
+                    excerpt.set_block_at(BlockCoord(bank.0, bank_y, bank.1), Block::Air);
+                    excerpt.set_block_at(BlockCoord(bank.0, bank_y - 1, bank.1), Block::StoneBricks);
+                }
+            }
+        }
+    }
+}
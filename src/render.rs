@@ -0,0 +1,234 @@
+//! Top-down renders of world excerpts, and of the differences between two renders of the
+//! same selection, for judging at a glance how much of the world a generation run changed.
+
+use image::{GrayImage, Rgb, RgbImage};
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+/// Colour the modified columns are tinted towards.
+const HIGHLIGHT: [u8; 3] = [255, 0, 255];
+/// Width of the gap between the before and after renders, when side by side.
+const GAP_WIDTH: u32 = 4;
+
+/// How the before and after renders are put together.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiffLayout {
+    /// The render before on the left, and the render after on the right.
+    SideBySide,
+    /// The renders before and after blended into one.
+    Blended,
+}
+
+/// The before and after render of a selection, with the modified columns highlighted.
+pub struct RenderDiff {
+    pub image: RgbImage,
+    /// Stencil of the columns with any block modified.
+    pub modified: GrayImage,
+    pub modified_count: usize,
+    pub column_count: usize,
+}
+
+/// Render of the excerpt seen from above, with the colour of the topmost block of each
+/// column, darker the lower it is.
+pub fn top_down(excerpt: &WorldExcerpt) -> RgbImage {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let height_map = excerpt.height_map();
+    let mut image = RgbImage::new(x_len as u32, z_len as u32);
+    for x in 0..x_len {
+        for z in 0..z_len {
+            let y = height_map.height_at((x, z)).unwrap_or(0) as i64 - 1;
+            let colour = match excerpt.block_at(BlockCoord(x as i64, y, z as i64)) {
+                Some(block) if y >= 0 => block_colour(block),
+                _ => Rgb([0, 0, 0]),
+            };
+            let shade = 0.6 + 0.4 * y.max(0) as f32 / y_len.max(1) as f32;
+            image.put_pixel(x as u32, z as u32, Rgb([
+                (colour[0] as f32 * shade) as u8,
+                (colour[1] as f32 * shade) as u8,
+                (colour[2] as f32 * shade) as u8,
+            ]));
+        }
+    }
+    image
+}
+
+/// Stencil of the columns where any block differs between the two excerpts.
+pub fn modified_columns(before: &WorldExcerpt, after: &WorldExcerpt) -> GrayImage {
+    let (x_len, y_len, z_len) = before.dim();
+    let mut modified = GrayImage::new(x_len as u32, z_len as u32);
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            let is_modified = (0..y_len as i64).any(|y| {
+                before.block_at(BlockCoord(x, y, z)) != after.block_at(BlockCoord(x, y, z))
+            });
+            if is_modified {
+                modified.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+    }
+    modified
+}
+
+/// Render the selection before and after, laid out as given, with the modified columns
+/// tinted in the render after, or in the blended render.
+pub fn render_diff(before: &WorldExcerpt, after: &WorldExcerpt, layout: DiffLayout) -> RenderDiff {
+    let modified = modified_columns(before, after);
+    let before_image = top_down(before);
+    let mut after_image = top_down(after);
+    let (x_len, z_len) = before_image.dimensions();
+
+    let mut modified_count = 0;
+    for (x, z, pixel) in modified.enumerate_pixels() {
+        if *pixel == image::Luma([255u8]) {
+            modified_count += 1;
+            if x < after_image.width() && z < after_image.height() {
+                let tinted = highlight(after_image[(x, z)]);
+                after_image.put_pixel(x, z, tinted);
+            }
+        }
+    }
+
+    let image = match layout {
+        DiffLayout::SideBySide => {
+            let mut image = RgbImage::from_pixel(x_len * 2 + GAP_WIDTH, z_len, Rgb([255, 255, 255]));
+            for (x, z, pixel) in before_image.enumerate_pixels() {
+                image.put_pixel(x, z, *pixel);
+            }
+            for (x, z, pixel) in after_image.enumerate_pixels() {
+                if x < x_len && z < z_len {
+                    image.put_pixel(x_len + GAP_WIDTH + x, z, *pixel);
+                }
+            }
+            image
+        }
+        DiffLayout::Blended => {
+            let mut image = before_image.clone();
+            for (x, z, pixel) in image.enumerate_pixels_mut() {
+                if x < after_image.width() && z < after_image.height() {
+                    let after_pixel = after_image[(x, z)];
+                    for channel in 0..3 {
+                        pixel[channel] = ((pixel[channel] as u16 + after_pixel[channel] as u16) / 2) as u8;
+                    }
+                }
+            }
+            image
+        }
+    };
+
+    RenderDiff {
+        image,
+        modified,
+        modified_count,
+        column_count: (x_len * z_len) as usize,
+    }
+}
+
+/// The pixel, tinted halfway towards the highlight colour.
+fn highlight(pixel: Rgb<u8>) -> Rgb<u8> {
+    Rgb([
+        ((pixel[0] as u16 + HIGHLIGHT[0] as u16) / 2) as u8,
+        ((pixel[1] as u16 + HIGHLIGHT[1] as u16) / 2) as u8,
+        ((pixel[2] as u16 + HIGHLIGHT[2] as u16) / 2) as u8,
+    ])
+}
+
+/// Rough colour of the block, seen from above.
+fn block_colour(block: &Block) -> Rgb<u8> {
+    match block {
+        Block::WaterSource | Block::Water { .. } => Rgb([48, 80, 200]),
+        Block::LavaSource | Block::Lava { .. } => Rgb([230, 100, 20]),
+        Block::GrassBlock => Rgb([96, 156, 60]),
+        Block::Leaves { .. } => Rgb([50, 110, 40]),
+        Block::Dirt | Block::CoarseDirt | Block::Podzol | Block::Farmland { .. } => Rgb([130, 94, 64]),
+        Block::Sand | Block::Sandstone | Block::SmoothSandstone => Rgb([218, 206, 150]),
+        Block::RedSand | Block::RedSandstone | Block::Terracotta { .. } => Rgb([180, 96, 44]),
+        Block::Gravel => Rgb([130, 124, 122]),
+        Block::Snow { .. } | Block::SnowBlock => Rgb([240, 250, 250]),
+        Block::Ice | Block::BlueIce | Block::FrostedIce => Rgb([150, 180, 250]),
+        Block::Log(_) | Block::Planks { .. } => Rgb([150, 110, 60]),
+        Block::BrickBlock => Rgb([150, 74, 60]),
+        Block::Netherrack | Block::NetherBricks => Rgb([110, 40, 40]),
+        Block::Stone
+        | Block::Andesite
+        | Block::Diorite
+        | Block::Granite
+        | Block::Cobblestone
+        | Block::MossyCobblestone
+        | Block::StoneBricks
+        | Block::MossyStoneBricks => Rgb([125, 125, 125]),
+        _ => Rgb([160, 160, 160]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small excerpt of air, with the given blocks set.
+    fn excerpt_with(blocks: &[(BlockCoord, Block)]) -> WorldExcerpt {
+        let mut excerpt = WorldExcerpt::new(3, 2, 3);
+        for x in 0..3 {
+            for y in 0..2 {
+                for z in 0..3 {
+                    excerpt.set_block_at(BlockCoord(x, y, z), Block::Air);
+                }
+            }
+        }
+        for (coordinates, block) in blocks {
+            excerpt.set_block_at(*coordinates, block.clone());
+        }
+        excerpt
+    }
+
+    #[test]
+    fn modified_columns_of_identical_excerpts() {
+        let before = excerpt_with(&[(BlockCoord(1, 0, 1), Block::Stone)]);
+        let after = excerpt_with(&[(BlockCoord(1, 0, 1), Block::Stone)]);
+        let modified = modified_columns(&before, &after);
+        assert!(modified.pixels().all(|pixel| *pixel == image::Luma([0u8])));
+    }
+
+    #[test]
+    fn modified_columns_marks_each_changed_column() {
+        let before = excerpt_with(&[(BlockCoord(1, 0, 1), Block::Stone)]);
+        let after = excerpt_with(&[
+            (BlockCoord(1, 0, 1), Block::Stone),
+            (BlockCoord(2, 0, 0), Block::Dirt),
+            (BlockCoord(0, 1, 2), Block::Cobblestone),
+        ]);
+
+        let modified = modified_columns(&before, &after);
+
+        let modified_at: Vec<(u32, u32)> = modified.enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == image::Luma([255u8]))
+            .map(|(x, z, _)| (x, z))
+            .collect();
+        assert_eq!(vec![(2, 0), (0, 2)], modified_at);
+    }
+
+    #[test]
+    fn render_diff_side_by_side() {
+        let before = excerpt_with(&[]);
+        let after = excerpt_with(&[(BlockCoord(2, 0, 0), Block::Dirt)]);
+
+        let diff = render_diff(&before, &after, DiffLayout::SideBySide);
+
+        assert_eq!(1, diff.modified_count);
+        assert_eq!(9, diff.column_count);
+        assert_eq!((3 * 2 + GAP_WIDTH, 3), diff.image.dimensions());
+    }
+
+    #[test]
+    fn render_diff_blended() {
+        let diff = render_diff(&excerpt_with(&[]), &excerpt_with(&[]), DiffLayout::Blended);
+
+        assert_eq!(0, diff.modified_count);
+        assert_eq!((3, 3), diff.image.dimensions());
+    }
+
+    #[test]
+    fn highlight_tints_halfway() {
+        assert_eq!(Rgb([128, 127, 128]), highlight(Rgb([1, 255, 1])));
+    }
+}
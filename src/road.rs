@@ -1,15 +1,69 @@
-use crate::geometry::{point_position_relative_to_polygon, InOutSide};
+use crate::block_palette::BlockPalette;
+use crate::config::GenerationConfig;
+use crate::features::Features;
+use crate::generation_context::paste_chopping_trees;
+use crate::geometry::{self, point_position_relative_to_polygon, InOutSide};
+use crate::hut::{self, HUT_FOUNDATION_DEPTH};
 use crate::line;
-use crate::pathfinding::{RoadNode, RoadNodeKind, RoadPath};
+use crate::pathfinding::{RoadClass, RoadNode, RoadNodeKind, RoadPath};
+use crate::signs;
+use crate::structure_builder::ExteriorDoor;
 use crate::tree;
 use crate::types::Snake;
 
 use image::GrayImage;
 use mcprogedit::block::Block;
-use mcprogedit::material::Material;
-use mcprogedit::positioning::Axis3;
+use mcprogedit::coordinates::BlockCoord;
+use mcprogedit::material::{CoralMaterial, FenceMaterial, Material, WoodMaterial};
+use mcprogedit::positioning::{Axis3, Surface2};
 use mcprogedit::world_excerpt::WorldExcerpt;
-use rand::{Rng, thread_rng};
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::collections::{HashMap, HashSet};
+
+/// Shortest distance between the abutments of a bridge, for it to get a bridge keeper.
+const LONG_BRIDGE_LENGTH: f32 = 20.0;
+/// Outer dimensions of the bridge keeper's hut.
+const BRIDGE_KEEPER_HUT_SIZE: i64 = 6;
+/// Largest difference in ground height under the bridge keeper's hut.
+const BRIDGE_KEEPER_HUT_SLOPE_MAX: u8 = 2;
+/// Distance between the piles holding up a boardwalk over water, along both axes.
+const BOARDWALK_PILE_SPACING: i64 = 3;
+/// Height of the roof of a covered street, above the street surface.
+const COVERED_STREET_ROOF_HEIGHT: i64 = 5;
+/// Distance along a covered street from one light well in the roof to the next.
+const LIGHT_WELL_SPACING: i64 = 9;
+/// Length of each light well, along the street.
+const LIGHT_WELL_LENGTH: i64 = 2;
+/// Distance between the posts carrying the roof of a covered street, where there are
+/// no houses along the street to carry it.
+const ROOF_POST_SPACING: i64 = 4;
+/// Distance along an enclosed walkway from one lantern hanging from the roof to the next.
+const WALKWAY_LANTERN_SPACING: i64 = 6;
+/// Distance out from the front doors to keep the walls of the enclosed walkways.
+const WALKWAY_DOOR_CLEARANCE: usize = 2;
+/// Smallest share of a street inside the market districts, for covering it as a market street.
+const MARKET_STREET_SHARE_MIN: f32 = 0.5;
+/// Height of the fence posts carrying the lanterns at the ends of a bridge.
+const BRIDGE_LAMP_POST_HEIGHT: i64 = 2;
+/// Largest drop beside a road that is left without a railing.
+const UNGUARDED_DROP_MAX: i64 = 2;
+/// Number of blocks out from the road that the ground is sloped, where the road was built
+/// up above it or cut down into it.
+const EMBANKMENT_REACH: usize = 3;
+/// Highest an embankment may be, above or below the road, before a retaining wall is built
+/// instead.
+const EMBANKMENT_HEIGHT_MAX: i64 = 3;
+/// Farthest from the end of a road another road may pass, for the two to meet in a junction.
+const JUNCTION_REACH: f32 = 2.0;
+/// Fewest roads leading out of a junction of major roads, for building a roundabout.
+const ROUNDABOUT_ARMS_MIN: usize = 4;
+/// Height of the lamp post on the island of a roundabout.
+const ROUNDABOUT_LAMP_POST_HEIGHT: i64 = 3;
+/// Height of the bore of a tunnel, from the road surface to the ceiling.
+const TUNNEL_HEIGHT: i64 = 3;
+/// Number of tunnel nodes from one timber frame supporting the tunnel ceiling to the next.
+const TUNNEL_FRAME_SPACING: usize = 3;
 
 /*
 // TODO implement a concept of "road", that contains both the path, the width,
@@ -20,6 +74,325 @@ struct Road {
 }
 */
 
+/// How the roads of a class are built.
+#[derive(Clone, Debug)]
+pub struct RoadStyle {
+    pub width: i64,
+    /// Blocks to cover the road with, picked at random. Repeated blocks are more common.
+    pub covers: Vec<Block>,
+    /// Block lining the edges of the road, level with the road surface, if any.
+    pub edging: Option<Block>,
+    /// Distance along the road between lamp posts, if the road is lit.
+    pub lighting_interval: Option<f32>,
+}
+
+impl RoadClass {
+    /// How roads of this class are built, with the widths from `config`.
+    pub fn style(self, config: &GenerationConfig) -> RoadStyle {
+        let dead_coral = |material| Block::CoralBlock { material, dead: true };
+        match self {
+            Self::Highway => RoadStyle {
+                width: config.country_road_width,
+                covers: [
+                    vec![Block::Gravel; 8],
+                    vec![dead_coral(CoralMaterial::Fire); 4],
+                    vec![
+                        dead_coral(CoralMaterial::Bubble),
+                        dead_coral(CoralMaterial::Horn),
+                        dead_coral(CoralMaterial::Tube),
+                    ],
+                    vec![Block::CoarseDirt; 3],
+                ].concat(),
+                edging: None,
+                lighting_interval: None,
+            },
+            Self::CityRoad => RoadStyle {
+                width: config.city_road_width,
+                covers: [
+                    vec![Block::Gravel; 4],
+                    vec![dead_coral(CoralMaterial::Fire); 4],
+                    vec![Block::Andesite; 2],
+                    vec![
+                        dead_coral(CoralMaterial::Bubble),
+                        dead_coral(CoralMaterial::Horn),
+                        dead_coral(CoralMaterial::Tube),
+                    ],
+                    vec![Block::CrackedStoneBricks; 2],
+                    vec![Block::StoneBricks],
+                    vec![Block::Cobblestone; 2],
+                ].concat(),
+                edging: Some(Block::StoneBricks),
+                lighting_interval: Some(16.0),
+            },
+            Self::Street => RoadStyle {
+                width: config.street_width,
+                covers: [
+                    vec![Block::Gravel; 8],
+                    vec![dead_coral(CoralMaterial::Fire); 2],
+                    vec![dead_coral(CoralMaterial::Horn)],
+                ].concat(),
+                edging: None,
+                lighting_interval: Some(16.0),
+            },
+            Self::Alley => RoadStyle {
+                width: config.alley_width,
+                covers: [vec![Block::Gravel; 2], vec![Block::CoarseDirt]].concat(),
+                edging: None,
+                lighting_interval: Some(24.0),
+            },
+            Self::Path => RoadStyle {
+                width: config.path_width,
+                covers: [vec![Block::CoarseDirt; 2], vec![Block::Gravel]].concat(),
+                edging: None,
+                lighting_interval: None,
+            },
+        }
+    }
+}
+
+impl RoadStyle {
+    /// The same road as a boardwalk of planks, for stilt villages, without edging.
+    pub fn boardwalk(self) -> Self {
+        Self {
+            covers: [
+                vec![Block::Planks { material: WoodMaterial::Spruce }; 3],
+                vec![Block::dark_oak_planks()],
+            ].concat(),
+            edging: None,
+            ..self
+        }
+    }
+}
+
+/// The class of a street between the city blocks: Short streets are alleys.
+pub fn street_class(street: &RoadPath, config: &GenerationConfig) -> RoadClass {
+    let length: f32 = street.windows(2)
+        .map(|pair| {
+            let (a, b) = (pair[0].coordinates, pair[1].coordinates);
+            (((a.0 - b.0).pow(2) + (a.2 - b.2).pow(2)) as f32).sqrt()
+        })
+        .sum();
+    if length <= config.alley_length_max as f32 {
+        RoadClass::Alley
+    } else {
+        RoadClass::Street
+    }
+}
+
+/// Build the road in the given style, with its edging.
+pub fn build_road_in_style(
+    excerpt: &mut WorldExcerpt,
+    path: &RoadPath,
+    height_map: &GrayImage,
+    style: &RoadStyle,
+    rng: &mut StdRng,
+) {
+    build_road(excerpt, path, height_map, style.width, &style.covers, rng);
+    if let Some(edging) = &style.edging {
+        build_edging(excerpt, path, style.width, edging);
+    }
+}
+
+/// Where roads meet.
+#[derive(Clone, Debug)]
+pub struct Junction {
+    pub center: BlockCoord,
+    /// Unit (x, z) directions of the roads leading out of the junction.
+    pub arms: Vec<(f32, f32)>,
+    /// Class of the widest road meeting here.
+    pub class: RoadClass,
+    /// Width of the widest road meeting here.
+    pub width: i64,
+    /// Whether all the roads meeting here are highways or city roads.
+    pub is_major: bool,
+}
+
+/// Find the junctions where the end of one road meets another road, from the roads with
+/// their classes and widths. Junctions at bridges are left out.
+pub fn find_junctions(roads: &[(&RoadPath, RoadClass, i64)]) -> Vec<Junction> {
+    let is_major = |class: RoadClass| class == RoadClass::Highway || class == RoadClass::CityRoad;
+    let direction = |from: BlockCoord, to: BlockCoord| {
+        let (dx, dz) = ((to.0 - from.0) as f32, (to.2 - from.2) as f32);
+        let length = (dx * dx + dz * dz).sqrt().max(f32::EPSILON);
+        (dx / length, dz / length)
+    };
+
+    let mut junctions: Vec<Junction> = Vec::new();
+    for (index, (path, class, width)) in roads.iter().enumerate() {
+        if path.len() < 2 {
+            continue;
+        }
+        for (end, next) in &[(0, 1), (path.len() - 1, path.len() - 2)] {
+            let center = path[*end].coordinates;
+            if path[*end].kind != RoadNodeKind::Ground
+                || junctions.iter().any(|junction| {
+                    horizontal_distance(junction.center, center) <= 2.0 * JUNCTION_REACH
+                })
+            {
+                continue;
+            }
+            let mut junction = Junction {
+                center,
+                arms: vec![direction(center, path[*next].coordinates)],
+                class: *class,
+                width: *width,
+                is_major: is_major(*class),
+            };
+
+            // Other roads passing by, or ending at, the end of this road
+            for (other_index, (other, other_class, other_width)) in roads.iter().enumerate() {
+                if other_index == index {
+                    continue;
+                }
+                let closest = other.iter()
+                    .enumerate()
+                    .filter(|(_, node)| node.kind == RoadNodeKind::Ground && (node.coordinates.1 - center.1).abs() <= 1)
+                    .min_by(|(_, a), (_, b)| {
+                        horizontal_distance(a.coordinates, center)
+                            .partial_cmp(&horizontal_distance(b.coordinates, center))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                let node_index = match closest {
+                    Some((node_index, node)) if horizontal_distance(node.coordinates, center) <= JUNCTION_REACH => node_index,
+                    _ => continue,
+                };
+                if node_index > 0 {
+                    junction.arms.push(direction(center, other[node_index - 1].coordinates));
+                }
+                if node_index + 1 < other.len() {
+                    junction.arms.push(direction(center, other[node_index + 1].coordinates));
+                }
+                if *other_width > junction.width {
+                    junction.class = *other_class;
+                    junction.width = *other_width;
+                }
+                junction.is_major &= is_major(*other_class);
+            }
+
+            if junction.arms.len() >= 3 {
+                junctions.push(junction);
+            }
+        }
+    }
+    junctions
+}
+
+/// Build the junction as a single paved pad, wide enough for turning, with curbs on the
+/// corners between the roads leading out of it. With `roundabout`, junctions of four or
+/// more major roads get a roundabout around a lit island in the middle.
+pub fn build_junction(
+    excerpt: &mut WorldExcerpt,
+    junction: &Junction,
+    style: &RoadStyle,
+    roundabout: bool,
+    rng: &mut StdRng,
+) {
+    let is_roundabout = roundabout && junction.is_major && junction.arms.len() >= ROUNDABOUT_ARMS_MIN;
+    let radius = junction.width / 2 + if is_roundabout { 3 } else { 1 };
+    let curb = style.edging.clone().unwrap_or(Block::StoneBricks);
+    let BlockCoord(center_x, y, center_z) = junction.center;
+    let is_inside = |dx: i64, dz: i64, radius: i64| dx * dx + dz * dz <= radius * radius + radius;
+
+    // The pad
+    for dx in -radius..=radius {
+        for dz in -radius..=radius {
+            if !is_inside(dx, dz, radius) {
+                continue;
+            }
+            let (x, z) = (center_x + dx, center_z + dz);
+            for clear_y in y..=y + 2 {
+                tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+            }
+            excerpt.set_block_at(BlockCoord(x, y - 2, z), Block::Cobblestone);
+            let cover = style.covers[rng.gen_range(0..style.covers.len())].clone();
+            excerpt.set_block_at(BlockCoord(x, y - 1, z), cover);
+        }
+    }
+
+    // Curbs around the pad, on the corners between the roads
+    let half_width = junction.width as f32 / 2.0 + 0.5;
+    for dx in -radius - 1..=radius + 1 {
+        for dz in -radius - 1..=radius + 1 {
+            if is_inside(dx, dz, radius) || !is_inside(dx, dz, radius + 1) {
+                continue;
+            }
+            let is_on_road = junction.arms.iter().any(|(arm_x, arm_z)| {
+                let along = dx as f32 * arm_x + dz as f32 * arm_z;
+                let across = (dx as f32 * arm_z - dz as f32 * arm_x).abs();
+                along > 0.0 && across <= half_width
+            });
+            let ground = BlockCoord(center_x + dx, y - 1, center_z + dz);
+            let is_level_ground = !matches!(
+                excerpt.block_at(ground),
+                None | Some(Block::Air) | Some(Block::None) | Some(Block::WaterSource) | Some(Block::Water { .. })
+            ) && excerpt.block_at(ground + BlockCoord(0, 1, 0)) == Some(&Block::Air);
+            if !is_on_road && is_level_ground {
+                excerpt.set_block_at(ground, curb.clone());
+            }
+        }
+    }
+
+    // The island in the middle of the roundabout, curbed, with a lamp post
+    if is_roundabout {
+        for (dx, dz) in &[(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let island = BlockCoord(center_x + dx, y, center_z + dz);
+            if (*dx, *dz) == (0, 0) {
+                excerpt.set_block_at(island - BlockCoord(0, 1, 0), Block::GrassBlock);
+                for post_y in 0..ROUNDABOUT_LAMP_POST_HEIGHT {
+                    excerpt.set_block_at(
+                        island + BlockCoord(0, post_y, 0),
+                        Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false },
+                    );
+                }
+                excerpt.set_block_at(
+                    island + BlockCoord(0, ROUNDABOUT_LAMP_POST_HEIGHT, 0),
+                    Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+                );
+            } else {
+                excerpt.set_block_at(island, curb.clone());
+            }
+        }
+    }
+}
+
+/// Distance between the (x, z) columns of two points.
+fn horizontal_distance(a: BlockCoord, b: BlockCoord) -> f32 {
+    (((a.0 - b.0).pow(2) + (a.2 - b.2).pow(2)) as f32).sqrt()
+}
+
+/// Line the edges of the road on the ground with the edging block, level with the road
+/// surface, where the ground beside the road is level with it.
+fn build_edging(excerpt: &mut WorldExcerpt, path: &RoadPath, road_width: i64, edging: &Block) {
+    let edge_distance = (road_width / 2 + 1) as f32;
+    for (index, segment) in path.windows(2).enumerate() {
+        if segment.iter().any(|node| node.kind != RoadNodeKind::Ground) {
+            continue;
+        }
+        for distance in &[-edge_distance, edge_distance] {
+            let edge_at = |index: usize| {
+                let RoadNode { coordinates, .. } = path[index];
+                let (side_x, side_z) = road_side(path, index);
+                BlockCoord(
+                    coordinates.0 + (side_x * distance).round() as i64,
+                    coordinates.1,
+                    coordinates.2 + (side_z * distance).round() as i64,
+                )
+            };
+            for position in line::line(&edge_at(index), &edge_at(index + 1), 1) {
+                let ground = position - BlockCoord(0, 1, 0);
+                let is_level_ground = matches!(
+                    excerpt.block_at(ground),
+                    Some(Block::GrassBlock) | Some(Block::Dirt) | Some(Block::Podzol) | Some(Block::Sand)
+                ) && matches!(excerpt.block_at(position), Some(Block::Air));
+                if is_level_ground {
+                    excerpt.set_block_at(ground, edging.clone());
+                }
+            }
+        }
+    }
+}
+
 /// Splits a set of roads into a set of city roads and a set of country roads,
 /// by splitting each road into the parts outside and inside of the given polygon,
 /// and putting all inside roads in the first item of the output and all outside
@@ -52,7 +425,7 @@ fn road_split(road: &RoadPath, polygon: &Snake) -> (Vec<RoadPath>, Vec<RoadPath>
 
     let (last_segment_is_inside, last_segment) = road.iter().fold(
         (first_is_inside, Vec::new()),
-        |accumulator: (bool, RoadPath), node: &RoadNode| {
+        |accumulator: (bool, Vec<RoadNode>), node: &RoadNode| {
             let (previous_was_inside, mut acc) = accumulator;
 
             let is_inside = InOutSide::Inside
@@ -68,7 +441,7 @@ fn road_split(road: &RoadPath, polygon: &Snake) -> (Vec<RoadPath>, Vec<RoadPath>
                 let new_acc = vec![*acc.last().unwrap(), *node];
 
                 // The current accumulator is outside, and should not contain the current node.
-                outside.push(acc);
+                outside.push(RoadPath::new(acc, road.class));
 
                 (is_inside, new_acc)
             } else {
@@ -78,7 +451,7 @@ fn road_split(road: &RoadPath, polygon: &Snake) -> (Vec<RoadPath>, Vec<RoadPath>
                 // The current accumulator is inside, and should contain the current node.
                 // (The transition edge is part of the inside.)
                 acc.push(*node);
-                inside.push(acc);
+                inside.push(RoadPath::new(acc, road.class));
 
                 (is_inside, new_acc)
             }
@@ -87,9 +460,9 @@ fn road_split(road: &RoadPath, polygon: &Snake) -> (Vec<RoadPath>, Vec<RoadPath>
 
     if last_segment.len() > 1 {
         if last_segment_is_inside {
-            inside.push(last_segment);
+            inside.push(RoadPath::new(last_segment, road.class));
         } else {
-            outside.push(last_segment);
+            outside.push(RoadPath::new(last_segment, road.class));
         }
     }
 
@@ -102,9 +475,8 @@ pub fn build_road(
     height_map: &GrayImage,
     road_width: i64,
     road_covers: &[Block],
+    rng: &mut StdRng,
 ) {
-    // Initialize randomizer
-    let mut rng = thread_rng();
     let cover_count = road_covers.len();
     let mut random_road_cover = || { road_covers[rng.gen_range(0..cover_count)].clone() };
 
@@ -162,13 +534,14 @@ pub fn build_road(
                 }
             }
         }
+
+        if segment.iter().all(|node| node.kind == RoadNodeKind::Ground) {
+            build_switchback(excerpt, segment[0].coordinates, segment[1].coordinates, road_width, &mut random_road_cover);
+        }
     }
 
     // Build the nodes
-    for RoadNode {
-        coordinates, kind, ..
-    } in path
-    {
+    for (index, RoadNode { coordinates, kind, .. }) in path.iter().enumerate() {
         let (x, y, z) = (coordinates.0, coordinates.1, coordinates.2);
 
         // Path and support at node
@@ -184,15 +557,22 @@ pub fn build_road(
                 );
             }*/
             RoadNodeKind::WoodenSupport => {
-                let image::Luma([ground]) = height_map[(x as u32, z as u32)];
-                for y in ground as i64..y {
-                    tree::chop(excerpt, (x, y, z).into());
-                    excerpt.set_block_at((x, y, z).into(), Block::oak_log(Axis3::Y));
+                // Pilings under both edges of the deck, down to the river bed or valley floor
+                let (side_x, side_z) = road_side(path, index);
+                let half_width = (road_width / 2) as f32;
+                for side in &[-half_width, half_width] {
+                    let x = x + (side_x * side).round() as i64;
+                    let z = z + (side_z * side).round() as i64;
+                    let floor_y = floor_below(excerpt, height_map, x, z).unwrap_or(y);
+                    for y in floor_y..y {
+                        tree::chop(excerpt, (x, y, z).into());
+                        excerpt.set_block_at((x, y, z).into(), Block::oak_log(Axis3::Y));
+                    }
                 }
             }
             RoadNodeKind::StoneSupport => {
-                let image::Luma([ground]) = height_map[(x as u32, z as u32)];
-                for y in ground as i64..y {
+                let floor_y = floor_below(excerpt, height_map, x, z).unwrap_or(y);
+                for y in floor_y..y {
                     let coordinates = (x + 1, y, z).into();
                     tree::chop(excerpt, coordinates);
                     excerpt.set_block_at(coordinates, Block::StoneBricks);
@@ -210,4 +590,889 @@ pub fn build_road(
             _ => (),
         }
     }
+
+    build_bridge_details(excerpt, path, height_map, road_width);
+    build_cutting_and_tunnel_details(excerpt, path, height_map, road_width);
+    build_embankments(excerpt, path, height_map, road_width);
+}
+
+/// Arches between the stone piers, railings along the decks,
+/// and lamp posts at both ends of each bridge along the road.
+fn build_bridge_details(excerpt: &mut WorldExcerpt, path: &RoadPath, height_map: &GrayImage, road_width: i64) {
+    for segment in path.windows(2) {
+        let (start, end) = (segment[0].coordinates, segment[1].coordinates);
+        let kinds = (segment[0].kind, segment[1].kind);
+        let is_wooden = kinds == (RoadNodeKind::WoodenSupport, RoadNodeKind::WoodenSupport);
+        let is_stone = kinds == (RoadNodeKind::StoneSupport, RoadNodeKind::StoneSupport);
+        if !is_wooden && !is_stone {
+            continue;
+        }
+
+        // Masonry under the deck, down to the piers at the ends of the span and
+        // curving up to the deck at the middle of it.
+        if is_stone {
+            let (dx, dz) = ((end.0 - start.0) as f32, (end.2 - start.2) as f32);
+            let length_squared = dx * dx + dz * dz;
+            if length_squared > 0.0 {
+                for position in line::line(&start, &end, road_width) {
+                    let t = (((position.0 - start.0) as f32 * dx + (position.2 - start.2) as f32 * dz)
+                        / length_squared)
+                        .clamp(0.0, 1.0);
+                    let floor_y = floor_below(excerpt, height_map, position.0, position.2).unwrap_or(position.1);
+                    let depth = ((position.1 - floor_y) as f32 * (1.0 - (std::f32::consts::PI * t).sin()))
+                        .round() as i64;
+                    for y in position.1 - depth..position.1 {
+                        tree::chop(excerpt, BlockCoord(position.0, y, position.2));
+                        excerpt.set_block_at(BlockCoord(position.0, y, position.2), Block::StoneBricks);
+                    }
+                }
+            }
+        }
+
+        // Railings along both sides of the deck
+        let deck: HashSet<(i64, i64)> = line::line(&start, &end, road_width).iter()
+            .map(|position| (position.0, position.2))
+            .collect();
+        for position in line::line(&start, &end, road_width + 2) {
+            if deck.contains(&(position.0, position.2))
+                || excerpt.block_at(position + BlockCoord(0, 1, 0)) != Some(&Block::Air)
+            {
+                continue;
+            }
+            let (ledge, railing) = if is_wooden {
+                (Block::dark_oak_planks(), Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false })
+            } else {
+                (Block::StoneBricks, Block::StoneBricks)
+            };
+            if excerpt.block_at(position) == Some(&Block::Air) {
+                excerpt.set_block_at(position, ledge);
+            }
+            excerpt.set_block_at(position + BlockCoord(0, 1, 0), railing);
+        }
+    }
+
+    // Lamp posts by the abutments, on the right hand side of the road
+    for bridge in bridges(path) {
+        for abutment in &[bridge.start_abutment, bridge.end_abutment] {
+            let (side_x, side_z) = road_side(path, *abutment);
+            let distance = (road_width / 2 + 1) as f32;
+            let node = path[*abutment].coordinates;
+            let post = BlockCoord(
+                node.0 + (side_x * distance).round() as i64,
+                node.1,
+                node.2 + (side_z * distance).round() as i64,
+            );
+            let has_room = (0..BRIDGE_LAMP_POST_HEIGHT + 1)
+                .all(|y| excerpt.block_at(post + BlockCoord(0, y, 0)) == Some(&Block::Air));
+            let has_ground = !matches!(
+                excerpt.block_at(post - BlockCoord(0, 1, 0)),
+                Some(Block::Air) | Some(Block::None) | Some(Block::WaterSource) | Some(Block::Water { .. }) | None
+            );
+            if !has_room || !has_ground {
+                continue;
+            }
+            for y in 0..BRIDGE_LAMP_POST_HEIGHT {
+                excerpt.set_block_at(
+                    post + BlockCoord(0, y, 0),
+                    Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false },
+                );
+            }
+            excerpt.set_block_at(
+                post + BlockCoord(0, BRIDGE_LAMP_POST_HEIGHT, 0),
+                Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+            );
+        }
+    }
+}
+
+/// Steps for a road segment climbing more than one block per block along it, which would
+/// otherwise be a cliff: The climb zig-zags across the road in flights of stairs, turning
+/// at the edges of the road, with a level landing at each end of the segment.
+fn build_switchback(
+    excerpt: &mut WorldExcerpt,
+    start: BlockCoord,
+    end: BlockCoord,
+    road_width: i64,
+    road_cover: &mut impl FnMut() -> Block,
+) {
+    let (low, high) = if start.1 <= end.1 { (start, end) } else { (end, start) };
+    let (dx, dz) = (high.0 - low.0, high.2 - low.2);
+    let run = dx.abs().max(dz.abs());
+    let rise = high.1 - low.1;
+    if rise <= run || run < 2 {
+        return;
+    }
+
+    // The columns of the segment, row by row from the low end, each row in the order the
+    // climb crosses it: From the left on the first row, from the right on the next, ...
+    let uphill = signs::surface_from_vector(dx as f32, dz as f32);
+    let right = geometry::offset(uphill.rotated_90_cw());
+    let left_edge = -(road_width - 1) / 2;
+    let right_edge = road_width / 2;
+    let mut columns: Vec<(BlockCoord, usize)> = Vec::new();
+    for along in 0..=run {
+        let mut acrosses: Vec<i64> = (left_edge..=right_edge).collect();
+        if along % 2 == 1 {
+            acrosses.reverse();
+        }
+        for across in acrosses {
+            let column = BlockCoord(
+                low.0 + (dx as f32 * along as f32 / run as f32).round() as i64 + right.0 * across,
+                0,
+                low.2 + (dz as f32 * along as f32 / run as f32).round() as i64 + right.2 * across,
+            );
+            columns.push((column, along as usize));
+        }
+    }
+
+    // One block up for each column of the rows between the landings
+    let mut y = low.1;
+    let mut previous: Option<BlockCoord> = None;
+    for (column, along) in columns {
+        let step_up = along > 0 && along < run as usize && y < high.1;
+        let surface_y = if along == run as usize { high.1 } else if step_up { y + 1 } else { y };
+
+        for clear_y in surface_y..=high.1 + 2 {
+            tree::chop(excerpt, BlockCoord(column.0, clear_y, column.2));
+            excerpt.set_block_at(BlockCoord(column.0, clear_y, column.2), Block::Air);
+        }
+        for fill_y in low.1 - 2..surface_y - 1 {
+            excerpt.set_block_at(BlockCoord(column.0, fill_y, column.2), Block::Cobblestone);
+        }
+        let top = match previous {
+            Some(previous) if surface_y > y => {
+                let climb = signs::surface_from_vector((column.0 - previous.0) as f32, (column.2 - previous.2) as f32);
+                Block::stairs(climb.into(), Material::Cobblestone)
+            }
+            _ => road_cover(),
+        };
+        excerpt.set_block_at(BlockCoord(column.0, surface_y - 1, column.2), top);
+
+        y = surface_y;
+        previous = Some(column);
+    }
+}
+
+/// Cuttings dug out to the sky between retaining walls, and tunnels lined with stone,
+/// with timber frames carrying the ceiling and lanterns lighting the way through.
+fn build_cutting_and_tunnel_details(
+    excerpt: &mut WorldExcerpt,
+    path: &RoadPath,
+    height_map: &GrayImage,
+    road_width: i64,
+) {
+    let half_width = (road_width / 2) as f32;
+    let wall_distance = half_width + 1.0;
+    let edge_at = |index: usize, distance: f32| {
+        let RoadNode { coordinates, .. } = path[index];
+        let (side_x, side_z) = road_side(path, index);
+        BlockCoord(
+            coordinates.0 + (side_x * distance).round() as i64,
+            coordinates.1,
+            coordinates.2 + (side_z * distance).round() as i64,
+        )
+    };
+
+    for (index, segment) in path.windows(2).enumerate() {
+        let kinds = (segment[0].kind, segment[1].kind);
+        let is_tunnel = kinds.0 == RoadNodeKind::Tunnel || kinds.1 == RoadNodeKind::Tunnel;
+        let is_cutting = kinds.0 == RoadNodeKind::Cutting || kinds.1 == RoadNodeKind::Cutting;
+        if !is_tunnel && !is_cutting {
+            continue;
+        }
+        let y = segment[0].coordinates.1.min(segment[1].coordinates.1);
+
+        if is_tunnel {
+            // Stone lining of the walls and the ceiling, holding back gravel, sand and water
+            let line = line::line(&segment[0].coordinates, &segment[1].coordinates, road_width);
+            for position in &line {
+                excerpt.set_block_at(
+                    BlockCoord(position.0, y + TUNNEL_HEIGHT, position.2),
+                    Block::StoneBricks,
+                );
+            }
+            for distance in &[-wall_distance, wall_distance] {
+                let wall = line::line(&edge_at(index, *distance), &edge_at(index + 1, *distance), 1);
+                for position in &wall {
+                    for wall_y in y - 1..=y + TUNNEL_HEIGHT {
+                        excerpt.set_block_at(BlockCoord(position.0, wall_y, position.2), Block::StoneBricks);
+                    }
+                }
+            }
+        } else {
+            // Open to the sky, with retaining walls up to the terrain on either side
+            let line = line::line(&segment[0].coordinates, &segment[1].coordinates, road_width);
+            for position in &line {
+                if let Some(terrain_y) = terrain_height(height_map, position.0, position.2) {
+                    for air_y in y + TUNNEL_HEIGHT..terrain_y.max(y + TUNNEL_HEIGHT) {
+                        let coordinates = BlockCoord(position.0, air_y, position.2);
+                        tree::chop(excerpt, coordinates);
+                        excerpt.set_block_at(coordinates, Block::Air);
+                    }
+                }
+            }
+            for distance in &[-wall_distance, wall_distance] {
+                let wall = line::line(&edge_at(index, *distance), &edge_at(index + 1, *distance), 1);
+                for position in &wall {
+                    if let Some(terrain_y) = terrain_height(height_map, position.0, position.2) {
+                        for wall_y in y - 1..terrain_y {
+                            let coordinates = BlockCoord(position.0, wall_y, position.2);
+                            tree::chop(excerpt, coordinates);
+                            excerpt.set_block_at(coordinates, Block::StoneBricks);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Timber frames carrying the tunnel ceiling, with lanterns hanging from every other frame
+    let tunnel_nodes = path.iter()
+        .enumerate()
+        .filter(|(_, node)| node.kind == RoadNodeKind::Tunnel)
+        .map(|(index, _)| index);
+    for (count, index) in tunnel_nodes.enumerate() {
+        if count % TUNNEL_FRAME_SPACING != 0 {
+            continue;
+        }
+        let y = path[index].coordinates.1;
+        let beam = line::line(&edge_at(index, -wall_distance), &edge_at(index, wall_distance), 1);
+        for position in &beam {
+            excerpt.set_block_at(BlockCoord(position.0, y + TUNNEL_HEIGHT, position.2), Block::dark_oak_planks());
+        }
+        for distance in &[-wall_distance, wall_distance] {
+            let post = edge_at(index, *distance);
+            for post_y in y..y + TUNNEL_HEIGHT {
+                excerpt.set_block_at(BlockCoord(post.0, post_y, post.2), Block::oak_log(Axis3::Y));
+            }
+        }
+        if count % (2 * TUNNEL_FRAME_SPACING) == 0 {
+            let lantern = edge_at(index, half_width);
+            excerpt.set_block_at(
+                BlockCoord(lantern.0, y + TUNNEL_HEIGHT - 1, lantern.2),
+                Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+            );
+        }
+    }
+}
+
+/// Shape the ground beside the road where it was filled up to, or cut down to, the road
+/// surface: Slopes of one block up or down per block out from the road, over the first
+/// few blocks beside it, or retaining walls of stone where the slopes would be too high.
+fn build_embankments(excerpt: &mut WorldExcerpt, path: &RoadPath, height_map: &GrayImage, road_width: i64) {
+    let road_columns: HashSet<(i64, i64)> = path.windows(2)
+        .flat_map(|segment| line::line(&segment[0].coordinates, &segment[1].coordinates, road_width))
+        .map(|position| (position.0, position.2))
+        .collect();
+
+    let half_width = (road_width / 2) as f32;
+    for (index, segment) in path.windows(2).enumerate() {
+        if segment.iter().any(|node| node.kind != RoadNodeKind::Ground) {
+            continue;
+        }
+        for side in &[-1.0, 1.0] {
+            for step in 0..EMBANKMENT_REACH {
+                let distance = side * (half_width + 1.0 + step as f32);
+                let edge_at = |index: usize| {
+                    let RoadNode { coordinates, .. } = path[index];
+                    let (side_x, side_z) = road_side(path, index);
+                    BlockCoord(
+                        coordinates.0 + (side_x * distance).round() as i64,
+                        coordinates.1,
+                        coordinates.2 + (side_z * distance).round() as i64,
+                    )
+                };
+                for position in line::line(&edge_at(index), &edge_at(index + 1), 1) {
+                    let BlockCoord(x, y, z) = position;
+                    if road_columns.contains(&(x, z)) {
+                        continue;
+                    }
+                    let ground_y = match terrain_height(height_map, x, z) {
+                        Some(ground_y) => ground_y,
+                        None => continue,
+                    };
+
+                    if ground_y < y {
+                        // Filled: Slope down from the road, or a wall down to the ground
+                        if y - ground_y > EMBANKMENT_HEIGHT_MAX {
+                            if step == 0 {
+                                for wall_y in ground_y - 1..y {
+                                    excerpt.set_block_at(BlockCoord(x, wall_y, z), Block::StoneBricks);
+                                }
+                            }
+                            continue;
+                        }
+                        let top_y = y - 1 - step as i64;
+                        for fill_y in ground_y..top_y {
+                            excerpt.set_block_at(BlockCoord(x, fill_y, z), Block::Dirt);
+                        }
+                        if top_y >= ground_y {
+                            excerpt.set_block_at(BlockCoord(x, top_y, z), Block::GrassBlock);
+                        }
+                    } else if ground_y > y {
+                        // Cut: Slope up from the road, or a wall up to the ground
+                        if ground_y - y > EMBANKMENT_HEIGHT_MAX {
+                            if step == 0 {
+                                for wall_y in y - 1..ground_y {
+                                    tree::chop(excerpt, BlockCoord(x, wall_y, z));
+                                    excerpt.set_block_at(BlockCoord(x, wall_y, z), Block::StoneBricks);
+                                }
+                            }
+                            continue;
+                        }
+                        let top_y = y + step as i64;
+                        for clear_y in top_y + 1..ground_y {
+                            tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                            excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+                        }
+                        if top_y < ground_y - 1 {
+                            excerpt.set_block_at(BlockCoord(x, top_y, z), Block::GrassBlock);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The terrain height of the height map at the given column, if within the map.
+fn terrain_height(height_map: &GrayImage, x: i64, z: i64) -> Option<i64> {
+    let (x_len, z_len) = height_map.dimensions();
+    if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 {
+        return None;
+    }
+    let image::Luma([ground]) = height_map[(x as u32, z as u32)];
+    Some(ground as i64)
+}
+
+/// The height of the river bed, sea floor or valley floor below a bridge: The bottom of
+/// any water under the terrain surface of the height map, at the given column.
+fn floor_below(excerpt: &WorldExcerpt, height_map: &GrayImage, x: i64, z: i64) -> Option<i64> {
+    let mut y = terrain_height(height_map, x, z)?;
+    while y > 0 {
+        match excerpt.block_at(BlockCoord(x, y - 1, z)) {
+            Some(Block::WaterSource) | Some(Block::Water { .. }) => y -= 1,
+            _ => break,
+        }
+    }
+    Some(y)
+}
+
+/// A bridge along a road.
+#[derive(Clone, Copy, Debug)]
+pub struct Bridge {
+    /// Index of the ground node at the start of the bridge.
+    pub start_abutment: usize,
+    /// Index of the ground node at the end of the bridge.
+    pub end_abutment: usize,
+}
+
+impl Bridge {
+    /// Horizontal distance between the two abutments of the bridge.
+    pub fn length(&self, path: &RoadPath) -> f32 {
+        let start = path[self.start_abutment].coordinates;
+        let end = path[self.end_abutment].coordinates;
+        (((end.0 - start.0).pow(2) + (end.2 - start.2).pow(2)) as f32).sqrt()
+    }
+}
+
+/// Share of the nodes of the road that are inside any of the given polygons.
+pub fn share_inside(path: &RoadPath, polygons: &[&Snake]) -> f32 {
+    if path.is_empty() {
+        return 0.0;
+    }
+    let inside_count = path.iter()
+        .filter(|node| polygons.iter().any(|polygon| {
+            InOutSide::Inside == point_position_relative_to_polygon(node.coordinates.into(), polygon)
+        }))
+        .count();
+    inside_count as f32 / path.len() as f32
+}
+
+/// The street running furthest through the market districts, to cover as a market street,
+/// with the share of it inside them. Only streets mostly inside the market districts count.
+pub fn market_street<'a>(streets: &'a [RoadPath], market_districts: &[&Snake]) -> Option<(&'a RoadPath, f32)> {
+    streets.iter()
+        .map(|street| (street, share_inside(street, market_districts)))
+        .filter(|(_, share)| *share >= MARKET_STREET_SHARE_MIN)
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// Cover the street with a roof of sandstone slabs, as a shaded market street. The roof spans
+/// the street from house to house, one block onto each side, resting on the walls of the houses
+/// where there are any, and on fence posts elsewhere. Gaps in the roof let daylight down at
+/// regular intervals. Bridges are left uncovered.
+pub fn build_covered_street(
+    excerpt: &mut WorldExcerpt,
+    path: &RoadPath,
+    height_map: &GrayImage,
+    road_width: i64,
+) {
+    let is_bridge = |segment: &[RoadNode]| segment.iter().any(|node| {
+        node.kind == RoadNodeKind::WoodenSupport || node.kind == RoadNodeKind::StoneSupport
+    });
+    let street: HashSet<(i64, i64)> = path.windows(2)
+        .flat_map(|segment| line::line(&segment[0].coordinates, &segment[1].coordinates, road_width))
+        .map(|position| (position.0, position.2))
+        .collect();
+
+    // Height of the roof over each column, and the distance along the street to it
+    let mut roof: HashMap<(i64, i64), (i64, i64)> = HashMap::new();
+    let mut segment_start_distance = 0.0;
+    for segment in path.windows(2) {
+        let (start, end) = (segment[0].coordinates, segment[1].coordinates);
+        if !is_bridge(segment) {
+            for position in line::line(&start, &end, road_width + 2) {
+                let distance = segment_start_distance
+                    + (((position.0 - start.0).pow(2) + (position.2 - start.2).pow(2)) as f32).sqrt();
+                roof.entry((position.0, position.2))
+                    .or_insert((position.1 + COVERED_STREET_ROOF_HEIGHT, distance as i64));
+            }
+        }
+        segment_start_distance += (((end.0 - start.0).pow(2) + (end.2 - start.2).pow(2)) as f32).sqrt();
+    }
+
+    let (x_len, z_len) = height_map.dimensions();
+    let mut columns: Vec<((i64, i64), (i64, i64))> = roof.into_iter().collect();
+    columns.sort_unstable();
+    for ((x, z), (roof_y, distance)) in columns {
+        if distance % LIGHT_WELL_SPACING < LIGHT_WELL_LENGTH {
+            continue;
+        }
+        // Leave the houses along the street as they are, and rest the roof against them
+        if excerpt.block_at(BlockCoord(x, roof_y, z)) != Some(&Block::Air) {
+            continue;
+        }
+        excerpt.set_block_at(BlockCoord(x, roof_y, z), Block::bottom_slab(Material::SmoothSandstone));
+
+        // Posts along the sides of the street, where nothing else carries the roof
+        if !street.contains(&(x, z))
+            && distance % ROOF_POST_SPACING == 0
+            && x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64
+        {
+            let image::Luma([ground_y]) = height_map[(x as u32, z as u32)];
+            let post = ground_y as i64..roof_y;
+            if post.clone().all(|y| excerpt.block_at(BlockCoord(x, y, z)) == Some(&Block::Air)) {
+                for y in post {
+                    excerpt.set_block_at(
+                        BlockCoord(x, y, z),
+                        Block::Fence { material: FenceMaterial::Oak, waterlogged: false },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Enclose each of the given streets, with their widths, in a walkway, leaving the walls
+/// open where the streets meet and in front of the given doors.
+pub fn build_enclosed_walkways<'a>(
+    excerpt: &mut WorldExcerpt,
+    walkways: &[(&RoadPath, i64)],
+    doors: impl Iterator<Item = &'a ExteriorDoor>,
+    height_map: &GrayImage,
+    wall: &Block,
+) {
+    // Keep the walls out of the other streets, and clear of the front doors
+    let mut openings: HashSet<(i64, i64)> = walkways.iter()
+        .flat_map(|(path, width)| path.windows(2)
+            .flat_map(move |segment| line::line(&segment[0].coordinates, &segment[1].coordinates, *width)))
+        .map(|position| (position.0, position.2))
+        .collect();
+    for door in doors {
+        let mut outside = door.coordinates;
+        for _ in 0..=WALKWAY_DOOR_CLEARANCE {
+            openings.insert((outside.0, outside.2));
+            outside = outside - geometry::offset(door.facing);
+        }
+    }
+
+    for (path, width) in walkways {
+        build_enclosed_walkway(excerpt, path, height_map, *width, &openings, wall);
+    }
+}
+
+/// Enclose the street in a walkway, with walls along both sides and a roof over it, as
+/// shelter from the fireballs of the ghasts in the Nether. The roof spans the street one
+/// block onto each side, resting on the walls, and on the houses along the street where
+/// there are any. The walls are left open at the `openings`, such as the streets joining
+/// this one and the front doors of the houses. Lanterns hang from the roof at regular
+/// intervals. Bridges are left unenclosed.
+pub fn build_enclosed_walkway(
+    excerpt: &mut WorldExcerpt,
+    path: &RoadPath,
+    height_map: &GrayImage,
+    road_width: i64,
+    openings: &HashSet<(i64, i64)>,
+    wall: &Block,
+) {
+    let is_bridge = |segment: &[RoadNode]| segment.iter().any(|node| {
+        node.kind == RoadNodeKind::WoodenSupport || node.kind == RoadNodeKind::StoneSupport
+    });
+    let street: HashSet<(i64, i64)> = path.windows(2)
+        .flat_map(|segment| line::line(&segment[0].coordinates, &segment[1].coordinates, road_width))
+        .map(|position| (position.0, position.2))
+        .collect();
+
+    // Height of the roof over each column, and the distance along the street to it
+    let mut roof: HashMap<(i64, i64), (i64, i64)> = HashMap::new();
+    let mut segment_start_distance = 0.0;
+    for segment in path.windows(2) {
+        let (start, end) = (segment[0].coordinates, segment[1].coordinates);
+        if !is_bridge(segment) {
+            for position in line::line(&start, &end, road_width + 2) {
+                let distance = segment_start_distance
+                    + (((position.0 - start.0).pow(2) + (position.2 - start.2).pow(2)) as f32).sqrt();
+                roof.entry((position.0, position.2))
+                    .or_insert((position.1 + COVERED_STREET_ROOF_HEIGHT, distance as i64));
+            }
+        }
+        segment_start_distance += (((end.0 - start.0).pow(2) + (end.2 - start.2).pow(2)) as f32).sqrt();
+    }
+
+    let (x_len, z_len) = height_map.dimensions();
+    let mut columns: Vec<((i64, i64), (i64, i64))> = roof.into_iter().collect();
+    columns.sort_unstable();
+    for ((x, z), (roof_y, distance)) in columns {
+        // Leave the houses along the street as they are, and rest the roof against them
+        if excerpt.block_at(BlockCoord(x, roof_y, z)) != Some(&Block::Air) {
+            continue;
+        }
+        excerpt.set_block_at(BlockCoord(x, roof_y, z), wall.clone());
+
+        if street.contains(&(x, z)) {
+            // Lanterns hanging from the roof, over the street
+            if distance % WALKWAY_LANTERN_SPACING == 0
+                && excerpt.block_at(BlockCoord(x, roof_y - 1, z)) == Some(&Block::Air)
+            {
+                excerpt.set_block_at(
+                    BlockCoord(x, roof_y - 1, z),
+                    Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+                );
+            }
+        } else if !openings.contains(&(x, z))
+            && x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64
+        {
+            // Walls along the sides of the street, filling in the open space up to the roof
+            let image::Luma([ground_y]) = height_map[(x as u32, z as u32)];
+            for y in ground_y as i64..roof_y {
+                if excerpt.block_at(BlockCoord(x, y, z)) == Some(&Block::Air) {
+                    excerpt.set_block_at(BlockCoord(x, y, z), wall.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Clear the snow off the road and its edges, leaving it lying elsewhere.
+/// Returns the number of snow layers cleared.
+pub fn clear_snow(excerpt: &mut WorldExcerpt, path: &RoadPath, road_width: i64) -> usize {
+    let mut cleared_count = 0;
+    for segment in path.windows(2) {
+        for position in line::line(&segment[0].coordinates, &segment[1].coordinates, road_width + 2) {
+            for y in 0..=1 {
+                let coordinates = position + BlockCoord(0, y, 0);
+                if let Some(Block::Snow { .. }) = excerpt.block_at(coordinates) {
+                    excerpt.set_block_at(coordinates, Block::Air);
+                    cleared_count += 1;
+                }
+            }
+        }
+    }
+    cleared_count
+}
+
+/// Lay the given snow layer on the ground stretches of the road, as on the roads of snowy
+/// towns after building them. Returns the number of snow layers laid.
+pub fn cover_with_snow(excerpt: &mut WorldExcerpt, path: &RoadPath, road_width: i64, snow_layer: &Block) -> usize {
+    let mut covered_count = 0;
+    for segment in path.windows(2) {
+        if segment.iter().any(|node| node.kind != RoadNodeKind::Ground) {
+            continue;
+        }
+        for position in line::line(&segment[0].coordinates, &segment[1].coordinates, road_width) {
+            let is_open = matches!(excerpt.block_at(position), Some(Block::Air));
+            let is_on_ground = !matches!(
+                excerpt.block_at(position - BlockCoord(0, 1, 0)),
+                None | Some(Block::Air) | Some(Block::WaterSource) | Some(Block::Water { .. }),
+            );
+            if is_open && is_on_ground {
+                excerpt.set_block_at(position, snow_layer.clone());
+                covered_count += 1;
+            }
+        }
+    }
+    covered_count
+}
+
+/// Raise the ground stretches of the road over water to a boardwalk, for stilt villages:
+/// A deck of planks at the water surface, on log piles down to the bottom, with the water
+/// under the deck kept in place. Returns the number of deck blocks laid.
+pub fn build_boardwalk(excerpt: &mut WorldExcerpt, path: &RoadPath, road_width: i64, features: &Features) -> usize {
+    let (x_len, z_len) = features.dimensions();
+    let mut visited = HashSet::new();
+    let mut deck_count = 0;
+    for segment in path.windows(2) {
+        if segment.iter().any(|node| node.kind != RoadNodeKind::Ground) {
+            continue;
+        }
+        for position in line::line(&segment[0].coordinates, &segment[1].coordinates, road_width) {
+            let (x, z) = (position.0, position.2);
+            if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 || !visited.insert((x, z)) {
+                continue;
+            }
+            if features.water[(x as u32, z as u32)] != image::Luma([255u8]) {
+                continue;
+            }
+            let surface_y = match features.height_map.height_at((x as usize, z as usize)) {
+                Some(height) => height as i64 - 1,
+                None => continue,
+            };
+            let image::Luma([bottom_y]) = features.terrain[(x as u32, z as u32)];
+
+            // Piles, with the water around them
+            let is_pile = x.rem_euclid(BOARDWALK_PILE_SPACING) == 0 && z.rem_euclid(BOARDWALK_PILE_SPACING) == 0;
+            for y in bottom_y as i64..surface_y {
+                let block = if is_pile { Block::oak_log(Axis3::Y) } else { Block::WaterSource };
+                excerpt.set_block_at(BlockCoord(x, y, z), block);
+            }
+
+            // Deck, with headroom above it
+            excerpt.set_block_at(BlockCoord(x, surface_y, z), Block::Planks { material: WoodMaterial::Spruce });
+            for y in surface_y + 1..=surface_y + 3 {
+                tree::chop(excerpt, BlockCoord(x, y, z));
+                excerpt.set_block_at(BlockCoord(x, y, z), Block::Air);
+            }
+            deck_count += 1;
+        }
+    }
+    deck_count
+}
+
+/// Put up railings along the sides of the road where it runs more than
+/// `UNGUARDED_DROP_MAX` blocks above the ground beside it, as on bridges, embankments
+/// and streets along the top of the town wall. Wooden decks get fences, everything else
+/// gets a parapet of `parapet` blocks. Returns the number of railing blocks placed.
+pub fn build_guard_rails(
+    excerpt: &mut WorldExcerpt,
+    path: &RoadPath,
+    road_width: i64,
+    parapet: &Block,
+) -> usize {
+    // Height of the walking surface of each road column, and whether it is a wooden deck
+    let mut surface: HashMap<(i64, i64), (i64, bool)> = HashMap::new();
+    for segment in path.windows(2) {
+        let is_wooden = segment.iter().any(|node| node.kind == RoadNodeKind::WoodenSupport);
+        let is_stone = segment.iter().any(|node| node.kind == RoadNodeKind::StoneSupport);
+        for position in line::line(&segment[0].coordinates, &segment[1].coordinates, road_width) {
+            let walk_y = if is_wooden || is_stone { position.1 + 1 } else { position.1 };
+            surface.entry((position.0, position.2)).or_insert((walk_y, is_wooden));
+        }
+    }
+
+    let is_open = |block: Option<&Block>| {
+        matches!(block, Some(Block::Air) | Some(Block::WaterSource) | Some(Block::Water { .. }))
+    };
+
+    // Columns beside the road with nothing to stand on close below the road surface
+    let mut rails: HashMap<(i64, i64), (i64, bool)> = HashMap::new();
+    for ((x, z), (walk_y, is_wooden)) in &surface {
+        for (dx, dz) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let column = (x + dx, z + dz);
+            if surface.contains_key(&column) || rails.contains_key(&column) {
+                continue;
+            }
+            let is_exposed = (1..=UNGUARDED_DROP_MAX + 1)
+                .all(|depth| is_open(excerpt.block_at(BlockCoord(column.0, walk_y - depth, column.1))));
+            if is_exposed && excerpt.block_at(BlockCoord(column.0, *walk_y, column.1)) == Some(&Block::Air) {
+                rails.insert(column, (*walk_y, *is_wooden));
+            }
+        }
+    }
+
+    let mut rails: Vec<((i64, i64), (i64, bool))> = rails.into_iter().collect();
+    rails.sort_unstable();
+    for ((x, z), (walk_y, is_wooden)) in &rails {
+        // The railing stands on a ledge beside the road, flush with the road surface.
+        let (ledge, railing) = if *is_wooden {
+            (Block::dark_oak_planks(), Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false })
+        } else {
+            (parapet.clone(), parapet.clone())
+        };
+        excerpt.set_block_at(BlockCoord(*x, walk_y - 1, *z), ledge);
+        excerpt.set_block_at(BlockCoord(*x, *walk_y, *z), railing);
+    }
+    rails.len()
+}
+
+/// Find the bridges along the given road, that have abutments on both ends.
+pub fn bridges(path: &RoadPath) -> Vec<Bridge> {
+    let is_support = |node: &RoadNode| {
+        node.kind == RoadNodeKind::WoodenSupport || node.kind == RoadNodeKind::StoneSupport
+    };
+
+    let mut bridges = Vec::new();
+    let mut start_abutment = None;
+
+    for (index, segment) in path.windows(2).enumerate() {
+        match (is_support(&segment[0]), is_support(&segment[1])) {
+            (false, true) => start_abutment = Some(index),
+            (true, false) => {
+                if let Some(start_abutment) = start_abutment.take() {
+                    bridges.push(Bridge { start_abutment, end_abutment: index + 1 });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    bridges
+}
+
+/// Light up the piers of the long bridges along the road with lanterns,
+/// and build a hut for a bridge keeper by one of the abutments of each.
+pub fn build_bridge_keepers(
+    excerpt: &mut WorldExcerpt,
+    path: &RoadPath,
+    features: &Features,
+    road_width: i64,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) {
+    for bridge in bridges(path) {
+        if bridge.length(path) < LONG_BRIDGE_LENGTH {
+            continue;
+        }
+
+        // Lantern on a post by the side of the deck, above each pier
+        for index in bridge.start_abutment + 1..bridge.end_abutment {
+            let (side_x, side_z) = road_side(path, index);
+            let distance = (road_width / 2 + 1) as f32;
+            let node = path[index].coordinates;
+            let post = BlockCoord(
+                node.0 + (side_x * distance).round() as i64,
+                node.1,
+                node.2 + (side_z * distance).round() as i64,
+            );
+            // On top of the railing, or on a post of its own where there is no railing
+            let lantern = match excerpt.block_at(post + BlockCoord(0, 1, 0)) {
+                Some(Block::Fence { .. }) => post + BlockCoord(0, 2, 0),
+                _ => {
+                    if excerpt.block_at(post) != Some(&Block::Air) {
+                        continue;
+                    }
+                    excerpt.set_block_at(post, Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false });
+                    post + BlockCoord(0, 1, 0)
+                }
+            };
+            if excerpt.block_at(lantern) != Some(&Block::Air) {
+                continue;
+            }
+            excerpt.set_block_at(lantern, Block::Lantern { mounted_at: Surface2::Down, waterlogged: false });
+        }
+
+        // Hut by whichever abutment has room for it
+        let abutments = [
+            (bridge.start_abutment, bridge.start_abutment + 1),
+            (bridge.end_abutment, bridge.end_abutment - 1),
+        ];
+        for (abutment, towards_bridge) in &abutments {
+            let has_room = build_bridge_keeper_hut(
+                excerpt,
+                path,
+                *abutment,
+                *towards_bridge,
+                features,
+                road_width,
+                palette,
+                rng,
+            );
+            if has_room {
+                break;
+            }
+        }
+    }
+}
+
+/// Unit vector pointing to the right hand side of the road, at the given node.
+fn road_side(path: &RoadPath, index: usize) -> (f32, f32) {
+    let previous = path[index.saturating_sub(1)].coordinates;
+    let next = path[(index + 1).min(path.len() - 1)].coordinates;
+    let (dx, dz) = ((next.0 - previous.0) as f32, (next.2 - previous.2) as f32);
+    let length = (dx * dx + dz * dz).sqrt();
+    if length == 0.0 {
+        (1.0, 0.0)
+    } else {
+        (-dz / length, dx / length)
+    }
+}
+
+/// Build a furnished hut on dry land next to the road at the abutment,
+/// set back from the bridge. Returns true if there was room for the hut.
+#[allow(clippy::too_many_arguments)]
+fn build_bridge_keeper_hut(
+    excerpt: &mut WorldExcerpt,
+    path: &RoadPath,
+    abutment: usize,
+    towards_bridge: usize,
+    features: &Features,
+    road_width: i64,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) -> bool {
+    let (x_len, z_len) = features.dimensions();
+    let start = path[abutment].coordinates;
+    let end = path[towards_bridge].coordinates;
+    let (dx, dz) = ((end.0 - start.0) as f32, (end.2 - start.2) as f32);
+    let length = (dx * dx + dz * dz).sqrt();
+    if length == 0.0 {
+        return false;
+    }
+    let (dx, dz) = (dx / length, dz / length);
+    let (side_x, side_z) = (-dz, dx);
+
+    // Back from the bridge, and off to the side of the road
+    let half_size = (BRIDGE_KEEPER_HUT_SIZE / 2) as f32;
+    let setback = half_size + 1.0;
+    let side_distance = (road_width / 2 + 2) as f32 + half_size;
+    let center_x = start.0 as f32 - dx * setback + side_x * side_distance;
+    let center_z = start.2 as f32 - dz * setback + side_z * side_distance;
+    let origin_x = (center_x - half_size).round() as i64;
+    let origin_z = (center_z - half_size).round() as i64;
+
+    let mut min_y = u8::MAX;
+    let mut max_y = u8::MIN;
+    for x in origin_x..origin_x + BRIDGE_KEEPER_HUT_SIZE {
+        for z in origin_z..origin_z + BRIDGE_KEEPER_HUT_SIZE {
+            if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 {
+                return false;
+            }
+            let column = (x as u32, z as u32);
+            if features.water[column] == image::Luma([255u8]) {
+                return false;
+            }
+            let image::Luma([y]) = features.terrain[column];
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+        }
+    }
+    if max_y - min_y > BRIDGE_KEEPER_HUT_SLOPE_MAX {
+        return false;
+    }
+
+    // The door faces the road.
+    let door_side = signs::surface_from_vector(-side_x, -side_z);
+    let hut = hut::build_hut(
+        (BRIDGE_KEEPER_HUT_SIZE, BRIDGE_KEEPER_HUT_SIZE),
+        door_side,
+        palette,
+        true,
+        rng,
+    );
+    paste_chopping_trees(
+        excerpt,
+        BlockCoord(origin_x, max_y as i64 - HUT_FOUNDATION_DEPTH, origin_z),
+        &hut,
+    );
+    true
 }
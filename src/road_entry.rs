@@ -0,0 +1,157 @@
+//! Entry points of the country roads on the edges of the generated area, where roads from
+//! beyond the area would most likely come in: Along existing paths, through low passes
+//! between higher ground, and through gaps along the shore. The edges are the sides of the
+//! build boundary where there is one, and the sides of the map otherwise.
+
+use image::GrayImage;
+use log::info;
+
+use crate::boundary;
+use crate::features::Features;
+
+/// Columns at each end of an edge not used as entry points, for roads not to start in a corner.
+const CORNER_MARGIN: usize = 4;
+/// Distance along the edge to the higher ground on either side of a pass.
+const PASS_RADIUS: usize = 16;
+/// Cost per block of height above the lowest passable column of the edge.
+const HEIGHT_COST: i64 = 4;
+/// Cost per block of slope at the column.
+const SLOPE_COST: i64 = 8;
+/// Bonus per block of depth of a pass, below the higher ground on its lower side.
+const PASS_BONUS: i64 = 6;
+/// Bonus for land along the shore.
+const SHORE_BONUS: i64 = 24;
+/// Distance along the edge to water, for land to count as along the shore.
+const SHORE_REACH: usize = 3;
+
+/// The columns on the edges of the area where the country roads enter it. An edge with an
+/// existing path leading into the area gets an entry point for each path; any other edge
+/// gets one at the most likely place for a road, if there is passable ground on it at all.
+/// Falls back to the corners of the area, where no edge has passable ground.
+pub fn road_entry_points(features: &Features) -> Vec<(i64, i64)> {
+    let (x_len, z_len) = features.terrain.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    let corners: Vec<(i64, i64)> = match &features.boundary {
+        Some(polygon) => polygon.iter()
+            .map(|point| (point.0, point.1))
+            .filter(|column| !features.is_outside(*column))
+            .collect(),
+        None => vec![
+            (0, 0),
+            (x_len - 1, 0),
+            (x_len - 1, z_len - 1),
+            (0, z_len - 1),
+        ],
+    };
+    let edges: Vec<Vec<(i64, i64)>> = match &features.boundary {
+        Some(polygon) => polygon.windows(2)
+            .map(|segment| {
+                boundary::segment_columns(segment[0], segment[1]).into_iter()
+                    .map(|column| (column.0, column.1))
+                    .filter(|column| !features.is_outside(*column))
+                    .collect()
+            })
+            .collect(),
+        None => vec![
+            (0..x_len).map(|x| (x, 0)).collect(),
+            (0..z_len).map(|z| (x_len - 1, z)).collect(),
+            (0..x_len).rev().map(|x| (x, z_len - 1)).collect(),
+            (0..z_len).rev().map(|z| (0, z)).collect(),
+        ],
+    };
+
+    let mut entry_points = Vec::new();
+    for edge in edges {
+        if edge.len() <= 2 * CORNER_MARGIN {
+            continue;
+        }
+        let edge = &edge[CORNER_MARGIN..edge.len() - CORNER_MARGIN];
+
+        let path_entries = path_entry_points(features, edge);
+        if path_entries.is_empty() {
+            entry_points.extend(best_entry_point(features, edge));
+        } else {
+            entry_points.extend(path_entries);
+        }
+    }
+
+    if entry_points.is_empty() {
+        info!("Found no passable ground on the edges, starting the country roads at the corners.");
+        return corners;
+    }
+
+    info!("Found {} country road entry point(s): {:?}", entry_points.len(), entry_points);
+    entry_points
+}
+
+/// The middle column of each stretch of existing path along the edge.
+fn path_entry_points(features: &Features, edge: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut entry_points = Vec::new();
+    let mut stretch = Vec::new();
+    for column in edge {
+        if is_set(&features.paths, *column) && is_passable(features, *column) {
+            stretch.push(*column);
+        } else if !stretch.is_empty() {
+            entry_points.push(stretch[stretch.len() / 2]);
+            stretch.clear();
+        }
+    }
+    if !stretch.is_empty() {
+        entry_points.push(stretch[stretch.len() / 2]);
+    }
+    entry_points
+}
+
+/// The passable column along the edge best suited for a road to enter through: Low, gently
+/// sloping, in a pass between higher ground, or along the shore.
+fn best_entry_point(features: &Features, edge: &[(i64, i64)]) -> Option<(i64, i64)> {
+    let height = |column: (i64, i64)| {
+        let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        y as i64
+    };
+    let edge_min = edge.iter()
+        .filter(|column| is_passable(features, **column))
+        .map(|column| height(*column))
+        .min()?;
+
+    let cost = |index: usize, column: (i64, i64)| -> i64 {
+        let y = height(column);
+        let image::Luma([slope]) = features.slope[(column.0 as u32, column.1 as u32)];
+
+        // Depth of the pass, as the rise to the lower of the high points on either side.
+        let rise = |columns: &[(i64, i64)]| {
+            columns.iter().map(|column| height(*column)).max().unwrap_or(y) - y
+        };
+        let before = &edge[index.saturating_sub(PASS_RADIUS)..index];
+        let after = &edge[index + 1..(index + 1 + PASS_RADIUS).min(edge.len())];
+        let pass_depth = rise(before).min(rise(after)).max(0);
+
+        let shore = &edge[index.saturating_sub(SHORE_REACH)..(index + 1 + SHORE_REACH).min(edge.len())];
+        let is_shore = is_set(&features.sand, column)
+            || shore.iter().any(|column| is_set(&features.water, *column));
+
+        let mut cost = (y - edge_min) * HEIGHT_COST + slope as i64 * SLOPE_COST - pass_depth * PASS_BONUS;
+        if is_shore {
+            cost -= SHORE_BONUS;
+        }
+        cost
+    };
+
+    edge.iter()
+        .enumerate()
+        .filter(|(_, column)| is_passable(features, **column))
+        .min_by_key(|(index, column)| cost(*index, **column))
+        .map(|(_, column)| *column)
+}
+
+/// Whether a road may start at the column: Not in water or lava, nor on a cliff.
+fn is_passable(features: &Features, column: (i64, i64)) -> bool {
+    !is_set(&features.water, column)
+        && !is_set(&features.lava, column)
+        && !is_set(&features.cliff, column)
+}
+
+fn is_set(stencil: &GrayImage, column: (i64, i64)) -> bool {
+    stencil[(column.0 as u32, column.1 as u32)] == image::Luma([255u8])
+}
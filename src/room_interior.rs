@@ -1,17 +1,18 @@
 use std::cmp::{max, min};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::convert::TryInto;
 
 use mcprogedit::block::Block;
 use mcprogedit::bounded_ints::*;
 use mcprogedit::colour::Colour;
 use mcprogedit::coordinates::BlockCoord;
-use mcprogedit::material::Material;
+use mcprogedit::material::{FenceMaterial, Material};
 use mcprogedit::positioning::{Axis3, Direction, DirectionFlags6, Surface2, Surface4, Surface5, Surface6};
 use mcprogedit::world_excerpt::WorldExcerpt;
 
 use log::{trace, warn};
-use rand::{Rng, thread_rng};
+use rand::Rng;
+use rand::rngs::StdRng;
 
 
 // What is the shape of the room?
@@ -24,6 +25,7 @@ pub enum ColumnKind {
     Window, // Wall with 1 m window starting 1 m above floor level
     Door, // Wall with door on floor level
     Floor(usize), // Open area inside room, usize gives height under ceiling
+    Stairwell, // Stairs or ladder to another floor, or the opening in the floor above them
 }
 
 /// 2D structural map of the room.
@@ -128,10 +130,10 @@ impl InteriorPlacementState {
     }
 }
 
-type InteriorPlacementStateMap = HashMap<(usize, usize, usize), InteriorPlacementState>;
+type InteriorPlacementStateMap = BTreeMap<(usize, usize, usize), InteriorPlacementState>;
 
 fn interior_placement_state_map_from_room_shape(room_shape: &RoomShape) -> InteriorPlacementStateMap {
-    let mut output = HashMap::new();
+    let mut output = BTreeMap::new();
 
     let (x_len, z_len) = room_shape.dimensions();
 
@@ -152,7 +154,8 @@ fn interior_placement_state_map_from_room_shape(room_shape: &RoomShape) -> Inter
                                     let direction = neighbour_direction((x, z), *neighbour_coordinates);
                                     available_placements.insert(PlacementOption::OnFloorBacked(direction));
                                 }
-                                Some(ColumnKind::Door) => must_be_kept_open = true,
+                                Some(ColumnKind::Door)
+                                | Some(ColumnKind::Stairwell) => must_be_kept_open = true,
                                 _ => (),
                             }
                         }
@@ -168,7 +171,8 @@ fn interior_placement_state_map_from_room_shape(room_shape: &RoomShape) -> Inter
                                     available_placements.insert(PlacementOption::OnWall(direction));
                                 }
                                 Some(ColumnKind::Window)
-                                | Some(ColumnKind::Door) => must_be_kept_open = true,
+                                | Some(ColumnKind::Door)
+                                | Some(ColumnKind::Stairwell) => must_be_kept_open = true,
                                 _ => (),
                             }
                         }
@@ -243,7 +247,7 @@ fn is_blocking_safe(
     }
 
     // Get map of walkable areas
-    let open_floor_map: HashSet<(usize, usize)> = interior_placement_state_map.iter()
+    let open_floor_map: BTreeSet<(usize, usize)> = interior_placement_state_map.iter()
         .filter_map(|(coordinates, state)| {
             if coordinates.1 == 0 && state.is_open() {
                 Some((coordinates.0, coordinates.2))
@@ -252,7 +256,7 @@ fn is_blocking_safe(
             }
         })
         .collect();
-    let open_head_height_map: HashSet<(usize, usize)> = interior_placement_state_map.iter()
+    let open_head_height_map: BTreeSet<(usize, usize)> = interior_placement_state_map.iter()
         .filter_map(|(coordinates, state)| {
             if coordinates.1 == 1 && state.is_open() {
                 Some((coordinates.0, coordinates.2))
@@ -261,19 +265,19 @@ fn is_blocking_safe(
             }
         })
         .collect();
-    let walkable_map: HashSet<(usize, usize)> =  open_floor_map.intersection(&open_head_height_map).copied().collect();
+    let walkable_map: BTreeSet<(usize, usize)> =  open_floor_map.intersection(&open_head_height_map).copied().collect();
 
     // Find block (x, z) coordinates that if placed will block movement
-    let movement_blocking_coordinates: HashSet<(usize, usize)> = blocking_coordinates.iter()
+    let movement_blocking_coordinates: BTreeSet<(usize, usize)> = blocking_coordinates.iter()
         .filter(|coordinates| coordinates.1 < 2) // Must be in one of bottom two layers
         .map(|coordinates| (coordinates.0, coordinates.2)) // Only x and z coordinates
         .collect();
 
     // Remove the blocking coordinates from the walkable map
-    let walkable_map: HashSet<(usize, usize)> = walkable_map.difference(&movement_blocking_coordinates).copied().collect();
+    let walkable_map: BTreeSet<(usize, usize)> = walkable_map.difference(&movement_blocking_coordinates).copied().collect();
 
     // Find neighbour coordinates of the blocking coordinates
-    let mut neighbours: HashSet<(usize, usize)> = HashSet::new();
+    let mut neighbours: BTreeSet<(usize, usize)> = BTreeSet::new();
     for blocking in &movement_blocking_coordinates {
         for neighbour in neighbourhood_4(*blocking) {
             neighbours.insert(neighbour);
@@ -336,6 +340,16 @@ fn neighbourhood_4_3d((x, y, z): (usize, usize, usize)) -> Vec<(usize, usize, us
     neighbourhood_coordinates
 }
 
+/// Fixed ordering of directions, so that placement does not depend on hash set iteration order.
+fn direction_order(direction: &Surface4) -> u8 {
+    match direction {
+        Surface4::North => 0,
+        Surface4::East => 1,
+        Surface4::South => 2,
+        Surface4::West => 3,
+    }
+}
+
 fn neighbour_direction(current: (usize, usize), neighbour: (usize, usize)) -> Surface4 {
     if neighbour.0 > current.0 {
         Surface4::East
@@ -386,7 +400,7 @@ fn coordinates_in_direction_3d(
 }
 
 /// Checks if all coordinates in the subset are connected via the coordinates in set.
-fn is_subset_connected(set: &HashSet<(usize, usize)>, subset: &HashSet<(usize, usize)>) -> bool {
+fn is_subset_connected(set: &BTreeSet<(usize, usize)>, subset: &BTreeSet<(usize, usize)>) -> bool {
     if subset.len() < 2 {
         return true;
     }
@@ -394,7 +408,7 @@ fn is_subset_connected(set: &HashSet<(usize, usize)>, subset: &HashSet<(usize, u
     let source = subset.into_iter().next().expect("We know that subset has len() >= 2 from previous check.");
     let mut subset = subset.clone();
     let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
-    let mut visited: HashSet<(usize, usize)> = HashSet::new();
+    let mut visited: BTreeSet<(usize, usize)> = BTreeSet::new();
 
     subset.remove(source);
     queue.push_back(*source);
@@ -423,7 +437,7 @@ fn is_subset_connected(set: &HashSet<(usize, usize)>, subset: &HashSet<(usize, u
     false
 }
 
-fn available_on_floor_backed(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn available_on_floor_backed(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection) = state {
@@ -440,7 +454,7 @@ fn available_on_floor_backed(state_map: &InteriorPlacementStateMap) -> HashSet<(
         .collect()
 }
 
-fn available_on_floor_freestanding(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn available_on_floor_freestanding(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection) = state {
@@ -457,11 +471,11 @@ fn available_on_floor_freestanding(state_map: &InteriorPlacementStateMap) -> Has
         .collect()
 }
 
-fn available_on_floor(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn available_on_floor(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     available_on_floor_backed(state_map).union(&available_on_floor_freestanding(state_map)).copied().collect()
 }
 
-fn available_on_wall(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn available_on_wall(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection) = state {
@@ -478,7 +492,7 @@ fn available_on_wall(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, u
         .collect()
 }
 
-fn placeable_on_floor_backed(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_floor_backed(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection)
@@ -496,7 +510,7 @@ fn placeable_on_floor_backed(state_map: &InteriorPlacementStateMap) -> HashSet<(
         .collect()
 }
 
-fn placeable_on_floor_freestanding(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_floor_freestanding(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection)
@@ -514,11 +528,11 @@ fn placeable_on_floor_freestanding(state_map: &InteriorPlacementStateMap) -> Has
         .collect()
 }
 
-fn placeable_on_floor(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_floor(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     placeable_on_floor_backed(state_map).union(&placeable_on_floor_freestanding(state_map)).copied().collect()
 }
 
-fn placeable_on_wall(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_wall(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection)
@@ -536,7 +550,7 @@ fn placeable_on_wall(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, u
         .collect()
 }
 
-fn placeable_on_side_surface(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_side_surface(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection)
@@ -554,11 +568,11 @@ fn placeable_on_side_surface(state_map: &InteriorPlacementStateMap) -> HashSet<(
         .collect()
 }
 
-fn placeable_on_side(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_side(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     placeable_on_wall(state_map).union(&placeable_on_side_surface(state_map)).copied().collect()
 }
 
-fn placeable_on_top_surface_backed(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_top_surface_backed(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection)
@@ -576,7 +590,7 @@ fn placeable_on_top_surface_backed(state_map: &InteriorPlacementStateMap) -> Has
         .collect()
 }
 
-fn placeable_on_top_surface_freestanding(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_top_surface_freestanding(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     state_map.iter()
         .filter_map(|(coordinates, state)| {
             if let InteriorPlacementState::Available(placement_collection)
@@ -594,13 +608,13 @@ fn placeable_on_top_surface_freestanding(state_map: &InteriorPlacementStateMap)
         .collect()
 }
 
-fn placeable_on_top_surface(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
+fn placeable_on_top_surface(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
     placeable_on_top_surface_backed(state_map).union(&placeable_on_top_surface_freestanding(state_map)).copied().collect()
 }
 
 /// Returns set of coordinates on layers 0 and 1, where the coordinate for both layers are open.
-fn walkable(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usize)> {
-    let open_floor_map: HashSet<(usize, usize)> = state_map.iter()
+fn walkable(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize, usize)> {
+    let open_floor_map: BTreeSet<(usize, usize)> = state_map.iter()
         .filter_map(|(coordinates, state)| {
             if coordinates.1 == 0 && state.is_open() {
                 Some((coordinates.0, coordinates.2))
@@ -609,7 +623,7 @@ fn walkable(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usi
             }
         })
         .collect();
-    let open_head_height_map: HashSet<(usize, usize)> = state_map.iter()
+    let open_head_height_map: BTreeSet<(usize, usize)> = state_map.iter()
         .filter_map(|(coordinates, state)| {
             if coordinates.1 == 1 && state.is_open() {
                 Some((coordinates.0, coordinates.2))
@@ -618,7 +632,7 @@ fn walkable(state_map: &InteriorPlacementStateMap) -> HashSet<(usize, usize, usi
             }
         })
         .collect();
-    let walkable_map: HashSet<(usize, usize)> =  open_floor_map.intersection(&open_head_height_map).copied().collect();
+    let walkable_map: BTreeSet<(usize, usize)> =  open_floor_map.intersection(&open_head_height_map).copied().collect();
 
     state_map.iter()
         .filter_map(|(coordinates, _)| {
@@ -638,7 +652,7 @@ fn on_floor_backed_directions(
     if let Some(state) = state_map.get(&coordinates) {
         if let InteriorPlacementState::Available(collection)
         | InteriorPlacementState::KeepOpen(collection) = state {
-            return collection.iter()
+            let mut directions: Vec<Surface4> = collection.iter()
                 .filter_map(|option| {
                     if let PlacementOption::OnFloorBacked(direction) = option {
                         Some(*direction)
@@ -647,6 +661,8 @@ fn on_floor_backed_directions(
                     }
                 })
                 .collect();
+            directions.sort_by_key(direction_order);
+            return directions;
         }
     }
 
@@ -660,7 +676,7 @@ fn on_wall_directions(
     if let Some(state) = state_map.get(&coordinates) {
         if let InteriorPlacementState::Available(collection)
         | InteriorPlacementState::KeepOpen(collection) = state {
-            return collection.iter()
+            let mut directions: Vec<Surface4> = collection.iter()
                 .filter_map(|option| {
                     if let PlacementOption::OnWall(direction) = option {
                         Some(*direction)
@@ -669,20 +685,22 @@ fn on_wall_directions(
                     }
                 })
                 .collect();
+            directions.sort_by_key(direction_order);
+            return directions;
         }
     }
 
     Vec::new()
 }
 
-fn _from_ceiling_backed_directions(
+fn from_ceiling_backed_directions(
     state_map: &InteriorPlacementStateMap,
     coordinates: (usize, usize, usize),
 ) -> Vec<Surface4> {
     if let Some(state) = state_map.get(&coordinates) {
         if let InteriorPlacementState::Available(collection)
         | InteriorPlacementState::KeepOpen(collection) = state {
-            return collection.iter()
+            let mut directions: Vec<Surface4> = collection.iter()
                 .filter_map(|option| {
                     if let PlacementOption::FromCeilingBacked(direction) = option {
                         Some(*direction)
@@ -691,6 +709,8 @@ fn _from_ceiling_backed_directions(
                     }
                 })
                 .collect();
+            directions.sort_by_key(direction_order);
+            return directions;
         }
     }
 
@@ -704,7 +724,7 @@ fn _on_top_surface_backed_directions(
     if let Some(state) = state_map.get(&coordinates) {
         if let InteriorPlacementState::Available(collection)
         | InteriorPlacementState::KeepOpen(collection) = state {
-            return collection.iter()
+            let mut directions: Vec<Surface4> = collection.iter()
                 .filter_map(|option| {
                     if let PlacementOption::OnTopSurfaceBacked(direction) = option {
                         Some(*direction)
@@ -713,6 +733,8 @@ fn _on_top_surface_backed_directions(
                     }
                 })
                 .collect();
+            directions.sort_by_key(direction_order);
+            return directions;
         }
     }
 
@@ -726,7 +748,7 @@ fn _on_side_surface_directions(
     if let Some(state) = state_map.get(&coordinates) {
         if let InteriorPlacementState::Available(collection)
         | InteriorPlacementState::KeepOpen(collection) = state {
-            return collection.iter()
+            let mut directions: Vec<Surface4> = collection.iter()
                 .filter_map(|option| {
                     if let PlacementOption::OnSideSurface(direction) = option {
                         Some(*direction)
@@ -735,6 +757,8 @@ fn _on_side_surface_directions(
                     }
                 })
                 .collect();
+            directions.sort_by_key(direction_order);
+            return directions;
         }
     }
 
@@ -748,7 +772,7 @@ fn any_directions(
     if let Some(state) = state_map.get(&coordinates) {
         if let InteriorPlacementState::Available(collection)
         | InteriorPlacementState::KeepOpen(collection) = state {
-            return collection.iter()
+            let mut directions: Vec<Surface4> = collection.iter()
                 .filter_map(|option| {
                     if let PlacementOption::OnFloorBacked(direction)
                     | PlacementOption::OnWall(direction)
@@ -761,6 +785,8 @@ fn any_directions(
                     }
                 })
                 .collect();
+            directions.sort_by_key(direction_order);
+            return directions;
         }
     }
 
@@ -871,7 +897,17 @@ fn is_suitable_for_one_high_top_surface(
 
 /// Place a bookshelf (on top of which other things can be placed.)
 fn place_bookshelf(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
-    let two_layer_opportunities: HashSet<ObjectAnchor> = available_on_floor_backed(&state_map)
+    place_bookshelf_along_wall(excerpt, state_map, 3)
+}
+
+/// Place a bookshelf two layers high, as long as possible along a wall, up to `length_max`.
+/// Placed over and over, the bookshelves line one wall after another.
+fn place_bookshelf_along_wall(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    length_max: usize,
+) -> bool {
+    let two_layer_opportunities: Vec<ObjectAnchor> = available_on_floor_backed(&state_map)
         .into_iter()
         .map(|location| {
             let output: Vec<ObjectAnchor> = on_floor_backed_directions(state_map, location)
@@ -914,7 +950,7 @@ fn place_bookshelf(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacement
         .collect();
 
     let longest_opportunity = two_layer_opportunities.iter()
-        .filter(|x| x.length_along_wall <= 3)
+        .filter(|x| x.length_along_wall <= length_max)
         .max_by(|x, y| x.length_along_wall.cmp(&y.length_along_wall));
 
     if let Some(bookshelf) = longest_opportunity {
@@ -952,6 +988,80 @@ fn place_bookshelf(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacement
     false
 }
 
+/// Place heating, so that every part of the room is within reach of a heat source:
+/// a furnace or campfire already in the room, or else a brazier of a campfire under iron bars.
+fn place_heating(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+    const HEAT_REACH: usize = 6;
+
+    fn warmed_coordinates(source: (usize, usize)) -> Vec<(usize, usize)> {
+        let (source_x, source_z) = source;
+        let mut output = Vec::new();
+        for x in source_x.saturating_sub(HEAT_REACH) .. source_x + HEAT_REACH + 1 {
+            for z in source_z.saturating_sub(HEAT_REACH) .. source_z + HEAT_REACH + 1 {
+                if max(source_x, x) - min(source_x, x) + max(source_z, z) - min(source_z, z) <= HEAT_REACH {
+                    output.push((x, z));
+                }
+            }
+        }
+        output
+    }
+
+    // These are the positions that should get heated
+    let mut cold_map: BTreeSet<(usize, usize)> = state_map.iter()
+        .map(|((x, _, z), _)| (*x, *z))
+        .collect();
+
+    // Heat sources already in the room
+    let furnaces: Vec<Block> = [Surface4::North, Surface4::South, Surface4::East, Surface4::West].iter()
+        .map(|direction| Block::furnace(*direction))
+        .collect();
+    let (x_len, y_len, z_len) = excerpt.dim();
+    for x in 0..x_len {
+        for y in 0..y_len {
+            for z in 0..z_len {
+                if let Some(block) = excerpt.block_at(BlockCoord(x as i64, y as i64, z as i64)) {
+                    if furnaces.contains(block) || matches!(block, Block::Campfire { .. }) {
+                        for surroundings in warmed_coordinates((x, z)) {
+                            cold_map.remove(&surroundings);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Put braziers against the walls
+    for location in available_on_floor_backed(&state_map) {
+        if cold_map.is_empty() {
+            break;
+        }
+        let above = (location.0, location.1 + 1, location.2);
+        if !cold_map.contains(&(location.0, location.2))
+        || !is_open(state_map, above)
+        || !is_blocking_safe(&state_map, &[location, above]) {
+            continue;
+        }
+
+        excerpt.set_block_at(
+            BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+            Block::Campfire { facing: Surface4::North, lit: true, waterlogged: false },
+        );
+        excerpt.set_block_at(
+            BlockCoord(above.0 as i64, above.1 as i64, above.2 as i64),
+            Block::IronBars { waterlogged: false },
+        );
+        // Bookkeeping
+        state_map_mark_blocking(state_map, location);
+        state_map_mark_blocking(state_map, above);
+        // Remove surroundings from cold map
+        for surroundings in warmed_coordinates((location.0, location.2)) {
+            cold_map.remove(&surroundings);
+        }
+    }
+
+    cold_map.is_empty()
+}
+
 /// Place objects fulfilling the "cooking" requirement, e.g. a furnace, or smoker.
 fn place_cooking(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
     let walkable_tiles = walkable(&state_map);
@@ -994,9 +1104,11 @@ fn place_cooking(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementSt
 }
 
 /// Place one object fulfilling the "decor" requirement.
-fn place_decor(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
-    let mut rng = thread_rng();
-
+fn place_decor(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
     // 1) TODO Freestanding on floor NB may need armour stand
     // 2) TODO On floor NB may need armour stand
 
@@ -1097,7 +1209,11 @@ fn place_decor(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStat
 }
 
 /// Place objects fulfilling the "hygiene" requirement, e.g. some washing utility.
-fn place_hygiene(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+fn place_hygiene(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
     let walkable_tiles = walkable(&state_map);
 
     let candidates: Vec<(usize, usize, usize)> = available_on_floor_backed(&state_map)
@@ -1112,7 +1228,6 @@ fn place_hygiene(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementSt
         for neighbour in neighbourhood_4_3d(location) {
             if walkable_tiles.contains(&neighbour)
             && is_blocking_safe(&state_map, &[location]) {
-                let mut rng = thread_rng();
                 let water_level = mcprogedit::bounded_ints::Int0Through3::new(rng.gen_range(0..=3)).unwrap();
 
                 excerpt.set_block_at(
@@ -1156,7 +1271,7 @@ fn place_lighting(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementS
     }
 
     // These are the positions that should get illuminated
-    let mut darkness_map: HashSet<(usize, usize)> = state_map.iter()
+    let mut darkness_map: BTreeSet<(usize, usize)> = state_map.iter()
         .map(|((x, _, z), _)| (*x, *z))
         .collect();
 
@@ -1302,10 +1417,12 @@ fn place_lighting(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementS
 // TODO place_double_sleep
 
 /// Place one shelf.
-fn place_shelf(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
-    let mut rng = thread_rng();
-
-    let placement_alternatives: HashSet<ObjectAnchor> = available_on_wall(&state_map)
+fn place_shelf(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
+    let placement_alternatives: Vec<ObjectAnchor> = available_on_wall(&state_map)
         .into_iter()
         .map(|location| {
             let output: Vec<ObjectAnchor> = on_wall_directions(state_map, location)
@@ -1394,7 +1511,11 @@ fn place_shelf(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStat
 }
 
 /// Place objects fulfilling the "sleep" requirement for one person, e.g. a bed.
-fn place_single_sleep(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+fn place_single_sleep(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
     // Find all ground tiles with wall (or other) backing, for bed head end.
     let on_floor_backed_tiles = available_on_floor_backed(&state_map);
     let on_floor_tiles = available_on_floor(&state_map);
@@ -1417,7 +1538,6 @@ fn place_single_sleep(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacem
                     let he = candidate_head_end;
                     let fe = candidate_foot_end;
 
-                    let mut rng = thread_rng();
                     let colour: Colour = rng.gen_range(0..=15).into();
 
                    // let colour = Colour::Red;
@@ -1452,9 +1572,190 @@ fn place_single_sleep(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacem
     false
 }
 
+/// Place a bunk bed, sleeping two: Two beds of the same colour stacked one above the other.
+fn place_bunk_bed(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
+    let on_floor_backed_tiles = available_on_floor_backed(&state_map);
+    let on_floor_tiles = available_on_floor(&state_map);
+    let walkable_tiles = walkable(&state_map);
+
+    for candidate_head_end in on_floor_backed_tiles {
+        for candidate_foot_end in neighbourhood_4((candidate_head_end.0, candidate_head_end.2))
+                .iter()
+                .map(|(x, z)| (*x, candidate_head_end.1, *z))
+                .filter(|c| on_floor_tiles.contains(&c)) {
+            let upper_head_end = (candidate_head_end.0, candidate_head_end.1 + 1, candidate_head_end.2);
+            let upper_foot_end = (candidate_foot_end.0, candidate_foot_end.1 + 1, candidate_foot_end.2);
+            if !is_open(&state_map, upper_head_end) || !is_open(&state_map, upper_foot_end) {
+                continue;
+            }
+            for candidate_open_tile in neighbourhood_4((candidate_foot_end.0, candidate_foot_end.2))
+                    .iter()
+                    .map(|(x, z)| (*x, candidate_foot_end.1, *z))
+                    .filter(|c| walkable_tiles.contains(&c) && *c != candidate_head_end) {
+                let bunk = [candidate_head_end, candidate_foot_end, upper_head_end, upper_foot_end];
+                if is_blocking_safe(&state_map, &bunk) {
+                    let colour: Colour = rng.gen_range(0..=15).into();
+                    let facing = neighbour_direction(
+                        (candidate_foot_end.0, candidate_foot_end.2),
+                        (candidate_head_end.0, candidate_head_end.2),
+                    );
+
+                    for (he, fe) in &[(candidate_head_end, candidate_foot_end), (upper_head_end, upper_foot_end)] {
+                        excerpt.set_block_at(
+                            BlockCoord(he.0 as i64, he.1 as i64, he.2 as i64),
+                            Block::Bed(
+                                mcprogedit::block::Bed { colour, facing, end: mcprogedit::block::BedEnd::Head }
+                            )
+                        );
+                        excerpt.set_block_at(
+                            BlockCoord(fe.0 as i64, fe.1 as i64, fe.2 as i64),
+                            Block::Bed(
+                                mcprogedit::block::Bed { colour, facing, end: mcprogedit::block::BedEnd::Foot }
+                            )
+                        );
+                    }
+
+                    // State bookkeeping
+                    for coordinates in &bunk {
+                        state_map_mark_blocking(state_map, *coordinates);
+                    }
+                    state_map_mark_open(state_map, candidate_open_tile);
+
+                    return true;
+                }
+            }
+        }
+    }
+
+    false
+}
+
+/// Place a weapon rack: A pair of posts side by side against a wall, for leaning spears and
+/// hanging swords on, with room in front of it for taking them.
+fn place_weapon_rack(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+    let on_floor_backed_tiles = available_on_floor_backed(&state_map);
+    let walkable_tiles = walkable(&state_map);
+
+    for location in &on_floor_backed_tiles {
+        for direction in on_floor_backed_directions(state_map, *location) {
+            let outward = direction.opposite();
+            let beside = match neighbour_in_direction_3d(*location, outward.rotated_90_cw()) {
+                Some(beside) => beside,
+                None => continue,
+            };
+            if !on_floor_backed_tiles.contains(&beside)
+            || !on_floor_backed_directions(state_map, beside).contains(&direction) {
+                continue;
+            }
+            let in_front = match neighbour_in_direction_3d(*location, outward) {
+                Some(in_front) => in_front,
+                None => continue,
+            };
+            if walkable_tiles.contains(&in_front) && is_blocking_safe(&state_map, &[*location, beside]) {
+                for coordinates in &[*location, beside] {
+                    excerpt.set_block_at(
+                        BlockCoord(coordinates.0 as i64, coordinates.1 as i64, coordinates.2 as i64),
+                        Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+                    );
+                    state_map_mark_blocking(state_map, *coordinates);
+                }
+                state_map_mark_open(state_map, in_front);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Place a bundle of dried herbs, hanging from a trapdoor under the ceiling against a wall.
+fn place_dried_herbs(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
+    let ceiling_positions: Vec<(usize, usize, usize)> = state_map.keys()
+        .filter(|(_, y, _)| *y >= 2)
+        .copied()
+        .collect();
+
+    for location in ceiling_positions {
+        let below = (location.0, location.1 - 1, location.2);
+        for direction in from_ceiling_backed_directions(state_map, location) {
+            if !on_wall_directions(state_map, below).contains(&direction)
+            || !is_nonblocking_safe(&state_map, &[location, below]) {
+                continue;
+            }
+            let trapdoor_material = match rng.gen_range(0..=3) {
+                0 => Material::Birch,
+                1 => Material::DarkOak,
+                2 => Material::Oak,
+                3 => Material::Spruce,
+                _ => unreachable!(),
+            };
+            excerpt.set_block_at(
+                BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+                Block::top_trapdoor(direction.opposite().into(), trapdoor_material),
+            );
+            excerpt.set_block_at(
+                BlockCoord(below.0 as i64, below.1 as i64, below.2 as i64),
+                Block::Vines(DirectionFlags6 {
+                    east: direction == Surface4::East,
+                    down: false,
+                    north: direction == Surface4::North,
+                    south: direction == Surface4::South,
+                    up: true,
+                    west: direction == Surface4::West,
+                }),
+            );
+            state_map_mark_occupied_open(state_map, location);
+            state_map_mark_occupied_open(state_map, below);
+            return true;
+        }
+    }
+    false
+}
+
+/// Place a potted herb on a top surface.
+fn place_potted_herb(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
+    for location in placeable_on_top_surface(state_map) {
+        if !is_nonblocking_safe(&state_map, &[location]) {
+            continue;
+        }
+        let plant = match rng.gen_range(0..=7) {
+            0 => mcprogedit::block::PottedPlant::Allium,
+            1 => mcprogedit::block::PottedPlant::BrownMushroom,
+            2 => mcprogedit::block::PottedPlant::Cornflower,
+            3 => mcprogedit::block::PottedPlant::Dandelion,
+            4 => mcprogedit::block::PottedPlant::Fern,
+            5 => mcprogedit::block::PottedPlant::LilyOfTheValley,
+            6 => mcprogedit::block::PottedPlant::OxeyeDaisy,
+            7 => mcprogedit::block::PottedPlant::RedMushroom,
+            _ => unreachable!(),
+        };
+        excerpt.set_block_at(
+            BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+            Block::FlowerPot(mcprogedit::block::FlowerPot::new_with_plant(plant)),
+        );
+        state_map_mark_occupied_open(state_map, location);
+        return true;
+    }
+    false
+}
+
 /// Place objects fulfilling the "store" requirement, e.g. a chest, or barrel.
-fn place_store(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
-    let mut rng = thread_rng();
+fn place_store(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
     let walkable_tiles = walkable(&state_map);
 
     for location in available_on_floor_backed(&state_map) {
@@ -1506,10 +1807,41 @@ fn place_store(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStat
 }
 
 /// Place one table.
-fn place_table(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
-    let mut rng = thread_rng();
+fn place_table(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
+    // Pick a material for the table
+    // Stair materials are a subset of slab materials, at least as of MC 1.16.5.
+    let table_material = match rng.gen_range(0..=12) {
+        0 => Material::Acacia,
+        1 => Material::Birch,
+        2 => Material::DarkOak,
+        3 => Material::Jungle,
+        4 => Material::Oak,
+        5 => Material::Spruce,
+        6 => Material::Warped,
+        7 => Material::Crimson,
+        8 => Material::Stone,
+        9 => Material::Andesite,
+        10 => Material::Diorite,
+        11 => Material::Granite,
+        12 => Material::MossyStoneBrick,
+        _ => unreachable!(),
+    };
+
+    place_counter(excerpt, state_map, 3, table_material)
+}
 
-    let placement_alternatives: HashSet<ObjectAnchor> = available_on_floor_backed(&state_map)
+/// Place one counter along a wall, as long as there is room for, up to `length_max`.
+fn place_counter(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    length_max: usize,
+    table_material: Material,
+) -> bool {
+    let placement_alternatives: Vec<ObjectAnchor> = available_on_floor_backed(&state_map)
         .into_iter()
         .map(|location| {
             let output: Vec<ObjectAnchor> = on_floor_backed_directions(state_map, location)
@@ -1551,32 +1883,13 @@ fn place_table(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStat
         .collect();
 
     let longest_alternative = placement_alternatives.iter()
-        .filter(|x| x.length_along_wall <= 3)
+        .filter(|x| x.length_along_wall <= length_max)
         .max_by(|x, y| x.length_along_wall.cmp(&y.length_along_wall));
 
     if let Some(structure) = longest_alternative {
         let structure_coordinates = structure.coordinate_list();
 
         if is_blocking_safe(state_map, &structure_coordinates) {
-            // Pick a material for the table
-            // Stair materials are a subset of slab materials, at least as of MC 1.16.5.
-            let table_material = match rng.gen_range(0..=12) {
-                0 => Material::Acacia,
-                1 => Material::Birch,
-                2 => Material::DarkOak,
-                3 => Material::Jungle,
-                4 => Material::Oak,
-                5 => Material::Spruce,
-                6 => Material::Warped,
-                7 => Material::Crimson,
-                8 => Material::Stone,
-                9 => Material::Andesite,
-                10 => Material::Diorite,
-                11 => Material::Granite,
-                12 => Material::MossyStoneBrick,
-                _ => unreachable!(),
-            };
-
             for location in &structure_coordinates {
                 // Treat first and last coordinates differently
                 if structure.length_along_wall == 1 {
@@ -1623,13 +1936,16 @@ fn place_table(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStat
 }
 
 /// Place one object providing a top surface for another object to rest on.
-fn place_top_surface(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+fn place_top_surface(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
     // TODO maybe use a "budget" argument, have a number of options ordered from large to small,
     // and create the largest one possible within the budget?
 
 //    let walkable_tiles = walkable(&state_map);
 
-    let mut rng = thread_rng();
     let die_roll = rng.gen_range(0..5);
 
     // A moderate chance of trying to place a bookshelf.
@@ -1637,19 +1953,19 @@ fn place_top_surface(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlaceme
         0 => if place_bookshelf(excerpt, state_map) {
             return true;
         }
-        1 | 2 => if place_table(excerpt, state_map) {
+        1 | 2 => if place_table(excerpt, state_map, rng) {
             return true;
         },
-        3 | 4 => if place_shelf(excerpt, state_map) {
+        3 | 4 => if place_shelf(excerpt, state_map, rng) {
             return true;
         },
         _ => unreachable!(),
     }
 
-    if place_shelf(excerpt, state_map) {
+    if place_shelf(excerpt, state_map, rng) {
         return true;
     }
-    if place_table(excerpt, state_map) {
+    if place_table(excerpt, state_map, rng) {
         return true;
     }
     if place_bookshelf(excerpt, state_map) {
@@ -1697,6 +2013,382 @@ fn place_top_surface(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlaceme
     false
 }
 
+/// Position of the given floor coordinates relative to the `front` end of the room,
+/// as `(depth, across)`, with depth 0 in the row closest to `front`.
+fn depth_and_across(
+    floor: &BTreeSet<(usize, usize)>,
+    front: Surface4,
+    (x, z): (usize, usize),
+) -> (usize, usize) {
+    let min_x = floor.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let max_x = floor.iter().map(|(x, _)| *x).max().unwrap_or(0);
+    let min_z = floor.iter().map(|(_, z)| *z).min().unwrap_or(0);
+    let max_z = floor.iter().map(|(_, z)| *z).max().unwrap_or(0);
+    match front {
+        Surface4::North => (z - min_z, x),
+        Surface4::South => (max_z - z, x),
+        Surface4::West => (x - min_x, z),
+        Surface4::East => (max_x - x, z),
+    }
+}
+
+/// The floor coordinates of the room, at ground level.
+fn floor_coordinates(state_map: &InteriorPlacementStateMap) -> BTreeSet<(usize, usize)> {
+    state_map.keys()
+        .filter(|(_, y, _)| *y == 0)
+        .map(|(x, _, z)| (*x, *z))
+        .collect()
+}
+
+/// The middle of the room, across the direction towards `front`.
+fn middle_across(floor: &BTreeSet<(usize, usize)>, front: Surface4) -> usize {
+    let across: Vec<usize> = floor.iter()
+        .map(|coordinates| depth_and_across(floor, front, *coordinates).1)
+        .collect();
+    let min = across.iter().copied().min().unwrap_or(0);
+    let max = across.iter().copied().max().unwrap_or(0);
+    (min + max) / 2
+}
+
+/// Place an altar in the middle of the `front` end of the room, with candle light on either end.
+fn place_altar(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    front: Surface4,
+) -> bool {
+    let floor = floor_coordinates(state_map);
+    if floor.is_empty() {
+        return false;
+    }
+    let middle = middle_across(&floor, front);
+
+    let altar: Vec<(usize, usize, usize)> = floor.iter()
+        .filter(|coordinates| {
+            let (depth, across) = depth_and_across(&floor, front, **coordinates);
+            depth == 0 && max(across, middle) - min(across, middle) <= 1
+        })
+        .map(|(x, z)| (*x, 0, *z))
+        .filter(|location| matches!(state_map.get(location), Some(InteriorPlacementState::Available(_))))
+        .collect();
+    if altar.is_empty() || !is_blocking_safe(state_map, &altar) {
+        return false;
+    }
+
+    for location in &altar {
+        let (_, across) = depth_and_across(&floor, front, (location.0, location.2));
+        let coordinates = BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64);
+        if across == middle {
+            excerpt.set_block_at(coordinates, Block::ChiseledStoneBricks);
+        } else {
+            excerpt.set_block_at(coordinates, Block::StoneBricks);
+            excerpt.set_block_at(
+                coordinates + BlockCoord(0, 1, 0),
+                Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+            );
+            state_map_mark_occupied_open(state_map, (location.0, location.1 + 1, location.2));
+        }
+
+        // Bookkeeping, keeping the space in front of the altar open
+        state_map_mark_blocking(state_map, *location);
+        if let Some(in_front) = neighbour_in_direction_3d(*location, front.opposite()) {
+            state_map_mark_open(state_map, in_front);
+            state_map_mark_open(state_map, (in_front.0, in_front.1 + 1, in_front.2));
+        }
+    }
+    true
+}
+
+/// Place rows of pews facing the `front` end of the room, on both sides of an aisle
+/// down the middle. Returns the number of pew blocks placed.
+fn place_pews(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    front: Surface4,
+    rng: &mut StdRng,
+) -> usize {
+    const FRONT_ROWS_OPEN: usize = 3;
+
+    let floor = floor_coordinates(state_map);
+    if floor.is_empty() {
+        return 0;
+    }
+    let middle = middle_across(&floor, front);
+    let max_depth = floor.iter()
+        .map(|coordinates| depth_and_across(&floor, front, *coordinates).0)
+        .max()
+        .unwrap_or(0);
+
+    let material = match rng.gen_range(0..=5) {
+        0 => Material::Acacia,
+        1 => Material::Birch,
+        2 => Material::DarkOak,
+        3 => Material::Jungle,
+        4 => Material::Oak,
+        5 => Material::Spruce,
+        _ => unreachable!(),
+    };
+
+    // Every other row, leaving the rows by the altar and the one by the back wall open
+    let mut count = 0;
+    for (x, z) in &floor {
+        let (depth, across) = depth_and_across(&floor, front, (*x, *z));
+        if depth < FRONT_ROWS_OPEN || depth + 1 >= max_depth
+            || (depth - FRONT_ROWS_OPEN) % 2 != 0 || across == middle
+        {
+            continue;
+        }
+        let location = (*x, 0, *z);
+        if !matches!(state_map.get(&location), Some(InteriorPlacementState::Available(_))) {
+            continue;
+        }
+
+        // The back rest away from the altar
+        excerpt.set_block_at(
+            BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+            Block::stairs(front.opposite().into(), material),
+        );
+        state_map_mark_blocking(state_map, location);
+        count += 1;
+    }
+    count
+}
+
+/// Place a bar counter along a wall, with barrels behind it if there is room.
+fn place_bar_counter(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
+    const BAR_COUNTER_LENGTH_MAX: usize = 6;
+
+    let material = match rng.gen_range(0..=2) {
+        0 => Material::DarkOak,
+        1 => Material::Oak,
+        2 => Material::Spruce,
+        _ => unreachable!(),
+    };
+    place_counter(excerpt, state_map, BAR_COUNTER_LENGTH_MAX, material)
+}
+
+/// Place a small table in the open, with a seat on either side of it.
+fn place_seated_table(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    rng: &mut StdRng,
+) -> bool {
+    let material = match rng.gen_range(0..=2) {
+        0 => Material::DarkOak,
+        1 => Material::Oak,
+        2 => Material::Spruce,
+        _ => unreachable!(),
+    };
+
+    for location in available_on_floor_freestanding(state_map) {
+        for direction in &[Surface4::North, Surface4::East] {
+            let seats = match (
+                neighbour_in_direction_3d(location, *direction),
+                neighbour_in_direction_3d(location, direction.opposite()),
+            ) {
+                (Some(a), Some(b)) => [(a, *direction), (b, direction.opposite())],
+                _ => continue,
+            };
+            let all_available = std::iter::once(location)
+                .chain(seats.iter().map(|(seat, _)| *seat))
+                .all(|coordinates| {
+                    matches!(state_map.get(&coordinates), Some(InteriorPlacementState::Available(_)))
+                        && is_open(state_map, (coordinates.0, coordinates.1 + 1, coordinates.2))
+                });
+            let blocking = [location, seats[0].0, seats[1].0];
+            if !all_available || !is_blocking_safe(state_map, &blocking) {
+                continue;
+            }
+
+            excerpt.set_block_at(
+                BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+                Block::Scaffolding { waterlogged: false },
+            );
+            state_map_mark_blocking(state_map, location);
+            state_map_add_top_surface(state_map, (location.0, location.1 + 1, location.2));
+
+            // Seats facing the table, with room to get to them
+            for (seat, side) in &seats {
+                excerpt.set_block_at(
+                    BlockCoord(seat.0 as i64, seat.1 as i64, seat.2 as i64),
+                    Block::stairs((*side).into(), material),
+                );
+                state_map_mark_blocking(state_map, *seat);
+                if let Some(behind) = neighbour_in_direction_3d(*seat, *side) {
+                    if state_map.contains_key(&behind) {
+                        state_map_mark_open(state_map, behind);
+                    }
+                }
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Place a barrel on its side against a wall, with the lid facing the room.
+fn place_barrel(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+    let walkable_tiles = walkable(&state_map);
+
+    for location in available_on_floor_backed(&state_map) {
+        for direction in on_floor_backed_directions(state_map, location) {
+            let direction = direction.opposite();
+            if let Some(neighbour) = neighbour_in_direction_3d(location, direction) {
+                if walkable_tiles.contains(&neighbour) && is_blocking_safe(&state_map, &[location]) {
+                    excerpt.set_block_at(
+                        BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+                        Block::barrel(Direction::from(direction).try_into().unwrap()),
+                    );
+                    state_map_mark_blocking(state_map, location);
+                    state_map_add_top_surface(state_map, (location.0, location.1 + 1, location.2));
+                    state_map_mark_open(state_map, neighbour);
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Place a jukebox against a wall.
+fn place_jukebox(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+    let walkable_tiles = walkable(&state_map);
+
+    for location in available_on_floor_backed(&state_map) {
+        for direction in on_floor_backed_directions(state_map, location) {
+            if let Some(neighbour) = neighbour_in_direction_3d(location, direction.opposite()) {
+                if walkable_tiles.contains(&neighbour) && is_blocking_safe(&state_map, &[location]) {
+                    excerpt.set_block_at(
+                        BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+                        Block::Jukebox(Box::new(mcprogedit::block::Jukebox { record: None })),
+                    );
+                    state_map_mark_blocking(state_map, location);
+                    state_map_add_top_surface(state_map, (location.0, location.1 + 1, location.2));
+                    state_map_mark_open(state_map, neighbour);
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Place a forge against a wall: a furnace under a stone hood reaching the ceiling,
+/// with a cauldron of water for quenching beside it.
+fn place_forge(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+    let (location, direction) = match place_hooded_furnace(excerpt, state_map) {
+        Some(placement) => placement,
+        None => return false,
+    };
+
+    // Quenching tub along the wall, on either side of the furnace
+    for side in &[direction.rotated_90_cw(), direction.rotated_90_ccw()] {
+        if let Some(tub) = neighbour_in_direction_3d(location, *side) {
+            if matches!(state_map.get(&tub), Some(InteriorPlacementState::Available(_)))
+                && is_blocking_safe(&state_map, &[tub])
+            {
+                excerpt.set_block_at(
+                    BlockCoord(tub.0 as i64, tub.1 as i64, tub.2 as i64),
+                    Block::Cauldron { water_level: Int0Through3::new(3).unwrap() },
+                );
+                state_map_mark_blocking(state_map, tub);
+                break;
+            }
+        }
+    }
+    true
+}
+
+/// Place a hearth against a wall: a furnace under a stone hood reaching the ceiling.
+fn place_hearth(excerpt: &mut WorldExcerpt, state_map: &mut InteriorPlacementStateMap) -> bool {
+    place_hooded_furnace(excerpt, state_map).is_some()
+}
+
+/// Place a furnace against a wall, under a stone hood reaching the ceiling, for a chimney
+/// to continue from. Returns the location of the furnace, and the direction it faces.
+fn place_hooded_furnace(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+) -> Option<((usize, usize, usize), Surface4)> {
+    let walkable_tiles = walkable(&state_map);
+
+    for location in available_on_floor_backed(&state_map) {
+        for direction in on_floor_backed_directions(state_map, location) {
+            let direction = direction.opposite();
+            let neighbour = match neighbour_in_direction_3d(location, direction) {
+                Some(neighbour) => neighbour,
+                None => continue,
+            };
+
+            // The hood takes up the column above the furnace, all the way up to the ceiling.
+            let hood: Vec<(usize, usize, usize)> = (1..)
+                .map(|y| (location.0, y, location.2))
+                .take_while(|coordinates| state_map.contains_key(coordinates))
+                .collect();
+            let mut blocking = vec![location];
+            blocking.extend(hood.iter().copied());
+
+            if !walkable_tiles.contains(&neighbour)
+                || !hood.iter().all(|coordinates| is_open(state_map, *coordinates))
+                || !is_blocking_safe(&state_map, &blocking)
+            {
+                continue;
+            }
+
+            excerpt.set_block_at(
+                BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+                Block::furnace(direction),
+            );
+            for coordinates in &hood {
+                excerpt.set_block_at(
+                    BlockCoord(coordinates.0 as i64, coordinates.1 as i64, coordinates.2 as i64),
+                    Block::Cobblestone,
+                );
+            }
+            for coordinates in &blocking {
+                state_map_mark_blocking(state_map, *coordinates);
+            }
+            state_map_mark_open(state_map, neighbour);
+            return Some((location, direction));
+        }
+    }
+    None
+}
+
+/// Place a workstation against a wall, with room in front of it for working.
+/// The block is given by `block`, from the direction facing out from the wall.
+fn place_workstation<F>(
+    excerpt: &mut WorldExcerpt,
+    state_map: &mut InteriorPlacementStateMap,
+    block: F,
+) -> bool
+where
+    F: Fn(Surface4) -> Block,
+{
+    let walkable_tiles = walkable(&state_map);
+
+    for location in available_on_floor_backed(&state_map) {
+        for direction in on_floor_backed_directions(state_map, location) {
+            let direction = direction.opposite();
+            if let Some(neighbour) = neighbour_in_direction_3d(location, direction) {
+                if walkable_tiles.contains(&neighbour) && is_blocking_safe(&state_map, &[location]) {
+                    excerpt.set_block_at(
+                        BlockCoord(location.0 as i64, location.1 as i64, location.2 as i64),
+                        block(direction),
+                    );
+                    state_map_mark_blocking(state_map, location);
+                    state_map_mark_open(state_map, neighbour);
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
 // Utility functions for placing objects
 /////////////////////////////////////////
 
@@ -1862,7 +2554,11 @@ pub fn _furnish_debug(room_shape: &RoomShape) -> Option<WorldExcerpt> {
     Some(output)
 }
 
-pub fn furnish_cooking_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
+/// Furnish a kitchen, around a hearth if there is room for one, or else a plain furnace.
+///
+/// The hood of the hearth reaches the ceiling, as cobblestone above the furnace in the
+/// returned excerpt, so that the chimney can be continued through the roof.
+pub fn furnish_cooking_area(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
     let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
 
     let (x, z) = room_shape.dimensions();
@@ -1877,21 +2573,24 @@ pub fn furnish_cooking_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
     let mut output = WorldExcerpt::new(x, y, z);
 
     // Fulfill cooking needs
-    place_table(&mut output, &mut placement_state_map);
-    place_cooking(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
-    place_shelf(&mut output, &mut placement_state_map);
-    place_decor(&mut output, &mut placement_state_map);
+    place_table(&mut output, &mut placement_state_map, rng);
+    if !place_hearth(&mut output, &mut placement_state_map) {
+        place_cooking(&mut output, &mut placement_state_map);
+    }
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_shelf(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
+    place_heating(&mut output, &mut placement_state_map);
     place_lighting(&mut output, &mut placement_state_map);
-    place_hygiene(&mut output, &mut placement_state_map);
-    place_decor(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
-    place_decor(&mut output, &mut placement_state_map);
+    place_hygiene(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
 
     Some(output)
 }
 
-pub fn furnish_cottage(room_shape: &RoomShape) -> Option<WorldExcerpt> {
+pub fn furnish_cottage(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
     let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
 
     let (x, z) = room_shape.dimensions();
@@ -1905,23 +2604,24 @@ pub fn furnish_cottage(room_shape: &RoomShape) -> Option<WorldExcerpt> {
 
     let mut output = WorldExcerpt::new(x, y, z);
 
-    place_single_sleep(&mut output, &mut placement_state_map);
+    place_single_sleep(&mut output, &mut placement_state_map, rng);
     place_cooking(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
-    place_hygiene(&mut output, &mut placement_state_map);
-    place_top_surface(&mut output, &mut placement_state_map);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_hygiene(&mut output, &mut placement_state_map, rng);
+    place_top_surface(&mut output, &mut placement_state_map, rng);
+    place_heating(&mut output, &mut placement_state_map);
     place_lighting(&mut output, &mut placement_state_map);
     // TODO Fulfill sitting need
-    place_store(&mut output, &mut placement_state_map);
-    place_decor(&mut output, &mut placement_state_map);
-    place_single_sleep(&mut output, &mut placement_state_map);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
+    place_single_sleep(&mut output, &mut placement_state_map, rng);
     // TODO Place some workstation? Crafting bench, loom, or other?
-    while place_decor(&mut output, &mut placement_state_map) {}
+    while place_decor(&mut output, &mut placement_state_map, rng) {}
 
     Some(output)
 }
 
-pub fn furnish_living_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
+pub fn furnish_living_area(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
     let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
 
     let (x, z) = room_shape.dimensions();
@@ -1936,18 +2636,19 @@ pub fn furnish_living_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
     let mut output = WorldExcerpt::new(x, y, z);
 
     // Fulfill living needs
-    place_top_surface(&mut output, &mut placement_state_map);
+    place_top_surface(&mut output, &mut placement_state_map, rng);
     // TODO Fulfill sitting need
-    place_top_surface(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
+    place_top_surface(&mut output, &mut placement_state_map, rng);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_heating(&mut output, &mut placement_state_map);
     place_lighting(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
-    while place_decor(&mut output, &mut placement_state_map) {}
+    place_store(&mut output, &mut placement_state_map, rng);
+    while place_decor(&mut output, &mut placement_state_map, rng) {}
 
     Some(output)
 }
 
-pub fn furnish_sleeping_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
+pub fn furnish_sleeping_area(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
     let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
 
     let (x, z) = room_shape.dimensions();
@@ -1962,19 +2663,131 @@ pub fn furnish_sleeping_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
     let mut output = WorldExcerpt::new(x, y, z);
 
     // Fulfill bedroom needs
-    place_single_sleep(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
-    place_top_surface(&mut output, &mut placement_state_map);
+    place_single_sleep(&mut output, &mut placement_state_map, rng);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_top_surface(&mut output, &mut placement_state_map, rng);
+    place_heating(&mut output, &mut placement_state_map);
     place_lighting(&mut output, &mut placement_state_map);
-    place_decor(&mut output, &mut placement_state_map);
-    place_single_sleep(&mut output, &mut placement_state_map);
+    place_decor(&mut output, &mut placement_state_map, rng);
+    place_single_sleep(&mut output, &mut placement_state_map, rng);
     // TODO FUlfill sitting need
     // TODO Maybe a desk and chair
 
     Some(output)
 }
 
-pub fn furnish_working_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
+/// Furnish a smithy, with a forge, an anvil, a grindstone and a smithing table.
+///
+/// The hood of the forge reaches the ceiling, as cobblestone in the top layer of the
+/// returned excerpt, so that the chimney can be continued through the roof.
+pub fn furnish_smithy(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
+    let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
+
+    let (x, z) = room_shape.dimensions();
+    if x == 0 || z == 0 {
+        // The room shape is empty, nothing to do here.
+        return None;
+    }
+
+    let y = room_shape.highest_ceiling()
+        .expect("We know the room shape is not empty, so we should have at least one height.");
+
+    let mut output = WorldExcerpt::new(x, y, z);
+
+    // Fulfill smithing needs
+    place_forge(&mut output, &mut placement_state_map);
+    place_workstation(&mut output, &mut placement_state_map, |direction| Block::Anvil {
+        facing: direction.rotated_90_cw(),
+        damage: mcprogedit::block::AnvilDamage::Intact,
+    });
+    place_workstation(&mut output, &mut placement_state_map, Block::grindstone);
+    place_workstation(&mut output, &mut placement_state_map, |_| Block::SmithingTable);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_lighting(&mut output, &mut placement_state_map);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
+
+    Some(output)
+}
+
+/// Furnish a bakery, with ovens, a counter for the bread and barrels of flour.
+///
+/// The hoods of the ovens reach the ceiling, as cobblestone above the furnaces in the
+/// returned excerpt, so that the chimneys can be continued through the roof.
+pub fn furnish_bakery(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
+    let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
+
+    let (x, z) = room_shape.dimensions();
+    if x == 0 || z == 0 {
+        // The room shape is empty, nothing to do here.
+        return None;
+    }
+
+    let y = room_shape.highest_ceiling()
+        .expect("We know the room shape is not empty, so we should have at least one height.");
+
+    let mut output = WorldExcerpt::new(x, y, z);
+
+    // Fulfill baking needs
+    if !place_hearth(&mut output, &mut placement_state_map) {
+        place_cooking(&mut output, &mut placement_state_map);
+    }
+    place_hearth(&mut output, &mut placement_state_map);
+    place_counter(&mut output, &mut placement_state_map, 3, Material::Spruce);
+    place_barrel(&mut output, &mut placement_state_map);
+    place_barrel(&mut output, &mut placement_state_map);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_heating(&mut output, &mut placement_state_map);
+    place_lighting(&mut output, &mut placement_state_map);
+    place_decor(&mut output, &mut placement_state_map, rng);
+
+    Some(output)
+}
+
+/// Furnish the shop of an apothecary, with brewing stands, cauldrons, a counter, shelves
+/// of potted herbs, and bundles of herbs hung up to dry.
+pub fn furnish_apothecary(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
+    const DRIED_HERBS_MAX: usize = 6;
+    const POTTED_HERBS_MAX: usize = 4;
+
+    let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
+
+    let (x, z) = room_shape.dimensions();
+    if x == 0 || z == 0 {
+        // The room shape is empty, nothing to do here.
+        return None;
+    }
+
+    let y = room_shape.highest_ceiling()
+        .expect("We know the room shape is not empty, so we should have at least one height.");
+
+    let mut output = WorldExcerpt::new(x, y, z);
+
+    // Fulfill brewing needs
+    place_workstation(&mut output, &mut placement_state_map, |_| Block::brewing_stand());
+    place_workstation(&mut output, &mut placement_state_map, |_| Block::Cauldron {
+        water_level: Int0Through3::new(3).unwrap(),
+    });
+    place_counter(&mut output, &mut placement_state_map, 3, Material::Spruce);
+    place_workstation(&mut output, &mut placement_state_map, |_| Block::brewing_stand());
+    place_shelf(&mut output, &mut placement_state_map, rng);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_lighting(&mut output, &mut placement_state_map);
+    for _ in 0..DRIED_HERBS_MAX {
+        if !place_dried_herbs(&mut output, &mut placement_state_map, rng) {
+            break;
+        }
+    }
+    for _ in 0..POTTED_HERBS_MAX {
+        if !place_potted_herb(&mut output, &mut placement_state_map, rng) {
+            break;
+        }
+    }
+
+    Some(output)
+}
+
+pub fn furnish_working_area(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
     let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
 
     let (x, z) = room_shape.dimensions();
@@ -1990,14 +2803,135 @@ pub fn furnish_working_area(room_shape: &RoomShape) -> Option<WorldExcerpt> {
 
     // Fulfill working needs
     // TODO Make different generators for different professions?
-    place_store(&mut output, &mut placement_state_map);
-    place_table(&mut output, &mut placement_state_map);
-    place_shelf(&mut output, &mut placement_state_map);
-    place_decor(&mut output, &mut placement_state_map);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_table(&mut output, &mut placement_state_map, rng);
+    place_shelf(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
+    place_lighting(&mut output, &mut placement_state_map);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
+
+    Some(output)
+}
+
+/// Furnish a church nave, with the altar at the `altar_side` end of the room,
+/// and pews facing it.
+pub fn furnish_nave(room_shape: &RoomShape, altar_side: Surface4, rng: &mut StdRng) -> Option<WorldExcerpt> {
+    let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
+
+    let (x, z) = room_shape.dimensions();
+    if x == 0 || z == 0 {
+        // The room shape is empty, nothing to do here.
+        return None;
+    }
+
+    let y = room_shape.highest_ceiling()
+        .expect("We know the room shape is not empty, so we should have at least one height.");
+
+    let mut output = WorldExcerpt::new(x, y, z);
+
+    // Fulfill worship needs
+    place_altar(&mut output, &mut placement_state_map, altar_side);
+    place_pews(&mut output, &mut placement_state_map, altar_side, rng);
+    place_lighting(&mut output, &mut placement_state_map);
+    place_decor(&mut output, &mut placement_state_map, rng);
+    place_decor(&mut output, &mut placement_state_map, rng);
+
+    Some(output)
+}
+
+/// Furnish the barracks of a guardhouse, with bunk beds, a weapon rack, a grindstone for
+/// keeping the blades sharp, and a mess table.
+pub fn furnish_barracks(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
+    let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
+
+    let (x, z) = room_shape.dimensions();
+    if x == 0 || z == 0 {
+        // The room shape is empty, nothing to do here.
+        return None;
+    }
+
+    let y = room_shape.highest_ceiling()
+        .expect("We know the room shape is not empty, so we should have at least one height.");
+
+    let mut output = WorldExcerpt::new(x, y, z);
+
+    // Fulfill the needs of the guards
+    place_bunk_bed(&mut output, &mut placement_state_map, rng);
+    place_weapon_rack(&mut output, &mut placement_state_map);
+    place_workstation(&mut output, &mut placement_state_map, Block::grindstone);
+    place_seated_table(&mut output, &mut placement_state_map, rng);
+    place_store(&mut output, &mut placement_state_map, rng);
+    place_lighting(&mut output, &mut placement_state_map);
+    while place_bunk_bed(&mut output, &mut placement_state_map, rng) {}
+    place_single_sleep(&mut output, &mut placement_state_map, rng);
+
+    Some(output)
+}
+
+/// Furnish a library, with bookshelves lining the walls, lecterns, and study desks with
+/// chairs.
+pub fn furnish_library(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
+    const BOOKSHELF_LENGTH_MAX: usize = 5;
+
+    let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
+
+    let (x, z) = room_shape.dimensions();
+    if x == 0 || z == 0 {
+        // The room shape is empty, nothing to do here.
+        return None;
+    }
+
+    let y = room_shape.highest_ceiling()
+        .expect("We know the room shape is not empty, so we should have at least one height.");
+
+    let mut output = WorldExcerpt::new(x, y, z);
+
+    // Fulfill reading and writing needs
+    place_seated_table(&mut output, &mut placement_state_map, rng);
+    place_workstation(&mut output, &mut placement_state_map, |direction| Block::Lectern {
+        facing: direction,
+        has_book: true,
+    });
+    while place_bookshelf_along_wall(&mut output, &mut placement_state_map, BOOKSHELF_LENGTH_MAX) {}
+    place_seated_table(&mut output, &mut placement_state_map, rng);
+    place_workstation(&mut output, &mut placement_state_map, |direction| Block::Lectern {
+        facing: direction,
+        has_book: true,
+    });
+    place_lighting(&mut output, &mut placement_state_map);
+    place_decor(&mut output, &mut placement_state_map, rng);
+
+    Some(output)
+}
+
+/// Furnish the taproom of a tavern, with a bar counter, barrels, seated tables and a jukebox.
+pub fn furnish_tavern(room_shape: &RoomShape, rng: &mut StdRng) -> Option<WorldExcerpt> {
+    let mut placement_state_map = interior_placement_state_map_from_room_shape(&room_shape);
+
+    let (x, z) = room_shape.dimensions();
+    if x == 0 || z == 0 {
+        // The room shape is empty, nothing to do here.
+        return None;
+    }
+
+    let y = room_shape.highest_ceiling()
+        .expect("We know the room shape is not empty, so we should have at least one height.");
+
+    let mut output = WorldExcerpt::new(x, y, z);
+
+    // Fulfill drinking and socialising needs
+    place_bar_counter(&mut output, &mut placement_state_map, rng);
+    place_barrel(&mut output, &mut placement_state_map);
+    place_barrel(&mut output, &mut placement_state_map);
+    place_jukebox(&mut output, &mut placement_state_map);
+    place_heating(&mut output, &mut placement_state_map);
+    place_lighting(&mut output, &mut placement_state_map);
+    while place_seated_table(&mut output, &mut placement_state_map, rng) {}
+    place_barrel(&mut output, &mut placement_state_map);
     place_lighting(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
-    place_store(&mut output, &mut placement_state_map);
-    place_decor(&mut output, &mut placement_state_map);
+    place_decor(&mut output, &mut placement_state_map, rng);
 
     Some(output)
 }
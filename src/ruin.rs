@@ -0,0 +1,128 @@
+//! Ruins of an abandoned settlement: Buildings fallen into decay, with holes in their roofs,
+//! cracked and mossy stonework, vines up the walls, rubble on the floors, and earth heaped
+//! up against the walls.
+
+use std::collections::HashSet;
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::block_palette;
+use crate::features::Features;
+use crate::generation_context::Building;
+use crate::geometry::{self, directions};
+use crate::weathering::{self, top_of};
+
+/// Most holes to open in the roof of a ruined building.
+const ROOF_HOLES_MAX: usize = 3;
+/// Largest distance from the middle of a hole in the roof to its edge.
+const ROOF_HOLE_RADIUS_MAX: i64 = 2;
+/// Chance for a piece of rubble to lie under each column of a hole in the roof.
+const RUBBLE_CHANCE: f64 = 0.5;
+/// Chance for each block of stone of a ruined building to be cracked or overgrown with moss.
+const WEATHERING_CHANCE: f64 = 0.4;
+/// Chance for each column of the outside walls to have vines growing down it.
+const VINE_CHANCE: f64 = 0.2;
+/// Chance for earth to be heaped up against each column of the outside walls.
+const BURIAL_CHANCE: f64 = 0.6;
+/// Highest earth may be heaped up against the walls.
+const BURIAL_HEIGHT_MAX: i64 = 2;
+
+/// Let the building fall into ruin: Open holes in its roof with the rubble lying below,
+/// put out its lights, crack and overgrow its stonework, let vines grow down its walls,
+/// and heap earth up against them, leaving the doorways clear.
+pub fn ruin_building(excerpt: &mut WorldExcerpt, building: &Building, features: &Features, rng: &mut StdRng) {
+    let (_, y_len, _) = excerpt.dim();
+    let footprint: HashSet<BlockColumnCoord> = building.footprint.iter().copied().collect();
+    let is_edge = |column: &BlockColumnCoord| {
+        directions().iter().any(|direction| !footprint.contains(&geometry::step(*column, *direction, 1)))
+    };
+    let (edge, inside): (Vec<BlockColumnCoord>, Vec<BlockColumnCoord>) =
+        building.footprint.iter().copied().partition(|column| is_edge(column));
+
+    // Holes in the roof, down through the ceiling of the top floor, with rubble on the floor below
+    let eaves_y = edge.iter().filter_map(|column| top_of(excerpt, *column)).min();
+    if let (Some(eaves_y), false) = (eaves_y, inside.is_empty()) {
+        for _ in 0..rng.gen_range(1..=ROOF_HOLES_MAX) {
+            let middle = inside[rng.gen_range(0..inside.len())];
+            let radius = rng.gen_range(0..=ROOF_HOLE_RADIUS_MAX);
+            for column in &inside {
+                if (column.0 - middle.0).abs().max((column.1 - middle.1).abs()) > radius {
+                    continue;
+                }
+                let top = match top_of(excerpt, *column) {
+                    Some(top) => top,
+                    None => continue,
+                };
+                for y in eaves_y..=top {
+                    excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::Air);
+                }
+                if rng.gen_bool(RUBBLE_CHANCE) {
+                    if let Some(floor) = top_of(excerpt, *column) {
+                        let rubble = match rng.gen_range(0..3) {
+                            0 => Block::Gravel,
+                            1 => Block::MossyCobblestone,
+                            _ => block_palette::weathered(&building.palette.wall, true)
+                                .unwrap_or_else(|| building.palette.wall.clone()),
+                        };
+                        excerpt.set_block_at(BlockCoord(column.0, floor + 1, column.1), rubble);
+                    }
+                }
+            }
+        }
+    }
+
+    // Lights put out, and stonework cracked and overgrown with moss
+    for column in &building.footprint {
+        for y in 0..y_len as i64 {
+            let coordinates = BlockCoord(column.0, y, column.1);
+            let block = match excerpt.block_at(coordinates) {
+                Some(block) => block.clone(),
+                None => continue,
+            };
+            if matches!(block, Block::Lantern { .. } | Block::Torch { .. } | Block::Campfire { .. }) {
+                excerpt.set_block_at(coordinates, Block::Air);
+            } else if rng.gen_bool(WEATHERING_CHANCE) {
+                if let Some(weathered) = block_palette::weathered(&block, rng.gen()) {
+                    excerpt.set_block_at(coordinates, weathered);
+                }
+            }
+        }
+    }
+
+    // Vines down the outside walls, and earth heaped up against them
+    weathering::hang_vines_on_walls(excerpt, building, VINE_CHANCE, rng);
+    let door_fronts: HashSet<BlockColumnCoord> = building.doors.iter()
+        .map(|door| {
+            let front = door.coordinates + geometry::offset(door.facing.opposite());
+            BlockColumnCoord(front.0, front.2)
+        })
+        .collect();
+    for column in &edge {
+        for direction in directions() {
+            let outside = geometry::step(*column, direction, 1);
+            if footprint.contains(&outside)
+                || door_fronts.contains(&outside)
+                || !rng.gen_bool(BURIAL_CHANCE)
+            {
+                continue;
+            }
+            let ground = match features.terrain_height_map.height_at((outside.0 as usize, outside.1 as usize)) {
+                Some(ground) => ground as i64,
+                None => continue,
+            };
+            let height = rng.gen_range(1..=BURIAL_HEIGHT_MAX);
+            for y in ground..ground + height {
+                let coordinates = BlockCoord(outside.0, y, outside.1);
+                if excerpt.block_at(coordinates) != Some(&Block::Air) {
+                    break;
+                }
+                let earth = if y == ground + height - 1 { Block::GrassBlock } else { Block::Dirt };
+                excerpt.set_block_at(coordinates, earth);
+            }
+        }
+    }
+}
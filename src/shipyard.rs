@@ -0,0 +1,202 @@
+//! Shipyard on a beach by the harbour: A slipway sloping down into the water, with the
+//! hull of a boat under construction resting on it, piles of logs beside it, and a shed
+//! for the shipwrights.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, Material, WoodMaterial};
+use mcprogedit::positioning::{Axis3, Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::features::Features;
+use crate::geometry;
+use crate::tree;
+
+/// Extent of the shipyard on land, along the shore: From the shed to the log piles.
+pub const SHIPYARD_ALONG: (i64, i64) = (-7, 4);
+/// Extent of the shipyard on land, inland from the water's edge.
+pub const SHIPYARD_DEPTH: i64 = 8;
+/// How far the slipway reaches out into the water.
+pub const SLIPWAY_REACH: i64 = 3;
+/// Half the width of the slipway.
+const SLIPWAY_HALF_WIDTH: i64 = 1;
+/// Length of the hull, from the stern at the water's edge.
+const HULL_LENGTH: i64 = 7;
+/// Length of the hull that is planked, from the stern; the rest is bare ribs.
+const HULL_PLANKED_LENGTH: i64 = 4;
+
+/// A shipyard on the shore.
+#[derive(Clone, Copy, Debug)]
+pub struct Shipyard {
+    /// Middle of the front of the shipyard, at the water's edge.
+    pub front_middle: BlockColumnCoord,
+    /// Y coordinate of the level ground of the shipyard.
+    pub floor_y: i64,
+    /// Y coordinate of the water surface, as the lowest air above the water.
+    pub water_y: i64,
+    /// Direction from the shore out towards the water.
+    pub seaward: Surface4,
+}
+
+impl Shipyard {
+    /// The columns of the shipyard on land.
+    pub fn footprint(&self) -> Vec<BlockColumnCoord> {
+        let mut footprint = Vec::new();
+        for across in 0..SHIPYARD_DEPTH {
+            for along in SHIPYARD_ALONG.0..=SHIPYARD_ALONG.1 {
+                footprint.push(self.column_at(along, across));
+            }
+        }
+        footprint
+    }
+
+    /// The columns of the slipway reaching out into the water.
+    pub fn slipway_reach(&self) -> Vec<BlockColumnCoord> {
+        let mut columns = Vec::new();
+        for across in -SLIPWAY_REACH..0 {
+            for along in -SLIPWAY_HALF_WIDTH..=SLIPWAY_HALF_WIDTH {
+                columns.push(self.column_at(along, across));
+            }
+        }
+        columns
+    }
+
+    /// The column `along` the shore and `across` inland from the middle of the front.
+    fn column_at(&self, along: i64, across: i64) -> BlockColumnCoord {
+        let along_offset = geometry::offset(self.seaward.rotated_90_cw());
+        let landward = geometry::offset(self.seaward.opposite());
+        BlockColumnCoord(
+            self.front_middle.0 + along_offset.0 * along + landward.0 * across,
+            self.front_middle.1 + along_offset.2 * along + landward.2 * across,
+        )
+    }
+
+    /// Height of the slipway surface, `across` inland from the water's edge, sloping
+    /// evenly from the back of the shipyard down to below the water at its far end.
+    fn slipway_y(&self, across: i64) -> i64 {
+        let (top, bottom) = (self.floor_y - 1, self.water_y - 2);
+        let length = SHIPYARD_DEPTH - 1 + SLIPWAY_REACH;
+        bottom + (top - bottom) * (across + SLIPWAY_REACH) / length
+    }
+}
+
+/// Build the shipyard: Level ground, a slipway of planks down into the water, the hull of
+/// a boat on a cradle on the slipway, planked at the stern and bare ribs towards the bow,
+/// piles of logs on one side and an open shed with tools and timber on the other.
+pub fn build_shipyard(excerpt: &mut WorldExcerpt, shipyard: &Shipyard, features: &Features, rng: &mut StdRng) {
+    let y = shipyard.floor_y;
+    let along_direction = shipyard.seaward.rotated_90_cw();
+    let landward = shipyard.seaward.opposite();
+    let at = |along: i64, across: i64, at_y: i64| {
+        let column = shipyard.column_at(along, across);
+        BlockCoord(column.0, at_y, column.1)
+    };
+    let fence = Block::Fence { material: FenceMaterial::Spruce, waterlogged: false };
+    let planks = Block::Planks { material: WoodMaterial::Spruce };
+    let log_inland = Block::oak_log(axis(landward));
+
+    // Level ground, cleared of vegetation
+    for column in shipyard.footprint() {
+        for clear_y in y..y + 5 {
+            tree::chop(excerpt, BlockCoord(column.0, clear_y, column.1));
+            excerpt.set_block_at(BlockCoord(column.0, clear_y, column.1), Block::Air);
+        }
+        let image::Luma([ground]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        for fill_y in ground as i64 - 1..y - 1 {
+            excerpt.set_block_at(BlockCoord(column.0, fill_y, column.1), Block::Dirt);
+        }
+        excerpt.set_block_at(BlockCoord(column.0, y - 1, column.1), Block::CoarseDirt);
+    }
+
+    // Slipway, sloping into the water on a bed of stone, with the ground above it cleared
+    for across in -SLIPWAY_REACH..SHIPYARD_DEPTH {
+        let slipway_y = shipyard.slipway_y(across);
+        for along in -SLIPWAY_HALF_WIDTH..=SLIPWAY_HALF_WIDTH {
+            let column = shipyard.column_at(along, across);
+            let image::Luma([ground]) = features.terrain[(column.0 as u32, column.1 as u32)];
+            for fill_y in (ground as i64 - 1).min(slipway_y)..slipway_y {
+                excerpt.set_block_at(at(along, across, fill_y), Block::Cobblestone);
+            }
+            excerpt.set_block_at(at(along, across, slipway_y), planks.clone());
+            for clear_y in slipway_y + 1..y {
+                let block = if clear_y < shipyard.water_y { Block::WaterSource } else { Block::Air };
+                excerpt.set_block_at(at(along, across, clear_y), block);
+            }
+        }
+    }
+
+    // Hull, level on a cradle of posts, keel first and the stern at the water's edge
+    let keel_y = shipyard.slipway_y(HULL_LENGTH - 1) + 2;
+    for across in 0..HULL_LENGTH {
+        for cradle_y in shipyard.slipway_y(across) + 1..keel_y {
+            excerpt.set_block_at(at(0, across, cradle_y), fence.clone());
+        }
+        excerpt.set_block_at(at(0, across, keel_y), log_inland.clone());
+
+        let is_planked = across < HULL_PLANKED_LENGTH;
+        let is_rib = across % 2 == 1;
+        if !is_planked && !is_rib {
+            continue;
+        }
+        for (along, side) in &[(1, along_direction), (-1, along_direction.opposite())] {
+            // The bottom curving up into the sides
+            excerpt.set_block_at(at(*along, across, keel_y), Block::stairs((*side).into(), Material::Spruce));
+            for up in 1..=2 {
+                let block = if is_planked { planks.clone() } else { fence.clone() };
+                excerpt.set_block_at(at(along * 2, across, keel_y + up), block);
+            }
+        }
+    }
+    // Transom at the stern
+    for along in -1..=1 {
+        excerpt.set_block_at(at(along, 0, keel_y + 1), planks.clone());
+    }
+
+    // Piles of logs, laid along the slipway
+    for along in 3..=4 {
+        for across in 1..=5 {
+            excerpt.set_block_at(at(along, across, y), log_inland.clone());
+            if along == 3 && (2..=4).contains(&across) {
+                excerpt.set_block_at(at(along, across, y + 1), log_inland.clone());
+            }
+        }
+    }
+
+    // Shed, open towards the slipway, with a slab roof
+    let (shed_along, shed_across) = ((-7, -3), (2, 6));
+    for along in shed_along.0..=shed_along.1 {
+        for across in shed_across.0..=shed_across.1 {
+            let is_corner = (along == shed_along.0 || along == shed_along.1)
+                && (across == shed_across.0 || across == shed_across.1);
+            let is_wall = along == shed_along.0 || across == shed_across.0 || across == shed_across.1;
+            for up in 0..3 {
+                let block = if is_corner {
+                    Block::oak_log(Axis3::Y)
+                } else if is_wall {
+                    planks.clone()
+                } else {
+                    continue;
+                };
+                excerpt.set_block_at(at(along, across, y + up), block);
+            }
+            excerpt.set_block_at(at(along, across, y + 3), Block::bottom_slab(Material::Spruce));
+        }
+    }
+    excerpt.set_block_at(at(shed_along.0 + 1, shed_across.1 - 1, y), Block::chest(along_direction));
+    excerpt.set_block_at(at(shed_along.0 + 1, shed_across.0 + 1, y), Block::barrel(Surface6::Up));
+    for across in shed_across.0 + 2..shed_across.1 - 1 {
+        if rng.gen_bool(0.75) {
+            excerpt.set_block_at(at(shed_along.0 + 1, across, y), planks.clone());
+        }
+    }
+}
+
+/// The axis of a log lying in the given direction.
+fn axis(direction: Surface4) -> Axis3 {
+    match direction {
+        Surface4::North | Surface4::South => Axis3::Z,
+        Surface4::East | Surface4::West => Axis3::X,
+    }
+}
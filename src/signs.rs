@@ -0,0 +1,370 @@
+//! House numbers by the doors, and street name signs at the street corners.
+
+use image::GrayImage;
+use mcprogedit::block::{Block, Sign, WallOrRotatedOnFloor};
+use mcprogedit::colour::Colour;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::SignMaterial;
+use mcprogedit::positioning::{Direction16, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::generation_context::Building;
+use crate::geometry::{offset, point_position_relative_to_line, LeftRightSide};
+use crate::pathfinding::RoadPath;
+use crate::structure_builder::ExteriorDoor;
+
+use log::info;
+
+/// Number of characters that comfortably fit on one line of a sign.
+const SIGN_LINE_LENGTH: usize = 15;
+
+/// How far from a street a door may be, for the house to get an address on that street.
+const MAX_ADDRESS_DISTANCE: f32 = 12.0;
+
+/// Distance from a street corner, along the street, to its street name sign.
+const CORNER_SIGN_SETBACK: f32 = 3.0;
+
+/// The address of a building.
+#[derive(Clone, Debug)]
+pub struct Address {
+    pub street: String,
+    pub number: usize,
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.street, self.number)
+    }
+}
+
+/// Where a point is, relative to a street.
+#[derive(Clone, Copy, Debug)]
+pub struct StreetPosition {
+    /// Shortest distance from the point to the street.
+    pub distance: f32,
+    /// Distance along the street, from its start to the point closest to the given point.
+    pub distance_along: f32,
+    /// Which side of the street the point is on, seen from the start of the street.
+    pub side: LeftRightSide,
+}
+
+/// Find the position of `point` relative to `street`.
+pub fn position_along_street(street: &RoadPath, point: BlockColumnCoord) -> Option<StreetPosition> {
+    let mut best: Option<StreetPosition> = None;
+    let mut length_so_far = 0.0;
+
+    for segment in street.windows(2) {
+        let start: BlockColumnCoord = segment[0].coordinates.into();
+        let end: BlockColumnCoord = segment[1].coordinates.into();
+
+        let (dx, dz) = ((end.0 - start.0) as f32, (end.1 - start.1) as f32);
+        let (px, pz) = ((point.0 - start.0) as f32, (point.1 - start.1) as f32);
+        let segment_length = (dx * dx + dz * dz).sqrt();
+
+        let t = if segment_length > 0.0 {
+            ((px * dx + pz * dz) / (segment_length * segment_length)).max(0.0).min(1.0)
+        } else {
+            0.0
+        };
+        let (cx, cz) = (px - t * dx, pz - t * dz);
+        let distance = (cx * cx + cz * cz).sqrt();
+
+        if best.map_or(true, |best| distance < best.distance) {
+            best = Some(StreetPosition {
+                distance,
+                distance_along: length_so_far + t * segment_length,
+                side: point_position_relative_to_line(point, (start, end)),
+            });
+        }
+
+        length_so_far += segment_length;
+    }
+
+    best
+}
+
+/// Give house numbers to the houses at the given positions along a single street.
+///
+/// Numbers increase along the street, with odd numbers on the left hand side
+/// and even numbers on the right hand side.
+pub fn house_numbers(positions: &[StreetPosition]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    order.sort_by(|a, b| {
+        positions[*a].distance_along
+            .partial_cmp(&positions[*b].distance_along)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut numbers = vec![0; positions.len()];
+    let mut next_odd = 1;
+    let mut next_even = 2;
+    for index in order {
+        match positions[index].side {
+            LeftRightSide::Left | LeftRightSide::On => {
+                numbers[index] = next_odd;
+                next_odd += 2;
+            }
+            LeftRightSide::Right => {
+                numbers[index] = next_even;
+                next_even += 2;
+            }
+        }
+    }
+    numbers
+}
+
+/// Put a sign with the address on the wall next to the given door.
+/// Returns true if there was room for the sign.
+pub fn place_house_sign(excerpt: &mut WorldExcerpt, door: &ExteriorDoor, address: &Address) -> bool {
+    let street_direction = door.facing.opposite();
+
+    for along_wall in &[door.facing.rotated_90_cw(), door.facing.rotated_90_ccw()] {
+        let wall = door.coordinates + offset(*along_wall) + BlockCoord(0, 1, 0);
+        let sign_coordinates = wall + offset(street_direction);
+
+        if excerpt.block_at(sign_coordinates) != Some(&Block::Air) {
+            continue;
+        }
+        match excerpt.block_at(wall) {
+            Some(Block::Air) | Some(Block::None) | None => continue,
+            _ => (),
+        }
+
+        excerpt.set_block_at(
+            sign_coordinates,
+            sign(WallOrRotatedOnFloor::Wall(door.facing), &address_lines(address)),
+        );
+        return true;
+    }
+
+    false
+}
+
+/// Put up signs with the street name at both ends of the street.
+pub fn place_street_name_signs(
+    excerpt: &mut WorldExcerpt,
+    street: &RoadPath,
+    name: &str,
+    street_width: i64,
+    height_map: &GrayImage,
+) {
+    if street.len() < 2 {
+        return;
+    }
+
+    let ends = [
+        (&street[0], &street[1]),
+        (&street[street.len() - 1], &street[street.len() - 2]),
+    ];
+
+    for (corner, next) in &ends {
+        let (dx, dz) = (
+            (next.coordinates.0 - corner.coordinates.0) as f32,
+            (next.coordinates.2 - corner.coordinates.2) as f32,
+        );
+        let length = (dx * dx + dz * dz).sqrt();
+        if length == 0.0 {
+            continue;
+        }
+        let (dx, dz) = (dx / length, dz / length);
+
+        // Step into the street, and off to its (right hand) side.
+        let side_distance = (street_width / 2 + 1) as f32;
+        let setback = CORNER_SIGN_SETBACK.min(length);
+        let x = (corner.coordinates.0 as f32 + dx * setback - dz * side_distance).round() as i64;
+        let z = (corner.coordinates.2 as f32 + dz * setback + dx * side_distance).round() as i64;
+
+        let (x_len, _, z_len) = excerpt.dim();
+        if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 {
+            continue;
+        }
+        let image::Luma([y]) = height_map[(x as u32, z as u32)];
+        let coordinates = BlockCoord(x, y as i64, z);
+
+        // Stand the sign on solid ground, facing the street.
+        if excerpt.block_at(coordinates) != Some(&Block::Air) {
+            continue;
+        }
+        match excerpt.block_at(coordinates - BlockCoord(0, 1, 0)) {
+            Some(Block::Air) | Some(Block::None) | Some(Block::WaterSource) | Some(Block::Water { .. }) | None => {
+                continue
+            }
+            _ => (),
+        }
+        let facing = direction_16(surface_from_vector(dz, -dx));
+        excerpt.set_block_at(coordinates, sign(WallOrRotatedOnFloor::Floor(facing), &name_lines(name)));
+    }
+}
+
+/// Give the buildings addresses on the named roads, given with their names and widths,
+/// numbering the houses along each road, and put up the house numbers by the doors and
+/// the road names at the corners.
+pub fn put_up_signs(
+    excerpt: &mut WorldExcerpt,
+    buildings: &mut [Building],
+    named_roads: &[(&RoadPath, &String, i64)],
+    height_map: &GrayImage,
+) {
+    // Find the street each building faces, and where along the street it is.
+    let mut buildings_along_road: Vec<Vec<(usize, StreetPosition)>> = vec![Vec::new(); named_roads.len()];
+    for (building_index, building) in buildings.iter().enumerate() {
+        let door = match building.doors.first() {
+            Some(door) => door,
+            None => continue,
+        };
+        let door_column: BlockColumnCoord = door.coordinates.into();
+
+        let closest = named_roads.iter()
+            .enumerate()
+            .filter_map(|(road_index, (road, _, _))| {
+                position_along_street(road, door_column.clone())
+                    .map(|position| (road_index, position))
+            })
+            .filter(|(_, position)| position.distance <= MAX_ADDRESS_DISTANCE)
+            .min_by(|(_, a), (_, b)| {
+                a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal)
+            });
+        if let Some((road_index, position)) = closest {
+            buildings_along_road[road_index].push((building_index, position));
+        }
+    }
+
+    // Number the houses, and put up a sign by each door.
+    for ((_, name, _), along_road) in named_roads.iter().zip(buildings_along_road.iter()) {
+        let positions: Vec<StreetPosition> = along_road.iter().map(|(_, position)| *position).collect();
+        let numbers = house_numbers(&positions);
+
+        for ((building_index, _), number) in along_road.iter().zip(numbers) {
+            let building = &mut buildings[*building_index];
+            let address = Address { street: name.to_string(), number };
+            if let Some(door) = building.doors.first() {
+                if !place_house_sign(excerpt, door, &address) {
+                    info!("No room for a sign at {}.", address);
+                }
+            }
+            building.address = Some(address);
+        }
+    }
+
+    // Street name signs at the corners
+    for (road, name, width) in named_roads {
+        place_street_name_signs(excerpt, road, name, *width, height_map);
+    }
+}
+
+fn address_lines(address: &Address) -> Vec<String> {
+    let full = address.to_string();
+    if full.chars().count() <= SIGN_LINE_LENGTH {
+        vec![String::new(), full]
+    } else {
+        vec![String::new(), address.street.clone(), address.number.to_string()]
+    }
+}
+
+fn name_lines(name: &str) -> Vec<String> {
+    vec![String::new(), name.to_string()]
+}
+
+/// A sign with the given lines of text.
+pub fn sign(placement: WallOrRotatedOnFloor, lines: &[String]) -> Block {
+    let line = |index: usize| lines.get(index).cloned().unwrap_or_default();
+    Block::Sign(Box::new(Sign {
+        material: SignMaterial::Oak,
+        placement,
+        waterlogged: false,
+        colour: Colour::Black,
+        text1: line(0),
+        text2: line(1),
+        text3: line(2),
+        text4: line(3),
+    }))
+}
+
+/// The cardinal direction closest to the direction of the vector (dx, dz).
+pub fn surface_from_vector(dx: f32, dz: f32) -> Surface4 {
+    if dx.abs() > dz.abs() {
+        if dx > 0.0 { Surface4::East } else { Surface4::West }
+    } else if dz > 0.0 {
+        Surface4::South
+    } else {
+        Surface4::North
+    }
+}
+
+/// The rotation of a standing sign facing `direction`.
+pub fn direction_16(direction: Surface4) -> Direction16 {
+    match direction {
+        Surface4::North => Direction16::North,
+        Surface4::East => Direction16::East,
+        Surface4::South => Direction16::South,
+        Surface4::West => Direction16::West,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pathfinding::{RoadNode, RoadNodeKind};
+
+    fn street(corners: &[(i64, i64)]) -> RoadPath {
+        corners.iter()
+            .map(|(x, z)| RoadNode { coordinates: BlockCoord(*x, 0, *z), kind: RoadNodeKind::Ground })
+            .collect()
+    }
+
+    fn position(distance_along: f32, side: LeftRightSide) -> StreetPosition {
+        StreetPosition { distance: 1.0, distance_along, side }
+    }
+
+    #[test]
+    fn position_left_of_straight_street() {
+        let position = position_along_street(&street(&[(0, 0), (10, 0)]), BlockColumnCoord(4, 3)).unwrap();
+        assert_eq!(3.0, position.distance);
+        assert_eq!(4.0, position.distance_along);
+        assert_eq!(LeftRightSide::Left, position.side);
+    }
+
+    #[test]
+    fn position_right_of_straight_street() {
+        let position = position_along_street(&street(&[(0, 0), (10, 0)]), BlockColumnCoord(4, -2)).unwrap();
+        assert_eq!(2.0, position.distance);
+        assert_eq!(4.0, position.distance_along);
+        assert_eq!(LeftRightSide::Right, position.side);
+    }
+
+    #[test]
+    fn position_along_bending_street() {
+        let position = position_along_street(&street(&[(0, 0), (10, 0), (10, 10)]), BlockColumnCoord(12, 6)).unwrap();
+        assert_eq!(2.0, position.distance);
+        assert_eq!(16.0, position.distance_along);
+        assert_eq!(LeftRightSide::Right, position.side);
+    }
+
+    #[test]
+    fn position_along_street_without_segments() {
+        assert!(position_along_street(&street(&[(0, 0)]), BlockColumnCoord(1, 1)).is_none());
+    }
+
+    #[test]
+    fn house_numbers_odd_left_even_right() {
+        let positions = [
+            position(5.0, LeftRightSide::Left),
+            position(1.0, LeftRightSide::Right),
+            position(3.0, LeftRightSide::Left),
+            position(2.0, LeftRightSide::Right),
+        ];
+        assert_eq!(vec![3, 2, 1, 4], house_numbers(&positions));
+    }
+
+    #[test]
+    fn house_numbers_on_the_street_are_odd() {
+        let positions = [position(2.0, LeftRightSide::On), position(1.0, LeftRightSide::Left)];
+        assert_eq!(vec![3, 1], house_numbers(&positions));
+    }
+
+    #[test]
+    fn house_numbers_without_houses() {
+        assert!(house_numbers(&[]).is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+//! Pipeline stages for settlement generation.
+//!
+//! Generation is driven through an ordered list of stages, each of which runs
+//! on the shared `GenerationContext`. Custom stages can be inserted anywhere in
+//! the list, e.g. for building a landmark after the plots are decided.
+
+use crate::generation_context::GenerationContext;
+
+/// One step of the settlement generation pipeline.
+///
+/// Stages are `Send`, so that a generator can be moved to another thread.
+pub trait Stage: Send {
+    /// Name of the stage, used for logging and for finding the stage in a list.
+    fn name(&self) -> &str;
+
+    /// Run the stage, reading from and adding to the context.
+    fn run(&mut self, context: &mut GenerationContext);
+}
+
+/// A stage made from a plain function.
+pub struct FnStage {
+    name: String,
+    function: fn(&mut GenerationContext),
+}
+
+impl FnStage {
+    pub fn new(name: &str, function: fn(&mut GenerationContext)) -> Self {
+        Self { name: name.to_string(), function }
+    }
+}
+
+impl Stage for FnStage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&mut self, context: &mut GenerationContext) {
+        (self.function)(context)
+    }
+}
+
+/// The built-in stages, in the order they must run.
+pub fn default_stages() -> Vec<Box<dyn Stage>> {
+    vec![
+        // Initial information extraction
+        Box::new(FnStage::new("extract_features", GenerationContext::extract_features)),
+        Box::new(FnStage::new("find_areas", GenerationContext::find_areas)),
+        Box::new(FnStage::new("find_villages", GenerationContext::find_villages)),
+
+        // Decide on area usage
+        //
+        // Some thoughts:
+        // - Fields on fertile, reasonably flat, open land
+        // - Wind mills on hills within or by fertile land
+        // - Fields closer to wind mills are predominantly wheat fields
+        // - Livestock on fertile, flat to half-steep, open to semi-open land
+        // - Forestry on forested land
+        // - Mining on exposed rock, either surface (quarry) or hillside (mining tunnel)
+        // - Fishing on shorelines with access to sea
+        // - Infrastructure: Maybe connect "traversable" areas through bridges, tunnels, etc?
+        // - Town is complicated. Can to some extent displace fields/livestock/forest
+        //
+        // TODO FUTURE WORK
+        // - Put major roads from primary sectors to town circumference
+        // - Connect primary sector areas to the road network between towns as well
+        Box::new(FnStage::new("locate_town", GenerationContext::locate_town)),
+        Box::new(FnStage::new("plan_ponds", GenerationContext::plan_ponds)),
+        Box::new(FnStage::new("plan_waterways", GenerationContext::plan_waterways)),
+        Box::new(FnStage::new("plan_roads", GenerationContext::plan_roads)),
+        Box::new(FnStage::new("plan_land_usage", GenerationContext::plan_land_usage)),
+        Box::new(FnStage::new("name_streets", GenerationContext::name_streets)),
+        Box::new(FnStage::new("plan_farms", GenerationContext::plan_farms)),
+        Box::new(FnStage::new("plan_windmill", GenerationContext::plan_windmill)),
+        Box::new(FnStage::new("plan_harbor", GenerationContext::plan_harbor)),
+        Box::new(FnStage::new("plan_lighthouse", GenerationContext::plan_lighthouse)),
+        Box::new(FnStage::new("plan_mine", GenerationContext::plan_mine)),
+        Box::new(FnStage::new("plan_lumber_camp", GenerationContext::plan_lumber_camp)),
+        Box::new(FnStage::new("plan_pastures", GenerationContext::plan_pastures)),
+        Box::new(FnStage::new("plan_viewpoints", GenerationContext::plan_viewpoints)),
+        Box::new(FnStage::new("plan_cliff_dwellings", GenerationContext::plan_cliff_dwellings)),
+        Box::new(FnStage::new("plan_signal_chains", GenerationContext::plan_signal_chains)),
+        Box::new(FnStage::new("plan_zoning", GenerationContext::plan_zoning)),
+        Box::new(FnStage::new("plan_amenities", GenerationContext::plan_amenities)),
+        Box::new(FnStage::new("plan_row_houses", GenerationContext::plan_row_houses)),
+        Box::new(FnStage::new("plan_nuisance_industries", GenerationContext::plan_nuisance_industries)),
+        Box::new(FnStage::new("plan_toll_stations", GenerationContext::plan_toll_stations)),
+
+        // Find local materials
+        Box::new(FnStage::new("survey_materials", GenerationContext::survey_materials)),
+
+        // Build structures
+        Box::new(FnStage::new("clear_pomerium", GenerationContext::clear_pomerium)),
+        Box::new(FnStage::new("build_wall", GenerationContext::build_wall)),
+        Box::new(FnStage::new("build_ponds", GenerationContext::build_ponds)),
+        Box::new(FnStage::new("build_waterways", GenerationContext::build_waterways)),
+        Box::new(FnStage::new("build_roads", GenerationContext::build_roads)),
+        Box::new(FnStage::new("build_farms", GenerationContext::build_farms)),
+        Box::new(FnStage::new("build_pastures", GenerationContext::build_pastures)),
+        Box::new(FnStage::new("build_windmill", GenerationContext::build_windmill)),
+        Box::new(FnStage::new("build_harbor", GenerationContext::build_harbor)),
+        Box::new(FnStage::new("build_lighthouse", GenerationContext::build_lighthouse)),
+        Box::new(FnStage::new("build_mine", GenerationContext::build_mine)),
+        Box::new(FnStage::new("build_lumber_camp", GenerationContext::build_lumber_camp)),
+        Box::new(FnStage::new("build_viewpoints", GenerationContext::build_viewpoints)),
+        Box::new(FnStage::new("build_nuisance_industries", GenerationContext::build_nuisance_industries)),
+        Box::new(FnStage::new("build_toll_stations", GenerationContext::build_toll_stations)),
+        Box::new(FnStage::new("place_waymarks", GenerationContext::place_waymarks)),
+        Box::new(FnStage::new("build_plaza", GenerationContext::build_plaza)),
+        Box::new(FnStage::new("build_landmark", GenerationContext::build_landmark)),
+        Box::new(FnStage::new("build_training_yard", GenerationContext::build_training_yard)),
+        Box::new(FnStage::new("build_plots", GenerationContext::build_plots)),
+        Box::new(FnStage::new("upgrade_villages", GenerationContext::upgrade_villages)),
+        Box::new(FnStage::new("build_cliff_dwellings", GenerationContext::build_cliff_dwellings)),
+        Box::new(FnStage::new("build_covered_market", GenerationContext::build_covered_market)),
+        Box::new(FnStage::new("build_enclosed_walkways", GenerationContext::build_enclosed_walkways)),
+        Box::new(FnStage::new("build_bathhouse", GenerationContext::build_bathhouse)),
+        Box::new(FnStage::new("build_wells", GenerationContext::build_wells)),
+        Box::new(FnStage::new("build_graveyards", GenerationContext::build_graveyards)),
+        Box::new(FnStage::new("ruin_buildings", GenerationContext::ruin_buildings)),
+        Box::new(FnStage::new("place_pets", GenerationContext::place_pets)),
+        Box::new(FnStage::new("build_guard_rails", GenerationContext::build_guard_rails)),
+        Box::new(FnStage::new("place_street_furniture", GenerationContext::place_street_furniture)),
+        Box::new(FnStage::new("place_signs", GenerationContext::place_signs)),
+        Box::new(FnStage::new("clear_snow_from_streets", GenerationContext::clear_snow_from_streets)),
+        Box::new(FnStage::new("build_wall_crowning", GenerationContext::build_wall_crowning)),
+        Box::new(FnStage::new("weather_town", GenerationContext::weather_town)),
+        Box::new(FnStage::new("light_for_night", GenerationContext::light_for_night)),
+        Box::new(FnStage::new("audit_lighting", GenerationContext::audit_lighting)),
+        Box::new(FnStage::new("hide_treasures", GenerationContext::hide_treasures)),
+        Box::new(FnStage::new("enforce_block_whitelist", GenerationContext::enforce_block_whitelist)),
+    ]
+}
@@ -0,0 +1,171 @@
+//! Street furniture: Lamp posts along the streets, benches by the street corners,
+//! and signposts at the street corners with the name of the district the street leads into.
+
+use mcprogedit::block::{Block, WallOrRotatedOnFloor};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, Material};
+use mcprogedit::positioning::Surface2;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::pathfinding::RoadPath;
+use crate::road::RoadStyle;
+use crate::signs;
+use crate::types::Snake;
+use crate::waymarks::{self, RoadPoint};
+
+/// Distance from a street corner, along the street, to its benches.
+const BENCH_SETBACK: f32 = 5.0;
+/// Number of benches by each street corner, one after the other along the street.
+const BENCH_COUNT: usize = 2;
+/// Distance from a street corner, along the street, to its district signpost.
+const SIGNPOST_SETBACK: f32 = 3.0;
+/// Closest street furniture may be to an entrance, so as not to block the way in.
+const ENTRANCE_CLEARANCE: usize = 2;
+/// Height of the fence post of a lamp post, up to the lantern on top.
+pub const LAMP_POST_HEIGHT: i64 = 3;
+
+/// Furnish the street with lamp posts along it, if the street style is lit, and with
+/// benches and a signpost with the name of the district the street leads into at each
+/// end, keeping clear of the `entrances` along the street. The lamp posts alternate
+/// between the sides of the street. Returns the number of pieces placed.
+pub fn place_street_furniture(
+    excerpt: &mut WorldExcerpt,
+    street: &RoadPath,
+    style: &RoadStyle,
+    features: &Features,
+    districts: &[Snake],
+    district_names: &[String],
+    entrances: &[BlockColumnCoord],
+) -> usize {
+    if street.len() < 2 {
+        return 0;
+    }
+    let side_distance = (style.width / 2 + 1) as f32;
+    let mut count = 0;
+
+    // Lamp posts, alternating between the right and left hand sides of the street
+    if let Some(lamp_post_spacing) = style.lighting_interval {
+        let length = waymarks::road_length(street);
+        let mut distance = lamp_post_spacing / 2.0;
+        let mut side = 1.0;
+        while distance < length {
+            if let Some(point) = waymarks::point_along(street, distance) {
+                if let Some(ground) = waymarks::roadside_ground(features, &point, side * side_distance) {
+                    if place_lamp_post(excerpt, ground, entrances) {
+                        count += 1;
+                    }
+                }
+            }
+            side = -side;
+            distance += lamp_post_spacing;
+        }
+    }
+
+    // Benches and signposts on the left hand side, seen from each corner, leaving the
+    // right hand side to the street name signs.
+    let reversed: RoadPath = street.iter().rev().copied().collect();
+    for from_corner in &[street, &reversed] {
+        if let Some(point) = waymarks::point_along(from_corner, SIGNPOST_SETBACK) {
+            if let Some(ground) = waymarks::roadside_ground(features, &point, -side_distance) {
+                let column = BlockColumnCoord(point.x.round() as i64, point.z.round() as i64);
+                let district_name = districts.iter()
+                    .zip(district_names)
+                    .find(|(district, _)| {
+                        InOutSide::Inside == geometry::point_position_relative_to_polygon(column, district)
+                    })
+                    .map(|(_, name)| name);
+                if let Some(name) = district_name {
+                    if place_signpost(excerpt, ground, &point, name, entrances) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        for bench in 0..BENCH_COUNT {
+            if let Some(point) = waymarks::point_along(from_corner, BENCH_SETBACK + bench as f32) {
+                if let Some(ground) = waymarks::roadside_ground(features, &point, -side_distance) {
+                    if place_bench(excerpt, ground, &point, entrances) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    count
+}
+
+/// Whether there is room for something `height` blocks tall at `coordinates`:
+/// Open air, on solid ground, away from the entrances.
+fn has_room(excerpt: &WorldExcerpt, coordinates: BlockCoord, height: i64, entrances: &[BlockColumnCoord]) -> bool {
+    let column: BlockColumnCoord = coordinates.into();
+    if entrances.iter().any(|entrance| geometry::manhattan_distance(*entrance, column) <= ENTRANCE_CLEARANCE) {
+        return false;
+    }
+    match excerpt.block_at(coordinates - BlockCoord(0, 1, 0)) {
+        Some(Block::Air) | Some(Block::None) | Some(Block::WaterSource) | Some(Block::Water { .. }) | None => {
+            return false
+        }
+        _ => (),
+    }
+    (0..height).all(|y| excerpt.block_at(coordinates + BlockCoord(0, y, 0)) == Some(&Block::Air))
+}
+
+/// Put up a lamp post: A lantern on top of a fence post.
+pub fn place_lamp_post(excerpt: &mut WorldExcerpt, coordinates: BlockCoord, entrances: &[BlockColumnCoord]) -> bool {
+    if !has_room(excerpt, coordinates, LAMP_POST_HEIGHT + 1, entrances) {
+        return false;
+    }
+    for y in 0..LAMP_POST_HEIGHT {
+        excerpt.set_block_at(
+            coordinates + BlockCoord(0, y, 0),
+            Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+        );
+    }
+    excerpt.set_block_at(
+        coordinates + BlockCoord(0, LAMP_POST_HEIGHT, 0),
+        Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+    );
+    true
+}
+
+/// Put a bench on the left hand side of the road, facing the road.
+fn place_bench(
+    excerpt: &mut WorldExcerpt,
+    coordinates: BlockCoord,
+    point: &RoadPoint,
+    entrances: &[BlockColumnCoord],
+) -> bool {
+    if !has_room(excerpt, coordinates, 2, entrances) {
+        return false;
+    }
+    // The back rest away from the road
+    let (dx, dz) = point.direction;
+    let away_from_road = signs::surface_from_vector(dz, -dx);
+    excerpt.set_block_at(coordinates, Block::stairs(away_from_road.into(), Material::Spruce));
+    true
+}
+
+/// Put up a signpost on the left hand side of the road, with the name of the district.
+fn place_signpost(
+    excerpt: &mut WorldExcerpt,
+    coordinates: BlockCoord,
+    point: &RoadPoint,
+    district_name: &str,
+    entrances: &[BlockColumnCoord],
+) -> bool {
+    if !has_room(excerpt, coordinates, 2, entrances) {
+        return false;
+    }
+    let (dx, dz) = point.direction;
+    let facing = signs::direction_16(signs::surface_from_vector(-dz, dx));
+    excerpt.set_block_at(coordinates, Block::Fence { material: FenceMaterial::Spruce, waterlogged: false });
+    excerpt.set_block_at(
+        coordinates + BlockCoord(0, 1, 0),
+        signs::sign(WallOrRotatedOnFloor::Floor(facing), &[String::new(), district_name.to_string()]),
+    );
+    true
+}
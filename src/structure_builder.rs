@@ -1,19 +1,26 @@
-use crate::block_palette::BlockPalette;
+use crate::block_palette::{self, BiomeStyle, BlockPalette, RidgeOrientation};
 use crate::build_area::BuildArea;
+use crate::embellishment::{self, Embellishment, HouseShell};
+use crate::foundation;
 use crate::geometry;
 use crate::geometry::{LeftRightSide, point_position_relative_to_line, RawEdge2d};
 use crate::line::{line, narrow_line};
+use crate::plot::{Plot, PlotEdgeKind};
 use crate::room_interior::{ColumnKind, neighbourhood_4, RoomShape};
 use crate::room_interior;
+use crate::signs;
 
 use log::{trace, warn};
+use rand::rngs::StdRng;
+use rand::Rng;
 use mcprogedit::block::{Block, Flower};
 use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
-use mcprogedit::positioning::{Surface4, Surface5};
+use mcprogedit::material::{FenceMaterial, Material};
+use mcprogedit::positioning::{Surface4, Surface5, Surface6};
 use mcprogedit::world_excerpt::WorldExcerpt;
 
 use std::cmp::{max, min};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
 pub fn _build_rock(
     excerpt: &WorldExcerpt,
@@ -47,11 +54,188 @@ pub fn _build_rock(
     Some(output)
 }
 
+/// Per-house choices, beyond the materials given by the block palette.
+#[derive(Clone, Debug)]
+pub struct HouseOptions {
+    /// Façade embellishment to add after the base shell, if there is room for it.
+    pub embellishment: Option<Embellishment>,
+    /// Smallest interior area (in m²) worth building a house for.
+    pub interior_area_min: usize,
+    /// Largest interior area (in m²) to build a single house for.
+    pub interior_area_max: usize,
+    /// Height of each story, from floor to ceiling, including the floor block.
+    pub story_height: usize,
+    /// Most stories to stack above the highest entrance.
+    pub stories_max: usize,
+    /// Tallest the house may be, in blocks, from its lowest floor to the eaves. 0 means no limit.
+    pub height_max: usize,
+    /// What the house is used for, deciding its rooms.
+    pub purpose: HousePurpose,
+    /// Direction towards the midday sun. Walls facing it get more windows, and walls facing
+    /// away from it fewer.
+    pub sunward: Surface4,
+    /// Whether to let the roof overhang the walls, and add dormers to gable roofs.
+    pub roof_details: bool,
+    /// Shape of the roof. If not given, one is chosen to suit the shape of the house.
+    pub roof_style: Option<RoofStyle>,
+    /// Whether to raise the house on stilts down to the ground, instead of on walls of
+    /// foundation, as over the swamp and shallow water of a stilt village.
+    pub stilts: bool,
+    /// Lowest height for the ground floor, in the excerpt, for keeping it above flood water.
+    /// Entrances below it are raised, with steps up from the street. 0 means no limit.
+    pub floor_y_min: usize,
+}
+
+/// Shape of the roof of a house.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoofStyle {
+    /// Gable roof, sloping down from the ridge towards two sides of the house.
+    Gable,
+    /// Roof sloping down towards every side of the house.
+    Hipped,
+    /// Flat roof terrace, with a fence along its edge.
+    FlatTerrace,
+}
+
+/// What a house is used for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HousePurpose {
+    /// A home, with kitchen, living room and bedrooms.
+    Dwelling,
+    /// A taproom on the ground floor, and beds for guests upstairs.
+    Tavern,
+    /// A smithy on the ground floor, and a home upstairs.
+    Workshop,
+    /// A bakery on the ground floor, and a home upstairs.
+    Bakery,
+    /// A school and scriptorium, with a library on every floor.
+    School,
+    /// An apothecary on the ground floor, with an herb garden in the yard, and a home upstairs.
+    Apothecary,
+}
+
+impl Default for HouseOptions {
+    fn default() -> Self {
+        Self {
+            embellishment: None,
+            interior_area_min: 9,
+            interior_area_max: 100,
+            story_height: 3,
+            stories_max: 1,
+            height_max: 0,
+            purpose: HousePurpose::Dwelling,
+            sunward: Surface4::South,
+            roof_details: false,
+            roof_style: None,
+            stilts: false,
+            floor_y_min: 0,
+        }
+    }
+}
+
+impl HouseOptions {
+    /// Keep the house no taller than `height_cap`, under a flat roof terrace.
+    pub fn cap_height(&mut self, height_cap: usize) {
+        if self.height_max == 0 || height_cap < self.height_max {
+            self.height_max = height_cap;
+        }
+        self.roof_style = Some(RoofStyle::FlatTerrace);
+    }
+
+    /// Build a gable roof, unless another roof style is chosen already, or in the desert style.
+    pub fn prefer_gable_roof(&mut self, biome: BiomeStyle) {
+        if self.roof_style.is_none() && biome != BiomeStyle::Desert {
+            self.roof_style = Some(RoofStyle::Gable);
+        }
+    }
+}
+
+/// Distance along the walls between the stilts of a house on stilts, between the corners.
+const STILT_SPACING: usize = 3;
+
+/// Closest two dormers on the same roof may be, measured between their windows.
+const DORMER_SPACING_MIN: usize = 5;
+
+/// Smallest difference in ground height under the interior, for stepping the house
+/// down the slope as terraces instead of building it with level floors.
+const TERRACE_HEIGHT_RANGE_MIN: i64 = 4;
+/// Difference in ground height to take up with each terrace.
+const TERRACE_STEP: i64 = 3;
+/// Most terraces in a single house.
+const TERRACE_COUNT_MAX: i64 = 3;
+/// Narrowest a terrace may be along the slope, including the wall on its uphill side.
+const TERRACE_WIDTH_MIN: i64 = 4;
+
+/// Most rain barrels on a single roof terrace.
+const RAIN_BARRELS_MAX: usize = 2;
+/// Smallest interior area of a house in a desert town, for putting up a wind catcher on its roof.
+const WIND_CATCHER_AREA_MIN: usize = 40;
+/// Height of a wind catcher above the roof terrace, up to its cap.
+const WIND_CATCHER_HEIGHT: i64 = 4;
+
+/// Depth of the shared chimney of semi-detached houses, down into the roof from its top.
+const SHARED_CHIMNEY_DEPTH: i64 = 2;
+/// Height of the shared chimney of semi-detached houses, above the top of the roof.
+const SHARED_CHIMNEY_HEIGHT: i64 = 2;
+
+/// Smallest plot area worth putting a tavern on.
+const TAVERN_PLOT_AREA_MIN: i64 = 120;
+/// Largest number of columns in the herb garden of an apothecary.
+const HERB_GARDEN_AREA_MAX: usize = 12;
+
+/// Whether the plot is a large corner plot, with streets along at least two sides,
+/// suitable for a tavern.
+pub fn suits_tavern(plot: &Plot) -> bool {
+    let street_sides = plot.edges.iter()
+        .filter(|edge| matches!(edge.kind, PlotEdgeKind::Road { .. }))
+        .count();
+    street_sides >= 2 && geometry::area(&plot.polygon()).abs() >= TAVERN_PLOT_AREA_MIN
+}
+
+/// Whether the plot faces a city road, at least `city_road_width` wide, suitable for a workshop.
+pub fn suits_workshop(plot: &Plot, city_road_width: usize) -> bool {
+    plot.edges.iter()
+        .any(|edge| matches!(edge.kind, PlotEdgeKind::Road { width } if width >= city_road_width))
+}
+
+/// Build the chimney stack shared by a pair of semi-detached houses, on the middle of the
+/// party wall between them, from just under the roof to above the ridge.
+pub fn build_shared_chimney(excerpt: &mut WorldExcerpt, party_wall: RawEdge2d) {
+    let x = (party_wall.0 .0 + party_wall.1 .0) / 2;
+    let z = (party_wall.0 .1 + party_wall.1 .1) / 2;
+    let (_, y_len, _) = excerpt.dim();
+    let roof_y = (0..y_len as i64)
+        .rev()
+        .find(|y| !matches!(excerpt.block_at(BlockCoord(x, *y, z)), None | Some(Block::Air) | Some(Block::None)));
+    if let Some(roof_y) = roof_y {
+        for y in roof_y - SHARED_CHIMNEY_DEPTH..=roof_y + SHARED_CHIMNEY_HEIGHT {
+            excerpt.set_block_at(BlockCoord(x, y, z), Block::Cobblestone);
+        }
+    }
+}
+
+/// An exterior door of a house.
+#[derive(Clone, Copy, Debug)]
+pub struct ExteriorDoor {
+    /// Coordinates of the lower half of the door.
+    pub coordinates: BlockCoord,
+    /// Direction from the street into the house.
+    pub facing: Surface4,
+}
+
+/// A generated house, with the details needed for placing things around it.
+pub struct House {
+    pub structure: WorldExcerpt,
+    pub doors: Vec<ExteriorDoor>,
+}
+
 pub fn build_house(
     excerpt: &WorldExcerpt,
     build_area: &BuildArea,
     palette: &BlockPalette,
-) -> Option <WorldExcerpt> {
+    options: &HouseOptions,
+    rng: &mut StdRng,
+) -> Option<House> {
 
     // WorldExcerpt for holding the additions/changes to the world
     let (x_len, y_len, z_len) = excerpt.dim();
@@ -100,16 +284,30 @@ pub fn build_house(
         }
     }
 
-    // Don't bother if the interior area of the building is less than 9 m²
-    if buildable_interior.len() < 9 {
-        trace!("Building would have less than 9 m² interior; aborting.");
+    // Don't bother if the interior area of the building is too small
+    if buildable_interior.len() < options.interior_area_min {
+        trace!("Building would have less than {} m² interior; aborting.", options.interior_area_min);
         return None;
-    // or larger than 100 m².
-    } else if buildable_interior.len() > 100 {
-        trace!("Building would have more than 100 m² interior; aborting.");
+    // or too large.
+    } else if buildable_interior.len() > options.interior_area_max {
+        trace!("Building would have more than {} m² interior; aborting.", options.interior_area_max);
         return None;
     }
 
+    // Step the house down the slope, if the ground is too steep for level floors.
+    let interior_ground: Vec<i64> = buildable_interior.iter()
+        .filter_map(|coordinates| height_map.height_at(*coordinates))
+        .map(|y| y as i64)
+        .collect();
+    if let (Some(lowest), Some(highest)) = (interior_ground.iter().min(), interior_ground.iter().max()) {
+        if highest - lowest > TERRACE_HEIGHT_RANGE_MIN && !options.stilts && options.floor_y_min == 0 {
+            if let Some(house) = build_terraced_house(excerpt, build_area, &buildable_interior, palette, options, rng) {
+                return Some(house);
+            }
+            trace!("Could not terrace the house; building it with level floors.");
+        }
+    }
+
     // Cells from the 8-neighbourhood of the interior, are outer walls.
     let mut interior_neighbours: HashSet<(usize, usize)> = HashSet::new();
 
@@ -130,7 +328,10 @@ pub fn build_house(
         facing: Surface4,
     }
 
-    let mut possible_door_positions: HashSet<DoorPlacement> = HashSet::new();
+    // Kept as an ordered list, so that ties between door positions are broken deterministically.
+    let mut possible_door_positions: Vec<DoorPlacement> = Vec::new();
+    // Height of the street outside each door position, below the door if it is raised.
+    let mut street_heights: HashMap<(usize, usize), usize> = HashMap::new();
 
     fn coordinates_in_direction(origo: &(usize, usize), direction: &Surface4, distance: usize) -> (usize, usize) {
         match direction {
@@ -141,7 +342,10 @@ pub fn build_house(
         }
     }
 
-    for (x, z) in &interior_neighbours {
+    let mut sorted_interior_neighbours: Vec<(usize, usize)> = interior_neighbours.iter().copied().collect();
+    sorted_interior_neighbours.sort();
+
+    for (x, z) in &sorted_interior_neighbours {
         'directions: for direction in [Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
             if buildable_interior.contains(&coordinates_in_direction(&(*x, *z), &direction, 1))
             && interior_neighbours.contains(&coordinates_in_direction(&(*x, *z), &direction.rotated_90_cw(), 1))
@@ -154,12 +358,13 @@ pub fn build_house(
                             if designation.is_buildable() {
                                 continue;
                             } else if designation.is_road() {
-                                let height = height_map.height_at(look_at_coordinates).unwrap_or(255);
-                                possible_door_positions.insert(DoorPlacement {
+                                let height = height_map.height_at(look_at_coordinates).unwrap_or(255) as usize;
+                                possible_door_positions.push(DoorPlacement {
                                     coordinates: (*x, *z),
-                                    height: height as usize,
+                                    height: max(height, options.floor_y_min + 1),
                                     facing: direction,
                                 });
+                                street_heights.insert((*x, *z), height);
                                 break 'directions;
                             } else {
                                 break;
@@ -196,8 +401,17 @@ pub fn build_house(
     let highest_door_position = door_positions.iter().max_by(|a, b| a.height.cmp(&b.height)).unwrap();
     let lowest_door_position = door_positions.iter().max_by(|a, b| b.height.cmp(&a.height)).unwrap();
 
-    const STORY_HEIGHT: usize = 3;
-    let cornice_height = highest_door_position.height + STORY_HEIGHT - 1;
+    // Stack as many stories above the highest entrance as allowed, within the height limit.
+    let story_height = options.story_height;
+    let stories = (1..=options.stories_max.max(1))
+        .take_while(|stories| {
+            options.height_max == 0
+                || highest_door_position.height + story_height * stories - lowest_door_position.height
+                    <= options.height_max
+        })
+        .last()
+        .unwrap_or(1);
+    let cornice_height = highest_door_position.height + story_height * stories - 1;
 
     // Clear area from bottom floor to some distance above top floor.
     for (x, z) in &buildable_interior {
@@ -207,18 +421,37 @@ pub fn build_house(
         }
     }
 
-    // Place (base/cellar) walls from upper door down
+    // Place (base/cellar) walls from upper door down, or on stilts from the lowest floor
+    // down, with logs at the corners and fences between them.
     for (x, z) in &interior_neighbours {
+        if options.stilts {
+            let floor_y = lowest_door_position.height as i64 - 1;
+            for y in floor_y..highest_door_position.height as i64 {
+                output.set_block_at(BlockCoord(*x as i64, y, *z as i64), palette.foundation.clone());
+            }
+            let is_on_outline = |x: usize, z: usize| interior_neighbours.contains(&(x, z));
+            let is_corner = !(is_on_outline(x - 1, *z) && is_on_outline(x + 1, *z))
+                && !(is_on_outline(*x, z - 1) && is_on_outline(*x, z + 1));
+            if is_corner || (x + z) % STILT_SPACING == 0 {
+                for (coordinates, block) in foundation::stilt(excerpt, *x as i64, floor_y, *z as i64, is_corner) {
+                    output.set_block_at(coordinates, block);
+                }
+            }
+            continue;
+        }
         let lowest_y = min(lowest_door_position.height, height_map.height_at((*x, *z)).unwrap_or(255) as usize - 1);
         for y in lowest_y..=highest_door_position.height - 1 {
             let coordinates = BlockCoord(*x as i64, y as i64, *z as i64);
             output.set_block_at(coordinates, palette.foundation.clone());
         }
+        for (coordinates, block) in foundation::underpinning(excerpt, *x as i64, lowest_y as i64, *z as i64, &palette.foundation) {
+            output.set_block_at(coordinates, block);
+        }
     }
 
     // Place walls from upper door up
     for (x, z) in &interior_neighbours {
-        for y in highest_door_position.height..highest_door_position.height + STORY_HEIGHT {
+        for y in highest_door_position.height..=cornice_height {
             let coordinates = BlockCoord(*x as i64, y as i64, *z as i64);
             output.set_block_at(coordinates, palette.wall.clone());
         }
@@ -243,6 +476,28 @@ pub fn build_house(
             hinged_at: mcprogedit::block::Hinge::Right,
             open: false,
         }));
+
+        // Steps down to the street from a door raised above it, on foundation down to the ground.
+        let street_height = street_heights.get(&door_position.coordinates).copied().unwrap_or(y);
+        let material = block_palette::stairs_material(&palette.foundation).unwrap_or(Material::StoneBrick);
+        let (dx, dz) = match door_position.facing.opposite() {
+            Surface4::North => (0, -1),
+            Surface4::South => (0, 1),
+            Surface4::East => (1, 0),
+            Surface4::West => (-1, 0),
+        };
+        for step in 1..=y.saturating_sub(street_height) as i64 {
+            let (step_x, step_z) = (x as i64 + dx * step, z as i64 + dz * step);
+            if step_x < 0 || step_z < 0 || step_x >= x_len as i64 || step_z >= z_len as i64 {
+                break;
+            }
+            let step_y = y as i64 - step;
+            output.set_block_at(BlockCoord(step_x, step_y, step_z), Block::stairs(door_position.facing.into(), material));
+            let ground_y = height_map.height_at((step_x as usize, step_z as usize)).unwrap_or(255) as i64;
+            for fill_y in ground_y..step_y {
+                output.set_block_at(BlockCoord(step_x, fill_y, step_z), palette.foundation.clone());
+            }
+        }
     }
 
     // Decide floor levels.
@@ -250,17 +505,27 @@ pub fn build_house(
     for door_position in &door_positions {
         floor_levels.insert(door_position.height as i64 - 1);
     }
+    for story in 1..stories {
+        floor_levels.insert((highest_door_position.height - 1 + story * story_height) as i64);
+    }
 
-    // Place floors.
+    // Place floors, with a support slab under the lowest one where it rests on loose ground.
     for y in &floor_levels {
         for (x, z) in &buildable_interior {
             let coordinates = BlockCoord(*x as i64, *y as i64, *z as i64);
             output.set_block_at(coordinates, palette.floor.clone());
         }
     }
+    let lowest_floor_y = lowest_door_position.height as i64 - 1;
+    for (x, z) in &buildable_interior {
+        if let Some((coordinates, block)) = foundation::support_slab(excerpt, *x as i64, lowest_floor_y, *z as i64) {
+            output.set_block_at(coordinates, block);
+        }
+    }
 
     // Find possible window locations
     let mut possible_window_coordinates: HashSet<BlockCoord> = HashSet::new();
+    let mut sunward_window_coordinates: HashSet<BlockCoord> = HashSet::new();
     for y in &floor_levels {
         'wall_piece: for (x, z) in &interior_neighbours {
             for direction in [Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
@@ -294,6 +559,16 @@ pub fn build_house(
                     // Check if the outside area is actually open air.
                     if let Some(outside_designation) = build_area.designation_at(outside_coordinates) {
                         if outside_designation.is_buildable() || outside_designation.is_road() {
+                            // Only every other window on walls facing away from the sun,
+                            // and unbroken rows of windows on walls facing the sun.
+                            let facade = direction.opposite();
+                            if facade == options.sunward.opposite() && (*x + *z) % 2 == 1 {
+                                continue 'wall_piece;
+                            }
+                            if facade == options.sunward {
+                                sunward_window_coordinates.insert(BlockCoord(*x as i64, y + 2, *z as i64));
+                            }
+
                             // This looks like a perfectly fine place to put a window.
                             possible_window_coordinates.insert(BlockCoord(*x as i64, y + 2, *z as i64));
                             continue 'wall_piece;
@@ -337,7 +612,7 @@ pub fn build_house(
             }
         }
     }
-    for split_coordinates in &window_splits {
+    for split_coordinates in window_splits.difference(&sunward_window_coordinates) {
         possible_window_coordinates.remove(split_coordinates);
     }
 
@@ -347,10 +622,41 @@ pub fn build_house(
     }
 
     // Calculate and place roof
-    let roof_coordinates = calculate_roof_coordinates(&interior_neighbours, &buildable_interior, cornice_height);
+    let roof_style = options.roof_style.unwrap_or_else(|| {
+        if palette.biome == BiomeStyle::Desert {
+            RoofStyle::FlatTerrace
+        } else {
+            choose_roof_style(&interior_neighbours, palette.biome, rng)
+        }
+    });
+    trace!("Roof style: {:?}", roof_style);
+    let (roof_coordinates, ridge_line) = match roof_style {
+        RoofStyle::Gable => {
+            let (roof, ridge_line) = calculate_roof_coordinates(
+                &interior_neighbours,
+                &buildable_interior,
+                cornice_height,
+                palette.roof_ridge,
+                Some(highest_door_position.facing),
+            );
+            (roof, Some(ridge_line))
+        }
+        RoofStyle::Hipped => (
+            calculate_hipped_roof_coordinates(&interior_neighbours, &buildable_interior, cornice_height),
+            None,
+        ),
+        RoofStyle::FlatTerrace => (
+            interior_neighbours.union(&buildable_interior)
+                .map(|(x, z)| BlockCoord(*x as i64, cornice_height as i64, *z as i64))
+                .collect(),
+            None,
+        ),
+    };
+    let roof_top_y = roof_coordinates.iter().map(|coordinates| coordinates.1).max().unwrap_or(0);
     for coordinates in &roof_coordinates {
         // NB TODO FIXME uncomment to put roof back in!
-        output.set_block_at(*coordinates, palette.roof.clone());
+        let block = roof_block(*coordinates, &roof_coordinates, roof_style, ridge_line, roof_top_y, palette);
+        output.set_block_at(*coordinates, block);
 
         // If over internal parts: Clear down to cornice_height
         if buildable_interior.contains(&(coordinates.0 as usize, coordinates.2 as usize)) {
@@ -369,6 +675,73 @@ pub fn build_house(
         }
     }
 
+    // Overhanging eaves, and dormers on the slopes of gable roofs
+    if options.roof_details && roof_style != RoofStyle::FlatTerrace {
+        for (x, z) in &interior_neighbours {
+            if !roof_coordinates.contains(&BlockCoord(*x as i64, cornice_height as i64, *z as i64)) {
+                continue;
+            }
+            for direction in [Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
+                let outside = coordinates_in_direction(&(*x, *z), &direction, 1);
+                if interior_neighbours.contains(&outside) || buildable_interior.contains(&outside) {
+                    continue;
+                }
+                if let Some(designation) = build_area.designation_at(outside) {
+                    if designation.is_buildable() || designation.is_road() {
+                        output.set_block_at(
+                            BlockCoord(outside.0 as i64, cornice_height as i64, outside.1 as i64),
+                            Block::stairs(direction.opposite().into(), palette.roof_stairs),
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(ridge_line) = ridge_line {
+            add_dormers(
+                &mut output,
+                &roof_coordinates,
+                ridge_line,
+                cornice_height as i64,
+                &buildable_interior,
+                palette,
+            );
+        }
+    }
+
+    add_roof_snow(&mut output, &roof_coordinates, palette);
+
+    // Fence along the edge of a roof terrace
+    if roof_style == RoofStyle::FlatTerrace {
+        for (x, z) in &interior_neighbours {
+            output.set_block_at(
+                BlockCoord(*x as i64, cornice_height as i64 + 1, *z as i64),
+                Block::Fence { material: FenceMaterial::Oak, waterlogged: false },
+            );
+        }
+        if palette.biome == BiomeStyle::Desert {
+            add_rain_barrels(&mut output, &interior_neighbours, &buildable_interior, cornice_height as i64 + 1);
+            if buildable_interior.len() >= WIND_CATCHER_AREA_MIN {
+                add_wind_catcher(&mut output, &buildable_interior, cornice_height as i64, palette);
+            }
+        }
+    }
+
+    // Add façade embellishments on top of the base shell.
+    if let Some(embellishment) = options.embellishment {
+        let doors: Vec<(usize, usize)> = door_positions.iter().map(|door| door.coordinates).collect();
+        let shell = HouseShell {
+            outline: &interior_neighbours,
+            interior: &buildable_interior,
+            doors: &doors,
+            street_facing: highest_door_position.facing,
+            top_floor: *floor_levels.iter().max().unwrap(),
+            cornice_height,
+        };
+        if !embellishment::add_embellishment(&mut output, build_area, &shell, palette, embellishment) {
+            trace!("No room for {:?}.", embellishment);
+        }
+    }
+
     let roof_height_lookup: HashMap<(usize, usize), usize> = roof_coordinates.iter()
         .map(|BlockCoord(x, y, z)| ((*x as usize, *z as usize), *y as usize))
         .collect();
@@ -376,14 +749,38 @@ pub fn build_house(
     floor_levels.sort();
     trace!("Floor levels: {:?}", floor_levels);
 
+    // Connect each floor to the one above it, with stairs or a ladder.
+    let door_columns: Vec<(usize, usize)> = door_positions.iter().map(|door| door.coordinates).collect();
+    let mut stairwells: Vec<Stairwell> = Vec::new();
+    for levels in floor_levels.windows(2) {
+        let occupied: HashSet<(usize, usize)> = stairwells.iter()
+            .flat_map(|stairwell| stairwell.steps.iter().copied())
+            .collect();
+        let free_interior: HashSet<(usize, usize)> = buildable_interior.difference(&occupied).copied().collect();
+        match plan_stairwell(&free_interior, &interior_neighbours, &door_columns, levels[0], levels[1]) {
+            Some(stairwell) => {
+                build_stairwell(&mut output, &stairwell);
+                stairwells.push(stairwell);
+            }
+            None => warn!("Found no room for stairs between floors {} and {}.", levels[0], levels[1]),
+        }
+    }
+
+    // Chimneys from the hearths and forges, as the column and the lowest y above the hood.
+    let mut chimneys: Vec<((usize, usize), i64)> = Vec::new();
+
     // Place interior
     // For each floor
     for (index, y) in floor_levels.iter().enumerate() {
         enum RoomKind {
+            Apothecary,
+            Bakery,
             Cooking,
             Cottage,
+            Library,
             Living,
             Sleeping,
+            Tavern,
             Working,
         }
 
@@ -392,8 +789,29 @@ pub fn build_house(
         let mut interior_doors: HashSet<DoorPlacement> = HashSet::new();
         let mut interior_wall_openings: HashSet<(usize, usize)> = HashSet::new();
 
+        // For taverns, have the taproom on the ground floor and the guest rooms upstairs.
+        if options.purpose == HousePurpose::Tavern {
+            let room_kind = if index == 0 { RoomKind::Tavern } else { RoomKind::Sleeping };
+            rooms.push((room_kind, buildable_interior.clone()));
+
+        // For workshops, have the smithy on the ground floor and the smith's home upstairs.
+        } else if options.purpose == HousePurpose::Workshop && index == 0 {
+            rooms.push((RoomKind::Working, buildable_interior.clone()));
+
+        // For bakeries, have the bakehouse on the ground floor and the baker's home upstairs.
+        } else if options.purpose == HousePurpose::Bakery && index == 0 {
+            rooms.push((RoomKind::Bakery, buildable_interior.clone()));
+
+        // For apothecaries, have the shop and brewery on the ground floor and the home upstairs.
+        } else if options.purpose == HousePurpose::Apothecary && index == 0 {
+            rooms.push((RoomKind::Apothecary, buildable_interior.clone()));
+
+        // For schools, have a library on every floor.
+        } else if options.purpose == HousePurpose::School {
+            rooms.push((RoomKind::Library, buildable_interior.clone()));
+
         // For small houses, have a single room with everything in it.
-        if buildable_interior.len() <= 30 {
+        } else if buildable_interior.len() <= 30 {
             rooms.push((RoomKind::Cottage, buildable_interior.clone()));
 
         } else { // For large houses, split into several rooms.
@@ -544,7 +962,7 @@ pub fn build_house(
 
                 // Figure out where the doors are
                 // NB TODO move this further up / out, it is needed for all scenarios and beyond!
-                let doors_on_this_floor: HashSet<(usize, usize)> = door_positions.iter()
+                let doors_on_this_floor: BTreeSet<(usize, usize)> = door_positions.iter()
                     .filter_map(|placement| {
                         if placement.height as i64 == y + 1 {
                             Some(placement.coordinates)
@@ -555,10 +973,14 @@ pub fn build_house(
                     .collect();
                 trace!("Found {:?} doors on this floor: {:?}", doors_on_this_floor.len(), doors_on_this_floor);
 
-                // Single out one main door.
+                // Single out one main door, or on the upper stories, the top of the stairs.
                 // NB Assuming the building at this point has one and only one door!
                 let main_door: (usize, usize) = doors_on_this_floor.into_iter().next()
-                    .expect("There should be at least one door on this floor.");
+                    .or_else(|| stairwells.iter()
+                        .find(|stairwell| stairwell.upper_floor == *y)
+                        .and_then(|stairwell| stairwell.steps.last().copied()))
+                    .or_else(|| buildable_interior.iter().min().copied())
+                    .expect("The interior of the building is not empty.");
                 let main_door_neighbours = neighbourhood_4(main_door);
 
                 // Figure out if the main door opening collides with any of the interior walls.
@@ -805,6 +1227,25 @@ pub fn build_house(
             //      * or find better suited assignations
         }
 
+        // Keep the stairwells clear of interior walls, doors and furniture.
+        let stairwell_columns: HashSet<(usize, usize)> = stairwells.iter()
+            .flat_map(|stairwell| stairwell.columns_on_floor(*y))
+            .collect();
+        interior_walls.retain(|coordinates| !stairwell_columns.contains(coordinates));
+        interior_doors.retain(|door| !stairwell_columns.contains(&door.coordinates));
+        interior_wall_openings.retain(|coordinates| !stairwell_columns.contains(coordinates));
+        for (_, interior_area) in rooms.iter_mut() {
+            interior_area.retain(|coordinates| !stairwell_columns.contains(coordinates));
+        }
+
+        // Keep the chimneys from the floors below clear of doors and furniture.
+        let chimney_columns: HashSet<(usize, usize)> = chimneys.iter().map(|(column, _)| *column).collect();
+        interior_doors.retain(|door| !chimney_columns.contains(&door.coordinates));
+        interior_wall_openings.retain(|coordinates| !chimney_columns.contains(coordinates));
+        for (_, interior_area) in rooms.iter_mut() {
+            interior_area.retain(|coordinates| !chimney_columns.contains(coordinates));
+        }
+
         // Place interior walls
         for (x, z) in &interior_walls {
             let ceiling_height = if index < floor_levels.len() - 1 {
@@ -871,8 +1312,8 @@ pub fn build_house(
             for coordinates in &interior_neighbours {
                 room_shape.set_column_kind_at(*coordinates, ColumnKind::Wall);
             }
-            // Interior walls.
-            for coordinates in &interior_walls {
+            // Interior walls, and chimneys passing through.
+            for coordinates in interior_walls.iter().chain(&chimney_columns) {
                 room_shape.set_column_kind_at(*coordinates, ColumnKind::Wall);
             }
             // Windows.
@@ -896,28 +1337,62 @@ pub fn build_house(
             for interior_opening in &interior_wall_openings {
                 room_shape.set_column_kind_at(*interior_opening, ColumnKind::Door);
             }
+            // Stairs and ladders, and the openings above them.
+            for coordinates in &stairwell_columns {
+                room_shape.set_column_kind_at(*coordinates, ColumnKind::Stairwell);
+            }
 
             // Furnish the room according to its function.
             match room_kind {
-                RoomKind::Cooking => if let Some(interior) = room_interior::furnish_cooking_area(&room_shape) {
+                RoomKind::Apothecary => if let Some(interior) = room_interior::furnish_apothecary(&room_shape, rng) {
+                    output.paste(BlockCoord(0, *y + 1, 0), &interior);
+                },
+                RoomKind::Bakery => if let Some(interior) = room_interior::furnish_bakery(&room_shape, rng) {
+                    output.paste(BlockCoord(0, *y + 1, 0), &interior);
+                    chimneys.extend(hood_columns(&interior, &interior_area).map(|column| (column, *y + 2)));
+                },
+                RoomKind::Cooking => if let Some(interior) = room_interior::furnish_cooking_area(&room_shape, rng) {
+                    output.paste(BlockCoord(0, *y + 1, 0), &interior);
+                    chimneys.extend(hood_columns(&interior, &interior_area).map(|column| (column, *y + 2)));
+                },
+                RoomKind::Cottage => if let Some(interior) = room_interior::furnish_cottage(&room_shape, rng) {
                     output.paste(BlockCoord(0, *y + 1, 0), &interior);
                 },
-                RoomKind::Cottage => if let Some(interior) = room_interior::furnish_cottage(&room_shape) {
+                RoomKind::Library => if let Some(interior) = room_interior::furnish_library(&room_shape, rng) {
                     output.paste(BlockCoord(0, *y + 1, 0), &interior);
                 },
-                RoomKind::Living => if let Some(interior) = room_interior::furnish_living_area(&room_shape) {
+                RoomKind::Living => if let Some(interior) = room_interior::furnish_living_area(&room_shape, rng) {
                     output.paste(BlockCoord(0, *y + 1, 0), &interior);
                 },
-                RoomKind::Sleeping => if let Some(interior) = room_interior::furnish_sleeping_area(&room_shape) {
+                RoomKind::Sleeping => if let Some(interior) = room_interior::furnish_sleeping_area(&room_shape, rng) {
                     output.paste(BlockCoord(0, *y + 1, 0), &interior);
                 },
-                RoomKind::Working => if let Some(interior) = room_interior::furnish_working_area(&room_shape) {
+                RoomKind::Tavern => if let Some(interior) = room_interior::furnish_tavern(&room_shape, rng) {
                     output.paste(BlockCoord(0, *y + 1, 0), &interior);
                 },
+                RoomKind::Working => if let Some(interior) = room_interior::furnish_smithy(&room_shape, rng) {
+                    output.paste(BlockCoord(0, *y + 1, 0), &interior);
+                    chimneys.extend(hood_columns(&interior, &interior_area).map(|column| (column, *y + 2)));
+                },
             }
         }
     }
 
+    // Continue the chimneys from the hoods up through the floors above and the roof,
+    // with a campfire on top sending up smoke.
+    for ((x, z), bottom_y) in &chimneys {
+        if let Some(roof_y) = roof_height_lookup.get(&(*x, *z)) {
+            let top_y = *roof_y as i64 + 2;
+            for chimney_y in *bottom_y..top_y {
+                output.set_block_at(BlockCoord(*x as i64, chimney_y, *z as i64), Block::Cobblestone);
+            }
+            output.set_block_at(
+                BlockCoord(*x as i64, top_y, *z as i64),
+                Block::Campfire { facing: Surface4::North, lit: true, waterlogged: false },
+            );
+        }
+    }
+
     // Place some flowers in suitable areas around the house.
     let outside_area: HashSet<(usize, usize)> = road_along_buildable
         .union(&buildable).cloned().collect::<HashSet<(usize, usize)>>()
@@ -983,36 +1458,720 @@ pub fn build_house(
         }
     }
 
-    Some(output)
+    // For apothecaries, plant an herb garden in the yard, in beds of podzol.
+    if options.purpose == HousePurpose::Apothecary {
+        let mut herbs: Vec<Block> = palette.flowers.iter()
+            .filter(|flower| !matches!(
+                flower,
+                Flower::LilacBottom | Flower::LilacTop
+                    | Flower::PeonyBottom | Flower::PeonyTop
+                    | Flower::RoseBushBottom | Flower::RoseBushTop
+                    | Flower::SunflowerBottom | Flower::SunflowerTop
+            ))
+            .map(|flower| Block::Flower(*flower))
+            .collect();
+        herbs.push(Block::BrownMushroom);
+        herbs.push(Block::RedMushroom);
+
+        let mut garden: Vec<(usize, usize)> = outside_area.iter()
+            .filter(|column| !road_along_buildable.contains(*column))
+            .copied()
+            .collect();
+        garden.sort_unstable();
+        let mut area = 0;
+        for (x, z) in garden {
+            if area >= HERB_GARDEN_AREA_MAX {
+                break;
+            }
+            if let Some(y) = height_map.height_at((x, z)) {
+                let ground_coordinates = BlockCoord(x as i64, y as i64 - 1, z as i64);
+                let bottom_coordinates = BlockCoord(x as i64, y as i64, z as i64);
+                let top_coordinates = BlockCoord(x as i64, y as i64 + 1, z as i64);
+                // Keep clear of the tall flowers, and of anything built above the ground
+                if !matches!(output.block_at(top_coordinates), None | Some(Block::None)) {
+                    continue;
+                }
+                if let Some(Block::GrassBlock)
+                | Some(Block::CoarseDirt)
+                | Some(Block::Dirt)
+                | Some(Block::Podzol) = excerpt.block_at(ground_coordinates) {
+                    output.set_block_at(ground_coordinates, Block::Podzol);
+                    output.set_block_at(bottom_coordinates, herbs[rng.gen_range(0..herbs.len())].clone());
+                    area += 1;
+                }
+            }
+        }
+    }
+
+    let doors = door_positions.iter()
+        .map(|door| ExteriorDoor {
+            coordinates: BlockCoord(door.coordinates.0 as i64, door.height as i64, door.coordinates.1 as i64),
+            facing: door.facing,
+        })
+        .collect();
+
+    Some(House { structure: output, doors })
+}
+
+/// One level of a terraced house.
+struct Terrace {
+    interior: HashSet<(usize, usize)>,
+    /// Walls around the terrace, including those shared with the neighbouring terraces.
+    outline: HashSet<(usize, usize)>,
+    floor_y: i64,
+    cornice_y: i64,
 }
 
+/// Build a house stepping down a steep slope: The interior is split across the slope into
+/// terraces, each a single story with its own floor level and roof, with doors from the
+/// streets at matching heights, and stairs through the walls between the terraces.
+fn build_terraced_house(
+    excerpt: &WorldExcerpt,
+    build_area: &BuildArea,
+    interior: &HashSet<(usize, usize)>,
+    palette: &BlockPalette,
+    options: &HouseOptions,
+    rng: &mut StdRng,
+) -> Option<House> {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut output = WorldExcerpt::new(x_len, y_len, z_len);
+
+    let height_map = excerpt.ground_height_map();
+    let ground = |column: (usize, usize)| height_map.height_at(column).map(|y| y as i64);
+    let step = |(x, z): (usize, usize), direction: Surface4, distance: i64| {
+        let offset = geometry::offset(direction);
+        ((x as i64 + offset.0 * distance) as usize, (z as i64 + offset.2 * distance) as usize)
+    };
+
+    // Find which way is uphill, from the slope of the ground along each axis.
+    let heights: Vec<((usize, usize), i64)> = interior.iter()
+        .filter_map(|column| ground(*column).map(|y| (*column, y)))
+        .collect();
+    if heights.is_empty() {
+        return None;
+    }
+    let slope = |position: fn(&(usize, usize)) -> usize| -> f64 {
+        let count = heights.len() as f64;
+        let mean_position = heights.iter().map(|(column, _)| position(column) as f64).sum::<f64>() / count;
+        let mean_height = heights.iter().map(|(_, y)| *y as f64).sum::<f64>() / count;
+        let (covariance, variance) = heights.iter().fold((0.0, 0.0), |(covariance, variance), (column, y)| {
+            let offset = position(column) as f64 - mean_position;
+            (covariance + offset * (*y as f64 - mean_height), variance + offset * offset)
+        });
+        if variance == 0.0 { 0.0 } else { covariance / variance }
+    };
+    let (slope_x, slope_z) = (slope(|column| column.0), slope(|column| column.1));
+    let uphill = if slope_x.abs() >= slope_z.abs() {
+        if slope_x >= 0.0 { Surface4::East } else { Surface4::West }
+    } else if slope_z >= 0.0 {
+        Surface4::South
+    } else {
+        Surface4::North
+    };
+    let uphill_offset = geometry::offset(uphill);
+    let along = |(x, z): (usize, usize)| x as i64 * uphill_offset.0 + z as i64 * uphill_offset.2;
+    let across = |(x, z): (usize, usize)| if uphill_offset.0 == 0 { x as i64 } else { z as i64 };
+
+    // Split the interior into terraces along the slope, with walls between them.
+    let along_min = interior.iter().map(|column| along(*column)).min()?;
+    let along_max = interior.iter().map(|column| along(*column)).max()?;
+    let length = along_max - along_min + 1;
+    let ground_min = heights.iter().map(|(_, y)| *y).min()?;
+    let ground_max = heights.iter().map(|(_, y)| *y).max()?;
+    let mut terrace_count = ((ground_max - ground_min + TERRACE_STEP - 1) / TERRACE_STEP).clamp(2, TERRACE_COUNT_MAX);
+    while terrace_count > 1 && length / terrace_count < TERRACE_WIDTH_MIN {
+        terrace_count -= 1;
+    }
+    if terrace_count < 2 {
+        trace!("Building is too short along the slope to be terraced.");
+        return None;
+    }
+    let starts: Vec<i64> = (0..terrace_count)
+        .map(|index| along_min + index * length / terrace_count)
+        .collect();
+    let is_partition = |position: i64| starts[1..].contains(&position);
+
+    let mut terraces: Vec<Terrace> = Vec::new();
+    for index in 0..starts.len() {
+        let terrace_interior: HashSet<(usize, usize)> = interior.iter()
+            .filter(|column| {
+                let position = along(**column);
+                starts.iter().rposition(|start| *start <= position) == Some(index) && !is_partition(position)
+            })
+            .copied()
+            .collect();
+        if terrace_interior.len() < options.interior_area_min {
+            trace!("Terrace {} would have less than {} m² interior.", index, options.interior_area_min);
+            return None;
+        }
+
+        let mut outline: HashSet<(usize, usize)> = HashSet::new();
+        for (x, z) in &terrace_interior {
+            for neighbour_x in x - 1..=x + 1 {
+                for neighbour_z in z - 1..=z + 1 {
+                    if !terrace_interior.contains(&(neighbour_x, neighbour_z)) {
+                        outline.insert((neighbour_x, neighbour_z));
+                    }
+                }
+            }
+        }
+
+        // Floor at the middle ground height of the terrace.
+        let mut ground_heights: Vec<i64> = terrace_interior.iter().filter_map(|column| ground(*column)).collect();
+        ground_heights.sort_unstable();
+        let floor_y = *ground_heights.get(ground_heights.len() / 2)? - 1;
+
+        terraces.push(Terrace {
+            interior: terrace_interior,
+            outline,
+            floor_y,
+            cornice_y: floor_y + options.story_height as i64,
+        });
+    }
+    trace!(
+        "Terraced house stepping {:?}, with floor levels {:?}.",
+        uphill,
+        terraces.iter().map(|terrace| terrace.floor_y).collect::<Vec<i64>>(),
+    );
+
+    // Walls reach from the lowest floor to the highest cornice of the terraces they enclose.
+    let mut walls: HashMap<(usize, usize), (i64, i64)> = HashMap::new();
+    for terrace in &terraces {
+        for column in &terrace.outline {
+            let (floor_y, top_y) = walls.entry(*column).or_insert((terrace.floor_y, terrace.cornice_y));
+            *floor_y = min(*floor_y, terrace.floor_y);
+            *top_y = max(*top_y, terrace.cornice_y);
+        }
+    }
+    let partition_walls: HashSet<(usize, usize)> = walls.keys()
+        .filter(|column| is_partition(along(**column)))
+        .copied()
+        .collect();
+
+    // Place foundations and walls, cutting into the slope where needed.
+    for (column, (floor_y, top_y)) in &walls {
+        let ground_y = ground(*column).unwrap_or(floor_y + 1);
+        let bottom_y = min(ground_y - 1, *floor_y);
+        for y in bottom_y..=*floor_y {
+            output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), palette.foundation.clone());
+        }
+        for (coordinates, block) in
+            foundation::underpinning(excerpt, column.0 as i64, bottom_y, column.1 as i64, &palette.foundation)
+        {
+            output.set_block_at(coordinates, block);
+        }
+        for y in floor_y + 1..=*top_y {
+            output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), palette.wall.clone());
+        }
+        for y in top_y + 1..ground_y {
+            output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), Block::Air);
+        }
+    }
+
+    // Place floors, and clear the rooms above them.
+    for terrace in &terraces {
+        for column in &terrace.interior {
+            let ground_y = ground(*column).unwrap_or(terrace.floor_y + 1);
+            let bottom_y = min(ground_y - 1, terrace.floor_y);
+            for y in bottom_y..terrace.floor_y {
+                output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), palette.foundation.clone());
+            }
+            if let Some((coordinates, block)) = foundation::support_slab(excerpt, column.0 as i64, bottom_y, column.1 as i64) {
+                output.set_block_at(coordinates, block);
+            }
+            output.set_block_at(BlockCoord(column.0 as i64, terrace.floor_y, column.1 as i64), palette.floor.clone());
+            for y in terrace.floor_y + 1..max(terrace.cornice_y, ground_y) {
+                output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), Block::Air);
+            }
+        }
+    }
+
+    // Place roofs, from the lowest terrace up, so that the higher roofs cover the walls between.
+    // Gable ridges run across the slope, so the eaves meet the walls between the terraces.
+    let roof_style = match options.roof_style {
+        Some(RoofStyle::FlatTerrace) => RoofStyle::FlatTerrace,
+        _ if palette.biome == BiomeStyle::Desert => RoofStyle::FlatTerrace,
+        _ => RoofStyle::Gable,
+    };
+    let mut roof_heights: HashMap<(usize, usize), i64> = HashMap::new();
+    let mut roof_order: Vec<&Terrace> = terraces.iter().collect();
+    roof_order.sort_by_key(|terrace| terrace.cornice_y);
+    for terrace in roof_order {
+        let (roof, ridge_line) = if roof_style == RoofStyle::Gable {
+            let (roof, ridge_line) = calculate_roof_coordinates(
+                &terrace.outline,
+                &terrace.interior,
+                terrace.cornice_y as usize,
+                RidgeOrientation::ParallelToStreet,
+                Some(uphill),
+            );
+            (roof, Some(ridge_line))
+        } else {
+            let roof: HashSet<BlockCoord> = terrace.outline.union(&terrace.interior)
+                .map(|(x, z)| BlockCoord(*x as i64, terrace.cornice_y, *z as i64))
+                .collect();
+            (roof, None)
+        };
+        let roof_top_y = roof.iter().map(|coordinates| coordinates.1).max().unwrap_or(0);
+        for coordinates in &roof {
+            let block = roof_block(*coordinates, &roof, roof_style, ridge_line, roof_top_y, palette);
+            output.set_block_at(*coordinates, block);
+
+            let column = (coordinates.0 as usize, coordinates.2 as usize);
+            let filler = if terrace.interior.contains(&column) {
+                roof_heights.insert(column, coordinates.1);
+                Block::Air
+            } else {
+                palette.wall.clone()
+            };
+            for y in terrace.cornice_y..coordinates.1 {
+                output.set_block_at(BlockCoord(coordinates.0, y, coordinates.2), filler.clone());
+            }
+        }
+        add_roof_snow(&mut output, &roof, palette);
+
+        if roof_style == RoofStyle::FlatTerrace {
+            for (x, z) in terrace.outline.difference(&partition_walls) {
+                output.set_block_at(
+                    BlockCoord(*x as i64, terrace.cornice_y + 1, *z as i64),
+                    Block::Fence { material: FenceMaterial::Oak, waterlogged: false },
+                );
+            }
+            if palette.biome == BiomeStyle::Desert {
+                add_rain_barrels(&mut output, &terrace.outline, &terrace.interior, terrace.cornice_y + 1);
+            }
+        }
+    }
+
+    // Close up the walls between the terraces, where the eaves of the lower roofs met them.
+    for column in &partition_walls {
+        let (floor_y, top_y) = walls[column];
+        for y in floor_y + 1..top_y {
+            output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), palette.wall.clone());
+        }
+    }
+
+    // Find a wall piece for a door from the street into each terrace, at a matching height.
+    let mut doors: Vec<(usize, ExteriorDoor)> = Vec::new();
+    for (index, terrace) in terraces.iter().enumerate() {
+        let entrance_y = terrace.floor_y + 1;
+        let mut outline: Vec<(usize, usize)> = terrace.outline.difference(&partition_walls).copied().collect();
+        outline.sort();
+
+        let mut best: Option<(i64, (usize, usize), Surface4)> = None;
+        for column in outline {
+            'directions: for direction in [Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
+                if !terrace.interior.contains(&step(column, direction, 1))
+                || !terrace.outline.contains(&step(column, direction.rotated_90_cw(), 1))
+                || !terrace.outline.contains(&step(column, direction.rotated_90_ccw(), 1)) {
+                    continue;
+                }
+                for distance in 1..=10 {
+                    let look_at = step(column, direction.opposite(), distance);
+                    match build_area.designation_at(look_at) {
+                        Some(designation) if designation.is_buildable() => continue,
+                        Some(designation) if designation.is_road() => {
+                            let mismatch = (ground(look_at).unwrap_or(255) - entrance_y).abs();
+                            if mismatch <= 1 && best.map_or(true, |(best_mismatch, _, _)| mismatch < best_mismatch) {
+                                best = Some((mismatch, column, direction));
+                            }
+                            break 'directions;
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+
+        if let Some((_, column, facing)) = best {
+            doors.push((index, ExteriorDoor {
+                coordinates: BlockCoord(column.0 as i64, entrance_y, column.1 as i64),
+                facing,
+            }));
+        }
+    }
+    if doors.is_empty() {
+        trace!("Found no street at the height of any of the terraces.");
+        return None;
+    }
+    let door_columns: Vec<(usize, usize)> = doors.iter()
+        .map(|(_, door)| (door.coordinates.0 as usize, door.coordinates.2 as usize))
+        .collect();
+
+    // Place doors, with a step in front of them down or up to the street.
+    for (_, door) in &doors {
+        let (x, y, z) = (door.coordinates.0, door.coordinates.1, door.coordinates.2);
+        for (half, door_y) in [(mcprogedit::block::DoorHalf::Lower, y), (mcprogedit::block::DoorHalf::Upper, y + 1)] {
+            output.set_block_at(BlockCoord(x, door_y, z), Block::Door(mcprogedit::block::Door {
+                material: mcprogedit::material::DoorMaterial::Oak,
+                facing: door.facing,
+                half,
+                hinged_at: mcprogedit::block::Hinge::Right,
+                open: false,
+            }));
+        }
+        let front = step((x as usize, z as usize), door.facing.opposite(), 1);
+        let front_ground_y = ground(front).unwrap_or(y);
+        for front_y in front_ground_y..y {
+            output.set_block_at(BlockCoord(front.0 as i64, front_y, front.1 as i64), palette.foundation.clone());
+        }
+        for front_y in y..max(front_ground_y, y + 2) {
+            output.set_block_at(BlockCoord(front.0 as i64, front_y, front.1 as i64), Block::Air);
+        }
+    }
+
+    // Place windows in the outer walls, in every other wall piece, or in rows broken
+    // up every third wall piece on the wall facing the sun.
+    let mut window_coordinates: HashSet<BlockCoord> = HashSet::new();
+    for terrace in &terraces {
+        let window_y = terrace.floor_y + 2;
+        'wall_piece: for column in terrace.outline.difference(&partition_walls) {
+            for direction in [Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
+                let first_side = step(*column, direction.rotated_90_cw(), 1);
+                let second_side = step(*column, direction.rotated_90_ccw(), 1);
+                let outside = step(*column, direction.opposite(), 1);
+                if !terrace.interior.contains(&step(*column, direction, 1))
+                || !terrace.outline.contains(&first_side)
+                || !terrace.outline.contains(&second_side) {
+                    continue;
+                }
+                if [*column, first_side, second_side].iter().any(|piece| door_columns.contains(piece)) {
+                    continue 'wall_piece;
+                }
+                if ground(outside).map_or(false, |outside_y| outside_y > window_y) {
+                    continue 'wall_piece;
+                }
+                let facade = direction.opposite();
+                let skip = if facade == options.sunward {
+                    (column.0 + column.1) % 3 == 1
+                } else {
+                    (column.0 + column.1) % 2 == 1
+                };
+                if !skip && build_area.designation_at(outside)
+                    .map_or(false, |designation| designation.is_buildable() || designation.is_road())
+                {
+                    window_coordinates.insert(BlockCoord(column.0 as i64, window_y, column.1 as i64));
+                }
+                continue 'wall_piece;
+            }
+        }
+    }
+    for coordinates in &window_coordinates {
+        output.set_block_at(*coordinates, Block::Glass { colour: None });
+    }
+
+    // Connect each pair of neighbouring terraces through the wall between them, with stairs
+    // sunk into the floor of the higher terrace, climbing away from the wall.
+    let mut openings: Vec<(usize, usize)> = Vec::new();
+    let mut stair_columns: Vec<(usize, usize)> = Vec::new();
+    for index in 1..terraces.len() {
+        let (low, high, climb) = if terraces[index].floor_y >= terraces[index - 1].floor_y {
+            (&terraces[index - 1], &terraces[index], uphill)
+        } else {
+            (&terraces[index], &terraces[index - 1], uphill.opposite())
+        };
+        let rise = high.floor_y - low.floor_y;
+        let is_clear = |column: (usize, usize)| {
+            !door_columns.iter().any(|door| neighbourhood_4(*door).contains(&column))
+        };
+
+        // Try the middle of the wall first.
+        let mut wall_pieces: Vec<(usize, usize)> = interior.iter()
+            .filter(|column| along(**column) == starts[index])
+            .copied()
+            .collect();
+        let middle = wall_pieces.iter().map(|column| across(*column)).sum::<i64>() / wall_pieces.len().max(1) as i64;
+        wall_pieces.sort_by_key(|column| ((across(*column) - middle).abs(), across(*column)));
+
+        let opening = wall_pieces.into_iter().find(|column| {
+            let below = step(*column, climb.opposite(), 1);
+            low.interior.contains(&below)
+                && is_clear(below)
+                && (1..=rise + 1).all(|distance| {
+                    let above = step(*column, climb, distance);
+                    high.interior.contains(&above) && is_clear(above)
+                })
+        });
+        let opening = match opening {
+            Some(opening) => opening,
+            None => {
+                warn!("Found no room for stairs between floors {} and {}.", low.floor_y, high.floor_y);
+                continue;
+            }
+        };
+
+        output.set_block_at(BlockCoord(opening.0 as i64, low.floor_y, opening.1 as i64), palette.floor.clone());
+        for y in low.floor_y + 1..=low.floor_y + 2 {
+            output.set_block_at(BlockCoord(opening.0 as i64, y, opening.1 as i64), Block::Air);
+        }
+        for distance in 1..=rise {
+            let (x, z) = step(opening, climb, distance);
+            let step_y = low.floor_y + distance;
+            output.set_block_at(BlockCoord(x as i64, step_y, z as i64), Block::stairs(climb.into(), Material::Spruce));
+            // Headroom above the step, through the floor of the higher terrace
+            for y in step_y + 1..=high.floor_y {
+                output.set_block_at(BlockCoord(x as i64, y, z as i64), Block::Air);
+            }
+            stair_columns.push((x, z));
+        }
+        openings.push(opening);
+    }
+
+    // Furnish the terraces, starting from the one with the main door: A cottage and a bedroom
+    // for two terraces, or a living room, a kitchen and a bedroom for three.
+    let main_terrace = doors[0].0;
+    let mut furnishing_order: Vec<usize> = (0..terraces.len()).collect();
+    furnishing_order.sort_by_key(|index| ((*index as i64 - main_terrace as i64).abs(), *index));
+    let mut chimneys: Vec<((usize, usize), i64)> = Vec::new();
+    for (position, index) in furnishing_order.into_iter().enumerate() {
+        let terrace = &terraces[index];
+        let room_area: HashSet<(usize, usize)> = terrace.interior.iter()
+            .filter(|column| !stair_columns.contains(*column))
+            .copied()
+            .collect();
+
+        let mut room_shape = RoomShape::new((x_len, z_len));
+        for column in &room_area {
+            let ceiling_height = roof_heights.get(column)
+                .expect("If it's in the interior of a terrace it should have a roof above.")
+                - terrace.floor_y
+                - 1;
+            room_shape.set_column_kind_at(*column, ColumnKind::Floor(ceiling_height as usize));
+        }
+        for column in &terrace.outline {
+            room_shape.set_column_kind_at(*column, ColumnKind::Wall);
+        }
+        for coordinates in window_coordinates.iter().filter(|coordinates| coordinates.1 == terrace.floor_y + 2) {
+            room_shape.set_column_kind_at((coordinates.0 as usize, coordinates.2 as usize), ColumnKind::Window);
+        }
+        for (door_terrace, door) in &doors {
+            if *door_terrace == index {
+                room_shape.set_column_kind_at((door.coordinates.0 as usize, door.coordinates.2 as usize), ColumnKind::Door);
+            }
+        }
+        for opening in openings.iter().filter(|opening| terrace.outline.contains(*opening)) {
+            room_shape.set_column_kind_at(*opening, ColumnKind::Door);
+        }
+        for column in stair_columns.iter().filter(|column| terrace.interior.contains(*column)) {
+            room_shape.set_column_kind_at(*column, ColumnKind::Stairwell);
+        }
+
+        let is_kitchen = position == 1 && terraces.len() > 2;
+        let furniture = match position {
+            0 if terraces.len() == 2 => room_interior::furnish_cottage(&room_shape, rng),
+            0 => room_interior::furnish_living_area(&room_shape, rng),
+            1 if is_kitchen => room_interior::furnish_cooking_area(&room_shape, rng),
+            _ => room_interior::furnish_sleeping_area(&room_shape, rng),
+        };
+        if let Some(furniture) = furniture {
+            output.paste(BlockCoord(0, terrace.floor_y + 1, 0), &furniture);
+            if is_kitchen {
+                chimneys.extend(hood_columns(&furniture, &room_area).map(|column| (column, terrace.floor_y + 2)));
+            }
+        }
+    }
+
+    // Continue the chimney from the hood of the hearth up through the roof.
+    for ((x, z), bottom_y) in &chimneys {
+        if let Some(roof_y) = roof_heights.get(&(*x, *z)) {
+            for chimney_y in *bottom_y..roof_y + 2 {
+                output.set_block_at(BlockCoord(*x as i64, chimney_y, *z as i64), Block::Cobblestone);
+            }
+            output.set_block_at(
+                BlockCoord(*x as i64, roof_y + 2, *z as i64),
+                Block::Campfire { facing: Surface4::North, lit: true, waterlogged: false },
+            );
+        }
+    }
+
+    Some(House {
+        structure: output,
+        doors: doors.into_iter().map(|(_, door)| door).collect(),
+    })
+}
+
+/// Stairs or a ladder between two floors of a house.
+struct Stairwell {
+    /// Floor level the stairwell leads up from.
+    lower_floor: i64,
+    /// Floor level the stairwell leads up to.
+    upper_floor: i64,
+    /// Columns of the steps, from the bottom step up, or the single column of a ladder.
+    steps: Vec<(usize, usize)>,
+    /// Direction up the stairs, or from the wall out into the room, for a ladder.
+    direction: Surface4,
+    is_ladder: bool,
+}
+
+impl Stairwell {
+    /// Columns taken up by the stairwell on the floor at level `y`: All the steps on the lower floor,
+    /// and on the upper floor the top step and the opening for the headroom of the steps below it.
+    fn columns_on_floor(&self, y: i64) -> Vec<(usize, usize)> {
+        if y == self.lower_floor {
+            self.steps.clone()
+        } else if y == self.upper_floor {
+            let opening_length = if self.is_ladder { 1 } else { 3 };
+            self.steps.iter().rev().take(opening_length).copied().collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Find room for stairs from `lower_floor` to `upper_floor`, along an outer wall of the house,
+/// with a free column at each end for stepping on and off. Fall back to a ladder against
+/// an outer wall, where there is no room for stairs. Stays clear of the exterior doors.
+fn plan_stairwell(
+    interior: &HashSet<(usize, usize)>,
+    outline: &HashSet<(usize, usize)>,
+    doors: &[(usize, usize)],
+    lower_floor: i64,
+    upper_floor: i64,
+) -> Option<Stairwell> {
+    let rise = (upper_floor - lower_floor) as usize;
+    let step = |(x, z): (usize, usize), direction: Surface4, distance: usize| match direction {
+        Surface4::North => (x, z.wrapping_sub(distance)),
+        Surface4::South => (x, z + distance),
+        Surface4::East => (x + distance, z),
+        Surface4::West => (x.wrapping_sub(distance), z),
+    };
+    let is_clear = |column: (usize, usize)| {
+        interior.contains(&column)
+            && !doors.iter().any(|door| *door == column || neighbourhood_4(*door).contains(&column))
+    };
+
+    // Sorted, so that the choice of stairwell is deterministic.
+    let mut columns: Vec<(usize, usize)> = interior.iter().copied().collect();
+    columns.sort();
+    let directions = [Surface4::North, Surface4::South, Surface4::East, Surface4::West];
+
+    for start in &columns {
+        for direction in directions {
+            for wall_side in [direction.rotated_90_cw(), direction.rotated_90_ccw()] {
+                let run: Vec<(usize, usize)> = (0..=rise + 1)
+                    .map(|distance| step(*start, direction, distance))
+                    .collect();
+                if run.iter().all(|column| is_clear(*column))
+                && run[1..=rise].iter().all(|column| outline.contains(&step(*column, wall_side, 1))) {
+                    return Some(Stairwell {
+                        lower_floor,
+                        upper_floor,
+                        steps: run[1..=rise].to_vec(),
+                        direction,
+                        is_ladder: false,
+                    });
+                }
+            }
+        }
+    }
+
+    for column in &columns {
+        for direction in directions {
+            if is_clear(*column)
+            && outline.contains(&step(*column, direction.opposite(), 1))
+            && is_clear(step(*column, direction, 1)) {
+                return Some(Stairwell {
+                    lower_floor,
+                    upper_floor,
+                    steps: vec![*column],
+                    direction,
+                    is_ladder: true,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Place the stairs or ladder, carving the opening through the floor above.
+fn build_stairwell(output: &mut WorldExcerpt, stairwell: &Stairwell) {
+    if stairwell.is_ladder {
+        let (x, z) = stairwell.steps[0];
+        for y in stairwell.lower_floor + 1..=stairwell.upper_floor {
+            output.set_block_at(
+                BlockCoord(x as i64, y, z as i64),
+                Block::Ladder { facing: stairwell.direction, waterlogged: false },
+            );
+        }
+        return;
+    }
+
+    for (index, (x, z)) in stairwell.steps.iter().enumerate() {
+        let step_y = stairwell.lower_floor + 1 + index as i64;
+        output.set_block_at(
+            BlockCoord(*x as i64, step_y, *z as i64),
+            Block::stairs(stairwell.direction.into(), Material::Spruce),
+        );
+        // Headroom above the step, up through the floor above
+        for y in step_y + 1..(step_y + 3).min(stairwell.upper_floor + 1) {
+            output.set_block_at(BlockCoord(*x as i64, y, *z as i64), Block::Air);
+        }
+    }
+}
+
+/// The columns of the given room with the hood of a hearth or forge, i.e. cobblestone
+/// right above the floor-level furniture in the furnished `interior`.
+fn hood_columns<'a>(
+    interior: &'a WorldExcerpt,
+    area: &'a HashSet<(usize, usize)>,
+) -> impl Iterator<Item = (usize, usize)> + 'a {
+    area.iter()
+        .filter(move |(x, z)| interior.block_at(BlockCoord(*x as i64, 1, *z as i64)) == Some(&Block::Cobblestone))
+        .copied()
+}
+
+/// Calculate the coordinates of a gable roof over the given outline and interior,
+/// along with the line the ridge follows.
+///
+/// The ridge follows one of the split lines of the outline, chosen according to
+/// `ridge_orientation`. `street_facing` is the facing of the (main) door, i.e.
+/// pointing from the street into the building. Without it the ridge follows the
+/// long axis of the building.
 fn calculate_roof_coordinates(
     outline: &HashSet<(usize, usize)>,
     interior: &HashSet<(usize, usize)>,
     height: usize,
-) -> HashSet<BlockCoord> {
+    ridge_orientation: RidgeOrientation,
+    street_facing: Option<Surface4>,
+) -> (HashSet<BlockCoord>, RawEdge2d) {
     let mut roof: HashSet<BlockCoord> = HashSet::new();
 
     let split_lines = compute_split_lines(outline);
 
-    // TODO: Actually use this for something, e.g. deciding type of roof.
     // Gather some stats on the split lines (only the lengths, for now)
     let (short_split_line, long_split_line) = split_lines;
     let short_len = geometry::manhattan_distance(short_split_line.0, short_split_line.1);
     let long_len = geometry::manhattan_distance(long_split_line.0, long_split_line.1);
     trace!("Roof split lines are of length {} and {}.", short_len, long_len);
 
+    // Decide which split line the ridge follows, and which one the roof spans.
+    let short_is_ridge = match (ridge_orientation, street_facing) {
+        (RidgeOrientation::ParallelToStreet, Some(facing)) => {
+            street_alignment(short_split_line, facing) < street_alignment(long_split_line, facing)
+        }
+        (RidgeOrientation::GableToStreet, Some(facing)) => {
+            street_alignment(short_split_line, facing) > street_alignment(long_split_line, facing)
+        }
+        _ => false,
+    };
+    let (ridge_line, span_len) = if short_is_ridge {
+        (short_split_line, long_len)
+    } else {
+        (long_split_line, short_len)
+    };
+
     // Calculate a gable roof
-    let gable_height = height + (short_len / 2);
+    let gable_height = height + (span_len / 2);
     let gable_line = (
-        BlockCoord(long_split_line.0.0, gable_height as i64, long_split_line.0.1),
-        BlockCoord(long_split_line.1.0, gable_height as i64, long_split_line.1.1),
+        BlockCoord(ridge_line.0.0, gable_height as i64, ridge_line.0.1),
+        BlockCoord(ridge_line.1.0, gable_height as i64, ridge_line.1.1),
     );
     let mut to_place: HashSet<BlockCoord> = line(&gable_line.0, &gable_line.1, 1).into_iter().collect();
 
     if to_place.is_empty() {
         warn!("No blocks in roof gable.");
-        return roof;
+        return (roof, ridge_line);
     }
 
     let mut unplaced: HashSet<(usize, usize)> = outline.union(interior).copied().collect();
@@ -1054,7 +2213,306 @@ fn calculate_roof_coordinates(
         roof = adjusted_roof;
     }
 
-    roof
+    (roof, ridge_line)
+}
+
+/// Choose a roof style suiting the shape of the house: Mostly gable roofs for oblong houses,
+/// and mostly hipped roofs or roof terraces for squarish ones. In the snowy style there are
+/// no flat roofs, and gable roofs are preferred. In the badlands style roof terraces are
+/// preferred.
+fn choose_roof_style(outline: &HashSet<(usize, usize)>, biome: BiomeStyle, rng: &mut StdRng) -> RoofStyle {
+    let (short_split_line, long_split_line) = compute_split_lines(outline);
+    let short_len = geometry::manhattan_distance(short_split_line.0, short_split_line.1).max(1);
+    let long_len = geometry::manhattan_distance(long_split_line.0, long_split_line.1);
+    let is_oblong = long_len * 2 >= short_len * 3;
+
+    let weights = match (biome, is_oblong) {
+        (BiomeStyle::Snowy, true) => [(RoofStyle::Gable, 6), (RoofStyle::Hipped, 1), (RoofStyle::FlatTerrace, 0)],
+        (BiomeStyle::Snowy, false) => [(RoofStyle::Gable, 3), (RoofStyle::Hipped, 2), (RoofStyle::FlatTerrace, 0)],
+        (BiomeStyle::Badlands, true) => [(RoofStyle::Gable, 2), (RoofStyle::Hipped, 1), (RoofStyle::FlatTerrace, 4)],
+        (BiomeStyle::Badlands, false) => [(RoofStyle::Gable, 0), (RoofStyle::Hipped, 1), (RoofStyle::FlatTerrace, 4)],
+        (_, true) => [(RoofStyle::Gable, 6), (RoofStyle::Hipped, 1), (RoofStyle::FlatTerrace, 1)],
+        (_, false) => [(RoofStyle::Gable, 2), (RoofStyle::Hipped, 3), (RoofStyle::FlatTerrace, 2)],
+    };
+    let mut choice = rng.gen_range(0..weights.iter().map(|(_, weight)| weight).sum::<u32>());
+    for (style, weight) in &weights {
+        if choice < *weight {
+            return *style;
+        }
+        choice -= weight;
+    }
+    RoofStyle::Gable
+}
+
+/// Calculate the coordinates of a hipped roof over the given outline and interior,
+/// rising one block for every block inwards from the outline.
+fn calculate_hipped_roof_coordinates(
+    outline: &HashSet<(usize, usize)>,
+    interior: &HashSet<(usize, usize)>,
+    height: usize,
+) -> HashSet<BlockCoord> {
+    let mut distances: HashMap<(usize, usize), usize> = outline.iter()
+        .map(|coordinates| (*coordinates, 0))
+        .collect();
+    let mut queue: VecDeque<(usize, usize)> = outline.iter().copied().collect();
+
+    while let Some((x, z)) = queue.pop_front() {
+        let distance = distances[&(x, z)];
+        for neighbour_x in x.saturating_sub(1)..=x + 1 {
+            for neighbour_z in z.saturating_sub(1)..=z + 1 {
+                let neighbour = (neighbour_x, neighbour_z);
+                if interior.contains(&neighbour) && !distances.contains_key(&neighbour) {
+                    distances.insert(neighbour, distance + 1);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    distances.iter()
+        .map(|((x, z), distance)| BlockCoord(*x as i64, (height + distance) as i64, *z as i64))
+        .collect()
+}
+
+/// The block to place at the given roof coordinates.
+///
+/// Sloped roofs get slabs along their top, and stairs ascending towards the ridge elsewhere,
+/// or towards whichever neighbouring roof block is one step up. Flat roofs, and roof blocks
+/// with no higher neighbour, get the roof block of the palette.
+fn roof_block(
+    coordinates: BlockCoord,
+    roof: &HashSet<BlockCoord>,
+    roof_style: RoofStyle,
+    ridge_line: Option<RawEdge2d>,
+    top_y: i64,
+    palette: &BlockPalette,
+) -> Block {
+    if roof_style == RoofStyle::FlatTerrace {
+        return palette.roof.clone();
+    }
+    if coordinates.1 == top_y {
+        // A full ridge, for the snow to lie on
+        if palette.snow_layer.is_some() {
+            return palette.roof.clone();
+        }
+        return Block::bottom_slab(palette.roof_stairs);
+    }
+    let is_higher = |direction: Surface4| {
+        roof.contains(&(coordinates + geometry::offset(direction) + BlockCoord(0, 1, 0)))
+    };
+
+    // Straight up the slope, towards the ridge line
+    if let Some(upwards) = ridge_line.and_then(|ridge_line| upslope(coordinates, ridge_line)) {
+        if is_higher(upwards) {
+            return Block::stairs(upwards.into(), palette.roof_stairs);
+        }
+    }
+
+    for direction in &[Surface4::North, Surface4::South, Surface4::East, Surface4::West] {
+        if is_higher(*direction) {
+            return Block::stairs((*direction).into(), palette.roof_stairs);
+        }
+    }
+    palette.roof.clone()
+}
+
+/// Lay snow on the full blocks of the roof that are open to the sky, in the snowy style.
+fn add_roof_snow(output: &mut WorldExcerpt, roof: &HashSet<BlockCoord>, palette: &BlockPalette) {
+    let snow_layer = match &palette.snow_layer {
+        Some(snow_layer) => snow_layer,
+        None => return,
+    };
+    for coordinates in roof {
+        let above = *coordinates + BlockCoord(0, 1, 0);
+        if output.block_at(*coordinates) == Some(&palette.roof)
+            && matches!(output.block_at(above), Some(Block::None) | Some(Block::Air))
+        {
+            output.set_block_at(above, snow_layer.clone());
+        }
+    }
+}
+
+/// The direction straight up the slope of a gable roof at the given coordinates,
+/// towards the ridge line. None on the ridge line itself.
+fn upslope(coordinates: BlockCoord, (start, end): RawEdge2d) -> Option<Surface4> {
+    let (ridge_x, ridge_z) = ((end.0 - start.0) as f32, (end.1 - start.1) as f32);
+    let side = ridge_x * (coordinates.2 - start.1) as f32 - ridge_z * (coordinates.0 - start.0) as f32;
+    if side == 0.0 {
+        None
+    } else {
+        Some(signs::surface_from_vector(ridge_z * side.signum(), -ridge_x * side.signum()))
+    }
+}
+
+/// Add small dormers with windows to the slopes of a gable roof, set back one block
+/// from the eaves, and spaced out along the ridge.
+fn add_dormers(
+    output: &mut WorldExcerpt,
+    roof: &HashSet<BlockCoord>,
+    ridge_line: RawEdge2d,
+    cornice_height: i64,
+    interior: &HashSet<(usize, usize)>,
+    palette: &BlockPalette,
+) {
+    let roof_y: HashMap<(i64, i64), i64> = roof.iter()
+        .map(|BlockCoord(x, y, z)| ((*x, *z), *y))
+        .collect();
+    let roof_y_at = |coordinates: BlockCoord| roof_y.get(&(coordinates.0, coordinates.2)).copied();
+
+    // Front middles of the dormers, one block in from the eaves
+    let mut candidates: Vec<BlockCoord> = roof.iter()
+        .filter(|coordinates| coordinates.1 == cornice_height + 1)
+        .copied()
+        .collect();
+    candidates.sort_by_key(|coordinates| (coordinates.0, coordinates.2));
+
+    let mut dormers: Vec<BlockCoord> = Vec::new();
+    for front in candidates {
+        if dormers.iter().any(|dormer| geometry::manhattan_distance_3d(*dormer, front) < DORMER_SPACING_MIN) {
+            continue;
+        }
+        let upwards = match upslope(front, ridge_line) {
+            Some(upwards) => upwards,
+            None => continue,
+        };
+        let back = geometry::offset(upwards);
+        let side = geometry::offset(upwards.rotated_90_cw());
+        let at = |along: i64, across: i64| {
+            BlockCoord(
+                front.0 + back.0 * along + side.0 * across,
+                cornice_height + 1,
+                front.2 + back.2 * along + side.2 * across,
+            )
+        };
+
+        // The front must be a straight stretch of roof, with the wall right in front of it,
+        // and the roof must rise high enough behind it to take the dormer.
+        let is_straight = (-1..=1).all(|across| {
+            let column = at(0, across);
+            interior.contains(&(column.0 as usize, column.2 as usize))
+                && roof_y_at(column) == Some(cornice_height + 1)
+                && roof_y_at(at(-1, across)) == Some(cornice_height)
+        });
+        let depth = (1..)
+            .take_while(|along| roof_y_at(at(*along, 0)).map_or(false, |y| y < cornice_height + 3))
+            .count() as i64;
+        if !is_straight || roof_y_at(at(depth + 1, 0)).is_none() {
+            continue;
+        }
+
+        for along in 0..=depth {
+            for across in -1..=1 {
+                let bottom = at(along, across);
+                let top = bottom + BlockCoord(0, 1, 0);
+                if across != 0 {
+                    // Cheeks
+                    output.set_block_at(bottom, palette.wall.clone());
+                    output.set_block_at(top, palette.wall.clone());
+                } else if along == 0 {
+                    // Window, under a lintel
+                    output.set_block_at(bottom, Block::glass_pane());
+                    output.set_block_at(top, palette.wall.clone());
+                } else {
+                    output.set_block_at(bottom, Block::Air);
+                    output.set_block_at(top, Block::Air);
+                }
+                output.set_block_at(top + BlockCoord(0, 1, 0), Block::bottom_slab(palette.roof_stairs));
+            }
+        }
+        dormers.push(front);
+    }
+    trace!("Added {} dormers.", dormers.len());
+}
+
+/// Set out rain barrels in the corners of a roof terrace, standing at level `y`.
+fn add_rain_barrels(
+    output: &mut WorldExcerpt,
+    outline: &HashSet<(usize, usize)>,
+    interior: &HashSet<(usize, usize)>,
+    y: i64,
+) {
+    let mut corners: Vec<(usize, usize)> = interior.iter()
+        .filter(|(x, z)| {
+            let outline_neighbours = (x - 1..=x + 1)
+                .flat_map(|neighbour_x| (z - 1..=z + 1).map(move |neighbour_z| (neighbour_x, neighbour_z)))
+                .filter(|neighbour| outline.contains(neighbour))
+                .count();
+            outline_neighbours >= 5
+        })
+        .copied()
+        .collect();
+    corners.sort();
+    for (x, z) in corners.into_iter().take(RAIN_BARRELS_MAX) {
+        output.set_block_at(BlockCoord(x as i64, y, z as i64), Block::barrel(Surface6::Up));
+    }
+}
+
+/// Put up a wind catcher on the flat roof at level `roof_y`, towards the corner furthest from
+/// the middle of the house: A hollow tower with vents near the top on all sides, leading the
+/// breeze down a shaft through the roof into the house.
+fn add_wind_catcher(
+    output: &mut WorldExcerpt,
+    interior: &HashSet<(usize, usize)>,
+    roof_y: i64,
+    palette: &BlockPalette,
+) {
+    let count = interior.len().max(1) as i64;
+    let middle = BlockColumnCoord(
+        interior.iter().map(|(x, _)| *x as i64).sum::<i64>() / count,
+        interior.iter().map(|(_, z)| *z as i64).sum::<i64>() / count,
+    );
+    let mut candidates: Vec<(usize, usize)> = interior.iter()
+        .filter(|(x, z)| {
+            (x - 1..=x + 1).all(|neighbour_x| {
+                (z - 1..=z + 1).all(|neighbour_z| interior.contains(&(neighbour_x, neighbour_z)))
+            })
+        })
+        .copied()
+        .collect();
+    candidates.sort_by_key(|(x, z)| {
+        let distance = geometry::manhattan_distance(BlockColumnCoord(*x as i64, *z as i64), middle);
+        (std::cmp::Reverse(distance), *x, *z)
+    });
+    let (x, z) = match candidates.first() {
+        Some(column) => *column,
+        None => return,
+    };
+
+    let top_y = roof_y + WIND_CATCHER_HEIGHT;
+    for dx in -1..=1 {
+        for dz in -1..=1 {
+            let (column_x, column_z) = (x as i64 + dx, z as i64 + dz);
+            if (dx, dz) == (0, 0) {
+                // The shaft, down through the roof
+                for y in roof_y..top_y {
+                    output.set_block_at(BlockCoord(column_x, y, column_z), Block::Air);
+                }
+            } else {
+                for y in roof_y + 1..top_y {
+                    let is_vent = y == top_y - 1 && (dx == 0 || dz == 0);
+                    let block = if is_vent { Block::Air } else { palette.wall.clone() };
+                    output.set_block_at(BlockCoord(column_x, y, column_z), block);
+                }
+            }
+            output.set_block_at(BlockCoord(column_x, top_y, column_z), Block::bottom_slab(palette.roof_stairs));
+        }
+    }
+}
+
+/// How well the given line aligns with the given facing, from 0.0 (perpendicular) to 1.0 (parallel).
+fn street_alignment(line: RawEdge2d, facing: Surface4) -> f64 {
+    let (facing_x, facing_z) = match facing {
+        Surface4::North => (0.0, -1.0),
+        Surface4::South => (0.0, 1.0),
+        Surface4::East => (1.0, 0.0),
+        Surface4::West => (-1.0, 0.0),
+    };
+    let (line_x, line_z) = ((line.1.0 - line.0.0) as f64, (line.1.1 - line.0.1) as f64);
+    let length = (line_x * line_x + line_z * line_z).sqrt();
+    if length == 0.0 {
+        return 0.0;
+    }
+    ((line_x * facing_x + line_z * facing_z) / length).abs()
 }
 
 fn compute_split_lines(points: &HashSet<(usize, usize)>) -> (RawEdge2d, RawEdge2d) {
@@ -1180,6 +2638,10 @@ pub fn _build_legacy_house(
         for y in terrain_y as i64..road_y_average as i64 {
             output.set_block_at(BlockCoord(*x as i64, y, *z as i64), palette.foundation.clone());
         }
+        let bottom_y = min(terrain_y as i64, road_y_average as i64);
+        for (coordinates, block) in foundation::underpinning(excerpt, *x as i64, bottom_y, *z as i64, &palette.foundation) {
+            output.set_block_at(coordinates, block);
+        }
         // Remove terrain from floor block level and up
         for y in road_y_average as i64..=terrain_y as i64 {
             output.set_block_at(BlockCoord(*x as i64, y, *z as i64), Block::Air);
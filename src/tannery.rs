@@ -0,0 +1,177 @@
+//! Nuisance industries, kept outside the town wall: Tanneries with their soaking pits
+//! and hides hung out to dry, and dyeworks with their vats and stores of dyed wool.
+
+use mcprogedit::block::{Banner, Block, WallOrRotatedOnFloor};
+use mcprogedit::bounded_ints::Int0Through3;
+use mcprogedit::colour::Colour;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, Material, WoodMaterial};
+use mcprogedit::positioning::{Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::block_palette::BlockPalette;
+use crate::features::Features;
+use crate::geometry;
+use crate::pathfinding::RoadPath;
+use crate::tree;
+
+/// Half the width of the fenced yard, from its middle to the fence.
+pub const YARD_HALF_WIDTH: i64 = 4;
+
+/// Colours of the wool in the stores of the dyeworks.
+const DYE_COLOURS: [Colour; 6] = [
+    Colour::Red,
+    Colour::Yellow,
+    Colour::Blue,
+    Colour::Green,
+    Colour::Purple,
+    Colour::Orange,
+];
+
+/// The kind of nuisance industry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NuisanceKind {
+    Tannery,
+    Dyeworks,
+}
+
+/// A tannery or dyeworks, in a fenced yard outside the town wall.
+#[derive(Clone, Debug)]
+pub struct NuisanceIndustry {
+    pub kind: NuisanceKind,
+    /// Middle of the yard.
+    pub center: BlockColumnCoord,
+    /// Y coordinate of the level ground of the yard.
+    pub floor_y: i64,
+    /// The side of the yard with the gate, towards the town gate.
+    pub front: Surface4,
+    /// Path from the gate of the yard to the town gate, if one was found.
+    pub path: Option<RoadPath>,
+}
+
+/// Build the fenced yard of the tannery or dyeworks, with its pits or vats.
+pub fn build_nuisance_industry(
+    excerpt: &mut WorldExcerpt,
+    industry: &NuisanceIndustry,
+    features: &Features,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) {
+    let y = industry.floor_y;
+    let ahead = geometry::offset(industry.front);
+    let side = geometry::offset(industry.front.rotated_90_cw());
+    let at = |along: i64, across: i64, up: i64| {
+        BlockCoord(
+            industry.center.0 + ahead.0 * along + side.0 * across,
+            y + up,
+            industry.center.1 + ahead.2 * along + side.2 * across,
+        )
+    };
+
+    // Level yard, with a fence around it and a gate in front
+    for along in -YARD_HALF_WIDTH..=YARD_HALF_WIDTH {
+        for across in -YARD_HALF_WIDTH..=YARD_HALF_WIDTH {
+            let ground = at(along, across, 0);
+            for up in 0..4 {
+                tree::chop(excerpt, at(along, across, up));
+                excerpt.set_block_at(at(along, across, up), Block::Air);
+            }
+            let image::Luma([ground_y]) = features.terrain[(ground.0 as u32, ground.2 as u32)];
+            for fill_y in ground_y as i64 - 1..y - 1 {
+                excerpt.set_block_at(BlockCoord(ground.0, fill_y, ground.2), palette.foundation.clone());
+            }
+            excerpt.set_block_at(at(along, across, -1), Block::CoarseDirt);
+
+            if along.abs() == YARD_HALF_WIDTH || across.abs() == YARD_HALF_WIDTH {
+                let block = if (along, across) == (YARD_HALF_WIDTH, 0) {
+                    Block::FenceGate { material: WoodMaterial::Spruce, facing: industry.front, open: false }
+                } else {
+                    Block::Fence { material: FenceMaterial::Spruce, waterlogged: false }
+                };
+                excerpt.set_block_at(ground, block);
+            }
+        }
+    }
+
+    match industry.kind {
+        NuisanceKind::Tannery => build_tannery(excerpt, &at, industry.front, rng),
+        NuisanceKind::Dyeworks => build_dyeworks(excerpt, &at, rng),
+    }
+
+    // Barrels in the front corners of the yard
+    for across in &[-(YARD_HALF_WIDTH - 1), YARD_HALF_WIDTH - 1] {
+        excerpt.set_block_at(at(YARD_HALF_WIDTH - 1, *across, 0), Block::barrel(Surface6::Up));
+    }
+}
+
+/// Soaking pits sunk into the ground, and a rack at the back with hides hung out to dry.
+fn build_tannery<F>(excerpt: &mut WorldExcerpt, at: &F, front: Surface4, rng: &mut StdRng)
+where
+    F: Fn(i64, i64, i64) -> BlockCoord,
+{
+    for along in &[-1, 1] {
+        for across in &[-2, 0, 2] {
+            let water_level = Int0Through3::new(rng.gen_range(1..=3)).unwrap();
+            excerpt.set_block_at(at(*along, *across, -1), Block::Cauldron { water_level });
+        }
+    }
+
+    let rack_along = -(YARD_HALF_WIDTH - 1);
+    for across in -2..=2 {
+        excerpt.set_block_at(
+            at(rack_along, across, 0),
+            Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+        );
+        for up in 1..=2 {
+            excerpt.set_block_at(at(rack_along, across, up), Block::Planks { material: WoodMaterial::Spruce });
+        }
+        if across != 0 {
+            excerpt.set_block_at(at(rack_along + 1, across, 1), hide(front));
+        }
+    }
+}
+
+/// Vats of dye, and a store of dyed wool at the back, under a slab roof.
+fn build_dyeworks<F>(excerpt: &mut WorldExcerpt, at: &F, rng: &mut StdRng)
+where
+    F: Fn(i64, i64, i64) -> BlockCoord,
+{
+    for along in &[-1, 1] {
+        for across in &[-2, 0, 2] {
+            excerpt.set_block_at(
+                at(*along, *across, 0),
+                Block::Cauldron { water_level: Int0Through3::new(3).unwrap() },
+            );
+        }
+    }
+
+    let store_along = -(YARD_HALF_WIDTH - 1);
+    for across in -(YARD_HALF_WIDTH - 1)..YARD_HALF_WIDTH {
+        let stack_height = rng.gen_range(1..=2);
+        for up in 0..stack_height {
+            let colour = DYE_COLOURS[rng.gen_range(0..DYE_COLOURS.len())];
+            excerpt.set_block_at(at(store_along, across, up), Block::Wool { colour });
+        }
+        if across.abs() == YARD_HALF_WIDTH - 1 {
+            for up in stack_height..2 {
+                excerpt.set_block_at(
+                    at(store_along, across, up),
+                    Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+                );
+            }
+        }
+        excerpt.set_block_at(at(store_along, across, 2), Block::bottom_slab(Material::Spruce));
+    }
+}
+
+/// A hide hung out to dry, as a brown banner on the side of a rack facing `facing`.
+fn hide(facing: Surface4) -> Block {
+    Block::Banner(Box::new(Banner {
+        colour: Colour::Brown,
+        custom_name: None,
+        placement: WallOrRotatedOnFloor::Wall(facing),
+        patterns: Vec::new(),
+    }))
+}
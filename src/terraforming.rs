@@ -0,0 +1,125 @@
+//! Terraforming of the plots before building on them: The ground of each plot is levelled
+//! towards a common floor height, and graded back to the original ground along the edges
+//! of the plot, so that houses on hills rest on the ground rather than on tall foundations.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::tree;
+
+/// Width of the band along the edges of the plot, grading the levelled ground back to
+/// the original ground.
+const GRADING_WIDTH: f32 = 3.0;
+/// Most the ground is raised or lowered at any column.
+const LEVELLING_DEPTH_MAX: i64 = 4;
+
+/// Level the ground of the plot with the given polygon towards the middle height of its
+/// ground, grading the ground along the edges of the plot back to its original height.
+/// Only natural ground is reshaped, keeping its surface blocks. Water is left as it is.
+///
+/// Returns the number of columns reshaped.
+pub fn level_plot(excerpt: &mut WorldExcerpt, polygon: &[BlockColumnCoord], features: &Features) -> usize {
+    let (x_len, z_len) = features.dimensions();
+    let (x_min, x_max) = (polygon.iter().map(|point| point.0).min(), polygon.iter().map(|point| point.0).max());
+    let (z_min, z_max) = (polygon.iter().map(|point| point.1).min(), polygon.iter().map(|point| point.1).max());
+    let (x_min, x_max, z_min, z_max) = match (x_min, x_max, z_min, z_max) {
+        (Some(x_min), Some(x_max), Some(z_min), Some(z_max)) => (
+            x_min.max(0),
+            x_max.min(x_len as i64 - 1),
+            z_min.max(0),
+            z_max.min(z_len as i64 - 1),
+        ),
+        _ => return 0,
+    };
+
+    // The columns of dry ground inside the plot, with their ground height
+    let mut columns: Vec<(BlockColumnCoord, i64)> = Vec::new();
+    for x in x_min..=x_max {
+        for z in z_min..=z_max {
+            let column = BlockColumnCoord(x, z);
+            if geometry::point_position_relative_to_polygon(column, polygon) != InOutSide::Inside
+                || features.water[(x as u32, z as u32)] == image::Luma([255u8])
+            {
+                continue;
+            }
+            let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+            columns.push((column, y as i64));
+        }
+    }
+    if columns.is_empty() {
+        return 0;
+    }
+    let mut heights: Vec<i64> = columns.iter().map(|(_, y)| *y).collect();
+    heights.sort_unstable();
+    let target_y = heights[heights.len() / 2];
+
+    let mut reshaped_count = 0;
+    for (column, ground_y) in columns {
+        let grading = (distance_to_edge(column, polygon) / GRADING_WIDTH).min(1.0);
+        let change = (target_y - ground_y).max(-LEVELLING_DEPTH_MAX).min(LEVELLING_DEPTH_MAX);
+        let new_y = ground_y + (change as f32 * grading).round() as i64;
+        if new_y == ground_y {
+            continue;
+        }
+
+        // Keep the surface of the ground, over matching ground
+        let surface = match excerpt.block_at(BlockCoord(column.0, ground_y - 1, column.1)) {
+            Some(block) if is_natural_ground(block) => block.clone(),
+            _ => continue,
+        };
+        let subsoil = match surface {
+            Block::GrassBlock | Block::Podzol | Block::CoarseDirt => Block::Dirt,
+            ref block => block.clone(),
+        };
+
+        if new_y > ground_y {
+            for y in ground_y - 1..new_y - 1 {
+                excerpt.set_block_at(BlockCoord(column.0, y, column.1), subsoil.clone());
+            }
+        } else {
+            for y in new_y..=ground_y {
+                tree::chop(excerpt, BlockCoord(column.0, y, column.1));
+                excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::Air);
+            }
+        }
+        excerpt.set_block_at(BlockCoord(column.0, new_y - 1, column.1), surface);
+        reshaped_count += 1;
+    }
+    reshaped_count
+}
+
+/// Whether the block is natural ground, that may be dug away or heaped up.
+fn is_natural_ground(block: &Block) -> bool {
+    matches!(
+        block,
+        Block::GrassBlock
+            | Block::Dirt
+            | Block::CoarseDirt
+            | Block::Podzol
+            | Block::Sand
+            | Block::RedSand
+            | Block::Gravel
+    )
+}
+
+/// Distance from the column to the closest edge of the polygon.
+fn distance_to_edge(column: BlockColumnCoord, polygon: &[BlockColumnCoord]) -> f32 {
+    let (x, z) = (column.0 as f32, column.1 as f32);
+    polygon.iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(start, end)| {
+            let (x0, z0) = (start.0 as f32, start.1 as f32);
+            let (dx, dz) = ((end.0 - start.0) as f32, (end.1 - start.1) as f32);
+            let length_squared = dx * dx + dz * dz;
+            let along = if length_squared == 0.0 {
+                0.0
+            } else {
+                (((x - x0) * dx + (z - z0) * dz) / length_squared).max(0.0).min(1.0)
+            };
+            ((x - x0 - along * dx).powi(2) + (z - z0 - along * dz).powi(2)).sqrt()
+        })
+        .fold(f32::INFINITY, f32::min)
+}
@@ -0,0 +1,170 @@
+//! Toll bars and guard lodges, where the country roads enter the generated area.
+
+use mcprogedit::block::{Block, WallOrRotatedOnFloor};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::WoodMaterial;
+use mcprogedit::positioning::{Surface2, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+
+use crate::block_palette::BlockPalette;
+use crate::features::Features;
+use crate::generation_context::paste_chopping_trees;
+use crate::geometry;
+use crate::hut::{self, HUT_FOUNDATION_DEPTH};
+use crate::pathfinding::{RoadNodeKind, RoadPath};
+use crate::signs;
+
+/// How close to the edge of the generated area a road must start, to count as an entrance.
+const ENTRANCE_EDGE_DISTANCE: i64 = 3;
+/// Distance along the road, from the edge of the generated area to the toll bar.
+const TOLL_BAR_DISTANCE: usize = 12;
+/// Outer dimensions of the guard lodge.
+const LODGE_SIZE: (i64, i64) = (5, 5);
+
+/// A toll bar across a road, with a guard lodge by the side of the road.
+#[derive(Clone, Debug)]
+pub struct TollStation {
+    /// Point on the middle of the road, where the toll bar crosses it.
+    pub road_point: BlockCoord,
+    /// Direction along the road, towards town.
+    pub towards_town: Surface4,
+    /// Which side of the road the lodge is on.
+    pub lodge_side: Surface4,
+}
+
+/// Find the entrances to the generated area along the given country roads.
+pub fn plan_toll_stations(country_roads: &[RoadPath], features: &Features) -> Vec<TollStation> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+    let is_at_edge = |BlockColumnCoord(x, z): BlockColumnCoord| {
+        x < ENTRANCE_EDGE_DISTANCE
+            || z < ENTRANCE_EDGE_DISTANCE
+            || x >= x_len - ENTRANCE_EDGE_DISTANCE
+            || z >= z_len - ENTRANCE_EDGE_DISTANCE
+    };
+
+    let mut stations = Vec::new();
+    for road in country_roads {
+        if road.len() <= TOLL_BAR_DISTANCE + 1 {
+            continue;
+        }
+
+        // The roads may have been found in either direction.
+        let inward: Vec<_> = if is_at_edge(road[0].coordinates.into()) {
+            road.iter().collect()
+        } else if is_at_edge(road[road.len() - 1].coordinates.into()) {
+            road.iter().rev().collect()
+        } else {
+            continue;
+        };
+
+        // No toll bars on bridges.
+        let node = inward[TOLL_BAR_DISTANCE];
+        if node.kind != RoadNodeKind::Ground {
+            continue;
+        }
+        let ahead = inward[TOLL_BAR_DISTANCE + 1];
+        let towards_town = signs::surface_from_vector(
+            (ahead.coordinates.0 - node.coordinates.0) as f32,
+            (ahead.coordinates.2 - node.coordinates.2) as f32,
+        );
+
+        stations.push(TollStation {
+            road_point: node.coordinates,
+            towards_town,
+            lodge_side: towards_town.rotated_90_cw(),
+        });
+    }
+    stations
+}
+
+/// Build the toll bar and the guard lodge of the given toll station.
+pub fn build_toll_station(
+    excerpt: &mut WorldExcerpt,
+    station: &TollStation,
+    features: &Features,
+    palette: &BlockPalette,
+    road_width: i64,
+    town_name: &str,
+    rng: &mut StdRng,
+) {
+    let (x_len, _, z_len) = excerpt.dim();
+    let ground_at = |BlockColumnCoord(x, z): BlockColumnCoord| -> Option<i64> {
+        if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 {
+            return None;
+        }
+        let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+        Some(y as i64)
+    };
+    let road_column: BlockColumnCoord = station.road_point.into();
+    let side = geometry::offset(station.lodge_side);
+    let half_width = road_width / 2 + 1;
+
+    let across = |distance: i64| {
+        BlockColumnCoord(road_column.0 + side.0 * distance, road_column.1 + side.2 * distance)
+    };
+
+    // Posts on each side of the road, with the bar across the road between them.
+    for (column, is_main_post) in &[(across(half_width), true), (across(-half_width), false)] {
+        if let Some(y) = ground_at(*column) {
+            for post_y in y..y + 2 {
+                excerpt.set_block_at(BlockCoord(column.0, post_y, column.1), palette.palisade.clone());
+            }
+            if *is_main_post {
+                excerpt.set_block_at(
+                    BlockCoord(column.0, y + 2, column.1),
+                    Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+                );
+                // Sign facing travellers on their way into town
+                let sign_coordinates = BlockCoord(column.0, y + 1, column.1)
+                    + geometry::offset(station.towards_town.opposite());
+                excerpt.set_block_at(
+                    sign_coordinates,
+                    signs::sign(
+                        WallOrRotatedOnFloor::Wall(station.towards_town),
+                        &["Toll".to_string(), String::new(), town_name.to_string()],
+                    ),
+                );
+            }
+        }
+    }
+    // The bar is left open, for the traffic to pass.
+    for distance in -(half_width - 1)..half_width {
+        let column = across(distance);
+        if let Some(y) = ground_at(column) {
+            excerpt.set_block_at(BlockCoord(column.0, y, column.1), Block::FenceGate {
+                material: WoodMaterial::Spruce,
+                facing: station.towards_town,
+                open: true,
+            });
+        }
+    }
+
+    // The guard lodge, next to the main post, with its door towards the road.
+    let lodge_distance = half_width + 2;
+    let lodge_center = across(lodge_distance + LODGE_SIZE.0 / 2);
+    let lodge_origin = BlockColumnCoord(lodge_center.0 - LODGE_SIZE.0 / 2, lodge_center.1 - LODGE_SIZE.1 / 2);
+
+    let mut lodge_ground = Vec::new();
+    for x in lodge_origin.0..lodge_origin.0 + LODGE_SIZE.0 {
+        for z in lodge_origin.1..lodge_origin.1 + LODGE_SIZE.1 {
+            match ground_at(BlockColumnCoord(x, z)) {
+                Some(_) if features.water[(x as u32, z as u32)] == image::Luma([255u8]) => return,
+                Some(y) => lodge_ground.push(y),
+                None => return,
+            }
+        }
+    }
+    let floor_y = match lodge_ground.iter().max() {
+        Some(y) => *y,
+        None => return,
+    };
+
+    let lodge = hut::build_hut(LODGE_SIZE, station.lodge_side.opposite(), palette, false, rng);
+    paste_chopping_trees(
+        excerpt,
+        BlockCoord(lodge_origin.0, floor_y - HUT_FOUNDATION_DEPTH, lodge_origin.1),
+        &lodge,
+    );
+}
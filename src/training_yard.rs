@@ -0,0 +1,213 @@
+//! Training yard for the town guard, near the barracks over the gates: A fenced yard of
+//! trampled earth, with archery targets on hay bales at the far end, weapon stands along
+//! the fence, and a small pavilion by the gate for shade.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, WoodMaterial};
+use mcprogedit::positioning::{Axis3, Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::block_palette::BlockPalette;
+use crate::build_area::BuildArea;
+use crate::geometry::{self, directions};
+use crate::signs;
+
+/// Smallest area inside the fence worth laying out a training yard on.
+const YARD_AREA_MIN: usize = 48;
+/// Most archery targets in the row at the far end of the yard.
+const TARGETS_MAX: i64 = 3;
+/// Distance between the archery targets in the row.
+const TARGET_SPACING: i64 = 2;
+/// Most weapon stands along the fence.
+const WEAPON_STANDS_MAX: usize = 3;
+/// Width and length of the pavilion.
+const PAVILION_SIZE: usize = 3;
+/// Height from the ground to the roof of the pavilion.
+const PAVILION_HEIGHT: i64 = 3;
+/// Chance for each column of the yard to be trampled down to bare earth.
+const TRAMPLED_CHANCE: f64 = 0.6;
+
+/// Lay out a training yard on the buildable part of the plot, with its gate towards the
+/// street. Returns the yard, or `None` if the plot is too small or has no street along it.
+pub fn build_training_yard(
+    excerpt: &WorldExcerpt,
+    build_area: &BuildArea,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) -> Option<WorldExcerpt> {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut output = WorldExcerpt::new(x_len, y_len, z_len);
+    let height_map = excerpt.ground_height_map();
+    let ground = |(x, z): (usize, usize)| height_map.height_at((x, z)).map(|y| y as i64);
+
+    let edge = build_area.buildable_edge_coordinates();
+    let inside: HashSet<(usize, usize)> = build_area.buildable_coordinates().difference(&edge).copied().collect();
+    if inside.len() < YARD_AREA_MIN {
+        return None;
+    }
+    let mut inside_sorted: Vec<(usize, usize)> = inside.iter().copied().collect();
+    inside_sorted.sort_unstable();
+    let mut edge_sorted: Vec<(usize, usize)> = edge.iter().copied().collect();
+    edge_sorted.sort_unstable();
+
+    // The gate, in the middle of the stretch of fence along the street
+    let gates: Vec<((usize, usize), Surface4)> = edge_sorted.iter()
+        .filter_map(|column| street_side(build_area, *column).map(|side| (*column, side)))
+        .collect();
+    let (gate, gate_side) = *gates.get(gates.len() / 2)?;
+
+    // Fence around the yard
+    for column in &edge_sorted {
+        let y = match ground(*column) {
+            Some(y) => y,
+            None => continue,
+        };
+        let coordinates = BlockCoord(column.0 as i64, y, column.1 as i64);
+        if *column == gate {
+            output.set_block_at(coordinates, Block::FenceGate {
+                material: WoodMaterial::Spruce,
+                facing: gate_side,
+                open: false,
+            });
+        } else {
+            output.set_block_at(coordinates, Block::Fence { material: FenceMaterial::Spruce, waterlogged: false });
+        }
+    }
+
+    // Trampled earth, cleared of grass and flowers
+    for column in &inside_sorted {
+        let y = match ground(*column) {
+            Some(y) => y,
+            None => continue,
+        };
+        output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), Block::Air);
+        let surface = BlockCoord(column.0 as i64, y - 1, column.1 as i64);
+        if excerpt.block_at(surface) == Some(&Block::GrassBlock) && rng.gen_bool(TRAMPLED_CHANCE) {
+            output.set_block_at(surface, Block::CoarseDirt);
+        }
+    }
+    let mut taken: HashSet<(usize, usize)> = HashSet::new();
+    let inside_gate = column_towards(gate, gate_side.opposite());
+    taken.extend(inside_gate);
+
+    // Archery targets on hay bales, in a row at the far end of the yard, facing the gate
+    let distance_squared = |a: (usize, usize), b: (usize, usize)| {
+        let (dx, dz) = (a.0 as i64 - b.0 as i64, a.1 as i64 - b.1 as i64);
+        dx * dx + dz * dz
+    };
+    let far_end = *inside_sorted.iter().max_by_key(|column| distance_squared(**column, gate))?;
+    let towards_gate = signs::surface_from_vector(
+        gate.0 as f32 - far_end.0 as f32,
+        gate.1 as f32 - far_end.1 as f32,
+    );
+    let across = geometry::offset(towards_gate.rotated_90_cw());
+    let mut target_count = 0;
+    for index in 0..TARGETS_MAX {
+        let distance = (index + 1) / 2 * TARGET_SPACING * if index % 2 == 0 { 1 } else { -1 };
+        let column = (far_end.0 as i64 + across.0 * distance, far_end.1 as i64 + across.2 * distance);
+        if column.0 < 0 || column.1 < 0 || !inside.contains(&(column.0 as usize, column.1 as usize)) {
+            continue;
+        }
+        let column = (column.0 as usize, column.1 as usize);
+        if let Some(y) = ground(column) {
+            output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), Block::HayBale { alignment: Axis3::Y });
+            output.set_block_at(BlockCoord(column.0 as i64, y + 1, column.1 as i64), Block::Target);
+            taken.insert(column);
+            target_count += 1;
+        }
+    }
+    if target_count == 0 {
+        return None;
+    }
+
+    // Pavilion by the gate, for shade: A roof on corner posts, with a barrel of arrows
+    let pavilion = inside_sorted.iter()
+        .map(|corner| {
+            (0..PAVILION_SIZE)
+                .flat_map(|dx| (0..PAVILION_SIZE).map(move |dz| (corner.0 + dx, corner.1 + dz)))
+                .collect::<Vec<(usize, usize)>>()
+        })
+        .filter(|columns| columns.iter().all(|column| inside.contains(column) && !taken.contains(column)))
+        .min_by_key(|columns| distance_squared(columns[PAVILION_SIZE * PAVILION_SIZE / 2], gate));
+    if let Some(columns) = pavilion {
+        let roof_y = columns.iter().filter_map(|column| ground(*column)).max().unwrap_or(0) + PAVILION_HEIGHT;
+        let (min, max) = (columns[0], columns[columns.len() - 1]);
+        for column in &columns {
+            let floor_y = ground(*column).unwrap_or(roof_y - PAVILION_HEIGHT);
+            let is_corner = (column.0 == min.0 || column.0 == max.0) && (column.1 == min.1 || column.1 == max.1);
+            for y in floor_y..roof_y {
+                let block = if is_corner {
+                    Block::Fence { material: FenceMaterial::Spruce, waterlogged: false }
+                } else {
+                    Block::Air
+                };
+                output.set_block_at(BlockCoord(column.0 as i64, y, column.1 as i64), block);
+            }
+            output.set_block_at(BlockCoord(column.0 as i64, roof_y, column.1 as i64), Block::bottom_slab(palette.roof_stairs));
+            taken.insert(*column);
+        }
+        let middle = columns[PAVILION_SIZE * PAVILION_SIZE / 2];
+        if let Some(y) = ground(middle) {
+            output.set_block_at(BlockCoord(middle.0 as i64, y, middle.1 as i64), Block::barrel(Surface6::Up));
+        }
+    }
+
+    // Weapon stands along the fence, and a grindstone for keeping the blades sharp
+    let along_fence: Vec<((usize, usize), Surface4)> = inside_sorted.iter()
+        .filter(|column| !taken.contains(column))
+        .filter_map(|column| {
+            directions().iter()
+                .find(|direction| column_towards(*column, **direction).map_or(false, |next| edge.contains(&next)))
+                .map(|direction| (*column, *direction))
+        })
+        .collect();
+    let mut stand_count = 0;
+    for (column, towards_fence) in along_fence {
+        if stand_count > WEAPON_STANDS_MAX {
+            break;
+        }
+        let is_spaced = directions().iter()
+            .filter_map(|direction| column_towards(column, *direction))
+            .all(|neighbour| !taken.contains(&neighbour));
+        let y = match ground(column) {
+            Some(y) if is_spaced => y,
+            _ => continue,
+        };
+        let coordinates = BlockCoord(column.0 as i64, y, column.1 as i64);
+        if stand_count == WEAPON_STANDS_MAX {
+            output.set_block_at(coordinates, Block::grindstone(towards_fence.opposite()));
+        } else {
+            for height in 0..2 {
+                output.set_block_at(
+                    coordinates + BlockCoord(0, height, 0),
+                    Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+                );
+            }
+        }
+        taken.insert(column);
+        stand_count += 1;
+    }
+
+    Some(output)
+}
+
+/// The side of the column the street is on, if there is a street next to it.
+fn street_side(build_area: &BuildArea, column: (usize, usize)) -> Option<Surface4> {
+    directions().iter().copied().find(|direction| {
+        column_towards(column, *direction)
+            .and_then(|next| build_area.designation_at(next))
+            .map_or(false, |designation| designation.is_road())
+    })
+}
+
+/// The column next to `column` in the given direction, unless that is off the edge of the area.
+fn column_towards(column: (usize, usize), direction: Surface4) -> Option<(usize, usize)> {
+    let next = geometry::step(BlockColumnCoord(column.0 as i64, column.1 as i64), direction, 1);
+    Some((usize::try_from(next.0).ok()?, usize::try_from(next.1).ok()?))
+}
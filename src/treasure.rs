@@ -0,0 +1,321 @@
+//! Hidden treasures, for exploring the town: Chests hidden under floorboards, in a
+//! crypt under the church and behind waterfalls, each with a book of town lore.
+
+use mcprogedit::block::{Block, Chest};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::inventory::Inventory;
+use mcprogedit::item::{Item, ItemStack, WrittenBook};
+use mcprogedit::positioning::{Surface2, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::generation_context::Building;
+use crate::geometry;
+use crate::tree;
+
+/// Half the width of the crypt, from its middle to the inside of the walls.
+const CRYPT_HALF_WIDTH: i64 = 2;
+/// Height of the crypt, from its floor to its ceiling, in blocks of air.
+const CRYPT_HEIGHT: i64 = 3;
+/// Depth of the crypt ceiling below the church floor, in blocks.
+const CRYPT_DEPTH: i64 = 2;
+/// Shortest fall of water counting as a waterfall.
+const WATERFALL_HEIGHT_MIN: i64 = 3;
+
+/// Where a treasure is hidden.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HidingPlace {
+    UnderFloorboards,
+    Crypt,
+    BehindWaterfall,
+}
+
+/// What the town lore books may tell about.
+pub struct Lore<'a> {
+    pub town_name: &'a str,
+    pub street_names: &'a [String],
+    pub district_names: &'a [String],
+    pub founder: String,
+    pub saint: String,
+}
+
+/// Hide up to `count` treasure chests around the town, each with a book of town lore:
+/// In a crypt under the church on the plot `church_plot`, behind the waterfall closest
+/// to the town center, and under the floorboards of houses for the rest. Returns the
+/// number of treasures hidden.
+pub fn hide_treasures(
+    excerpt: &mut WorldExcerpt,
+    buildings: &[Building],
+    church_plot: Option<usize>,
+    town_center: BlockColumnCoord,
+    lore: &Lore,
+    count: usize,
+    rng: &mut StdRng,
+) -> usize {
+    let mut hidden: Vec<(BlockCoord, Surface4, HidingPlace)> = Vec::new();
+
+    // In a crypt under the church
+    let church = church_plot
+        .and_then(|index| buildings.iter().find(|building| building.plot_index == index));
+    if let Some(church) = church {
+        if let Some(door) = church.doors.first() {
+            let front = door.facing.opposite();
+            let center = geometry::polygon_center(&church.footprint);
+            let chest_at = dig_crypt(excerpt, center, door.coordinates.1, front);
+            hidden.push((chest_at, front, HidingPlace::Crypt));
+        }
+    }
+
+    // Behind the waterfall closest to the town center
+    let waterfall = find_waterfalls(excerpt)
+        .into_iter()
+        .min_by_key(|(foot, _)| geometry::manhattan_distance((*foot).into(), town_center));
+    if let Some((foot, behind)) = waterfall {
+        let chest_at = carve_nook(excerpt, foot, behind);
+        hidden.push((chest_at, behind.opposite(), HidingPlace::BehindWaterfall));
+    }
+
+    // Under the floorboards of houses picked at random, for the rest
+    let mut houses: Vec<&Building> = buildings.iter()
+        .filter(|building| Some(building.plot_index) != church_plot && !building.doors.is_empty())
+        .collect();
+    houses.shuffle(rng);
+    for house in houses {
+        if hidden.len() >= count {
+            break;
+        }
+        let floor_y = house.doors.iter().map(|door| door.coordinates.1).min().unwrap();
+        let doors: Vec<BlockColumnCoord> = house.doors.iter().map(|door| door.coordinates.into()).collect();
+        if let Some(chest_at) = under_floorboards(excerpt, &house.footprint, floor_y, &doors, rng) {
+            hidden.push((chest_at, Surface4::North, HidingPlace::UnderFloorboards));
+        }
+    }
+
+    hidden.truncate(count);
+    for (chest_at, facing, hiding_place) in &hidden {
+        let book = lore_book(lore, *hiding_place, rng);
+        let chest = treasure_chest(*facing, book, rng);
+        excerpt.set_block_at(*chest_at, chest);
+    }
+    hidden.len()
+}
+
+/// Write a book of town lore, fitting for where it is hidden.
+pub fn lore_book(lore: &Lore, hiding_place: HidingPlace, rng: &mut StdRng) -> WrittenBook {
+    let street = lore.street_names.choose(rng).map(String::as_str).unwrap_or("the square");
+    let district = lore.district_names.choose(rng).map(String::as_str).unwrap_or("the town");
+    let year = rng.gen_range(900..1300);
+
+    let (title, pages) = match hiding_place {
+        HidingPlace::UnderFloorboards => (
+            format!("Diary from {}", street),
+            vec![
+                format!(
+                    "I have kept my savings under the floor, as my father did before me. \
+                     No one in {} trusts the moneylenders of {}.",
+                    lore.town_name, district,
+                ),
+                format!(
+                    "Should anything befall me, let whoever finds this remember that the \
+                     house on {} was built honestly, stone by stone.",
+                    street,
+                ),
+            ],
+        ),
+        HidingPlace::Crypt => (
+            format!("Chronicle of {}", lore.town_name),
+            vec![
+                format!(
+                    "In the year {}, {} led our people to this place, and founded {} \
+                     where the land was good.",
+                    year, lore.founder, lore.town_name,
+                ),
+                format!(
+                    "The church was raised in honour of {}, and {} rests beneath it, \
+                     with the wealth of the founding families.",
+                    lore.saint, lore.founder,
+                ),
+                format!("May {} guard {} for all time.", lore.saint, lore.town_name),
+            ],
+        ),
+        HidingPlace::BehindWaterfall => (
+            String::from("The Smuggler's Ledger"),
+            vec![
+                format!(
+                    "The toll keepers of {} never think to look behind the falling water. \
+                     Here the goods wait until the watch of {} grows lazy.",
+                    lore.town_name, district,
+                ),
+                format!("Meet by {} at midnight, and bring the cart.", street),
+            ],
+        ),
+    };
+
+    WrittenBook { title, author: lore.founder.clone(), pages }
+}
+
+/// A chest facing `facing`, holding the book and a handful of valuables.
+pub fn treasure_chest(facing: Surface4, book: WrittenBook, rng: &mut StdRng) -> Block {
+    let mut items = Inventory::new();
+    items.insert(0, ItemStack::new(Item::WrittenBook(book), 1));
+    let valuables = [Item::GoldIngot, Item::Emerald, Item::Diamond, Item::IronIngot];
+    for slot in 1..=rng.gen_range(2..=4) {
+        let valuable = valuables.choose(rng).unwrap().clone();
+        items.insert(slot * 4, ItemStack::new(valuable, rng.gen_range(1..=8)));
+    }
+    Block::Chest(Box::new(Chest {
+        facing,
+        variant: None,
+        waterlogged: false,
+        custom_name: None,
+        lock: None,
+        items,
+    }))
+}
+
+/// Find a spot under the floorboards of a house, with the given footprint and its lowest
+/// floor at `floor_y`: A column with a floor to lift up and room to stand on it, out of
+/// the way of the doors. Returns where the chest goes, under the floor.
+pub fn under_floorboards(
+    excerpt: &WorldExcerpt,
+    footprint: &[BlockColumnCoord],
+    floor_y: i64,
+    doors: &[BlockColumnCoord],
+    rng: &mut StdRng,
+) -> Option<BlockCoord> {
+    let mut candidates: Vec<BlockCoord> = footprint.iter()
+        .filter(|column| {
+            doors.iter().all(|door| geometry::manhattan_distance(**column, *door) > 1)
+        })
+        .map(|column| BlockCoord(column.0, floor_y, column.1))
+        .filter(|coordinates| {
+            let below = |depth: i64| excerpt.block_at(*coordinates - BlockCoord(0, depth, 0));
+            excerpt.block_at(*coordinates) == Some(&Block::Air)
+                && is_solid(below(1))
+                && is_solid(below(2))
+        })
+        .collect();
+    candidates.shuffle(rng);
+    candidates.first().map(|coordinates| *coordinates - BlockCoord(0, 2, 0))
+}
+
+/// Dig a sealed crypt under the middle of the church with its floor at `floor_y`, walled
+/// in stone bricks, with tombs along the sides and lanterns in the corners. Returns where
+/// the chest goes, against the back wall, facing `front`.
+pub fn dig_crypt(excerpt: &mut WorldExcerpt, center: BlockColumnCoord, floor_y: i64, front: Surface4) -> BlockCoord {
+    let ceiling_y = floor_y - 1 - CRYPT_DEPTH;
+    let bottom_y = ceiling_y - CRYPT_HEIGHT - 1;
+    let outer = CRYPT_HALF_WIDTH + 1;
+
+    for dx in -outer..=outer {
+        for dz in -outer..=outer {
+            let (x, z) = (center.0 + dx, center.1 + dz);
+            let is_wall = dx.abs() == outer || dz.abs() == outer;
+            for y in bottom_y..=ceiling_y {
+                let block = if is_wall || y == bottom_y || y == ceiling_y {
+                    Block::StoneBricks
+                } else {
+                    Block::Air
+                };
+                tree::chop(excerpt, BlockCoord(x, y, z));
+                excerpt.set_block_at(BlockCoord(x, y, z), block);
+            }
+        }
+    }
+
+    // Tombs along the sides, and lanterns in the corners
+    let ahead = geometry::offset(front);
+    let side = geometry::offset(front.rotated_90_cw());
+    for across in &[-CRYPT_HALF_WIDTH, CRYPT_HALF_WIDTH] {
+        for along in -1..=1 {
+            excerpt.set_block_at(
+                BlockCoord(
+                    center.0 + ahead.0 * along + side.0 * across,
+                    bottom_y + 1,
+                    center.1 + ahead.2 * along + side.2 * across,
+                ),
+                Block::StoneBricks,
+            );
+        }
+    }
+    for dx in &[-CRYPT_HALF_WIDTH, CRYPT_HALF_WIDTH] {
+        for dz in &[-CRYPT_HALF_WIDTH, CRYPT_HALF_WIDTH] {
+            let corner = BlockCoord(center.0 + dx, bottom_y + 1, center.1 + dz);
+            if excerpt.block_at(corner) == Some(&Block::Air) {
+                excerpt.set_block_at(corner, Block::Lantern { mounted_at: Surface2::Down, waterlogged: false });
+            }
+        }
+    }
+
+    let back = geometry::offset(front.opposite());
+    BlockCoord(center.0 + back.0 * CRYPT_HALF_WIDTH, bottom_y + 1, center.1 + back.2 * CRYPT_HALF_WIDTH)
+}
+
+/// Find waterfalls in the excerpt: Water falling at least `WATERFALL_HEIGHT_MIN` blocks
+/// down a face of solid ground. Returns the foot of each waterfall, and the direction
+/// from the waterfall into the ground behind it.
+pub fn find_waterfalls(excerpt: &WorldExcerpt) -> Vec<(BlockCoord, Surface4)> {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut waterfalls = Vec::new();
+
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            let mut fall_height = 0;
+            for y in (0..y_len as i64).rev() {
+                let coordinates = BlockCoord(x, y, z);
+                if let Some(Block::Water { .. }) = excerpt.block_at(coordinates) {
+                    fall_height += 1;
+                    continue;
+                }
+                if fall_height >= WATERFALL_HEIGHT_MIN {
+                    let foot = coordinates + BlockCoord(0, 1, 0);
+                    let behind = [Surface4::North, Surface4::East, Surface4::South, Surface4::West]
+                        .iter()
+                        .copied()
+                        .find(|direction| {
+                            (0..2).all(|up| {
+                                let ground = foot + geometry::offset(*direction) + BlockCoord(0, up, 0);
+                                is_solid(excerpt.block_at(ground))
+                            })
+                        });
+                    if let Some(direction) = behind {
+                        waterfalls.push((foot, direction));
+                    }
+                }
+                fall_height = 0;
+            }
+        }
+    }
+
+    waterfalls
+}
+
+/// Carve a nook into the ground behind the foot of the waterfall.
+/// Returns where the chest goes, at the back of the nook.
+pub fn carve_nook(excerpt: &mut WorldExcerpt, foot: BlockCoord, behind: Surface4) -> BlockCoord {
+    let step = geometry::offset(behind);
+    for depth in 1..=2 {
+        for up in 0..2 {
+            excerpt.set_block_at(
+                foot + BlockCoord(step.0 * depth, up, step.2 * depth),
+                Block::Air,
+            );
+        }
+    }
+    foot + BlockCoord(step.0 * 2, 0, step.2 * 2)
+}
+
+/// Whether the block is solid enough to hide something in or behind.
+fn is_solid(block: Option<&Block>) -> bool {
+    !matches!(
+        block,
+        None | Some(Block::Air)
+            | Some(Block::None)
+            | Some(Block::WaterSource)
+            | Some(Block::Water { .. })
+            | Some(Block::LavaSource)
+            | Some(Block::Lava { .. })
+    ) && !block.map(Block::is_foilage).unwrap_or(false)
+}
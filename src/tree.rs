@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 
+use log::trace;
 use mcprogedit::block::{Block, Log};
 use mcprogedit::coordinates::BlockCoord;
 use mcprogedit::material::{LeavesMaterial, WoodMaterial};
@@ -19,11 +20,9 @@ pub fn _prune(_excerpt: &mut WorldExcerpt, _at: BlockCoord) {
 pub fn chop(excerpt: &mut WorldExcerpt, at: BlockCoord) {
     let to_chop = find_tree(excerpt, &at);
 
-    /*
-    if to_chop.len() > 0 {
-        println!("Found a tree, need to remove {} blocks!", to_chop.len());
+    if !to_chop.is_empty() {
+        trace!("Found a tree, need to remove {} blocks!", to_chop.len());
     }
-    */
 
     for coordinates in to_chop {
         excerpt.set_block_at(coordinates, Block::Air);
@@ -0,0 +1,431 @@
+//! Cairns, shrines and beacon pyres on the high points overlooking town, each with
+//! a footpath from the nearest country road, and chains of signal pyres between the towns.
+
+use std::cmp::Reverse;
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::info;
+use mcprogedit::block::{Block, WallOrRotatedOnFloor};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, Material};
+use mcprogedit::positioning::{Surface2, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::config::GenerationConfig;
+use crate::features::Features;
+use crate::geometry;
+use crate::naming;
+use crate::pathfinding::{self, RoadPath};
+use crate::road;
+use crate::signs;
+use crate::tree;
+use crate::types::Snake;
+
+/// Largest number of viewpoints around town.
+const VIEWPOINT_COUNT_MAX: usize = 2;
+/// Farthest a viewpoint may be from the town center.
+const TOWN_DISTANCE_MAX: f32 = 96.0;
+/// Distance the viewpoints must keep from the towns.
+const CLEARANCE: u8 = 8;
+/// Shortest distance between two viewpoints, or between a viewpoint and another landmark.
+const VIEWPOINT_SPACING: usize = 48;
+/// Distance from the viewpoint to the ring of terrain it must overlook.
+const VIEW_RADIUS: f32 = 12.0;
+/// Number of directions in which the view is checked.
+const VIEW_DIRECTIONS: usize = 16;
+/// Smallest drop from the viewpoint to the terrain it overlooks, in every direction.
+const VIEW_DROP_MIN: i64 = 2;
+/// Largest difference in ground height under the structure.
+const SITE_SLOPE_MAX: i64 = 1;
+/// Longest footpath from a viewpoint to the nearest country road, counted in blocks.
+const FOOTPATH_LENGTH_MAX: usize = 64;
+/// Distance between the sites considered for signal pyres.
+const SIGNAL_SITE_SPACING: usize = 4;
+/// Distance the signal pyres must keep from other structures.
+const SIGNAL_CLEARANCE: usize = 16;
+/// Height of the fire of a signal pyre, above the ground.
+const SIGNAL_FIRE_HEIGHT: i64 = 5;
+/// Height above the ground at the town center, from which the signals are watched.
+const TOWN_LOOKOUT_HEIGHT: i64 = 12;
+/// Largest number of signal pyres between two towns.
+const SIGNAL_CHAIN_LENGTH_MAX: usize = 8;
+
+/// What is built at a viewpoint.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ViewpointKind {
+    /// A pile of stones.
+    Cairn,
+    /// A small roofed altar, with a lantern and the name of a saint.
+    Shrine,
+    /// A brazier on a pillar, lit for signalling.
+    BeaconPyre,
+    /// A beacon pyre on a raised platform, one in a chain of signals between towns.
+    SignalPyre,
+}
+
+/// A structure on a high point near town, and the footpath up to it.
+#[derive(Clone, Debug)]
+pub struct Viewpoint {
+    pub kind: ViewpointKind,
+    /// Middle of the structure.
+    pub center: BlockColumnCoord,
+    /// Y coordinate of the first block above the highest ground under the structure.
+    pub ground_y: i64,
+    /// Direction from the middle of the structure towards its footpath.
+    pub front: Surface4,
+    /// Footpath from the front of the structure to the nearest country road, if one was found.
+    pub path: Option<RoadPath>,
+}
+
+/// Find the high points near town with the best view of their surroundings,
+/// keeping clear of the towns and of `landmarks`, the other structures around town.
+pub fn plan_viewpoints(
+    features: &Features,
+    wall_circles: &[&Snake],
+    town_center: BlockColumnCoord,
+    country_roads: &[RoadPath],
+    landmarks: &[BlockColumnCoord],
+    rng: &mut StdRng,
+) -> Vec<Viewpoint> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    let occupied = occupied_by_towns(features, wall_circles);
+
+    let road_nodes = road_nodes(country_roads);
+    if road_nodes.is_empty() {
+        return Vec::new();
+    }
+
+    // Candidate sites, scored by how far the terrain around them drops away.
+    let margin = VIEW_RADIUS.ceil() as i64 + 1;
+    let mut candidates: Vec<(i64, BlockColumnCoord, i64)> = Vec::new();
+    for x in margin..x_len - margin {
+        for z in margin..z_len - margin {
+            let center = BlockColumnCoord(x, z);
+            if geometry::euclidean_distance(center, town_center) > TOWN_DISTANCE_MAX {
+                continue;
+            }
+            let ground_y = match site_ground(features, &occupied, center) {
+                Some(ground_y) => ground_y,
+                None => continue,
+            };
+            if let Some(drop) = view_drop(features, center, ground_y) {
+                candidates.push((drop, center, ground_y));
+            }
+        }
+    }
+    candidates.sort_unstable_by_key(|(drop, center, _)| (Reverse(*drop), center.0, center.1));
+
+    let mut taken: Vec<BlockColumnCoord> = landmarks.to_vec();
+    let mut viewpoints = Vec::new();
+    for (_, center, ground_y) in candidates {
+        if viewpoints.len() >= VIEWPOINT_COUNT_MAX {
+            break;
+        }
+        if taken.iter().any(|other| geometry::manhattan_distance(center, *other) < VIEWPOINT_SPACING) {
+            continue;
+        }
+
+        let (front, path) = match footpath(features, center, &road_nodes) {
+            Some(footpath) => footpath,
+            None => continue,
+        };
+
+        let kind = match rng.gen_range(0..3) {
+            0 => ViewpointKind::Cairn,
+            1 => ViewpointKind::Shrine,
+            _ => ViewpointKind::BeaconPyre,
+        };
+        info!("Planned a {:?} at the viewpoint at {:?}.", kind, center);
+        taken.push(center);
+        viewpoints.push(Viewpoint { kind, center, ground_y, front, path });
+    }
+
+    viewpoints
+}
+
+/// Plan chains of signal pyres between neighbouring towns, on hilltops within sight
+/// of each other, so that a fire lit in one town can be seen from the next.
+/// Keeps clear of `taken`, the other structures around town.
+pub fn plan_signal_chains(
+    features: &Features,
+    town_centers: &[BlockColumnCoord],
+    wall_circles: &[&Snake],
+    country_roads: &[RoadPath],
+    taken: &[BlockColumnCoord],
+) -> Vec<Viewpoint> {
+    if town_centers.len() < 2 {
+        return Vec::new();
+    }
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+    let occupied = occupied_by_towns(features, wall_circles);
+    let road_nodes = road_nodes(country_roads);
+
+    // Hilltops with room for a pyre, at the height of its fire
+    let mut sites: Vec<BlockCoord> = Vec::new();
+    for x in (3..x_len - 3).step_by(SIGNAL_SITE_SPACING) {
+        for z in (3..z_len - 3).step_by(SIGNAL_SITE_SPACING) {
+            let center = BlockColumnCoord(x, z);
+            if features.hilltop[(x as u32, z as u32)] == image::Luma([0u8])
+                || taken.iter().any(|other| geometry::manhattan_distance(center, *other) < SIGNAL_CLEARANCE)
+            {
+                continue;
+            }
+            if let Some(ground_y) = site_ground(features, &occupied, center) {
+                sites.push(BlockCoord(x, ground_y + SIGNAL_FIRE_HEIGHT, z));
+            }
+        }
+    }
+
+    let lookout = |center: BlockColumnCoord| {
+        let image::Luma([y]) = features.terrain[(center.0 as u32, center.1 as u32)];
+        BlockCoord(center.0, y as i64 + TOWN_LOOKOUT_HEIGHT, center.1)
+    };
+
+    let mut pyres: Vec<BlockCoord> = Vec::new();
+    for (from, to) in geometry::relative_neighbourhood_graph(town_centers) {
+        let (start, goal) = (lookout(town_centers[from]), lookout(town_centers[to]));
+        let mut chain = Vec::new();
+        let mut current = start;
+        let mut complete = true;
+
+        // Hop to the visible site closest to the goal, until the goal is in sight.
+        while !line_of_sight(features, current, goal) {
+            let remaining = geometry::manhattan_distance(current.into(), goal.into());
+            let next = sites.iter()
+                .filter(|site| geometry::manhattan_distance((**site).into(), goal.into()) < remaining)
+                .filter(|site| line_of_sight(features, current, **site))
+                .min_by_key(|site| geometry::manhattan_distance((**site).into(), goal.into()));
+            match next {
+                Some(site) if chain.len() < SIGNAL_CHAIN_LENGTH_MAX => {
+                    chain.push(*site);
+                    current = *site;
+                }
+                _ => {
+                    complete = false;
+                    break;
+                }
+            }
+        }
+
+        if !complete {
+            info!("Found no chain of signal pyres between {:?} and {:?}.", town_centers[from], town_centers[to]);
+            continue;
+        }
+        for site in chain {
+            if !pyres.contains(&site) {
+                pyres.push(site);
+            }
+        }
+    }
+
+    pyres.into_iter()
+        .map(|site| {
+            let center = BlockColumnCoord(site.0, site.2);
+            let ground_y = site.1 - SIGNAL_FIRE_HEIGHT;
+            let (front, path) = footpath(features, center, &road_nodes).unwrap_or((Surface4::North, None));
+            info!("Planned a signal pyre at {:?}.", center);
+            Viewpoint { kind: ViewpointKind::SignalPyre, center, ground_y, front, path }
+        })
+        .collect()
+}
+
+/// Whether the terrain stays below the straight line between the two points.
+fn line_of_sight(features: &Features, from: BlockCoord, to: BlockCoord) -> bool {
+    let (dx, dy, dz) = (to.0 - from.0, to.1 - from.1, to.2 - from.2);
+    let steps = dx.abs().max(dz.abs());
+    (1..steps).all(|step| {
+        let t = step as f32 / steps as f32;
+        let x = (from.0 as f32 + t * dx as f32).round() as u32;
+        let z = (from.2 as f32 + t * dz as f32).round() as u32;
+        let image::Luma([y]) = features.terrain[(x, z)];
+        (y as f32) <= from.1 as f32 + t * dy as f32
+    })
+}
+
+/// The towns, with some clearance around them.
+fn occupied_by_towns(features: &Features, wall_circles: &[&Snake]) -> GrayImage {
+    let (x_len, z_len) = features.dimensions();
+    let mut occupied = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut occupied, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+    imageproc::morphology::dilate(&occupied, Norm::LInf, CLEARANCE)
+}
+
+fn road_nodes(roads: &[RoadPath]) -> Vec<BlockCoord> {
+    roads.iter()
+        .flat_map(|road| road.iter().map(|node| node.coordinates))
+        .collect()
+}
+
+/// Footpath from the front of the structure at `center` to the nearest road node,
+/// and the direction of the front. None if there is no road node close enough.
+fn footpath(
+    features: &Features,
+    center: BlockColumnCoord,
+    road_nodes: &[BlockCoord],
+) -> Option<(Surface4, Option<RoadPath>)> {
+    let node = *road_nodes.iter()
+        .min_by_key(|node| geometry::manhattan_distance(center, (**node).into()))?;
+    if geometry::manhattan_distance(center, node.into()) > FOOTPATH_LENGTH_MAX {
+        return None;
+    }
+    let front = signs::surface_from_vector((node.0 - center.0) as f32, (node.2 - center.1) as f32);
+    let path_start = BlockCoord(center.0, 0, center.1) + geometry::offset(front) + geometry::offset(front);
+    let image::Luma([start_y]) = features.terrain[(path_start.0 as u32, path_start.2 as u32)];
+    let path_start = BlockCoord(path_start.0, start_y as i64, path_start.2);
+    let path = pathfinding::road_path(path_start, node, &features.terrain, Some(&features.water));
+    Some((front, path))
+}
+
+/// Check if the structure fits at `center`. Returns the height of the first block above
+/// the highest ground under the structure, if it does.
+fn site_ground(features: &Features, occupied: &GrayImage, center: BlockColumnCoord) -> Option<i64> {
+    let mut min_y = i64::MAX;
+    let mut max_y = i64::MIN;
+
+    for x in center.0 - 1..=center.0 + 1 {
+        for z in center.1 - 1..=center.1 + 1 {
+            let column = (x as u32, z as u32);
+            if occupied[column] == image::Luma([255u8]) || features.water[column] == image::Luma([255u8]) {
+                return None;
+            }
+            let image::Luma([y]) = features.terrain[column];
+            min_y = min_y.min(y as i64);
+            max_y = max_y.max(y as i64);
+        }
+    }
+
+    if max_y - min_y > SITE_SLOPE_MAX {
+        None
+    } else {
+        Some(max_y)
+    }
+}
+
+/// The total drop from `ground_y` to the ring of terrain around `center`,
+/// if the terrain drops away in every direction.
+fn view_drop(features: &Features, center: BlockColumnCoord, ground_y: i64) -> Option<i64> {
+    let mut total_drop = 0;
+    for direction in 0..VIEW_DIRECTIONS {
+        let angle = direction as f32 * std::f32::consts::TAU / VIEW_DIRECTIONS as f32;
+        let x = center.0 + (angle.cos() * VIEW_RADIUS).round() as i64;
+        let z = center.1 + (angle.sin() * VIEW_RADIUS).round() as i64;
+        let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+        let drop = ground_y - y as i64;
+        if drop < VIEW_DROP_MIN {
+            return None;
+        }
+        total_drop += drop;
+    }
+    Some(total_drop)
+}
+
+/// Build the structure at the viewpoint, and the footpath up to it.
+pub fn build_viewpoint(
+    excerpt: &mut WorldExcerpt,
+    viewpoint: &Viewpoint,
+    features: &Features,
+    config: &GenerationConfig,
+    rng: &mut StdRng,
+) {
+    if let Some(path) = &viewpoint.path {
+        road::build_road_in_style(excerpt, path, &features.terrain, &path.class.style(config), rng);
+    }
+
+    // Level stone footing, cleared of vegetation
+    let BlockColumnCoord(cx, cz) = viewpoint.center;
+    let y = viewpoint.ground_y;
+    for x in cx - 1..=cx + 1 {
+        for z in cz - 1..=cz + 1 {
+            for clear_y in y..y + 5 {
+                tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+            }
+            let image::Luma([ground]) = features.terrain[(x as u32, z as u32)];
+            for fill_y in ground as i64 - 1..y {
+                excerpt.set_block_at(BlockCoord(x, fill_y, z), Block::Cobblestone);
+            }
+        }
+    }
+
+    let center = BlockCoord(cx, y, cz);
+    match viewpoint.kind {
+        ViewpointKind::Cairn => build_cairn(excerpt, center, rng),
+        ViewpointKind::Shrine => build_shrine(excerpt, center, viewpoint.front, &naming::saint_name(rng)),
+        ViewpointKind::BeaconPyre => build_beacon_pyre(excerpt, center),
+        ViewpointKind::SignalPyre => {
+            for dx in -1..=1 {
+                for dz in -1..=1 {
+                    excerpt.set_block_at(center + BlockCoord(dx, 0, dz), Block::StoneBricks);
+                }
+            }
+            build_beacon_pyre(excerpt, center + BlockCoord(0, 1, 0));
+        }
+    }
+}
+
+/// A rough pile of stones, narrowing towards the top.
+fn build_cairn(excerpt: &mut WorldExcerpt, center: BlockCoord, rng: &mut StdRng) {
+    let mut stone = || if rng.gen_bool(0.3) { Block::Andesite } else { Block::Cobblestone };
+    for dx in -1..=1i64 {
+        for dz in -1..=1i64 {
+            let is_corner = dx != 0 && dz != 0;
+            excerpt.set_block_at(center + BlockCoord(dx, 0, dz), stone());
+            if !is_corner {
+                excerpt.set_block_at(center + BlockCoord(dx, 1, dz), stone());
+            }
+        }
+    }
+    excerpt.set_block_at(center + BlockCoord(0, 2, 0), stone());
+    excerpt.set_block_at(center + BlockCoord(0, 3, 0), Block::Cobblestone);
+}
+
+/// A stone altar under a slab roof on fence posts, with a lantern,
+/// and a sign with the name of the saint on the side facing the footpath.
+fn build_shrine(excerpt: &mut WorldExcerpt, center: BlockCoord, front: Surface4, saint: &str) {
+    for dx in -1..=1i64 {
+        for dz in -1..=1i64 {
+            excerpt.set_block_at(center + BlockCoord(dx, -1, dz), Block::StoneBricks);
+            if dx != 0 && dz != 0 {
+                for y in 0..2 {
+                    excerpt.set_block_at(
+                        center + BlockCoord(dx, y, dz),
+                        Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+                    );
+                }
+            }
+            excerpt.set_block_at(center + BlockCoord(dx, 2, dz), Block::bottom_slab(Material::Cobblestone));
+        }
+    }
+    excerpt.set_block_at(center, Block::ChiseledStoneBricks);
+    excerpt.set_block_at(
+        center + BlockCoord(0, 1, 0),
+        Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+    );
+
+    let lines = [String::new(), saint.to_string()];
+    excerpt.set_block_at(
+        center + geometry::offset(front),
+        signs::sign(WallOrRotatedOnFloor::Wall(front.opposite()), &lines),
+    );
+}
+
+/// A stone pillar carrying a brazier with a lava fire, seen from afar.
+fn build_beacon_pyre(excerpt: &mut WorldExcerpt, center: BlockCoord) {
+    excerpt.set_block_at(center, Block::Cobblestone);
+    excerpt.set_block_at(center + BlockCoord(0, 1, 0), Block::Cobblestone);
+    for dx in -1..=1i64 {
+        for dz in -1..=1i64 {
+            excerpt.set_block_at(center + BlockCoord(dx, 2, dz), Block::Cobblestone);
+            let rim = if (dx, dz) == (0, 0) { Block::LavaSource } else { Block::Cobblestone };
+            excerpt.set_block_at(center + BlockCoord(dx, 3, dz), rim);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Existing villages within the selection, such as those generated by Minecraft itself:
+//! Found from the doors of their houses, and adopted into the settlement rather than built
+//! over. Their paths are joined to the road network, those close to the town are walled in
+//! with it, and their houses are rebuilt in the materials of the town.
+
+use std::collections::{HashSet, VecDeque};
+
+use image::GrayImage;
+use log::info;
+use mcprogedit::block::{Block, Door, DoorHalf};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_palette::{is_window, BlockPalette};
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::types::Snake;
+
+/// Fewest houses for a group of buildings to count as a village.
+const HOUSE_COUNT_MIN: usize = 3;
+/// Farthest apart the doors of neighbouring houses of a village may be.
+const DOOR_SPACING_MAX: f32 = 32.0;
+/// Largest area of a house, in columns. Larger built up areas are not taken as houses.
+const HOUSE_AREA_MAX: usize = 256;
+/// Deepest below the top of the terrain to look for doors, below the roofs of the houses.
+const DOOR_DEPTH_MAX: u8 = 16;
+/// Height range above the door, in which the blocks of its house are found.
+const HOUSE_HEIGHT: i64 = 10;
+/// Farthest from a door an existing path may be, to count as a path of the village.
+const PATH_REACH: i64 = 16;
+/// Room to keep between the village and the town wall, when walling it in.
+const WALL_MARGIN: i64 = 4;
+
+/// A house of a village.
+#[derive(Clone, Debug)]
+pub struct VillageHouse {
+    /// The columns covered by the house.
+    pub footprint: Vec<BlockColumnCoord>,
+    /// The lower half of the front door.
+    pub door: BlockCoord,
+}
+
+/// A village found in the selection.
+#[derive(Clone, Debug)]
+pub struct Village {
+    pub houses: Vec<VillageHouse>,
+    /// The existing paths between the houses.
+    pub paths: Vec<BlockColumnCoord>,
+    pub center: BlockColumnCoord,
+}
+
+impl Village {
+    /// The column of the village to lead a road to it from `from`: The path of the village
+    /// closest to it, or the door closest to it, if the village has no paths.
+    pub fn road_end(&self, from: BlockColumnCoord) -> BlockColumnCoord {
+        self.paths.iter()
+            .copied()
+            .min_by_key(|column| geometry::manhattan_distance(*column, from))
+            .unwrap_or_else(|| {
+                self.houses.iter()
+                    .map(|house| BlockColumnCoord(house.door.0, house.door.2))
+                    .min_by_key(|column| geometry::manhattan_distance(*column, from))
+                    .unwrap_or(self.center)
+            })
+    }
+}
+
+/// Find the villages in the excerpt: Groups of houses, each found from a door and the
+/// built up columns around it, with the doors of neighbouring houses near each other.
+pub fn find_villages(excerpt: &WorldExcerpt, features: &Features) -> Vec<Village> {
+    let (x_len, z_len) = features.terrain.dimensions();
+
+    // The houses, from the doors found below the tops of the columns
+    let mut houses: Vec<VillageHouse> = Vec::new();
+    let mut in_house: HashSet<BlockColumnCoord> = HashSet::new();
+    for x in 0..x_len {
+        for z in 0..z_len {
+            let column = BlockColumnCoord(x as i64, z as i64);
+            if in_house.contains(&column) || features.is_outside((column.0, column.1)) {
+                continue;
+            }
+            let image::Luma([top]) = features.terrain[(x, z)];
+            let door = (top.saturating_sub(DOOR_DEPTH_MAX)..=top)
+                .map(|y| BlockCoord(column.0, y as i64, column.1))
+                .find(|coordinates| {
+                    matches!(excerpt.block_at(*coordinates), Some(Block::Door(Door { half: DoorHalf::Lower, .. })))
+                });
+            if let Some(door) = door {
+                if let Some(footprint) = house_footprint(excerpt, door) {
+                    in_house.extend(footprint.iter().copied());
+                    houses.push(VillageHouse { footprint, door });
+                }
+            }
+        }
+    }
+
+    // Group the houses into villages, by the distance between their doors
+    let door_column = |house: &VillageHouse| BlockColumnCoord(house.door.0, house.door.2);
+    let mut grouped = vec![false; houses.len()];
+    let mut villages = Vec::new();
+    for first in 0..houses.len() {
+        if grouped[first] {
+            continue;
+        }
+        grouped[first] = true;
+        let mut members = vec![first];
+        let mut queue = VecDeque::from(vec![first]);
+        while let Some(index) = queue.pop_front() {
+            for other in 0..houses.len() {
+                if !grouped[other]
+                    && geometry::euclidean_distance(door_column(&houses[index]), door_column(&houses[other]))
+                        <= DOOR_SPACING_MAX
+                {
+                    grouped[other] = true;
+                    members.push(other);
+                    queue.push_back(other);
+                }
+            }
+        }
+        if members.len() < HOUSE_COUNT_MIN {
+            continue;
+        }
+
+        let village_houses: Vec<VillageHouse> = members.iter().map(|index| houses[*index].clone()).collect();
+        let doors: Vec<BlockColumnCoord> = village_houses.iter().map(door_column).collect();
+        let center = BlockColumnCoord(
+            doors.iter().map(|door| door.0).sum::<i64>() / doors.len() as i64,
+            doors.iter().map(|door| door.1).sum::<i64>() / doors.len() as i64,
+        );
+        let paths = village_paths(features, &doors);
+        info!(
+            "Found a village of {} houses at {:?}, with {} columns of paths.",
+            village_houses.len(),
+            center,
+            paths.len(),
+        );
+        villages.push(Village { houses: village_houses, paths, center });
+    }
+    villages
+}
+
+/// Mark the houses of the villages in the stencil, for keeping roads and streets out of them.
+pub fn mark_houses(stencil: &mut GrayImage, villages: &[Village]) {
+    let (x_len, z_len) = stencil.dimensions();
+    for column in villages.iter().flat_map(|village| &village.houses).flat_map(|house| &house.footprint) {
+        if column.0 >= 0 && column.1 >= 0 && column.0 < x_len as i64 && column.1 < z_len as i64 {
+            stencil.put_pixel(column.0 as u32, column.1 as u32, image::Luma([255u8]));
+        }
+    }
+}
+
+/// Whether any house of the villages stands inside the polygon.
+pub fn has_house_inside(polygon: &[BlockColumnCoord], villages: &[Village]) -> bool {
+    villages.iter()
+        .flat_map(|village| &village.houses)
+        .flat_map(|house| &house.footprint)
+        .any(|column| geometry::point_position_relative_to_polygon(*column, polygon) == InOutSide::Inside)
+}
+
+/// The town circumference, grown to wall in the villages outside it with a house within
+/// `distance_max` of it. The grown circumference is the convex hull of the town and the
+/// villages, keeping the direction of the circumference. `dimensions` is the size of the
+/// excerpt, for keeping the wall off its edges.
+pub fn wall_in_villages(
+    circumference: Snake,
+    villages: &[Village],
+    distance_max: f32,
+    dimensions: (usize, usize),
+) -> Snake {
+    let mut circle = circumference.clone();
+    circle.push(circumference[0]);
+    let is_near = |village: &&Village| {
+        geometry::point_position_relative_to_polygon(village.center, &circle) == InOutSide::Outside
+            && village.houses.iter().flat_map(|house| &house.footprint).any(|column| {
+                circumference.iter().any(|point| geometry::euclidean_distance(*column, *point) <= distance_max)
+            })
+    };
+    let near_villages: Vec<&Village> = villages.iter().filter(is_near).collect();
+    if near_villages.is_empty() {
+        return circumference;
+    }
+
+    // The corners of the villages, with room for the wall around them
+    let (x_len, z_len) = (dimensions.0 as i64, dimensions.1 as i64);
+    let mut points = circumference.clone();
+    for village in &near_villages {
+        let columns: Vec<BlockColumnCoord> = village.houses.iter()
+            .flat_map(|house| house.footprint.iter().copied())
+            .chain(village.paths.iter().copied())
+            .collect();
+        let x_min = columns.iter().map(|column| column.0).min().unwrap_or(village.center.0) - WALL_MARGIN;
+        let x_max = columns.iter().map(|column| column.0).max().unwrap_or(village.center.0) + WALL_MARGIN;
+        let z_min = columns.iter().map(|column| column.1).min().unwrap_or(village.center.1) - WALL_MARGIN;
+        let z_max = columns.iter().map(|column| column.1).max().unwrap_or(village.center.1) + WALL_MARGIN;
+        for (x, z) in &[(x_min, z_min), (x_max, z_min), (x_max, z_max), (x_min, z_max)] {
+            points.push(BlockColumnCoord((*x).max(2).min(x_len - 3), (*z).max(2).min(z_len - 3)));
+        }
+    }
+
+    let mut hull = convex_hull(points);
+    if (geometry::area(&hull) > 0) != (geometry::area(&circumference) > 0) {
+        hull.reverse();
+    }
+    info!("Walled in {} village(s) close to the town.", near_villages.len());
+    hull
+}
+
+/// Rebuild the houses of the village in the materials of the town: The cobblestone as the
+/// foundation, and the planks and the windows of the outer walls as the walls and the
+/// windows. Returns the number of blocks replaced.
+pub fn upgrade_houses(excerpt: &mut WorldExcerpt, village: &Village, palette: &BlockPalette) -> usize {
+    let mut count = 0;
+    for house in &village.houses {
+        let footprint: HashSet<BlockColumnCoord> = house.footprint.iter().copied().collect();
+        for column in &house.footprint {
+            let is_outer_wall = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter()
+                .any(|(dx, dz)| !footprint.contains(&BlockColumnCoord(column.0 + dx, column.1 + dz)));
+            for y in house.door.1 - 1..house.door.1 + HOUSE_HEIGHT {
+                let coordinates = BlockCoord(column.0, y, column.1);
+                let replacement = match excerpt.block_at(coordinates) {
+                    Some(Block::Cobblestone) | Some(Block::MossyCobblestone) => palette.foundation.clone(),
+                    Some(Block::Planks { .. }) if is_outer_wall => palette.wall.clone(),
+                    Some(block) if is_outer_wall && is_window(block) => palette.flat_window.clone(),
+                    _ => continue,
+                };
+                if excerpt.block_at(coordinates) != Some(&replacement) {
+                    excerpt.set_block_at(coordinates, replacement);
+                    count += 1;
+                }
+            }
+        }
+    }
+    count
+}
+
+/// The columns of the house with its door at `door`: The built up columns connected to the
+/// door. None if there are too many of them for a house.
+fn house_footprint(excerpt: &WorldExcerpt, door: BlockCoord) -> Option<Vec<BlockColumnCoord>> {
+    let is_built = |column: BlockColumnCoord| {
+        (door.1 - 1..door.1 + HOUSE_HEIGHT)
+            .any(|y| excerpt.block_at(BlockCoord(column.0, y, column.1)).map_or(false, is_building_block))
+    };
+    let start = BlockColumnCoord(door.0, door.2);
+    let mut footprint = vec![start];
+    let mut visited: HashSet<BlockColumnCoord> = HashSet::new();
+    visited.insert(start);
+    let mut queue = VecDeque::from(vec![start]);
+    while let Some(column) = queue.pop_front() {
+        for (dx, dz) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let neighbour = BlockColumnCoord(column.0 + dx, column.1 + dz);
+            if visited.insert(neighbour) && is_built(neighbour) {
+                footprint.push(neighbour);
+                if footprint.len() > HOUSE_AREA_MAX {
+                    return None;
+                }
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    Some(footprint)
+}
+
+/// The existing paths within `PATH_REACH` of any of the doors.
+fn village_paths(features: &Features, doors: &[BlockColumnCoord]) -> Vec<BlockColumnCoord> {
+    let (x_len, z_len) = features.paths.dimensions();
+    let x_min = doors.iter().map(|door| door.0).min().unwrap_or(0) - PATH_REACH;
+    let x_max = doors.iter().map(|door| door.0).max().unwrap_or(0) + PATH_REACH;
+    let z_min = doors.iter().map(|door| door.1).min().unwrap_or(0) - PATH_REACH;
+    let z_max = doors.iter().map(|door| door.1).max().unwrap_or(0) + PATH_REACH;
+
+    let mut paths = Vec::new();
+    for x in x_min.max(0)..=x_max.min(x_len as i64 - 1) {
+        for z in z_min.max(0)..=z_max.min(z_len as i64 - 1) {
+            let column = BlockColumnCoord(x, z);
+            if features.paths[(x as u32, z as u32)] == image::Luma([255u8])
+                && doors.iter().any(|door| geometry::manhattan_distance(*door, column) as i64 <= PATH_REACH)
+            {
+                paths.push(column);
+            }
+        }
+    }
+    paths
+}
+
+/// The convex hull of the points, counter-clockwise in the plane, by the monotone chain.
+fn convex_hull(mut points: Vec<BlockColumnCoord>) -> Snake {
+    points.sort_by_key(|point| (point.0, point.1));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: BlockColumnCoord, a: BlockColumnCoord, b: BlockColumnCoord| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<BlockColumnCoord> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        let ordered: Vec<BlockColumnCoord> = if pass == 0 {
+            points.clone()
+        } else {
+            points.iter().rev().copied().collect()
+        };
+        for point in ordered {
+            while hull.len() >= start + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0 {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        hull.pop();
+    }
+    hull
+}
+
+/// Whether the block is part of a building, rather than of the landscape.
+fn is_building_block(block: &Block) -> bool {
+    matches!(
+        block,
+        Block::Planks { .. }
+            | Block::Cobblestone
+            | Block::MossyCobblestone
+            | Block::StoneBricks
+            | Block::BrickBlock
+            | Block::Door(_)
+            | Block::Glass { .. }
+    ) || *block == Block::glass_pane()
+}
@@ -1,17 +1,64 @@
 use crate::block_palette::BlockPalette;
 use crate::features::Features;
+use crate::foundation;
+use crate::geometry::{self, point_position_relative_to_polygon, InOutSide};
 use crate::line;
+use crate::partitioning::snake_bounding_box;
+use crate::pathfinding::RoadPath;
+use crate::room_interior::{self, ColumnKind, RoomShape};
+use crate::signs;
 use crate::tree;
 use crate::types::Snake;
-use mcprogedit::block::Block;
-use mcprogedit::coordinates::BlockColumnCoord;
+use mcprogedit::block::{Block, Door, DoorHalf, Hinge};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{DoorMaterial, FenceMaterial};
+use mcprogedit::positioning::{Surface2, Surface4};
 use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
+/// The kind of defences to put around the town.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefenceKind {
+    /// Masonry wall with pillars and crowning.
+    StoneWall,
+    /// Dry ditch outside an earthen rampart, topped with a palisade.
+    Earthwork,
+}
+
+/// Height of the earthen rampart, above the surrounding terrain.
+const RAMPART_HEIGHT: i64 = 3;
+/// Depth of the dry ditch, below the surrounding terrain.
+const DITCH_DEPTH: i64 = 2;
+/// Width of the dry ditch.
+const DITCH_WIDTH: f32 = 4.0;
+/// Height of the palisade on top of the rampart.
+const PALISADE_HEIGHT: i64 = 2;
+
+/// Height of a gate opening, in the middle of the arch.
+const GATE_HEIGHT: i64 = 4;
+/// Farthest a road end may be from the wall, for the road to get a gate.
+const GATE_REACH: f32 = 3.0;
+/// Shortest distance between two gates in the same wall.
+const GATE_SPACING: i64 = 12;
+/// Width of the towers flanking a gate.
+const GATE_TOWER_WIDTH: i64 = 3;
+/// Height of the towers flanking a gate, above the ground.
+const GATE_TOWER_HEIGHT: i64 = 8;
+
+/// Build the town wall along the town circumference,
+/// with a gatehouse wherever one of the `roads`, `road_width` wide, passes through it.
 pub fn build_wall(
     excerpt: &mut WorldExcerpt,
     town_circumference: &Snake,
+    roads: &[RoadPath],
+    road_width: i64,
     features: &Features,
     palette: &BlockPalette,
+    width: i64,
+    rng: &mut StdRng,
 ) {
     // Build the walls pt. 1: Segments of wall.
     for wall_segment in town_circumference.windows(2) {
@@ -24,7 +71,7 @@ pub fn build_wall(
         let line = line::line(
             &(start.0 as i64, start_ground + 4, start.1 as i64).into(),
             &(end.0 as i64, end_ground + 4, end.1 as i64).into(),
-            3,
+            width,
         );
 
         for position in line {
@@ -40,6 +87,16 @@ pub fn build_wall(
             excerpt.set_block_at(position - (0, 3, 0).into(), palette.city_wall_main.clone());
             excerpt.set_block_at(position - (0, 4, 0).into(), palette.city_wall_main.clone());
             excerpt.set_block_at(position - (0, 5, 0).into(), palette.city_wall_main.clone());
+            let underpinning = foundation::underpinning(
+                excerpt,
+                position.0,
+                position.1 - 5,
+                position.2,
+                &palette.city_wall_main,
+            );
+            for (coordinates, block) in underpinning {
+                excerpt.set_block_at(coordinates, block);
+            }
         }
     }
 
@@ -55,18 +112,250 @@ pub fn build_wall(
             tree::chop(excerpt, coordinates);
             excerpt.set_block_at(coordinates, palette.city_wall_main.clone());
         }
+        for (coordinates, block) in foundation::underpinning(excerpt, *x, ground, *z, &palette.city_wall_main) {
+            excerpt.set_block_at(coordinates, block);
+        }
         let coordinates = (*x, ground + 5, *z).into();
         tree::chop(excerpt, coordinates);
-        excerpt.set_block_at(coordinates, Block::torch());
+        excerpt.set_block_at(coordinates, palette.standing_light());
+    }
+
+    // Build the walls pt. 3: Gatehouses where the roads pass through.
+    for (gate, outward) in gates(town_circumference, roads) {
+        build_gatehouse(excerpt, gate, outward, road_width, features, palette, width, rng);
+    }
+}
+
+/// Find where the roads pass through the town circumference. Returns the position
+/// of each gate on the circumference, and the direction out of town through it.
+pub fn gates(town_circumference: &Snake, roads: &[RoadPath]) -> Vec<(BlockColumnCoord, Surface4)> {
+    let mut gates: Vec<(BlockColumnCoord, Surface4)> = Vec::new();
+
+    for road in roads {
+        if road.len() < 2 {
+            continue;
+        }
+        // The road ends close to the wall, where the road was split at the circumference,
+        // and any node right on the wall, where the road crosses it.
+        let last = road.len() - 1;
+        for (index, node) in road.iter().enumerate() {
+            let point: BlockColumnCoord = node.coordinates.into();
+            let (gate, distance) = closest_point_on_snake(point, town_circumference);
+            let reach = if index == 0 || index == last { GATE_REACH } else { 1.0 };
+            if distance > reach {
+                continue;
+            }
+            if gates.iter().any(|(other, _)| {
+                (other.0 - gate.0).abs().max((other.1 - gate.1).abs()) < GATE_SPACING
+            }) {
+                continue;
+            }
+
+            // Direction of the road through the gate
+            let (before, after) = if index == last { (index - 1, index) } else { (index, index + 1) };
+            let (before, after) = (road[before].coordinates, road[after].coordinates);
+            let along = signs::surface_from_vector(
+                (after.0 - before.0) as f32,
+                (after.2 - before.2) as f32,
+            );
+            let ahead = geometry::offset(along);
+            let ahead = BlockColumnCoord(gate.0 + ahead.0 * 4, gate.1 + ahead.2 * 4);
+            let outward = match point_position_relative_to_polygon(ahead, town_circumference) {
+                InOutSide::Outside => along,
+                _ => along.opposite(),
+            };
+            gates.push((gate, outward));
+        }
+    }
+
+    gates
+}
+
+/// Number of blocks on each side of the middle of a gate opening, for a road of the given width.
+fn gate_half_width(road_width: i64) -> i64 {
+    ((road_width + 1) / 2).max(1)
+}
+
+/// The columns covered by the gatehouse at `gate`, for a road of the given `road_width`,
+/// in a wall of the given `width`.
+fn gatehouse_columns(gate: BlockColumnCoord, outward: Surface4, road_width: i64, width: i64) -> Vec<BlockColumnCoord> {
+    let half_width = gate_half_width(road_width);
+    let (forward, right) = (geometry::offset(outward), geometry::offset(outward.rotated_90_cw()));
+    let depth = width / 2 + 1;
+    let outer_across = half_width + GATE_TOWER_WIDTH;
+    let mut columns = Vec::new();
+    for across in -outer_across..=outer_across {
+        for along in -depth..=depth + 1 {
+            columns.push(BlockColumnCoord(
+                gate.0 + forward.0 * along + right.0 * across,
+                gate.1 + forward.2 * along + right.2 * across,
+            ));
+        }
+    }
+    columns
+}
+
+/// Build a gatehouse in the wall at `gate`: an arched opening through the wall, as wide as
+/// the road, with open doors on the inside and a raised portcullis on the outside,
+/// between two flanking towers, and a guard room furnished as barracks above it.
+fn build_gatehouse(
+    excerpt: &mut WorldExcerpt,
+    gate: BlockColumnCoord,
+    outward: Surface4,
+    road_width: i64,
+    features: &Features,
+    palette: &BlockPalette,
+    width: i64,
+    rng: &mut StdRng,
+) {
+    let half_width = gate_half_width(road_width);
+    let ground = match features.terrain_height_map.height_at((gate.0 as usize, gate.1 as usize)) {
+        Some(ground) => ground as i64,
+        None => return,
+    };
+    // Positions `along` the road outwards, and `across` the road to the right.
+    let (forward, right) = (geometry::offset(outward), geometry::offset(outward.rotated_90_cw()));
+    let at = |along: i64, across: i64, y: i64| {
+        BlockCoord(
+            gate.0 + forward.0 * along + right.0 * across,
+            y,
+            gate.1 + forward.2 * along + right.2 * across,
+        )
+    };
+    let depth = width / 2 + 1;
+
+    // Flanking towers, reaching a step out from the wall
+    let tower_across = half_width + 1..half_width + 1 + GATE_TOWER_WIDTH;
+    for across in tower_across.clone().chain(tower_across.map(|across| -across)) {
+        for along in -depth..=depth + 1 {
+            for y in ground - 1..ground + GATE_TOWER_HEIGHT {
+                let position = at(along, across, y);
+                tree::chop(excerpt, position);
+                excerpt.set_block_at(position, palette.city_wall_main.clone());
+            }
+        }
+    }
+
+    // Roof joining the tower tops over the gate, with crenellations around the edge
+    let outer_across = half_width + GATE_TOWER_WIDTH;
+    for across in -outer_across..=outer_across {
+        for along in -depth..=depth + 1 {
+            let top = at(along, across, ground + GATE_TOWER_HEIGHT);
+            tree::chop(excerpt, top);
+            excerpt.set_block_at(top, palette.city_wall_top.clone());
+
+            let is_edge = along == -depth || along == depth + 1 || across.abs() == outer_across;
+            if is_edge && (along + across) % 2 == 0 {
+                let crenel = top + BlockCoord(0, 1, 0);
+                tree::chop(excerpt, crenel);
+                excerpt.set_block_at(crenel, palette.city_wall_coronation.clone());
+            }
+        }
+    }
+
+    // Opening through the wall, arched, with a lantern hanging from the middle
+    for along in -depth..=depth + 1 {
+        for across in -half_width..=half_width {
+            let height = if across.abs() == half_width { GATE_HEIGHT - 1 } else { GATE_HEIGHT };
+            for y in ground..ground + height {
+                let position = at(along, across, y);
+                tree::chop(excerpt, position);
+                excerpt.set_block_at(position, Block::Air);
+            }
+        }
+    }
+    excerpt.set_block_at(
+        at(0, 0, ground + GATE_HEIGHT - 1),
+        Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+    );
+
+    // Guard room above the gate, entered from the wall walk on either side
+    let floor_y = ground + GATE_HEIGHT;
+    let room_height = GATE_TOWER_HEIGHT - GATE_HEIGHT - 1;
+    let is_doorway = |along: i64, across: i64| along == 0 && across.abs() == outer_across;
+    let is_slit = |along: i64, across: i64| along == depth + 1 && across % 2 == 0 && across.abs() < outer_across;
+    let mut columns: Vec<(ColumnKind, BlockColumnCoord)> = Vec::new();
+    for across in -outer_across..=outer_across {
+        for along in -depth..=depth + 1 {
+            let is_wall = along == -depth || along == depth + 1 || across.abs() == outer_across;
+            if across.abs() == half_width {
+                let springer = at(along, across, floor_y - 1);
+                if excerpt.block_at(springer) == Some(&Block::Air) {
+                    excerpt.set_block_at(springer, palette.city_wall_main.clone());
+                }
+            }
+            excerpt.set_block_at(at(along, across, floor_y), palette.city_wall_main.clone());
+            for y in floor_y + 1..floor_y + 1 + room_height {
+                let position = at(along, across, y);
+                tree::chop(excerpt, position);
+                let is_open = !is_wall
+                    || (is_doorway(along, across) && y < floor_y + 3)
+                    || (is_slit(along, across) && y == floor_y + 2);
+                let block = if is_open { Block::Air } else { palette.city_wall_main.clone() };
+                excerpt.set_block_at(position, block);
+            }
+
+            let column_kind = if is_doorway(along, across) {
+                ColumnKind::Door
+            } else if is_slit(along, across) {
+                ColumnKind::Window
+            } else if is_wall {
+                ColumnKind::Wall
+            } else {
+                ColumnKind::Floor(room_height as usize)
+            };
+            let position = at(along, across, floor_y);
+            columns.push((column_kind, BlockColumnCoord(position.0, position.2)));
+        }
+    }
+    let min_x = columns.iter().map(|(_, column)| column.0).min().unwrap_or(0);
+    let max_x = columns.iter().map(|(_, column)| column.0).max().unwrap_or(0);
+    let min_z = columns.iter().map(|(_, column)| column.1).min().unwrap_or(0);
+    let max_z = columns.iter().map(|(_, column)| column.1).max().unwrap_or(0);
+    let mut room_shape = RoomShape::new(((max_x - min_x + 1) as usize, (max_z - min_z + 1) as usize));
+    for (column_kind, column) in columns {
+        room_shape.set_column_kind_at(((column.0 - min_x) as usize, (column.1 - min_z) as usize), column_kind);
+    }
+    if let Some(interior) = room_interior::furnish_barracks(&room_shape, rng) {
+        excerpt.paste(BlockCoord(min_x, floor_y + 1, min_z), &interior);
+    }
+
+    // Raised portcullis on the outer face
+    for across in -half_width..=half_width {
+        excerpt.set_block_at(
+            at(depth, across, ground + GATE_HEIGHT - 1),
+            Block::Fence { material: FenceMaterial::DarkOak, waterlogged: false },
+        );
+    }
+
+    // Double doors on the inner face, standing open
+    for (across, hinged_at) in &[(-half_width, Hinge::Left), (half_width, Hinge::Right)] {
+        for (y, half) in &[(ground, DoorHalf::Lower), (ground + 1, DoorHalf::Upper)] {
+            excerpt.set_block_at(at(-depth, *across, *y), Block::Door(Door {
+                material: DoorMaterial::Oak,
+                facing: outward,
+                half: *half,
+                hinged_at: *hinged_at,
+                open: true,
+            }));
+        }
     }
 }
 
 pub fn build_wall_crowning(
     excerpt: &mut WorldExcerpt,
     town_circumference: &Snake,
+    roads: &[RoadPath],
+    road_width: i64,
     features: &Features,
     palette: &BlockPalette,
+    width: i64,
 ) {
+    // Leave the gatehouses, with their guard rooms, as they are.
+    let gatehouses: HashSet<BlockColumnCoord> = gates(town_circumference, roads).into_iter()
+        .flat_map(|(gate, outward)| gatehouse_columns(gate, outward, road_width, width))
+        .collect();
+
     for wall_segment in town_circumference.windows(2) {
         let (start, end) = (wall_segment[0], wall_segment[1]);
         let start = (start.0 as usize, start.1 as usize);
@@ -77,10 +366,13 @@ pub fn build_wall_crowning(
         let line = line::line(
             &(start.0 as i64, start_ground + 4, start.1 as i64).into(),
             &(end.0 as i64, end_ground + 4, end.1 as i64).into(),
-            4,
+            width + 1,
         );
 
         for position in line {
+            if gatehouses.contains(&BlockColumnCoord(position.0, position.2)) {
+                continue;
+            }
             tree::chop(excerpt, position);
             excerpt.set_block_at(position, palette.city_wall_top.clone());
         }
@@ -88,12 +380,204 @@ pub fn build_wall_crowning(
         let line = line::double_line(
             &(start.0 as i64, start_ground + 5, start.1 as i64).into(),
             &(end.0 as i64, end_ground + 5, end.1 as i64).into(),
-            4,
+            width + 1,
         );
 
         for position in line {
+            if gatehouses.contains(&BlockColumnCoord(position.0, position.2)) {
+                continue;
+            }
             tree::chop(excerpt, position);
             excerpt.set_block_at(position, palette.city_wall_coronation.clone());
         }
     }
 }
+
+/// Clear the trees and the undergrowth off the strip of `pomerium_width` on either side of
+/// a town wall of the given width, keeping the wall in plain view of its defenders.
+pub fn clear_pomerium(
+    excerpt: &mut WorldExcerpt,
+    town_circumference: &Snake,
+    features: &Features,
+    wall_width: i64,
+    pomerium_width: i64,
+) {
+    let (x_len, z_len) = features.dimensions();
+    let mut columns = HashSet::new();
+    for wall_segment in town_circumference.windows(2) {
+        let (start, end) = (wall_segment[0], wall_segment[1]);
+        let line = line::line(
+            &(start.0, 0, start.1).into(),
+            &(end.0, 0, end.1).into(),
+            wall_width + 2 * pomerium_width,
+        );
+        for position in line {
+            if position.0 >= 0 && position.2 >= 0 && (position.0 as usize) < x_len && (position.2 as usize) < z_len {
+                columns.insert((position.0 as usize, position.2 as usize));
+            }
+        }
+    }
+
+    for (x, z) in columns {
+        let ground = features.terrain_height_map.height_at((x, z)).unwrap_or(0) as i64;
+        let top = features.height_map.height_at((x, z)).unwrap_or(0) as i64;
+        for y in ground..top.max(ground + 2) {
+            let coordinates = BlockCoord(x as i64, y, z as i64);
+            tree::chop(excerpt, coordinates);
+            if excerpt.block_at(coordinates).map(Block::is_foilage).unwrap_or(false) {
+                excerpt.set_block_at(coordinates, Block::Air);
+            }
+        }
+    }
+}
+
+/// Build an earthwork along the town circumference: a dry ditch on the outside,
+/// with the material cut from the ditch piled up into a rampart along the inside,
+/// and a palisade on top of the rampart.
+pub fn build_earthwork(
+    excerpt: &mut WorldExcerpt,
+    town_circumference: &Snake,
+    features: &Features,
+    palette: &BlockPalette,
+    width: i64,
+) {
+    // The rampart is flat on top and sloping down towards the ditch,
+    // with the ditch starting at the foot of the slope.
+    let crest_half_width = width as f32 / 2.0;
+    let rampart_reach = crest_half_width + RAMPART_HEIGHT as f32;
+    let ditch_reach = rampart_reach + DITCH_WIDTH;
+
+    let (x_len, _, z_len) = excerpt.dim();
+    let (offset, dimensions) = snake_bounding_box(town_circumference);
+    let margin = ditch_reach.ceil() as i64 + 1;
+    let min_x = (offset.0 - margin).max(0);
+    let min_z = (offset.1 - margin).max(0);
+    let max_x = (offset.0 + dimensions.0 + margin).min(x_len as i64 - 1);
+    let max_z = (offset.1 + dimensions.1 + margin).min(z_len as i64 - 1);
+
+    // Sort the columns near the circumference into rampart and ditch,
+    // by their signed distance to the circumference (positive outside of town).
+    let mut rampart: Vec<(BlockColumnCoord, i64)> = Vec::new();
+    let mut ditch: Vec<BlockColumnCoord> = Vec::new();
+    for x in min_x..=max_x {
+        for z in min_z..=max_z {
+            let column = BlockColumnCoord(x, z);
+            let distance = distance_to_snake(column, town_circumference);
+            if distance > ditch_reach {
+                continue;
+            }
+            let signed_distance = match point_position_relative_to_polygon(column, town_circumference) {
+                InOutSide::Outside => distance,
+                _ => -distance,
+            };
+
+            if signed_distance < -crest_half_width {
+                continue;
+            } else if signed_distance <= crest_half_width {
+                rampart.push((column, RAMPART_HEIGHT));
+            } else if signed_distance <= rampart_reach {
+                let height = RAMPART_HEIGHT - (signed_distance - crest_half_width).ceil() as i64 + 1;
+                rampart.push((column, height.max(1)));
+            } else {
+                ditch.push(column);
+            }
+        }
+    }
+
+    // Dig the ditch, keeping the cut material.
+    let mut cut_material: Vec<Block> = Vec::new();
+    for BlockColumnCoord(x, z) in &ditch {
+        let ground = match features.terrain_height_map.height_at((*x as usize, *z as usize)) {
+            Some(ground) => ground as i64,
+            None => continue,
+        };
+        for y in ground - DITCH_DEPTH..ground {
+            let coordinates = BlockCoord(*x, y, *z);
+            tree::chop(excerpt, coordinates);
+            match excerpt.block_at(coordinates) {
+                Some(Block::Air) | Some(Block::None) | None => (),
+                Some(Block::WaterSource) | Some(Block::Water { .. }) => continue,
+                Some(Block::GrassBlock) => cut_material.push(Block::Dirt),
+                Some(block) => cut_material.push(block.clone()),
+            }
+            excerpt.set_block_at(coordinates, Block::Air);
+        }
+        tree::chop(excerpt, BlockCoord(*x, ground, *z));
+    }
+
+    // Pile the cut material up into the rampart, one layer at a time.
+    let mut cut_material = cut_material.into_iter();
+    'layers: for layer in 0..RAMPART_HEIGHT {
+        for (BlockColumnCoord(x, z), height) in &rampart {
+            if layer >= *height {
+                continue;
+            }
+            let ground = match features.terrain_height_map.height_at((*x as usize, *z as usize)) {
+                Some(ground) => ground as i64,
+                None => continue,
+            };
+            let block = match cut_material.next() {
+                Some(block) => block,
+                None => break 'layers,
+            };
+            let coordinates = BlockCoord(*x, ground + layer, *z);
+            tree::chop(excerpt, coordinates);
+            excerpt.set_block_at(coordinates, block);
+        }
+    }
+
+    // Palisade along the crest of the rampart.
+    for wall_segment in town_circumference.windows(2) {
+        let (start, end) = (wall_segment[0], wall_segment[1]);
+        let line = line::line(
+            &(start.0, 0, start.1).into(),
+            &(end.0, 0, end.1).into(),
+            1,
+        );
+        for BlockCoord(x, _, z) in line {
+            let ground = match features.terrain_height_map.height_at((x as usize, z as usize)) {
+                Some(ground) => ground as i64,
+                None => continue,
+            };
+            // Only put palisade on top of material, not in thin air.
+            let top = BlockCoord(x, ground + RAMPART_HEIGHT - 1, z);
+            match excerpt.block_at(top) {
+                Some(Block::Air) | Some(Block::None) | None => continue,
+                _ => (),
+            }
+            for y in ground + RAMPART_HEIGHT..ground + RAMPART_HEIGHT + PALISADE_HEIGHT {
+                let coordinates = BlockCoord(x, y, z);
+                tree::chop(excerpt, coordinates);
+                excerpt.set_block_at(coordinates, palette.palisade.clone());
+            }
+        }
+    }
+}
+
+/// Shortest distance from `point` to any segment of `snake`.
+fn distance_to_snake(point: BlockColumnCoord, snake: &Snake) -> f32 {
+    closest_point_on_snake(point, snake).1
+}
+
+/// The point on `snake` closest to `point`, and the distance between them.
+fn closest_point_on_snake(point: BlockColumnCoord, snake: &Snake) -> (BlockColumnCoord, f32) {
+    snake.windows(2)
+        .map(|segment| {
+            let (start, end) = (segment[0], segment[1]);
+            let (dx, dz) = ((end.0 - start.0) as f32, (end.1 - start.1) as f32);
+            let (px, pz) = ((point.0 - start.0) as f32, (point.1 - start.1) as f32);
+            let length_squared = dx * dx + dz * dz;
+            let t = if length_squared > 0.0 {
+                ((px * dx + pz * dz) / length_squared).max(0.0).min(1.0)
+            } else {
+                0.0
+            };
+            let (cx, cz) = (px - t * dx, pz - t * dz);
+            let closest = BlockColumnCoord(
+                start.0 + (t * dx).round() as i64,
+                start.1 + (t * dz).round() as i64,
+            );
+            (closest, (cx * cx + cz * cz).sqrt())
+        })
+        .fold((point, f32::MAX), |best, candidate| if candidate.1 < best.1 { candidate } else { best })
+}
@@ -5,6 +5,7 @@ use image::imageops::colorops::invert;
 use image::{GrayImage, RgbImage};
 use imageproc::contrast::*;
 use imageproc::distance_transform::*;
+use imageproc::drawing::draw_line_segment_mut;
 use imageproc::map::map_colors;
 use imageproc::morphology::*;
 use imageproc::suppress::suppress_non_maximum;
@@ -12,14 +13,34 @@ use log::info;
 use mcprogedit::coordinates::BlockColumnCoord;
 
 use crate::types::*;
-use crate::Areas;
-use crate::Features;
+use crate::areas::Areas;
+use crate::features::Features;
+use crate::geometry::euclidean_distance;
+use crate::inspection::Inspection;
 
-#[cfg(feature = "debug_images")]
-use imageproc::drawing::draw_line_segment_mut;
+/// Energy taken off the plateaus, drawing the town walls out along their edges.
+const PLATEAU_ENERGY_BONUS: u8 = 32;
+/// Radius added to the town center candidates on a plateau, for preferring them as town sites.
+const PLATEAU_RADIUS_BONUS: u8 = 8;
 
 /// Find the most suitable closed loop perimeter for a town wall.
-pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockColumnCoord) {
+pub fn walled_town_contour(
+    features: &Features,
+    areas: &Areas,
+    inspection: &Inspection,
+) -> (Snake, BlockColumnCoord) {
+    walled_town_contours(features, areas, 1, inspection).remove(0)
+}
+
+/// Find up to `max_count` sites for walled towns, that do not overlap each other,
+/// with the most suitable closed loop perimeter for the wall of each.
+/// The sites are ordered by potential town size, largest first.
+pub fn walled_town_contours(
+    features: &Features,
+    areas: &Areas,
+    max_count: usize,
+    inspection: &Inspection,
+) -> Vec<(Snake, BlockColumnCoord)> {
     let mut not_town = areas.town.clone();
     invert(&mut not_town);
 
@@ -32,12 +53,12 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
         image::Luma([u8::MAX])
     );
 
-    #[cfg(feature = "debug_images")]
-    not_town.save("T-01 not town.png").unwrap();
+    inspection.save_image("T-01 not town.png", &not_town);
 
     // Mask for town circumference start circle
     // Energy map for finding town circumference
     // * Top of hill (+)
+    // * Plateau (+)
     // * (Larger) water (-)
     // * Steep terrain (-)
     // * Forest (-)
@@ -49,10 +70,7 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
         image::Luma([p[0].saturating_mul(p[0])])
     });
 
-    #[cfg(feature = "debug_images")]
-    water_depth_energy
-        .save("T-04 water depth energy.png")
-        .unwrap();
+    inspection.save_image("T-04 water depth energy.png", &water_depth_energy);
 
     // Distance from shore -> penalty
     // TODO Maybe start the penalty a few blocks ashore?
@@ -63,10 +81,7 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
         image::Luma([p[0].saturating_mul(4)])
     });
 
-    #[cfg(feature = "debug_images")]
-    offshore_distance_energy
-        .save("T-05 offshore distance energy.png")
-        .unwrap();
+    inspection.save_image("T-05 offshore distance energy.png", &offshore_distance_energy);
 
     // Steep terrain -> penalty
     let mut slope_energy = features.scharr.clone();
@@ -76,8 +91,7 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
     threshold_mut(&mut slope_energy, 16u8);
     close_mut(&mut slope_energy, Norm::LInf, 3);
 
-    #[cfg(feature = "debug_images")]
-    slope_energy.save("T-07 slope energy.png").unwrap();
+    inspection.save_image("T-07 slope energy.png", &slope_energy);
 
     let mut energy = image::ImageBuffer::new(x_len as u32, z_len as u32);
     for x in 0..x_len {
@@ -93,31 +107,40 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
         }
     }
     const NEUTRAL_ENERGY: u8 = u8::MAX / 2;
-    let mut energy = imageproc::map::map_colors2(&energy, &features.hilltop, |p, q| {
+    let energy = imageproc::map::map_colors2(&energy, &features.hilltop, |p, q| {
         image::Luma([p[0].saturating_add(NEUTRAL_ENERGY).saturating_sub(q[0])])
     });
 
+    // Defensible plateaus (+)
+    let mut energy = imageproc::map::map_colors2(&energy, &areas.defensible, |p, q| {
+        if q[0] == 0 {
+            p
+        } else {
+            image::Luma([p[0].saturating_sub(PLATEAU_ENERGY_BONUS)])
+        }
+    });
+
     // Keep away from the edges of the map
     let (width, height) = energy.dimensions();
     imageproc::drawing::draw_hollow_rect_mut(&mut energy, imageproc::rect::Rect::at(0, 0).of_size(width, height) , image::Luma([u8::MAX]));
     imageproc::drawing::draw_hollow_rect_mut(&mut energy, imageproc::rect::Rect::at(1, 1).of_size(width - 2, height - 2) , image::Luma([u8::MAX]));
     imageproc::drawing::draw_hollow_rect_mut(&mut energy, imageproc::rect::Rect::at(2, 2).of_size(width - 4, height - 4) , image::Luma([u8::MIN]));
 
-    #[cfg(feature = "debug_images")]
-    energy.save("T-10 energy.png").unwrap();
+    inspection.save_image("T-10 energy.png", &energy);
 
     // map of distance from (potential) town edge
     let town_density = distance_transform(&threshold(&energy, NEUTRAL_ENERGY), Norm::LInf);
 
-    #[cfg(feature = "debug_images")]
-    town_density.save("T-02 town density.png").unwrap();
+    inspection.save_image("T-02 town density.png", &town_density);
 
     // points the farthest away from (potential) town edge are potential town centers.
     let mut town_centers = suppress_non_maximum(&town_density, 8);
 
-    // List and sort town center points according to potential town size.
+    // List and sort town center points according to potential town size,
+    // with the points on defensible plateaus counting as larger.
     #[derive(Eq, Ord, PartialEq, PartialOrd)]
     struct TownCenterPoint {
+        score: u8,
         radius: u8,
         point: BlockColumnCoord,
     }
@@ -127,7 +150,14 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
         for z in 1..z_len as i64 - 1 {
             let image::Luma([radius]) = town_centers[(x as u32, z as u32)];
             if radius != 0 {
+                let image::Luma([defensible]) = areas.defensible[(x as u32, z as u32)];
+                let score = if defensible == 0 {
+                    radius
+                } else {
+                    radius.saturating_add(PLATEAU_RADIUS_BONUS)
+                };
                 town_center_list.push(TownCenterPoint {
+                    score,
                     radius,
                     point: (x, z).into(),
                 });
@@ -139,7 +169,7 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
     threshold_mut(&mut town_centers, 0u8);
 
     // Put in circles for towns
-    for TownCenterPoint { radius, point } in &town_center_list {
+    for TownCenterPoint { radius, point, .. } in &town_center_list {
         imageproc::drawing::draw_hollow_circle_mut(
             &mut town_centers,
             (point.0 as i32, point.1 as i32),
@@ -148,23 +178,47 @@ pub fn walled_town_contour(features: &Features, areas: &Areas) -> (Snake, BlockC
         );
     }
 
-    #[cfg(feature = "debug_images")]
-    town_centers.save("T-03 town centers.png").unwrap();
-
-    // TODO Maybe calculate and rate the N most promising locations?
-    //      For now: Use the one the farthest away from "non-suitable" features/areas.
-    const TOWN_INDEX: usize = 0; // Nth largest town center: TODO reset to 0
-
-    (
-        walled_town_contour_internal(
-            &energy,
-            &features.coloured_map,
-            town_center_list[TOWN_INDEX].radius,
-            town_center_list[TOWN_INDEX].point,
-            (x_len as i64, z_len as i64).into(),
-        ),
-        town_center_list[TOWN_INDEX].point,
-    )
+    inspection.save_image("T-03 town centers.png", &town_centers);
+
+    // The largest town is always placed. Further towns must be of some size,
+    // and must keep clear of the towns already placed.
+    const MIN_OUTLYING_TOWN_RADIUS: u8 = 12;
+    const TOWN_SPACING: f32 = 2.0;
+
+    let mut chosen_centers: Vec<&TownCenterPoint> = Vec::new();
+    for candidate in &town_center_list {
+        if chosen_centers.len() >= max_count.max(1) {
+            break;
+        }
+        if !chosen_centers.is_empty() && candidate.radius < MIN_OUTLYING_TOWN_RADIUS {
+            continue;
+        }
+        let is_clear = chosen_centers.iter().all(|town| {
+            euclidean_distance(town.point, candidate.point)
+                > (town.radius as f32 + candidate.radius as f32) * TOWN_SPACING
+        });
+        if is_clear {
+            chosen_centers.push(candidate);
+        }
+    }
+    info!("Found {} town site(s).", chosen_centers.len());
+
+    chosen_centers
+        .iter()
+        .map(|TownCenterPoint { radius, point, .. }| {
+            (
+                walled_town_contour_internal(
+                    &energy,
+                    &features.coloured_map,
+                    *radius,
+                    *point,
+                    (x_len as i64, z_len as i64).into(),
+                    inspection,
+                ),
+                *point,
+            )
+        })
+        .collect()
 }
 
 fn circle_snake(
@@ -202,11 +256,11 @@ fn snake_with_duplicate_points_removed(snake: &Snake) -> Snake {
 // Try to find a good walled town circumference
 fn walled_town_contour_internal(
     costs: &GrayImage,
-    #[allow(unused_variables)]
     map_img: &RgbImage,
     radius: u8,
     center: BlockColumnCoord,
     max: BlockColumnCoord,
+    inspection: &Inspection,
 ) -> Snake {
     // Parameters for the active contour model
     const ALPHA: f32 = 0.60; // weight for averaging snake line lengths
@@ -217,20 +271,14 @@ fn walled_town_contour_internal(
     let num_points = radius as usize * 2;
     let mut snake = circle_snake(num_points, radius as usize, center, max);
 
-    #[cfg(feature = "debug_images")]
-    save_snake_image(&snake, &map_img, &"acm_000.png".to_string());
+    save_snake_image(&snake, map_img, "acm_000.png", inspection);
 
-    #[allow(unused_variables)] // 'iteration' is used only for feature 'debug_images'
     for iteration in 1..=100 {
         let (s, _energy) = active_contour_model(snake.clone(), costs, ALPHA, BETA, GAMMA, INFLATE);
 
-        #[cfg(feature = "debug_images")]
-        if iteration == 1 {
-            save_snake_image(&snake, &map_img, &"acm_001.png".to_string());
-        } else if iteration % 10 == 0 {
-            let file_name = format!("acm_{:0>3}.png", iteration.to_string());
-            save_snake_image(&snake, &map_img, &file_name);
-            info!("Saved {}", file_name);
+        if iteration == 1 || iteration % 10 == 0 {
+            let file_name = format!("acm_{:0>3}.png", iteration);
+            save_snake_image(&snake, map_img, &file_name, inspection);
         }
 
         snake = s;
@@ -239,7 +287,6 @@ fn walled_town_contour_internal(
     snake_with_duplicate_points_removed(&snake)
 }
 
-#[cfg(feature = "debug_images")]
 pub fn draw_snake(image: &mut RgbImage, snake: &Snake) {
     const MARKER_RADIUS: i64 = 0;
     let (x_len, z_len) = image.dimensions();
@@ -264,12 +311,13 @@ pub fn draw_snake(image: &mut RgbImage, snake: &Snake) {
     }
 }
 
-// Print a snake superimposed on an image
-#[cfg(feature = "debug_images")]
-pub fn save_snake_image(snake: &Snake, image: &RgbImage, path: &str) {
-    let mut image = image.clone();
-    draw_snake(&mut image, snake);
-    image.save(path).unwrap();
+// Save a snake superimposed on an image, for inspection
+pub fn save_snake_image(snake: &Snake, image: &RgbImage, name: &str, inspection: &Inspection) {
+    if inspection.is_enabled() {
+        let mut image = image.clone();
+        draw_snake(&mut image, snake);
+        inspection.save_image(name, &image);
+    }
 }
 
 /// Perform one iteration of active contour model, for a circular (closed) snake.
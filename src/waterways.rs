@@ -0,0 +1,293 @@
+//! Waterways into town: A canal from the river or the sea, in a channel lined with stone
+//! and ending in a basin inside the town wall, and an aqueduct on piers, carrying water
+//! from higher ground to a cistern in town.
+//!
+//! The canal is cut into the terrain features before the streets are laid out, so that
+//! the streets crossing it bridge it, and it is part of the land usage graph, bordering
+//! the districts and plots along it.
+
+use std::collections::HashSet;
+
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::line;
+use crate::pathfinding::{self, RoadNode, RoadNodeKind, RoadPath};
+use crate::tree;
+use crate::types::Snake;
+
+/// Width of the water in the canal, between its walls.
+pub const CANAL_WIDTH: i64 = 3;
+/// Depth of the water in the canal.
+const CANAL_DEPTH: i64 = 2;
+/// Deepest the canal may be cut into the terrain, below the ground.
+const CANAL_CUT_DEPTH_MAX: i64 = 6;
+/// Distance from the town center to the end of the canal, in its basin.
+const BASIN_DISTANCE: usize = 16;
+/// Distance from the end of the canal to the edge of its basin.
+const BASIN_RADIUS: i64 = 3;
+/// Farthest from the town center to look for a source for the aqueduct.
+const AQUEDUCT_REACH: usize = 96;
+/// Lowest the water channel of the aqueduct may run above the cistern.
+const AQUEDUCT_HEIGHT_MIN: i64 = 4;
+/// Distance between the piers of the aqueduct.
+const AQUEDUCT_PIER_SPACING: usize = 4;
+/// Distance from the middle of the cistern to the inside of its rim.
+const CISTERN_RADIUS: i64 = 2;
+
+/// A canal from the water outside town into a basin inside the town wall.
+#[derive(Clone, Debug)]
+pub struct Canal {
+    /// The middle of the canal, from the open water to the basin, at the water surface.
+    pub path: RoadPath,
+    /// Height of the water surface, as the lowest air above the water.
+    pub water_y: i64,
+}
+
+/// An aqueduct carrying water from higher ground into a cistern in town.
+#[derive(Clone, Debug)]
+pub struct Aqueduct {
+    /// The middle of the aqueduct, from its source to the cistern, at the water channel.
+    pub path: RoadPath,
+    /// Height of the water channel.
+    pub channel_y: i64,
+    /// The middle of the cistern, at the ground.
+    pub cistern: BlockCoord,
+}
+
+/// Route a canal from the open water closest to the town into the town, ending in a basin
+/// some way from the town center. The canal is level with the open water, cut into the
+/// ground where the ground is higher. Returns `None` if there is no water outside town,
+/// or the ground between is too high for the canal.
+pub fn plan_canal(features: &Features, town_center: BlockColumnCoord, wall_circle: &Snake) -> Option<Canal> {
+    let (x_len, z_len) = features.dimensions();
+    let is_inside = |column: BlockColumnCoord| {
+        geometry::point_position_relative_to_polygon(column, wall_circle) == InOutSide::Inside
+    };
+
+    // The open water outside town closest to the town center
+    let mut source = None;
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            let column = BlockColumnCoord(x, z);
+            if features.water[(x as u32, z as u32)] != image::Luma([255u8]) || is_inside(column) {
+                continue;
+            }
+            let distance = geometry::manhattan_distance(column, town_center);
+            if source.map_or(true, |(_, closest)| distance < closest) {
+                source = Some((column, distance));
+            }
+        }
+    }
+    let (source, _) = source?;
+    let water_y = features.height_map.height_at((source.0 as usize, source.1 as usize))? as i64;
+
+    let ground_at = |column: BlockColumnCoord| {
+        let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        y as i64
+    };
+    let path = pathfinding::road_path(
+        BlockCoord(source.0, ground_at(source), source.1),
+        BlockCoord(town_center.0, ground_at(town_center), town_center.1),
+        &features.terrain,
+        None,
+    )?;
+
+    // Level with the open water, up to the basin
+    let mut canal_path = RoadPath::default();
+    for node in path {
+        let column: BlockColumnCoord = node.coordinates.into();
+        if ground_at(column) - water_y > CANAL_CUT_DEPTH_MAX {
+            info!("No canal into town, as the ground at {:?} is too high for it.", column);
+            return None;
+        }
+        canal_path.push(RoadNode {
+            coordinates: BlockCoord(column.0, water_y, column.1),
+            kind: RoadNodeKind::Ground,
+        });
+        if geometry::manhattan_distance(column, town_center) <= BASIN_DISTANCE {
+            break;
+        }
+    }
+    if !canal_path.iter().any(|node| is_inside(node.coordinates.into())) {
+        return None;
+    }
+
+    info!("Planned a canal into town, from the water at {:?}.", source);
+    Some(Canal { path: canal_path, water_y })
+}
+
+/// Cut the canal and its walls into the terrain features, so that the streets laid out
+/// afterwards bridge it rather than run along its bottom.
+pub fn mark_canal(features: &mut Features, canal: &Canal) {
+    let (x_len, z_len) = features.dimensions();
+    let bed_y = (canal.water_y - CANAL_DEPTH).max(0) as u8;
+    for column in canal_columns(canal, CANAL_WIDTH + 2) {
+        if column.0 >= 0 && column.1 >= 0 && column.0 < x_len as i64 && column.1 < z_len as i64 {
+            features.terrain.put_pixel(column.0 as u32, column.1 as u32, image::Luma([bed_y]));
+        }
+    }
+}
+
+/// Build the canal: A channel of water between walls of stone bricks, level with the open
+/// water, with the ground above the water cleared away, and a basin at its end.
+pub fn build_canal(excerpt: &mut WorldExcerpt, canal: &Canal) {
+    let water: HashSet<BlockColumnCoord> = canal_columns(canal, CANAL_WIDTH).into_iter().collect();
+    let bed_y = canal.water_y - CANAL_DEPTH - 1;
+    let (_, y_len, _) = excerpt.dim();
+
+    for column in canal_columns(canal, CANAL_WIDTH + 2) {
+        let is_water = water.contains(&column);
+        for y in bed_y..y_len as i64 {
+            let coordinates = BlockCoord(column.0, y, column.1);
+            let block = if y == bed_y || (!is_water && y < canal.water_y) {
+                Block::StoneBricks
+            } else if y < canal.water_y {
+                Block::WaterSource
+            } else if excerpt.block_at(coordinates).map_or(true, |block| *block == Block::Air) {
+                break;
+            } else {
+                Block::Air
+            };
+            tree::chop(excerpt, coordinates);
+            excerpt.set_block_at(coordinates, block);
+        }
+    }
+}
+
+/// The columns covered by the canal and its basin, with the canal the given width.
+fn canal_columns(canal: &Canal, width: i64) -> Vec<BlockColumnCoord> {
+    let mut columns: Vec<BlockColumnCoord> = canal.path.windows(2)
+        .flat_map(|segment| line::line(&segment[0].coordinates, &segment[1].coordinates, width))
+        .map(|position| BlockColumnCoord(position.0, position.2))
+        .collect();
+    if let Some(end) = canal.path.last() {
+        let radius = BASIN_RADIUS + (width - CANAL_WIDTH) / 2;
+        for dx in -radius..=radius {
+            for dz in -radius..=radius {
+                columns.push(BlockColumnCoord(end.coordinates.0 + dx, end.coordinates.2 + dz));
+            }
+        }
+    }
+    columns.sort_unstable_by_key(|column| (column.0, column.1));
+    columns.dedup();
+    columns
+}
+
+/// Route an aqueduct in a straight line from the highest ground within reach outside the
+/// town wall, to a cistern some way from the town center. The water channel runs level,
+/// at least `AQUEDUCT_HEIGHT_MIN` blocks above the ground at the cistern. Returns `None`
+/// if there is no ground high enough for a source.
+pub fn plan_aqueduct(features: &Features, town_center: BlockColumnCoord, wall_circle: &Snake) -> Option<Aqueduct> {
+    let (x_len, z_len) = features.dimensions();
+    let ground_at = |column: BlockColumnCoord| {
+        let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        y as i64
+    };
+
+    // The highest dry ground within reach outside town
+    let mut source: Option<(BlockColumnCoord, i64)> = None;
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            let column = BlockColumnCoord(x, z);
+            if geometry::manhattan_distance(column, town_center) > AQUEDUCT_REACH
+                || features.water[(x as u32, z as u32)] == image::Luma([255u8])
+                || geometry::point_position_relative_to_polygon(column, wall_circle) == InOutSide::Inside
+            {
+                continue;
+            }
+            if source.map_or(true, |(_, highest)| ground_at(column) > highest) {
+                source = Some((column, ground_at(column)));
+            }
+        }
+    }
+    let (source, source_y) = source?;
+
+    // The cistern on the way in from the source, short of the town center
+    let (dx, dz) = ((source.0 - town_center.0) as f32, (source.1 - town_center.1) as f32);
+    let length = (dx * dx + dz * dz).sqrt().max(1.0);
+    let cistern = BlockColumnCoord(
+        town_center.0 + (dx / length * BASIN_DISTANCE as f32).round() as i64,
+        town_center.1 + (dz / length * BASIN_DISTANCE as f32).round() as i64,
+    );
+    let cistern_y = ground_at(cistern);
+    if source_y - cistern_y < AQUEDUCT_HEIGHT_MIN {
+        info!("No aqueduct into town, as there is no ground high enough for a source.");
+        return None;
+    }
+    let channel_y = source_y;
+
+    let path = RoadPath::from(vec![
+        RoadNode { coordinates: BlockCoord(source.0, channel_y, source.1), kind: RoadNodeKind::Ground },
+        RoadNode { coordinates: BlockCoord(cistern.0, channel_y, cistern.1), kind: RoadNodeKind::Ground },
+    ]);
+    info!("Planned an aqueduct into town, from the high ground at {:?}.", source);
+    Some(Aqueduct { path, channel_y, cistern: BlockCoord(cistern.0, cistern_y, cistern.1) })
+}
+
+/// Build the aqueduct: A water channel between parapets of stone bricks, on a deck carried
+/// by piers down to the ground, ending in a waterfall into a cistern.
+pub fn build_aqueduct(excerpt: &mut WorldExcerpt, aqueduct: &Aqueduct, features: &Features) {
+    let (x_len, z_len) = features.dimensions();
+    let ground_at = |x: i64, z: i64| -> Option<i64> {
+        if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 {
+            return None;
+        }
+        let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+        Some(y as i64)
+    };
+    let channel_y = aqueduct.channel_y;
+
+    // The deck, the parapets and the water channel, with piers at regular intervals,
+    // ending over the cistern, for the water to fall into it
+    let (start, end) = (aqueduct.path[0].coordinates, aqueduct.path[aqueduct.path.len() - 1].coordinates);
+    let (dx, dz) = ((end.0 - start.0) as f32, (end.2 - start.2) as f32);
+    let length = (dx * dx + dz * dz).sqrt().max(1.0);
+    let side = ((-dz / length).round() as i64, (dx / length).round() as i64);
+    let cistern = aqueduct.cistern;
+    for (index, position) in line::line(&start, &end, 1).iter().enumerate() {
+        let (x, z) = (position.0, position.2);
+        if (x - cistern.0).abs() <= CISTERN_RADIUS && (z - cistern.2).abs() <= CISTERN_RADIUS {
+            if (x, z) == (cistern.0, cistern.2) {
+                excerpt.set_block_at(BlockCoord(x, channel_y, z), Block::WaterSource);
+            }
+            continue;
+        }
+        let is_pier = index % AQUEDUCT_PIER_SPACING == 0;
+        for across in -1..=1 {
+            let (x, z) = (x + side.0 * across, z + side.1 * across);
+            excerpt.set_block_at(BlockCoord(x, channel_y - 1, z), Block::StoneBricks);
+            let top = if across == 0 { Block::WaterSource } else { Block::StoneBricks };
+            excerpt.set_block_at(BlockCoord(x, channel_y, z), top);
+            if is_pier {
+                if let Some(ground_y) = ground_at(x, z) {
+                    for y in ground_y - 1..channel_y - 1 {
+                        tree::chop(excerpt, BlockCoord(x, y, z));
+                        excerpt.set_block_at(BlockCoord(x, y, z), Block::StoneBricks);
+                    }
+                }
+            }
+        }
+    }
+
+    // The cistern, sunk into the ground, filled by the water falling from the channel
+    let outer = CISTERN_RADIUS + 1;
+    for dx in -outer..=outer {
+        for dz in -outer..=outer {
+            let (x, z) = (cistern.0 + dx, cistern.2 + dz);
+            let is_rim = dx.abs() == outer || dz.abs() == outer;
+            excerpt.set_block_at(BlockCoord(x, cistern.1 - 3, z), Block::StoneBricks);
+            for y in cistern.1 - 2..cistern.1 {
+                let block = if is_rim { Block::StoneBricks } else { Block::WaterSource };
+                excerpt.set_block_at(BlockCoord(x, y, z), block);
+            }
+            let top = if is_rim { Block::StoneBricks } else { Block::Air };
+            tree::chop(excerpt, BlockCoord(x, cistern.1, z));
+            excerpt.set_block_at(BlockCoord(x, cistern.1, z), top);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Milestones and wayside shrines along the country roads.
+
+use mcprogedit::block::{Block, WallOrRotatedOnFloor};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::positioning::Surface2;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::features::Features;
+use crate::geometry;
+use crate::naming;
+use crate::pathfinding::{RoadNodeKind, RoadPath};
+use crate::signs;
+use crate::tree;
+use crate::types::Snake;
+
+/// Distance along the road between milestones.
+const MILESTONE_INTERVAL: f32 = 100.0;
+/// Chance of a wayside shrine halfway between two milestones.
+const SHRINE_PROBABILITY: f64 = 0.3;
+/// Farthest a road may end from the town wall, to count as leading to town.
+const TOWN_END_DISTANCE_MAX: f32 = 4.0;
+
+/// A point along a road, with the direction of the road at that point.
+pub(crate) struct RoadPoint {
+    pub(crate) x: f32,
+    pub(crate) z: f32,
+    /// Unit vector along the road.
+    pub(crate) direction: (f32, f32),
+    pub(crate) on_bridge: bool,
+}
+
+/// Put up milestones along the country roads leading to town, showing the distance to town,
+/// and the occasional wayside shrine along any country road.
+pub fn place_waymarks(
+    excerpt: &mut WorldExcerpt,
+    country_roads: &[RoadPath],
+    features: &Features,
+    wall_circle: &Snake,
+    town_name: &str,
+    road_width: i64,
+    rng: &mut StdRng,
+) {
+    let distance_to_wall = |column: BlockColumnCoord| {
+        wall_circle.iter()
+            .map(|point| geometry::euclidean_distance(column, *point))
+            .fold(f32::MAX, f32::min)
+    };
+
+    for road in country_roads {
+        if road.len() < 2 {
+            continue;
+        }
+
+        // Measure distances from the end closest to town.
+        let first_distance = distance_to_wall(road[0].coordinates.into());
+        let last_distance = distance_to_wall(road[road.len() - 1].coordinates.into());
+        let leads_to_town = first_distance.min(last_distance) <= TOWN_END_DISTANCE_MAX;
+        let road: RoadPath = if last_distance < first_distance {
+            road.iter().rev().copied().collect()
+        } else {
+            road.clone()
+        };
+
+        let length = road_length(&road);
+        let mut milestone_count = 1;
+        while (milestone_count as f32) * MILESTONE_INTERVAL < length {
+            let distance = milestone_count as f32 * MILESTONE_INTERVAL;
+
+            if rng.gen_bool(SHRINE_PROBABILITY) {
+                if let Some(point) = point_along(&road, distance - MILESTONE_INTERVAL / 2.0) {
+                    let saint = naming::saint_name(rng);
+                    place_shrine(excerpt, features, &point, road_width, &saint);
+                }
+            }
+            if leads_to_town {
+                if let Some(point) = point_along(&road, distance) {
+                    place_milestone(excerpt, features, &point, road_width, town_name, distance as usize);
+                }
+            }
+
+            milestone_count += 1;
+        }
+    }
+}
+
+/// Total horizontal length of the road.
+pub(crate) fn road_length(road: &RoadPath) -> f32 {
+    road.windows(2)
+        .map(|segment| {
+            geometry::euclidean_distance(segment[0].coordinates.into(), segment[1].coordinates.into())
+        })
+        .sum()
+}
+
+/// Find the point at the given distance along the road, measured from its start.
+pub(crate) fn point_along(road: &RoadPath, distance: f32) -> Option<RoadPoint> {
+    let mut length_so_far = 0.0;
+
+    for segment in road.windows(2) {
+        let (start, end) = (segment[0].coordinates, segment[1].coordinates);
+        let (dx, dz) = ((end.0 - start.0) as f32, (end.2 - start.2) as f32);
+        let segment_length = (dx * dx + dz * dz).sqrt();
+
+        if segment_length > 0.0 && length_so_far + segment_length >= distance {
+            let t = (distance - length_so_far) / segment_length;
+            let is_support = |kind| {
+                kind == RoadNodeKind::WoodenSupport || kind == RoadNodeKind::StoneSupport
+            };
+            return Some(RoadPoint {
+                x: start.0 as f32 + t * dx,
+                z: start.2 as f32 + t * dz,
+                direction: (dx / segment_length, dz / segment_length),
+                on_bridge: is_support(segment[0].kind) || is_support(segment[1].kind),
+            });
+        }
+
+        length_so_far += segment_length;
+    }
+
+    None
+}
+
+/// Find the ground just off the road, `side_distance` to the right (or left, if negative) of the point.
+/// Returns None if the ground there is unsuitable, e.g. under water or outside the area.
+pub(crate) fn roadside_ground(features: &Features, point: &RoadPoint, side_distance: f32) -> Option<BlockCoord> {
+    if point.on_bridge {
+        return None;
+    }
+
+    let (dx, dz) = point.direction;
+    let x = (point.x - dz * side_distance).round() as i64;
+    let z = (point.z + dx * side_distance).round() as i64;
+
+    let (x_len, z_len) = features.dimensions();
+    if x < 0 || z < 0 || x >= x_len as i64 || z >= z_len as i64 {
+        return None;
+    }
+    if features.water[(x as u32, z as u32)] == image::Luma([255u8]) {
+        return None;
+    }
+    let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+    Some(BlockCoord(x, y as i64, z))
+}
+
+/// Put up a milestone by the road, with a sign showing the distance to town.
+fn place_milestone(
+    excerpt: &mut WorldExcerpt,
+    features: &Features,
+    point: &RoadPoint,
+    road_width: i64,
+    town_name: &str,
+    distance: usize,
+) {
+    let side_distance = (road_width / 2 + 1) as f32;
+    let coordinates = match roadside_ground(features, point, side_distance) {
+        Some(coordinates) => coordinates,
+        None => return,
+    };
+
+    for y in 0..2 {
+        tree::chop(excerpt, coordinates + BlockCoord(0, y, 0));
+    }
+    excerpt.set_block_at(coordinates, Block::ChiseledStoneBricks);
+
+    // The sign on top faces the road.
+    let (dx, dz) = point.direction;
+    let facing = signs::direction_16(signs::surface_from_vector(dz, -dx));
+    let mut lines = vec![String::new()];
+    if !town_name.is_empty() {
+        lines.push(town_name.to_string());
+    }
+    lines.push(format!("{} m", distance));
+    excerpt.set_block_at(
+        coordinates + BlockCoord(0, 1, 0),
+        signs::sign(WallOrRotatedOnFloor::Floor(facing), &lines),
+    );
+}
+
+/// Build a wayside shrine on the left hand side of the road: A stone pillar,
+/// with a lantern in a niche and a sign with the name of the saint.
+fn place_shrine(
+    excerpt: &mut WorldExcerpt,
+    features: &Features,
+    point: &RoadPoint,
+    road_width: i64,
+    saint: &str,
+) {
+    let side_distance = -((road_width / 2 + 2) as f32);
+    let coordinates = match roadside_ground(features, point, side_distance) {
+        Some(coordinates) => coordinates,
+        None => return,
+    };
+
+    for y in 0..4 {
+        tree::chop(excerpt, coordinates + BlockCoord(0, y, 0));
+    }
+    excerpt.set_block_at(coordinates, Block::StoneBricks);
+    excerpt.set_block_at(coordinates + BlockCoord(0, 1, 0), Block::StoneBricks);
+    excerpt.set_block_at(
+        coordinates + BlockCoord(0, 2, 0),
+        Block::Lantern { mounted_at: Surface2::Down, waterlogged: false },
+    );
+    excerpt.set_block_at(coordinates + BlockCoord(0, 3, 0), Block::StoneBricks);
+
+    // Sign on the side of the pillar facing the road
+    let (dx, dz) = point.direction;
+    let towards_road = signs::surface_from_vector(dz, -dx).opposite();
+    let sign_coordinates = coordinates + BlockCoord(0, 1, 0) + geometry::offset(towards_road);
+    if excerpt.block_at(sign_coordinates) == Some(&Block::Air) {
+        let lines = [String::new(), saint.to_string()];
+        excerpt.set_block_at(
+            sign_coordinates,
+            signs::sign(WallOrRotatedOnFloor::Wall(towards_road.opposite()), &lines),
+        );
+    }
+}
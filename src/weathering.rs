@@ -0,0 +1,160 @@
+//! Weathering of the finished town, for a lived-in look: Stonework cracked and overgrown
+//! with moss, vines growing down the walls, cobwebs in the corners under the ceilings, and
+//! grass growing through the gravel of the roads and paths.
+
+use std::collections::HashSet;
+
+use mcprogedit::block::{Block, Grass};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::positioning::{DirectionFlags6, Surface4};
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use crate::block_palette::{self, is_open};
+use crate::generation_context::Building;
+use crate::geometry::{self, directions};
+
+/// Chance for each block of stone to be cracked or overgrown with moss, at full intensity.
+const STONE_CHANCE: f64 = 0.3;
+/// Chance for each block of gravel open to the sky to be grown over, at full intensity.
+const GRASS_CHANCE: f64 = 0.15;
+/// Chance for each column of the outside walls to have vines growing down it, at full intensity.
+const VINE_CHANCE: f64 = 0.15;
+/// Chance for each corner under a ceiling to have a cobweb in it, at full intensity.
+const COBWEB_CHANCE: f64 = 0.5;
+/// Chance for each column of the outside walls to have vines growing down it, in the jungle style.
+const JUNGLE_VINE_CHANCE: f64 = 0.35;
+/// Longest a vine may hang down the wall.
+const VINE_LENGTH_MAX: i64 = 5;
+
+/// Weather the whole excerpt, and the buildings in it, with the given `intensity`, from 0
+/// for no weathering to 1 for the most. Returns the number of blocks changed.
+pub fn weather(excerpt: &mut WorldExcerpt, buildings: &[Building], intensity: f32, rng: &mut StdRng) -> usize {
+    let intensity = intensity.max(0.0).min(1.0) as f64;
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut count = 0;
+
+    // Cracked and mossy stonework, and grass through the gravel
+    for x in 0..x_len as i64 {
+        for z in 0..z_len as i64 {
+            for y in 0..y_len as i64 {
+                let coordinates = BlockCoord(x, y, z);
+                let above = coordinates + BlockCoord(0, 1, 0);
+                match excerpt.block_at(coordinates) {
+                    Some(Block::Gravel) if excerpt.block_at(above) == Some(&Block::Air) => {
+                        if rng.gen_bool(intensity * GRASS_CHANCE) {
+                            excerpt.set_block_at(coordinates, Block::GrassBlock);
+                            if rng.gen() {
+                                excerpt.set_block_at(above, Block::Grass(Grass::Grass));
+                            }
+                            count += 1;
+                        }
+                    }
+                    Some(block) => {
+                        if let Some(weathered) = block_palette::weathered(block, rng.gen()) {
+                            if rng.gen_bool(intensity * STONE_CHANCE) {
+                                excerpt.set_block_at(coordinates, weathered);
+                                count += 1;
+                            }
+                        }
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+
+    // Vines down the outside walls, and cobwebs in the corners inside
+    for building in buildings {
+        count += hang_vines_on_walls(excerpt, building, intensity * VINE_CHANCE, rng);
+        count += spin_cobwebs(excerpt, building, intensity * COBWEB_CHANCE, rng);
+    }
+
+    count
+}
+
+/// Let vines grow from the roofs and down the outside walls of all the buildings, as in the
+/// jungle style. Returns the number of vine blocks placed.
+pub fn overgrow_with_vines(excerpt: &mut WorldExcerpt, buildings: &[Building], rng: &mut StdRng) -> usize {
+    buildings
+        .iter()
+        .map(|building| hang_vines_on_walls(excerpt, building, JUNGLE_VINE_CHANCE, rng))
+        .sum()
+}
+
+/// Let vines grow down the outside walls of the building, from the top of the wall, on each
+/// column of wall with the given `chance`. Returns the number of vine blocks placed.
+pub fn hang_vines_on_walls(excerpt: &mut WorldExcerpt, building: &Building, chance: f64, rng: &mut StdRng) -> usize {
+    let footprint: HashSet<BlockColumnCoord> = building.footprint.iter().copied().collect();
+    let mut count = 0;
+    for column in &building.footprint {
+        for direction in &directions() {
+            let offset = geometry::offset(*direction);
+            let outside = BlockColumnCoord(column.0 + offset.0, column.1 + offset.2);
+            if footprint.contains(&outside) || !rng.gen_bool(chance) {
+                continue;
+            }
+            if let Some(top) = top_of(excerpt, *column) {
+                count += hang_vines(excerpt, BlockCoord(outside.0, top, outside.1), direction.opposite(), rng);
+            }
+        }
+    }
+    count
+}
+
+/// Put cobwebs in the corners under the ceilings of the building, each with the given
+/// `chance`. Returns the number of cobwebs placed.
+fn spin_cobwebs(excerpt: &mut WorldExcerpt, building: &Building, chance: f64, rng: &mut StdRng) -> usize {
+    let (_, y_len, _) = excerpt.dim();
+    let mut count = 0;
+    for column in &building.footprint {
+        for y in 0..y_len as i64 {
+            let coordinates = BlockCoord(column.0, y, column.1);
+            if excerpt.block_at(coordinates) != Some(&Block::Air)
+                || is_open(excerpt.block_at(coordinates + BlockCoord(0, 1, 0)))
+            {
+                continue;
+            }
+            let is_walled = |direction: Surface4| !is_open(excerpt.block_at(coordinates + geometry::offset(direction)));
+            let is_corner = (is_walled(Surface4::North) || is_walled(Surface4::South))
+                && (is_walled(Surface4::East) || is_walled(Surface4::West));
+            if is_corner && rng.gen_bool(chance) {
+                excerpt.set_block_at(coordinates, Block::Cobweb);
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// Hang vines down from `start`, clinging to the wall on the `wall` side of them, for as
+/// long as there is wall behind them and air for them to grow in. Returns the length of
+/// the vine.
+pub fn hang_vines(excerpt: &mut WorldExcerpt, start: BlockCoord, wall: Surface4, rng: &mut StdRng) -> usize {
+    let vines = Block::Vines(DirectionFlags6 {
+        east: wall == Surface4::East,
+        down: false,
+        north: wall == Surface4::North,
+        south: wall == Surface4::South,
+        up: false,
+        west: wall == Surface4::West,
+    });
+    let behind = geometry::offset(wall);
+    let mut length = 0;
+    for depth in 0..rng.gen_range(1..=VINE_LENGTH_MAX) {
+        let coordinates = start - BlockCoord(0, depth, 0);
+        if excerpt.block_at(coordinates) != Some(&Block::Air) || is_open(excerpt.block_at(coordinates + behind)) {
+            break;
+        }
+        excerpt.set_block_at(coordinates, vines.clone());
+        length += 1;
+    }
+    length
+}
+
+/// Height of the highest block of the column that is not air, if there is one.
+pub fn top_of(excerpt: &WorldExcerpt, column: BlockColumnCoord) -> Option<i64> {
+    let (_, y_len, _) = excerpt.dim();
+    (0..y_len as i64).rev().find(|y| !is_open(excerpt.block_at(BlockCoord(column.0, *y, column.1))))
+}
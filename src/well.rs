@@ -0,0 +1,189 @@
+//! Wells: a cobblestone ring around a shaft of water, under a roof with a hanging chain,
+//! or in desert towns a covered cistern of sandstone.
+
+use mcprogedit::block::Block;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{FenceMaterial, Material};
+use mcprogedit::positioning::Axis3;
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::plot::{Plot, PlotEdgeKind};
+use crate::tree;
+
+use std::collections::HashSet;
+
+/// Distance from the plot corner to the middle of the well, along each axis.
+const CORNER_INSET: i64 = 2;
+/// Depth of the water in the shaft.
+const WATER_DEPTH: i64 = 2;
+
+/// Find room for a well in a corner of the plot next to a street.
+/// Returns the middle of the well, if there is room for one.
+pub fn well_site(plot: &Plot, features: &Features) -> Option<BlockColumnCoord> {
+    let polygon = plot.polygon();
+    let middle = geometry::polygon_center(&polygon);
+    let (x_len, z_len) = features.dimensions();
+
+    let fits = |center: BlockColumnCoord| {
+        (center.0 - 1..=center.0 + 1).all(|x| {
+            (center.1 - 1..=center.1 + 1).all(|z| {
+                x >= 0 && z >= 0 && x < x_len as i64 && z < z_len as i64
+                    && features.water[(x as u32, z as u32)] != image::Luma([255u8])
+                    && geometry::point_position_relative_to_polygon(BlockColumnCoord(x, z), &polygon)
+                        == InOutSide::Inside
+            })
+        })
+    };
+
+    plot.edges.iter()
+        .filter(|edge| matches!(edge.kind, PlotEdgeKind::Road { .. }))
+        .flat_map(|edge| vec![edge.points.0, edge.points.1])
+        .map(|corner| {
+            BlockColumnCoord(
+                corner.0 + (middle.0 - corner.0).signum() * CORNER_INSET,
+                corner.2 + (middle.1 - corner.2).signum() * CORNER_INSET,
+            )
+        })
+        .find(|center| fits(*center))
+}
+
+/// Dig a well on one of the given courtyard plots in each district, and then on more of
+/// them until there are `wells_min` wells. The plots are given with their indices, and
+/// the districts they are in. Digs covered cisterns instead of open wells if `cisterns`.
+/// Returns the indices of the plots with a well.
+pub fn dig_wells(
+    excerpt: &mut WorldExcerpt,
+    courtyards: &[(usize, Option<usize>, &Plot)],
+    wells_min: usize,
+    features: &Features,
+    cisterns: bool,
+) -> HashSet<usize> {
+    let build = if cisterns { build_cistern } else { build_well };
+
+    let mut districts_with_well = HashSet::new();
+    let mut well_plots = HashSet::new();
+    for (index, district, plot) in courtyards {
+        let district = match district {
+            Some(district) if !districts_with_well.contains(district) => *district,
+            _ => continue,
+        };
+        if let Some(center) = well_site(plot, features) {
+            build(excerpt, center, features);
+            districts_with_well.insert(district);
+            well_plots.insert(*index);
+        }
+    }
+
+    // More wells, for a large population
+    for (index, _, plot) in courtyards {
+        if well_plots.len() >= wells_min {
+            break;
+        }
+        if well_plots.contains(index) {
+            continue;
+        }
+        if let Some(center) = well_site(plot, features) {
+            build(excerpt, center, features);
+            well_plots.insert(*index);
+        }
+    }
+    well_plots
+}
+
+/// Build a well centered on `center`, level with the highest ground around it:
+/// A cobblestone ring around a shaft of water, with a slab roof on fence posts
+/// and a chain hanging from the roof into the shaft.
+pub fn build_well(excerpt: &mut WorldExcerpt, center: BlockColumnCoord, features: &Features) {
+    let ground_at = |x: i64, z: i64| {
+        let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+        y as i64
+    };
+    let y = (center.0 - 1..=center.0 + 1)
+        .flat_map(|x| (center.1 - 1..=center.1 + 1).map(move |z| (x, z)))
+        .map(|(x, z)| ground_at(x, z))
+        .max()
+        .unwrap_or(0);
+    let bottom_y = y - WATER_DEPTH - 1;
+
+    for dx in -1..=1 {
+        for dz in -1..=1 {
+            let (x, z) = (center.0 + dx, center.1 + dz);
+            for clear_y in y..y + 4 {
+                tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+            }
+
+            if (dx, dz) == (0, 0) {
+                // The shaft, with water at the bottom
+                excerpt.set_block_at(BlockCoord(x, bottom_y, z), Block::Cobblestone);
+                for water_y in bottom_y + 1..y {
+                    excerpt.set_block_at(BlockCoord(x, water_y, z), Block::WaterSource);
+                }
+            } else {
+                // The ring, on a foundation reaching the bottom of the shaft
+                for ring_y in bottom_y.min(ground_at(x, z) - 1)..=y {
+                    excerpt.set_block_at(BlockCoord(x, ring_y, z), Block::Cobblestone);
+                }
+            }
+
+            // Corner posts carrying the roof
+            if dx != 0 && dz != 0 {
+                for post_y in y + 1..y + 3 {
+                    excerpt.set_block_at(
+                        BlockCoord(x, post_y, z),
+                        Block::Fence { material: FenceMaterial::Spruce, waterlogged: false },
+                    );
+                }
+            }
+            excerpt.set_block_at(BlockCoord(x, y + 3, z), Block::bottom_slab(Material::Spruce));
+        }
+    }
+
+    // Chain for the bucket, hanging from the middle of the roof
+    for chain_y in y + 1..y + 3 {
+        excerpt.set_block_at(BlockCoord(center.0, chain_y, center.1), Block::Chain { alignment: Axis3::Y });
+    }
+}
+
+/// Build a covered cistern centered on `center`, level with the highest ground around it:
+/// A sandstone ring around a shaft of water, covered by slabs and an iron grate
+/// over the shaft, keeping the water clean and cool.
+pub fn build_cistern(excerpt: &mut WorldExcerpt, center: BlockColumnCoord, features: &Features) {
+    let ground_at = |x: i64, z: i64| {
+        let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+        y as i64
+    };
+    let y = (center.0 - 1..=center.0 + 1)
+        .flat_map(|x| (center.1 - 1..=center.1 + 1).map(move |z| (x, z)))
+        .map(|(x, z)| ground_at(x, z))
+        .max()
+        .unwrap_or(0);
+    let bottom_y = y - WATER_DEPTH - 1;
+
+    for dx in -1..=1 {
+        for dz in -1..=1 {
+            let (x, z) = (center.0 + dx, center.1 + dz);
+            for clear_y in y..y + 3 {
+                tree::chop(excerpt, BlockCoord(x, clear_y, z));
+                excerpt.set_block_at(BlockCoord(x, clear_y, z), Block::Air);
+            }
+
+            if (dx, dz) == (0, 0) {
+                // The shaft, with water at the bottom, under a grate
+                excerpt.set_block_at(BlockCoord(x, bottom_y, z), Block::Sandstone);
+                for water_y in bottom_y + 1..y {
+                    excerpt.set_block_at(BlockCoord(x, water_y, z), Block::WaterSource);
+                }
+                excerpt.set_block_at(BlockCoord(x, y + 1, z), Block::IronBars { waterlogged: false });
+            } else {
+                // The ring, on a foundation reaching the bottom of the shaft, with a slab cover
+                for ring_y in bottom_y.min(ground_at(x, z) - 1)..=y {
+                    excerpt.set_block_at(BlockCoord(x, ring_y, z), Block::Sandstone);
+                }
+                excerpt.set_block_at(BlockCoord(x, y + 1, z), Block::bottom_slab(Material::SmoothSandstone));
+            }
+        }
+    }
+}
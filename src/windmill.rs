@@ -0,0 +1,222 @@
+//! Windmill on elevated ground by the fields, for grinding their grain.
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::info;
+use mcprogedit::block::Block;
+use mcprogedit::colour::Colour;
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::material::{DoorMaterial, FenceMaterial};
+use mcprogedit::positioning::{Axis3, Surface2, Surface4, Surface6};
+use mcprogedit::world_excerpt::WorldExcerpt;
+
+use crate::block_palette::BlockPalette;
+use crate::farm::Field;
+use crate::features::Features;
+use crate::geometry::{self, disc, is_in_disc, step};
+use crate::pathfinding::{self, RoadPath};
+use crate::signs;
+use crate::tree;
+use crate::types::Snake;
+
+/// Radius of the tower, measured to the middle of the wall.
+const TOWER_RADIUS: i64 = 3;
+/// Height of the tower walls, above the floor.
+const TOWER_HEIGHT: i64 = 9;
+/// Height of the conical roof.
+const ROOF_HEIGHT: i64 = 4;
+/// Length of each of the four sails, from the hub.
+const SAIL_LENGTH: i64 = 6;
+/// Farthest the windmill may be from the nearest field.
+const FIELD_DISTANCE_MAX: i64 = 24;
+/// Distance the windmill must keep from the fields and from the town walls.
+const CLEARANCE: u8 = 4;
+/// Largest difference in ground height under the tower.
+const SITE_SLOPE_MAX: i64 = 3;
+/// Bonus to the height of a site, for being on a hilltop.
+const HILLTOP_BONUS: i64 = 4;
+
+/// A windmill, and the path from its door to the nearest field.
+#[derive(Clone, Debug)]
+pub struct Windmill {
+    /// Middle of the tower.
+    pub center: BlockColumnCoord,
+    /// Y coordinate of the floor.
+    pub floor_y: i64,
+    /// The side of the tower with the door.
+    pub door_side: Surface4,
+    /// Path from the door to the gate of the nearest field, if one was found.
+    pub path: Option<RoadPath>,
+}
+
+/// Find the highest ground close to the fields, preferably on a hilltop, for a windmill.
+pub fn plan_windmill(features: &Features, fields: &[Field], wall_circles: &[&Snake]) -> Option<Windmill> {
+    if fields.is_empty() {
+        return None;
+    }
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+
+    // Keep clear of the fields and the towns.
+    let mut occupied = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut occupied, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+    for field in fields {
+        let (min, max) = field.fenced_area();
+        for x in min.0.max(0)..=max.0.min(x_len - 1) {
+            for z in min.1.max(0)..=max.1.min(z_len - 1) {
+                occupied.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+    }
+    let occupied = imageproc::morphology::dilate(&occupied, Norm::LInf, CLEARANCE);
+
+    let margin = TOWER_RADIUS + SAIL_LENGTH;
+    let mut best: Option<(i64, BlockColumnCoord, i64)> = None;
+    for x in margin..x_len - margin {
+        for z in margin..z_len - margin {
+            let center = BlockColumnCoord(x, z);
+            let near_field = fields.iter().any(|field| {
+                geometry::manhattan_distance(center, field.gate()) as i64 <= FIELD_DISTANCE_MAX
+            });
+            if !near_field {
+                continue;
+            }
+            if let Some(floor_y) =
+                features.site_floor(&occupied, &disc(center, TOWER_RADIUS), SITE_SLOPE_MAX)
+            {
+                let image::Luma([hilltop]) = features.hilltop[(x as u32, z as u32)];
+                let score = floor_y + if hilltop > 0 { HILLTOP_BONUS } else { 0 };
+                if best.map_or(true, |(best_score, _, _)| score > best_score) {
+                    best = Some((score, center, floor_y));
+                }
+            }
+        }
+    }
+    let (_, center, floor_y) = best?;
+
+    // Door towards the nearest field, with a path to its gate.
+    let field = fields.iter().min_by_key(|field| geometry::manhattan_distance(center, field.gate()))?;
+    let gate = field.gate();
+    let door_side = signs::surface_from_vector((gate.0 - center.0) as f32, (gate.1 - center.1) as f32);
+    let outside_door = step(center, door_side, TOWER_RADIUS + 1);
+    let outside_gate = step(gate, field.gate_side, 1);
+    let image::Luma([door_y]) = features.terrain[(outside_door.0 as u32, outside_door.1 as u32)];
+    let image::Luma([gate_y]) = features.terrain[(outside_gate.0 as u32, outside_gate.1 as u32)];
+    let path = pathfinding::road_path(
+        BlockCoord(outside_door.0, door_y as i64, outside_door.1),
+        BlockCoord(outside_gate.0, gate_y as i64, outside_gate.1),
+        &features.terrain,
+        Some(&features.water),
+    );
+
+    info!("Planned a windmill at {:?}.", center);
+    Some(Windmill { center, floor_y, door_side, path })
+}
+
+/// Build the windmill tower with its roof and sails.
+pub fn build_windmill(
+    excerpt: &mut WorldExcerpt,
+    windmill: &Windmill,
+    features: &Features,
+    palette: &BlockPalette,
+) {
+    let BlockColumnCoord(cx, cz) = windmill.center;
+    let floor_y = windmill.floor_y;
+    let top_y = floor_y + TOWER_HEIGHT;
+
+    // Round stone tower on a solid foundation
+    for (x, z) in disc(windmill.center, TOWER_RADIUS) {
+        let image::Luma([ground]) = features.terrain[(x as u32, z as u32)];
+        for y in ground as i64 - 1..floor_y {
+            excerpt.set_block_at(BlockCoord(x, y, z), palette.foundation.clone());
+        }
+        excerpt.set_block_at(BlockCoord(x, floor_y, z), palette.floor.clone());
+
+        let is_wall = !is_in_disc((x - cx, z - cz), TOWER_RADIUS - 1);
+        for y in floor_y + 1..=top_y {
+            tree::chop(excerpt, BlockCoord(x, y, z));
+            let block = if is_wall { palette.foundation.clone() } else { Block::Air };
+            excerpt.set_block_at(BlockCoord(x, y, z), block);
+        }
+    }
+
+    // Conical roof
+    for level in 0..ROOF_HEIGHT {
+        let radius = TOWER_RADIUS - level;
+        for (x, z) in disc(windmill.center, radius) {
+            tree::chop(excerpt, BlockCoord(x, top_y + 1 + level, z));
+            excerpt.set_block_at(BlockCoord(x, top_y + 1 + level, z), palette.roof.clone());
+        }
+    }
+
+    // Door, and windows on the sides
+    let door = step(windmill.center, windmill.door_side, TOWER_RADIUS);
+    let facing = windmill.door_side.opposite();
+    for (y, half) in &[
+        (floor_y + 1, mcprogedit::block::DoorHalf::Lower),
+        (floor_y + 2, mcprogedit::block::DoorHalf::Upper),
+    ] {
+        excerpt.set_block_at(BlockCoord(door.0, *y, door.1), Block::Door(mcprogedit::block::Door {
+            material: DoorMaterial::Oak,
+            facing,
+            half: *half,
+            hinged_at: mcprogedit::block::Hinge::Right,
+            open: false,
+        }));
+    }
+    for side in &[windmill.door_side.rotated_90_cw(), windmill.door_side.rotated_90_ccw()] {
+        let window = step(windmill.center, *side, TOWER_RADIUS);
+        for y in &[floor_y + 3, floor_y + 7] {
+            excerpt.set_block_at(BlockCoord(window.0, *y, window.1), palette.flat_window.clone());
+        }
+    }
+
+    // Sacks of grain and flour along the back wall, and a lantern in the ceiling
+    let back = windmill.door_side.opposite();
+    let inside_back = step(windmill.center, back, TOWER_RADIUS - 1);
+    for across in -1..=1 {
+        let column = step(inside_back, back.rotated_90_cw(), across);
+        excerpt.set_block_at(BlockCoord(column.0, floor_y + 1, column.1), Block::barrel(Surface6::Up));
+    }
+    excerpt.set_block_at(
+        BlockCoord(cx, top_y, cz),
+        Block::Lantern { mounted_at: Surface2::Up, waterlogged: false },
+    );
+
+    build_sails(excerpt, windmill);
+}
+
+/// Put the sails on the back of the tower, away from the door, in a pinwheel pattern.
+fn build_sails(excerpt: &mut WorldExcerpt, windmill: &Windmill) {
+    let back = windmill.door_side.opposite();
+    let hub_column = step(windmill.center, back, TOWER_RADIUS + 1);
+    let hub = BlockCoord(hub_column.0, windmill.floor_y + TOWER_HEIGHT - 1, hub_column.1);
+
+    let axle_axis = match back {
+        Surface4::North | Surface4::South => Axis3::Z,
+        Surface4::East | Surface4::West => Axis3::X,
+    };
+    excerpt.set_block_at(hub, Block::oak_log(axle_axis));
+
+    // The four arms, each with its sail cloth on the next arm's side.
+    let side = geometry::offset(back.rotated_90_cw());
+    let up = BlockCoord(0, 1, 0);
+    let down = BlockCoord(0, -1, 0);
+    let left = BlockCoord(-side.0, 0, -side.2);
+    let arms = [(up, side), (side, down), (down, left), (left, up)];
+
+    for (arm, cloth) in &arms {
+        for distance in 1..=SAIL_LENGTH {
+            let spar = hub + BlockCoord(arm.0 * distance, arm.1 * distance, arm.2 * distance);
+            tree::chop(excerpt, spar);
+            excerpt.set_block_at(spar, Block::Fence { material: FenceMaterial::Spruce, waterlogged: false });
+            if distance > 1 {
+                let sail = spar + *cloth;
+                tree::chop(excerpt, sail);
+                excerpt.set_block_at(sail, Block::Wool { colour: Colour::White });
+            }
+        }
+    }
+}
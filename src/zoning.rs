@@ -0,0 +1,637 @@
+//! Zoning of the town districts into land uses, subject to the constraints
+//! on the mix of uses given in the configuration, and placement of the nuisance
+//! industries outside the town wall.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
+
+use image::GrayImage;
+use imageproc::distance_transform::Norm;
+use log::{info, warn};
+use mcprogedit::block::{Block, Flower};
+use mcprogedit::coordinates::{BlockColumnCoord, BlockCoord};
+use mcprogedit::positioning::Surface4;
+use mcprogedit::world_excerpt::WorldExcerpt;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::Serialize;
+
+use crate::block_palette::BlockPalette;
+use crate::build_area::BuildArea;
+use crate::config::GenerationConfig;
+use crate::farm::Field;
+use crate::features::Features;
+use crate::geometry::{self, InOutSide};
+use crate::line::line;
+use crate::pathfinding::{self, RoadPath};
+use crate::plot::Plot;
+use crate::signs;
+use crate::tannery::{NuisanceIndustry, NuisanceKind, YARD_HALF_WIDTH};
+use crate::types::Snake;
+use crate::wall;
+
+/// Farthest a district may be from a gate, for the district to count as being by the gate.
+const GATE_ADJACENCY_DISTANCE: usize = 12;
+/// Direction the prevailing wind blows towards, carrying the stench of the nuisance industries.
+const PREVAILING_WIND: Surface4 = Surface4::East;
+/// Distance kept between the nuisance industries and the towns, fields and each other.
+const NUISANCE_CLEARANCE: u8 = 8;
+/// Farthest from a town gate a nuisance industry may be.
+const NUISANCE_GATE_DISTANCE_MAX: usize = 80;
+/// Farthest from the river the yard of a nuisance industry may be.
+const NUISANCE_RIVER_DISTANCE_MAX: i64 = 3;
+/// Farthest from the town wall the river may be, for it to count as running past town.
+const TOWN_RIVER_REACH: u8 = 16;
+/// Closest to the other structures outside town a nuisance industry may be.
+const NUISANCE_TAKEN_DISTANCE_MIN: usize = 24;
+/// Largest difference in ground height under the yard of a nuisance industry.
+const NUISANCE_SLOPE_MAX: i64 = 2;
+/// Distance from the sightline to the edge of a view corridor.
+const VIEW_CORRIDOR_HALF_WIDTH: i64 = 2;
+
+/// What a district is used for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistrictUse {
+    /// Houses.
+    Residential,
+    /// Trade, around open squares.
+    Market,
+    /// Parks and gardens, without houses.
+    Green,
+    /// Workshops and crafts.
+    Workshop,
+    /// The paved market square at the town center.
+    Plaza,
+}
+
+/// A constraint on the mix of district uses that could not be satisfied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Infeasibility {
+    /// There are fewer districts available than the required number of market districts.
+    TooFewMarketDistricts { required: usize, available: usize },
+    /// The green space can not reach its lower limit without exceeding its upper limit.
+    GreenSpaceOutOfRange { min: f32, max: f32, reached: f32 },
+    /// A workshop quarter by the mine gate is required, but there is no mine.
+    NoMine,
+    /// A workshop quarter by the mine gate is required, but no road passes through the wall.
+    NoGate,
+    /// No free district lies by the gate facing the mine.
+    NoDistrictByGate { gate: BlockColumnCoord },
+}
+
+impl fmt::Display for Infeasibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooFewMarketDistricts { required, available } => write!(
+                f,
+                "{} market districts required, but only {} districts available",
+                required, available,
+            ),
+            Self::GreenSpaceOutOfRange { min, max, reached } => write!(
+                f,
+                "green space must be between {:.0}% and {:.0}%, but only {:.0}% could be set aside",
+                min * 100.0, max * 100.0, reached * 100.0,
+            ),
+            Self::NoMine => write!(f, "workshop quarter by the mine required, but there is no mine"),
+            Self::NoGate => write!(f, "workshop quarter by the mine gate required, but the town has no gate"),
+            Self::NoDistrictByGate { gate } => write!(f, "no free district by the gate at {:?}", gate),
+        }
+    }
+}
+
+/// Decide the use of each district, satisfying the constraints given in `config`.
+///
+/// Constraints that can not be satisfied are reported, and otherwise ignored,
+/// so that the returned uses are always a complete plan.
+pub fn plan_zoning(
+    districts: &[Snake],
+    town_center: BlockColumnCoord,
+    wall_circle: &Snake,
+    city_roads: &[RoadPath],
+    mine_site: Option<BlockColumnCoord>,
+    config: &GenerationConfig,
+) -> (Vec<DistrictUse>, Vec<Infeasibility>) {
+    let mut uses = vec![DistrictUse::Residential; districts.len()];
+    let mut infeasibilities = Vec::new();
+
+    let centroids: Vec<BlockColumnCoord> = districts.iter().map(|district| centroid(district)).collect();
+    let areas: Vec<i64> = districts.iter().map(|district| geometry::area(district).abs()).collect();
+
+    // Plaza, in the district at the town center
+    if config.central_plaza {
+        if let Some(index) = central_district(districts, &centroids, town_center) {
+            uses[index] = DistrictUse::Plaza;
+        }
+    }
+
+    // Workshop quarter, by the gate facing the mine
+    if config.workshop_quarter_by_mine {
+        match workshop_district(districts, &uses, wall_circle, city_roads, mine_site) {
+            Ok(index) => uses[index] = DistrictUse::Workshop,
+            Err(infeasibility) => infeasibilities.push(infeasibility),
+        }
+    }
+
+    // Market districts, closest to the town center
+    let mut by_centrality: Vec<usize> = (0..districts.len()).collect();
+    by_centrality.sort_by_key(|index| geometry::manhattan_distance(centroids[*index], town_center));
+    let mut market_count = 0;
+    for index in &by_centrality {
+        if market_count >= config.market_districts_min {
+            break;
+        }
+        if uses[*index] == DistrictUse::Residential {
+            uses[*index] = DistrictUse::Market;
+            market_count += 1;
+        }
+    }
+    if market_count < config.market_districts_min {
+        infeasibilities.push(Infeasibility::TooFewMarketDistricts {
+            required: config.market_districts_min,
+            available: market_count,
+        });
+    }
+
+    // Green space, farthest from the town center, within the limits on its share of the town
+    let total_area = areas.iter().sum::<i64>().max(1) as f32;
+    let mut green_area = 0;
+    for index in by_centrality.iter().rev() {
+        if green_area as f32 / total_area >= config.green_space_min {
+            break;
+        }
+        let share = (green_area + areas[*index]) as f32 / total_area;
+        if uses[*index] == DistrictUse::Residential && share <= config.green_space_max {
+            uses[*index] = DistrictUse::Green;
+            green_area += areas[*index];
+        }
+    }
+    let green_share = green_area as f32 / total_area;
+    if green_share < config.green_space_min {
+        infeasibilities.push(Infeasibility::GreenSpaceOutOfRange {
+            min: config.green_space_min,
+            max: config.green_space_max,
+            reached: green_share,
+        });
+    }
+
+    for infeasibility in &infeasibilities {
+        warn!("Zoning constraint not satisfied: {}.", infeasibility);
+    }
+    info!("Zoned the districts as {:?}.", uses);
+
+    (uses, infeasibilities)
+}
+
+/// Most stories for the houses in each district, shaping the skyline of the town:
+/// `config.stories_max` in the districts closest to the town center, falling off
+/// evenly towards a single story in the districts farthest out.
+pub fn district_story_caps(
+    districts: &[Snake],
+    town_center: BlockColumnCoord,
+    config: &GenerationConfig,
+) -> Vec<usize> {
+    let distances: Vec<f32> = districts.iter()
+        .map(|district| geometry::euclidean_distance(centroid(district), town_center))
+        .collect();
+    let nearest = distances.iter().copied().fold(f32::INFINITY, f32::min);
+    let farthest = distances.iter().copied().fold(0.0, f32::max);
+    let stories_max = config.stories_max.max(1);
+
+    distances.iter()
+        .map(|distance| {
+            let outwards = if farthest > nearest { (distance - nearest) / (farthest - nearest) } else { 0.0 };
+            stories_max - (outwards * (stories_max - 1) as f32).round() as usize
+        })
+        .collect()
+}
+
+/// Tallest the houses on each plot may be, in blocks, keeping the view corridors open:
+/// the straight sightlines from each town gate to the landmark at the town center.
+///
+/// Plots reaching into a corridor are limited to `config.view_corridor_height`, all others
+/// get 0, meaning no limit. All plots get 0 if the view corridors are not protected.
+pub fn view_corridor_height_caps(
+    plots: &[Plot],
+    wall_circle: &Snake,
+    city_roads: &[RoadPath],
+    town_center: BlockColumnCoord,
+    config: &GenerationConfig,
+) -> Vec<usize> {
+    let mut caps = vec![0; plots.len()];
+    if config.view_corridor_height == 0 {
+        return caps;
+    }
+
+    // Columns covered by the corridors
+    let mut corridor: HashSet<(i64, i64)> = HashSet::new();
+    for (gate, _) in wall::gates(wall_circle, city_roads) {
+        let sightline = line(&BlockCoord(gate.0, 0, gate.1), &BlockCoord(town_center.0, 0, town_center.1), 1);
+        for BlockCoord(x, _, z) in sightline {
+            for dx in -VIEW_CORRIDOR_HALF_WIDTH..=VIEW_CORRIDOR_HALF_WIDTH {
+                for dz in -VIEW_CORRIDOR_HALF_WIDTH..=VIEW_CORRIDOR_HALF_WIDTH {
+                    corridor.insert((x + dx, z + dz));
+                }
+            }
+        }
+    }
+
+    for (index, plot) in plots.iter().enumerate() {
+        let polygon = plot.polygon();
+        if corridor.iter().any(|(x, z)| {
+            geometry::point_position_relative_to_polygon(BlockColumnCoord(*x, *z), &polygon) == InOutSide::Inside
+        }) {
+            caps[index] = config.view_corridor_height;
+        }
+    }
+    info!(
+        "Limited {} plots to {} blocks, to keep the view corridors open.",
+        caps.iter().filter(|cap| **cap > 0).count(),
+        config.view_corridor_height,
+    );
+
+    caps
+}
+
+/// The district containing the town center, or if the center is on a street,
+/// the district closest to it.
+fn central_district(
+    districts: &[Snake],
+    centroids: &[BlockColumnCoord],
+    town_center: BlockColumnCoord,
+) -> Option<usize> {
+    districts.iter()
+        .position(|district| {
+            geometry::point_position_relative_to_polygon(town_center, district) == InOutSide::Inside
+        })
+        .or_else(|| {
+            (0..districts.len())
+                .min_by_key(|index| geometry::manhattan_distance(centroids[*index], town_center))
+        })
+}
+
+/// The free district closest to the town gate facing the mine.
+fn workshop_district(
+    districts: &[Snake],
+    uses: &[DistrictUse],
+    wall_circle: &Snake,
+    city_roads: &[RoadPath],
+    mine_site: Option<BlockColumnCoord>,
+) -> Result<usize, Infeasibility> {
+    let mine_site = mine_site.ok_or(Infeasibility::NoMine)?;
+    let (gate, _) = wall::gates(wall_circle, city_roads)
+        .into_iter()
+        .min_by_key(|(gate, _)| geometry::manhattan_distance(*gate, mine_site))
+        .ok_or(Infeasibility::NoGate)?;
+
+    districts.iter()
+        .enumerate()
+        .filter(|(index, _)| uses[*index] == DistrictUse::Residential)
+        .filter_map(|(index, district)| {
+            district.iter()
+                .map(|point| geometry::manhattan_distance(*point, gate))
+                .min()
+                .map(|distance| (distance, index))
+        })
+        .filter(|(distance, _)| *distance <= GATE_ADJACENCY_DISTANCE)
+        .min()
+        .map(|(_, index)| index)
+        .ok_or(Infeasibility::NoDistrictByGate { gate })
+}
+
+/// Find sites outside the town wall for a tannery and a dyeworks, on the river bank
+/// downstream from town, and preferably downwind of it, with paths to the nearest gate.
+/// The sites keep clear of the towns, the fields, and the `taken` sites of other structures.
+///
+/// Nothing is placed if no river runs past the town, or the town has no gate.
+pub fn plan_nuisance_industries(
+    features: &Features,
+    wall_circle: &Snake,
+    city_roads: &[RoadPath],
+    town_center: BlockColumnCoord,
+    wall_circles: &[&Snake],
+    fields: &[Field],
+    taken: &[BlockColumnCoord],
+) -> Vec<NuisanceIndustry> {
+    let (x_len, z_len) = features.dimensions();
+    let (x_len, z_len) = (x_len as i64, z_len as i64);
+    let is_water = |column: BlockColumnCoord| {
+        column.0 >= 0 && column.1 >= 0 && column.0 < x_len && column.1 < z_len
+            && features.water[(column.0 as u32, column.1 as u32)] == image::Luma([255u8])
+    };
+    let water_level = |column: BlockColumnCoord| {
+        let image::Luma([bottom]) = features.terrain[(column.0 as u32, column.1 as u32)];
+        let image::Luma([depth]) = features.water_depth[(column.0 as u32, column.1 as u32)];
+        bottom as i64 + depth as i64
+    };
+
+    let gates = wall::gates(wall_circle, city_roads);
+    if gates.is_empty() {
+        return Vec::new();
+    }
+
+    // Water level of the river where it passes closest to town
+    let mut town = GrayImage::new(x_len as u32, z_len as u32);
+    geometry::draw_area(&mut town, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    let near_town = imageproc::morphology::dilate(&town, Norm::LInf, TOWN_RIVER_REACH);
+    let mut town_river: Option<(usize, i64)> = None;
+    for x in 0..x_len {
+        for z in 0..z_len {
+            let column = BlockColumnCoord(x, z);
+            if is_water(column) && near_town[(x as u32, z as u32)] == image::Luma([255u8]) {
+                let distance = geometry::manhattan_distance(column, town_center);
+                if town_river.map_or(true, |(closest, _)| distance < closest) {
+                    town_river = Some((distance, water_level(column)));
+                }
+            }
+        }
+    }
+    let town_water_level = match town_river {
+        Some((_, level)) => level,
+        None => {
+            info!("No river runs past the town, so there are no nuisance industries.");
+            return Vec::new();
+        }
+    };
+
+    // Keep clear of the towns and fields.
+    let mut occupied = GrayImage::new(x_len as u32, z_len as u32);
+    for wall_circle in wall_circles {
+        geometry::draw_area(&mut occupied, wall_circle, BlockColumnCoord(0, 0), image::Luma([255u8]));
+    }
+    for field in fields {
+        let (min, max) = field.fenced_area();
+        for x in min.0.max(0)..=max.0.min(x_len - 1) {
+            for z in min.1.max(0)..=max.1.min(z_len - 1) {
+                occupied.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+    }
+    let mut occupied = imageproc::morphology::dilate(&occupied, Norm::LInf, NUISANCE_CLEARANCE);
+
+    // Dry, level yards on the river bank, downstream from town
+    let wind = geometry::offset(PREVAILING_WIND);
+    let reach = YARD_HALF_WIDTH + NUISANCE_RIVER_DISTANCE_MAX;
+    let mut candidates: Vec<(i64, Reverse<i64>, BlockColumnCoord, i64)> = Vec::new();
+    for x in reach..x_len - reach {
+        for z in reach..z_len - reach {
+            let center = BlockColumnCoord(x, z);
+            if occupied[(x as u32, z as u32)] == image::Luma([255u8])
+                || gates.iter().all(|(gate, _)| {
+                    geometry::manhattan_distance(center, *gate) > NUISANCE_GATE_DISTANCE_MAX
+                })
+                || taken.iter().any(|other| {
+                    geometry::manhattan_distance(center, *other) < NUISANCE_TAKEN_DISTANCE_MIN
+                })
+            {
+                continue;
+            }
+            let river_level = [Surface4::North, Surface4::East, Surface4::South, Surface4::West].iter()
+                .filter_map(|direction| {
+                    let offset = geometry::offset(*direction);
+                    (YARD_HALF_WIDTH + 1..=reach)
+                        .map(|distance| BlockColumnCoord(x + offset.0 * distance, z + offset.2 * distance))
+                        .find(|column| is_water(*column))
+                        .map(water_level)
+                })
+                .min();
+            let river_level = match river_level {
+                Some(level) if level <= town_water_level => level,
+                _ => continue,
+            };
+            if let Some(floor_y) = yard_floor(features, &occupied, center) {
+                let downwind = (x - town_center.0) * wind.0 + (z - town_center.1) * wind.2;
+                candidates.push((river_level, Reverse(downwind), center, floor_y));
+            }
+        }
+    }
+    candidates.sort_unstable_by_key(|(river_level, downwind, center, _)| {
+        (*river_level, *downwind, center.0, center.1)
+    });
+
+    let mut industries = Vec::new();
+    for kind in &[NuisanceKind::Tannery, NuisanceKind::Dyeworks] {
+        let site = candidates.iter()
+            .find(|(_, _, center, _)| occupied[(center.0 as u32, center.1 as u32)] != image::Luma([255u8]));
+        let (center, floor_y) = match site {
+            Some((_, _, center, floor_y)) => (*center, *floor_y),
+            None => break,
+        };
+
+        // Gate of the yard towards the nearest town gate, with a path to it
+        let (gate, outwards) = *gates.iter()
+            .min_by_key(|(gate, _)| geometry::manhattan_distance(center, *gate))
+            .expect("There is at least one gate.");
+        let front = signs::surface_from_vector((gate.0 - center.0) as f32, (gate.1 - center.1) as f32);
+        let ahead = geometry::offset(front);
+        let out = geometry::offset(outwards);
+        let yard_gate = BlockColumnCoord(
+            center.0 + ahead.0 * (YARD_HALF_WIDTH + 1),
+            center.1 + ahead.2 * (YARD_HALF_WIDTH + 1),
+        );
+        let outside_gate = BlockColumnCoord(gate.0 + out.0 * 2, gate.1 + out.2 * 2);
+        let ground = |column: BlockColumnCoord| {
+            let image::Luma([y]) = features.terrain[(column.0 as u32, column.1 as u32)];
+            BlockCoord(column.0, y as i64, column.1)
+        };
+        let path = pathfinding::road_path(
+            ground(yard_gate),
+            ground(outside_gate),
+            &features.terrain,
+            Some(&features.water),
+        );
+
+        // Keep the next industry clear of this one.
+        let half_width = YARD_HALF_WIDTH + NUISANCE_CLEARANCE as i64;
+        for x in (center.0 - half_width).max(0)..=(center.0 + half_width).min(x_len - 1) {
+            for z in (center.1 - half_width).max(0)..=(center.1 + half_width).min(z_len - 1) {
+                occupied.put_pixel(x as u32, z as u32, image::Luma([255u8]));
+            }
+        }
+
+        info!("Planned a {:?} at {:?}.", kind, center);
+        industries.push(NuisanceIndustry { kind: *kind, center, floor_y, front, path });
+    }
+
+    industries
+}
+
+/// Check if the yard of a nuisance industry fits at `center`.
+/// Returns the height of the ground of the yard if it does, level with the highest ground.
+fn yard_floor(features: &Features, occupied: &GrayImage, center: BlockColumnCoord) -> Option<i64> {
+    let mut min_y = i64::MAX;
+    let mut max_y = i64::MIN;
+    for x in center.0 - YARD_HALF_WIDTH..=center.0 + YARD_HALF_WIDTH {
+        for z in center.1 - YARD_HALF_WIDTH..=center.1 + YARD_HALF_WIDTH {
+            if features.water[(x as u32, z as u32)] == image::Luma([255u8])
+                || occupied[(x as u32, z as u32)] == image::Luma([255u8])
+            {
+                return None;
+            }
+            let image::Luma([y]) = features.terrain[(x as u32, z as u32)];
+            min_y = min_y.min(y as i64);
+            max_y = max_y.max(y as i64);
+        }
+    }
+    if max_y - min_y > NUISANCE_SLOPE_MAX {
+        None
+    } else {
+        Some(max_y)
+    }
+}
+
+/// Lay out a small park on the buildable part of the plot, with flowers in the grass.
+pub fn build_park(
+    excerpt: &WorldExcerpt,
+    build_area: &BuildArea,
+    palette: &BlockPalette,
+    rng: &mut StdRng,
+) -> WorldExcerpt {
+    let (x_len, y_len, z_len) = excerpt.dim();
+    let mut output = WorldExcerpt::new(x_len, y_len, z_len);
+    let height_map = excerpt.ground_height_map();
+
+    // Only the flowers that are a single block tall
+    let flowers: Vec<Flower> = palette.flowers.iter()
+        .copied()
+        .filter(|flower| !matches!(
+            flower,
+            Flower::LilacBottom | Flower::LilacTop
+                | Flower::PeonyBottom | Flower::PeonyTop
+                | Flower::RoseBushBottom | Flower::RoseBushTop
+                | Flower::SunflowerBottom | Flower::SunflowerTop
+        ))
+        .collect();
+    if flowers.is_empty() {
+        return output;
+    }
+
+    let mut buildable: Vec<(usize, usize)> = build_area.buildable_coordinates().into_iter().collect();
+    buildable.sort_unstable();
+    for (x, z) in buildable {
+        if !rng.gen_bool(0.25) {
+            continue;
+        }
+        if let Some(y) = height_map.height_at((x, z)) {
+            let ground = BlockCoord(x as i64, y as i64 - 1, z as i64);
+            let above = BlockCoord(x as i64, y as i64, z as i64);
+            if excerpt.block_at(ground) == Some(&Block::GrassBlock)
+                && excerpt.block_at(above) == Some(&Block::Air)
+            {
+                let flower = flowers[rng.gen_range(0..flowers.len())];
+                output.set_block_at(above, Block::Flower(flower));
+            }
+        }
+    }
+
+    output
+}
+
+fn centroid(polygon: &[BlockColumnCoord]) -> BlockColumnCoord {
+    let count = polygon.len().max(1) as i64;
+    let sum = polygon.iter().fold(BlockColumnCoord(0, 0), |sum, point| sum + *point);
+    BlockColumnCoord(sum.0 / count, sum.1 / count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three square districts in a row, 10 blocks on the side, with the town center in the middle one.
+    fn districts() -> Vec<Snake> {
+        (0..3)
+            .map(|index| {
+                let x = index * 10;
+                vec![
+                    BlockColumnCoord(x, 0),
+                    BlockColumnCoord(x + 10, 0),
+                    BlockColumnCoord(x + 10, 10),
+                    BlockColumnCoord(x, 10),
+                    BlockColumnCoord(x, 0),
+                ]
+            })
+            .collect()
+    }
+
+    fn zoning(config: &GenerationConfig) -> (Vec<DistrictUse>, Vec<Infeasibility>) {
+        plan_zoning(&districts(), BlockColumnCoord(15, 5), &Vec::new(), &[], None, config)
+    }
+
+    #[test]
+    fn plaza_in_central_district() {
+        let (uses, infeasibilities) = zoning(&GenerationConfig::default());
+        assert_eq!(vec![DistrictUse::Residential, DistrictUse::Plaza, DistrictUse::Residential], uses);
+        assert!(infeasibilities.is_empty());
+    }
+
+    #[test]
+    fn market_districts_around_plaza() {
+        let config = GenerationConfig { market_districts_min: 2, ..Default::default() };
+        let (uses, infeasibilities) = zoning(&config);
+        assert_eq!(vec![DistrictUse::Market, DistrictUse::Plaza, DistrictUse::Market], uses);
+        assert!(infeasibilities.is_empty());
+    }
+
+    #[test]
+    fn too_few_market_districts() {
+        let config = GenerationConfig { market_districts_min: 5, ..Default::default() };
+        let (_, infeasibilities) = zoning(&config);
+        assert_eq!(
+            vec![Infeasibility::TooFewMarketDistricts { required: 5, available: 2 }],
+            infeasibilities,
+        );
+    }
+
+    #[test]
+    fn green_space_farthest_from_center() {
+        let config = GenerationConfig {
+            central_plaza: false,
+            green_space_min: 0.3,
+            green_space_max: 0.4,
+            ..Default::default()
+        };
+        let (uses, infeasibilities) = zoning(&config);
+        assert_eq!(vec![DistrictUse::Green, DistrictUse::Residential, DistrictUse::Residential], uses);
+        assert!(infeasibilities.is_empty());
+    }
+
+    #[test]
+    fn green_space_out_of_range() {
+        let config = GenerationConfig {
+            central_plaza: false,
+            green_space_min: 0.5,
+            green_space_max: 0.4,
+            ..Default::default()
+        };
+        let (uses, infeasibilities) = zoning(&config);
+        assert_eq!(1, uses.iter().filter(|district_use| **district_use == DistrictUse::Green).count());
+        assert!(matches!(infeasibilities[..], [Infeasibility::GreenSpaceOutOfRange { .. }]));
+    }
+
+    #[test]
+    fn workshop_quarter_without_mine() {
+        let config = GenerationConfig { workshop_quarter_by_mine: true, ..Default::default() };
+        let (_, infeasibilities) = zoning(&config);
+        assert_eq!(vec![Infeasibility::NoMine], infeasibilities);
+    }
+
+    #[test]
+    fn workshop_quarter_without_gate() {
+        let config = GenerationConfig { workshop_quarter_by_mine: true, ..Default::default() };
+        let (_, infeasibilities) =
+            plan_zoning(&districts(), BlockColumnCoord(15, 5), &Vec::new(), &[], Some(BlockColumnCoord(50, 5)), &config);
+        assert_eq!(vec![Infeasibility::NoGate], infeasibilities);
+    }
+
+    #[test]
+    fn no_districts() {
+        let config = GenerationConfig { market_districts_min: 1, ..Default::default() };
+        let (uses, infeasibilities) = plan_zoning(&[], BlockColumnCoord(0, 0), &Vec::new(), &[], None, &config);
+        assert!(uses.is_empty());
+        assert_eq!(
+            vec![Infeasibility::TooFewMarketDistricts { required: 1, available: 0 }],
+            infeasibilities,
+        );
+    }
+}